@@ -0,0 +1,138 @@
+// Synthetic upstream SSE transcripts shaped like real provider streams: a long
+// run of text deltas followed by a streamed tool call. Shared by the criterion
+// benchmarks and the stream conversion regression test.
+
+use bytes::Bytes;
+use llm_router::config::ApiType;
+use serde_json::json;
+
+pub const PAIRS: [(ApiType, ApiType); 9] = [
+    (ApiType::OpenAI, ApiType::OpenAI),
+    (ApiType::OpenAI, ApiType::Anthropic),
+    (ApiType::OpenAI, ApiType::Gemini),
+    (ApiType::Anthropic, ApiType::OpenAI),
+    (ApiType::Anthropic, ApiType::Anthropic),
+    (ApiType::Anthropic, ApiType::Gemini),
+    (ApiType::Gemini, ApiType::OpenAI),
+    (ApiType::Gemini, ApiType::Anthropic),
+    (ApiType::Gemini, ApiType::Gemini),
+];
+
+// Tool argument fragments streamed after the text
+const TOOL_DELTAS: usize = 20;
+
+pub fn name(api_type: &ApiType) -> &'static str {
+    match api_type {
+        ApiType::OpenAI => "openai",
+        ApiType::Anthropic => "anthropic",
+        ApiType::Gemini => "gemini",
+    }
+}
+
+/// SSE body for `source` with `text_deltas` text events, cut into `chunk_size`
+/// byte network chunks so lines straddle chunk boundaries.
+pub fn transcript(source: &ApiType, text_deltas: usize, chunk_size: usize) -> Vec<Bytes> {
+    let body = match source {
+        ApiType::OpenAI => openai(text_deltas),
+        ApiType::Anthropic => anthropic(text_deltas),
+        ApiType::Gemini => gemini(text_deltas),
+    };
+    body.as_bytes().chunks(chunk_size).map(Bytes::copy_from_slice).collect()
+}
+
+/// Number of upstream data events in a transcript with `text_deltas` text events.
+pub fn event_count(source: &ApiType, text_deltas: usize) -> usize {
+    match source {
+        // role, text, tool start, tool args, finish
+        ApiType::OpenAI => 1 + text_deltas + 1 + TOOL_DELTAS + 1,
+        // message_start, block start/stop x2, text, tool args, message_delta, message_stop
+        ApiType::Anthropic => 1 + 2 + text_deltas + 2 + TOOL_DELTAS + 2,
+        // text, function call, finish
+        ApiType::Gemini => text_deltas + 2,
+    }
+}
+
+fn word(i: usize) -> String {
+    format!("token{} ", i % 97)
+}
+
+fn openai(text_deltas: usize) -> String {
+    let chunk = |delta: serde_json::Value, finish: Option<&str>| {
+        let v = json!({
+            "id": "chatcmpl-bench",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "gpt-4o",
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish}]
+        });
+        format!("data: {}\n\n", v)
+    };
+    let mut out = chunk(json!({"role": "assistant", "content": ""}), None);
+    for i in 0..text_deltas {
+        out.push_str(&chunk(json!({"content": word(i)}), None));
+    }
+    out.push_str(&chunk(
+        json!({"tool_calls": [{"index": 0, "id": "call_1", "type": "function", "function": {"name": "lookup", "arguments": ""}}]}),
+        None,
+    ));
+    for i in 0..TOOL_DELTAS {
+        out.push_str(&chunk(json!({"tool_calls": [{"index": 0, "function": {"arguments": format!("{{\"k{}\":1,", i)}}]}), None));
+    }
+    out.push_str(&chunk(json!({}), Some("tool_calls")));
+    out.push_str("data: [DONE]\n\n");
+    out
+}
+
+fn anthropic(text_deltas: usize) -> String {
+    let event = |v: serde_json::Value| format!("event: {}\ndata: {}\n\n", v["type"].as_str().unwrap(), v);
+    let mut out = event(json!({
+        "type": "message_start",
+        "message": {"id": "msg_bench", "type": "message", "role": "assistant", "content": [], "model": "claude-sonnet-4",
+                    "usage": {"input_tokens": 100, "output_tokens": 1}}
+    }));
+    out.push_str(&event(json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})));
+    for i in 0..text_deltas {
+        out.push_str(&event(json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": word(i)}})));
+    }
+    out.push_str(&event(json!({"type": "content_block_stop", "index": 0})));
+    out.push_str(&event(json!({
+        "type": "content_block_start", "index": 1,
+        "content_block": {"type": "tool_use", "id": "toolu_1", "name": "lookup", "input": {}}
+    })));
+    for i in 0..TOOL_DELTAS {
+        out.push_str(&event(json!({
+            "type": "content_block_delta", "index": 1,
+            "delta": {"type": "input_json_delta", "partial_json": format!("{{\"k{}\":1,", i)}
+        })));
+    }
+    out.push_str(&event(json!({"type": "content_block_stop", "index": 1})));
+    out.push_str(&event(json!({
+        "type": "message_delta", "delta": {"stop_reason": "tool_use"},
+        "usage": {"input_tokens": 100, "output_tokens": 500}
+    })));
+    out.push_str(&event(json!({"type": "message_stop"})));
+    out
+}
+
+fn gemini(text_deltas: usize) -> String {
+    let chunk = |parts: serde_json::Value, finish: Option<&str>| {
+        let mut candidate = json!({"content": {"role": "model", "parts": parts}, "index": 0});
+        if let Some(f) = finish {
+            candidate["finishReason"] = json!(f);
+        }
+        let v = json!({
+            "candidates": [candidate],
+            "usageMetadata": {"promptTokenCount": 100, "candidatesTokenCount": 5, "totalTokenCount": 105},
+            "modelVersion": "gemini-2.5-pro",
+            "responseId": "resp-bench"
+        });
+        format!("data: {}\r\n\r\n", v)
+    };
+    let mut out = String::new();
+    for i in 0..text_deltas {
+        out.push_str(&chunk(json!([{"text": word(i)}]), None));
+    }
+    out.push_str(&chunk(json!([{"functionCall": {"name": "lookup", "args": {"k": 1}}}]), None));
+    out.push_str(&chunk(json!([{"text": ""}]), Some("STOP")));
+    out
+}
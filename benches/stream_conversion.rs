@@ -0,0 +1,43 @@
+// Throughput of the streaming converter for every upstream -> client format pair.
+// Run with `cargo bench --bench stream_conversion`.
+
+#[path = "common/transcripts.rs"]
+mod transcripts;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use futures::stream;
+use http_body_util::BodyExt;
+use llm_router::converters::response_handler::{StreamOptions, handle_streaming_response};
+
+const TEXT_DELTAS: usize = 2_000;
+const CHUNK_SIZE: usize = 4096;
+
+fn stream_conversion(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let mut group = c.benchmark_group("stream_conversion");
+    for (source, target) in transcripts::PAIRS {
+        let chunks = transcripts::transcript(&source, TEXT_DELTAS, CHUNK_SIZE);
+        group.throughput(Throughput::Elements(transcripts::event_count(&source, TEXT_DELTAS) as u64));
+        let id = BenchmarkId::from_parameter(format!("{}_to_{}", transcripts::name(&source), transcripts::name(&target)));
+        group.bench_function(id, |b| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let input = stream::iter(chunks.clone().into_iter().map(Ok::<_, reqwest::Error>));
+                    let resp = handle_streaming_response(
+                        input,
+                        "bench".to_string(),
+                        source.clone(),
+                        target.clone(),
+                        StreamOptions::default(),
+                    )
+                    .await;
+                    resp.into_body().collect().await.unwrap().to_bytes()
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, stream_conversion);
+criterion_main!(benches);
@@ -0,0 +1,25 @@
+// Raw bytes as a request body in one format, converted to the other two and back.
+// The first byte picks the format. Run with `cargo fuzz run request_conversion`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use llm_router::converters::request_wrapper::RequestWrapper;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((format, body)) = data.split_first() else { return };
+    let request = match format % 3 {
+        0 => serde_json::from_slice(body).map(RequestWrapper::OpenAI),
+        1 => serde_json::from_slice(body).map(RequestWrapper::Anthropic),
+        _ => serde_json::from_slice(body).map(RequestWrapper::Gemini),
+    };
+    let Ok(request) = request else { return };
+    for converted in [
+        RequestWrapper::OpenAI(request.get_openai()),
+        RequestWrapper::Anthropic(request.get_anthropic()),
+        RequestWrapper::Gemini(request.get_gemini()),
+    ] {
+        let _ = serde_json::to_vec(&converted);
+        let _ = (converted.get_openai(), converted.get_anthropic(), converted.get_gemini());
+    }
+});
@@ -0,0 +1,29 @@
+// Raw bytes as an upstream SSE body, converted for a client of another format.
+// The first byte picks the pair and where the body is cut into network chunks.
+// Run with `cargo fuzz run stream_conversion`.
+
+#![no_main]
+
+use bytes::Bytes;
+use futures::stream;
+use http_body_util::BodyExt;
+use libfuzzer_sys::fuzz_target;
+use llm_router::config::ApiType;
+use llm_router::converters::response_handler::{StreamOptions, handle_streaming_response};
+
+const APIS: [ApiType; 3] = [ApiType::OpenAI, ApiType::Anthropic, ApiType::Gemini];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((control, body)) = data.split_first() else { return };
+    let source = APIS[(control % 3) as usize].clone();
+    let target = APIS[(control / 3 % 3) as usize].clone();
+    let chunk_size = (control / 9) as usize % 16 + 1;
+    let chunks: Vec<_> = body.chunks(chunk_size).map(Bytes::copy_from_slice).collect();
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let input = stream::iter(chunks.into_iter().map(Ok::<_, reqwest::Error>));
+        let resp = handle_streaming_response(input, "fuzz".to_string(), source, target, StreamOptions::default()).await;
+        let _ = resp.into_body().collect().await;
+    });
+});
@@ -0,0 +1,250 @@
+use crate::auth::AppState;
+use crate::config::{Config, ModelConfig};
+use crate::error::RouterError;
+use crate::loop_guard;
+use crate::model_checks;
+use crate::model_manager::{self, ModelManager};
+use crate::size_stats::SizeMetric;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+#[derive(Debug, Deserialize)]
+pub struct WeightsPatch {
+    /// member name -> new weight
+    pub weights: HashMap<String, u32>,
+    /// Also write the updated config back to the config file
+    #[serde(default)]
+    pub persist: bool,
+}
+
+// PATCH /admin/groups/{group}/weights
+pub async fn patch_group_weights(
+    State(app_state): State<AppState>,
+    Path(group): Path<String>,
+    Json(patch): Json<WeightsPatch>,
+) -> Response {
+    let mut model_manager = app_state.model_manager.write().await;
+    if !model_manager.get_config().router_settings.model_groups.iter().any(|g| g.name == group) {
+        return RouterError::client(StatusCode::NOT_FOUND, "group_not_found", format!("Model group '{}' not found", group))
+            .into_response();
+    }
+    if let Err(e) = model_manager.set_group_weights(&group, &patch.weights) {
+        return RouterError::client(StatusCode::BAD_REQUEST, "invalid_weights", e.to_string()).into_response();
+    }
+    info!("Updated weights for group {}: {:?}", group, patch.weights);
+
+    let config = model_manager.get_config().clone();
+    drop(model_manager);
+
+    if patch.persist {
+        let weights: Vec<(String, u32)> = config
+            .router_settings
+            .model_groups
+            .iter()
+            .find(|g| g.name == group)
+            .map(|g| g.models.iter().map(|m| (m.name.clone(), m.weight)).collect())
+            .unwrap_or_default();
+        if let Err(e) = Config::persist_group_weights(&app_state.config_path, &group, &weights) {
+            warn!("Failed to persist weights next to {}: {}", app_state.config_path, e);
+            return RouterError::Internal(format!("Weights applied but not persisted: {}", e)).into_response();
+        }
+    }
+
+    let members: Vec<_> = config
+        .router_settings
+        .model_groups
+        .iter()
+        .find(|g| g.name == group)
+        .map(|g| g.models.iter().map(|m| json!({"name": m.name, "weight": m.weight})).collect())
+        .unwrap_or_default();
+    Json(json!({"group": group, "models": members, "persisted": patch.persist})).into_response()
+}
+
+// GET /admin/groups
+// Each group's members with their configured weight (after normalize_weights)
+// and the weight SWRR currently uses once health is factored in
+pub async fn group_status(State(app_state): State<AppState>) -> Response {
+    let model_manager = app_state.model_manager.read().await;
+    let config = model_manager.get_config();
+    let groups: Vec<_> = config
+        .router_settings
+        .model_groups
+        .iter()
+        .map(|g| {
+            let members: Vec<_> = model_manager
+                .effective_weights(&g.name)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, weight, effective)| json!({"name": name, "weight": weight, "effective_weight": effective}))
+                .collect();
+            json!({"name": g.name, "models": members})
+        })
+        .collect();
+    // Weights above are the configured ones; an active strategy rule may override them
+    let active_rule = model_manager.active_rule().map(|(idx, rule)| {
+        json!({"index": idx, "name": rule.name, "strategy": rule.strategy, "weights": rule.weights})
+    });
+    Json(json!({
+        "strategy": config.router_settings.strategy,
+        "normalize_weights": config.router_settings.normalize_weights,
+        "active_rule": active_rule,
+        "groups": groups,
+    }))
+    .into_response()
+}
+
+// POST /admin/reload
+// Re-read the config file and apply it without dropping in-flight state.
+// Tenants can be changed but not added or removed, which needs a restart.
+pub async fn reload_config(State(app_state): State<AppState>) -> Response {
+    let config = match Config::from_file_with_overrides(&app_state.config_path, &app_state.config_overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("Config reload from {} failed: {}", app_state.config_path, e);
+            return RouterError::client(StatusCode::BAD_REQUEST, "invalid_config", e.to_string()).into_response();
+        }
+    };
+    let tenants: BTreeSet<&String> = config.tenants.iter().map(|t| &t.name).collect();
+    if tenants != app_state.tenants.keys().collect::<BTreeSet<_>>() {
+        return RouterError::client(
+            StatusCode::CONFLICT,
+            "restart_required",
+            "Adding or removing tenants requires a restart".to_string(),
+        )
+        .into_response();
+    }
+
+    // Two-phase: nothing is applied unless every upstream the new config adds answers
+    let mut probed = 0;
+    if config.router_settings.reload_probes.enabled {
+        let mut current = vec![app_state.model_manager.read().await.get_config().clone()];
+        for manager in app_state.tenants.values() {
+            current.push(manager.read().await.get_config().clone());
+        }
+        let current: Vec<&ModelConfig> = current.iter().flat_map(|c| &c.model_list).collect();
+        let candidates: Vec<&ModelConfig> =
+            config.model_list.iter().chain(config.tenants.iter().flat_map(|t| &t.config.model_list)).collect();
+        let timeout = Duration::from_millis(config.router_settings.reload_probes.timeout_ms);
+        let identity = &config.router_settings.upstream_identity;
+        let failed;
+        (probed, failed) = model_checks::probe_added(&current, &candidates, &app_state.llm_client, identity, timeout).await;
+        if !failed.is_empty() {
+            warn!("Config reload from {} rejected: {} of {} new upstreams failed their probe", app_state.config_path, failed.len(), probed);
+            let error = RouterError::client(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "probe_failed",
+                format!("{} of {} new upstreams failed their probe; the current config stays", failed.len(), probed),
+            );
+            // The usual error body, plus which upstreams failed
+            let body = json!({"reloaded": false, "error": error.detail(), "failed": failed});
+            let mut response = (error.status(), Json(body)).into_response();
+            response.extensions_mut().insert(error.kind());
+            return response;
+        }
+    }
+
+    let mut tenant_reports = serde_json::Map::new();
+    for tenant in &config.tenants {
+        let manager = &app_state.tenants[&tenant.name];
+        let report = manager.write().await.update_config(Arc::new(tenant.config.clone()));
+        collect_orphans_later(manager, &report);
+        tenant_reports.insert(tenant.name.clone(), json!(report));
+    }
+    app_state.tenant_index.rebuild(&config);
+    let unknown_keys = config.unknown_keys.clone();
+    let self_targets = loop_guard::self_targets(&config, loop_guard::listeners());
+    if !self_targets.is_empty() {
+        warn!("api_base of {} points back at this router; requests to them are refused after {} hops", self_targets.join(", "), config.router_settings.max_hops);
+    }
+    let mut manager = app_state.model_manager.write().await;
+    // Ports are bound at startup, so listener edits wait for a restart
+    let mut restart_required = Vec::new();
+    if manager.get_config().router_settings.listeners != config.router_settings.listeners {
+        warn!("Listener changes in {} apply after a restart", app_state.config_path);
+        restart_required.push("listeners");
+    }
+    let report = manager.update_config(Arc::new(config));
+    drop(manager);
+    collect_orphans_later(&app_state.model_manager, &report);
+    Json(json!({
+        "reloaded": true,
+        "probed": probed,
+        "report": report,
+        "tenants": tenant_reports,
+        "unknown_keys": unknown_keys,
+        "self_targets": self_targets,
+        "restart_required": restart_required,
+    }))
+    .into_response()
+}
+
+fn collect_orphans_later(manager: &Arc<RwLock<ModelManager>>, report: &model_manager::ReloadReport) {
+    if !report.orphaned.is_empty() {
+        tokio::spawn(model_manager::collect_when_drained(manager.clone(), Duration::from_secs(1)));
+    }
+}
+
+// GET /admin/health/models
+// Each model's health, weights, breaker state and recent outcomes, so an
+// external scheduler can place requests on the router's signals
+pub async fn model_health(State(app_state): State<AppState>) -> Response {
+    let mut tenants = serde_json::Map::new();
+    for (name, manager) in app_state.tenants.iter() {
+        tenants.insert(name.clone(), json!(manager.read().await.model_health()));
+    }
+    let models = app_state.model_manager.read().await.model_health();
+    Json(json!({"window_secs": model_manager::OUTCOME_WINDOW_SECS, "models": models, "tenants": tenants})).into_response()
+}
+
+// POST /admin/health/models/reset-peaks
+// Start peak in-flight, peak queue depth and last error time over, e.g. after
+// a capacity change
+pub async fn reset_peaks(State(app_state): State<AppState>) -> Response {
+    let mut tenants = serde_json::Map::new();
+    for (name, manager) in app_state.tenants.iter() {
+        tenants.insert(name.clone(), json!(manager.read().await.reset_peaks()));
+    }
+    let reset = app_state.model_manager.read().await.reset_peaks();
+    Json(json!({"reset": reset, "tenants": tenants})).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeavyHittersQuery {
+    // How many models, keys and requests to list
+    #[serde(default = "default_top")]
+    pub top: usize,
+    #[serde(default)]
+    pub by: SizeMetric,
+}
+
+fn default_top() -> usize {
+    10
+}
+
+// GET /admin/heavy-hitters?top=10&by=request_bytes
+// Models and keys with the most traffic by `by`, and the largest single requests
+pub async fn heavy_hitters(State(app_state): State<AppState>, Query(query): Query<HeavyHittersQuery>) -> Response {
+    Json(app_state.sizes.report(query.top, query.by)).into_response()
+}
+
+// GET /admin/orphans
+// Members removed by a reload that still have requests in flight
+pub async fn orphaned_state(State(app_state): State<AppState>) -> Response {
+    let mut tenants = serde_json::Map::new();
+    for (name, manager) in app_state.tenants.iter() {
+        tenants.insert(name.clone(), json!(manager.read().await.orphaned_state()));
+    }
+    let orphaned = app_state.model_manager.read().await.orphaned_state();
+    Json(json!({"orphaned": orphaned, "tenants": tenants})).into_response()
+}
@@ -0,0 +1,108 @@
+use crate::error::RouterError;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use tracing::debug;
+
+/// Response header naming the API version the router answered with.
+pub const HEADER: &str = "x-llm-router-api-version";
+
+/// `anthropic-version` values accepted from clients. Only the version the router
+/// converts to and from is listed; bodies are not converted between versions.
+pub const ANTHROPIC_VERSIONS: &[&str] = &["2023-06-01"];
+
+/// Version sent to Anthropic upstreams, and assumed when the client sends none.
+pub const ANTHROPIC_DEFAULT_VERSION: &str = "2023-06-01";
+
+/// Gemini API versions served under `/{version}/models/...`. Request and response
+/// bodies are the same in both; the upstream version comes from its `api_base`.
+pub const GEMINI_VERSIONS: &[&str] = &["v1", "v1beta"];
+
+// Version OpenAI clients get; betas are negotiated separately
+pub const OPENAI_VERSION: &str = "v1";
+
+fn unsupported(message: String) -> RouterError {
+    RouterError::client(StatusCode::BAD_REQUEST, "unsupported_api_version", message)
+}
+
+/// The client's `anthropic-version`, or the default when absent.
+pub fn anthropic_version(headers: &HeaderMap) -> Result<&'static str, RouterError> {
+    let Some(value) = headers.get("anthropic-version") else {
+        return Ok(ANTHROPIC_DEFAULT_VERSION);
+    };
+    let requested = value.to_str().unwrap_or_default().trim();
+    ANTHROPIC_VERSIONS
+        .iter()
+        .find(|v| **v == requested)
+        .copied()
+        .ok_or_else(|| {
+            unsupported(format!(
+                "anthropic-version '{}' is not supported; use one of {}",
+                requested,
+                ANTHROPIC_VERSIONS.join(", ")
+            ))
+        })
+}
+
+/// Features from the client's `OpenAI-Beta` header, each `name=vN`. The header may
+/// repeat and each value may list several comma-separated features.
+pub fn openai_betas(headers: &HeaderMap) -> Result<Vec<String>, RouterError> {
+    let mut betas = Vec::new();
+    for value in headers.get_all("openai-beta") {
+        for beta in value.to_str().unwrap_or_default().split(',').map(str::trim).filter(|b| !b.is_empty()) {
+            let valid = beta.split_once('=').is_some_and(|(name, version)| {
+                !name.is_empty()
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    && version.strip_prefix('v').is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            });
+            if !valid {
+                return Err(unsupported(format!("OpenAI-Beta '{}' is not of the form feature=vN", beta)));
+            }
+            betas.push(beta.to_string());
+        }
+    }
+    Ok(betas)
+}
+
+/// The Gemini version a request path starts with, e.g. `/v1beta/models/...`.
+pub fn gemini_version(path: &str) -> Result<&'static str, RouterError> {
+    let requested = path.trim_start_matches('/').split('/').next().unwrap_or_default();
+    GEMINI_VERSIONS.iter().find(|v| **v == requested).copied().ok_or_else(|| {
+        unsupported(format!("Gemini API version '{}' is not supported; use one of {}", requested, GEMINI_VERSIONS.join(", ")))
+    })
+}
+
+/// Tell the client which version it was answered with.
+pub fn apply(response: &mut axum::response::Response, version: &str) {
+    match HeaderValue::from_str(version) {
+        Ok(v) => {
+            response.headers_mut().insert(HEADER, v);
+        }
+        Err(e) => debug!("Not reporting API version {:?}: {}", version, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_versions() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(anthropic_version(&headers).unwrap(), "2023-06-01");
+        headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+        assert_eq!(anthropic_version(&headers).unwrap(), "2023-06-01");
+        // The older streaming format is not produced, so the version is refused
+        headers.insert("anthropic-version", HeaderValue::from_static("2023-01-01"));
+        assert!(anthropic_version(&headers).is_err());
+        headers.insert("anthropic-version", HeaderValue::from_static("2099-01-01"));
+        assert!(anthropic_version(&headers).is_err());
+
+        headers.insert("openai-beta", HeaderValue::from_static("assistants=v2, realtime=v1"));
+        assert_eq!(openai_betas(&headers).unwrap(), vec!["assistants=v2", "realtime=v1"]);
+        headers.insert("openai-beta", HeaderValue::from_static("assistants"));
+        assert!(openai_betas(&headers).is_err());
+
+        assert_eq!(gemini_version("/v1/models/gemini-pro:generateContent").unwrap(), "v1");
+        assert_eq!(gemini_version("/v1beta/models/gemini-pro:generateContent").unwrap(), "v1beta");
+        assert!(gemini_version("/v2/models/gemini-pro:generateContent").is_err());
+    }
+}
@@ -1,14 +1,21 @@
+use crate::error::RouterError;
 use crate::llm_client::LlmClient;
+use crate::config::{Config, VirtualKey};
+use crate::mcp::McpGateway;
+use crate::metrics::Metrics;
 use crate::model_manager::ModelManager;
-use crate::models::{ErrorDetail, ErrorResponse};
+use crate::response_store::ResponseStore;
+use crate::retry_queue::RetryQueue;
+use crate::session_caps::SessionLedger;
+use crate::size_stats::SizeStats;
 use axum::{
-    Json,
     extract::{Request, State},
     http::StatusCode,
     middleware::Next,
     response::{IntoResponse, Response},
 };
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock as StdRwLock};
 use tokio::sync::RwLock;
 use tracing::{debug, info};
 
@@ -17,20 +24,142 @@ pub struct AppState {
     pub model_manager: Arc<RwLock<ModelManager>>,
     pub token: Option<String>,
     pub llm_client: Arc<LlmClient>,
+    pub metrics: Arc<Metrics>,
+    pub config_path: String,
+    // --set overrides, applied again whenever the config is reloaded
+    pub config_overrides: Arc<Vec<String>>,
+    pub response_store: Arc<ResponseStore>,
+    pub mcp: Arc<McpGateway>,
+    pub sessions: Arc<SessionLedger>,
+    // Request and response sizes per model and key, router-wide
+    pub sizes: Arc<SizeStats>,
+    // Requests waiting out upstream rate limits, router-wide
+    pub retry_queue: Arc<RetryQueue>,
+    // One model manager per tenant, so counters, health and bulkheads never mix
+    pub tenants: Arc<HashMap<String, Arc<RwLock<ModelManager>>>>,
+    // Tenant credentials by token, rebuilt whenever the config is (re)loaded
+    pub tenant_index: Arc<TenantIndex>,
+    // Tenant this state is scoped to; None for the root config
+    pub tenant: Option<String>,
+}
+
+/// Tenant selected by the request's credentials, set by `require_authorization`.
+#[derive(Debug, Clone)]
+pub struct TenantId(pub String);
+
+/// Tenant keys and tenant virtual keys by token, so authenticating a request
+/// does not visit every tenant's model manager.
+#[derive(Debug, Default)]
+pub struct TenantIndex(StdRwLock<HashMap<String, (TenantId, Option<VirtualKey>)>>);
+
+impl TenantIndex {
+    pub fn new(config: &Config) -> Self {
+        let index = Self::default();
+        index.rebuild(config);
+        index
+    }
+
+    /// Replace the index with the tenants of `config`, e.g. after a reload.
+    pub fn rebuild(&self, config: &Config) {
+        let mut tokens = HashMap::new();
+        for tenant in &config.tenants {
+            let id = TenantId(tenant.name.clone());
+            for key in &tenant.keys {
+                tokens.insert(key.clone(), (id.clone(), None));
+            }
+            for vk in &tenant.config.virtual_keys {
+                tokens.insert(vk.key.clone(), (id.clone(), Some(vk.clone())));
+            }
+        }
+        *self.0.write().unwrap() = tokens;
+    }
+
+    fn find(&self, token: &str) -> Option<(TenantId, Option<VirtualKey>)> {
+        self.0.read().unwrap().get(token).cloned()
+    }
+}
+
+/// Who a credential belongs to, as found by `AppState::authenticate`.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub tenant: Option<TenantId>,
+    pub virtual_key: Option<VirtualKey>,
+}
+
+impl AppState {
+    /// The state as seen by requests of `tenant`: its own model manager and response store keys.
+    pub fn scoped(&self, tenant: Option<&TenantId>) -> AppState {
+        let Some(TenantId(name)) = tenant else { return self.clone() };
+        let Some(model_manager) = self.tenants.get(name) else { return self.clone() };
+        AppState { model_manager: model_manager.clone(), tenant: Some(name.clone()), ..self.clone() }
+    }
+
+    /// Response store and session key for a client-supplied id. Tenant names
+    /// never contain '/' and the root namespace is empty, so no id can reach
+    /// into another namespace.
+    pub fn response_key(&self, id: &str) -> String {
+        format!("{}/{}", self.tenant.as_deref().unwrap_or_default(), id)
+    }
+
+    /// Whether requests must carry a credential: a router token, a virtual key or a tenant is configured.
+    pub async fn auth_required(&self) -> bool {
+        self.token.is_some() || !self.tenants.is_empty() || !self.model_manager.read().await.get_config().virtual_keys.is_empty()
+    }
+
+    /// Check a credential: the router token, a virtual key whose settings travel
+    /// with the request, or a tenant credential that scopes the request to that tenant.
+    /// Callers skip this when `auth_required` is false.
+    pub async fn authenticate(&self, token: Option<&str>) -> Result<Credentials, RouterError> {
+        let Some(token) = token else {
+            return Err(RouterError::client(StatusCode::UNAUTHORIZED, "missing_auth_token", "Authentication token is required"));
+        };
+        if self.token.as_deref() == Some(token) {
+            return Ok(Credentials::default());
+        }
+        let virtual_key = self.model_manager.read().await.get_config().virtual_keys.iter().find(|vk| vk.key == token).cloned();
+        if let Some(virtual_key) = virtual_key {
+            return Ok(Credentials { tenant: None, virtual_key: Some(virtual_key) });
+        }
+        match self.find_tenant(token) {
+            Some((tenant, virtual_key)) => Ok(Credentials { tenant: Some(tenant), virtual_key }),
+            None => Err(RouterError::client(StatusCode::UNAUTHORIZED, "invalid_token", "Invalid authentication token")),
+        }
+    }
+
+    // Finds the tenant (and virtual key) a credential belongs to
+    fn find_tenant(&self, token: &str) -> Option<(TenantId, Option<VirtualKey>)> {
+        self.tenant_index.find(token)
+    }
 }
 
 pub async fn require_authorization(
     State(app_state): State<AppState>,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> Response {
     // Skip authorization for health check endpoint
-    if request.uri().path() == "/health" || request.uri().path() == "/v1/models" {
+    if request.uri().path() == "/health" {
+        return next.run(request).await;
+    }
+    // The model list stays public, but a tenant key lists that tenant's groups
+    // and a virtual key only the models it may call
+    if request.uri().path() == "/v1/models" {
+        let token = bearer_token(&request).map(str::to_string);
+        if let Some(token) = token
+            && let Ok(credentials) = app_state.authenticate(Some(&token)).await
+        {
+            if let Some(tenant) = credentials.tenant {
+                request.extensions_mut().insert(tenant);
+            }
+            if let Some(virtual_key) = credentials.virtual_key {
+                request.extensions_mut().insert(virtual_key);
+            }
+        }
         return next.run(request).await;
     }
 
-    // If no token is configured, skip authorization
-    if app_state.token.is_none() {
+    // Nothing to check when no token, virtual key or tenant is configured
+    if !app_state.auth_required().await {
         return next.run(request).await;
     }
 
@@ -50,7 +179,7 @@ pub async fn require_authorization(
             .and_then(|hv| hv.to_str().ok())
             .map(|s| s.trim())
             .map(|s| s)
-    } else if path.starts_with("/v1beta/models/") {
+    } else if path.starts_with("/v1beta/models/") || path.starts_with("/v1/models/") {
         request
             .uri()
             .query()
@@ -79,40 +208,40 @@ pub async fn require_authorization(
     };
 
     if provided_token.is_none() {
-        provided_token = request
-            .headers()
-            .get("Authorization")
-            .and_then(|hv| hv.to_str().ok())
-            .map(|s| s.trim())
-            .and_then(|s| s.strip_prefix("Bearer ").map(|t| t.trim()))
-            .map(|s| s);
+        provided_token = bearer_token(&request);
     }
 
-    if provided_token.is_none() {
-        info!("Missing authentication token for path: {}", path);
-        let error_response = ErrorResponse {
-            error: ErrorDetail {
-                message: format!("Authentication token is required"),
-                r#type: "invalid_request_error".to_string(),
-                code: Some("missing_auth_token".to_string()),
-            },
-        };
-        return (StatusCode::UNAUTHORIZED, Json(error_response)).into_response();
+    let path = path.to_string();
+    let credentials = match app_state.authenticate(provided_token).await {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            info!("Rejected credentials for path {}: {}", path, e);
+            return e.into_response();
+        }
+    };
+    if let Some(tenant) = credentials.tenant {
+        // Router-wide endpoints would expose other tenants
+        if path.starts_with("/admin") || path == "/metrics" {
+            info!("Tenant {} may not access {}", tenant.0, path);
+            return RouterError::client(StatusCode::FORBIDDEN, "forbidden", "Tenant keys cannot access this endpoint")
+                .into_response();
+        }
+        debug!("Request scoped to tenant {}", tenant.0);
+        request.extensions_mut().insert(tenant);
     }
-
-    // Validate token
-    if provided_token.as_deref() != app_state.token.as_deref() {
-        info!("Invalid token provided");
-        let error_response = ErrorResponse {
-            error: ErrorDetail {
-                message: "Invalid authentication token".to_string(),
-                r#type: "invalid_request_error".to_string(),
-                code: Some("invalid_token".to_string()),
-            },
-        };
-        return (StatusCode::UNAUTHORIZED, Json(error_response)).into_response();
+    if let Some(virtual_key) = credentials.virtual_key {
+        request.extensions_mut().insert(virtual_key);
     }
 
     debug!("Token validation successful");
     next.run(request).await
 }
+
+fn bearer_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get("Authorization")
+        .and_then(|hv| hv.to_str().ok())
+        .map(|s| s.trim())
+        .and_then(|s| s.strip_prefix("Bearer ").map(|t| t.trim()))
+}
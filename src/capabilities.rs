@@ -0,0 +1,145 @@
+//! `GET /v1/capabilities`: what this router build and its config support, so
+//! orchestration tooling can compare deployed routers.
+
+use crate::auth::{AppState, TenantId};
+use crate::config::{ApiType, ConversionPair, RouterSettings};
+use crate::metrics::api_name;
+use crate::startup_report;
+use axum::{Extension, Json, extract::State};
+use serde::Serialize;
+
+/// Every endpoint the router serves, kept in step with the routes in main.
+/// Listed by `/v1/capabilities` and in 404 responses.
+pub const ENDPOINTS: &[(&str, &str)] = &[
+    ("POST", "/v1/chat/completions"),
+    ("POST", "/v1/messages"),
+    ("POST", "/v1beta/models/{model}:generateContent"),
+    ("POST", "/v1beta/models/{model}:streamGenerateContent"),
+    ("POST", "/v1/models/{model}:generateContent"),
+    ("POST", "/v1/models/{model}:streamGenerateContent"),
+    ("GET", "/v1/models"),
+    ("GET", "/v1/capabilities"),
+    ("GET", "/v1/responses/{id}/events"),
+    ("GET", "/metrics"),
+    ("GET", "/admin/groups"),
+    ("PATCH", "/admin/groups/{group}/weights"),
+    ("POST", "/admin/reload"),
+    ("GET", "/admin/orphans"),
+    ("GET", "/admin/health/models"),
+    ("POST", "/admin/health/models/reset-peaks"),
+    ("GET", "/admin/heavy-hitters"),
+    ("GET", "/health"),
+];
+
+/// The endpoints as `METHOD path` strings.
+pub fn endpoint_list() -> Vec<String> {
+    ENDPOINTS.iter().map(|(method, path)| format!("{} {}", method, path)).collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    pub version: &'static str,
+    // Cargo features this binary was built with
+    pub build_features: Vec<&'static str>,
+    // Optional behaviour switched on in the config
+    pub features: Vec<&'static str>,
+    pub storage: Storage,
+    pub endpoints: Vec<String>,
+    pub conversions: Vec<Conversion>,
+}
+
+/// Where state that outlives a request is kept.
+#[derive(Debug, Serialize)]
+pub struct Storage {
+    // Resumable streams; null when the response store is off
+    pub response_store: Option<&'static str>,
+    // Model health; null when it is not persisted
+    pub health_state: Option<&'static str>,
+}
+
+/// How requests in one format reach an upstream of another.
+#[derive(Debug, Serialize)]
+pub struct Conversion {
+    pub from: &'static str,
+    pub to: &'static str,
+    // `none`, `direct` or `openai`, as in the x-llm-router-conversion header
+    pub request: &'static str,
+    pub stream: &'static str,
+}
+
+fn build_features() -> Vec<&'static str> {
+    [("grpc", cfg!(feature = "grpc")), ("image-transcode", cfg!(feature = "image-transcode"))]
+        .into_iter()
+        .filter_map(|(name, built)| built.then_some(name))
+        .collect()
+}
+
+impl Capabilities {
+    pub fn new(settings: &RouterSettings) -> Self {
+        let apis = [ApiType::OpenAI, ApiType::Anthropic, ApiType::Gemini];
+        let mut conversions = Vec::new();
+        for from in &apis {
+            for to in &apis {
+                let pair = ConversionPair { from: from.clone(), to: to.clone() };
+                let (request, stream) = if from == to {
+                    ("none", "none")
+                } else if pair.has_direct_converter() && settings.direct_conversions.contains(&pair) {
+                    ("direct", "openai")
+                } else {
+                    ("openai", "openai")
+                };
+                conversions.push(Conversion { from: api_name(from), to: api_name(to), request, stream });
+            }
+        }
+        Capabilities {
+            version: env!("CARGO_PKG_VERSION"),
+            build_features: build_features(),
+            features: startup_report::features(settings),
+            storage: Storage {
+                response_store: settings.response_store.enabled.then_some("memory"),
+                health_state: settings.health_state.path.is_some().then_some("file"),
+            },
+            endpoints: endpoint_list(),
+            conversions,
+        }
+    }
+}
+
+// GET /v1/capabilities; tenant keys see their tenant's config
+pub async fn capabilities(State(app_state): State<AppState>, tenant: Option<Extension<TenantId>>) -> Json<Capabilities> {
+    let app_state = app_state.scoped(tenant.as_deref());
+    let model_manager = app_state.model_manager.read().await;
+    Json(Capabilities::new(&model_manager.get_config().router_settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_capabilities_follow_config() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+model_list: []
+router_settings:
+  strategy: roundrobin
+  model_groups: []
+  retry_queue: {enabled: true}
+  direct_conversions: [{from: gemini, to: anthropic}]
+"#,
+        )
+        .unwrap();
+        let caps = serde_json::to_value(Capabilities::new(&config.router_settings)).unwrap();
+        assert_eq!(caps["features"], serde_json::json!(["retry_queue"]));
+        assert_eq!(caps["storage"]["response_store"], serde_json::Value::Null);
+        let conversion = |from: &str, to: &str| {
+            caps["conversions"].as_array().unwrap().iter().find(|c| c["from"] == from && c["to"] == to).unwrap().clone()
+        };
+        assert_eq!(conversion("gemini", "anthropic")["request"], "direct");
+        assert_eq!(conversion("anthropic", "gemini")["request"], "openai");
+        assert_eq!(conversion("openai", "openai")["request"], "none");
+        assert_eq!(caps["conversions"].as_array().unwrap().len(), 9);
+        assert!(caps["endpoints"].as_array().unwrap().contains(&serde_json::json!("GET /v1/capabilities")));
+    }
+}
@@ -1,98 +1,1774 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use crate::utils::jq_util::check_jaq_filter;
+use std::collections::BTreeMap;
+use crate::utils::jq_util::{JqFilter, check_jaq_filter};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub model_list: Vec<ModelConfig>,
     pub router_settings: RouterSettings,
+    // Extra client tokens with per-key request defaults
+    #[serde(default)]
+    pub virtual_keys: Vec<VirtualKey>,
+    // Isolated namespaces with their own models, groups and keys
+    #[serde(default)]
+    pub tenants: Vec<Tenant>,
+    // Keys of the file this version does not know, as paths like
+    // `router_settings.some_key`; they are ignored
+    #[serde(skip)]
+    pub unknown_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tenant {
+    pub name: String,
+    // Client tokens that select this tenant; may reference environment variables as ${VAR}
+    pub keys: Vec<String>,
+    // Tokens and cost all of the tenant's requests may use per period
+    #[serde(default, skip_serializing_if = "TenantBudget::is_unlimited")]
+    pub budget: TenantBudget,
+    // model_list, router_settings and virtual_keys of the tenant; nested tenants are not allowed
+    #[serde(flatten)]
+    pub config: Config,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualKey {
+    // may reference environment variables as ${VAR}
+    pub key: String,
+    // Shown in /admin/heavy-hitters instead of the masked key
+    #[serde(default)]
+    pub name: Option<String>,
+    // Model or group used when the request has no model or asks for "auto"
+    #[serde(default)]
+    pub default_model: Option<String>,
+    // Top-level body fields filled in when the request does not set them
+    #[serde(default = "default_json_object")]
+    pub defaults: Value,
+    // Highest x-llm-router-priority the key may ask for
+    #[serde(default)]
+    pub max_priority: Priority,
+    // Pace streamed answers to about this many output tokens per second
+    #[serde(default)]
+    pub stream_tokens_per_sec: Option<f64>,
+    // Models and groups the key may call and see on /v1/models; unset allows all
+    #[serde(default)]
+    pub allowed_models: Option<Vec<String>>,
+    // Models and groups the key may not call by name, even when allowed above;
+    // groups that contain them still route to them
+    #[serde(default)]
+    pub denied_models: Vec<String>,
+}
+
+impl VirtualKey {
+    pub fn allows(&self, model: &str) -> bool {
+        self.is_listed(model) && !self.denies(model)
+    }
+
+    /// Whether `model` passes `allowed_models`, regardless of `denied_models`.
+    pub fn is_listed(&self, model: &str) -> bool {
+        self.allowed_models.as_ref().is_none_or(|allowed| allowed.iter().any(|m| m == model))
+    }
+
+    pub fn denies(&self, model: &str) -> bool {
+        self.denied_models.iter().any(|m| m == model)
+    }
+
+    /// What the key may call instead of `model`: the groups holding it and
+    /// their other members.
+    pub fn alternatives(&self, model: &str, groups: &[ModelGroup]) -> Vec<String> {
+        let mut alternatives: Vec<String> = Vec::new();
+        for group in groups.iter().filter(|g| g.models.iter().any(|e| e.name == model)) {
+            let names = std::iter::once(&group.name).chain(group.models.iter().map(|e| &e.name));
+            for name in names {
+                if name != model && self.allows(name) && !alternatives.contains(name) {
+                    alternatives.push(name.clone());
+                }
+            }
+        }
+        alternatives
+    }
+
+    /// `name`, or the key's last 4 characters, for reports that must not show the key.
+    pub fn label(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        // Short keys would be given away by their tail
+        let len = self.key.chars().count();
+        let tail: String = if len > 8 { self.key.chars().skip(len - 4).collect() } else { String::new() };
+        format!("...{}", tail)
+    }
+}
+
+// Request priority from the x-llm-router-priority header, lowest first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelConfig {
+    pub model_name: String,
+    pub llm_params: LLMParams,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiType {
+    OpenAI,
+    Anthropic,
+    Gemini,
+}
+
+impl ApiType {
+    /// Top-level request fields the upstream API accepts; used by `scrub_unknown_fields`.
+    pub fn request_fields(&self) -> &'static [&'static str] {
+        match self {
+            ApiType::OpenAI => &[
+                "model", "messages", "stream", "stream_options", "temperature", "top_p", "n", "stop",
+                "max_tokens", "max_completion_tokens", "presence_penalty", "frequency_penalty",
+                "logit_bias", "logprobs", "top_logprobs", "user", "tools", "tool_choice",
+                "parallel_tool_calls", "response_format", "seed", "reasoning_effort", "modalities",
+                "audio", "prediction", "service_tier", "store", "metadata", "functions",
+                "function_call", "web_search_options", "verbosity",
+            ],
+            ApiType::Anthropic => &[
+                "model", "messages", "system", "max_tokens", "metadata", "stop_sequences", "stream",
+                "temperature", "top_p", "top_k", "tools", "tool_choice", "thinking", "service_tier",
+                "container", "mcp_servers",
+            ],
+            ApiType::Gemini => &[
+                "contents", "tools", "toolConfig", "safetySettings", "systemInstruction",
+                "generationConfig", "cachedContent", "labels", "tool_config", "safety_settings",
+                "system_instruction", "generation_config", "cached_content",
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LLMParams {
+    pub api_type: ApiType,
+    pub model: String,
+    pub api_base: String,
+    pub api_key: String,
+    // String values may use request placeholders such as {request_id} or {key_name}
+    #[serde(default = "default_json_object")]
+    pub rewrite_body: Value,
+    // Header values may be strings, numbers or booleans; null removes a header
+    // the router would otherwise send
+    #[serde(default = "default_json_object")]
+    pub rewrite_header: Value,
+    // Let rewrite_header set or remove auth headers (Authorization, x-api-key,
+    // x-goog-api-key, Proxy-Authorization and the names in auth_headers)
+    #[serde(default)]
+    pub rewrite_auth_headers: bool,
+    // Route this model through --proxy; set false to connect directly
+    #[serde(default = "default_true")]
+    pub use_proxy: bool,
+    // Context window in tokens; group members too small for a request are skipped
+    #[serde(default)]
+    pub context_window: Option<u32>,
+    // Max in-flight requests to this model; extra requests are rejected with 503
+    #[serde(default)]
+    pub max_concurrency: Option<u32>,
+    // Static auth headers sent to the upstream (e.g. OpenAI-Organization);
+    // values may reference environment variables as ${VAR}
+    #[serde(default)]
+    pub auth_headers: BTreeMap<String, String>,
+    // Sign each upstream request (for gateways that require it)
+    #[serde(default)]
+    pub signing: Option<RequestSigning>,
+    // anthropic-beta values this model accepts; requests asking for others are
+    // rejected. When unset, the header is not forwarded at all.
+    #[serde(default)]
+    pub anthropic_betas: Option<Vec<String>>,
+    // Drop top-level body fields this model's API type does not define (keys set
+    // by rewrite_body are kept), for upstreams that reject unknown fields
+    #[serde(default)]
+    pub scrub_unknown_fields: bool,
+    // How a client latency budget (x-llm-router-latency-budget-ms) changes the
+    // upstream request; without it the budget only affects member selection
+    #[serde(default)]
+    pub latency_hints: Option<LatencyHints>,
+    // Request shaping for reasoning models (o-series, gpt-5 style) that reject
+    // sampling parameters or the plain max_tokens field
+    #[serde(default)]
+    pub reasoning_model: Option<ReasoningModel>,
+    // Prices for the x-llm-router-cost response header
+    #[serde(default)]
+    pub pricing: Option<Pricing>,
+    // Overrides router_settings.tool_arguments for streams served by this model
+    #[serde(default)]
+    pub tool_arguments: Option<ToolArgumentsMode>,
+    // Overrides router_settings.stream_delta_chars for streams served by this model
+    #[serde(default)]
+    pub stream_delta_chars: Option<usize>,
+    // Size cap for inline (base64) images sent to this model
+    #[serde(default)]
+    pub image_limits: Option<ImageLimits>,
+    // Reject conversations with more messages than this, counted after
+    // conversion to this model's API type
+    #[serde(default)]
+    pub max_messages: Option<usize>,
+    // Reject requests whose converted body is larger than this
+    #[serde(default)]
+    pub max_body_bytes: Option<u64>,
+    // Most output tokens this model can produce; requests asking for more are
+    // handled by max_tokens_policy instead of failing upstream
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub max_tokens_policy: MaxTokensPolicy,
+    // Largest upstream answer read into memory, including a stream collected
+    // for soft_timeout_ms; a bigger one fails with 502 and a bigger error
+    // body is cut off (default 64 MiB)
+    #[serde(default)]
+    pub max_response_bytes: Option<u64>,
+    // The upstream runs the client's hosted web search tool itself, so
+    // router_settings.web_search leaves such requests alone
+    #[serde(default)]
+    pub native_web_search: bool,
+    // Non-streaming requests to this model are streamed internally; after this
+    // many milliseconds the client gets what has arrived so far, with finish
+    // reason `length`, instead of a timeout error
+    #[serde(default)]
+    pub soft_timeout_ms: Option<u64>,
+    // Upstream response headers passed back to the client with an x-upstream-
+    // prefix, so client schedulers can see provider rate limits; a trailing *
+    // matches any suffix. Set [] to forward none.
+    #[serde(default = "default_forward_headers")]
+    pub forward_headers: Vec<String>,
+    // Send a HEAD request to api_base this often, so a warm TLS connection is
+    // waiting after idle periods; keep it below the 90s idle pool timeout
+    #[serde(default)]
+    pub keepalive_ms: Option<u64>,
+}
+
+// Inline image limits for upstreams that reject large payloads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageLimits {
+    // Largest decoded image accepted, in bytes
+    pub max_bytes: u64,
+    // Downscale and recompress oversized images to JPEG instead of rejecting the
+    // request; needs the image-transcode build feature
+    #[serde(default)]
+    pub transcode: bool,
+}
+
+/// What happens to a request whose max tokens exceed the model's `max_output_tokens`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaxTokensPolicy {
+    /// Lower it to the limit and say so in an x-llm-router-warning header
+    #[default]
+    Clamp,
+    /// Fail with 400, naming the limit and the group members that allow more
+    Reject,
+}
+
+// Token prices per million tokens, in whatever currency the operator uses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pricing {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+impl Pricing {
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_per_mtok + output_tokens as f64 * self.output_per_mtok) / 1_000_000.0
+    }
+}
+
+// Request knobs applied when a client sends a latency budget
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyHints {
+    // Budgets at or below this many milliseconds count as tight
+    #[serde(default = "default_tight_budget_ms")]
+    pub tight_budget_ms: u64,
+    // OpenAI service_tier sent when the budget is tight (e.g. "priority")
+    #[serde(default)]
+    pub service_tier: Option<String>,
+    // Expected generation speed; max tokens are capped so output fits the budget
+    #[serde(default)]
+    pub output_tokens_per_sec: Option<u32>,
+}
+
+// How requests are adjusted for a reasoning model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReasoningModel {
+    // Sampling fields removed from the request, by their OpenAI name
+    #[serde(default = "default_reasoning_strip")]
+    pub strip: Vec<String>,
+    // Send OpenAI max_tokens as max_completion_tokens
+    #[serde(default = "default_true")]
+    pub max_completion_tokens: bool,
+    // OpenAI reasoning_effort filled in when the request sets none
+    #[serde(default)]
+    pub default_effort: Option<String>,
+}
+
+/// Upstream request signing scheme.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "scheme", rename_all = "snake_case")]
+pub enum RequestSigning {
+    /// HMAC-SHA256 over the timestamp and body digest
+    HmacSha256 {
+        // may reference environment variables as ${VAR}
+        secret: String,
+        #[serde(default = "default_timestamp_header")]
+        timestamp_header: String,
+        #[serde(default = "default_digest_header")]
+        digest_header: String,
+        #[serde(default = "default_signature_header")]
+        signature_header: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouterSettings {
+    pub strategy: RoutingStrategy,
+    pub model_groups: Vec<ModelGroup>,
+    // Add x-llm-router-* headers describing the upstream that served each response
+    #[serde(default)]
+    pub routing_headers: bool,
+    // List every group on /v1/models, even those outside the caller's allowed_models
+    #[serde(default)]
+    pub list_all_models: bool,
+    #[serde(default)]
+    pub sse_terminators: SseTerminators,
+    #[serde(default)]
+    pub response_store: ResponseStoreSettings,
+    // Model or group used when a request omits the model field
+    #[serde(default)]
+    pub default_model: Option<String>,
+    #[serde(default)]
+    pub health_state: HealthStateSettings,
+    // Close truncated tool-call JSON when upstream ends mid-arguments instead of dropping the call
+    #[serde(default)]
+    pub repair_tool_arguments: bool,
+    #[serde(default)]
+    pub tool_arguments: ToolArgumentSettings,
+    // Split streamed text deltas longer than this many characters into several events
+    #[serde(default)]
+    pub stream_delta_chars: Option<usize>,
+    // Convert request and response bodies of at least this many bytes on the
+    // blocking pool instead of an async worker
+    #[serde(default)]
+    pub offload_conversion_bytes: Option<usize>,
+    #[serde(default)]
+    pub refusal_fallback: RefusalFallbackSettings,
+    #[serde(default)]
+    pub image_fetch: ImageFetchSettings,
+    #[serde(default)]
+    pub mcp: McpSettings,
+    #[serde(default)]
+    pub web_search: WebSearchSettings,
+    // Requests that already passed through this many routers (x-llm-router-hops) are
+    // rejected with 508, which stops a router that proxies to itself
+    #[serde(default = "default_max_hops")]
+    pub max_hops: u32,
+    #[serde(default)]
+    pub upstream_identity: UpstreamIdentity,
+    // Scale every group's weights to add up to this, at load and after weight
+    // updates, so SWRR shares read directly off the config
+    #[serde(default)]
+    pub normalize_weights: Option<u32>,
+    #[serde(default)]
+    pub session_caps: SessionCapSettings,
+    // Checked in order on every routing decision; the first rule that matches
+    // replaces the strategy and/or group weights until it stops matching
+    #[serde(default)]
+    pub strategy_rules: Vec<StrategyRule>,
+    #[serde(default)]
+    pub output_validation: OutputValidationSettings,
+    #[serde(default)]
+    pub priority: PrioritySettings,
+    #[serde(default)]
+    pub retry_queue: RetryQueueSettings,
+    #[serde(default)]
+    pub ttfb_health: TtfbHealthSettings,
+    #[serde(default)]
+    pub stream_failover: StreamFailoverSettings,
+    #[serde(default)]
+    pub reload_probes: ReloadProbeSettings,
+    // Share of requests (percent) the explore strategy sends to members running
+    // below their configured weight, so recovered upstreams win traffic back
+    #[serde(default = "default_exploration_percent")]
+    pub exploration_percent: u32,
+    // Extra ports served next to --port, each with its own endpoints and auth
+    #[serde(default)]
+    pub listeners: Vec<ListenerSettings>,
+    // Seed for random picks and tie-breaks, so routing repeats exactly across
+    // runs (integration tests, reproducing a report); unset uses fresh randomness
+    #[serde(default)]
+    pub routing_seed: Option<u64>,
+    // Client -> upstream format pairs whose requests and answers are converted
+    // directly; every other pair goes through the OpenAI format
+    #[serde(default = "default_direct_conversions")]
+    pub direct_conversions: Vec<ConversionPair>,
+    // Count tokens generated before a client cancelled its stream in the key's
+    // usage on /admin/heavy-hitters; the model's totals always include them
+    #[serde(default = "default_true")]
+    pub count_aborted_usage: bool,
+    // Largest request body read from clients; read at startup
+    #[serde(default = "default_max_request_bytes")]
+    pub max_request_bytes: usize,
+}
+
+// A client format and the upstream format its requests are sent in
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConversionPair {
+    pub from: ApiType,
+    pub to: ApiType,
+}
+
+impl ConversionPair {
+    /// Pairs with a converter of their own, which keeps fields the OpenAI format has no place for.
+    pub fn has_direct_converter(&self) -> bool {
+        matches!(
+            (&self.from, &self.to),
+            (ApiType::Anthropic, ApiType::Gemini) | (ApiType::Gemini, ApiType::Anthropic)
+        )
+    }
+}
+
+// How request priority plays into model bulkheads (max_concurrency)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrioritySettings {
+    // How long normal and high priority requests wait for a free slot, highest
+    // priority first; 0 rejects them at once. Low priority requests never wait.
+    #[serde(default)]
+    pub queue_timeout_ms: u64,
+    // Slots of every bulkhead that low priority requests may not take
+    #[serde(default)]
+    pub low_reserve: u32,
+    // Streaming requests that wait for a slot or in the retry queue get an SSE
+    // comment with their queue position and ETA this often; 0 sends none
+    #[serde(default)]
+    pub queue_events_ms: u64,
+    // Highest priority the router token and tenant keys may ask for; virtual
+    // keys have their own max_priority
+    #[serde(default)]
+    pub max_priority: Priority,
+    // High priority non-streaming requests to a group that have no answer after
+    // this long are also sent to another member, and the first answer wins; 0 never hedges
+    #[serde(default)]
+    pub hedge_after_ms: u64,
+}
+
+// Hold requests that every candidate answered with 429 and a Retry-After, and
+// send them again once the earliest limit lifts instead of failing at once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryQueueSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // Requests waiting at once, router-wide; further ones fail straight away
+    #[serde(default = "default_retry_queue_max_waiting")]
+    pub max_waiting: usize,
+    // Longest a request may be held in total; a shorter latency budget wins
+    #[serde(default = "default_retry_queue_max_wait_ms")]
+    pub max_wait_ms: u64,
+}
+
+impl Default for RetryQueueSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_waiting: default_retry_queue_max_waiting(),
+            max_wait_ms: default_retry_queue_max_wait_ms(),
+        }
+    }
+}
+
+// Lower the health of models whose streams keep taking much longer than
+// usual to send their first byte, though they never fail
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TtfbHealthSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // A stream is slow when its first byte takes this many times the model's usual time
+    #[serde(default = "default_ttfb_ratio")]
+    pub ratio: f64,
+    // ... and longer than this, so fast models are not judged on jitter
+    #[serde(default = "default_ttfb_min_ms")]
+    pub min_ms: u64,
+    // Slow streams in a row that halve the model's health factor
+    #[serde(default = "default_ttfb_samples")]
+    pub samples: u32,
+}
+
+impl Default for TtfbHealthSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ratio: default_ttfb_ratio(),
+            min_ms: default_ttfb_min_ms(),
+            samples: default_ttfb_samples(),
+        }
+    }
+}
+
+// Send streamed requests to another group member when the upstream fails
+// before its first byte; once bytes have reached the client the stream is kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamFailoverSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // Further members tried after the first one fails
+    #[serde(default = "default_stream_failover_max_retries")]
+    pub max_retries: u32,
+}
+
+impl Default for StreamFailoverSettings {
+    fn default() -> Self {
+        Self { enabled: false, max_retries: default_stream_failover_max_retries() }
+    }
+}
+
+// On POST /admin/reload, ping upstreams the new config adds and keep the old
+// config unless every ping succeeds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadProbeSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // How long each upstream has to answer its probe
+    #[serde(default = "default_reload_probe_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for ReloadProbeSettings {
+    fn default() -> Self {
+        Self { enabled: false, timeout_ms: default_reload_probe_timeout_ms() }
+    }
+}
+
+// Check complete answers to JSON-mode requests (and optionally against a regex)
+// and retry once with a corrective note before handing a bad answer to the client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputValidationSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // Parse answers to JSON-mode requests, and check them against the request's schema if it has one
+    #[serde(default = "default_true")]
+    pub json: bool,
+    // Regex every text answer must match
+    #[serde(default)]
+    pub pattern: Option<Pattern>,
+    // Retry on another member of the group instead of the model that answered
+    #[serde(default)]
+    pub alternate_model: bool,
+    // System instruction added for the retry; {error} is replaced with what was wrong
+    #[serde(default = "default_validation_nudge")]
+    pub nudge: String,
+}
+
+impl Default for OutputValidationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            json: true,
+            pattern: None,
+            alternate_model: false,
+            nudge: default_validation_nudge(),
+        }
+    }
+}
+
+// When a rule applies: every condition given must hold. Hours and days are UTC.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyRule {
+    // Shown in logs and /admin/groups
+    #[serde(default)]
+    pub name: Option<String>,
+    // [start, end) hours; wraps past midnight when start > end, e.g. [22, 6]
+    #[serde(default)]
+    pub hours: Option<[u8; 2]>,
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    // Requests per second over the last 10 seconds, across all groups
+    #[serde(default)]
+    pub min_rps: Option<f64>,
+    #[serde(default)]
+    pub max_rps: Option<f64>,
+    #[serde(default)]
+    pub strategy: Option<RoutingStrategy>,
+    // group -> member -> weight; members not listed keep their weight
+    #[serde(default)]
+    pub weights: BTreeMap<String, BTreeMap<String, u32>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+// Cumulative spend caps per client session, to halt runaway agent loops. A
+// session is whatever the client sends in `header`; requests without it are not capped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionCapSettings {
+    #[serde(default = "default_session_header")]
+    pub header: String,
+    // Input plus output tokens the session may use
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    // Spend in the currency of model `pricing`; models without pricing add nothing
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+    // Sessions idle for this long start over
+    #[serde(default = "default_session_idle_secs")]
+    pub idle_secs: u64,
+}
+
+impl Default for SessionCapSettings {
+    fn default() -> Self {
+        Self { header: default_session_header(), max_tokens: None, max_cost: None, idle_secs: default_session_idle_secs() }
+    }
+}
+
+impl SessionCapSettings {
+    pub fn enabled(&self) -> bool {
+        self.max_tokens.is_some() || self.max_cost.is_some()
+    }
+}
+
+// Spend cap of a whole tenant, whichever of its keys and sessions the requests use
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TenantBudget {
+    // Input plus output tokens per period
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    // Spend in the currency of model `pricing` per period
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+    // The budget starts over this long after the period's first request
+    #[serde(default = "default_budget_period_secs")]
+    pub period_secs: u64,
+}
+
+impl Default for TenantBudget {
+    fn default() -> Self {
+        Self { max_tokens: None, max_cost: None, period_secs: default_budget_period_secs() }
+    }
+}
+
+impl TenantBudget {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_tokens.is_none() && self.max_cost.is_none()
+    }
+}
+
+fn default_budget_period_secs() -> u64 { 86_400 }
+
+// How the router identifies itself and its callers to upstreams, for providers
+// that attribute abuse per user. Templates may use {version}, {request_id} and
+// {model} (the router's model name).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamIdentity {
+    // User-Agent header; rewrite_header can still replace it per model
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    // Sent as OpenAI `user` or Anthropic `metadata.user_id` when the client set
+    // none; Gemini has no such field
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+impl Default for UpstreamIdentity {
+    fn default() -> Self {
+        Self { user_agent: default_user_agent(), user: None }
+    }
+}
+
+impl UpstreamIdentity {
+    /// Fill the placeholders of `template` for one upstream request.
+    pub fn render(template: &str, request_id: &str, model: &str) -> String {
+        template
+            .replace("{version}", env!("CARGO_PKG_VERSION"))
+            .replace("{request_id}", request_id)
+            .replace("{model}", model)
+    }
+}
+
+// Run searches for clients that ask for hosted web search (OpenAI web_search_options,
+// Anthropic web_search tools) when the upstream cannot do it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSearchSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // Search API endpoint, called with GET
+    #[serde(default)]
+    pub url: String,
+    // Query parameter carrying the search terms
+    #[serde(default = "default_web_search_query_param")]
+    pub query_param: String,
+    // Query parameter carrying max_results; omitted when empty
+    #[serde(default = "default_web_search_count_param")]
+    pub count_param: String,
+    // Fixed query parameters, e.g. format: json
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    // Request headers such as API keys; values may reference environment variables as ${VAR}
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // jq filter turning the API response into [{title, url, snippet}]
+    #[serde(default = "default_web_search_results")]
+    pub results: JqFilter,
+    #[serde(default = "default_web_search_max_results")]
+    pub max_results: u32,
+    // Searches per request; a lower max_uses from the client wins
+    #[serde(default = "default_web_search_max_uses")]
+    pub max_uses: u32,
+    #[serde(default = "default_web_search_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for WebSearchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            query_param: default_web_search_query_param(),
+            count_param: default_web_search_count_param(),
+            params: BTreeMap::new(),
+            headers: BTreeMap::new(),
+            results: default_web_search_results(),
+            max_results: default_web_search_max_results(),
+            max_uses: default_web_search_max_uses(),
+            timeout_ms: default_web_search_timeout_ms(),
+        }
+    }
+}
+
+// MCP servers whose tools are offered to the model on non-streaming requests and run by the router
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpSettings {
+    #[serde(default)]
+    pub servers: Vec<McpServer>,
+    // Model turns per request that may call MCP tools; the response after the last one is returned as is
+    #[serde(default = "default_mcp_max_rounds")]
+    pub max_rounds: u32,
+    // How long a server's tool list is reused before it is listed again
+    #[serde(default = "default_mcp_tools_ttl_secs")]
+    pub tools_ttl_secs: u64,
+}
+
+impl Default for McpSettings {
+    fn default() -> Self {
+        Self { servers: Vec::new(), max_rounds: default_mcp_max_rounds(), tools_ttl_secs: default_mcp_tools_ttl_secs() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpServer {
+    // Prefix of the server's tools as seen by the model: <name>__<tool>
+    pub name: String,
+    // Streamable HTTP endpoint, e.g. http://localhost:3000/mcp
+    pub url: String,
+    // Extra request headers such as Authorization; values may reference environment variables as ${VAR}
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // Per-call timeout
+    #[serde(default = "default_mcp_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+// Download http(s) image URLs for upstreams that only take inline image data (Gemini)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageFetchSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // Hosts that may be fetched ("example.com" or "*.example.com"); empty allows any public host
+    #[serde(default)]
+    pub allow_hosts: Vec<String>,
+    // Hosts that are never fetched, checked before allow_hosts
+    #[serde(default)]
+    pub deny_hosts: Vec<String>,
+    // Permit hosts resolving to loopback, private or link-local addresses
+    #[serde(default)]
+    pub allow_private: bool,
+    // Largest image downloaded, in bytes
+    #[serde(default = "default_image_fetch_max_bytes")]
+    pub max_bytes: u64,
+    // Most distinct image URLs fetched for one request
+    #[serde(default = "default_image_fetch_max_images")]
+    pub max_images: usize,
+    // Per-image download timeout
+    #[serde(default = "default_image_fetch_timeout_ms")]
+    pub timeout_ms: u64,
+    // Accepted Content-Type values
+    #[serde(default = "default_image_fetch_content_types")]
+    pub content_types: Vec<String>,
+}
+
+impl Default for ImageFetchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allow_hosts: Vec::new(),
+            deny_hosts: Vec::new(),
+            allow_private: false,
+            max_bytes: default_image_fetch_max_bytes(),
+            max_images: default_image_fetch_max_images(),
+            timeout_ms: default_image_fetch_timeout_ms(),
+            content_types: default_image_fetch_content_types(),
+        }
+    }
+}
+
+// Retry non-streaming requests an upstream refused on a group member flagged `uncensored`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefusalFallbackSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // Case-insensitive regexes matched against the response text
+    #[serde(default = "default_refusal_patterns", deserialize_with = "case_insensitive_patterns")]
+    pub patterns: Vec<Pattern>,
+    // Finish/stop reasons (in the client's format) that mark a refusal, compared case-insensitively
+    #[serde(default = "default_refusal_finish_reasons")]
+    pub finish_reasons: Vec<String>,
+    // Retries after the first refusal; each goes to a member not tried yet
+    #[serde(default = "default_refusal_max_retries")]
+    pub max_retries: u32,
+}
+
+impl Default for RefusalFallbackSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            patterns: default_refusal_patterns(),
+            finish_reasons: default_refusal_finish_reasons(),
+            max_retries: default_refusal_max_retries(),
+        }
+    }
+}
+
+/// A regex compiled when the config is loaded, so a bad pattern fails the load
+/// and requests never compile one. Serializes as written.
+#[derive(Debug, Clone)]
+pub struct Pattern(regex::Regex);
+
+impl Pattern {
+    pub fn new(source: &str, case_insensitive: bool) -> Result<Self, regex::Error> {
+        regex::RegexBuilder::new(source).case_insensitive(case_insensitive).build().map(Self)
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Pattern::new(&source, false).map_err(|e| serde::de::Error::custom(format!("invalid pattern {:?}: {}", source, e)))
+    }
+}
+
+fn case_insensitive_patterns<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<Pattern>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|source| {
+            Pattern::new(source, true).map_err(|e| serde::de::Error::custom(format!("invalid pattern {:?}: {}", source, e)))
+        })
+        .collect()
+}
+
+// Persist health/breaker/weight state so a restart keeps tripped circuits open
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthStateSettings {
+    // State file; persistence is off when unset
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default = "default_health_save_interval_secs")]
+    pub save_interval_secs: u64,
+}
+
+impl Default for HealthStateSettings {
+    fn default() -> Self {
+        Self { path: None, save_interval_secs: default_health_save_interval_secs() }
+    }
+}
+
+// Buffer streamed responses so clients can resume them after a disconnect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseStoreSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // How long a finished stream stays resumable
+    #[serde(default = "default_response_ttl_secs")]
+    pub ttl_secs: u64,
+    // How long a stream that never finishes stays buffered
+    #[serde(default = "default_response_pending_ttl_secs")]
+    pub pending_ttl_secs: u64,
+    // Streams kept at once; the oldest, finished ones first, make room
+    #[serde(default = "default_response_max_streams")]
+    pub max_streams: usize,
+}
+
+impl Default for ResponseStoreSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default_response_ttl_secs(),
+            pending_ttl_secs: default_response_pending_ttl_secs(),
+            max_streams: default_response_max_streams(),
+        }
+    }
+}
+
+/// How a client-facing stream is closed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminatorMode {
+    /// Emit exactly one terminator at the end of the stream
+    #[default]
+    Ensure,
+    /// Forward the terminator only if upstream (or conversion) produced one
+    Passthrough,
+    /// Never emit a terminator
+    Suppress,
+}
+
+// Terminator rules per client-facing API type. OpenAI streams end with
+// `data: [DONE]`, Anthropic streams with `message_stop`; Gemini SSE has none.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SseTerminators {
+    #[serde(default)]
+    pub openai: TerminatorMode,
+    #[serde(default)]
+    pub anthropic: TerminatorMode,
+}
+
+impl SseTerminators {
+    pub fn for_target(&self, api_type: &ApiType) -> TerminatorMode {
+        match api_type {
+            ApiType::OpenAI => self.openai,
+            ApiType::Anthropic => self.anthropic,
+            ApiType::Gemini => TerminatorMode::Suppress,
+        }
+    }
+}
+
+/// How streamed tool-call arguments reach the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolArgumentsMode {
+    /// Hold fragments back and send the arguments once they parse as JSON
+    Aggregate,
+    /// Forward every fragment as it arrives
+    Passthrough,
+}
+
+impl ToolArgumentsMode {
+    /// Gemini clients expect complete `functionCall.args` objects; the others take fragments.
+    pub fn default_for(api_type: &ApiType) -> Self {
+        match api_type {
+            ApiType::Gemini => ToolArgumentsMode::Aggregate,
+            ApiType::OpenAI | ApiType::Anthropic => ToolArgumentsMode::Passthrough,
+        }
+    }
+}
+
+// Tool-argument handling per client-facing API type; LLMParams.tool_arguments
+// overrides it for a single model
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolArgumentSettings {
+    #[serde(default)]
+    pub openai: Option<ToolArgumentsMode>,
+    #[serde(default)]
+    pub anthropic: Option<ToolArgumentsMode>,
+    #[serde(default)]
+    pub gemini: Option<ToolArgumentsMode>,
+}
+
+impl ToolArgumentSettings {
+    pub fn for_target(&self, api_type: &ApiType) -> ToolArgumentsMode {
+        let mode = match api_type {
+            ApiType::OpenAI => self.openai,
+            ApiType::Anthropic => self.anthropic,
+            ApiType::Gemini => self.gemini,
+        };
+        mode.unwrap_or_else(|| ToolArgumentsMode::default_for(api_type))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListenerSettings {
+    // Defaults to --ip
+    #[serde(default)]
+    pub ip: Option<String>,
+    pub port: u16,
+    // Check client tokens, virtual keys and tenant keys as the main port does;
+    // without it every request uses the root config
+    #[serde(default = "default_true")]
+    pub auth: bool,
+    // Endpoint sets served; /health is always served
+    #[serde(default = "default_listener_routes")]
+    pub routes: Vec<RouteSet>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteSet {
+    // Chat endpoints, /v1/models, /v1/capabilities and stream resumption
+    Inference,
+    // /admin/*
+    Admin,
+    // /metrics
+    Metrics,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoutingStrategy {
+    RoundRobin,
+    LeastConn,
+    Random,
+    Explore,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelGroup {
+    pub name: String,
+    
+    pub models: Vec<ModelGroupEntry>,
+    // Generation parameters filled into requests for this group that leave them out
+    #[serde(default, skip_serializing_if = "GenerationDefaults::is_empty")]
+    pub defaults: GenerationDefaults,
+    // Weight recovery of members after failures
+    #[serde(default, skip_serializing_if = "Recovery::is_default")]
+    pub recovery: Recovery,
+}
+
+// How a member's health factor climbs back after failures
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recovery {
+    // Successes in a row that restore full weight and close a half-open
+    // circuit; unset leaves both to the schedule and the first success
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success_threshold: Option<u32>,
+    #[serde(default)]
+    pub schedule: RecoverySchedule,
+}
+
+impl Recovery {
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecoverySchedule {
+    // A fixed step per success
+    #[default]
+    Linear,
+    // The factor doubles per success, undoing one halving each time
+    Exponential,
+}
+
+// Typed, so each value lands in the right field of every API format
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationDefaults {
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub top_p: Option<f64>,
+    // Also Gemini's maxOutputTokens; Anthropic clients always send max_tokens
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+impl GenerationDefaults {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelGroupEntry {
+    pub name: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    // Optional jq selector; when present and non-empty, request must satisfy it
+    #[serde(default)]
+    pub selector: Option<String>,
+    // Takes over requests other members refused (see refusal_fallback)
+    #[serde(default)]
+    pub uncensored: bool,
+}
+
+fn default_weight() -> u32 {
+    100
+}
+
+fn default_refusal_patterns() -> Vec<Pattern> {
+    [
+        r"^\s*I['’]m sorry, but I can(?:no|['’])t (?:help|assist|comply)",
+        r"^\s*I can(?:no|['’])t (?:help|assist) with (?:that|this)",
+    ]
+    .iter()
+    .map(|p| Pattern::new(p, true).expect("valid default refusal pattern"))
+    .collect()
+}
+
+fn default_refusal_finish_reasons() -> Vec<String> {
+    ["content_filter", "refusal", "SAFETY", "PROHIBITED_CONTENT", "BLOCKLIST", "SPII"].iter().map(|r| r.to_string()).collect()
+}
+
+fn default_image_fetch_max_bytes() -> u64 { 10 * 1024 * 1024 }
+
+fn default_image_fetch_max_images() -> usize { 16 }
+
+fn default_image_fetch_timeout_ms() -> u64 { 5_000 }
+
+fn default_image_fetch_content_types() -> Vec<String> {
+    ["image/png", "image/jpeg", "image/webp", "image/gif", "image/heic", "image/heif"].iter().map(|t| t.to_string()).collect()
+}
+
+fn default_refusal_max_retries() -> u32 { 1 }
+
+fn default_validation_nudge() -> String {
+    "Your previous answer was rejected: {error}. Answer again, following the required output format exactly and with nothing else.".to_string()
+}
+
+fn default_json_object() -> Value { json!({}) }
+
+fn default_true() -> bool { true }
+
+fn default_reasoning_strip() -> Vec<String> {
+    ["temperature", "top_p", "presence_penalty", "frequency_penalty", "logprobs", "top_logprobs", "logit_bias"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_forward_headers() -> Vec<String> {
+    vec!["x-ratelimit-*".to_string(), "retry-after".to_string(), "anthropic-ratelimit-*".to_string()]
+}
+
+fn default_direct_conversions() -> Vec<ConversionPair> {
+    vec![
+        ConversionPair { from: ApiType::Anthropic, to: ApiType::Gemini },
+        ConversionPair { from: ApiType::Gemini, to: ApiType::Anthropic },
+    ]
+}
+
+fn default_retry_queue_max_waiting() -> usize { 100 }
+
+fn default_retry_queue_max_wait_ms() -> u64 { 30_000 }
+
+fn default_ttfb_ratio() -> f64 { 3.0 }
+
+fn default_ttfb_min_ms() -> u64 { 2_000 }
+
+fn default_ttfb_samples() -> u32 { 3 }
+
+fn default_stream_failover_max_retries() -> u32 { 2 }
+
+fn default_reload_probe_timeout_ms() -> u64 { 15_000 }
+
+fn default_exploration_percent() -> u32 { 5 }
+
+// axum's own default for the Json extractor
+fn default_max_request_bytes() -> usize { 2 * 1024 * 1024 }
+
+fn default_listener_routes() -> Vec<RouteSet> {
+    vec![RouteSet::Inference, RouteSet::Admin, RouteSet::Metrics]
+}
+
+fn default_response_ttl_secs() -> u64 { 300 }
+
+fn default_response_pending_ttl_secs() -> u64 { 3600 }
+
+fn default_response_max_streams() -> usize { 1000 }
+
+fn default_tight_budget_ms() -> u64 { 10_000 }
+
+fn default_health_save_interval_secs() -> u64 { 10 }
+
+fn default_max_hops() -> u32 { 5 }
+
+fn default_user_agent() -> String { "llm-router/{version}".to_string() }
+
+fn default_session_header() -> String { "x-session-id".to_string() }
+
+fn default_session_idle_secs() -> u64 { 3600 }
+
+fn default_mcp_max_rounds() -> u32 { 5 }
+
+fn default_mcp_tools_ttl_secs() -> u64 { 300 }
+
+fn default_mcp_timeout_ms() -> u64 { 30_000 }
+
+fn default_web_search_query_param() -> String { "q".to_string() }
+
+fn default_web_search_count_param() -> String { "count".to_string() }
+
+// Understands Brave (.web.results), SearXNG and most others (.results)
+fn default_web_search_results() -> JqFilter {
+    JqFilter::compile("(.web.results? // .results? // []) | map({title: (.title // \"\"), url: (.url // .link // \"\"), snippet: (.description // .content // .snippet // \"\")})")
+        .expect("valid default web_search results filter")
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ModelConfig {
-    pub model_name: String,
-    pub llm_params: LLMParams,
-}
+fn default_web_search_max_results() -> u32 { 5 }
+
+fn default_web_search_max_uses() -> u32 { 3 }
+
+fn default_web_search_timeout_ms() -> u64 { 10_000 }
+
+fn default_timestamp_header() -> String { "X-Timestamp".to_string() }
+
+fn default_digest_header() -> String { "X-Content-SHA256".to_string() }
+
+fn default_signature_header() -> String { "X-Signature".to_string() }
+
+impl Config {
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        Self::from_file_with_overrides(path, &[])
+    }
+
+    /// Load `path` with `key.path=value` overrides (from `--set`) applied to the
+    /// YAML before it is parsed and validated.
+    pub fn from_file_with_overrides(path: &str, overrides: &[String]) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let mut value: serde_yaml::Value = serde_yaml::from_str(&content)?;
+        apply_weights_overlay(&mut value, &read_weights_overlay(&Self::weights_overlay_path(path))?);
+        for set in overrides {
+            apply_override(&mut value, set).map_err(|e| anyhow::anyhow!("Invalid --set '{}': {}", set, e))?;
+        }
+        let tenants = value.get("tenants").cloned();
+        let mut unknown = Vec::new();
+        let mut config: Config = serde_ignored::deserialize(value, |path| unknown.push(path.to_string()))?;
+        // Keys under a flattened field never reach the callback, so each tenant's
+        // config is checked again on its own
+        if let Some(serde_yaml::Value::Sequence(tenants)) = tenants {
+            for (i, tenant) in tenants.into_iter().enumerate() {
+                let serde_yaml::Value::Mapping(mut tenant) = tenant else { continue };
+                for key in ["name", "keys", "budget"] {
+                    tenant.remove(key);
+                }
+                let _: Config = serde_ignored::deserialize(serde_yaml::Value::Mapping(tenant), |path| {
+                    unknown.push(format!("tenants.{}.{}", i, path))
+                })?;
+            }
+        }
+        config.unknown_keys = unknown;
+        for key in &config.unknown_keys {
+            tracing::warn!(path = %key, "Ignoring unknown config key");
+        }
+        Self::prepare(&mut config)?;
+        for tenant in &mut config.tenants {
+            Self::prepare(&mut tenant.config).map_err(|e| anyhow::anyhow!("Tenant '{}': {}", tenant.name, e))?;
+        }
+        Self::resolve_tenants(&mut config)?;
+        Ok(config)
+    }
+
+    // Normalize and validate one namespace (the root config or a tenant)
+    fn prepare(config: &mut Config) -> anyhow::Result<()> {
+        // Normalize rewrite_body/rewrite_header allowing stringified JSON in YAML
+        for mc in &mut config.model_list {
+            normalize_llm_params(&mut mc.llm_params);
+        }
+
+        Self::resolve_auth_headers(config)?;
+
+        Self::validate_rewrite_headers(config)?;
+        
+        Self::validate_model_names(config)?;
+        
+        Self::validate_model_group_names(config)?;
+        
+        Self::validate_model_group_model_names(config)?;
+
+        Self::validate_model_group_weights(config)?;
+
+        // Validate selectors in model groups (non-empty only)
+        Self::validate_model_group_selectors(config)?;
+
+        Self::validate_nested_groups(config)?;
+
+        Self::resolve_virtual_keys(config)?;
+
+        Self::validate_default_model(config)?;
+
+        Self::resolve_mcp_servers(config)?;
+
+        Self::resolve_web_search(config)?;
+
+        Self::validate_upstream_identity(config)?;
+
+        Self::validate_session_caps(config)?;
+
+        Self::validate_strategy_rules(config)?;
+
+        Self::validate_ttfb_health(config)?;
+        Self::validate_reload_probes(config)?;
+        Self::validate_group_defaults(config)?;
+        Self::validate_group_recovery(config)?;
+        Self::validate_exploration(config)?;
+        Self::validate_max_request_bytes(config)?;
+        Self::validate_max_hops(config)?;
+        Self::validate_keepalive(config)?;
+        Self::validate_listeners(config)?;
+        Self::validate_stream_delta_chars(config)?;
+        Self::validate_max_output_tokens(config)?;
+        Self::validate_direct_conversions(config)?;
+        
+        Ok(())
+    }
+    
+    /// Where weights persisted at runtime are kept for the config at `path`.
+    /// The config file itself is never written: it holds comments and
+    /// `${VAR}` references that a serialized `Config` would lose.
+    pub fn weights_overlay_path(path: &str) -> String {
+        format!("{}.weights.yaml", path)
+    }
+
+    /// Record the member weights of `group` in the weights overlay of the
+    /// config at `path`, replacing the overlay atomically. The overlay is
+    /// applied on every load, before `--set` overrides.
+    pub fn persist_group_weights(path: &str, group: &str, weights: &[(String, u32)]) -> anyhow::Result<()> {
+        let overlay_path = Self::weights_overlay_path(path);
+        let mut overlay = read_weights_overlay(&overlay_path)?;
+        overlay.insert(group.to_string(), weights.iter().cloned().collect());
+        let content = serde_yaml::to_string(&overlay)?;
+        let dir = std::path::Path::new(&overlay_path)
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| std::path::Path::new("."));
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+        std::io::Write::write_all(&mut tmp, content.as_bytes())?;
+        tmp.persist(&overlay_path)?;
+        Ok(())
+    }
+
+    // Expand ${VAR} in auth_headers/signing and make sure every header is sendable
+    fn resolve_auth_headers(config: &mut Config) -> anyhow::Result<()> {
+        for mc in &mut config.model_list {
+            for (name, value) in mc.llm_params.auth_headers.iter_mut() {
+                reqwest::header::HeaderName::try_from(name.as_str()).map_err(|e| {
+                    anyhow::anyhow!("Invalid auth header name '{}' for model '{}': {}", name, mc.model_name, e)
+                })?;
+                *value = interpolate_env(value).map_err(|e| {
+                    anyhow::anyhow!("Auth header '{}' for model '{}': {}", name, mc.model_name, e)
+                })?;
+                reqwest::header::HeaderValue::from_str(value).map_err(|e| {
+                    anyhow::anyhow!("Invalid auth header value for '{}' in model '{}': {}", name, mc.model_name, e)
+                })?;
+            }
+            if let Some(RequestSigning::HmacSha256 { secret, timestamp_header, digest_header, signature_header }) =
+                &mut mc.llm_params.signing
+            {
+                *secret = interpolate_env(secret)
+                    .map_err(|e| anyhow::anyhow!("Signing secret for model '{}': {}", mc.model_name, e))?;
+                for name in [timestamp_header, digest_header, signature_header] {
+                    reqwest::header::HeaderName::try_from(name.as_str()).map_err(|e| {
+                        anyhow::anyhow!("Invalid signing header name '{}' for model '{}': {}", name, mc.model_name, e)
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Reject rewrite_header entries reqwest could not send, and auth header
+    // overrides the model did not opt into
+    fn validate_rewrite_headers(config: &Config) -> anyhow::Result<()> {
+        for mc in &config.model_list {
+            let map = match &mc.llm_params.rewrite_header {
+                Value::Object(map) => map,
+                Value::Null => continue,
+                other => {
+                    return Err(anyhow::anyhow!(
+                        "rewrite_header for model '{}' must be a map of header names to values, got {}",
+                        mc.model_name,
+                        other
+                    ));
+                }
+            };
+            for (name, value) in map {
+                let header = reqwest::header::HeaderName::try_from(name.as_str()).map_err(|e| {
+                    anyhow::anyhow!("rewrite_header for model '{}': '{}' is not a valid header name: {}", mc.model_name, name, e)
+                })?;
+                let is_auth = AUTH_HEADER_NAMES.contains(&header.as_str())
+                    || mc.llm_params.auth_headers.keys().any(|k| k.eq_ignore_ascii_case(name));
+                if is_auth && !mc.llm_params.rewrite_auth_headers {
+                    return Err(anyhow::anyhow!(
+                        "rewrite_header for model '{}' changes auth header '{}'; set rewrite_auth_headers: true to allow it",
+                        mc.model_name,
+                        name
+                    ));
+                }
+                let text = match value {
+                    Value::Null => continue,
+                    Value::String(s) => s.clone(),
+                    Value::Number(n) => n.to_string(),
+                    Value::Bool(b) => b.to_string(),
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "rewrite_header for model '{}': '{}' must be a string, number, boolean or null",
+                            mc.model_name,
+                            name
+                        ));
+                    }
+                };
+                reqwest::header::HeaderValue::from_str(&text).map_err(|e| {
+                    anyhow::anyhow!("rewrite_header for model '{}': invalid value for '{}': {}", mc.model_name, name, e)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    // Expand ${VAR} in keys and check defaults point at something routable
+    fn resolve_virtual_keys(config: &mut Config) -> anyhow::Result<()> {
+        let mut seen = std::collections::HashSet::new();
+        for (idx, vk) in config.virtual_keys.iter_mut().enumerate() {
+            vk.key = interpolate_env(&vk.key).map_err(|e| anyhow::anyhow!("Virtual key #{}: {}", idx, e))?;
+            if vk.key.is_empty() {
+                return Err(anyhow::anyhow!("Virtual key #{} is empty", idx));
+            }
+            if !seen.insert(vk.key.clone()) {
+                return Err(anyhow::anyhow!("Virtual key #{} is a duplicate", idx));
+            }
+            if !vk.defaults.is_object() {
+                return Err(anyhow::anyhow!("Virtual key #{}: defaults must be a mapping", idx));
+            }
+            if vk.stream_tokens_per_sec.is_some_and(|rate| !rate.is_finite() || rate <= 0.0) {
+                return Err(anyhow::anyhow!("Virtual key #{}: stream_tokens_per_sec must be a positive number", idx));
+            }
+            if let Some(model) = &vk.default_model
+                && !config.model_list.iter().any(|m| &m.model_name == model)
+                && !config.router_settings.model_groups.iter().any(|g| &g.name == model)
+            {
+                return Err(anyhow::anyhow!(
+                    "Virtual key #{}: default_model '{}' is neither a model nor a model group",
+                    idx, model
+                ));
+            }
+            for model in vk.allowed_models.iter().flatten() {
+                if !config.model_list.iter().any(|m| &m.model_name == model)
+                    && !config.router_settings.model_groups.iter().any(|g| &g.name == model)
+                {
+                    return Err(anyhow::anyhow!(
+                        "Virtual key #{}: allowed model '{}' is neither a model nor a model group",
+                        idx, model
+                    ));
+                }
+            }
+            for model in &vk.denied_models {
+                if !config.model_list.iter().any(|m| &m.model_name == model)
+                    && !config.router_settings.model_groups.iter().any(|g| &g.name == model)
+                {
+                    return Err(anyhow::anyhow!(
+                        "Virtual key #{}: denied model '{}' is neither a model nor a model group",
+                        idx, model
+                    ));
+                }
+            }
+            if let Some(model) = &vk.default_model
+                && !vk.is_listed(model)
+            {
+                return Err(anyhow::anyhow!("Virtual key #{}: default_model '{}' is not in allowed_models", idx, model));
+            }
+            if let Some(model) = &vk.default_model
+                && vk.denies(model)
+            {
+                return Err(anyhow::anyhow!("Virtual key #{}: default_model '{}' is in denied_models", idx, model));
+            }
+        }
+        Ok(())
+    }
+
+    // Expand ${VAR} in MCP headers; server names become tool prefixes, so they must be unique and plain
+    fn resolve_mcp_servers(config: &mut Config) -> anyhow::Result<()> {
+        let mut seen = std::collections::HashSet::new();
+        for server in &mut config.router_settings.mcp.servers {
+            if server.name.is_empty() || server.name.contains("__") || !server.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                return Err(anyhow::anyhow!("MCP server name '{}' must be non-empty letters, digits, '-' or '_' without '__'", server.name));
+            }
+            if !seen.insert(server.name.clone()) {
+                return Err(anyhow::anyhow!("Duplicate MCP server name '{}'", server.name));
+            }
+            reqwest::Url::parse(&server.url).map_err(|e| anyhow::anyhow!("Invalid url for MCP server '{}': {}", server.name, e))?;
+            for (name, value) in server.headers.iter_mut() {
+                reqwest::header::HeaderName::try_from(name.as_str())
+                    .map_err(|e| anyhow::anyhow!("Invalid header name '{}' for MCP server '{}': {}", name, server.name, e))?;
+                *value = interpolate_env(value).map_err(|e| anyhow::anyhow!("Header '{}' for MCP server '{}': {}", name, server.name, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Expand ${VAR} in search API headers and check the endpoint and filter
+    fn resolve_web_search(config: &mut Config) -> anyhow::Result<()> {
+        let settings = &mut config.router_settings.web_search;
+        if !settings.enabled {
+            return Ok(());
+        }
+        reqwest::Url::parse(&settings.url).map_err(|e| anyhow::anyhow!("Invalid web_search url '{}': {}", settings.url, e))?;
+        for (name, value) in settings.headers.iter_mut() {
+            reqwest::header::HeaderName::try_from(name.as_str())
+                .map_err(|e| anyhow::anyhow!("Invalid web_search header name '{}': {}", name, e))?;
+            *value = interpolate_env(value).map_err(|e| anyhow::anyhow!("web_search header '{}': {}", name, e))?;
+        }
+        Ok(())
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
-pub enum ApiType {
-    OpenAI,
-    Anthropic,
-    Gemini,
-}
+    fn validate_upstream_identity(config: &Config) -> anyhow::Result<()> {
+        let identity = &config.router_settings.upstream_identity;
+        let user_agent = UpstreamIdentity::render(&identity.user_agent, "request-id", "model");
+        if identity.user_agent.trim().is_empty() || reqwest::header::HeaderValue::from_str(&user_agent).is_err() {
+            return Err(anyhow::anyhow!("Invalid upstream_identity user_agent: '{}'", identity.user_agent));
+        }
+        if identity.user.as_ref().is_some_and(|user| user.trim().is_empty()) {
+            return Err(anyhow::anyhow!("upstream_identity user must not be empty"));
+        }
+        Ok(())
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LLMParams {
-    pub api_type: ApiType,
-    pub model: String,
-    pub api_base: String,
-    pub api_key: String,
-    #[serde(default = "default_json_object")]
-    pub rewrite_body: Value,
-    #[serde(default = "default_json_object")]
-    pub rewrite_header: Value,
-}
+    fn validate_session_caps(config: &Config) -> anyhow::Result<()> {
+        let caps = &config.router_settings.session_caps;
+        reqwest::header::HeaderName::try_from(caps.header.as_str())
+            .map_err(|e| anyhow::anyhow!("Invalid session_caps header '{}': {}", caps.header, e))?;
+        if caps.max_cost.is_some_and(|cost| !cost.is_finite() || cost < 0.0) {
+            return Err(anyhow::anyhow!("session_caps max_cost must be a non-negative number"));
+        }
+        Ok(())
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RouterSettings {
-    pub strategy: RoutingStrategy,
-    pub model_groups: Vec<ModelGroup>,
-}
+    fn validate_ttfb_health(config: &Config) -> anyhow::Result<()> {
+        let ttfb = &config.router_settings.ttfb_health;
+        if !ttfb.ratio.is_finite() || ttfb.ratio < 1.0 {
+            return Err(anyhow::anyhow!("ttfb_health ratio must be at least 1"));
+        }
+        if ttfb.samples == 0 {
+            return Err(anyhow::anyhow!("ttfb_health samples must be at least 1"));
+        }
+        Ok(())
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum RoutingStrategy {
-    RoundRobin,
-    LeastConn,
-    Random,
-}
+    fn validate_reload_probes(config: &Config) -> anyhow::Result<()> {
+        let probes = &config.router_settings.reload_probes;
+        if probes.enabled && probes.timeout_ms == 0 {
+            return Err(anyhow::anyhow!("reload_probes timeout_ms must be greater than 0"));
+        }
+        Ok(())
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ModelGroup {
-    pub name: String,
-    
-    pub models: Vec<ModelGroupEntry>,
-}
+    fn validate_listeners(config: &Config) -> anyhow::Result<()> {
+        let listeners = &config.router_settings.listeners;
+        for (i, listener) in listeners.iter().enumerate() {
+            if listener.port == 0 {
+                return Err(anyhow::anyhow!("listeners[{}] needs a port", i));
+            }
+            if listeners[..i].iter().any(|other| other.port == listener.port && other.ip == listener.ip) {
+                return Err(anyhow::anyhow!("listeners[{}] repeats port {}", i, listener.port));
+            }
+            if let Some(ip) = &listener.ip
+                && ip.parse::<std::net::IpAddr>().is_err()
+            {
+                return Err(anyhow::anyhow!("listeners[{}] has an invalid ip '{}'", i, ip));
+            }
+            if !listener.auth && listener.routes.contains(&RouteSet::Admin) {
+                return Err(anyhow::anyhow!(
+                    "listeners[{}] serves admin routes without auth; drop 'admin' from its routes or set auth: true",
+                    i
+                ));
+            }
+        }
+        Ok(())
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ModelGroupEntry {
-    pub name: String,
-    #[serde(default = "default_weight")]
-    pub weight: u32,
-    // Optional jq selector; when present and non-empty, request must satisfy it
-    #[serde(default)]
-    pub selector: Option<String>,
-}
+    fn validate_exploration(config: &Config) -> anyhow::Result<()> {
+        if config.router_settings.exploration_percent > 100 {
+            return Err(anyhow::anyhow!("exploration_percent must be between 0 and 100"));
+        }
+        Ok(())
+    }
 
-fn default_weight() -> u32 {
-    100
-}
+    fn validate_max_request_bytes(config: &Config) -> anyhow::Result<()> {
+        if config.router_settings.max_request_bytes == 0 {
+            return Err(anyhow::anyhow!("max_request_bytes must be greater than 0"));
+        }
+        Ok(())
+    }
 
-fn default_json_object() -> Value { json!({}) }
+    fn validate_max_hops(config: &Config) -> anyhow::Result<()> {
+        if config.router_settings.max_hops == 0 {
+            return Err(anyhow::anyhow!("max_hops must be at least 1; 0 would reject every request"));
+        }
+        Ok(())
+    }
 
-impl Config {
-    pub fn from_file(path: &str) -> anyhow::Result<Self> {
-        let content = std::fs::read_to_string(path)?;
-        let mut config: Config = serde_yaml::from_str(&content)?;
-        
-        // Normalize rewrite_body/rewrite_header allowing stringified JSON in YAML
-        for mc in &mut config.model_list {
-            normalize_llm_params(&mut mc.llm_params);
+    fn validate_keepalive(config: &Config) -> anyhow::Result<()> {
+        if let Some(mc) = config.model_list.iter().find(|mc| mc.llm_params.keepalive_ms == Some(0)) {
+            return Err(anyhow::anyhow!("Model '{}' keepalive_ms must be greater than 0", mc.model_name));
         }
-        
-        Self::validate_model_names(&config)?;
-        
-        Self::validate_model_group_names(&config)?;
-        
-        Self::validate_model_group_model_names(&config)?;
+        Ok(())
+    }
 
-        // Validate selectors in model groups (non-empty only)
-        Self::validate_model_group_selectors(&config)?;
-        
-        Ok(config)
+    fn validate_stream_delta_chars(config: &Config) -> anyhow::Result<()> {
+        if config.router_settings.stream_delta_chars == Some(0) {
+            return Err(anyhow::anyhow!("stream_delta_chars must be at least 1"));
+        }
+        if let Some(mc) = config.model_list.iter().find(|mc| mc.llm_params.stream_delta_chars == Some(0)) {
+            return Err(anyhow::anyhow!("stream_delta_chars for model '{}' must be at least 1", mc.model_name));
+        }
+        Ok(())
     }
-    
+
+    fn validate_max_output_tokens(config: &Config) -> anyhow::Result<()> {
+        if let Some(mc) = config.model_list.iter().find(|mc| mc.llm_params.max_output_tokens == Some(0)) {
+            return Err(anyhow::anyhow!("max_output_tokens for model '{}' must be greater than 0", mc.model_name));
+        }
+        Ok(())
+    }
+
+    fn validate_group_defaults(config: &Config) -> anyhow::Result<()> {
+        for group in &config.router_settings.model_groups {
+            let defaults = &group.defaults;
+            if defaults.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+                return Err(anyhow::anyhow!("Model group '{}' default temperature must be between 0 and 2", group.name));
+            }
+            if defaults.top_p.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+                return Err(anyhow::anyhow!("Model group '{}' default top_p must be between 0 and 1", group.name));
+            }
+            if defaults.max_tokens == Some(0) {
+                return Err(anyhow::anyhow!("Model group '{}' default max_tokens must be greater than 0", group.name));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_group_recovery(config: &Config) -> anyhow::Result<()> {
+        if let Some(group) = config.router_settings.model_groups.iter().find(|g| g.recovery.success_threshold == Some(0)) {
+            return Err(anyhow::anyhow!("Model group '{}' recovery success_threshold must be greater than 0", group.name));
+        }
+        Ok(())
+    }
+
+    fn validate_direct_conversions(config: &Config) -> anyhow::Result<()> {
+        for pair in &config.router_settings.direct_conversions {
+            if !pair.has_direct_converter() {
+                return Err(anyhow::anyhow!(
+                    "direct_conversions: no direct converter from {:?} to {:?}",
+                    pair.from, pair.to
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_strategy_rules(config: &Config) -> anyhow::Result<()> {
+        for (idx, rule) in config.router_settings.strategy_rules.iter().enumerate() {
+            let name = rule.name.clone().unwrap_or_else(|| format!("#{}", idx));
+            if let Some([start, end]) = rule.hours
+                && (start > 23 || end > 24 || start == end)
+            {
+                return Err(anyhow::anyhow!("Strategy rule {}: hours must be [start, end) within 0-24", name));
+            }
+            if let (Some(min), Some(max)) = (rule.min_rps, rule.max_rps)
+                && min > max
+            {
+                return Err(anyhow::anyhow!("Strategy rule {}: min_rps is above max_rps", name));
+            }
+            for (group, weights) in &rule.weights {
+                let model_group = config
+                    .router_settings
+                    .model_groups
+                    .iter()
+                    .find(|g| &g.name == group)
+                    .ok_or_else(|| anyhow::anyhow!("Strategy rule {}: unknown model group '{}'", name, group))?;
+                if let Some(member) = weights.keys().find(|m| !model_group.models.iter().any(|e| &e.name == *m)) {
+                    return Err(anyhow::anyhow!("Strategy rule {}: '{}' is not a member of group '{}'", name, member, group));
+                }
+                // The rule may not take every member out of rotation
+                let applied: Vec<u32> =
+                    model_group.models.iter().map(|e| weights.get(&e.name).copied().unwrap_or(e.weight)).collect();
+                checked_group_weights(group, &applied, None).map_err(|e| anyhow::anyhow!("Strategy rule {}: {}", name, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Expand tenant keys and make sure every client credential selects exactly one namespace
+    fn resolve_tenants(config: &mut Config) -> anyhow::Result<()> {
+        let mut names = std::collections::HashSet::new();
+        let mut credentials: std::collections::HashSet<String> =
+            config.virtual_keys.iter().map(|vk| vk.key.clone()).collect();
+        for tenant in &mut config.tenants {
+            if tenant.name.is_empty() {
+                return Err(anyhow::anyhow!("Tenant name must not be empty"));
+            }
+            // Stored streams and sessions are keyed `tenant/id`
+            if tenant.name.contains('/') {
+                return Err(anyhow::anyhow!("Tenant name '{}' must not contain '/'", tenant.name));
+            }
+            if tenant.budget.max_cost.is_some_and(|cost| !cost.is_finite() || cost < 0.0) {
+                return Err(anyhow::anyhow!("Tenant '{}' budget max_cost must be a non-negative number", tenant.name));
+            }
+            if tenant.budget.period_secs == 0 {
+                return Err(anyhow::anyhow!("Tenant '{}' budget period_secs must be greater than 0", tenant.name));
+            }
+            if !names.insert(tenant.name.clone()) {
+                return Err(anyhow::anyhow!("Duplicate tenant name: '{}'", tenant.name));
+            }
+            if !tenant.config.tenants.is_empty() {
+                return Err(anyhow::anyhow!("Tenant '{}' must not define tenants", tenant.name));
+            }
+            for key in &mut tenant.keys {
+                *key = interpolate_env(key).map_err(|e| anyhow::anyhow!("Tenant '{}': {}", tenant.name, e))?;
+                if key.is_empty() {
+                    return Err(anyhow::anyhow!("Tenant '{}' has an empty key", tenant.name));
+                }
+            }
+            for key in tenant.keys.iter().chain(tenant.config.virtual_keys.iter().map(|vk| &vk.key)) {
+                if !credentials.insert(key.clone()) {
+                    return Err(anyhow::anyhow!("Tenant '{}' reuses a key that is already in use", tenant.name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_default_model(config: &Config) -> anyhow::Result<()> {
+        if let Some(model) = &config.router_settings.default_model
+            && !config.model_list.iter().any(|m| &m.model_name == model)
+            && !config.router_settings.model_groups.iter().any(|g| &g.name == model)
+        {
+            return Err(anyhow::anyhow!(
+                "router_settings.default_model '{}' is neither a model nor a model group",
+                model
+            ));
+        }
+        Ok(())
+    }
+
     fn validate_model_names(config: &Config) -> anyhow::Result<()> {
         let mut seen_names = std::collections::HashSet::new();
         
@@ -142,6 +1818,58 @@ impl Config {
         Ok(())
     }
 
+    fn validate_model_group_weights(config: &mut Config) -> anyhow::Result<()> {
+        let normalize_to = config.router_settings.normalize_weights;
+        for model_group in &mut config.router_settings.model_groups {
+            let weights: Vec<u32> = model_group.models.iter().map(|m| m.weight).collect();
+            let weights = checked_group_weights(&model_group.name, &weights, normalize_to)?;
+            for (entry, weight) in model_group.models.iter_mut().zip(weights) {
+                entry.weight = weight;
+            }
+        }
+        Ok(())
+    }
+
+    // Group members may name other groups; reject reference cycles
+    fn validate_nested_groups(config: &Config) -> anyhow::Result<()> {
+        let model_names: std::collections::HashSet<&str> =
+            config.model_list.iter().map(|m| m.model_name.as_str()).collect();
+        let groups: std::collections::HashMap<&str, &ModelGroup> = config
+            .router_settings
+            .model_groups
+            .iter()
+            .map(|g| (g.name.as_str(), g))
+            .collect();
+
+        fn visit<'a>(
+            name: &'a str,
+            groups: &std::collections::HashMap<&'a str, &'a ModelGroup>,
+            model_names: &std::collections::HashSet<&str>,
+            path: &mut Vec<&'a str>,
+        ) -> anyhow::Result<()> {
+            if let Some(pos) = path.iter().position(|g| *g == name) {
+                let mut cycle = path[pos..].to_vec();
+                cycle.push(name);
+                return Err(anyhow::anyhow!("Cycle detected in nested model groups: {}", cycle.join(" -> ")));
+            }
+            let Some(group) = groups.get(name) else { return Ok(()) };
+            path.push(name);
+            for entry in &group.models {
+                // Model names take precedence over group names
+                if !model_names.contains(entry.name.as_str()) && groups.contains_key(entry.name.as_str()) {
+                    visit(&entry.name, groups, model_names, path)?;
+                }
+            }
+            path.pop();
+            Ok(())
+        }
+
+        for group in &config.router_settings.model_groups {
+            visit(&group.name, &groups, &model_names, &mut Vec::new())?;
+        }
+        Ok(())
+    }
+
     fn validate_model_group_selectors(config: &Config) -> anyhow::Result<()> {
         for group in &config.router_settings.model_groups {
             for entry in &group.models {
@@ -163,6 +1891,9 @@ impl Config {
     }
 }
 
+// Headers that carry upstream credentials, lowercase
+const AUTH_HEADER_NAMES: [&str; 4] = ["authorization", "proxy-authorization", "x-api-key", "x-goog-api-key"];
+
 fn normalize_llm_params(params: &mut LLMParams) {
     // If the YAML provided a quoted JSON string, try to parse into JSON object/value
     if let Value::String(s) = &params.rewrite_body {
@@ -172,3 +1903,445 @@ fn normalize_llm_params(params: &mut LLMParams) {
         if let Ok(v) = serde_json::from_str::<Value>(s) { params.rewrite_header = v; }
     }
 }
+
+/// The weights of group `group` as they take effect, normalized to
+/// `normalize_to` when set. A group whose weights are all zero could only
+/// pick members at random; single zero weights are fine and take a member
+/// out of rotation.
+pub fn checked_group_weights(group: &str, weights: &[u32], normalize_to: Option<u32>) -> anyhow::Result<Vec<u32>> {
+    if !weights.is_empty() && weights.iter().all(|&w| w == 0) {
+        return Err(anyhow::anyhow!("Model group '{}' has no member with a nonzero weight", group));
+    }
+    match normalize_to {
+        Some(sum) => normalize_weights(weights, sum).map_err(|e| anyhow::anyhow!("Model group '{}': {}", group, e)),
+        None => Ok(weights.to_vec()),
+    }
+}
+
+/// Scale `weights` to add up to `sum` by largest remainder. Zero weights stay
+/// zero and nonzero ones stay at least 1; all-zero input is returned unchanged.
+pub fn normalize_weights(weights: &[u32], sum: u32) -> anyhow::Result<Vec<u32>> {
+    let total: u64 = weights.iter().map(|&w| w as u64).sum();
+    if total == 0 {
+        return Ok(weights.to_vec());
+    }
+    let nonzero = weights.iter().filter(|&&w| w > 0).count();
+    if (sum as usize) < nonzero {
+        return Err(anyhow::anyhow!("normalize_weights {} is less than the {} members with a weight", sum, nonzero));
+    }
+    let mut out: Vec<u32> = weights
+        .iter()
+        .map(|&w| if w == 0 { 0 } else { ((w as u64 * sum as u64 / total) as u32).max(1) })
+        .collect();
+    // Hand out what rounding down left over, largest remainder first
+    let mut by_remainder: Vec<usize> = (0..weights.len()).filter(|&i| weights[i] > 0).collect();
+    by_remainder.sort_by_key(|&i| std::cmp::Reverse(weights[i] as u64 * sum as u64 % total));
+    let mut assigned: u32 = out.iter().sum();
+    for &i in by_remainder.iter().cycle().take(sum.saturating_sub(assigned) as usize) {
+        out[i] += 1;
+    }
+    assigned = assigned.max(sum);
+    // Members lifted to 1 may overshoot; take it back from the largest
+    while assigned > sum {
+        let largest = (0..out.len()).max_by_key(|&i| out[i]).expect("nonzero weights exist");
+        out[largest] -= 1;
+        assigned -= 1;
+    }
+    Ok(out)
+}
+
+// Replace every ${VAR} with the value of environment variable VAR
+fn interpolate_env(s: &str) -> anyhow::Result<String> {
+    let re = regex::Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").expect("valid env placeholder regex");
+    let mut missing = None;
+    let out = re.replace_all(s, |caps: &regex::Captures| {
+        std::env::var(&caps[1]).unwrap_or_else(|_| {
+            missing.get_or_insert_with(|| caps[1].to_string());
+            String::new()
+        })
+    });
+    match missing {
+        Some(var) => Err(anyhow::anyhow!("environment variable '{}' is not set", var)),
+        None => Ok(out.into_owned()),
+    }
+}
+
+// Group name -> member name -> weight
+type WeightsOverlay = BTreeMap<String, BTreeMap<String, u32>>;
+
+fn read_weights_overlay(path: &str) -> anyhow::Result<WeightsOverlay> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_yaml::from_str(&content).map_err(|e| anyhow::anyhow!("Invalid weights overlay {}: {}", path, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(WeightsOverlay::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Set the overlay's weights in the raw YAML. Groups and members that are no
+// longer in the config are skipped.
+fn apply_weights_overlay(root: &mut serde_yaml::Value, overlay: &WeightsOverlay) {
+    use serde_yaml::Value as Yaml;
+
+    let Some(groups) = root.get_mut("router_settings").and_then(|r| r.get_mut("model_groups")).and_then(Yaml::as_sequence_mut)
+    else {
+        return;
+    };
+    for group in groups {
+        let Some(weights) = group.get("name").and_then(Yaml::as_str).and_then(|name| overlay.get(name)) else { continue };
+        let weights = weights.clone();
+        let Some(members) = group.get_mut("models").and_then(Yaml::as_sequence_mut) else { continue };
+        for member in members {
+            let Some(&weight) = member.get("name").and_then(Yaml::as_str).and_then(|name| weights.get(name)) else { continue };
+            if let Some(member) = member.as_mapping_mut() {
+                member.insert(Yaml::String("weight".to_string()), Yaml::Number(weight.into()));
+            }
+        }
+    }
+}
+
+// Set one `key.path=value` in the raw YAML. Mapping keys are created as needed;
+// list items are addressed by index or by their `model_name`/`name`. The value
+// is parsed as YAML, so `5`, `true` and `[a, b]` keep their types, except that
+// it stays text when it replaces a string: `api_key=12345` is still a key.
+// A quoted value (`key="5"`) is a string wherever it goes.
+fn apply_override(root: &mut serde_yaml::Value, set: &str) -> anyhow::Result<()> {
+    use serde_yaml::Value as Yaml;
+
+    let (path, raw) = set.split_once('=').ok_or_else(|| anyhow::anyhow!("expected key.path=value"))?;
+    let keys: Vec<&str> = path.trim().split('.').collect();
+    if keys.iter().any(|k| k.is_empty()) {
+        return Err(anyhow::anyhow!("empty key in '{}'", path));
+    }
+    let value: Yaml = serde_yaml::from_str(raw).unwrap_or_else(|_| Yaml::String(raw.to_string()));
+
+    let mut node = root;
+    for (i, key) in keys.iter().enumerate() {
+        if node.is_null() {
+            *node = Yaml::Mapping(Default::default());
+        }
+        node = match node {
+            Yaml::Mapping(map) => {
+                let key = Yaml::String(key.to_string());
+                if !map.contains_key(&key) {
+                    map.insert(key.clone(), Yaml::Null);
+                }
+                map.get_mut(&key).expect("key just inserted")
+            }
+            Yaml::Sequence(items) => {
+                let index = match key.parse::<usize>() {
+                    Ok(index) => Some(index).filter(|i| *i < items.len()),
+                    Err(_) => items.iter().position(|item| {
+                        ["model_name", "name"].iter().any(|field| item.get(field).and_then(Yaml::as_str) == Some(key))
+                    }),
+                };
+                let index = index.ok_or_else(|| anyhow::anyhow!("no item '{}' in '{}'", key, keys[..i].join(".")))?;
+                &mut items[index]
+            }
+            _ => return Err(anyhow::anyhow!("'{}' is not a mapping or list", keys[..i].join("."))),
+        };
+    }
+    *node = match (&*node, value) {
+        (Yaml::String(_), value) if !value.is_string() && !value.is_null() => Yaml::String(raw.to_string()),
+        (_, value) => value,
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loads `yaml` from a file, as the router does at startup
+    fn load_yaml(yaml: &str) -> anyhow::Result<Config> {
+        load_yaml_with_overrides(yaml, &[])
+    }
+
+    fn load_yaml_with_overrides(yaml: &str, sets: &[&str]) -> anyhow::Result<Config> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, yaml.as_bytes()).unwrap();
+        let sets: Vec<String> = sets.iter().map(|s| s.to_string()).collect();
+        Config::from_file_with_overrides(file.path().to_str().unwrap(), &sets)
+    }
+
+    #[test]
+    fn test_interpolate_env() {
+        // SAFETY: test-only variable name not read concurrently elsewhere
+        unsafe { std::env::set_var("LLM_ROUTER_TEST_ORG", "org-123") };
+        assert_eq!(interpolate_env("${LLM_ROUTER_TEST_ORG}").unwrap(), "org-123");
+        assert_eq!(interpolate_env("id=${LLM_ROUTER_TEST_ORG};").unwrap(), "id=org-123;");
+        assert_eq!(interpolate_env("plain").unwrap(), "plain");
+        assert!(interpolate_env("${LLM_ROUTER_TEST_UNSET_VAR}").is_err());
+    }
+
+    #[test]
+    fn test_tenants_are_validated() {
+        let yaml = r#"
+model_list:
+  - model_name: m
+    llm_params: {api_type: openai, model: x, api_base: "http://localhost", api_key: k}
+router_settings:
+  strategy: roundrobin
+  model_groups: [{name: root_group, models: [{name: m}]}]
+tenants:
+  - name: a
+    keys: [key-a]
+    model_list:
+      - model_name: m
+        llm_params: {api_type: openai, model: y, api_base: "http://localhost", api_key: k}
+    router_settings:
+      strategy: roundrobin
+      model_groups: [{name: tenant_group, models: [{name: m}]}]
+"#;
+        let config = load_yaml(yaml).unwrap();
+        assert_eq!(config.tenants[0].config.router_settings.model_groups[0].name, "tenant_group");
+        assert_eq!(config.tenants[0].config.model_list[0].llm_params.model, "y");
+
+        let err = load_yaml(&format!("{}virtual_keys: [{{key: key-a}}]\n", yaml)).unwrap_err();
+        assert!(err.to_string().contains("already in use"), "{}", err);
+        let err = load_yaml(&yaml.replace("name: a", "name: a/b")).unwrap_err();
+        assert!(err.to_string().contains("must not contain '/'"), "{}", err);
+        let config = load_yaml(&yaml.replace("keys: [key-a]", "keys: [key-a]\n    budget: {max_tokens: 1000}")).unwrap();
+        assert_eq!(config.tenants[0].budget.max_tokens, Some(1000));
+        assert_eq!(config.tenants[0].budget.period_secs, 86_400);
+    }
+
+    #[test]
+    fn test_rewrite_headers_are_validated() {
+        let load = |params: &str| {
+            let yaml = format!(
+                r#"
+model_list:
+  - model_name: m
+    llm_params: {{api_type: openai, model: x, api_base: "http://localhost", api_key: k, {}}}
+router_settings:
+  strategy: roundrobin
+  model_groups: [{{name: g, models: [{{name: m}}]}}]
+"#,
+                params
+            );
+            load_yaml(&yaml)
+        };
+
+        assert!(load(r#"rewrite_header: {x-route: "{tenant}", x-retries: 3, user-agent: null}"#).is_ok());
+        let err = load(r#"rewrite_header: {"bad name": v}"#).unwrap_err();
+        assert!(err.to_string().contains("not a valid header name"), "{}", err);
+        let err = load(r#"rewrite_header: {x-route: "a\nb"}"#).unwrap_err();
+        assert!(err.to_string().contains("invalid value for 'x-route'"), "{}", err);
+        assert!(load("rewrite_header: {x-route: [a]}").is_err());
+        let err = load("rewrite_header: {Authorization: Bearer other}").unwrap_err();
+        assert!(err.to_string().contains("rewrite_auth_headers"), "{}", err);
+        assert!(load("rewrite_header: {openai-organization: o}, auth_headers: {OpenAI-Organization: p}").is_err());
+        assert!(load("rewrite_header: {Authorization: null}, rewrite_auth_headers: true").is_ok());
+    }
+
+    #[test]
+    fn test_virtual_key_allowed_models() {
+        let yaml = r#"
+model_list:
+  - model_name: m
+    llm_params: {api_type: openai, model: x, api_base: "http://localhost", api_key: k}
+router_settings:
+  strategy: roundrobin
+  model_groups: [{name: g, models: [{name: m}]}]
+"#;
+        let load = |keys: &str| load_yaml(&format!("{}virtual_keys: [{}]\n", yaml, keys));
+
+        let config = load("{key: k1, allowed_models: [g]}, {key: k2}").unwrap();
+        assert!(config.virtual_keys[0].allows("g"));
+        assert!(!config.virtual_keys[0].allows("m"));
+        assert!(config.virtual_keys[1].allows("m"));
+
+        let err = load("{key: k1, allowed_models: [nope]}").unwrap_err();
+        assert!(err.to_string().contains("neither a model nor a model group"), "{}", err);
+        let err = load("{key: k1, default_model: m, allowed_models: [g]}").unwrap_err();
+        assert!(err.to_string().contains("not in allowed_models"), "{}", err);
+    }
+
+    #[test]
+    fn test_virtual_key_denied_models() {
+        let yaml = r#"
+model_list:
+  - model_name: big
+    llm_params: {api_type: openai, model: x, api_base: "http://localhost", api_key: k}
+  - model_name: small
+    llm_params: {api_type: openai, model: y, api_base: "http://localhost", api_key: k}
+  - model_name: other
+    llm_params: {api_type: openai, model: z, api_base: "http://localhost", api_key: k}
+router_settings:
+  strategy: roundrobin
+  model_groups: [{name: g, models: [{name: big}, {name: small}, {name: other}]}]
+"#;
+        let load = |keys: &str| load_yaml(&format!("{}virtual_keys: [{}]\n", yaml, keys));
+
+        let config = load("{key: k1, denied_models: [big], allowed_models: [g, big, small]}").unwrap();
+        let vk = &config.virtual_keys[0];
+        assert!(!vk.allows("big"));
+        assert!(vk.is_listed("big"));
+        assert!(vk.allows("g"));
+        assert_eq!(vk.alternatives("big", &config.router_settings.model_groups), ["g", "small"]);
+
+        let err = load("{key: k1, denied_models: [nope]}").unwrap_err();
+        assert!(err.to_string().contains("neither a model nor a model group"), "{}", err);
+        let err = load("{key: k1, default_model: big, denied_models: [big]}").unwrap_err();
+        assert!(err.to_string().contains("is in denied_models"), "{}", err);
+    }
+
+    #[test]
+    fn test_persisted_weights_overlay_the_config() {
+        let yaml = r#"
+model_list:
+  - model_name: m1
+    llm_params: {api_type: openai, model: x, api_base: "http://localhost", api_key: "${LLM_ROUTER_TEST_OVERLAY_KEY}"}
+  - model_name: m2
+    llm_params: {api_type: openai, model: x, api_base: "http://localhost", api_key: k}
+router_settings:
+  strategy: roundrobin
+  model_groups: [{name: g, models: [{name: m1}, {name: m2, weight: 5}]}]
+"#;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(&path, yaml).unwrap();
+        let path = path.to_str().unwrap();
+
+        let weights = [("m1".to_string(), 0), ("m2".to_string(), 3), ("gone".to_string(), 1)];
+        Config::persist_group_weights(path, "g", &weights).unwrap();
+        Config::persist_group_weights(path, "removed_group", &weights).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), yaml);
+
+        let config = Config::from_file(path).unwrap();
+        let members: Vec<u32> = config.router_settings.model_groups[0].models.iter().map(|m| m.weight).collect();
+        assert_eq!(members, [0, 3]);
+        // --set still wins over the overlay
+        let config = Config::from_file_with_overrides(path, &["router_settings.model_groups.g.models.m2.weight=9".to_string()]).unwrap();
+        assert_eq!(config.router_settings.model_groups[0].models[1].weight, 9);
+    }
+
+    #[test]
+    fn test_overrides_apply_before_validation() {
+        let yaml = r#"
+model_list:
+  - model_name: m1
+    llm_params: {api_type: openai, model: x, api_base: "http://localhost", api_key: k}
+router_settings:
+  strategy: roundrobin
+  model_groups: [{name: g, models: [{name: m1}]}]
+"#;
+        let load = |sets: &[&str]| load_yaml_with_overrides(yaml, sets);
+
+        let config = load(&[
+            "router_settings.strategy=leastconn",
+            "router_settings.max_hops=2",
+            "model_list.m1.llm_params.model=y",
+            "router_settings.model_groups.0.models.m1.weight=7",
+        ])
+        .unwrap();
+        assert!(matches!(config.router_settings.strategy, RoutingStrategy::LeastConn));
+        assert_eq!(config.router_settings.max_hops, 2);
+        assert_eq!(config.model_list[0].llm_params.model, "y");
+        assert_eq!(config.router_settings.model_groups[0].models[0].weight, 7);
+
+        // Numbers replacing a string stay text
+        let config = load(&["model_list.m1.llm_params.api_key=12345", "model_list.m1.llm_params.model=1.50"]).unwrap();
+        assert_eq!(config.model_list[0].llm_params.api_key, "12345");
+        assert_eq!(config.model_list[0].llm_params.model, "1.50");
+
+        assert!(load(&["router_settings.strategy"]).is_err());
+        assert!(load(&["model_list.m2.llm_params.model=y"]).is_err());
+        assert!(load(&["router_settings.max_hops=0"]).is_err());
+        assert!(load(&["model_list.m1.llm_params.keepalive_ms=0"]).is_err());
+        // Overridden values are still validated
+        assert!(load(&["router_settings.default_model=nope"]).is_err());
+    }
+
+    #[test]
+    fn test_strategy_rule_weights_are_validated() {
+        let load = |weights: &str| {
+            let yaml = format!(
+                r#"
+model_list:
+  - model_name: m1
+    llm_params: {{api_type: openai, model: x, api_base: "http://localhost", api_key: k}}
+  - model_name: m2
+    llm_params: {{api_type: openai, model: x, api_base: "http://localhost", api_key: k}}
+router_settings:
+  strategy: roundrobin
+  model_groups: [{{name: g, models: [{{name: m1}}, {{name: m2, weight: 0}}]}}]
+  strategy_rules: [{{weights: {{g: {}}}}}]
+"#,
+                weights
+            );
+            load_yaml(&yaml)
+        };
+        assert!(load("{m1: 0, m2: 5}").is_ok());
+        assert!(load("{m3: 1}").is_err());
+        let err = load("{m1: 0}").unwrap_err().to_string();
+        assert!(err.contains("Strategy rule #0") && err.contains("nonzero weight"), "{}", err);
+    }
+
+    #[test]
+    fn test_listeners_are_validated() {
+        let yaml = r#"
+model_list:
+  - model_name: m1
+    llm_params: {api_type: openai, model: x, api_base: "http://localhost", api_key: k}
+router_settings:
+  strategy: roundrobin
+  model_groups: [{name: g, models: [{name: m1}]}]
+  listeners:
+    - {port: 9000, auth: false, routes: [inference]}
+    - {port: 9100, routes: [admin, metrics]}
+"#;
+        let load = |sets: &[&str]| load_yaml_with_overrides(yaml, sets);
+
+        let config = load(&[]).unwrap();
+        let listeners = &config.router_settings.listeners;
+        assert!(!listeners[0].auth);
+        assert_eq!(listeners[0].routes, vec![RouteSet::Inference]);
+        assert!(listeners[1].auth);
+        assert_eq!(listeners[1].routes, vec![RouteSet::Admin, RouteSet::Metrics]);
+
+        assert!(load(&["router_settings.listeners.1.port=9000"]).is_err());
+        assert!(load(&["router_settings.listeners.1.ip=localhost"]).is_err());
+        assert!(load(&["router_settings.listeners.1.ip=127.0.0.1", "router_settings.listeners.1.port=9000"]).is_ok());
+        assert!(load(&["router_settings.listeners.0.routes=[inference, admin]"]).is_err());
+        assert!(load(&["router_settings.listeners.1.auth=false"]).is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_collected() {
+        let yaml = r#"
+model_list:
+  - model_name: m1
+    llm_params: {api_type: openai, model: x, api_base: "http://localhost", api_key: k, rewrite_body: {anything: 1}, future_param: 2}
+router_settings:
+  strategy: roundrobin
+  newer_setting: {depth: 3}
+  model_groups: [{name: g, models: [{name: m1}], defaults: {}, recovery: {schedule: linear}}]
+tenants:
+  - name: t
+    keys: [tenant-key]
+    model_list: []
+    router_settings: {strategy: random, model_groups: []}
+    quota: 5
+from_the_future: true
+"#;
+        let config = load_yaml(yaml).unwrap();
+        assert_eq!(
+            config.unknown_keys,
+            vec![
+                "model_list.0.llm_params.future_param",
+                "router_settings.newer_setting",
+                "from_the_future",
+                "tenants.0.quota",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize_weights() {
+        assert_eq!(normalize_weights(&[1, 2, 3], 100).unwrap(), vec![17, 33, 50]);
+        assert_eq!(normalize_weights(&[100, 0, 1], 10).unwrap(), vec![9, 0, 1]);
+        assert_eq!(normalize_weights(&[0, 0], 10).unwrap(), vec![0, 0]);
+        assert!(normalize_weights(&[1, 1, 1], 2).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::config::ApiType;
+use crate::converters::request_wrapper::RequestWrapper;
+use crate::converters::response_handler::{DEFAULT_MAX_RESPONSE_BYTES, handle_non_streaming_response};
+use http_body_util::BodyExt;
+
+/// What `llm-router convert` reads: a client request or a complete upstream answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvertKind {
+    Request,
+    Response,
+}
+
+/// Convert one JSON body from `from` to `to` the way the router would, and
+/// return it pretty-printed. `via_openai` goes through the OpenAI format even
+/// for pairs with a direct converter (Anthropic <-> Gemini).
+pub async fn convert(kind: ConvertKind, from: ApiType, to: ApiType, input: &str, via_openai: bool) -> anyhow::Result<String> {
+    let output = match kind {
+        ConvertKind::Request => {
+            let request = match from {
+                ApiType::OpenAI => RequestWrapper::OpenAI(serde_json::from_str(input)?),
+                ApiType::Anthropic => RequestWrapper::Anthropic(serde_json::from_str(input)?),
+                ApiType::Gemini => RequestWrapper::Gemini(serde_json::from_str(input)?),
+            };
+            let request = if via_openai { request.via_openai() } else { request };
+            match to {
+                ApiType::OpenAI => serde_json::to_value(request.get_openai())?,
+                ApiType::Anthropic => serde_json::to_value(request.get_anthropic())?,
+                ApiType::Gemini => serde_json::to_value(request.get_gemini())?,
+            }
+        }
+        ConvertKind::Response => {
+            // Keep the answer's own model name
+            let value: serde_json::Value = serde_json::from_str(input)?;
+            let model = value.get("model").or_else(|| value.get("modelVersion")).and_then(|m| m.as_str()).unwrap_or_default();
+            // Handled like an upstream answer, so the output matches what clients get
+            let upstream = reqwest::Response::from(axum::http::Response::new(input.to_string()));
+            let converted = handle_non_streaming_response(upstream, model.to_string(), from, to, !via_openai, DEFAULT_MAX_RESPONSE_BYTES).await;
+            let status = converted.status();
+            let body = converted.into_body().collect().await?.to_bytes();
+            if !status.is_success() {
+                anyhow::bail!("{}", String::from_utf8_lossy(&body));
+            }
+            serde_json::from_slice(&body)?
+        }
+    };
+    Ok(serde_json::to_string_pretty(&output)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    #[tokio::test]
+    async fn test_convert_request_and_response() {
+        let request = json!({"model": "gpt-4o", "max_tokens": 64, "messages": [{"role": "user", "content": "hi"}]});
+        let out = convert(ConvertKind::Request, ApiType::OpenAI, ApiType::Gemini, &request.to_string(), false).await.unwrap();
+        let out: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(out["contents"][0]["parts"][0]["text"], "hi");
+        assert_eq!(out["generationConfig"]["maxOutputTokens"], 64);
+
+        let response = json!({
+            "id": "msg_1", "type": "message", "role": "assistant", "model": "claude-x",
+            "content": [{"type": "text", "text": "hello"}],
+            "stop_reason": "end_turn", "usage": {"input_tokens": 3, "output_tokens": 1}
+        });
+        let out = convert(ConvertKind::Response, ApiType::Anthropic, ApiType::OpenAI, &response.to_string(), false).await.unwrap();
+        let out: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(out["model"], "claude-x");
+        assert_eq!(out["choices"][0]["message"]["content"], "hello");
+
+        assert!(convert(ConvertKind::Response, ApiType::Gemini, ApiType::OpenAI, "{\"candidates\": 1}", false).await.is_err());
+    }
+}
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// Cache writes split by TTL, as reported next to `cache_creation_input_tokens`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnthropicCacheCreation {
+    #[serde(default)]
+    pub ephemeral_5m_input_tokens: u32,
+    #[serde(default)]
+    pub ephemeral_1h_input_tokens: u32,
+}
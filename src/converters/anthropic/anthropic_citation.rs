@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// A citation attached to a text block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum AnthropicCitation {
+    #[serde(rename = "web_search_result_location")]
+    WebSearchResultLocation {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default)]
+        cited_text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        encrypted_index: Option<String>,
+    },
+    /// Document locations (char_location, page_location, ...), kept verbatim
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
@@ -9,4 +9,7 @@ pub enum AnthropicContentBlock {
     Thinking { thinking: String, signature: String },
     #[serde(rename = "tool_use")]
     ToolUse { id: String, name: String, input: serde_json::Value },
+    /// Any other block type (server_tool_use, web_search_tool_result, ...), kept verbatim
+    #[serde(untagged)]
+    Other(serde_json::Value),
 }
\ No newline at end of file
@@ -1,11 +1,15 @@
 use serde::{Deserialize, Serialize};
-use crate::converters::anthropic::AnthropicImageSource;
+use crate::converters::anthropic::{AnthropicCitation, AnthropicImageSource};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum AnthropicContentObject {
     #[serde(rename = "text")]
-    Text { text: String },
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        citations: Option<Vec<AnthropicCitation>>,
+    },
     #[serde(rename = "thinking")]
     Thinking { thinking: String, signature: Option<String> },
     #[serde(rename = "redacted_thinking")]
@@ -16,4 +20,7 @@ pub enum AnthropicContentObject {
     ToolUse { id: String, name: String, input: serde_json::Value },
     #[serde(rename = "tool_result")]
     ToolResult { tool_use_id: String, content: String },
+    /// Any other block type (server_tool_use, web_search_tool_result, ...), kept verbatim
+    #[serde(untagged)]
+    Other(serde_json::Value),
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use crate::converters::anthropic::AnthropicContent;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnthropicMessage {
     pub role: String,
     pub content: AnthropicContent,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
\ No newline at end of file
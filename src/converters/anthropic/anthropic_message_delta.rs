@@ -4,4 +4,6 @@ use serde::{Deserialize, Serialize};
 pub struct AnthropicMessageDelta {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequence: Option<String>,
 }
\ No newline at end of file
@@ -1,13 +1,17 @@
 use crate::converters::anthropic::{
     AnthropicContent, AnthropicContentObject, AnthropicImageSource, AnthropicMessage,
-    AnthropicMetadata, AnthropicSystemContent, AnthropicTool,
+    AnthropicMetadata, AnthropicSystemContent, AnthropicTool, AnthropicToolChoice,
 };
-use crate::converters::openai::{OpenAIContent, OpenAIRequest};
+use crate::converters::attribution::Attribution;
+use crate::converters::gemini::{GeminiPart, GeminiRequest};
+use crate::converters::openai::{OpenAIContent, OpenAIRequest, OpenAIStop};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnthropicRequest {
+    // May be omitted when the caller's virtual key has a default model
+    #[serde(default)]
     pub model: String,
     pub max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -16,28 +20,42 @@ pub struct AnthropicRequest {
     pub system: Option<AnthropicSystemContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<AnthropicTool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<AnthropicToolChoice>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<AnthropicMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    // Values of the client's anthropic-beta header; not part of the body
+    #[serde(skip)]
+    pub betas: Vec<String>,
     #[serde(flatten)]
     pub extra_fields: HashMap<String, serde_json::Value>,
 }
 
 // 转换实现
 impl From<OpenAIRequest> for AnthropicRequest {
-    fn from(openai_request: OpenAIRequest) -> Self {
+    fn from(mut openai_request: OpenAIRequest) -> Self {
+        let attribution = Attribution::take_openai(&mut openai_request.extra_fields);
         let mut anthropic_request = AnthropicRequest {
             model: openai_request.model,
             max_tokens: openai_request.max_tokens.unwrap_or(4096),
             messages: None,
             system: None,
             tools: None,
-            metadata: None,
+            tool_choice: AnthropicToolChoice::from_openai(
+                openai_request.tool_choice.as_ref(),
+                openai_request.parallel_tool_calls,
+            ),
+            metadata: attribution.anthropic_metadata(),
             stream: openai_request.stream,
             temperature: openai_request.temperature,
+            stop_sequences: openai_request.stop.map(OpenAIStop::into_vec),
+            betas: Vec::new(),
             extra_fields: std::collections::HashMap::new(),
         };
 
@@ -55,7 +73,7 @@ impl From<OpenAIRequest> for AnthropicRequest {
 
                 let text_for_tool_result = match &message.content {
                     OpenAIContent::Text(text) => {
-                        content.push(AnthropicContentObject::Text { text: text.clone() });
+                        content.push(AnthropicContentObject::Text { text: text.clone(), citations: None });
                         text.clone()
                     }
                     OpenAIContent::Array(array) => {
@@ -66,6 +84,7 @@ impl From<OpenAIRequest> for AnthropicRequest {
                                     if let Some(text) = &item.text {
                                         content.push(AnthropicContentObject::Text {
                                             text: text.clone(),
+                                            citations: None,
                                         });
                                     }
                                 }
@@ -123,6 +142,7 @@ impl From<OpenAIRequest> for AnthropicRequest {
                     }
                     .to_string(),
                     content: AnthropicContent::Array(content),
+                    extra_fields: HashMap::new(),
                 });
             }
         }
@@ -140,6 +160,7 @@ impl From<OpenAIRequest> for AnthropicRequest {
                     name: tool.function.name,
                     description: tool.function.description,
                     input_schema: tool.function.parameters,
+                    extra_fields: HashMap::new(),
                 })
                 .collect();
             anthropic_request.tools = Some(anthropic_tools);
@@ -153,3 +174,105 @@ impl From<OpenAIRequest> for AnthropicRequest {
         anthropic_request
     }
 }
+
+
+// Direct conversion, without the OpenAI pivot. Gemini calls carry no ids, so
+// each functionCall gets one and the next functionResponse of that name refers to it.
+impl From<GeminiRequest> for AnthropicRequest {
+    fn from(mut gemini: GeminiRequest) -> Self {
+        let attribution = Attribution::take_gemini(&mut gemini.extra_fields);
+        let system = gemini.system_instruction.and_then(|content| {
+            let text: String = content
+                .parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    GeminiPart::Text { text, .. } => Some(text),
+                    _ => None,
+                })
+                .collect();
+            (!text.is_empty()).then_some(AnthropicSystemContent::Text(text))
+        });
+
+        let mut pending_calls: Vec<(String, String)> = Vec::new();
+        let mut call_count = 0;
+        let mut messages = Vec::new();
+        for content in gemini.contents {
+            let role = if content.role.as_deref() == Some("model") { "assistant" } else { "user" };
+            let mut blocks = Vec::new();
+            for part in content.parts {
+                match part {
+                    // Thoughts are not replayed; Anthropic would need its own signatures
+                    GeminiPart::Text { thought: Some(true), .. } => {}
+                    GeminiPart::Text { text: t, .. } if t.is_empty() => {}
+                    // Consecutive text parts make one block, in place among the others
+                    GeminiPart::Text { text: t, .. } => match blocks.last_mut() {
+                        Some(AnthropicContentObject::Text { text, .. }) => text.push_str(&t),
+                        _ => blocks.push(AnthropicContentObject::Text { text: t, citations: None }),
+                    },
+                    GeminiPart::InlineData { inline_data } => blocks.push(AnthropicContentObject::Image {
+                        source: AnthropicImageSource {
+                            r#type: "base64".to_string(),
+                            media_type: Some(inline_data.mime_type),
+                            data: Some(inline_data.data),
+                            url: None,
+                        },
+                    }),
+                    GeminiPart::FunctionCall { function_call, .. } => {
+                        call_count += 1;
+                        let id = format!("toolu_{}", call_count);
+                        pending_calls.push((id.clone(), function_call.name.clone()));
+                        blocks.push(AnthropicContentObject::ToolUse { id, name: function_call.name, input: function_call.args });
+                    }
+                    GeminiPart::FunctionResponse { function_response } => {
+                        let tool_use_id = match pending_calls.iter().position(|(_, name)| *name == function_response.name) {
+                            Some(index) => pending_calls.remove(index).0,
+                            None => function_response.name,
+                        };
+                        let content = match function_response.response {
+                            Some(serde_json::Value::String(text)) => text,
+                            Some(response) => response.to_string(),
+                            None => String::new(),
+                        };
+                        blocks.push(AnthropicContentObject::ToolResult { tool_use_id, content });
+                    }
+                }
+            }
+            if !blocks.is_empty() {
+                messages.push(AnthropicMessage {
+                    role: role.to_string(),
+                    content: AnthropicContent::Array(blocks),
+                    extra_fields: HashMap::new(),
+                });
+            }
+        }
+
+        let tools: Vec<AnthropicTool> = gemini
+            .tools
+            .into_iter()
+            .flatten()
+            .flat_map(|tool| tool.function_declarations)
+            .map(|declaration| AnthropicTool {
+                name: declaration.name,
+                description: declaration.description.unwrap_or_default(),
+                input_schema: declaration.parameters.unwrap_or_else(|| serde_json::json!({"type": "object"})),
+                extra_fields: HashMap::new(),
+            })
+            .collect();
+        let config = gemini.generation_config.unwrap_or_default();
+
+        AnthropicRequest {
+            model: gemini.model,
+            max_tokens: config.max_output_tokens.unwrap_or(4096),
+            messages: (!messages.is_empty()).then_some(messages),
+            system,
+            tools: (!tools.is_empty()).then_some(tools),
+            tool_choice: None,
+            stream: gemini.stream,
+            temperature: config.temperature,
+            metadata: attribution.anthropic_metadata(),
+            stop_sequences: config.stop_sequences,
+            betas: Vec::new(),
+            extra_fields: gemini.extra_fields,
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use crate::converters::anthropic::{AnthropicContentObject, AnthropicUsage};
+use crate::converters::gemini::{GeminiFinishReason, GeminiPart, GeminiResponse};
 use crate::converters::openai::OpenAIResponse;
+use crate::utils::clock;
 use serde_json::Value;
-use crate::converters::helpers;
+use crate::converters::{citations, helpers};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnthropicResponse {
@@ -17,6 +20,8 @@ pub struct AnthropicResponse {
     pub stop_sequence: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<AnthropicUsage>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
 
 impl From<OpenAIResponse> for AnthropicResponse {
@@ -27,16 +32,17 @@ impl From<OpenAIResponse> for AnthropicResponse {
             if !reasoning_content.trim().is_empty() {
                 content_objects.push(AnthropicContentObject::Thinking {
                     thinking: reasoning_content.clone(),
-                    signature: None
+                    signature: citations::thought_signature(&openai_resp.choices[0].message.extra_fields),
                 });
             }
         }
         
         if let Some(content) = &openai_resp.choices[0].message.content {
             if !content.trim().is_empty() {
-                content_objects.push(AnthropicContentObject::Text {
-                    text: content.clone()
-                });
+                content_objects.extend(citations::cited_text_blocks(
+                    content,
+                    openai_resp.choices[0].message.annotations.as_ref(),
+                ));
             }
         }
         
@@ -51,18 +57,95 @@ impl From<OpenAIResponse> for AnthropicResponse {
             }
         }
         
+        let stop_sequence = helpers::stop_sequence(&openai_resp.choices[0].extra_fields);
         AnthropicResponse {
             id: openai_resp.id,
             r#type: "message".to_string(),
             role: "assistant".to_string(),
             content: content_objects,
             model: openai_resp.model.clone(),
+            stop_reason: Some(match &stop_sequence {
+                Some(_) => "stop_sequence".to_string(),
+                None => helpers::map_openai_finish_reason_to_anthropic(&Value::String(openai_resp.choices[0].finish_reason.clone())).as_str().unwrap_or("end_turn").to_string(),
+            }),
+            stop_sequence,
+            usage: openai_resp.usage.map(AnthropicUsage::from),
+            extra_fields: HashMap::new(),
+        }
+    }
+}
+
+
+// Direct conversion, same result as going through OpenAIResponse
+impl From<GeminiResponse> for AnthropicResponse {
+    fn from(resp: GeminiResponse) -> Self {
+        let mut content = Vec::new();
+        // No candidates at all when the prompt itself was blocked
+        let blocked = resp.prompt_feedback.as_ref().is_some_and(|f| f.block_reason.is_some());
+        let mut stop_reason = if blocked { "refusal" } else { "end_turn" };
+        if let Some(first) = resp.candidates.first() {
+            let mut text = String::new();
+            let mut thinking = String::new();
+            let mut tool_uses = Vec::new();
+            // Where each answer part starts in `text`, for grounding segments
+            let mut part_offsets = HashMap::new();
+            let mut signature = None;
+            for (idx, part) in first.content.parts.iter().enumerate() {
+                match part {
+                    GeminiPart::Text { text: t, thought, thought_signature } => {
+                        if *thought == Some(true) {
+                            thinking.push_str(t);
+                        } else {
+                            part_offsets.insert(idx, text.len());
+                            text.push_str(t);
+                        }
+                        if thought_signature.is_some() {
+                            signature = thought_signature.clone();
+                        }
+                    }
+                    GeminiPart::FunctionCall { function_call, .. } => tool_uses.push(AnthropicContentObject::ToolUse {
+                        id: format!("tool_call_{}", idx),
+                        name: function_call.name.clone(),
+                        input: function_call.args.clone(),
+                    }),
+                    GeminiPart::InlineData { .. } | GeminiPart::FunctionResponse { .. } => {}
+                }
+            }
+            if !thinking.trim().is_empty() {
+                content.push(AnthropicContentObject::Thinking { thinking, signature });
+            }
+            if !text.trim().is_empty() {
+                let annotations = citations::gemini_annotations(first, &text, &part_offsets);
+                content.extend(citations::cited_text_blocks(&text, Some(&annotations)));
+            }
+            stop_reason = if !tool_uses.is_empty() {
+                "tool_use"
+            } else if matches!(first.finish_reason, Some(GeminiFinishReason::MaxTokens)) {
+                "max_tokens"
+            } else if first.finish_reason.as_ref().is_some_and(GeminiFinishReason::is_content_filter) {
+                "refusal"
+            } else {
+                "end_turn"
+            };
+            content.extend(tool_uses);
+        }
+
+        AnthropicResponse {
+            id: clock::new_id("gen"),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content,
+            model: resp.model_version.unwrap_or_else(|| "gemini".to_string()),
+            stop_reason: Some(stop_reason.to_string()),
             stop_sequence: None,
-            stop_reason: Some(helpers::map_openai_finish_reason_to_anthropic(&Value::String(openai_resp.choices[0].finish_reason.clone())).as_str().unwrap_or("end_turn").to_string()),
-            usage: openai_resp.usage.map(|usage| AnthropicUsage {
-                input_tokens: usage.prompt_tokens,
-                output_tokens: usage.completion_tokens,
+            usage: resp.usage_metadata.map(|u| {
+                AnthropicUsage::from_prompt(
+                    u.prompt_token_count.unwrap_or(0),
+                    u.candidates_token_count.unwrap_or(0),
+                    u.cached_content_token_count,
+                )
             }),
+            extra_fields: HashMap::new(),
         }
     }
 }
@@ -73,6 +156,46 @@ mod tests {
     use serde_json::json;
     use super::*;
 
+    #[test]
+    fn test_gemini_to_anthropic_response_matches_openai_pivot() {
+        let gemini: GeminiResponse = serde_json::from_value(json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "Checking hours.", "thought": true, "thoughtSignature": "sig"},
+                    {"text": "Café opens at 9. It closes at 5."},
+                    {"functionCall": {"name": "book", "args": {"time": "9"}}}
+                ]},
+                "finishReason": "STOP",
+                "groundingMetadata": {
+                    "groundingChunks": [
+                        {"web": {"uri": "https://a.example", "title": "a.example"}},
+                        {"retrievedContext": {"uri": "gs://bucket/hours.pdf", "title": "hours.pdf"}}
+                    ],
+                    "groundingSupports": [{"segment": {"partIndex": 1, "startIndex": 0, "endIndex": 17}, "groundingChunkIndices": [0, 1]}]
+                }
+            }],
+            "usageMetadata": {"promptTokenCount": 7, "candidatesTokenCount": 11, "totalTokenCount": 18},
+            "modelVersion": "gemini-2.5-flash"
+        }))
+        .unwrap();
+
+        let direct = {
+            let _frozen = clock::freeze(1_700_000_000);
+            serde_json::to_value(AnthropicResponse::from(gemini.clone())).unwrap()
+        };
+        let pivoted = {
+            let _frozen = clock::freeze(1_700_000_000);
+            serde_json::to_value(AnthropicResponse::from(OpenAIResponse::from(gemini))).unwrap()
+        };
+        assert_eq!(direct, pivoted);
+        assert_eq!(direct["stop_reason"], "tool_use");
+        assert_eq!(direct["content"][0]["signature"], "sig");
+        // Only the web source has an Anthropic counterpart; no location is made up for the file
+        let citations = direct["content"][1]["citations"].as_array().unwrap();
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0]["type"], "web_search_result_location");
+    }
+
 
     #[test]
     fn test_openai_to_anthropic_response() {
@@ -107,7 +230,7 @@ mod tests {
         assert_eq!(anthropic_response.id, "chatcmpl-123");
         assert_eq!(anthropic_response.r#type, "message");
         assert_eq!(anthropic_response.role, "assistant");
-        if let AnthropicContentObject::Text { text } = &anthropic_response.content[0] {
+        if let AnthropicContentObject::Text { text, .. } = &anthropic_response.content[0] {
             assert_eq!(text, "Hello, how can I help you today?");
         } else {
             panic!("Expected AnthropicContentObject::Text");
@@ -159,7 +282,7 @@ mod tests {
         } else {
             panic!("Expected AnthropicContentObject::Text");
         }
-        if let AnthropicContentObject::Text { text } = &anthropic_response.content[1] {
+        if let AnthropicContentObject::Text { text, .. } = &anthropic_response.content[1] {
             assert_eq!(text, "The answer is 42.");
         } else {
             panic!("Expected AnthropicContentObject::Text");
@@ -215,7 +338,7 @@ mod tests {
         assert_eq!(anthropic_response.id, "chatcmpl-789");
         assert_eq!(anthropic_response.r#type, "message");
         assert_eq!(anthropic_response.role, "assistant");
-        if let AnthropicContentObject::Text { text} = &anthropic_response.content[0] {
+        if let AnthropicContentObject::Text { text, .. } = &anthropic_response.content[0] {
             assert_eq!(text, "I'll help you get the weather.");
         } else {
             panic!("Expected AnthropicContentObject::Text");
@@ -295,7 +418,7 @@ mod tests {
         assert_eq!(anthropic_response.id, "chatcmpl-max");
         assert_eq!(anthropic_response.r#type, "message");
         assert_eq!(anthropic_response.role, "assistant");
-        if let AnthropicContentObject::Text { text} = &anthropic_response.content[0] {
+        if let AnthropicContentObject::Text { text, .. } = &anthropic_response.content[0] {
             assert_eq!(text, "This is a truncated response because");
         } else {
             panic!("Expected AnthropicContentObject::Text");
@@ -303,5 +426,25 @@ mod tests {
         assert_eq!(anthropic_response.stop_reason.unwrap(), "max_tokens");
     }
 
+    #[test]
+    fn test_stop_sequence_round_trip() {
+        let anthropic_response: AnthropicResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude",
+            "content": [{ "type": "text", "text": "1, 2, 3" }],
+            "stop_reason": "stop_sequence",
+            "stop_sequence": "4"
+        }))
+        .unwrap();
+        let openai_response: OpenAIResponse = anthropic_response.into();
+        let choice = serde_json::to_value(&openai_response.choices[0]).unwrap();
+        assert_eq!(choice["finish_reason"], "stop");
+        assert_eq!(choice["finish_details"], json!({ "type": "stop", "stop": "4" }));
 
-}
\ No newline at end of file
+        let anthropic_response: AnthropicResponse = openai_response.into();
+        assert_eq!(anthropic_response.stop_reason.as_deref(), Some("stop_sequence"));
+        assert_eq!(anthropic_response.stop_sequence.as_deref(), Some("4"));
+    }
+}
@@ -35,11 +35,14 @@ pub enum AnthropicStreamChunk {
     MessageStop,
     #[serde(rename = "ping")]
     Ping,
+    /// Any other event type (error, future events), kept verbatim
+    #[serde(untagged)]
+    Other(Value),
 }
 
 impl AnthropicStreamChunk {
 
-    pub fn stream_type(&self) -> &'static str {
+    pub fn stream_type(&self) -> &str {
         match self {
             AnthropicStreamChunk::MessageStart { .. } => "message_start",
             AnthropicStreamChunk::ContentBlockStart { .. } => "content_block_start",
@@ -48,6 +51,7 @@ impl AnthropicStreamChunk {
             AnthropicStreamChunk::MessageDelta { .. } => "message_delta",
             AnthropicStreamChunk::MessageStop => "message_stop",
             AnthropicStreamChunk::Ping => "ping",
+            AnthropicStreamChunk::Other(value) => value.get("type").and_then(Value::as_str).unwrap_or("unknown"),
         }
     }
 }
@@ -74,10 +78,7 @@ impl From<OpenAIStreamChunk> for AnthropicStreamChunk {
         };
 
         // 处理使用统计
-        let usage = openai_chunk.usage.map(|usage| AnthropicUsage {
-            input_tokens: usage.prompt_tokens,
-            output_tokens: usage.completion_tokens,
-        });
+        let usage = openai_chunk.usage.map(AnthropicUsage::from);
 
         // 处理内容增量
         let delta = &first_choice.delta;
@@ -113,8 +114,9 @@ impl From<OpenAIStreamChunk> for AnthropicStreamChunk {
         if let Some(tool_calls) = delta.as_ref().and_then(|d| d.tool_calls.as_ref()) {
             for tool_call in tool_calls.iter() {
                 if let Some(function) = tool_call.function.as_ref() {
+                    // The OpenAI tool call index, replaced by the block index once streamed
                     return AnthropicStreamChunk::ContentBlockDelta {
-                        index: 0,
+                        index: tool_call.index,
                         delta: AnthropicStreamDelta::InputJsonDelta {
                             partial_json: function.arguments.clone(),
                             name: function.name.clone(),
@@ -128,17 +130,16 @@ impl From<OpenAIStreamChunk> for AnthropicStreamChunk {
         // 检查是否是停止消息
         if let Some(finish_reason) = &first_choice.finish_reason {
             if !finish_reason.is_empty() {
+                let stop_sequence = helpers::stop_sequence(&first_choice.extra_fields);
+                let stop_reason = match stop_sequence {
+                    Some(_) => "stop_sequence".to_string(),
+                    None => helpers::map_openai_finish_reason_to_anthropic(&Value::String(finish_reason.clone()))
+                        .as_str()
+                        .unwrap_or("end_turn")
+                        .to_string(),
+                };
                 return AnthropicStreamChunk::MessageDelta {
-                    delta: AnthropicMessageDelta {
-                        stop_reason: Some(
-                            helpers::map_openai_finish_reason_to_anthropic(&Value::String(
-                                finish_reason.clone(),
-                            ))
-                            .as_str()
-                            .unwrap_or("end_turn")
-                            .to_string(),
-                        ),
-                    },
+                    delta: AnthropicMessageDelta { stop_reason: Some(stop_reason), stop_sequence },
                     usage,
                 };
             }
@@ -160,6 +161,21 @@ mod tests {
         serde_json::to_value(anthropic_stream_chunk).unwrap_or_default()
     }
 
+    #[test]
+    fn test_unknown_blocks_round_trip() {
+        let events = [
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "EqQB"}}),
+            json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}),
+        ];
+        for event in events {
+            let chunk: AnthropicStreamChunk = serde_json::from_value(event.clone()).unwrap();
+            assert_eq!(serde_json::to_value(&chunk).unwrap(), event);
+        }
+        let chunk: AnthropicStreamChunk = serde_json::from_value(json!({"type": "error", "error": {}})).unwrap();
+        assert_eq!(chunk.stream_type(), "error");
+    }
+
     #[test]
     fn test_openai_to_anthropic_stream_chunk_with_text_content() {
         // 测试包含文本内容的流式响应块
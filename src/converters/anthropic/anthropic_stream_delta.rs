@@ -16,4 +16,7 @@ pub enum AnthropicStreamDelta {
         #[serde(skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    /// Any other delta type (signature_delta, citations_delta, ...), kept verbatim
+    #[serde(untagged)]
+    Other(serde_json::Value),
 }
\ No newline at end of file
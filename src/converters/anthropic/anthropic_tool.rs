@@ -1,8 +1,15 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnthropicTool {
     pub name: String,
+    // Server tools such as web_search_20250305 have a `type` and neither of these
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub description: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
     pub input_schema: serde_json::Value,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
\ No newline at end of file
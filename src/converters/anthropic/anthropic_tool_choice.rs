@@ -0,0 +1,85 @@
+use crate::converters::openai::{OpenAIToolChoice, ToolChoiceMode};
+use serde::{Deserialize, Serialize};
+
+/// Anthropic `tool_choice`: `auto`, `any`, `tool` (with `name`) or `none`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnthropicToolChoice {
+    #[serde(rename = "type")]
+    pub r#type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_parallel_tool_use: Option<bool>,
+}
+
+// Anthropic server tools standing in for Responses hosted tools
+fn server_tool(hosted: &str) -> Option<&'static str> {
+    match hosted {
+        "web_search" | "web_search_preview" | "web_search_preview_2025_03_11" => Some("web_search"),
+        "code_interpreter" => Some("code_execution"),
+        "computer_use_preview" => Some("computer"),
+        _ => None,
+    }
+}
+
+impl AnthropicToolChoice {
+    /// The Anthropic equivalent of an OpenAI `tool_choice` and
+    /// `parallel_tool_calls`; `None` when neither is set.
+    pub fn from_openai(choice: Option<&OpenAIToolChoice>, parallel_tool_calls: Option<bool>) -> Option<Self> {
+        if choice.is_none() && parallel_tool_calls.is_none() {
+            return None;
+        }
+        let (r#type, name) = match choice.map_or(&ToolChoiceMode::Auto, OpenAIToolChoice::mode) {
+            ToolChoiceMode::Auto => ("auto", None),
+            ToolChoiceMode::None => ("none", None),
+            ToolChoiceMode::Required => ("any", None),
+            ToolChoiceMode::Tool(name) => ("tool", Some(name.clone())),
+            // Hosted tools without a server tool counterpart still force some tool
+            ToolChoiceMode::Hosted(hosted) => match server_tool(hosted) {
+                Some(tool) => ("tool", Some(tool.to_string())),
+                None => ("any", None),
+            },
+        };
+        // Anthropic rejects the flag next to "none"
+        let disable_parallel_tool_use = (r#type != "none").then_some(parallel_tool_calls.map(|p| !p)).flatten();
+        Some(AnthropicToolChoice { r#type: r#type.to_string(), name, disable_parallel_tool_use })
+    }
+
+    /// The OpenAI `tool_choice` and `parallel_tool_calls` with the same effect.
+    pub fn to_openai(&self) -> (OpenAIToolChoice, Option<bool>) {
+        let choice = match (self.r#type.as_str(), &self.name) {
+            ("none", _) => ToolChoiceMode::None,
+            ("any", _) => ToolChoiceMode::Required,
+            ("tool", Some(name)) => ToolChoiceMode::Tool(name.clone()),
+            _ => ToolChoiceMode::Auto,
+        };
+        (choice.into(), self.disable_parallel_tool_use.map(|d| !d))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_choice_round_trip() {
+        let forced = OpenAIToolChoice::from(ToolChoiceMode::Tool("get_weather".to_string()));
+        let choice = AnthropicToolChoice::from_openai(Some(&forced), Some(false)).unwrap();
+        assert_eq!(choice.r#type, "tool");
+        assert_eq!(choice.name.as_deref(), Some("get_weather"));
+        assert_eq!(choice.disable_parallel_tool_use, Some(true));
+        assert_eq!(choice.to_openai(), (forced, Some(false)));
+
+        let hosted = OpenAIToolChoice::from(ToolChoiceMode::Hosted("web_search_preview".to_string()));
+        let choice = AnthropicToolChoice::from_openai(Some(&hosted), None).unwrap();
+        assert_eq!((choice.r#type.as_str(), choice.name.as_deref()), ("tool", Some("web_search")));
+        let hosted = OpenAIToolChoice::from(ToolChoiceMode::Hosted("file_search".to_string()));
+        assert_eq!(AnthropicToolChoice::from_openai(Some(&hosted), None).unwrap().r#type, "any");
+
+        let choice = AnthropicToolChoice::from_openai(None, Some(false)).unwrap();
+        assert_eq!((choice.r#type.as_str(), choice.disable_parallel_tool_use), ("auto", Some(true)));
+        let choice = AnthropicToolChoice::from_openai(Some(&ToolChoiceMode::None.into()), Some(false)).unwrap();
+        assert_eq!(choice.disable_parallel_tool_use, None);
+        assert!(AnthropicToolChoice::from_openai(None, None).is_none());
+    }
+}
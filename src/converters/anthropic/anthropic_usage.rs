@@ -1,7 +1,100 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::converters::anthropic::AnthropicCacheCreation;
+use crate::converters::openai::{OpenAIPromptTokensDetails, OpenAIUsage};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnthropicUsage {
+    // Uncached input only; cache reads and writes are counted apart
     pub input_tokens: u32,
     pub output_tokens: u32,
-}
\ No newline at end of file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation: Option<AnthropicCacheCreation>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
+}
+
+impl AnthropicUsage {
+    /// Usage of a prompt of `prompt_tokens`, `cached` of which were read from cache.
+    pub fn from_prompt(prompt_tokens: u32, output_tokens: u32, cached: Option<u32>) -> Self {
+        AnthropicUsage {
+            input_tokens: prompt_tokens.saturating_sub(cached.unwrap_or(0)),
+            output_tokens,
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: cached,
+            cache_creation: None,
+            extra_fields: HashMap::new(),
+        }
+    }
+
+    /// The whole prompt, as OpenAI counts `prompt_tokens`: uncached input plus
+    /// cache reads and writes.
+    pub fn prompt_tokens(&self) -> u32 {
+        self.input_tokens + self.cache_read_input_tokens.unwrap_or(0) + self.cache_creation_input_tokens.unwrap_or(0)
+    }
+}
+
+impl From<AnthropicUsage> for OpenAIUsage {
+    fn from(usage: AnthropicUsage) -> Self {
+        let prompt_tokens = usage.prompt_tokens();
+        OpenAIUsage {
+            prompt_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: prompt_tokens + usage.output_tokens,
+            completion_tokens_details: None,
+            prompt_tokens_details: usage
+                .cache_read_input_tokens
+                .map(|cached| OpenAIPromptTokensDetails { audio_tokens: None, cached_tokens: Some(cached) }),
+        }
+    }
+}
+
+impl From<OpenAIUsage> for AnthropicUsage {
+    fn from(usage: OpenAIUsage) -> Self {
+        let cached = usage.prompt_tokens_details.and_then(|d| d.cached_tokens);
+        AnthropicUsage::from_prompt(usage.prompt_tokens, usage.completion_tokens, cached)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cache_usage_maps_to_cached_tokens() {
+        let usage: AnthropicUsage = serde_json::from_value(json!({
+            "input_tokens": 10,
+            "output_tokens": 5,
+            "cache_creation_input_tokens": 200,
+            "cache_read_input_tokens": 1000,
+            "cache_creation": {"ephemeral_5m_input_tokens": 50, "ephemeral_1h_input_tokens": 150},
+            "service_tier": "standard"
+        }))
+        .unwrap();
+        assert_eq!(
+            usage.cache_creation,
+            Some(AnthropicCacheCreation { ephemeral_5m_input_tokens: 50, ephemeral_1h_input_tokens: 150 })
+        );
+        assert_eq!(usage.extra_fields.len(), 1);
+        let round_trip = serde_json::to_value(&usage).unwrap();
+        assert_eq!(round_trip["cache_creation"]["ephemeral_1h_input_tokens"], 150);
+
+        let openai = OpenAIUsage::from(usage);
+        assert_eq!(openai.prompt_tokens, 1210);
+        assert_eq!(openai.total_tokens, 1215);
+        assert_eq!(openai.prompt_tokens_details.unwrap().cached_tokens, Some(1000));
+
+        let back = AnthropicUsage::from(serde_json::from_value::<OpenAIUsage>(json!({
+            "prompt_tokens": 170, "completion_tokens": 72, "total_tokens": 242,
+            "prompt_tokens_details": {"cached_tokens": 43}
+        })).unwrap());
+        assert_eq!((back.input_tokens, back.cache_read_input_tokens), (127, Some(43)));
+    }
+}
@@ -1,3 +1,5 @@
+pub mod anthropic_cache_creation;
+pub mod anthropic_citation;
 pub mod anthropic_content;
 pub mod anthropic_content_block;
 pub mod anthropic_content_object;
@@ -12,8 +14,11 @@ pub mod anthropic_stream_delta;
 pub mod anthropic_stream_message;
 pub mod anthropic_system_content;
 pub mod anthropic_tool;
+pub mod anthropic_tool_choice;
 pub mod anthropic_usage;
 
+pub use anthropic_cache_creation::AnthropicCacheCreation;
+pub use anthropic_citation::AnthropicCitation;
 pub use anthropic_content::AnthropicContent;
 pub use anthropic_content_block::AnthropicContentBlock;
 pub use anthropic_content_object::AnthropicContentObject;
@@ -28,4 +33,5 @@ pub use anthropic_stream_delta::AnthropicStreamDelta;
 pub use anthropic_stream_message::AnthropicStreamMessage;
 pub use anthropic_system_content::{AnthropicSystemContent, AnthropicSystemContentObject};
 pub use anthropic_tool::AnthropicTool;
+pub use anthropic_tool_choice::AnthropicToolChoice;
 pub use anthropic_usage::AnthropicUsage;
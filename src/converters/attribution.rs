@@ -0,0 +1,187 @@
+//! End-user attribution across formats. OpenAI names the end user in
+//! `safety_identifier` (or the older `user`) and tags requests with a string
+//! `metadata` map; Anthropic only takes `metadata.user_id`, Gemini a `labels`
+//! map with restricted keys and values. Only Vertex AI accepts `labels`, so
+//! the client drops them for other Gemini upstreams.
+
+use crate::converters::anthropic::AnthropicMetadata;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+// Gemini label carrying the end user
+const USER_LABEL: &str = "user";
+// Gemini allows 64 labels of up to 63 characters each
+const MAX_LABELS: usize = 64;
+const MAX_LABEL_LEN: usize = 63;
+// OpenAI allows 16 metadata pairs
+const MAX_METADATA: usize = 16;
+
+/// Who a request is for, and the caller's tags for it.
+#[derive(Debug, Default)]
+pub struct Attribution {
+    pub user: Option<String>,
+    pub tags: Vec<(String, String)>,
+}
+
+impl Attribution {
+    /// Remove OpenAI's attribution fields from a request's extra fields.
+    pub fn take_openai(extra: &mut HashMap<String, Value>) -> Self {
+        let safety_identifier = extra.remove("safety_identifier");
+        let user = extra.remove("user");
+        let tags = match extra.remove("metadata") {
+            Some(Value::Object(map)) => map
+                .into_iter()
+                .filter_map(|(k, v)| Some((k, v.as_str()?.to_string())))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let user = [safety_identifier, user]
+            .into_iter()
+            .flatten()
+            .find_map(|v| v.as_str().filter(|s| !s.is_empty()).map(str::to_string));
+        Attribution { user, tags }
+    }
+
+    /// Remove Gemini's `labels` from a request's extra fields.
+    pub fn take_gemini(extra: &mut HashMap<String, Value>) -> Self {
+        let mut attribution = Attribution::default();
+        if let Some(Value::Object(labels)) = extra.remove("labels") {
+            for (key, value) in labels {
+                let Some(value) = value.as_str().map(str::to_string) else { continue };
+                if key == USER_LABEL {
+                    attribution.user = Some(decode_user(&value).unwrap_or(value));
+                } else {
+                    attribution.tags.push((key, value));
+                }
+            }
+        }
+        attribution
+    }
+
+    pub fn from_anthropic(metadata: Option<&AnthropicMetadata>) -> Self {
+        Attribution { user: metadata.and_then(|m| m.user_id.clone()), tags: Vec::new() }
+    }
+
+    /// Anthropic metadata; its only field is the user, so tags are dropped.
+    pub fn anthropic_metadata(&self) -> Option<AnthropicMetadata> {
+        self.user.clone().map(|user_id| AnthropicMetadata { user_id: Some(user_id) })
+    }
+
+    /// Put the user and tags back as OpenAI `user` and `metadata`; `user` is
+    /// the field every OpenAI-compatible upstream accepts.
+    pub fn insert_openai(self, extra: &mut HashMap<String, Value>) {
+        if let Some(user) = self.user {
+            extra.insert("user".to_string(), Value::String(user));
+        }
+        if !self.tags.is_empty() {
+            let metadata: Map<String, Value> = self
+                .tags
+                .into_iter()
+                .take(MAX_METADATA)
+                .map(|(k, v)| (k, Value::String(v)))
+                .collect();
+            extra.insert("metadata".to_string(), Value::Object(metadata));
+        }
+    }
+
+    /// Put the user and tags in Gemini `labels`. Tags are rewritten to the
+    /// characters labels allow; the user is escaped so it reads back intact,
+    /// and left out if that makes it too long.
+    pub fn insert_gemini(self, extra: &mut HashMap<String, Value>) {
+        let user = self.user.as_deref().and_then(encode_user).map(|user| (USER_LABEL.to_string(), Value::String(user)));
+        let tags = self.tags.into_iter().filter_map(|(k, v)| {
+            let key = label_text(&k);
+            // Keys must start with a letter
+            (key != USER_LABEL && key.starts_with(|c: char| c.is_ascii_lowercase())).then(|| (key, Value::String(label_text(&v))))
+        });
+        let labels: Map<String, Value> = user.into_iter().chain(tags).take(MAX_LABELS).collect();
+        if !labels.is_empty() {
+            extra.insert("labels".to_string(), Value::Object(labels));
+        }
+    }
+}
+
+// Lowercase letters, digits, `_` and `-` only
+fn label_text(text: &str) -> String {
+    text.chars()
+        .map(|c| c.to_ascii_lowercase())
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .take(MAX_LABEL_LEN)
+        .collect()
+}
+
+// Lowercase letters, digits and `-` are kept; every other byte becomes `_`
+// and two hex digits
+fn encode_user(user: &str) -> Option<String> {
+    let mut encoded = String::new();
+    for byte in user.bytes() {
+        if byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("_{byte:02x}"));
+        }
+    }
+    (encoded.len() <= MAX_LABEL_LEN).then_some(encoded)
+}
+
+// None when the label was not written by `encode_user`
+fn decode_user(label: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = label.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'_' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            if !hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_openai_attribution_to_gemini_labels() {
+        let mut extra: HashMap<String, Value> = serde_json::from_value(json!({
+            "user": "legacy",
+            "safety_identifier": "User@Example.com",
+            "metadata": {"Team": "Search Infra", "1st": "x", "count": 3},
+            "seed": 7,
+        }))
+        .unwrap();
+        let attribution = Attribution::take_openai(&mut extra);
+        assert_eq!(attribution.user.as_deref(), Some("User@Example.com"));
+        assert_eq!(extra.keys().collect::<Vec<_>>(), ["seed"]);
+        assert_eq!(attribution.anthropic_metadata().unwrap().user_id.as_deref(), Some("User@Example.com"));
+
+        attribution.insert_gemini(&mut extra);
+        assert_eq!(extra["labels"], json!({"user": "_55ser_40_45xample_2ecom", "team": "search_infra"}));
+
+        let attribution = Attribution::take_gemini(&mut extra);
+        assert!(!extra.contains_key("labels"));
+        attribution.insert_openai(&mut extra);
+        assert_eq!(extra["user"], "User@Example.com");
+        assert_eq!(extra["metadata"], json!({"team": "search_infra"}));
+    }
+
+    #[test]
+    fn test_gemini_user_label_round_trip() {
+        for user in ["u-1", "Ünïcode_user", "a b"] {
+            assert_eq!(decode_user(&encode_user(user).unwrap()).as_deref(), Some(user));
+        }
+        // Too long once escaped
+        assert_eq!(encode_user(&"A".repeat(30)), None);
+        // Labels a Gemini client wrote itself are kept as they are
+        let mut extra: HashMap<String, Value> = serde_json::from_value(json!({"labels": {"user": "team_x"}})).unwrap();
+        assert_eq!(Attribution::take_gemini(&mut extra).user.as_deref(), Some("team_x"));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::converters::anthropic::{AnthropicCitation, AnthropicContentObject};
+use crate::converters::gemini::{
+    GeminiCandidate, GeminiGroundingChunk, GeminiGroundingMetadata, GeminiGroundingSupport,
+    GeminiRetrievedContext, GeminiSegment, GeminiWebSource,
+};
+use crate::converters::openai::{OpenAIAnnotation, OpenAIFileCitation, OpenAIUrlCitation};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+// Gemini's OpenAI-compatible API carries thought signatures under this key
+const EXTRA_CONTENT: &str = "extra_content";
+
+/// Provider fields holding a Gemini thought signature, as `extra_content.google.thought_signature`.
+pub fn thought_signature_fields(signature: Option<&String>) -> HashMap<String, Value> {
+    let mut fields = HashMap::new();
+    if let Some(signature) = signature {
+        fields.insert(EXTRA_CONTENT.to_string(), json!({ "google": { "thought_signature": signature } }));
+    }
+    fields
+}
+
+/// The Gemini thought signature stored by `thought_signature_fields`, if any.
+pub fn thought_signature(fields: &HashMap<String, Value>) -> Option<String> {
+    fields
+        .get(EXTRA_CONTENT)?
+        .pointer("/google/thought_signature")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Citations for a Gemini candidate's answer text: grounding supports (web
+/// chunks become URL citations, retrieved files file citations) and recitation sources.
+///
+/// `part_offsets` maps a part index to where that part starts in `text`, in bytes;
+/// Gemini measures segments in bytes within a part, OpenAI in characters of the content.
+pub fn gemini_annotations(
+    candidate: &GeminiCandidate,
+    text: &str,
+    part_offsets: &HashMap<usize, usize>,
+) -> Vec<OpenAIAnnotation> {
+    let mut annotations = Vec::new();
+    let url_citation = |url: &str, title: Option<&String>, start: usize, end: usize| {
+        OpenAIAnnotation::UrlCitation {
+            url_citation: OpenAIUrlCitation {
+                url: url.to_string(),
+                title: title.cloned(),
+                start_index: char_index(text, start),
+                end_index: char_index(text, end),
+            },
+        }
+    };
+    let file_citation = |file_id: &str, title: Option<&String>, start: usize, end: usize| {
+        OpenAIAnnotation::FileCitation {
+            file_citation: OpenAIFileCitation { file_id: file_id.to_string(), filename: title.cloned() },
+            start_index: char_index(text, start),
+            end_index: char_index(text, end),
+        }
+    };
+
+    if let Some(grounding) = &candidate.grounding_metadata {
+        for support in &grounding.grounding_supports {
+            let segment = &support.segment;
+            let base = match segment.part_index {
+                Some(part) => match part_offsets.get(&part) {
+                    Some(offset) => *offset,
+                    None => continue,
+                },
+                None => 0,
+            };
+            let (start, end) = (base + segment.start_index, base + segment.end_index);
+            for &chunk_index in &support.grounding_chunk_indices {
+                let Some(chunk) = grounding.grounding_chunks.get(chunk_index) else { continue };
+                if let Some(web) = &chunk.web {
+                    annotations.push(url_citation(&web.uri, web.title.as_ref(), start, end));
+                } else if let Some(uri) = chunk.retrieved_context.as_ref().and_then(|c| c.uri.as_ref()) {
+                    let title = chunk.retrieved_context.as_ref().and_then(|c| c.title.as_ref());
+                    annotations.push(file_citation(uri, title, start, end));
+                }
+            }
+        }
+    }
+
+    if let Some(citations) = &candidate.citation_metadata {
+        for source in &citations.citation_sources {
+            if let Some(uri) = &source.uri {
+                annotations.push(url_citation(uri, source.title.as_ref(), source.start_index, source.end_index));
+            }
+        }
+    }
+    annotations
+}
+
+/// Anthropic text blocks for `text`, split so that each URL-cited span becomes
+/// its own block with a `web_search_result_location` citation. Anthropic has no
+/// citation that names a file by id, so file citations are left out.
+pub fn cited_text_blocks(text: &str, annotations: Option<&Vec<OpenAIAnnotation>>) -> Vec<AnthropicContentObject> {
+    let mut spans: Vec<(usize, usize, &OpenAIAnnotation)> = annotations
+        .into_iter()
+        .flatten()
+        .filter_map(|a| span(a).map(|(start, end)| (start, end, a)))
+        .filter(|(start, end, _)| start < end)
+        .collect();
+    spans.sort_by_key(|(start, end, _)| (*start, *end));
+
+    let mut blocks: Vec<AnthropicContentObject> = Vec::new();
+    let mut cursor = 0;
+    for (start, end, annotation) in spans {
+        let start = byte_index(text, start);
+        let end = byte_index(text, end);
+        if start >= end {
+            continue;
+        }
+        let cited_text = text[start..end].to_string();
+        let citation = match annotation {
+            OpenAIAnnotation::UrlCitation { url_citation } => AnthropicCitation::WebSearchResultLocation {
+                url: url_citation.url.clone(),
+                title: url_citation.title.clone(),
+                cited_text,
+                encrypted_index: None,
+            },
+            OpenAIAnnotation::FileCitation { .. } | OpenAIAnnotation::Other(_) => continue,
+        };
+        if start < cursor {
+            // Same or overlapping span: cite it on the block already emitted
+            if let Some(AnthropicContentObject::Text { citations: Some(citations), .. }) = blocks.last_mut() {
+                citations.push(citation);
+            }
+            continue;
+        }
+        if start > cursor {
+            blocks.push(AnthropicContentObject::Text { text: text[cursor..start].to_string(), citations: None });
+        }
+        blocks.push(AnthropicContentObject::Text { text: text[start..end].to_string(), citations: Some(vec![citation]) });
+        cursor = end;
+    }
+    if cursor < text.len() || blocks.is_empty() {
+        blocks.push(AnthropicContentObject::Text { text: text[cursor..].to_string(), citations: None });
+    }
+    blocks
+}
+
+/// OpenAI annotations for the citations of an Anthropic text block that spans
+/// characters `start..end` of the message content. Web results become URL
+/// citations; document and search-result locations point into the request,
+/// which other formats cannot refer to, so they are left out.
+pub fn anthropic_annotations(citations: &[AnthropicCitation], start: usize, end: usize) -> Vec<OpenAIAnnotation> {
+    citations
+        .iter()
+        .filter_map(|citation| match citation {
+            AnthropicCitation::WebSearchResultLocation { url, title, .. } => Some(OpenAIAnnotation::UrlCitation {
+                url_citation: OpenAIUrlCitation { url: url.clone(), title: title.clone(), start_index: start, end_index: end },
+            }),
+            AnthropicCitation::Other(_) => None,
+        })
+        .collect()
+}
+
+/// Gemini grounding metadata for annotations on `text`, which is the text of
+/// part `part_index`: one chunk per distinct source, one support per cited span.
+pub fn gemini_grounding(
+    text: &str,
+    part_index: usize,
+    annotations: Option<&Vec<OpenAIAnnotation>>,
+) -> Option<GeminiGroundingMetadata> {
+    let mut grounding = GeminiGroundingMetadata::default();
+    let mut chunk_ids: HashMap<(bool, String), usize> = HashMap::new();
+    for annotation in annotations.into_iter().flatten() {
+        let (chunk, key) = match annotation {
+            OpenAIAnnotation::UrlCitation { url_citation } => (
+                GeminiGroundingChunk {
+                    web: Some(GeminiWebSource { uri: url_citation.url.clone(), title: url_citation.title.clone() }),
+                    retrieved_context: None,
+                    extra_fields: HashMap::new(),
+                },
+                (false, url_citation.url.clone()),
+            ),
+            OpenAIAnnotation::FileCitation { file_citation, .. } => (
+                GeminiGroundingChunk {
+                    web: None,
+                    retrieved_context: Some(GeminiRetrievedContext {
+                        uri: Some(file_citation.file_id.clone()),
+                        title: file_citation.filename.clone(),
+                        extra_fields: HashMap::new(),
+                    }),
+                    extra_fields: HashMap::new(),
+                },
+                (true, file_citation.file_id.clone()),
+            ),
+            OpenAIAnnotation::Other(_) => continue,
+        };
+        let Some((start, end)) = span(annotation) else { continue };
+        let (start, end) = (byte_index(text, start), byte_index(text, end));
+        if start >= end {
+            continue;
+        }
+        let next_id = chunk_ids.len();
+        let chunk_index = *chunk_ids.entry(key).or_insert_with(|| {
+            grounding.grounding_chunks.push(chunk);
+            next_id
+        });
+        match grounding
+            .grounding_supports
+            .iter_mut()
+            .find(|s| s.segment.start_index == start && s.segment.end_index == end)
+        {
+            Some(support) => support.grounding_chunk_indices.push(chunk_index),
+            None => grounding.grounding_supports.push(GeminiGroundingSupport {
+                segment: GeminiSegment {
+                    part_index: Some(part_index),
+                    start_index: start,
+                    end_index: end,
+                    text: Some(text[start..end].to_string()),
+                },
+                grounding_chunk_indices: vec![chunk_index],
+                extra_fields: HashMap::new(),
+            }),
+        }
+    }
+    (!grounding.grounding_chunks.is_empty()).then_some(grounding)
+}
+
+// Character span an annotation covers
+fn span(annotation: &OpenAIAnnotation) -> Option<(usize, usize)> {
+    match annotation {
+        OpenAIAnnotation::UrlCitation { url_citation } => Some((url_citation.start_index, url_citation.end_index)),
+        OpenAIAnnotation::FileCitation { start_index, end_index, .. } => Some((*start_index, *end_index)),
+        OpenAIAnnotation::Other(_) => None,
+    }
+}
+
+// Character index of a byte offset, rounding down to a character boundary
+fn char_index(text: &str, byte: usize) -> usize {
+    let mut byte = byte.min(text.len());
+    while !text.is_char_boundary(byte) {
+        byte -= 1;
+    }
+    text[..byte].chars().count()
+}
+
+// Byte offset of a character index, clamped to the end of the text
+fn byte_index(text: &str, chars: usize) -> usize {
+    text.char_indices().nth(chars).map(|(i, _)| i).unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gemini_grounding_to_citation_blocks() {
+        let candidate: GeminiCandidate = serde_json::from_value(json!({
+            "content": { "role": "model", "parts": [ { "text": "Café opens at 9. It closes at 5." } ] },
+            "index": 0,
+            "groundingMetadata": {
+                "groundingChunks": [
+                    { "web": { "uri": "https://a.example", "title": "a.example" } },
+                    { "web": { "uri": "https://b.example", "title": "b.example" } }
+                ],
+                "groundingSupports": [
+                    { "segment": { "startIndex": 0, "endIndex": 17, "text": "Café opens at 9." },
+                      "groundingChunkIndices": [0, 1] }
+                ],
+                "webSearchQueries": ["cafe hours"]
+            }
+        }))
+        .unwrap();
+        let text = "Café opens at 9. It closes at 5.";
+        let annotations = gemini_annotations(&candidate, text, &HashMap::from([(0, 0)]));
+        assert_eq!(annotations.len(), 2);
+        // "é" is two bytes, so byte 17 is character 16
+        let OpenAIAnnotation::UrlCitation { url_citation } = &annotations[0] else { panic!("expected url_citation") };
+        assert_eq!((url_citation.start_index, url_citation.end_index), (0, 16));
+
+        let blocks = serde_json::to_value(cited_text_blocks(text, Some(&annotations))).unwrap();
+        assert_eq!(blocks[0]["text"], "Café opens at 9.");
+        assert_eq!(blocks[0]["citations"][1]["url"], "https://b.example");
+        assert_eq!(blocks[0]["citations"][0]["type"], "web_search_result_location");
+        assert_eq!(blocks[0]["citations"][0]["cited_text"], "Café opens at 9.");
+        assert_eq!(blocks[1]["text"], " It closes at 5.");
+        assert!(blocks[1].get("citations").is_none());
+    }
+
+    #[test]
+    fn test_anthropic_citations_to_openai_and_gemini() {
+        use crate::converters::anthropic::AnthropicResponse;
+        use crate::converters::gemini::GeminiResponse;
+        use crate::converters::openai::OpenAIResponse;
+
+        let anthropic: AnthropicResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude",
+            "content": [
+                { "type": "text", "text": "Per the report, " },
+                { "type": "text", "text": "sales grew",
+                  "citations": [
+                      { "type": "char_location", "cited_text": "Sales grew 5%", "document_index": 0,
+                        "document_title": "report.pdf", "start_char_index": 0, "end_char_index": 13 },
+                      { "type": "web_search_result_location", "url": "https://news.example", "title": "News",
+                        "cited_text": "sales grew", "encrypted_index": "abc" }
+                  ] },
+                { "type": "text", "text": "." }
+            ],
+            "stop_reason": "end_turn"
+        }))
+        .unwrap();
+        let openai: OpenAIResponse = anthropic.into();
+        let message = serde_json::to_value(&openai.choices[0].message).unwrap();
+        assert_eq!(message["content"], "Per the report, sales grew.");
+        // The document citation points into the request and has no counterpart
+        assert_eq!(message["annotations"].as_array().unwrap().len(), 1);
+        assert_eq!(message["annotations"][0]["url_citation"]["start_index"], 16);
+        assert_eq!(message["annotations"][0]["url_citation"]["end_index"], 26);
+
+        let blocks = serde_json::to_value(cited_text_blocks(
+            openai.choices[0].message.content.as_ref().unwrap(),
+            openai.choices[0].message.annotations.as_ref(),
+        ))
+        .unwrap();
+        assert_eq!(blocks[1]["citations"], json!([{"type": "web_search_result_location", "url": "https://news.example", "title": "News", "cited_text": "sales grew"}]));
+
+        let gemini: GeminiResponse = openai.into();
+        let grounding = serde_json::to_value(&gemini.candidates[0].grounding_metadata).unwrap();
+        assert_eq!(grounding["groundingChunks"][0]["web"]["uri"], "https://news.example");
+        assert_eq!(grounding["groundingSupports"][0]["segment"]["text"], "sales grew");
+        assert_eq!(grounding["groundingSupports"][0]["groundingChunkIndices"], json!([0]));
+    }
+
+    #[test]
+    fn test_file_citations_are_not_invented_for_anthropic() {
+        let text = "See the manual.";
+        let annotations = vec![OpenAIAnnotation::FileCitation {
+            file_citation: OpenAIFileCitation { file_id: "file-1".to_string(), filename: Some("manual.pdf".to_string()) },
+            start_index: 4,
+            end_index: 14,
+        }];
+        let blocks = serde_json::to_value(cited_text_blocks(text, Some(&annotations))).unwrap();
+        assert_eq!(blocks, json!([{"type": "text", "text": "See the manual."}]));
+        // Gemini can name the file, so the citation survives there
+        let grounding = serde_json::to_value(gemini_grounding(text, 0, Some(&annotations))).unwrap();
+        assert_eq!(grounding["groundingChunks"][0]["retrievedContext"]["uri"], "file-1");
+    }
+}
@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
-use crate::converters::gemini::{GeminiContent, GeminiFinishReason};
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::converters::gemini::{
+    GeminiCitationMetadata, GeminiContent, GeminiFinishReason, GeminiGroundingMetadata,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GeminiCandidate {
@@ -8,4 +12,12 @@ pub struct GeminiCandidate {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub finish_reason: Option<GeminiFinishReason>,
     pub index: Option<u32>,
+    #[serde(rename = "groundingMetadata")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grounding_metadata: Option<GeminiGroundingMetadata>,
+    #[serde(rename = "citationMetadata")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citation_metadata: Option<GeminiCitationMetadata>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
\ No newline at end of file
@@ -31,3 +31,18 @@ pub enum GeminiFinishReason {
     #[serde(rename = "TOO_MANY_TOOL_CALLS")]
     TooManyToolCalls,
 }
+
+impl GeminiFinishReason {
+    /// Generation was stopped by a safety or policy filter rather than by the model.
+    pub fn is_content_filter(&self) -> bool {
+        matches!(
+            self,
+            GeminiFinishReason::Safety
+                | GeminiFinishReason::Recitation
+                | GeminiFinishReason::Blocklist
+                | GeminiFinishReason::ProhibitedContent
+                | GeminiFinishReason::Spii
+                | GeminiFinishReason::ImageSafety
+        )
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Sources behind a grounded (e.g. Google Search) answer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeminiGroundingMetadata {
+    #[serde(rename = "groundingChunks")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grounding_chunks: Vec<GeminiGroundingChunk>,
+    #[serde(rename = "groundingSupports")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grounding_supports: Vec<GeminiGroundingSupport>,
+    // webSearchQueries, searchEntryPoint, ...
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiGroundingChunk {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web: Option<GeminiWebSource>,
+    // File search / RAG corpus source
+    #[serde(rename = "retrievedContext")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieved_context: Option<GeminiRetrievedContext>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiWebSource {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiRetrievedContext {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
+}
+
+/// Links a span of the answer to the grounding chunks that support it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiGroundingSupport {
+    pub segment: GeminiSegment,
+    #[serde(rename = "groundingChunkIndices")]
+    #[serde(default)]
+    pub grounding_chunk_indices: Vec<usize>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
+}
+
+/// A span of one part's text; indices are UTF-8 byte offsets within that part.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeminiSegment {
+    #[serde(rename = "partIndex")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part_index: Option<usize>,
+    #[serde(rename = "startIndex")]
+    #[serde(default)]
+    pub start_index: usize,
+    #[serde(rename = "endIndex")]
+    #[serde(default)]
+    pub end_index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// Recitation sources the model quoted from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeminiCitationMetadata {
+    #[serde(rename = "citationSources")]
+    #[serde(default)]
+    pub citation_sources: Vec<GeminiCitationSource>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiCitationSource {
+    #[serde(rename = "startIndex")]
+    #[serde(default)]
+    pub start_index: usize,
+    #[serde(rename = "endIndex")]
+    #[serde(default)]
+    pub end_index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+}
@@ -1,5 +1,9 @@
+use crate::converters::anthropic::{
+    AnthropicContent, AnthropicContentObject, AnthropicRequest, AnthropicSystemContent, AnthropicSystemContentObject,
+};
+use crate::converters::attribution::Attribution;
 use crate::converters::openai::{
-    OpenAIRequest, OpenAIContent, OpenAITool
+    OpenAIRequest, OpenAIContent, OpenAIStop, OpenAITool
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -13,6 +17,8 @@ use crate::converters::gemini::{
     gemini_tool::GeminiTool,
     gemini_function_declaration::GeminiFunctionDeclaration,
     gemini_generation_config::GeminiGenerationConfig,
+    gemini_funtion_call::GeminiFunctionCall,
+    gemini_funtion_response::GeminiFunctionResponse,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +35,12 @@ pub struct GeminiRequest {
     #[serde(rename = "generationConfig")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub generation_config: Option<GeminiGenerationConfig>,
+    // Explicit cache to prepend, "cachedContents/{id}" or on Vertex
+    // "projects/{project}/locations/{location}/cachedContents/{id}"; only
+    // Gemini upstreams have it
+    #[serde(rename = "cachedContent")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_content: Option<String>,
     // Not sent to Gemini API; used only for routing
     #[serde(skip_serializing)]
     pub stream: Option<bool>,
@@ -136,7 +148,7 @@ impl From<OpenAIRequest> for GeminiRequest {
             thinking_config: None,
             response_mime_type: None,
             response_schema: None,
-            stop_sequences: None,
+            stop_sequences: openai.stop.map(OpenAIStop::into_vec),
             temperature: openai.temperature,
             top_p: None,
             top_k: None,
@@ -158,6 +170,11 @@ impl From<OpenAIRequest> for GeminiRequest {
             }
         }
         let generation_config = Some(generation_config);
+        let mut extra_fields = openai.extra_fields;
+        Attribution::take_openai(&mut extra_fields).insert_gemini(&mut extra_fields);
+        if let Some(choice) = &openai.tool_choice {
+            extra_fields.insert("toolConfig".to_string(), choice.to_gemini());
+        }
 
         GeminiRequest {
             model: openai.model,
@@ -165,8 +182,124 @@ impl From<OpenAIRequest> for GeminiRequest {
             system_instruction,
             tools,
             generation_config,
+            cached_content: None,
             stream: openai.stream,
-            extra_fields: openai.extra_fields,
+            extra_fields,
+        }
+    }
+}
+
+
+// Direct conversion, without the OpenAI pivot: tool use and results become
+// functionCall / functionResponse parts and base64 images inline data
+impl From<AnthropicRequest> for GeminiRequest {
+    fn from(anthropic: AnthropicRequest) -> Self {
+        let system_text = match anthropic.system {
+            Some(AnthropicSystemContent::Text(text)) => text,
+            Some(AnthropicSystemContent::Array(items)) => items
+                .into_iter()
+                .map(|item| match item {
+                    AnthropicSystemContentObject::Text { text } => text,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => String::new(),
+        };
+        let system_instruction = (!system_text.is_empty()).then(|| GeminiContent {
+            role: Some("user".to_string()),
+            parts: vec![GeminiPart::Text { text: system_text, thought: None, thought_signature: None }],
+        });
+
+        // functionResponse parts name the function, tool_result blocks only the call id
+        let mut tool_names: HashMap<String, String> = HashMap::new();
+        let mut contents = Vec::new();
+        for message in anthropic.messages.into_iter().flatten() {
+            let role = Some(if message.role == "assistant" { "model" } else { "user" }.to_string());
+            let blocks = match message.content {
+                AnthropicContent::Text(text) => {
+                    contents.push(GeminiContent { role, parts: vec![GeminiPart::Text { text, thought: None, thought_signature: None }] });
+                    continue;
+                }
+                AnthropicContent::Array(blocks) => blocks,
+            };
+            let mut parts = Vec::new();
+            // A thinking block's signature came from Gemini on the part after the
+            // thought, so it goes back onto the next text or function call
+            let mut signature = None;
+            for block in blocks {
+                match block {
+                    AnthropicContentObject::Text { text: t, .. } if t.is_empty() => {}
+                    // Consecutive text blocks make one part, in place among the others
+                    AnthropicContentObject::Text { text: t, .. } => match parts.last_mut() {
+                        Some(GeminiPart::Text { text, thought: None, .. }) if signature.is_none() => text.push_str(&t),
+                        _ => parts.push(GeminiPart::Text { text: t, thought: None, thought_signature: signature.take() }),
+                    },
+                    AnthropicContentObject::Image { source } => {
+                        if let (Some(mime_type), Some(data)) = (source.media_type, source.data) {
+                            parts.push(GeminiPart::InlineData { inline_data: GeminiInlineData { mime_type, data } });
+                        }
+                    }
+                    AnthropicContentObject::ToolUse { id, name, input } => {
+                        tool_names.insert(id, name.clone());
+                        parts.push(GeminiPart::FunctionCall {
+                            function_call: GeminiFunctionCall { name, args: input, thought_signature: None },
+                            thought_signature: signature.take(),
+                        });
+                    }
+                    AnthropicContentObject::ToolResult { tool_use_id, content } => {
+                        let name = tool_names.get(&tool_use_id).cloned().unwrap_or(tool_use_id);
+                        parts.push(GeminiPart::FunctionResponse {
+                            function_response: GeminiFunctionResponse { name, response: Some(serde_json::json!({"content": content})) },
+                        });
+                    }
+                    // The thought text itself is not sent back; server tool blocks have no equivalent
+                    AnthropicContentObject::Thinking { signature: s, .. } => signature = s.or(signature),
+                    AnthropicContentObject::RedactedThinking { .. } | AnthropicContentObject::Other(_) => {}
+                }
+            }
+            if !parts.is_empty() {
+                contents.push(GeminiContent { role, parts });
+            }
+        }
+
+        // Server tools (web search, code execution, ...) have no function equivalent
+        let function_declarations: Vec<GeminiFunctionDeclaration> = anthropic
+            .tools
+            .into_iter()
+            .flatten()
+            .filter(|tool| tool.extra_fields.get("type").is_none_or(|t| t == "custom"))
+            .map(|tool| {
+                let mut parameters = tool.input_schema;
+                let parameters = if parameters.is_null() {
+                    None
+                } else {
+                    clean_json_schema_for_gemini(&mut parameters);
+                    Some(parameters)
+                };
+                GeminiFunctionDeclaration { name: tool.name, description: Some(tool.description), parameters }
+            })
+            .collect();
+        let tools = (!function_declarations.is_empty()).then(|| vec![GeminiTool { function_declarations }]);
+        let mut extra_fields = anthropic.extra_fields;
+        Attribution::from_anthropic(anthropic.metadata.as_ref()).insert_gemini(&mut extra_fields);
+        if let Some(choice) = &anthropic.tool_choice {
+            extra_fields.insert("toolConfig".to_string(), choice.to_openai().0.to_gemini());
+        }
+
+        GeminiRequest {
+            model: anthropic.model,
+            contents,
+            system_instruction,
+            tools,
+            generation_config: Some(GeminiGenerationConfig {
+                stop_sequences: anthropic.stop_sequences,
+                temperature: anthropic.temperature,
+                max_output_tokens: Some(anthropic.max_tokens),
+                ..Default::default()
+            }),
+            cached_content: None,
+            stream: anthropic.stream,
+            extra_fields,
         }
     }
 }
@@ -193,7 +326,17 @@ fn clean_json_schema_for_gemini(schema: &mut Value) {
     }
 }
 
-fn parse_data_url(url: &str) -> Option<(String, String)> {
+/// Whether `name` names an explicit cache: `cachedContents/{id}`, or the full
+/// Vertex resource name `projects/{project}/locations/{location}/cachedContents/{id}`.
+pub(crate) fn is_cached_content_name(name: &str) -> bool {
+    let segments: Vec<&str> = name.split('/').collect();
+    if segments.iter().any(|s| s.is_empty()) {
+        return false;
+    }
+    matches!(segments.as_slice(), ["cachedContents", _] | ["projects", _, "locations", _, "cachedContents", _])
+}
+
+pub(crate) fn parse_data_url(url: &str) -> Option<(String, String)> {
     // Expected format: data:<mime>;base64,<data>
     if let Some(rest) = url.strip_prefix("data:") {
         let mut iter = rest.splitn(2, ',');
@@ -1,5 +1,9 @@
+use crate::converters::anthropic::{AnthropicContentObject, AnthropicResponse};
+use crate::converters::citations;
 use crate::converters::openai::OpenAIResponse;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 
 use crate::converters::gemini::{
     gemini_candidate::GeminiCandidate, gemini_content::GeminiContent,
@@ -10,6 +14,8 @@ use crate::converters::gemini::{
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GeminiResponse {
+    // Absent when the prompt was blocked; see prompt_feedback
+    #[serde(default)]
     pub candidates: Vec<GeminiCandidate>,
     #[serde(rename = "usageMetadata")]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -23,6 +29,8 @@ pub struct GeminiResponse {
     #[serde(rename = "responseId")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub response_id: Option<String>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
 
 impl From<OpenAIResponse> for GeminiResponse {
@@ -39,14 +47,17 @@ impl From<OpenAIResponse> for GeminiResponse {
             }
         }
 
-        if let Some(content) = &openai_resp.choices[0].message.content {
-            if !content.trim().is_empty() {
-                parts.push(GeminiPart::Text {
-                    text: content.clone(),
-                    thought: None,
-                    thought_signature: None,
-                });
-            }
+        let message = &openai_resp.choices[0].message;
+        let mut grounding_metadata = None;
+        if let Some(content) = &message.content
+            && !content.trim().is_empty()
+        {
+            grounding_metadata = citations::gemini_grounding(content, parts.len(), message.annotations.as_ref());
+            parts.push(GeminiPart::Text {
+                text: content.clone(),
+                thought: None,
+                thought_signature: citations::thought_signature(&message.extra_fields),
+            });
         }
 
         if let Some(tool_calls) = &openai_resp.choices[0].message.tool_calls {
@@ -59,7 +70,7 @@ impl From<OpenAIResponse> for GeminiResponse {
                         args,
                         thought_signature: None,
                     },
-                    thought_signature: None,
+                    thought_signature: citations::thought_signature(&tc.extra_fields),
                 });
             }
         }
@@ -67,6 +78,7 @@ impl From<OpenAIResponse> for GeminiResponse {
         let finish_reason = match openai_resp.choices[0].finish_reason.as_str() {
             "stop" => Some(GeminiFinishReason::Stop),
             "length" => Some(GeminiFinishReason::MaxTokens),
+            "content_filter" => Some(GeminiFinishReason::Safety),
             // No perfect mapping for tool_calls; leave unspecified
             "tool_calls" => Some(GeminiFinishReason::FinishReasonUnspecified),
             _ => Some(GeminiFinishReason::FinishReasonUnspecified),
@@ -79,6 +91,9 @@ impl From<OpenAIResponse> for GeminiResponse {
             },
             finish_reason,
             index: None,
+            grounding_metadata,
+            citation_metadata: None,
+            extra_fields: HashMap::new(),
         };
 
         GeminiResponse {
@@ -89,10 +104,164 @@ impl From<OpenAIResponse> for GeminiResponse {
                 total_token_count: Some(u.total_tokens),
                 prompt_tokens_details: None,
                 thoughts_token_count: None,
+                cached_content_token_count: u.prompt_tokens_details.and_then(|d| d.cached_tokens),
             }),
             model_version: Some(openai_resp.model),
             prompt_feedback: None,
             response_id: Some(openai_resp.id),
+            extra_fields: HashMap::new(),
+        }
+    }
+}
+
+// Direct conversion, same result as going through OpenAIResponse
+impl From<AnthropicResponse> for GeminiResponse {
+    fn from(resp: AnthropicResponse) -> Self {
+        let mut thinking = String::new();
+        let mut text = String::new();
+        // Annotation indices count characters of the joined text blocks
+        let mut text_chars = 0;
+        let mut annotations = Vec::new();
+        let mut calls = Vec::new();
+        for block in resp.content {
+            match block {
+                AnthropicContentObject::Text { text: t, citations } => {
+                    let start = text_chars;
+                    text_chars += t.chars().count();
+                    if let Some(citations) = citations {
+                        annotations.extend(citations::anthropic_annotations(&citations, start, text_chars));
+                    }
+                    text.push_str(&t);
+                }
+                AnthropicContentObject::Thinking { thinking: t, .. } => thinking.push_str(&t),
+                AnthropicContentObject::RedactedThinking { data } => {
+                    thinking.push_str(&format!("<redacted_thinking>{}</redacted_thinking>", data));
+                }
+                AnthropicContentObject::ToolUse { name, input, .. } => calls.push(GeminiPart::FunctionCall {
+                    function_call: GeminiFunctionCall { name, args: input, thought_signature: None },
+                    thought_signature: None,
+                }),
+                AnthropicContentObject::Image { .. }
+                | AnthropicContentObject::ToolResult { .. }
+                | AnthropicContentObject::Other(_) => {}
+            }
+        }
+
+        let mut parts = Vec::new();
+        if !thinking.trim().is_empty() {
+            parts.push(GeminiPart::Text { text: thinking, thought: Some(true), thought_signature: None });
+        }
+        let mut grounding_metadata = None;
+        if !text.trim().is_empty() {
+            let annotations = (!annotations.is_empty()).then_some(annotations);
+            grounding_metadata = citations::gemini_grounding(&text, parts.len(), annotations.as_ref());
+            parts.push(GeminiPart::Text { text, thought: None, thought_signature: None });
+        }
+        parts.extend(calls);
+
+        let finish_reason = match resp.stop_reason.as_deref() {
+            Some("max_tokens") => GeminiFinishReason::MaxTokens,
+            Some("tool_use") => GeminiFinishReason::FinishReasonUnspecified,
+            Some("refusal") => GeminiFinishReason::Safety,
+            _ => GeminiFinishReason::Stop,
+        };
+
+        GeminiResponse {
+            candidates: vec![GeminiCandidate {
+                content: GeminiContent { role: Some("model".to_string()), parts },
+                finish_reason: Some(finish_reason),
+                index: None,
+                grounding_metadata,
+                citation_metadata: None,
+                extra_fields: HashMap::new(),
+            }],
+            usage_metadata: resp.usage.map(|u| GeminiUsage {
+                prompt_token_count: Some(u.prompt_tokens()),
+                candidates_token_count: Some(u.output_tokens),
+                total_token_count: Some(u.prompt_tokens() + u.output_tokens),
+                prompt_tokens_details: None,
+                thoughts_token_count: None,
+                cached_content_token_count: u.cache_read_input_tokens,
+            }),
+            model_version: Some(resp.model),
+            prompt_feedback: None,
+            response_id: Some(resp.id),
+            extra_fields: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock;
+    use serde_json::json;
+
+    #[test]
+    fn test_anthropic_to_gemini_response_matches_openai_pivot() {
+        let anthropic: AnthropicResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-sonnet-4",
+            "content": [
+                {"type": "thinking", "thinking": "Look it up.", "signature": "s"},
+                {"type": "text", "text": "It opens at 9.", "citations": [
+                    {"type": "web_search_result_location", "url": "https://a.example", "title": "A", "cited_text": "9"}
+                ]},
+                {"type": "text", "text": " Shall I book?"},
+                {"type": "tool_use", "id": "toolu_1", "name": "book", "input": {"time": "9"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 5, "output_tokens": 9}
+        }))
+        .unwrap();
+
+        let direct = {
+            let _frozen = clock::freeze(1_700_000_000);
+            serde_json::to_value(GeminiResponse::from(anthropic.clone())).unwrap()
+        };
+        let pivoted = {
+            let _frozen = clock::freeze(1_700_000_000);
+            serde_json::to_value(GeminiResponse::from(OpenAIResponse::from(anthropic))).unwrap()
+        };
+        assert_eq!(direct, pivoted);
+        assert_eq!(direct["candidates"][0]["content"]["parts"][1]["text"], "It opens at 9. Shall I book?");
+        assert!(direct["candidates"][0]["groundingMetadata"]["groundingSupports"].is_array());
+        assert_eq!(direct["usageMetadata"]["totalTokenCount"], 14);
+    }
+
+    #[test]
+    fn test_cached_prompt_tokens_cross_formats() {
+        let openai: OpenAIResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "gpt",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "hi"}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 100, "completion_tokens": 2, "total_tokens": 102, "prompt_tokens_details": {"cached_tokens": 64}}
+        }))
+        .unwrap();
+        let gemini = serde_json::to_value(GeminiResponse::from(openai)).unwrap();
+        assert_eq!(gemini["usageMetadata"]["cachedContentTokenCount"], 64);
+
+        let gemini: GeminiResponse = serde_json::from_value(gemini).unwrap();
+        let openai = serde_json::to_value(OpenAIResponse::from(gemini)).unwrap();
+        assert_eq!(openai["usage"]["prompt_tokens_details"]["cached_tokens"], 64);
+
+        let request: crate::converters::gemini::GeminiRequest = serde_json::from_value(json!({
+            "contents": [{"role": "user", "parts": [{"text": "summarize"}]}],
+            "cachedContent": "cachedContents/abc123"
+        }))
+        .unwrap();
+        assert_eq!(request.cached_content.as_deref(), Some("cachedContents/abc123"));
+        assert_eq!(serde_json::to_value(&request).unwrap()["cachedContent"], "cachedContents/abc123");
+
+        use crate::converters::gemini::gemini_request::is_cached_content_name;
+        assert!(is_cached_content_name("cachedContents/abc123"));
+        assert!(is_cached_content_name("projects/p1/locations/us-central1/cachedContents/abc123"));
+        for name in ["abc123", "cachedContents/", "cachedContents/a/b", "projects/p1/cachedContents/abc123", "projects//locations/l/cachedContents/x"] {
+            assert!(!is_cached_content_name(name), "{}", name);
         }
     }
 }
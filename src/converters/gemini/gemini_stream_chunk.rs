@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 
 use crate::converters::gemini::{GeminiCandidate, GeminiFinishReason, GeminiUsage};
 use crate::converters::gemini::{GeminiContent, GeminiPart};
@@ -10,6 +12,8 @@ use crate::converters::openai::{
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GeminiStreamChunk {
+    // Absent when the prompt was blocked; see prompt_feedback
+    #[serde(default)]
     pub candidates: Vec<GeminiCandidate>,
     #[serde(rename = "usageMetadata")]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -20,6 +24,8 @@ pub struct GeminiStreamChunk {
     #[serde(rename = "responseId")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub response_id: Option<String>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
 
 impl From<OpenAIStreamChunk> for GeminiStreamChunk {
@@ -39,9 +45,11 @@ impl From<OpenAIStreamChunk> for GeminiStreamChunk {
                 total_token_count: Some(u.total_tokens as u32),
                 prompt_tokens_details: None,
                 thoughts_token_count: None,
+                cached_content_token_count: u.prompt_tokens_details.and_then(|d| d.cached_tokens),
             }),
             model_version: Some(openai_chunk.model),
             response_id: Some(openai_chunk.id),
+            extra_fields: HashMap::new(),
         }
     }
 }
@@ -76,9 +84,10 @@ fn map_openai_choice_to_gemini_candidate(choice: OpenAIStreamChoice) -> GeminiCa
             for tc in tool_calls.into_iter() {
                 if let Some(func) = tc.function {
                     let name = func.name.unwrap_or_default();
+                    // Arguments that are not JSON (a fragment, or a flushed incomplete call) stay raw text
                     let args_val = func
                         .arguments
-                        .and_then(|a| serde_json::from_str::<serde_json::Value>(&a).ok())
+                        .map(|a| serde_json::from_str::<serde_json::Value>(&a).unwrap_or(serde_json::Value::String(a)))
                         .unwrap_or_else(|| serde_json::Value::String(String::new()));
                     parts.push(GeminiPart::FunctionCall {
                         function_call: GeminiFunctionCall {
@@ -99,6 +108,9 @@ fn map_openai_choice_to_gemini_candidate(choice: OpenAIStreamChoice) -> GeminiCa
         content: GeminiContent { role, parts },
         finish_reason,
         index: Some(choice.index as u32),
+        grounding_metadata: None,
+        citation_metadata: None,
+        extra_fields: HashMap::new(),
     }
 }
 
@@ -106,6 +118,7 @@ fn map_openai_finish_reason(r: String) -> Option<GeminiFinishReason> {
     match r.as_str() {
         "stop" => Some(GeminiFinishReason::Stop),
         "length" => Some(GeminiFinishReason::MaxTokens),
+        "content_filter" => Some(GeminiFinishReason::Safety),
         // Tool call related stop doesn't have a direct mapping; keep as unspecified
         "tool_calls" => Some(GeminiFinishReason::FinishReasonUnspecified),
         _ => Some(GeminiFinishReason::FinishReasonUnspecified),
@@ -14,6 +14,10 @@ pub struct GeminiUsage {
     #[serde(rename = "thoughtsTokenCount")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub thoughts_token_count: Option<u32>,
+    // Prompt tokens served from a cache, explicit (cachedContent) or implicit
+    #[serde(rename = "cachedContentTokenCount")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_content_token_count: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
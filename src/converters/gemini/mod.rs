@@ -6,6 +6,7 @@ pub mod gemini_function_declaration;
 pub mod gemini_funtion_call;
 pub mod gemini_funtion_response;
 pub mod gemini_generation_config;
+pub mod gemini_grounding;
 pub mod gemini_harm_category;
 pub mod gemini_harm_probability;
 pub mod gemini_inline_data;
@@ -24,6 +25,10 @@ pub use gemini_candidate::GeminiCandidate;
 pub use gemini_content::GeminiContent;
 pub use gemini_finish_reason::GeminiFinishReason;
 pub use gemini_function_declaration::GeminiFunctionDeclaration;
+pub use gemini_grounding::{
+    GeminiCitationMetadata, GeminiCitationSource, GeminiGroundingChunk, GeminiGroundingMetadata,
+    GeminiGroundingSupport, GeminiRetrievedContext, GeminiSegment, GeminiWebSource,
+};
 pub use gemini_harm_category::GeminiHarmCategory;
 pub use gemini_harm_probability::GeminiHarmProbability;
 pub use gemini_inline_data::GeminiInlineData;
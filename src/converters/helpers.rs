@@ -1,4 +1,7 @@
+use crate::config::ApiType;
+use crate::converters::gemini::GeminiFinishReason;
 use serde_json::{json, Value};
+use std::collections::HashMap;
 
 
 // 停止原因映射
@@ -7,7 +10,7 @@ pub fn map_openai_finish_reason_to_anthropic(finish_reason: &Value) -> Value {
         Some("stop") => json!("end_turn"),
         Some("length") => json!("max_tokens"),
         Some("tool_calls") => json!("tool_use"),
-        Some("content_filter") => json!("stop_sequence"),
+        Some("content_filter") => json!("refusal"),
         _ => json!("end_turn")
     }
 }
@@ -18,6 +21,115 @@ pub fn map_anthropic_stop_reason_to_openai(stop_reason: Option<&Value>) -> Value
         Some("max_tokens") => json!("length"),
         Some("tool_use") => json!("tool_calls"),
         Some("stop_sequence") => json!("stop"),
+        Some("refusal") => json!("content_filter"),
         _ => json!("stop")
     }
 }
+
+/// An Anthropic stream `error` event (e.g. `overloaded_error` mid-answer),
+/// as (error type, message).
+pub fn anthropic_stream_error(data: &str) -> Option<(String, String)> {
+    if !data.contains("\"error\"") {
+        return None;
+    }
+    let value: Value = serde_json::from_str(data).ok()?;
+    if value.get("type")?.as_str()? != "error" {
+        return None;
+    }
+    let error = value.get("error");
+    let r#type = error.and_then(|e| e.get("type")).and_then(Value::as_str).unwrap_or("api_error");
+    let message = error.and_then(|e| e.get("message")).and_then(Value::as_str).unwrap_or("upstream stream error");
+    Some((r#type.to_string(), message.to_string()))
+}
+
+/// An error sent in place of a chunk mid-stream, as (error type, message):
+/// Anthropic `error` events, or OpenAI and Gemini data with a top-level `error`.
+pub fn stream_error(api_type: &ApiType, data: &str) -> Option<(String, String)> {
+    if *api_type == ApiType::Anthropic {
+        return anthropic_stream_error(data);
+    }
+    if !data.contains("\"error\"") {
+        return None;
+    }
+    let value: Value = serde_json::from_str(data).ok()?;
+    let error = value.get("error")?;
+    let r#type = error.get("type").or_else(|| error.get("status")).and_then(Value::as_str).unwrap_or("api_error");
+    let message = error.get("message").and_then(Value::as_str).or_else(|| error.as_str()).unwrap_or("upstream stream error");
+    Some((r#type.to_string(), message.to_string()))
+}
+
+/// Whether an Anthropic error type says the upstream, not the request, failed.
+pub fn is_upstream_error_type(error_type: &str) -> bool {
+    matches!(error_type, "overloaded_error" | "api_error" | "rate_limit_error" | "timeout_error")
+}
+
+/// HTTP code and status a Gemini error would carry for an Anthropic error type.
+pub fn anthropic_error_to_gemini_status(error_type: &str) -> (u16, &'static str) {
+    match error_type {
+        "invalid_request_error" | "request_too_large" => (400, "INVALID_ARGUMENT"),
+        "authentication_error" => (401, "UNAUTHENTICATED"),
+        "permission_error" => (403, "PERMISSION_DENIED"),
+        "not_found_error" => (404, "NOT_FOUND"),
+        "rate_limit_error" => (429, "RESOURCE_EXHAUSTED"),
+        "overloaded_error" => (503, "UNAVAILABLE"),
+        "timeout_error" => (504, "DEADLINE_EXCEEDED"),
+        _ => (500, "INTERNAL"),
+    }
+}
+
+/// Response header naming a content filter that ended an upstream answer.
+pub const FINISH_DETAIL_HEADER: &str = "x-llm-router-finish-detail";
+
+/// Header value for an upstream answer that a content filter ended, as a
+/// structured-field item, e.g. `content_filter; provider=gemini; reason=SAFETY; scope=output`.
+/// Finish reasons are lossy across formats; this keeps the upstream's own.
+pub fn content_filter_detail(api_type: &ApiType, body: &str) -> Option<String> {
+    // Most answers are not filtered; skip parsing those
+    let markers: &[&str] = match api_type {
+        ApiType::OpenAI => &["content_filter"],
+        ApiType::Anthropic => &["refusal"],
+        // A blocked prompt, or a filtering finish reason (`SAFETY` also covers `IMAGE_SAFETY`)
+        ApiType::Gemini => &["blockReason", "SAFETY", "RECITATION", "BLOCKLIST", "PROHIBITED_CONTENT", "SPII"],
+    };
+    if !markers.iter().any(|marker| body.contains(marker)) {
+        return None;
+    }
+    let body: Value = serde_json::from_str(body).ok()?;
+    let (provider, reason, scope) = match api_type {
+        ApiType::OpenAI => {
+            let reason = body.pointer("/choices/0/finish_reason")?.as_str()?;
+            ("openai", (reason == "content_filter").then_some(reason)?, "output")
+        }
+        ApiType::Anthropic => {
+            let reason = body.get("stop_reason")?.as_str()?;
+            ("anthropic", (reason == "refusal").then_some(reason)?, "output")
+        }
+        ApiType::Gemini => match body.pointer("/promptFeedback/blockReason").and_then(Value::as_str) {
+            Some(reason) => ("gemini", reason, "prompt"),
+            None => {
+                let reason = body.pointer("/candidates/0/finishReason")?;
+                let filtered = serde_json::from_value::<GeminiFinishReason>(reason.clone()).ok()?.is_content_filter();
+                ("gemini", filtered.then_some(reason.as_str()?)?, "output")
+            }
+        },
+    };
+    Some(format!("content_filter; provider={}; reason={}; scope={}", provider, reason, scope))
+}
+
+// OpenAI has no stop_sequence field; the matched sequence travels as an
+// Azure-style `finish_details` extension on the choice
+const FINISH_DETAILS: &str = "finish_details";
+
+/// Choice fields recording the stop sequence that ended generation, if any.
+pub fn finish_details_fields(stop_sequence: Option<&String>) -> HashMap<String, Value> {
+    let mut fields = HashMap::new();
+    if let Some(stop) = stop_sequence {
+        fields.insert(FINISH_DETAILS.to_string(), json!({ "type": "stop", "stop": stop }));
+    }
+    fields
+}
+
+/// The stop sequence recorded by `finish_details_fields`.
+pub fn stop_sequence(fields: &HashMap<String, Value>) -> Option<String> {
+    fields.get(FINISH_DETAILS)?.get("stop")?.as_str().map(str::to_string)
+}
@@ -1,3 +1,11 @@
+//! Conversions between the OpenAI, Anthropic and Gemini formats.
+//!
+//! Most format types keep fields they do not model in a flattened
+//! `extra_fields` map, so a body passed between the same format keeps
+//! provider-specific fields; conversions to another format drop them.
+
+pub mod attribution;
+pub mod citations;
 pub mod helpers;
 pub mod openai;
 pub mod anthropic;
@@ -1,3 +1,5 @@
+pub mod openai_annotation;
+pub mod openai_audio;
 pub mod openai_choice;
 pub mod openai_content;
 pub mod openai_content_item;
@@ -17,8 +19,11 @@ pub mod openai_stream_tool_call_function;
 pub mod openai_tool;
 pub mod openai_tool_call;
 pub mod openai_tool_call_function;
+pub mod openai_tool_choice;
 pub mod openai_usage;
 
+pub use openai_annotation::{OpenAIAnnotation, OpenAIFileCitation, OpenAIUrlCitation};
+pub use openai_audio::OpenAIAudio;
 pub use openai_choice::OpenAIChoice;
 pub use openai_content::OpenAIContent;
 pub use openai_content_item::OpenAIContentItem;
@@ -26,7 +31,7 @@ pub use openai_function::OpenAIFunction;
 pub use openai_image_url::OpenAIImageUrl;
 pub use openai_message::OpenAIMessage;
 pub use openai_prompt_tokens_details::OpenAIPromptTokensDetails;
-pub use openai_request::OpenAIRequest;
+pub use openai_request::{OpenAIRequest, OpenAIStop};
 pub use openai_response::OpenAIResponse;
 pub use openai_response_message::OpenAIResponseMessage;
 pub use openai_stream_choice::OpenAIStreamChoice;
@@ -37,4 +42,5 @@ pub use openai_stream_tool_call_function::OpenAIStreamToolCallFunction;
 pub use openai_tool::OpenAITool;
 pub use openai_tool_call::OpenAIToolCall;
 pub use openai_tool_call_function::OpenAIToolCallFunction;
+pub use openai_tool_choice::{OpenAIToolChoice, ToolChoiceMode};
 pub use openai_usage::OpenAIUsage;
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// A message annotation; `url_citation` and `file_citation` mark a span of the
+/// content backed by a web source or an uploaded file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum OpenAIAnnotation {
+    #[serde(rename = "url_citation")]
+    UrlCitation { url_citation: OpenAIUrlCitation },
+    #[serde(rename = "file_citation")]
+    FileCitation {
+        file_citation: OpenAIFileCitation,
+        #[serde(default)]
+        start_index: usize,
+        #[serde(default)]
+        end_index: usize,
+    },
+    /// Any other annotation type, kept verbatim
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+/// Indices are character offsets into the message content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIUrlCitation {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub start_index: usize,
+    pub end_index: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIFileCitation {
+    pub file_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
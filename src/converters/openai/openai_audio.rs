@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Spoken answer of a request with `modalities: ["text", "audio"]`. Stream
+/// deltas carry pieces of it: `data` and `transcript` arrive in fragments.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpenAIAudio {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    // Base64-encoded audio in the requested format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transcript: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
+}
+
+impl OpenAIAudio {
+    /// Fold a stream delta into the audio collected so far.
+    pub fn append(&mut self, delta: OpenAIAudio) {
+        if delta.id.is_some() {
+            self.id = delta.id;
+        }
+        if delta.expires_at.is_some() {
+            self.expires_at = delta.expires_at;
+        }
+        if let Some(data) = delta.data {
+            self.data.get_or_insert_default().push_str(&data);
+        }
+        if let Some(transcript) = delta.transcript {
+            self.transcript.get_or_insert_default().push_str(&transcript);
+        }
+        self.extra_fields.extend(delta.extra_fields);
+    }
+}
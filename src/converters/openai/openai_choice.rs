@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use crate::converters::openai::openai_response_message::OpenAIResponseMessage;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -6,4 +8,6 @@ pub struct OpenAIChoice {
     pub index: i32,
     pub message: OpenAIResponseMessage,
     pub finish_reason: String,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
\ No newline at end of file
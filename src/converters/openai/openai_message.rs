@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use crate::converters::openai::openai_content::OpenAIContent;
 use crate::converters::openai::openai_tool_call::OpenAIToolCall;
 
@@ -12,4 +14,6 @@ pub struct OpenAIMessage {
     pub tool_call_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reasoning_content: Option<String>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
\ No newline at end of file
@@ -2,16 +2,19 @@ use crate::converters::anthropic::{
     AnthropicContent, AnthropicContentObject, AnthropicRequest, AnthropicSystemContent,
     AnthropicSystemContentObject,
 };
+use crate::converters::attribution::Attribution;
 use crate::converters::gemini::{GeminiPart, GeminiRequest};
 use crate::converters::openai::{
     OpenAIContent, OpenAIContentItem, OpenAIFunction, OpenAIImageUrl, OpenAIMessage, OpenAITool,
-    OpenAIToolCall, OpenAIToolCallFunction,
+    OpenAIToolCall, OpenAIToolCallFunction, OpenAIToolChoice,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIRequest {
+    // May be omitted when the caller's virtual key has a default model
+    #[serde(default)]
     pub model: String,
     #[serde(alias = "input")]
     pub messages: Vec<OpenAIMessage>,
@@ -23,12 +26,41 @@ pub struct OpenAIRequest {
     pub response_format: Option<OpenAIResponseFormat>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<OpenAITool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<OpenAIToolChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<OpenAIStop>,
+    // Output types, e.g. ["text", "audio"]; audio needs an OpenAI upstream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modalities: Option<Vec<String>>,
+    // Values of the client's OpenAI-Beta header; not part of the body
+    #[serde(skip)]
+    pub betas: Vec<String>,
     #[serde(flatten)]
     pub extra_fields: HashMap<String, serde_json::Value>,
 }
 
+/// `stop` accepts a single sequence or a list of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OpenAIStop {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl OpenAIStop {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            OpenAIStop::Single(s) => vec![s],
+            OpenAIStop::Multiple(v) => v,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIResponseFormat {
     #[serde(rename = "type")]
@@ -76,6 +108,7 @@ impl From<AnthropicRequest> for OpenAIRequest {
                 tool_calls: None,
                 tool_call_id: None,
                 reasoning_content: None,
+                extra_fields: HashMap::new(),
             });
         }
 
@@ -93,7 +126,7 @@ impl From<AnthropicRequest> for OpenAIRequest {
                     AnthropicContent::Array(array) => {
                         for item in array.iter() {
                             match item {
-                                AnthropicContentObject::Text { text } => {
+                                AnthropicContentObject::Text { text, .. } => {
                                     content_items.push(OpenAIContentItem {
                                         r#type: "text".to_string(),
                                         text: Some(text.clone()),
@@ -106,13 +139,13 @@ impl From<AnthropicRequest> for OpenAIRequest {
                                 } => {}
                                 AnthropicContentObject::RedactedThinking { data: _ } => {}
                                 AnthropicContentObject::Image { source } => {
-                                    let image_url = if &source.r#type == "base64" {
-                                        format!(
-                                            "data:{:?};base64,{:?}",
-                                            source.media_type, source.data
-                                        )
-                                    } else {
-                                        source.url.clone().expect("url error")
+                                    let image_url = match (&source.media_type, &source.data, &source.url) {
+                                        (Some(media_type), Some(data), _) if source.r#type == "base64" => {
+                                            format!("data:{};base64,{}", media_type, data)
+                                        }
+                                        (_, _, Some(url)) => url.clone(),
+                                        // Nothing to point at
+                                        _ => continue,
                                     };
                                     content_items.push(OpenAIContentItem {
                                         r#type: "image_url".to_string(),
@@ -129,6 +162,7 @@ impl From<AnthropicRequest> for OpenAIRequest {
                                             arguments: serde_json::to_string(&input)
                                                 .unwrap_or_else(|_| "{}".to_string()),
                                         },
+                                        extra_fields: HashMap::new(),
                                     });
                                 }
                                 AnthropicContentObject::ToolResult {
@@ -141,8 +175,11 @@ impl From<AnthropicRequest> for OpenAIRequest {
                                         tool_calls: None,
                                         tool_call_id: Some(tool_use_id.clone()),
                                         reasoning_content: None,
+                                        extra_fields: HashMap::new(),
                                     });
                                 }
+                                // Server tool blocks have no OpenAI equivalent
+                                AnthropicContentObject::Other(_) => {}
                             }
                         }
                     }
@@ -159,6 +196,7 @@ impl From<AnthropicRequest> for OpenAIRequest {
                         },
                         tool_call_id: None,
                         reasoning_content: None,
+                        extra_fields: HashMap::new(),
                     });
                 } else {
                     messages.push(OpenAIMessage {
@@ -171,20 +209,33 @@ impl From<AnthropicRequest> for OpenAIRequest {
                         },
                         tool_call_id: None,
                         reasoning_content: None,
+                        extra_fields: HashMap::new(),
                     });
                 }
             }
         }
 
+        let (tool_choice, parallel_tool_calls) = match &anthropic_request.tool_choice {
+            Some(choice) => {
+                let (choice, parallel) = choice.to_openai();
+                (Some(choice), parallel)
+            }
+            None => (None, None),
+        };
+        let mut extra_fields = anthropic_request.extra_fields;
+        Attribution::from_anthropic(anthropic_request.metadata.as_ref()).insert_openai(&mut extra_fields);
+
         let openai_request = OpenAIRequest {
             model: anthropic_request.model,
             messages,
             max_tokens: Some(anthropic_request.max_tokens),
             temperature: None,
             response_format: None,
-            tools: anthropic_request.tools.map(|tools| {
-                tools
+            // Server tools (web search, code execution, ...) have no function equivalent
+            tools: anthropic_request.tools.and_then(|tools| {
+                let tools: Vec<OpenAITool> = tools
                     .into_iter()
+                    .filter(|tool| tool.extra_fields.get("type").is_none_or(|t| t == "custom"))
                     .map(|tool| OpenAITool {
                         r#type: "function".to_string(),
                         function: OpenAIFunction {
@@ -194,10 +245,16 @@ impl From<AnthropicRequest> for OpenAIRequest {
                         },
                         strict: None,
                     })
-                    .collect()
+                    .collect();
+                (!tools.is_empty()).then_some(tools)
             }),
+            tool_choice,
+            parallel_tool_calls,
             stream: anthropic_request.stream,
-            extra_fields: anthropic_request.extra_fields,
+            stop: anthropic_request.stop_sequences.map(OpenAIStop::Multiple),
+            betas: Vec::new(),
+            modalities: None,
+            extra_fields,
         };
 
         openai_request
@@ -205,7 +262,9 @@ impl From<AnthropicRequest> for OpenAIRequest {
 }
 
 impl From<GeminiRequest> for OpenAIRequest {
-    fn from(g: GeminiRequest) -> Self {
+    fn from(mut g: GeminiRequest) -> Self {
+        let attribution = Attribution::take_gemini(&mut g.extra_fields);
+
         let mut messages = Vec::new();
 
         if let Some(sys) = g.system_instruction {
@@ -223,6 +282,7 @@ impl From<GeminiRequest> for OpenAIRequest {
                     tool_calls: None,
                     tool_call_id: None,
                     reasoning_content: None,
+                    extra_fields: HashMap::new(),
                 });
             }
         }
@@ -233,17 +293,33 @@ impl From<GeminiRequest> for OpenAIRequest {
                 _ => "user",
             };
             let mut text = String::new();
+            let mut images = Vec::new();
             for p in c.parts.into_iter() {
-                if let GeminiPart::Text { text: t, .. } = p {
-                    text.push_str(&t);
+                match p {
+                    GeminiPart::Text { text: t, .. } => text.push_str(&t),
+                    GeminiPart::InlineData { inline_data } => images.push(OpenAIContentItem {
+                        r#type: "image_url".to_string(),
+                        text: None,
+                        image_url: Some(OpenAIImageUrl {
+                            url: format!("data:{};base64,{}", inline_data.mime_type, inline_data.data),
+                        }),
+                    }),
+                    _ => {}
                 }
             }
+            let content = if images.is_empty() {
+                OpenAIContent::Text(text)
+            } else {
+                let text = (!text.is_empty()).then(|| OpenAIContentItem { r#type: "text".to_string(), text: Some(text), image_url: None });
+                OpenAIContent::Array(text.into_iter().chain(images).collect())
+            };
             messages.push(crate::converters::openai::OpenAIMessage {
                 role: role.to_string(),
-                content: OpenAIContent::Text(text),
+                content,
                 tool_calls: None,
                 tool_call_id: None,
                 reasoning_content: None,
+                extra_fields: HashMap::new(),
             });
         }
 
@@ -273,6 +349,9 @@ impl From<GeminiRequest> for OpenAIRequest {
             _ => None,
         };
 
+        let mut extra_fields = std::mem::take(&mut g.extra_fields);
+        attribution.insert_openai(&mut extra_fields);
+
         OpenAIRequest {
             model: g.model,
             messages,
@@ -283,8 +362,17 @@ impl From<GeminiRequest> for OpenAIRequest {
             temperature: g.generation_config.as_ref().and_then(|gc| gc.temperature),
             response_format,
             tools: None,
+            tool_choice: None,
+            parallel_tool_calls: None,
             stream: g.stream,
-            extra_fields: g.extra_fields,
+            stop: g
+                .generation_config
+                .as_ref()
+                .and_then(|gc| gc.stop_sequences.clone())
+                .map(OpenAIStop::Multiple),
+            betas: Vec::new(),
+            modalities: None,
+            extra_fields,
         }
     }
 }
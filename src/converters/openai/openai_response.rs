@@ -1,11 +1,13 @@
 use crate::converters::anthropic::{AnthropicContentObject, AnthropicResponse};
 use crate::converters::gemini::{GeminiResponse, GeminiPart, GeminiFinishReason};
-use crate::converters::helpers;
+use crate::converters::{citations, helpers};
 use crate::converters::openai::{
-    OpenAIChoice, OpenAIResponseMessage, OpenAIToolCall, OpenAIToolCallFunction, OpenAIUsage,
+    OpenAIChoice, OpenAIPromptTokensDetails, OpenAIResponseMessage, OpenAIToolCall, OpenAIToolCallFunction, OpenAIUsage,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
+use crate::utils::clock;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIResponse {
@@ -20,18 +22,27 @@ pub struct OpenAIResponse {
     pub system_fingerprint: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub service_tier : Option<String>,
-
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
 
 impl From<AnthropicResponse> for OpenAIResponse {
     fn from(anthropic_resp: AnthropicResponse) -> Self {
         let mut reasoning_text = String::new();
         let mut content_text = String::new();
+        // Annotation indices count characters of the joined text blocks
+        let mut content_chars = 0;
+        let mut annotations = Vec::new();
         let mut tool_calls = Vec::new();
 
         for content in anthropic_resp.content {
             match content {
-                AnthropicContentObject::Text { text } => {
+                AnthropicContentObject::Text { text, citations } => {
+                    let start = content_chars;
+                    content_chars += text.chars().count();
+                    if let Some(citations) = citations {
+                        annotations.extend(citations::anthropic_annotations(&citations, start, content_chars));
+                    }
                     content_text.push_str(&text);
                 }
                 AnthropicContentObject::Thinking {
@@ -57,6 +68,7 @@ impl From<AnthropicResponse> for OpenAIResponse {
                             arguments: serde_json::to_string(&input)
                                 .unwrap_or_else(|_| "{}".to_string()),
                         },
+                        extra_fields: HashMap::new(),
                     });
                 }
                 AnthropicContentObject::ToolResult {
@@ -65,16 +77,16 @@ impl From<AnthropicResponse> for OpenAIResponse {
                 } => {
                     // 工具结果在响应中不太常见，暂不处理
                 }
+                AnthropicContentObject::Other(_) => {
+                    // Server tool blocks have no OpenAI equivalent
+                }
             }
         }
 
         OpenAIResponse {
             id: anthropic_resp.id,
             object: Some("chat.completion".to_string()),
-            created: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
+            created: clock::now_secs(),
             model: anthropic_resp.model,
             choices: vec![OpenAIChoice {
                 index: 0,
@@ -95,6 +107,9 @@ impl From<AnthropicResponse> for OpenAIResponse {
                     } else {
                         Some(tool_calls)
                     },
+                    annotations: if annotations.is_empty() { None } else { Some(annotations) },
+                    audio: None,
+                    extra_fields: HashMap::new(),
                 },
                 finish_reason: match anthropic_resp.stop_reason {
                     Some(s) => helpers::map_anthropic_stop_reason_to_openai(Some(&Value::String(
@@ -105,44 +120,44 @@ impl From<AnthropicResponse> for OpenAIResponse {
                     .to_string(),
                     None => "stop".to_string(),
                 },
+                extra_fields: helpers::finish_details_fields(anthropic_resp.stop_sequence.as_ref()),
             }],
-            usage: anthropic_resp.usage.map(|usage| OpenAIUsage {
-                prompt_tokens: usage.input_tokens,
-                completion_tokens: usage.output_tokens,
-                total_tokens: usage.input_tokens + usage.output_tokens,
-                completion_tokens_details: None,
-                prompt_tokens_details: None,
-            }),
+            usage: anthropic_resp.usage.map(OpenAIUsage::from),
             service_tier: None,
             system_fingerprint: None,
+            extra_fields: HashMap::new(),
         }
     }
 }
 
 impl From<GeminiResponse> for OpenAIResponse {
     fn from(resp: GeminiResponse) -> Self {
-        let now_secs = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-
-        let (text, reasoning_text, tool_calls, finish_reason) = if let Some(first) = resp.candidates.get(0) {
+        let (text, reasoning_text, tool_calls, finish_reason, annotations, signature) = if let Some(first) = resp.candidates.first() {
             let mut t = String::new();
             let mut rt = String::new();
             let mut tool_calls: Vec<OpenAIToolCall> = Vec::new();
             let mut saw_tool_call = false;
+            // Where each answer part starts in `t`, for grounding segments
+            let mut part_offsets = HashMap::new();
+            let mut signature = None;
             for (idx, p) in first.content.parts.iter().enumerate() {
                 match p {
-                    GeminiPart::Text { text, thought, thought_signature: _ } => {
+                    GeminiPart::Text { text, thought, thought_signature } => {
                         if let Some(true) = thought {
                             rt.push_str(&text);
                         } else {
+                            part_offsets.insert(idx, t.len());
                             t.push_str(&text);
                         }
+                        if thought_signature.is_some() {
+                            signature = thought_signature.clone();
+                        }
                     },
                     GeminiPart::InlineData { inline_data: _ } => {},
-                    GeminiPart::FunctionCall { function_call, thought_signature: _ } => {
+                    GeminiPart::FunctionCall { function_call, thought_signature } => {
                         saw_tool_call = true;
+                        // Gemini requires the signature back on the call in the next turn
+                        let signature = thought_signature.as_ref().or(function_call.thought_signature.as_ref());
                         tool_calls.push(OpenAIToolCall {
                             id: format!("tool_call_{}", idx),
                             r#type: "function".to_string(),
@@ -151,6 +166,7 @@ impl From<GeminiResponse> for OpenAIResponse {
                                 arguments: serde_json::to_string(&function_call.args)
                                     .unwrap_or_else(|_| "{}".to_string()),
                             },
+                            extra_fields: citations::thought_signature_fields(signature),
                         });
                     },
                     GeminiPart::FunctionResponse { function_response: _ } => {},
@@ -162,18 +178,23 @@ impl From<GeminiResponse> for OpenAIResponse {
                 match first.finish_reason.as_ref() {
                     Some(GeminiFinishReason::Stop) => "stop".to_string(),
                     Some(GeminiFinishReason::MaxTokens) => "length".to_string(),
+                    Some(fr) if fr.is_content_filter() => "content_filter".to_string(),
                     _ => "stop".to_string(),
                 }
             };
-            (Some(t), Some(rt), if tool_calls.is_empty() { None } else { Some(tool_calls) }, fr)
+            let annotations = citations::gemini_annotations(first, &t, &part_offsets);
+            let annotations = if annotations.is_empty() { None } else { Some(annotations) };
+            (Some(t), Some(rt), if tool_calls.is_empty() { None } else { Some(tool_calls) }, fr, annotations, signature)
         } else {
-            (None, None, None, "stop".to_string())
+            // No candidates at all when the prompt itself was blocked
+            let blocked = resp.prompt_feedback.as_ref().is_some_and(|f| f.block_reason.is_some());
+            (None, None, None, if blocked { "content_filter" } else { "stop" }.to_string(), None, None)
         };
 
         OpenAIResponse {
-            id: format!("gen-{}", now_secs),
+            id: clock::new_id("gen"),
             object: Some("chat.completion".to_string()),
-            created: now_secs,
+            created: clock::now_secs(),
             model: resp.model_version.unwrap_or_else(|| "gemini".to_string()),
             choices: vec![OpenAIChoice {
                 index: 0,
@@ -185,18 +206,25 @@ impl From<GeminiResponse> for OpenAIResponse {
                         _ => None,
                     },
                     tool_calls,
+                    annotations,
+                    audio: None,
+                    extra_fields: citations::thought_signature_fields(signature.as_ref()),
                 },
                 finish_reason,
+                extra_fields: HashMap::new(),
             }],
             usage: resp.usage_metadata.as_ref().map(|u| OpenAIUsage {
                 prompt_tokens: u.prompt_token_count.unwrap_or(0),
                 completion_tokens: u.candidates_token_count.unwrap_or(0),
                 total_tokens: u.total_token_count.unwrap_or(0),
                 completion_tokens_details: None,
-                prompt_tokens_details: None,
+                prompt_tokens_details: u
+                    .cached_content_token_count
+                    .map(|cached| OpenAIPromptTokensDetails { audio_tokens: None, cached_tokens: Some(cached) }),
             }),
             system_fingerprint: None,
             service_tier: None,
+            extra_fields: HashMap::new(),
         }
     }
 }
@@ -207,6 +235,26 @@ mod tests {
     use serde_json::json;
     use super::*;
 
+    #[test]
+    fn test_openai_response_keeps_unknown_fields() {
+        let json_response = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1677652288,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi", "refusal": null, "annotations": []},
+                "logprobs": null,
+                "finish_reason": "stop"
+            }],
+            "service_tier": "default",
+            "x_vendor_trace": {"region": "eu"}
+        });
+        let resp: OpenAIResponse = serde_json::from_value(json_response.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&resp).unwrap(), json_response);
+    }
+
     #[test]
     fn test_anthropic_to_openai_response() {
         // 测试基本的文本响应
@@ -417,4 +465,35 @@ mod tests {
         assert_eq!(openai_response.choices[0].finish_reason, "length");
     }
 
+    #[test]
+    fn test_gemini_to_openai_response_keeps_grounding_and_signatures() {
+        let gemini_response: GeminiResponse = serde_json::from_value(json!({
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        { "text": "thinking", "thought": true },
+                        { "text": "Sunny today.", "thoughtSignature": "sig-text" },
+                        { "functionCall": { "name": "get_weather", "args": {} }, "thoughtSignature": "sig-call" }
+                    ]
+                },
+                "finishReason": "STOP",
+                "groundingMetadata": {
+                    "groundingChunks": [ { "web": { "uri": "https://weather.example", "title": "weather.example" } } ],
+                    "groundingSupports": [
+                        { "segment": { "partIndex": 1, "startIndex": 0, "endIndex": 6 }, "groundingChunkIndices": [0] }
+                    ]
+                }
+            }]
+        }))
+        .unwrap();
+        let openai_response: OpenAIResponse = gemini_response.into();
+        let message = serde_json::to_value(&openai_response.choices[0].message).unwrap();
+        assert_eq!(message["annotations"][0]["type"], "url_citation");
+        assert_eq!(message["annotations"][0]["url_citation"]["url"], "https://weather.example");
+        assert_eq!(message["annotations"][0]["url_citation"]["end_index"], 6);
+        assert_eq!(message["extra_content"]["google"]["thought_signature"], "sig-text");
+        assert_eq!(message["tool_calls"][0]["extra_content"]["google"]["thought_signature"], "sig-call");
+    }
+
 }
\ No newline at end of file
@@ -1,4 +1,8 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::converters::openai::openai_annotation::OpenAIAnnotation;
+use crate::converters::openai::openai_audio::OpenAIAudio;
 use crate::converters::openai::openai_tool_call::OpenAIToolCall;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,4 +14,10 @@ pub struct OpenAIResponseMessage {
     pub reasoning_content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<OpenAIToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<OpenAIAudio>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Vec<OpenAIAnnotation>>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
\ No newline at end of file
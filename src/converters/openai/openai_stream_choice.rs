@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use crate::converters::openai::openai_stream_delta::OpenAIStreamDelta;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -7,4 +9,6 @@ pub struct OpenAIStreamChoice {
     pub delta: Option<OpenAIStreamDelta>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub finish_reason: Option<String>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
\ No newline at end of file
@@ -7,10 +7,12 @@ use crate::converters::gemini::{
 };
 use crate::converters::openai::{
     OpenAIStreamChoice, OpenAIStreamDelta, OpenAIStreamToolCall, OpenAIStreamToolCallFunction,
-    OpenAIUsage,
+    OpenAIPromptTokensDetails, OpenAIUsage,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
+use crate::utils::clock;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIStreamChunk {
@@ -21,6 +23,8 @@ pub struct OpenAIStreamChunk {
     pub choices: Option<Vec<OpenAIStreamChoice>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<OpenAIUsage>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
 
 impl From<AnthropicStreamChunk> for OpenAIStreamChunk {
@@ -33,6 +37,7 @@ impl From<AnthropicStreamChunk> for OpenAIStreamChunk {
             AnthropicStreamChunk::MessageDelta { .. } => "chatcmpl-default".to_string(),
             AnthropicStreamChunk::MessageStop => "chatcmpl-default".to_string(),
             AnthropicStreamChunk::Ping => "chatcmpl-default".to_string(),
+            AnthropicStreamChunk::Other(_) => "chatcmpl-default".to_string(),
         };
         
         let mut delta = OpenAIStreamDelta {
@@ -40,9 +45,12 @@ impl From<AnthropicStreamChunk> for OpenAIStreamChunk {
             content: None,
             reasoning_content: None,
             tool_calls: None,
+            audio: None,
+            extra_fields: HashMap::new(),
         };
         
         let mut finish_reason = None;
+        let mut choice_extra_fields = HashMap::new();
         let mut usage = None;
         
         // 根据 chunk 类型处理
@@ -76,6 +84,9 @@ impl From<AnthropicStreamChunk> for OpenAIStreamChunk {
                             }),
                         }]);
                     }
+                    AnthropicContentBlock::Other(_) => {
+                        // Server tool blocks have no OpenAI equivalent
+                    }
                 }
             }
             AnthropicStreamChunk::ContentBlockDelta { index: _, delta: chunk_delta } => {
@@ -99,6 +110,7 @@ impl From<AnthropicStreamChunk> for OpenAIStreamChunk {
                             }),
                         }]);
                     }
+                    AnthropicStreamDelta::Other(_) => {}
                 }
             }
             AnthropicStreamChunk::ContentBlockStop { .. } => {
@@ -108,6 +120,8 @@ impl From<AnthropicStreamChunk> for OpenAIStreamChunk {
                     content: None,
                     reasoning_content: None,
                     tool_calls: None,
+                    audio: None,
+                    extra_fields: HashMap::new(),
                 };
             }
             AnthropicStreamChunk::MessageDelta { delta: chunk_delta, usage: chunk_usage } => {
@@ -117,13 +131,8 @@ impl From<AnthropicStreamChunk> for OpenAIStreamChunk {
                         Some(&Value::String(stop_reason))
                     ).as_str().unwrap_or("stop").to_string());
                 }
-                usage = chunk_usage.map(|u| OpenAIUsage {
-                    prompt_tokens: u.input_tokens,
-                    completion_tokens: u.output_tokens,
-                    total_tokens: u.input_tokens + u.output_tokens,
-                    completion_tokens_details: None,
-                    prompt_tokens_details: None,
-                });
+                choice_extra_fields = helpers::finish_details_fields(chunk_delta.stop_sequence.as_ref());
+                usage = chunk_usage.map(OpenAIUsage::from);
             }
             AnthropicStreamChunk::MessageStop => {
                 // 消息结束
@@ -133,15 +142,19 @@ impl From<AnthropicStreamChunk> for OpenAIStreamChunk {
                     content: None,
                     reasoning_content: None,
                     tool_calls: None,
+                    audio: None,
+                    extra_fields: HashMap::new(),
                 };
             }
-            AnthropicStreamChunk::Ping => {
+            AnthropicStreamChunk::Ping | AnthropicStreamChunk::Other(_) => {
                 // 心跳包，返回空的增量
                 delta = OpenAIStreamDelta {
                     role: None,
                     content: None,
                     reasoning_content: None,
                     tool_calls: None,
+                    audio: None,
+                    extra_fields: HashMap::new(),
                 };
             }
         }
@@ -150,17 +163,16 @@ impl From<AnthropicStreamChunk> for OpenAIStreamChunk {
         OpenAIStreamChunk {
             id,
             object: Some("chat.completion.chunk".to_string()),
-            created: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
+            created: clock::now_secs(),
             model: "claude-3-opus".to_string(), // 默认模型，实际应该从请求中获取
             choices: Some(vec![OpenAIStreamChoice {
                 index: 0,
                 delta: Some(delta),
                 finish_reason,
+                extra_fields: choice_extra_fields,
             }]),
             usage,
+            extra_fields: HashMap::new(),
         }
     }
 }
@@ -442,19 +454,19 @@ impl From<GeminiStreamChunk> for OpenAIStreamChunk {
                 u.prompt_token_count.unwrap_or(0) + u.candidates_token_count.unwrap_or(0),
             ),
             completion_tokens_details: None,
-            prompt_tokens_details: None,
+            prompt_tokens_details: u
+                .cached_content_token_count
+                .map(|cached| OpenAIPromptTokensDetails { audio_tokens: None, cached_tokens: Some(cached) }),
         });
 
         OpenAIStreamChunk {
             id,
             object: Some("chat.completion.chunk".to_string()),
-            created: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
+            created: clock::now_secs(),
             model,
             choices: Some(choices),
             usage,
+            extra_fields: HashMap::new(),
         }
     }
 }
@@ -514,6 +526,8 @@ fn map_gemini_candidate_to_openai_choice(
             Some(reasoning_acc)
         },
         tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+        audio: None,
+        extra_fields: HashMap::new(),
     };
 
     let finish_reason = candidate
@@ -524,6 +538,7 @@ fn map_gemini_candidate_to_openai_choice(
         index,
         delta: Some(delta),
         finish_reason,
+        extra_fields: HashMap::new(),
     }
 }
 
@@ -535,9 +550,7 @@ fn map_gemini_finish_reason_to_openai(fr: GeminiFinishReason) -> Option<String>
         // Tool-related
         GFR::UnexpectedToolCall | GFR::TooManyToolCalls => "tool_calls",
         // Safety/content filter related
-        GFR::Safety | GFR::Blocklist | GFR::ProhibitedContent | GFR::ImageSafety | GFR::Spii => {
-            "content_filter"
-        }
+        fr if fr.is_content_filter() => "content_filter",
         // Others map to unspecified; do not set
         _ => return None,
     };
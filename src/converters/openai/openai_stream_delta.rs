@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::converters::openai::openai_audio::OpenAIAudio;
 use crate::converters::openai::openai_stream_tool_call::OpenAIStreamToolCall;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,4 +14,8 @@ pub struct OpenAIStreamDelta {
     pub reasoning_content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<OpenAIStreamToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<OpenAIAudio>,
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use crate::converters::openai::openai_tool_call_function::OpenAIToolCallFunction;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -6,4 +8,7 @@ pub struct OpenAIToolCall {
     pub id: String,
     pub r#type: String,
     pub function: OpenAIToolCallFunction,
+    // Provider-specific fields (e.g. extra_content.google.thought_signature)
+    #[serde(flatten)]
+    pub extra_fields: HashMap<String, Value>,
 }
\ No newline at end of file
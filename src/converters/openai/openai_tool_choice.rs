@@ -0,0 +1,165 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+/// `tool_choice` of a Chat Completions or Responses request. The value is kept
+/// as sent, so a request going to an OpenAI upstream passes it on unchanged;
+/// `mode` is what other formats are converted from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenAIToolChoice {
+    raw: Value,
+    mode: ToolChoiceMode,
+}
+
+/// What a `tool_choice` asks of the model.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolChoiceMode {
+    Auto,
+    None,
+    Required,
+    // A function or custom tool, by name
+    Tool(String),
+    // A Responses hosted tool, by type, e.g. "web_search_preview"
+    Hosted(String),
+}
+
+impl OpenAIToolChoice {
+    pub fn mode(&self) -> &ToolChoiceMode {
+        &self.mode
+    }
+
+    /// Gemini `toolConfig` with the same effect. Hosted tools are not function
+    /// calls there, so they leave the choice to the model.
+    pub fn to_gemini(&self) -> Value {
+        let config = match &self.mode {
+            ToolChoiceMode::Auto | ToolChoiceMode::Hosted(_) => json!({"mode": "AUTO"}),
+            ToolChoiceMode::None => json!({"mode": "NONE"}),
+            ToolChoiceMode::Required => json!({"mode": "ANY"}),
+            ToolChoiceMode::Tool(name) => json!({"mode": "ANY", "allowedFunctionNames": [name]}),
+        };
+        json!({"functionCallingConfig": config})
+    }
+}
+
+// Converted choices are written in the Chat Completions shape
+impl From<ToolChoiceMode> for OpenAIToolChoice {
+    fn from(mode: ToolChoiceMode) -> Self {
+        let raw = match &mode {
+            ToolChoiceMode::Auto => json!("auto"),
+            ToolChoiceMode::None => json!("none"),
+            ToolChoiceMode::Required => json!("required"),
+            ToolChoiceMode::Tool(name) => json!({"type": "function", "function": {"name": name}}),
+            ToolChoiceMode::Hosted(hosted) => json!({"type": hosted}),
+        };
+        OpenAIToolChoice { raw, mode }
+    }
+}
+
+impl ToolChoiceMode {
+    // Accept both shapes:
+    // - Chat Completions style: "auto" | "none" | "required" | { "type": "function", "function": { name } },
+    //                           { "type": "custom", "custom": { name } } or { "type": "allowed_tools", "allowed_tools": { mode, tools } }
+    // - Responses API style:    { "type": "function" | "custom", name }, { "type": "mcp", server_label, name? },
+    //                           { "type": "allowed_tools", mode, tools } or a hosted tool such as { "type": "web_search_preview" }
+    fn parse(v: &Value) -> Result<Self, String> {
+        if let Some(mode) = v.as_str() {
+            return match mode {
+                "auto" => Ok(ToolChoiceMode::Auto),
+                "none" => Ok(ToolChoiceMode::None),
+                "required" => Ok(ToolChoiceMode::Required),
+                other => Err(format!("unknown tool_choice '{}'", other)),
+            };
+        }
+        let name_of = |v: &Value| {
+            v.get("name")
+                .or_else(|| v.get("function").and_then(|f| f.get("name")))
+                .or_else(|| v.get("custom").and_then(|c| c.get("name")))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        let Some(r#type) = v.get("type").and_then(Value::as_str) else {
+            return Err("invalid tool_choice format".to_string());
+        };
+        Ok(match r#type {
+            "function" | "custom" => match name_of(v) {
+                Some(name) => ToolChoiceMode::Tool(name),
+                None => return Err("tool_choice names no tool".to_string()),
+            },
+            // Without a tool name any tool of the server will do
+            "mcp" => name_of(v).map_or(ToolChoiceMode::Required, ToolChoiceMode::Tool),
+            "allowed_tools" => {
+                let allowed = v.get("allowed_tools").unwrap_or(v);
+                let tools = allowed.get("tools").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+                match (allowed.get("mode").and_then(Value::as_str), tools) {
+                    (Some("required"), [tool]) => name_of(tool).map_or(ToolChoiceMode::Required, ToolChoiceMode::Tool),
+                    (Some("required"), _) => ToolChoiceMode::Required,
+                    _ => ToolChoiceMode::Auto,
+                }
+            }
+            hosted => ToolChoiceMode::Hosted(hosted.to_string()),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for OpenAIToolChoice {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = Value::deserialize(deserializer)?;
+        let mode = ToolChoiceMode::parse(&raw).map_err(de::Error::custom)?;
+        Ok(OpenAIToolChoice { raw, mode })
+    }
+}
+
+impl Serialize for OpenAIToolChoice {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.raw.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_choice_shapes() {
+        let parse = |v: Value| serde_json::from_value::<OpenAIToolChoice>(v).unwrap().mode;
+        assert_eq!(parse(json!("required")), ToolChoiceMode::Required);
+        assert_eq!(parse(json!({"type": "function", "function": {"name": "f"}})), ToolChoiceMode::Tool("f".into()));
+        assert_eq!(parse(json!({"type": "function", "name": "f"})), ToolChoiceMode::Tool("f".into()));
+        assert_eq!(parse(json!({"type": "custom", "custom": {"name": "f"}})), ToolChoiceMode::Tool("f".into()));
+        assert_eq!(parse(json!({"type": "mcp", "server_label": "files"})), ToolChoiceMode::Required);
+        assert_eq!(
+            parse(json!({"type": "allowed_tools", "mode": "required", "tools": [{"type": "function", "name": "f"}]})),
+            ToolChoiceMode::Tool("f".into())
+        );
+        assert_eq!(
+            parse(json!({"type": "allowed_tools", "allowed_tools": {"mode": "required", "tools": [{"type": "function", "function": {"name": "f"}}]}})),
+            ToolChoiceMode::Tool("f".into())
+        );
+        assert_eq!(
+            parse(json!({"type": "allowed_tools", "allowed_tools": {"mode": "required", "tools": [{"type": "function", "function": {"name": "f"}}, {"type": "function", "function": {"name": "g"}}]}})),
+            ToolChoiceMode::Required
+        );
+        assert_eq!(parse(json!({"type": "allowed_tools", "mode": "auto", "tools": []})), ToolChoiceMode::Auto);
+        assert_eq!(parse(json!({"type": "web_search_preview"})), ToolChoiceMode::Hosted("web_search_preview".into()));
+        assert!(serde_json::from_value::<OpenAIToolChoice>(json!("sometimes")).is_err());
+
+        // Passed on as sent
+        for sent in [
+            json!({"type": "web_search_preview"}),
+            json!({"type": "custom", "custom": {"name": "f"}}),
+            json!({"type": "allowed_tools", "allowed_tools": {"mode": "auto", "tools": [{"type": "function", "function": {"name": "f"}}]}}),
+        ] {
+            let choice: OpenAIToolChoice = serde_json::from_value(sent.clone()).unwrap();
+            assert_eq!(serde_json::to_value(choice).unwrap(), sent);
+        }
+
+        let forced = OpenAIToolChoice::from(ToolChoiceMode::Tool("f".into()));
+        assert_eq!(serde_json::to_value(&forced).unwrap(), json!({"type": "function", "function": {"name": "f"}}));
+        assert_eq!(forced.to_gemini(), json!({"functionCallingConfig": {"mode": "ANY", "allowedFunctionNames": ["f"]}}));
+    }
+}
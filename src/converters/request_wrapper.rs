@@ -1,6 +1,10 @@
 use super::openai::OpenAIRequest;
 use super::anthropic::AnthropicRequest;
-use super::gemini::GeminiRequest;
+use super::gemini::{GeminiPart, GeminiRequest};
+use super::gemini::gemini_request::parse_data_url;
+use super::openai::OpenAIContent;
+use super::anthropic::{AnthropicContent, AnthropicContentObject};
+use crate::config::{ApiType, GenerationDefaults};
 
 use serde::{Deserialize, Serialize};
 
@@ -25,10 +29,7 @@ impl RequestWrapper {
         match self {
             RequestWrapper::Anthropic(req) => req.clone(),
             RequestWrapper::OpenAI(req) => req.clone().into(),
-            RequestWrapper::Gemini(req) => {
-                let oai: OpenAIRequest = req.clone().into();
-                oai.into()
-            }
+            RequestWrapper::Gemini(req) => req.clone().into(),
         }
     }
 
@@ -36,10 +37,20 @@ impl RequestWrapper {
         match self {
             RequestWrapper::Gemini(req) => req.clone(),
             RequestWrapper::OpenAI(req) => req.clone().into(),
-            RequestWrapper::Anthropic(req) => {
-                let oai: OpenAIRequest = req.clone().into();
-                oai.into()
-            }
+            RequestWrapper::Anthropic(req) => req.clone().into(),
+        }
+    }
+
+    // The request in the OpenAI format, for pairs converted through it
+    pub fn via_openai(&self) -> RequestWrapper {
+        RequestWrapper::OpenAI(self.get_openai())
+    }
+
+    pub fn api_type(&self) -> ApiType {
+        match self {
+            RequestWrapper::OpenAI(_) => ApiType::OpenAI,
+            RequestWrapper::Anthropic(_) => ApiType::Anthropic,
+            RequestWrapper::Gemini(_) => ApiType::Gemini,
         }
     }
 
@@ -51,6 +62,224 @@ impl RequestWrapper {
         }
     }
 
+    pub fn set_model(&mut self, model: String) {
+        match self {
+            RequestWrapper::OpenAI(req) => req.model = model,
+            RequestWrapper::Anthropic(req) => req.model = model,
+            RequestWrapper::Gemini(req) => req.model = model,
+        }
+    }
+
+    pub fn set_stream(&mut self, stream: bool) {
+        match self {
+            RequestWrapper::OpenAI(req) => req.stream = Some(stream),
+            RequestWrapper::Anthropic(req) => req.stream = Some(stream),
+            RequestWrapper::Gemini(req) => req.stream = Some(stream),
+        }
+    }
+
+    // Output tokens the client asked for; OpenAI's max_completion_tokens wins over max_tokens
+    pub fn max_tokens(&self) -> Option<u32> {
+        match self {
+            RequestWrapper::OpenAI(req) => req
+                .extra_fields
+                .get("max_completion_tokens")
+                .and_then(serde_json::Value::as_u64)
+                .map(|n| n.min(u32::MAX as u64) as u32)
+                .or(req.max_tokens),
+            RequestWrapper::Anthropic(req) => Some(req.max_tokens),
+            RequestWrapper::Gemini(req) => req.generation_config.as_ref().and_then(|gc| gc.max_output_tokens),
+        }
+    }
+
+    // Replace the max tokens the client asked for, in the field it used
+    pub fn set_max_tokens(&mut self, max_tokens: u32) {
+        match self {
+            RequestWrapper::OpenAI(req) => match req.extra_fields.get_mut("max_completion_tokens") {
+                Some(value) => *value = max_tokens.into(),
+                None => req.max_tokens = Some(max_tokens),
+            },
+            RequestWrapper::Anthropic(req) => req.max_tokens = max_tokens,
+            RequestWrapper::Gemini(req) => {
+                req.generation_config.get_or_insert_with(Default::default).max_output_tokens = Some(max_tokens);
+            }
+        }
+    }
+
+    // Fill top-level body fields the client did not set
+    pub fn merge_defaults(&mut self, defaults: &serde_json::Map<String, serde_json::Value>) -> serde_json::Result<()> {
+        if defaults.is_empty() {
+            return Ok(());
+        }
+        self.edit_json(|obj| {
+            for (k, v) in defaults {
+                obj.entry(k.clone()).or_insert_with(|| v.clone());
+            }
+        })
+    }
+
+    /// Fill in the generation parameters of `defaults` the request leaves out,
+    /// in the fields of the request's own format.
+    pub fn merge_generation_defaults(&mut self, defaults: &GenerationDefaults) {
+        let top_p = defaults.top_p.map(serde_json::Value::from);
+        match self {
+            RequestWrapper::OpenAI(req) => {
+                req.temperature = req.temperature.or(defaults.temperature);
+                if let Some(top_p) = top_p {
+                    req.extra_fields.entry("top_p".to_string()).or_insert(top_p);
+                }
+                // Newer clients send max_completion_tokens instead
+                if !req.extra_fields.contains_key("max_completion_tokens") {
+                    req.max_tokens = req.max_tokens.or(defaults.max_tokens);
+                }
+            }
+            RequestWrapper::Anthropic(req) => {
+                req.temperature = req.temperature.or(defaults.temperature);
+                if let Some(top_p) = top_p {
+                    req.extra_fields.entry("top_p".to_string()).or_insert(top_p);
+                }
+            }
+            RequestWrapper::Gemini(req) => {
+                let config = req.generation_config.get_or_insert_with(Default::default);
+                config.temperature = config.temperature.or(defaults.temperature);
+                config.top_p = config.top_p.or(defaults.top_p);
+                config.max_output_tokens = config.max_output_tokens.or(defaults.max_tokens);
+            }
+        }
+    }
+
+    // Rewrite the request as a client-format JSON object and parse it back
+    pub fn edit_json(&mut self, f: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>)) -> serde_json::Result<()> {
+        // Fields skipped by serialization (Gemini model/stream, betas) are carried over
+        let model = self.get_model().clone();
+        let stream = *self.is_stream();
+        let betas = match self {
+            RequestWrapper::OpenAI(req) => req.betas.clone(),
+            _ => self.anthropic_betas().to_vec(),
+        };
+        let mut value = serde_json::to_value(&*self)?;
+        if let Some(obj) = value.as_object_mut() {
+            f(obj);
+        }
+        *self = match self {
+            RequestWrapper::OpenAI(_) => RequestWrapper::OpenAI(serde_json::from_value(value)?),
+            RequestWrapper::Anthropic(_) => RequestWrapper::Anthropic(serde_json::from_value(value)?),
+            RequestWrapper::Gemini(_) => RequestWrapper::Gemini(serde_json::from_value(value)?),
+        };
+        self.set_model(model);
+        match self {
+            RequestWrapper::Gemini(req) => req.stream = stream,
+            RequestWrapper::Anthropic(req) => req.betas = betas,
+            RequestWrapper::OpenAI(req) => req.betas = betas,
+        }
+        Ok(())
+    }
+
+    // Requested Anthropic betas; only Anthropic clients can ask for them
+    pub fn anthropic_betas(&self) -> &[String] {
+        match self {
+            RequestWrapper::Anthropic(req) => &req.betas,
+            _ => &[],
+        }
+    }
+
+    // Requested OpenAI-Beta features; only OpenAI clients can ask for them
+    pub fn openai_betas(&self) -> &[String] {
+        match self {
+            RequestWrapper::OpenAI(req) => &req.betas,
+            _ => &[],
+        }
+    }
+
+    // Drop a router-only body extension so it is not forwarded upstream
+    pub fn remove_extra_field(&mut self, key: &str) -> Option<serde_json::Value> {
+        match self {
+            RequestWrapper::OpenAI(req) => req.extra_fields.remove(key),
+            RequestWrapper::Anthropic(req) => req.extra_fields.remove(key),
+            RequestWrapper::Gemini(req) => req.extra_fields.remove(key),
+        }
+    }
+
+    // Visit every base64 image in the request as (mime type, data); `f` may rewrite both
+    pub fn for_each_inline_image<E>(&mut self, f: &mut impl FnMut(&mut String, &mut String) -> Result<(), E>) -> Result<(), E> {
+        match self {
+            RequestWrapper::OpenAI(req) => {
+                for message in &mut req.messages {
+                    let OpenAIContent::Array(items) = &mut message.content else { continue };
+                    for image in items.iter_mut().filter_map(|item| item.image_url.as_mut()) {
+                        let Some((mut mime_type, mut data)) = parse_data_url(&image.url) else { continue };
+                        f(&mut mime_type, &mut data)?;
+                        image.url = format!("data:{};base64,{}", mime_type, data);
+                    }
+                }
+            }
+            RequestWrapper::Anthropic(req) => {
+                for message in req.messages.iter_mut().flatten() {
+                    let AnthropicContent::Array(blocks) = &mut message.content else { continue };
+                    for block in blocks {
+                        if let AnthropicContentObject::Image { source } = block
+                            && source.r#type == "base64"
+                            && let Some(data) = source.data.as_mut()
+                        {
+                            let mut media_type = source.media_type.clone().unwrap_or_default();
+                            f(&mut media_type, data)?;
+                            if !media_type.is_empty() {
+                                source.media_type = Some(media_type);
+                            }
+                        }
+                    }
+                }
+            }
+            RequestWrapper::Gemini(req) => {
+                for part in req.contents.iter_mut().flat_map(|c| c.parts.iter_mut()) {
+                    if let GeminiPart::InlineData { inline_data } = part {
+                        f(&mut inline_data.mime_type, &mut inline_data.data)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Visit every image referenced by an http(s) URL; when `f` returns a
+    // (mime type, base64 data) pair the image is inlined in place of the URL
+    pub fn inline_remote_images(&mut self, f: &mut impl FnMut(&str) -> Option<(String, String)>) {
+        let is_remote = |url: &str| url.starts_with("http://") || url.starts_with("https://");
+        match self {
+            RequestWrapper::OpenAI(req) => {
+                for message in &mut req.messages {
+                    let OpenAIContent::Array(items) = &mut message.content else { continue };
+                    for image in items.iter_mut().filter_map(|item| item.image_url.as_mut()) {
+                        if is_remote(&image.url)
+                            && let Some((mime_type, data)) = f(&image.url)
+                        {
+                            image.url = format!("data:{};base64,{}", mime_type, data);
+                        }
+                    }
+                }
+            }
+            RequestWrapper::Anthropic(req) => {
+                for message in req.messages.iter_mut().flatten() {
+                    let AnthropicContent::Array(blocks) = &mut message.content else { continue };
+                    for block in blocks {
+                        if let AnthropicContentObject::Image { source } = block
+                            && source.r#type == "url"
+                            && let Some(url) = source.url.as_deref().filter(|url| is_remote(url))
+                            && let Some((mime_type, data)) = f(url)
+                        {
+                            source.r#type = "base64".to_string();
+                            source.media_type = Some(mime_type);
+                            source.data = Some(data);
+                            source.url = None;
+                        }
+                    }
+                }
+            }
+            // Gemini clients send inline data or file references already
+            RequestWrapper::Gemini(_) => {}
+        }
+    }
+
     pub fn is_stream(&self) -> &Option<bool> {
         match self {
             RequestWrapper::OpenAI(req) => &req.stream,
@@ -59,3 +288,193 @@ impl RequestWrapper {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_defaults_keeps_client_values() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "messages": [{"role": "user", "content": "hi"}],
+            "temperature": 0.9
+        }))
+        .unwrap();
+        let mut wrapper = RequestWrapper::OpenAI(req);
+        assert_eq!(wrapper.get_model(), "");
+        wrapper.set_model("gpt_models".to_string());
+
+        let defaults = json!({"temperature": 0.2, "max_tokens": 256});
+        wrapper.merge_defaults(defaults.as_object().unwrap()).unwrap();
+        let RequestWrapper::OpenAI(req) = wrapper else { panic!("variant changed") };
+        assert_eq!(req.model, "gpt_models");
+        assert_eq!(req.temperature, Some(0.9));
+        assert_eq!(req.max_tokens, Some(256));
+    }
+
+    #[test]
+    fn test_generation_defaults_land_in_each_format() {
+        let defaults = GenerationDefaults { temperature: Some(0.2), top_p: Some(0.9), max_tokens: Some(512) };
+        let mut openai = RequestWrapper::OpenAI(
+            serde_json::from_value(json!({"messages": [], "temperature": 1.0, "max_completion_tokens": 64})).unwrap(),
+        );
+        openai.merge_generation_defaults(&defaults);
+        let body = serde_json::to_value(&openai).unwrap();
+        assert_eq!((body["temperature"].as_f64(), body["top_p"].as_f64()), (Some(1.0), Some(0.9)));
+        assert!(body.get("max_tokens").is_none());
+
+        let mut gemini = RequestWrapper::Gemini(
+            serde_json::from_value(json!({"contents": [], "generationConfig": {"maxOutputTokens": 100}})).unwrap(),
+        );
+        gemini.merge_generation_defaults(&defaults);
+        let body = serde_json::to_value(&gemini).unwrap();
+        assert_eq!(body["generationConfig"], json!({"temperature": 0.2, "topP": 0.9, "maxOutputTokens": 100}));
+    }
+
+    #[test]
+    fn test_stop_sequences_convert_between_formats() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "m",
+            "messages": [{"role": "user", "content": "hi"}],
+            "stop": "END"
+        }))
+        .unwrap();
+        let wrapper = RequestWrapper::OpenAI(req);
+        let anthropic = serde_json::to_value(wrapper.get_anthropic()).unwrap();
+        assert_eq!(anthropic["stop_sequences"], json!(["END"]));
+        assert!(anthropic.get("stop").is_none());
+        let gemini = serde_json::to_value(wrapper.get_gemini()).unwrap();
+        assert_eq!(gemini["generationConfig"]["stopSequences"], json!(["END"]));
+
+        let req: AnthropicRequest = serde_json::from_value(json!({
+            "model": "m",
+            "max_tokens": 16,
+            "messages": [{"role": "user", "content": "hi"}],
+            "stop_sequences": ["a", "b"]
+        }))
+        .unwrap();
+        let openai = serde_json::to_value(RequestWrapper::Anthropic(req).get_openai()).unwrap();
+        assert_eq!(openai["stop"], json!(["a", "b"]));
+        assert!(openai.get("stop_sequences").is_none());
+    }
+
+    #[test]
+    fn test_anthropic_and_gemini_convert_directly() {
+        let req: AnthropicRequest = serde_json::from_value(json!({
+            "model": "m",
+            "max_tokens": 64,
+            "temperature": 0.3,
+            "system": [{"type": "text", "text": "Be brief."}],
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is this?"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0K"}}
+                ]},
+                {"role": "assistant", "content": [{"type": "tool_use", "id": "toolu_9", "name": "lookup", "input": {"q": "png"}}]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_9", "content": "an image"},
+                    {"type": "text", "text": "Describe it"}
+                ]}
+            ],
+            "tools": [
+                {"name": "lookup", "description": "Look up", "input_schema": {"type": "object", "additionalProperties": false}},
+                {"type": "web_search_20250305", "name": "web_search"}
+            ]
+        }))
+        .unwrap();
+        let gemini = RequestWrapper::Anthropic(req).get_gemini();
+        let body = serde_json::to_value(&gemini).unwrap();
+        assert_eq!(body["system_instruction"]["parts"][0]["text"], "Be brief.");
+        assert_eq!(body["contents"][0]["parts"][1]["inlineData"], json!({"mimeType": "image/png", "data": "iVBORw0K"}));
+        assert_eq!(body["contents"][1]["role"], "model");
+        assert_eq!(body["contents"][1]["parts"][0]["functionCall"]["args"], json!({"q": "png"}));
+        assert_eq!(body["contents"][2]["parts"][0]["functionResponse"], json!({"name": "lookup", "response": {"content": "an image"}}));
+        assert_eq!(body["contents"][2]["parts"][1]["text"], "Describe it");
+        assert_eq!(body["tools"][0]["functionDeclarations"].as_array().unwrap().len(), 1);
+        assert!(body["tools"][0]["functionDeclarations"][0]["parameters"].get("additionalProperties").is_none());
+        assert_eq!(body["generationConfig"]["temperature"], 0.3);
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 64);
+
+        // Back again: calls get ids and each response refers to its call
+        let anthropic = serde_json::to_value(RequestWrapper::Gemini(gemini).get_anthropic()).unwrap();
+        assert_eq!(anthropic["system"], "Be brief.");
+        assert_eq!(anthropic["messages"][0]["content"][1]["source"]["media_type"], "image/png");
+        let tool_use = &anthropic["messages"][1]["content"][0];
+        assert_eq!(tool_use["type"], "tool_use");
+        assert_eq!(anthropic["messages"][2]["content"][0]["tool_use_id"], tool_use["id"]);
+        assert_eq!(anthropic["messages"][2]["content"][0]["content"], r#"{"content":"an image"}"#);
+        // Text stays where it was among the other blocks
+        assert_eq!(anthropic["messages"][2]["content"][1]["text"], "Describe it");
+        assert_eq!(anthropic["tools"][0]["name"], "lookup");
+        assert_eq!(anthropic["max_tokens"], 64);
+    }
+
+    // Golden output of the direct pair, so changes to what it carries over show up here
+    #[test]
+    fn test_anthropic_gemini_direct_golden() {
+        let req: AnthropicRequest = serde_json::from_value(json!({
+            "model": "m",
+            "max_tokens": 64,
+            "system": "Be brief.",
+            "messages": [
+                {"role": "user", "content": "Find a png"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "Search first", "signature": "sig-1"},
+                    {"type": "tool_use", "id": "toolu_9", "name": "lookup", "input": {"q": "png"}}
+                ]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "toolu_9", "content": "found"}]}
+            ],
+            "tools": [{"name": "lookup", "description": "Look up", "input_schema": {"type": "object"}}]
+        }))
+        .unwrap();
+        let gemini = RequestWrapper::Anthropic(req).get_gemini();
+        assert_eq!(
+            serde_json::to_value(&gemini).unwrap(),
+            json!({
+                "contents": [
+                    {"role": "user", "parts": [{"text": "Find a png"}]},
+                    {"role": "model", "parts": [{"functionCall": {"name": "lookup", "args": {"q": "png"}, "thoughtSignature": null}, "thoughtSignature": "sig-1"}]},
+                    {"role": "user", "parts": [{"functionResponse": {"name": "lookup", "response": {"content": "found"}}}]}
+                ],
+                "system_instruction": {"role": "user", "parts": [{"text": "Be brief."}]},
+                "tools": [{"functionDeclarations": [{"name": "lookup", "description": "Look up", "parameters": {"type": "object"}}]}],
+                "generationConfig": {"maxOutputTokens": 64}
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(RequestWrapper::Gemini(gemini).get_anthropic()).unwrap(),
+            json!({
+                "model": "m",
+                "max_tokens": 64,
+                "system": "Be brief.",
+                "messages": [
+                    {"role": "user", "content": [{"type": "text", "text": "Find a png"}]},
+                    {"role": "assistant", "content": [{"type": "tool_use", "id": "toolu_1", "name": "lookup", "input": {"q": "png"}}]},
+                    {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "toolu_1", "content": "{\"content\":\"found\"}"}]}
+                ],
+                "tools": [{"name": "lookup", "description": "Look up", "input_schema": {"type": "object"}}]
+            })
+        );
+    }
+
+    #[test]
+    fn test_end_user_survives_conversion() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "m",
+            "messages": [{"role": "user", "content": "hi"}],
+            "user": "u-1",
+            "metadata": {"team": "search"}
+        }))
+        .unwrap();
+        let anthropic = serde_json::to_value(RequestWrapper::OpenAI(req.clone()).get_anthropic()).unwrap();
+        assert_eq!(anthropic["metadata"], json!({"user_id": "u-1"}));
+        assert!(anthropic.get("user").is_none());
+
+        let gemini = RequestWrapper::OpenAI(req).get_gemini();
+        assert_eq!(serde_json::to_value(&gemini).unwrap()["labels"], json!({"user": "u-1", "team": "search"}));
+        let anthropic = serde_json::to_value(RequestWrapper::Gemini(gemini).get_anthropic()).unwrap();
+        assert_eq!(anthropic["metadata"], json!({"user_id": "u-1"}));
+        assert!(anthropic.get("labels").is_none());
+    }
+}
@@ -2,238 +2,434 @@ use super::anthropic::{
     AnthropicContentBlock, AnthropicStreamChunk, AnthropicStreamDelta, AnthropicStreamMessage,
 };
 use super::gemini::GeminiStreamChunk;
-use super::openai::OpenAIStreamChunk;
-use crate::config::ApiType;
+use super::openai::{OpenAIStreamChunk, OpenAIStreamToolCall};
+use crate::config::{ApiType, TerminatorMode, ToolArgumentsMode};
 use crate::converters::anthropic::AnthropicResponse;
 use crate::converters::gemini::GeminiResponse;
-use crate::converters::openai::OpenAIResponse;
+use crate::converters::openai::{OpenAIAudio, OpenAIResponse};
+use crate::converters::helpers;
 use crate::converters::response_wrapper::ResponseWrapper;
-use crate::models::{ErrorDetail, ErrorResponse};
+use crate::error::RouterError;
+use crate::metrics::{self, ConversionKind};
+use crate::offload;
+use crate::response_store::{StoredStream, frames_to_sse};
+use crate::{stream_fence, stream_smoothing};
+use crate::utils::clock;
+use crate::utils::json_repair::repair_json;
 use axum::{
     Json,
-    http::StatusCode,
+    http::{HeaderValue, header},
     response::{IntoResponse, sse::Event, sse::Sse},
 };
 use bytes::Bytes;
 use futures::{Stream, StreamExt, stream};
 use serde_json::json;
 use std::convert::Infallible;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use tracing::{debug, warn};
 
+// Cap on a non-streaming upstream answer when the model sets no max_response_bytes
+pub const DEFAULT_MAX_RESPONSE_BYTES: u64 = 64 * 1024 * 1024;
+
+fn too_large(bytes: String, max_bytes: u64) -> RouterError {
+    warn!("Upstream response of {} bytes exceeds the {} byte limit", bytes, max_bytes);
+    RouterError::conversion(
+        "response_too_large",
+        format!("Upstream response is {} bytes, over the {} byte limit for this model", bytes, max_bytes),
+    )
+}
+
+/// Read at most `max_bytes` of an upstream error body. The rest is dropped
+/// rather than failing, so the client still gets the upstream's status.
+pub async fn read_error_body(mut response: reqwest::Response, max_bytes: u64) -> Bytes {
+    let max_bytes = usize::try_from(max_bytes).unwrap_or(usize::MAX);
+    let mut body = Vec::new();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        let room = max_bytes - body.len();
+        body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        if chunk.len() > room {
+            warn!("Upstream error body cut off at the {} byte limit", max_bytes);
+            break;
+        }
+    }
+    Bytes::from(body)
+}
+
+/// Read the upstream body as text, giving up with a 502 once it is larger than
+/// `max_bytes` rather than holding an unbounded payload in memory.
+async fn read_body_bounded(mut response: reqwest::Response, max_bytes: u64) -> Result<String, RouterError> {
+    if let Some(length) = response.content_length().filter(|length| *length > max_bytes) {
+        return Err(too_large(length.to_string(), max_bytes));
+    }
+    let mut body = Vec::new();
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                if (body.len() + chunk.len()) as u64 > max_bytes {
+                    return Err(too_large(format!("more than {}", body.len() + chunk.len()), max_bytes));
+                }
+                body.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Failed to parse response: {}", e);
+                return Err(RouterError::conversion("parse_error", format!("Failed to parse response: {}", e)));
+            }
+        }
+    }
+    String::from_utf8(body).map_err(|e| {
+        warn!("Failed to parse response: {}", e);
+        RouterError::conversion("parse_error", format!("Failed to parse response: {}", e))
+    })
+}
+
+/// Converts a complete upstream answer to the client's format. `direct` picks the
+/// pair's own converter over the OpenAI pivot where one exists (Anthropic <-> Gemini).
 pub async fn handle_non_streaming_response(
     response: reqwest::Response,
     model: String,
     source_api_type: ApiType,
     target_api_type: ApiType,
+    direct: bool,
+    max_bytes: u64,
 ) -> axum::response::Response {
-    let response_text: String = match response.text().await {
+    let status = response.status();
+    let response_text: String = match read_body_bounded(response, max_bytes).await {
         Ok(resp) => resp,
-        Err(e) => {
-            warn!("Failed to parse response: {}", e);
-            let error_response = ErrorResponse {
-                error: ErrorDetail {
-                    message: format!("Failed to parse response: {}", e),
-                    r#type: "api_error".to_string(),
-                    code: Some("parse_error".to_string()),
-                },
-            };
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)).into_response();
-        }
+        Err(e) => return e.into_response(),
     };
     debug!("raw response: {:?}", &response_text);
+    if response_text.trim().is_empty() {
+        return empty_completion(model, status, target_api_type);
+    }
 
-    let response_wrapper = match (source_api_type, target_api_type) {
-        (ApiType::OpenAI, ApiType::OpenAI) => {
-            match serde_json::from_str::<OpenAIResponse>(&response_text) {
-                Ok(mut resp) => {
-                    resp.model = model.clone();
-                    ResponseWrapper::OpenAI(resp)
-                },
-                Err(e) => {
-                    warn!("Failed to deserialize OpenAI response: {}", e);
-                    let error_response = ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Failed to deserialize response: {}", e),
-                            r#type: "api_error".to_string(),
-                            code: Some("deserialize_error".to_string()),
+    let (from, to) = (source_api_type.clone(), target_api_type.clone());
+    // Large answers are parsed and serialized off the async worker
+    let converted = offload::run(response_text.len(), || {
+        let response_wrapper = metrics::time_conversion(ConversionKind::Response, &from, &to, || {
+            Ok(match (source_api_type, target_api_type) {
+                (ApiType::OpenAI, ApiType::OpenAI) => {
+                    match serde_json::from_str::<OpenAIResponse>(&response_text) {
+                        Ok(mut resp) => {
+                            resp.model = model.clone();
+                            ResponseWrapper::OpenAI(resp)
                         },
-                    };
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-                        .into_response();
+                        Err(e) => {
+                            warn!("Failed to deserialize OpenAI response: {}", e);
+                            return Err(RouterError::conversion("deserialize_error", format!("Failed to deserialize response: {}", e)));
+                        }
+                    }
                 }
-            }
-        }
-        (ApiType::Gemini, ApiType::Gemini) => {
-            match serde_json::from_str::<GeminiResponse>(&response_text) {
-                Ok(mut resp) => {
-                    resp.model_version = Some(model.clone());
-                    ResponseWrapper::Gemini(resp)
-                },
-                Err(e) => {
-                    warn!("Failed to deserialize Gemini response: {}", e);
-                    let error_response = ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Failed to deserialize response: {}", e),
-                            r#type: "api_error".to_string(),
-                            code: Some("deserialize_error".to_string()),
+                (ApiType::Gemini, ApiType::Gemini) => {
+                    match serde_json::from_str::<GeminiResponse>(&response_text) {
+                        Ok(mut resp) => {
+                            resp.model_version = Some(model.clone());
+                            ResponseWrapper::Gemini(resp)
                         },
-                    };
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-                        .into_response();
+                        Err(e) => {
+                            warn!("Failed to deserialize Gemini response: {}", e);
+                            return Err(RouterError::conversion("deserialize_error", format!("Failed to deserialize response: {}", e)));
+                        }
+                    }
                 }
-            }
-        }
-        (ApiType::Anthropic, ApiType::Anthropic) => {
-            match serde_json::from_str::<AnthropicResponse>(&response_text) {
-                Ok(mut resp) => {
-                    resp.model = model.clone();
-                    ResponseWrapper::Anthropic(resp)
-                },
-                Err(e) => {
-                    warn!("Failed to deserialize Anthropic response: {}", e);
-                    let error_response = ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Failed to deserialize response: {}", e),
-                            r#type: "api_error".to_string(),
-                            code: Some("deserialize_error".to_string()),
+                (ApiType::Anthropic, ApiType::Anthropic) => {
+                    match serde_json::from_str::<AnthropicResponse>(&response_text) {
+                        Ok(mut resp) => {
+                            resp.model = model.clone();
+                            ResponseWrapper::Anthropic(resp)
                         },
-                    };
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-                        .into_response();
+                        Err(e) => {
+                            warn!("Failed to deserialize Anthropic response: {}", e);
+                            return Err(RouterError::conversion("deserialize_error", format!("Failed to deserialize response: {}", e)));
+                        }
+                    }
                 }
-            }
-        }
-        (ApiType::Anthropic, ApiType::OpenAI) => {
-            match serde_json::from_str::<AnthropicResponse>(&response_text) {
-                Ok(mut resp) => {
-                    resp.model = model.clone();
-                    ResponseWrapper::OpenAI(resp.into())
+                (ApiType::Anthropic, ApiType::OpenAI) => {
+                    match serde_json::from_str::<AnthropicResponse>(&response_text) {
+                        Ok(mut resp) => {
+                            resp.model = model.clone();
+                            ResponseWrapper::OpenAI(resp.into())
+                        }
+                        Err(e) => {
+                            warn!("Failed to deserialize Anthropic response: {}", e);
+                            return Err(RouterError::conversion("deserialize_error", format!("Failed to deserialize response: {}", e)));
+                        }
+                    }
                 }
-                Err(e) => {
-                    warn!("Failed to deserialize Anthropic response: {}", e);
-                    let error_response = ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Failed to deserialize response: {}", e),
-                            r#type: "api_error".to_string(),
-                            code: Some("deserialize_error".to_string()),
-                        },
-                    };
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-                        .into_response();
+                (ApiType::OpenAI, ApiType::Anthropic) => {
+                    match serde_json::from_str::<OpenAIResponse>(&response_text) {
+                        Ok(mut resp) => {
+                            resp.model = model.clone();
+                            ResponseWrapper::Anthropic(resp.into())
+                        }
+                        Err(e) => {
+                            warn!("Failed to deserialize OpenAI response: {}", e);
+                            return Err(RouterError::conversion("deserialize_error", format!("Failed to deserialize response: {}", e)));
+                        }
+                    }
                 }
-            }
-        }
-        (ApiType::OpenAI, ApiType::Anthropic) => {
-            match serde_json::from_str::<OpenAIResponse>(&response_text) {
-                Ok(mut resp) => {
-                    resp.model = model.clone();
-                    ResponseWrapper::Anthropic(resp.into())
+                (ApiType::Gemini, ApiType::OpenAI) => {
+                    match serde_json::from_str::<GeminiResponse>(&response_text) {
+                        Ok(mut resp) => {
+                            resp.model_version = Some(model.clone());
+                            ResponseWrapper::OpenAI(resp.into())
+                        }
+                        Err(e) => {
+                            warn!("Failed to deserialize Gemini response: {}", e);
+                            return Err(RouterError::conversion("deserialize_error", format!("Failed to deserialize response: {}", e)));
+                        }
+                    }
                 }
-                Err(e) => {
-                    warn!("Failed to deserialize OpenAI response: {}", e);
-                    let error_response = ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Failed to deserialize response: {}", e),
-                            r#type: "api_error".to_string(),
-                            code: Some("deserialize_error".to_string()),
-                        },
-                    };
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-                        .into_response();
+                (ApiType::Gemini, ApiType::Anthropic) => {
+                    match serde_json::from_str::<GeminiResponse>(&response_text) {
+                        Ok(mut resp) => {
+                            resp.model_version = Some(model.clone());
+                            ResponseWrapper::Anthropic(if direct { resp.into() } else { OpenAIResponse::from(resp).into() })
+                        }
+                        Err(e) => {
+                            warn!("Failed to deserialize Gemini response: {}", e);
+                            return Err(RouterError::conversion("deserialize_error", format!("Failed to deserialize response: {}", e)));
+                        }
+                    }
                 }
-            }
-        }
-        (ApiType::Gemini, ApiType::OpenAI) => {
-            match serde_json::from_str::<GeminiResponse>(&response_text) {
-                Ok(mut resp) => {
-                    resp.model_version = Some(model.clone());
-                    ResponseWrapper::OpenAI(resp.into())
+                (ApiType::Anthropic, ApiType::Gemini) => {
+                    match serde_json::from_str::<AnthropicResponse>(&response_text) {
+                        Ok(mut resp) => {
+                            resp.model = model.clone();
+                            ResponseWrapper::Gemini(if direct { resp.into() } else { OpenAIResponse::from(resp).into() })
+                        }
+                        Err(e) => {
+                            warn!("Failed to deserialize Anthropic response: {}", e);
+                            return Err(RouterError::conversion("deserialize_error", format!("Failed to deserialize response: {}", e)));
+                        }
+                    }
                 }
-                Err(e) => {
-                    warn!("Failed to deserialize Gemini response: {}", e);
-                    let error_response = ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Failed to deserialize response: {}", e),
-                            r#type: "api_error".to_string(),
-                            code: Some("deserialize_error".to_string()),
-                        },
-                    };
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-                        .into_response();
+                (ApiType::OpenAI, ApiType::Gemini) => {
+                    match serde_json::from_str::<OpenAIResponse>(&response_text) {
+                        Ok(mut resp) => {
+                            resp.model = model.clone();
+                            ResponseWrapper::Gemini(resp.into())
+                        }
+                        Err(e) => {
+                            warn!("Failed to deserialize OpenAI response: {}", e);
+                            return Err(RouterError::conversion("deserialize_error", format!("Failed to deserialize response: {}", e)));
+                        }
+                    }
                 }
+            })
+        })?;
+        debug!(
+            "Response received with model updated to: {}\n{:?}",
+            model,
+            serde_json::to_string(&response_wrapper)
+        );
+        let usage = response_wrapper.token_usage();
+        Ok::<_, RouterError>((Json(response_wrapper).into_response(), usage))
+    });
+    let (mut resp, usage) = match converted {
+        Ok(converted) => converted,
+        Err(err) => return err.into_response(),
+    };
+    // Lets the router price the request without re-parsing the body
+    if let Some(usage) = usage {
+        resp.extensions_mut().insert(usage);
+    }
+    if let Some(Ok(detail)) = helpers::content_filter_detail(&from, &response_text).map(|d| axum::http::HeaderValue::from_str(&d)) {
+        resp.headers_mut().insert(helpers::FINISH_DETAIL_HEADER, detail);
+    }
+    resp
+}
+
+// Some gateways answer a filtered prompt with 204 or an empty 200. Clients get a
+// valid completion with no content and finish reason `content_filter` (in their
+// format) instead of a deserialize error, plus a header naming the upstream status.
+fn empty_completion(model: String, status: reqwest::StatusCode, target_api_type: ApiType) -> axum::response::Response {
+    warn!("Upstream returned {} with an empty body, answering with an empty completion", status);
+    let collected = CollectedResponse { finish_reason: Some("content_filter".to_string()), ..Default::default() };
+    let response = match collected.finish(model, false) {
+        Ok(response) => response,
+        Err(e) => return e.into_response(),
+    };
+    let response_wrapper = match target_api_type {
+        ApiType::OpenAI => ResponseWrapper::OpenAI(response),
+        ApiType::Anthropic => ResponseWrapper::Anthropic(response.into()),
+        ApiType::Gemini => ResponseWrapper::Gemini(response.into()),
+    };
+    let mut resp = Json(response_wrapper).into_response();
+    resp.headers_mut().insert("x-llm-router-empty-upstream", axum::http::HeaderValue::from(status.as_u16()));
+    resp
+}
+
+/// Reads an upstream stream into one complete response in the client's format,
+/// for non-streaming requests with a soft deadline. When `deadline` passes
+/// first, what has arrived so far is returned with finish reason `length` and
+/// the `x-llm-router-partial: true` header instead of a timeout error.
+pub async fn collect_streaming_response(
+    stream: impl Stream<Item = Result<Bytes, reqwest::Error>> + Send,
+    model: String,
+    source_api_type: ApiType,
+    target_api_type: ApiType,
+    deadline: tokio::time::Instant,
+    max_bytes: u64,
+) -> axum::response::Response {
+    let mut stream = std::pin::pin!(stream);
+    let mut collected = CollectedResponse::default();
+    let mut pending_bytes: Vec<u8> = Vec::new();
+    let mut received: u64 = 0;
+    let partial = loop {
+        let bytes = match tokio::time::timeout_at(deadline, stream.next()).await {
+            Err(_) => break true,
+            Ok(None) => break false,
+            Ok(Some(Err(e))) => {
+                warn!("Upstream streaming error: {}", e);
+                return RouterError::from_reqwest(&e).into_response();
             }
+            Ok(Some(Ok(bytes))) => bytes,
+        };
+        // The answer is built in memory, so the whole stream counts against the limit
+        received += bytes.len() as u64;
+        if received > max_bytes {
+            return too_large(format!("more than {}", received), max_bytes).into_response();
         }
-        (ApiType::Gemini, ApiType::Anthropic) => {
-            match serde_json::from_str::<GeminiResponse>(&response_text) {
-                Ok(mut resp) => {
-                    resp.model_version = Some(model.clone());
-                    let resp1: OpenAIResponse = resp.into();
-                    ResponseWrapper::Anthropic(resp1.into())
-                }
-                Err(e) => {
-                    warn!("Failed to deserialize Gemini response: {}", e);
-                    let error_response = ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Failed to deserialize response: {}", e),
-                            r#type: "api_error".to_string(),
-                            code: Some("deserialize_error".to_string()),
-                        },
-                    };
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-                        .into_response();
-                }
+        pending_bytes.extend_from_slice(&bytes);
+        while let Some(pos) = pending_bytes.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending_bytes.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim_end().strip_prefix("data:").map(str::trim_start) else { continue };
+            // An error mid-answer fails the request rather than returning the text so far as complete
+            if let Some((error_type, message)) = helpers::stream_error(&source_api_type, data) {
+                warn!("Upstream sent a {} error mid-stream: {}", error_type, message);
+                return RouterError::Transport(format!("Upstream stream failed: {}", message)).into_response();
             }
-        }
-        (ApiType::Anthropic, ApiType::Gemini) => {
-            match serde_json::from_str::<AnthropicResponse>(&response_text) {
-                Ok(mut resp) => {
-                    resp.model = model.clone();
-                    let resp1: OpenAIResponse = resp.into();
-                    ResponseWrapper::Gemini(resp1.into())
-                }
-                Err(e) => {
-                    warn!("Failed to deserialize Anthropic response: {}", e);
-                    let error_response = ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Failed to deserialize response: {}", e),
-                            r#type: "api_error".to_string(),
-                            code: Some("deserialize_error".to_string()),
-                        },
-                    };
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-                        .into_response();
-                }
+            if data != "[DONE]"
+                && let Some(chunk) = to_openai_chunk(&source_api_type, data)
+            {
+                collected.push(chunk);
             }
         }
-        (ApiType::OpenAI, ApiType::Gemini) => {
-            match serde_json::from_str::<OpenAIResponse>(&response_text) {
-                Ok(mut resp) => {
-                    resp.model = model.clone();
-                    ResponseWrapper::Gemini(resp.into())
+    };
+    if partial {
+        warn!("Soft timeout reached for model {}, returning a partial response", model);
+    }
+
+    let response = match collected.finish(model, partial) {
+        Ok(response) => response,
+        Err(e) => return e.into_response(),
+    };
+    let response_wrapper = match target_api_type {
+        ApiType::OpenAI => ResponseWrapper::OpenAI(response),
+        ApiType::Anthropic => ResponseWrapper::Anthropic(response.into()),
+        ApiType::Gemini => ResponseWrapper::Gemini(response.into()),
+    };
+    let usage = response_wrapper.token_usage();
+    let mut resp = Json(response_wrapper).into_response();
+    if let Some(usage) = usage {
+        resp.extensions_mut().insert(usage);
+    }
+    if partial {
+        resp.headers_mut().insert("x-llm-router-partial", axum::http::HeaderValue::from_static("true"));
+    }
+    resp
+}
+
+// Running sum of OpenAI pivot chunks, rebuilt into a chat completion at the end
+#[derive(Debug, Default)]
+struct CollectedResponse {
+    id: Option<String>,
+    created: Option<u64>,
+    content: String,
+    reasoning: String,
+    // (id, name, arguments) by tool call index
+    tool_calls: std::collections::BTreeMap<i32, (String, String, String)>,
+    audio: Option<OpenAIAudio>,
+    finish_reason: Option<String>,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+impl CollectedResponse {
+    fn push(&mut self, chunk: OpenAIStreamChunk) {
+        if self.id.is_none() && !chunk.id.is_empty() && chunk.id != "chatcmpl-default" {
+            self.id = Some(chunk.id.clone());
+        }
+        self.created.get_or_insert(chunk.created);
+        // Some formats report input and output tokens in different chunks
+        if let Some(usage) = &chunk.usage {
+            self.prompt_tokens = self.prompt_tokens.max(usage.prompt_tokens);
+            self.completion_tokens = self.completion_tokens.max(usage.completion_tokens);
+        }
+        for choice in chunk.choices.into_iter().flatten().filter(|c| c.index == 0) {
+            if let Some(reason) = choice.finish_reason {
+                self.finish_reason = Some(reason);
+            }
+            let Some(delta) = choice.delta else { continue };
+            self.content.push_str(delta.content.as_deref().unwrap_or_default());
+            self.reasoning.push_str(delta.reasoning_content.as_deref().unwrap_or_default());
+            if let Some(audio) = delta.audio {
+                self.audio.get_or_insert_default().append(audio);
+            }
+            for call in delta.tool_calls.into_iter().flatten() {
+                let entry = self.tool_calls.entry(call.index).or_default();
+                if let Some(id) = call.id {
+                    entry.0 = id;
                 }
-                Err(e) => {
-                    warn!("Failed to deserialize OpenAI response: {}", e);
-                    let error_response = ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Failed to deserialize response: {}", e),
-                            r#type: "api_error".to_string(),
-                            code: Some("deserialize_error".to_string()),
-                        },
-                    };
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-                        .into_response();
+                if let Some(function) = call.function {
+                    if let Some(name) = function.name {
+                        entry.1.push_str(&name);
+                    }
+                    if let Some(arguments) = function.arguments {
+                        entry.2.push_str(&arguments);
+                    }
                 }
             }
         }
-    };
+    }
 
-    debug!(
-        "Response received with model updated to: {}\n{:?}",
-        model,
-        serde_json::to_string(&response_wrapper)
-    );
-    Json(response_wrapper).into_response()
+    fn finish(self, model: String, partial: bool) -> Result<OpenAIResponse, RouterError> {
+        let tool_calls: Vec<_> = self
+            .tool_calls
+            .into_values()
+            .map(|(id, name, arguments)| {
+                // A call cut off by the deadline still gets parseable arguments
+                let arguments = if partial { repair_json(&arguments).unwrap_or_else(|| "{}".to_string()) } else { arguments };
+                json!({"id": id, "type": "function", "function": {"name": name, "arguments": arguments}})
+            })
+            .collect();
+        let finish_reason = match self.finish_reason {
+            Some(reason) if !partial => reason,
+            _ if partial => "length".to_string(),
+            _ if !tool_calls.is_empty() => "tool_calls".to_string(),
+            _ => "stop".to_string(),
+        };
+        let mut message = json!({"role": "assistant", "content": self.content});
+        if !self.reasoning.is_empty() {
+            message["reasoning_content"] = json!(self.reasoning);
+        }
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = json!(tool_calls);
+        }
+        if let Some(audio) = self.audio {
+            message["audio"] = json!(audio);
+        }
+        let mut response = json!({
+            "id": self.id.unwrap_or_else(|| clock::new_id("chatcmpl")),
+            "object": "chat.completion",
+            "created": self.created.unwrap_or_else(clock::now_secs),
+            "model": model,
+            "choices": [{"index": 0, "message": message, "finish_reason": finish_reason}],
+        });
+        if self.prompt_tokens > 0 || self.completion_tokens > 0 {
+            response["usage"] = json!({
+                "prompt_tokens": self.prompt_tokens,
+                "completion_tokens": self.completion_tokens,
+                "total_tokens": self.prompt_tokens + self.completion_tokens,
+            });
+        }
+        serde_json::from_value(response)
+            .map_err(|e| RouterError::conversion("deserialize_error", format!("Failed to assemble the collected response: {}", e)))
+    }
 }
 
 pub async fn handle_streaming_response(
@@ -241,27 +437,52 @@ pub async fn handle_streaming_response(
     model: String,
     source_api_type: ApiType,
     target_api_type: ApiType,
+    options: StreamOptions,
 ) -> axum::response::Response {
     // Track contextual state needed for conversion
     let mut previous_event = String::new();
     let mut previous_delta_type = String::new();
-    let mut previous_function_arg = String::new();
+    let mut open_tool = None;
     let mut msg_index = 0;
+    let tool_args_mode = options
+        .tool_arguments
+        .unwrap_or_else(|| ToolArgumentsMode::default_for(&target_api_type));
+    let mut tool_args = ToolArgsBuffer::new(tool_args_mode, options.repair_tool_arguments);
+    let mut identity = ChunkIdentity::default();
 
     // Byte buffer to accumulate partial UTF-8 lines across chunks
     let mut pending_bytes: Vec<u8> = Vec::new();
 
     // Move these once into the closure to avoid per-line clones in the hot path
     let src_api = source_api_type;
-    let tgt_api = target_api_type;
+    let tgt_api = target_api_type.clone();
+    let on_error = options.on_upstream_error.clone();
 
+    // A trailing `None` marks the end of upstream so buffered tool arguments can be flushed
     let event_stream = stream
+        .map(Some)
+        .chain(stream::once(async { None }))
         .map(move |result| match result {
-            Ok(bytes) => {
+            None => {
+                let frames = match tool_args.flush() {
+                    Some(chunk) => openai_chunk_frames(
+                        &tgt_api,
+                        chunk,
+                        &model,
+                        &mut previous_event,
+                        &mut previous_delta_type,
+                        &mut open_tool,
+                        &mut msg_index,
+                    ),
+                    None => vec![],
+                };
+                stream::iter(frames)
+            }
+            Some(Ok(bytes)) => {
                 // Accumulate bytes; handle partial lines safely without lossy conversion
                 pending_bytes.extend_from_slice(&bytes);
 
-                let mut out: Vec<Result<Event, Infallible>> = Vec::new();
+                let mut out: Vec<SseFrame> = Vec::new();
 
                 // Find and process complete lines terminated by '\n'
                 loop {
@@ -281,26 +502,28 @@ pub async fn handle_streaming_response(
 
                                 if line_str.starts_with("data: ") {
                                     let data = &line_str[6..];
-                                    if data == "[DONE]" && tgt_api == ApiType::OpenAI {
-                                        out.push(Ok(Event::default().data("[DONE]")));
-                                    } else {
-                                        let converted = convert_sse_data_line(
-                                            &src_api,
-                                            &tgt_api,
-                                            data,
-                                            &model,
-                                            &mut previous_event,
-                                            &mut previous_delta_type,
-                                            &mut previous_function_arg,
-                                            &mut msg_index,
-                                        );
-                                        for (event_opt, payload) in converted.into_iter() {
-                                            let mut ev = Event::default().data(payload);
-                                            if let Some(name) = event_opt {
-                                                ev = ev.event(name);
-                                            }
-                                            out.push(Ok(ev));
+                                    report_stream_error(&src_api, data, &on_error);
+                                    if data == "[DONE]" {
+                                        // Only OpenAI has a [DONE] frame; terminator rules decide its fate
+                                        if tgt_api == ApiType::OpenAI {
+                                            out.push((None, "[DONE]".to_string()));
                                         }
+                                    } else {
+                                        let converted = metrics::time_conversion(ConversionKind::StreamChunk, &src_api, &tgt_api, || {
+                                            convert_sse_data_line(
+                                                &src_api,
+                                                &tgt_api,
+                                                data,
+                                                &model,
+                                                &mut previous_event,
+                                                &mut previous_delta_type,
+                                                &mut open_tool,
+                                                &mut tool_args,
+                                                &mut identity,
+                                                &mut msg_index,
+                                            )
+                                        });
+                                        out.extend(converted);
                                     }
                                 }
 
@@ -323,28 +546,29 @@ pub async fn handle_streaming_response(
                                 }
                                 if line_str.starts_with("data: ") {
                                     let data = &line_str[6..];
-                                    if data == "[DONE]" && tgt_api == ApiType::OpenAI {
-                                        out.push(Ok(Event::default().data("[DONE]")));
+                                    report_stream_error(&src_api, data, &on_error);
+                                    if data == "[DONE]" {
+                                        if tgt_api == ApiType::OpenAI {
+                                            out.push((None, "[DONE]".to_string()));
+                                        }
                                         pending_bytes.clear();
                                     } else {
-                                        let converted = convert_sse_data_line(
-                                            &src_api,
-                                            &tgt_api,
-                                            data,
-                                            &model,
-                                            &mut previous_event,
-                                            &mut previous_delta_type,
-                                            &mut previous_function_arg,
-                                            &mut msg_index,
-                                        );
+                                        let converted = metrics::time_conversion(ConversionKind::StreamChunk, &src_api, &tgt_api, || {
+                                            convert_sse_data_line(
+                                                &src_api,
+                                                &tgt_api,
+                                                data,
+                                                &model,
+                                                &mut previous_event,
+                                                &mut previous_delta_type,
+                                                &mut open_tool,
+                                                &mut tool_args,
+                                                &mut identity,
+                                                &mut msg_index,
+                                            )
+                                        });
                                         if !converted.is_empty() {
-                                            for (event_opt, payload) in converted.into_iter() {
-                                                let mut ev = Event::default().data(payload);
-                                                if let Some(name) = event_opt {
-                                                    ev = ev.event(name);
-                                                }
-                                                out.push(Ok(ev));
-                                            }
+                                            out.extend(converted);
                                             // Clear pending only when successfully parsed
                                             pending_bytes.clear();
                                         }
@@ -364,7 +588,7 @@ pub async fn handle_streaming_response(
 
                 stream::iter(out)
             }
-            Err(e) => {
+            Some(Err(e)) => {
                 // Log upstream errors and emit an error event to help clients
                 warn!("Upstream streaming error: {}", e);
                 let payload = serde_json::to_string(&json!({
@@ -376,73 +600,396 @@ pub async fn handle_streaming_response(
                 .unwrap_or_else(|_| {
                     "{\"error\":{\"message\":\"upstream streaming error\"}}".to_string()
                 });
-                let ev = Event::default().event("error").data(payload);
-                stream::iter(vec![Ok(ev)])
+                stream::iter(vec![(Some("error".to_string()), payload)])
             }
         })
         .flatten();
 
+    let smoothing_api = target_api_type.clone();
+    let event_stream = event_stream.flat_map(move |frame| {
+        stream::iter(match options.max_delta_chars {
+            Some(max_chars) => stream_smoothing::split_frame(frame, &smoothing_api, max_chars),
+            None => vec![frame],
+        })
+    });
+
+    let frames = apply_terminator(event_stream, target_api_type, options.terminator);
+
+    // Buffered streams run to completion even if the client goes away; the
+    // client reads them back from the store like any later resume would.
+    if let Some(stored) = options.store {
+        stored.record(frames);
+        return frames_to_sse(stored.subscribe(None));
+    }
+
+    let event_stream = frames.map(
+        |(event_opt, payload)| -> Result<Event, Infallible> {
+            let mut ev = Event::default().data(payload);
+            if let Some(name) = event_opt {
+                ev = ev.event(name);
+            }
+            Ok(ev)
+        },
+    );
+
     // Return SSE with keep-alive
     Sse::new(event_stream)
         .keep_alive(axum::response::sse::KeepAlive::new().interval(Duration::from_secs(1)))
         .into_response()
 }
 
-fn accumulate_function_args_and_patch(
-    openai_chunk: &mut OpenAIStreamChunk,
-    previous_function_arg: &mut String,
-) -> bool {
-    // Extract current function-call args (if any)
-    let args: &str = openai_chunk
-        .choices
-        .as_ref()
-        .and_then(|cs| cs.first())
-        .and_then(|c| c.delta.as_ref())
-        .and_then(|d| d.tool_calls.as_ref())
-        .and_then(|tcs| {
-            tcs.iter()
-                .find(|tc| tc.r#type.as_deref() == Some("function"))
+/// One outgoing SSE frame: optional event name and data payload.
+pub type SseFrame = (Option<String>, String);
+
+/// Per-stream knobs supplied by the router.
+#[derive(Debug, Clone, Default)]
+pub struct StreamOptions {
+    pub terminator: TerminatorMode,
+    /// Buffer the stream in the response store once upstream starts streaming
+    pub resumable: bool,
+    /// Record frames for later resumption via `/v1/responses/{id}/events`
+    pub store: Option<Arc<StoredStream>>,
+    /// Aggregate or pass through tool-call argument fragments; `None` uses the target's default
+    pub tool_arguments: Option<ToolArgumentsMode>,
+    /// Close truncated tool-call JSON when a buffered call is flushed incomplete
+    pub repair_tool_arguments: bool,
+    /// Told about error events the upstream sent mid-stream that count against it
+    pub on_upstream_error: Option<UpstreamErrorHook>,
+    /// Split text deltas longer than this many characters into several frames
+    pub max_delta_chars: Option<usize>,
+}
+
+/// Called with the error type and message of an upstream's mid-stream error event.
+#[derive(Clone)]
+pub struct UpstreamErrorHook(pub Arc<UpstreamErrorFn>);
+
+pub type UpstreamErrorFn = dyn Fn(&str, &str) + Send + Sync;
+
+impl std::fmt::Debug for UpstreamErrorHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("UpstreamErrorHook")
+    }
+}
+
+// Report an Anthropic error event to the hook when it is the upstream's fault
+fn report_stream_error(source_api_type: &ApiType, data: &str, hook: &Option<UpstreamErrorHook>) {
+    if *source_api_type != ApiType::Anthropic {
+        return;
+    }
+    if let Some((error_type, message)) = helpers::anthropic_stream_error(data) {
+        warn!("Upstream sent a {} event mid-stream: {}", error_type, message);
+        if let Some(hook) = hook
+            && helpers::is_upstream_error_type(&error_type)
+        {
+            (hook.0)(&error_type, &message);
+        }
+    }
+}
+
+// An upstream error event in the client's format. OpenAI clients get an error
+// chunk and [DONE], Gemini clients a Gemini error object.
+fn stream_error_frames(target_api_type: &ApiType, error_type: &str, message: &str) -> Vec<SseFrame> {
+    match target_api_type {
+        ApiType::OpenAI => vec![
+            (None, json!({"error": {"message": message, "type": error_type, "code": error_type}}).to_string()),
+            (None, "[DONE]".to_string()),
+        ],
+        ApiType::Anthropic => vec![(
+            Some("error".to_string()),
+            json!({"type": "error", "error": {"type": error_type, "message": message}}).to_string(),
+        )],
+        ApiType::Gemini => {
+            let (code, status) = helpers::anthropic_error_to_gemini_status(error_type);
+            vec![(None, json!({"error": {"code": code, "message": message, "status": status}}).to_string())]
+        }
+    }
+}
+
+// The frame that closes a stream in the client-facing format, if the format has one
+fn terminator_frame(target_api_type: &ApiType) -> Option<SseFrame> {
+    match target_api_type {
+        ApiType::OpenAI => Some((None, "[DONE]".to_string())),
+        ApiType::Anthropic => serde_json::to_string(&AnthropicStreamChunk::MessageStop)
+            .ok()
+            .map(|s| (Some("message_stop".to_string()), s)),
+        ApiType::Gemini => None,
+    }
+}
+
+fn is_terminator(target_api_type: &ApiType, frame: &SseFrame) -> bool {
+    match target_api_type {
+        ApiType::OpenAI => frame.0.is_none() && frame.1 == "[DONE]",
+        ApiType::Anthropic => frame.0.as_deref() == Some("message_stop"),
+        ApiType::Gemini => false,
+    }
+}
+
+// An error event, converted or raised by the router, that ends the stream
+fn is_error_frame(target_api_type: &ApiType, frame: &SseFrame) -> bool {
+    frame.0.as_deref() == Some("error") || helpers::stream_error(target_api_type, &frame.1).is_some()
+}
+
+/// Applies the terminator rule for the client-facing format:
+/// `ensure` emits exactly one terminator at the end, `passthrough` forwards
+/// only what upstream/conversion produced, `suppress` drops it. A stream that
+/// ended in an error gets no terminator added, so it does not read as complete.
+fn apply_terminator(
+    frames: impl Stream<Item = SseFrame> + Send + 'static,
+    target_api_type: ApiType,
+    mode: TerminatorMode,
+) -> impl Stream<Item = SseFrame> + Send + 'static {
+    let seen = Arc::new(AtomicBool::new(false));
+    let seen_tail = seen.clone();
+    let failed = Arc::new(AtomicBool::new(false));
+    let failed_tail = failed.clone();
+    let tail_api_type = target_api_type.clone();
+    frames
+        .filter(move |frame| {
+            let keep = if !is_terminator(&target_api_type, frame) {
+                failed.store(is_error_frame(&target_api_type, frame), Ordering::SeqCst);
+                true
+            } else {
+                match mode {
+                    TerminatorMode::Suppress => false,
+                    TerminatorMode::Passthrough => true,
+                    TerminatorMode::Ensure => !seen.swap(true, Ordering::SeqCst),
+                }
+            };
+            futures::future::ready(keep)
         })
-        .and_then(|tc| tc.function.as_ref())
-        .and_then(|f| f.arguments.as_deref())
-        .unwrap_or("");
+        .chain(stream::once(async move {
+            if mode == TerminatorMode::Ensure && !seen_tail.load(Ordering::SeqCst) && !failed_tail.load(Ordering::SeqCst) {
+                terminator_frame(&tail_api_type)
+            } else {
+                None
+            }
+        }).filter_map(futures::future::ready))
+}
 
-    if args.is_empty() {
-        return false;
+/// Re-frames a streamed answer for Gemini clients that asked for the JSON array
+/// form (`streamGenerateContent` without `alt=sse`): each SSE data payload
+/// becomes an array element. The closing `]` is always sent, also after an
+/// error, since the body would not parse without it.
+pub fn sse_to_json_array(response: axum::response::Response) -> axum::response::Response {
+    let (mut parts, body) = response.into_parts();
+    parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let mut pending = Vec::new();
+    let mut first = true;
+    let elements = body.into_data_stream().map(move |bytes| {
+        match bytes {
+            Ok(bytes) => pending.extend_from_slice(&bytes),
+            Err(e) => warn!("Stream failed while framing it as a JSON array: {}", e),
+        }
+        let mut out = String::new();
+        while let Some((at, len)) = stream_fence::frame_end(&pending) {
+            let frame: Vec<u8> = pending.drain(..at + len).collect();
+            let frame = String::from_utf8_lossy(&frame);
+            // Keep-alive comments carry no data
+            let data: Vec<&str> =
+                frame.lines().filter_map(|l| l.strip_prefix("data:")).map(|d| d.strip_prefix(' ').unwrap_or(d)).collect();
+            if data.is_empty() {
+                continue;
+            }
+            if !std::mem::take(&mut first) {
+                out.push_str(",\r\n");
+            }
+            out.push_str(&data.join("\n"));
+        }
+        Ok::<_, Infallible>(Bytes::from(out))
+    });
+    let body = stream::once(futures::future::ready(Ok(Bytes::from_static(b"["))))
+        .chain(elements)
+        .chain(stream::once(futures::future::ready(Ok(Bytes::from_static(b"]")))));
+    axum::response::Response::from_parts(parts, axum::body::Body::from_stream(body))
+}
+
+/// Tool-call argument fragments held back until they form complete JSON, for
+/// clients in `aggregate` mode. Passthrough mode forwards every fragment as is.
+#[derive(Debug)]
+pub struct ToolArgsBuffer {
+    mode: ToolArgumentsMode,
+    repair: bool,
+    args: String,
+    // First chunk of the buffered call; it carries the call's name and id
+    pending: Option<OpenAIStreamChunk>,
+}
+
+impl ToolArgsBuffer {
+    pub fn new(mode: ToolArgumentsMode, repair: bool) -> Self {
+        Self { mode, repair, args: String::new(), pending: None }
     }
 
-    previous_function_arg.push_str(args);
-    let parse_ok = serde_json::from_str::<serde_json::Value>(&previous_function_arg).is_ok();
-    if !parse_ok {
-        // Need to wait for more data to form valid JSON
-        return true;
+    /// Hands the chunks to send for `chunk` to `emit`, in order: none while a
+    /// call's arguments are incomplete, and any buffered call that ends here first.
+    fn push(&mut self, mut chunk: OpenAIStreamChunk, mut emit: impl FnMut(OpenAIStreamChunk)) {
+        if self.mode == ToolArgumentsMode::Passthrough {
+            return emit(chunk);
+        }
+        let finishing = chunk
+            .choices
+            .as_ref()
+            .and_then(|cs| cs.first())
+            .is_some_and(|c| c.finish_reason.is_some());
+
+        let Some(call) = function_call(&mut chunk) else {
+            // No arguments in this chunk; a finish still flushes what is buffered
+            if finishing && let Some(flushed) = self.flush() {
+                emit(flushed);
+            }
+            return emit(chunk);
+        };
+        // A new parallel call ends the previous one
+        let index = call.index;
+        let args = call.function.as_mut().and_then(|f| f.arguments.take()).unwrap_or_default();
+        if self.pending.as_mut().and_then(function_call).is_some_and(|first| first.index != index)
+            && let Some(flushed) = self.flush()
+        {
+            emit(flushed);
+        }
+        let Some(call) = function_call(&mut chunk) else { return };
+        self.args.push_str(&args);
+
+        if serde_json::from_str::<serde_json::Value>(&self.args).is_ok() {
+            // Later fragments usually omit the name and id; take them from the first one
+            if let Some(first) = self.pending.take().as_mut().and_then(function_call) {
+                call.id = call.id.take().or(first.id.take());
+                if let (Some(function), Some(first_function)) = (call.function.as_mut(), first.function.as_mut()) {
+                    function.name = function.name.take().or(first_function.name.take());
+                }
+            }
+            if let Some(function) = call.function.as_mut() {
+                function.arguments = Some(std::mem::take(&mut self.args));
+            }
+            return emit(chunk);
+        }
+
+        if self.pending.is_none() {
+            let mut first = chunk.clone();
+            if let Some(choice) = first.choices.as_mut().and_then(|cs| cs.first_mut()) {
+                choice.finish_reason = None;
+            }
+            self.pending = Some(first);
+        }
+        if !finishing {
+            // Need to wait for more data to form valid JSON
+            return;
+        }
+        // The call ends here whether or not its arguments parse
+        if let Some(delta) = chunk.choices.as_mut().and_then(|cs| cs.first_mut()).and_then(|c| c.delta.as_mut()) {
+            delta.tool_calls = None;
+        }
+        if let Some(flushed) = self.flush() {
+            emit(flushed);
+        }
+        emit(chunk)
     }
 
-    // Parsing succeeded: set function call args to accumulated buffer
-    if let Some(choices) = openai_chunk.choices.as_mut() {
-        if let Some(first) = choices.first_mut() {
-            if let Some(delta) = first.delta.as_mut() {
-                if let Some(tool_calls) = delta.tool_calls.as_mut() {
-                    if let Some(tc) = tool_calls
-                        .iter_mut()
-                        .find(|tc| tc.r#type.as_deref() == Some("function"))
-                    {
-                        if let Some(function) = tc.function.as_mut() {
-                            function.arguments = Some(previous_function_arg.clone());
-                            previous_function_arg.clear();
-                        }
-                    }
+    /// The buffered call with whatever arguments arrived, or `None` if nothing
+    /// is buffered. With repair on, truncated JSON is closed and the chunk flagged.
+    pub fn flush(&mut self) -> Option<OpenAIStreamChunk> {
+        let mut chunk = self.pending.take()?;
+        let partial = std::mem::take(&mut self.args);
+        let mut arguments = partial.clone();
+        if serde_json::from_str::<serde_json::Value>(&partial).is_err() {
+            match self.repair.then(|| repair_json(&partial)).flatten() {
+                Some(repaired) => {
+                    warn!("Tool call arguments were cut off; repaired {:?} to {:?}", partial, repaired);
+                    arguments = repaired;
+                    chunk.extra_fields.insert("routerWarning".to_string(), json!("truncated_tool_arguments_repaired"));
                 }
+                None => warn!("Tool call arguments were cut off; forwarding {} bytes as received", partial.len()),
             }
         }
+        if let Some(function) = function_call(&mut chunk).and_then(|c| c.function.as_mut()) {
+            function.arguments = Some(arguments);
+        }
+        Some(chunk)
+    }
+}
+
+// The chunk's function tool call; OpenAI sends `type` only on a call's first fragment
+fn function_call(chunk: &mut OpenAIStreamChunk) -> Option<&mut OpenAIStreamToolCall> {
+    chunk
+        .choices
+        .as_mut()
+        .and_then(|cs| cs.first_mut())
+        .and_then(|c| c.delta.as_mut())
+        .and_then(|d| d.tool_calls.as_mut())
+        .and_then(|tcs| {
+            tcs.iter_mut()
+                .find(|tc| tc.function.is_some() && tc.r#type.as_deref().is_none_or(|t| t == "function"))
+        })
+}
+
+/// Id and `created` shared by every chunk of one converted stream, taken from
+/// its first chunk. Converted chunks otherwise get a fresh timestamp each and,
+/// before the upstream names the response, a placeholder id.
+#[derive(Debug, Default)]
+pub struct ChunkIdentity {
+    id: Option<String>,
+    created: Option<u64>,
+}
+
+impl ChunkIdentity {
+    pub fn stamp(&mut self, chunk: &mut OpenAIStreamChunk) {
+        chunk.created = *self.created.get_or_insert(chunk.created);
+        let id = self.id.get_or_insert_with(|| match chunk.id.as_str() {
+            "" | "chatcmpl-default" => clock::new_id("chatcmpl"),
+            id => id.to_string(),
+        });
+        chunk.id.clone_from(id);
     }
+}
 
-    false
+// Parses an upstream `data:` payload into the OpenAI chunk used as the pivot format
+fn to_openai_chunk(source_api_type: &ApiType, data: &str) -> Option<OpenAIStreamChunk> {
+    match source_api_type {
+        ApiType::OpenAI => serde_json::from_str::<OpenAIStreamChunk>(data).ok(),
+        ApiType::Anthropic => serde_json::from_str::<AnthropicStreamChunk>(data).ok().map(Into::into),
+        ApiType::Gemini => serde_json::from_str::<GeminiStreamChunk>(data).ok().map(Into::into),
+    }
+}
+
+/// Renders an OpenAI pivot chunk as frames in the client-facing format.
+pub fn openai_chunk_frames(
+    target_api_type: &ApiType,
+    chunk: OpenAIStreamChunk,
+    model: &String,
+    previous_event: &mut String,
+    previous_delta_type: &mut String,
+    open_tool: &mut Option<(i32, Option<String>)>,
+    msg_index: &mut i32,
+) -> Vec<SseFrame> {
+    match target_api_type {
+        ApiType::OpenAI => serde_json::to_string(&chunk).map(|s| vec![(None, s)]).unwrap_or_default(),
+        ApiType::Anthropic => openai_to_anthropic_stream_chunks(
+            &chunk,
+            model,
+            previous_event,
+            previous_delta_type,
+            open_tool,
+            msg_index,
+        )
+        .into_iter()
+        .map(|(event, payload)| (Some(event), payload))
+        .collect(),
+        ApiType::Gemini => {
+            let warning = chunk.extra_fields.get("routerWarning").cloned();
+            let mut gemini_chunk: GeminiStreamChunk = chunk.into();
+            gemini_chunk.model_version = Some(model.clone());
+            if let Some(warning) = warning {
+                gemini_chunk.extra_fields.insert("routerWarning".to_string(), warning);
+            }
+            serde_json::to_string(&gemini_chunk).map(|s| vec![(None, s)]).unwrap_or_default()
+        }
+    }
 }
 
 /// 将单行 SSE `data:` 载荷从 source -> target 转换为输出帧集合。
 /// 返回的 Vec 中，(None, data) 表示 OpenAI 风格的无事件名数据帧；
 /// (Some(event_name), data) 表示 Anthropic 风格的具名事件帧。
+#[allow(clippy::too_many_arguments)]
 pub fn convert_sse_data_line(
     source_api_type: &ApiType,
     target_api_type: &ApiType,
@@ -450,19 +997,12 @@ pub fn convert_sse_data_line(
     model: &String,
     previous_event: &mut String,
     previous_delta_type: &mut String,
-    previous_function_arg: &mut String,
+    open_tool: &mut Option<(i32, Option<String>)>,
+    tool_args: &mut ToolArgsBuffer,
+    identity: &mut ChunkIdentity,
     msg_index: &mut i32,
 ) -> Vec<(Option<String>, String)> {
     match (source_api_type, target_api_type) {
-        (ApiType::OpenAI, ApiType::OpenAI) => {
-            if let Ok(mut chunk) = serde_json::from_str::<OpenAIStreamChunk>(data) {
-                chunk.model = model.clone();
-                if let Ok(s) = serde_json::to_string(&chunk) {
-                    return vec![(None, s)];
-                }
-            }
-            vec![]
-        }
         (ApiType::Gemini, ApiType::Gemini) => {
             if let Ok(mut chunk) = serde_json::from_str::<GeminiStreamChunk>(data) {
                 chunk.model_version = Some(model.clone());
@@ -485,86 +1025,31 @@ pub fn convert_sse_data_line(
             }
             vec![]
         }
-        (ApiType::Anthropic, ApiType::OpenAI) => {
-            if let Ok(chunk) = serde_json::from_str::<AnthropicStreamChunk>(data) {
-                let mut openai_chunk: OpenAIStreamChunk = chunk.into();
-                openai_chunk.model = model.clone();
-                if let Ok(s) = serde_json::to_string(&openai_chunk) {
-                    return vec![(None, s)];
-                }
-            }
-            vec![]
-        }
-        (ApiType::OpenAI, ApiType::Anthropic) => {
-            if let Ok(mut chunk) = serde_json::from_str::<OpenAIStreamChunk>(data) {
-                chunk.model = model.clone();
-                return openai_to_anthropic_stream_chunks(
-                    &chunk,
-                    model,
-                    previous_event,
-                    previous_delta_type,
-                    msg_index,
-                )
-                .into_iter()
-                .map(|(event, payload)| (Some(event), payload))
-                .collect();
-            }
-            vec![]
-        }
-        (ApiType::Gemini, ApiType::OpenAI) => {
-            if let Ok(mut chunk) = serde_json::from_str::<GeminiStreamChunk>(data) {
-                chunk.model_version = Some(model.clone());
-                let openai_chunk: OpenAIStreamChunk = chunk.into();
-                if let Ok(s) = serde_json::to_string(&openai_chunk) {
-                    return vec![(None, s)];
-                }
+        // Everything else pivots through an OpenAI chunk
+        _ => {
+            if *source_api_type == ApiType::Anthropic
+                && let Some((error_type, message)) = helpers::anthropic_stream_error(data)
+            {
+                return stream_error_frames(target_api_type, &error_type, &message);
             }
-            vec![]
-        }
-        (ApiType::Gemini, ApiType::Anthropic) => {
-            if let Ok(mut chunk) = serde_json::from_str::<GeminiStreamChunk>(data) {
-                chunk.model_version = Some(model.clone());
-                let openai_chunk: OpenAIStreamChunk = chunk.into();
-                return openai_to_anthropic_stream_chunks(
-                    &openai_chunk,
+            let Some(mut openai_chunk) = to_openai_chunk(source_api_type, data) else {
+                return vec![];
+            };
+            openai_chunk.model = model.clone();
+            identity.stamp(&mut openai_chunk);
+            let mut frames = Vec::new();
+            tool_args.push(openai_chunk, |chunk| {
+                frames.extend(openai_chunk_frames(
+                    target_api_type,
+                    chunk,
                     model,
                     previous_event,
                     previous_delta_type,
+                    open_tool,
                     msg_index,
-                )
-                .into_iter()
-                .map(|(event, payload)| (Some(event), payload))
-                .collect();
-            }
-            vec![]
-        }
-        (ApiType::Anthropic, ApiType::Gemini) => {
-            if let Ok(anth_chunk) = serde_json::from_str::<AnthropicStreamChunk>(data) {
-                let mut openai_chunk: OpenAIStreamChunk = anth_chunk.into();
-                if accumulate_function_args_and_patch(&mut openai_chunk, previous_function_arg) {
-                    return vec![];
-                }
-
-                let mut gemini_chunk: GeminiStreamChunk = openai_chunk.into();
-                gemini_chunk.model_version = Some(model.clone());
-                if let Ok(s) = serde_json::to_string(&gemini_chunk) {
-                    return vec![(None, s)];
-                }
-            }
-            vec![]
-        }
-        (ApiType::OpenAI, ApiType::Gemini) => {
-            if let Ok(mut openai_chunk) = serde_json::from_str::<OpenAIStreamChunk>(data) {
-                openai_chunk.model = model.clone();
-                if accumulate_function_args_and_patch(&mut openai_chunk, previous_function_arg) {
-                    return vec![];
-                }
-                let gemini_chunk: GeminiStreamChunk = openai_chunk.into();
-                if let Ok(s) = serde_json::to_string(&gemini_chunk) {
-                    return vec![(None, s)];
-                }
-            }
-            vec![]
+                ))
+            });
+            frames
         }
     }
 }
@@ -576,18 +1061,15 @@ fn handle_content_block_start_typed(
     match anthropic_delta {
         AnthropicStreamChunk::ContentBlockDelta { index: _, delta } => match delta {
             AnthropicStreamDelta::InputJsonDelta { name, id, .. } => {
-                if let (Some(name), Some(id)) = (name.as_ref(), id.as_ref()) {
-                    Some(AnthropicStreamChunk::ContentBlockStart {
-                        index,
-                        content_block: AnthropicContentBlock::ToolUse {
-                            id: id.clone(),
-                            name: name.clone(),
-                            input: json!({}),
-                        },
-                    })
-                } else {
-                    None
-                }
+                // Some OpenAI-compatible upstreams omit the call id
+                name.as_ref().map(|name| AnthropicStreamChunk::ContentBlockStart {
+                    index,
+                    content_block: AnthropicContentBlock::ToolUse {
+                        id: id.clone().unwrap_or_else(|| format!("toolu_{}", index)),
+                        name: name.clone(),
+                        input: json!({}),
+                    },
+                })
             }
             AnthropicStreamDelta::ThinkingDelta { .. } => {
                 Some(AnthropicStreamChunk::ContentBlockStart {
@@ -607,6 +1089,7 @@ fn handle_content_block_start_typed(
                     },
                 })
             }
+            AnthropicStreamDelta::Other(_) => None,
         },
         _ => None,
     }
@@ -618,18 +1101,61 @@ fn delta_kind(anthropic_delta: &AnthropicStreamChunk) -> Option<&'static str> {
             AnthropicStreamDelta::InputJsonDelta { .. } => Some("input_json_delta"),
             AnthropicStreamDelta::ThinkingDelta { .. } => Some("thinking_delta"),
             AnthropicStreamDelta::TextDelta { .. } => Some("text_delta"),
+            AnthropicStreamDelta::Other(_) => None,
         },
         AnthropicStreamChunk::MessageDelta { .. } => Some("message_delta"),
         _ => None,
     }
 }
 
+// A tool call chunk naming a call other than the open one (by OpenAI tool call
+// index, or by id when both carry one) opens a new tool_use block even when the
+// previous block was a tool call too. Upstreams that repeat the name or id on
+// every chunk of a call stay in its block.
+fn starts_tool_use(open_tool: &Option<(i32, Option<String>)>, index: i32, delta: &AnthropicStreamDelta) -> bool {
+    let AnthropicStreamDelta::InputJsonDelta { name, id, .. } = delta else { return false };
+    if name.is_none() && id.is_none() {
+        return false;
+    }
+    match open_tool {
+        None => true,
+        Some((open_index, open_id)) => *open_index != index || (id.is_some() && open_id.is_some() && id != open_id),
+    }
+}
+
+// The tool call a newly opened block belongs to, None for other blocks
+fn opened_tool(index: i32, delta: &AnthropicStreamDelta) -> Option<(i32, Option<String>)> {
+    match delta {
+        AnthropicStreamDelta::InputJsonDelta { id, .. } => Some((index, id.clone())),
+        _ => None,
+    }
+}
+
+// Emits `delta` at `index`. Tool deltas lose their name/id (already sent in the
+// block start) and are skipped when they carry no argument text, as the name/id
+// chunk usually does.
+fn push_block_delta(results: &mut Vec<(String, String)>, index: i32, delta: &AnthropicStreamDelta) {
+    let delta = match delta {
+        AnthropicStreamDelta::InputJsonDelta { partial_json, .. } => {
+            if partial_json.as_deref().is_none_or(str::is_empty) {
+                return;
+            }
+            AnthropicStreamDelta::InputJsonDelta { partial_json: partial_json.clone(), name: None, id: None }
+        }
+        other => other.clone(),
+    };
+    if let Ok(s) = serde_json::to_string(&AnthropicStreamChunk::ContentBlockDelta { index, delta }) {
+        results.push(("content_block_delta".to_string(), s));
+    }
+}
+
 /// 将 OpenAI 流式响应块转换为 Anthropic 流式事件序列（不使用 serde_json::Value 作为输入）。
 pub fn openai_to_anthropic_stream_chunks(
     chunk: &OpenAIStreamChunk,
     model: &String,
     previous_event: &mut String,
     previous_delta_type: &mut String,
+    open_tool: &mut Option<(i32, Option<String>)>,
     msg_index: &mut i32,
 ) -> Vec<(String, String)> {
     let mut results: Vec<(String, String)> = vec![];
@@ -703,24 +1229,9 @@ pub fn openai_to_anthropic_stream_chunks(
 
         // 发送对应的增量事件（设置正确 index；tool_use 去掉 id/name）
         match &base_chunk {
-            AnthropicStreamChunk::ContentBlockDelta { delta, .. } => {
-                let delta_for_emit = match delta.clone() {
-                    AnthropicStreamDelta::InputJsonDelta { partial_json, .. } => {
-                        AnthropicStreamDelta::InputJsonDelta {
-                            partial_json,
-                            name: None,
-                            id: None,
-                        }
-                    }
-                    other => other,
-                };
-                let emit = AnthropicStreamChunk::ContentBlockDelta {
-                    index: *msg_index,
-                    delta: delta_for_emit,
-                };
-                if let Ok(s) = serde_json::to_string(&emit) {
-                    results.push((event_type.to_string(), s));
-                }
+            AnthropicStreamChunk::ContentBlockDelta { index, delta } => {
+                *open_tool = opened_tool(*index, delta);
+                push_block_delta(&mut results, *msg_index, delta);
                 previous_delta_type.clear();
                 previous_delta_type.push_str(current_delta_type);
                 previous_event.clear();
@@ -741,32 +1252,16 @@ pub fn openai_to_anthropic_stream_chunks(
 
     if previous_event == "content_block_delta" {
         match &base_chunk {
-            AnthropicStreamChunk::ContentBlockDelta { delta, .. } => {
+            AnthropicStreamChunk::ContentBlockDelta { index, delta } => {
                 let new_delta_type = current_delta_type;
-                if new_delta_type == previous_delta_type {
+                if new_delta_type == previous_delta_type && !starts_tool_use(open_tool, *index, delta) {
                     // 同一内容类型，直接追加增量
-                    let delta_for_emit = match delta.clone() {
-                        AnthropicStreamDelta::InputJsonDelta { partial_json, .. } => {
-                            AnthropicStreamDelta::InputJsonDelta {
-                                partial_json,
-                                name: None,
-                                id: None,
-                            }
-                        }
-                        other => other,
-                    };
-                    let emit = AnthropicStreamChunk::ContentBlockDelta {
-                        index: *msg_index,
-                        delta: delta_for_emit,
-                    };
-                    if let Ok(s) = serde_json::to_string(&emit) {
-                        results.push(("content_block_delta".to_string(), s));
-                    }
+                    push_block_delta(&mut results, *msg_index, delta);
                 } else if matches!(
                     new_delta_type,
                     "input_json_delta" | "thinking_delta" | "text_delta"
                 ) {
-                    // 切换内容类型，先停止当前内容块
+                    // 切换内容类型或开始下一个工具调用，先停止当前内容块
                     if let Ok(s) = serde_json::to_string(&AnthropicStreamChunk::ContentBlockStop {
                         index: *msg_index,
                     }) {
@@ -782,23 +1277,8 @@ pub fn openai_to_anthropic_stream_chunks(
                     }
 
                     // 发送新的增量
-                    let delta_for_emit = match delta.clone() {
-                        AnthropicStreamDelta::InputJsonDelta { partial_json, .. } => {
-                            AnthropicStreamDelta::InputJsonDelta {
-                                partial_json,
-                                name: None,
-                                id: None,
-                            }
-                        }
-                        other => other,
-                    };
-                    let emit = AnthropicStreamChunk::ContentBlockDelta {
-                        index: *msg_index,
-                        delta: delta_for_emit,
-                    };
-                    if let Ok(s) = serde_json::to_string(&emit) {
-                        results.push(("content_block_delta".to_string(), s));
-                    }
+                    *open_tool = opened_tool(*index, delta);
+                    push_block_delta(&mut results, *msg_index, delta);
 
                     previous_delta_type.clear();
                     previous_delta_type.push_str(new_delta_type);
@@ -885,7 +1365,13 @@ mod tests {
             "test".to_string(),
             ApiType::OpenAI,
             ApiType::OpenAI,
+            true,
+            DEFAULT_MAX_RESPONSE_BYTES,
         ).await;
+        assert_eq!(
+            axum_resp.extensions().get::<crate::converters::response_wrapper::TokenUsage>(),
+            Some(&crate::converters::response_wrapper::TokenUsage { input_tokens: 170, output_tokens: 89 })
+        );
         
         let body_bytes = axum_resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body_bytes.to_vec()).unwrap();
@@ -900,6 +1386,85 @@ mod tests {
         assert_eq!(json_body["choices"][0]["message"]["tool_calls"][0]["function"]["arguments"], "{\"a\": 365, \"b\": 96}");
     }
 
+    #[tokio::test]
+    async fn test_oversized_upstream_body_is_rejected() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let body = json!({"id": "x", "padding": "a".repeat(2000)}).to_string();
+        let _sized = server.mock("POST", "/sized").with_body(body.clone()).create();
+        let _chunked = server
+            .mock("POST", "/chunked")
+            .with_chunked_body(move |w| w.write_all(body.as_bytes()))
+            .create();
+
+        for path in ["sized", "chunked"] {
+            let response = reqwest::Client::new().post(format!("{}/{}", url, path)).send().await.expect("request failed");
+            let axum_resp = handle_non_streaming_response(response, "test".to_string(), ApiType::OpenAI, ApiType::OpenAI, true, 1024).await;
+            assert_eq!(axum_resp.status(), 502, "{}", path);
+            let body_bytes = axum_resp.into_body().collect().await.unwrap().to_bytes();
+            let json_body: Value = serde_json::from_slice(&body_bytes).unwrap();
+            assert_eq!(json_body["error"]["code"], "response_too_large");
+            assert!(json_body["error"]["message"].as_str().unwrap().contains("1024 byte limit"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_upstream_body_becomes_empty_completion() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let _m = server.mock("POST", "/test").with_status(204).create();
+
+        let response = reqwest::Client::new().post(format!("{}/test", url)).send().await.expect("request failed");
+        let axum_resp = handle_non_streaming_response(response, "test".to_string(), ApiType::OpenAI, ApiType::OpenAI, true, DEFAULT_MAX_RESPONSE_BYTES).await;
+        assert_eq!(axum_resp.status(), 200);
+        assert_eq!(axum_resp.headers()["x-llm-router-empty-upstream"], "204");
+
+        let body_bytes = axum_resp.into_body().collect().await.unwrap().to_bytes();
+        let json_body: Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(json_body["model"], "test");
+        assert_eq!(json_body["choices"][0]["finish_reason"], "content_filter");
+        assert_eq!(json_body["choices"][0]["message"]["content"], "");
+    }
+
+
+    #[tokio::test]
+    async fn test_content_filters_map_across_formats() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let _m = server
+            .mock("POST", "/gemini")
+            .with_body(json!({
+                "candidates": [{"content": {"role": "model", "parts": [{"text": "I can"}]}, "finishReason": "SAFETY"}],
+                "usageMetadata": {"promptTokenCount": 5, "candidatesTokenCount": 2, "totalTokenCount": 7}
+            }).to_string())
+            .create();
+        let _b = server.mock("POST", "/blocked").with_body(json!({"promptFeedback": {"blockReason": "SAFETY"}}).to_string()).create();
+
+        for (path, client, finish_field, finish, scope) in [
+            ("/gemini", ApiType::Anthropic, "/stop_reason", "refusal", "output"),
+            ("/gemini", ApiType::OpenAI, "/choices/0/finish_reason", "content_filter", "output"),
+            ("/blocked", ApiType::OpenAI, "/choices/0/finish_reason", "content_filter", "prompt"),
+        ] {
+            let response = reqwest::Client::new().post(format!("{}{}", url, path)).send().await.expect("request failed");
+            let axum_resp = handle_non_streaming_response(response, "test".to_string(), ApiType::Gemini, client, true, DEFAULT_MAX_RESPONSE_BYTES).await;
+            assert_eq!(
+                axum_resp.headers()[helpers::FINISH_DETAIL_HEADER],
+                format!("content_filter; provider=gemini; reason=SAFETY; scope={}", scope).as_str()
+            );
+            let body_bytes = axum_resp.into_body().collect().await.unwrap().to_bytes();
+            let json_body: Value = serde_json::from_slice(&body_bytes).unwrap();
+            assert_eq!(json_body.pointer(finish_field).unwrap(), finish);
+        }
+
+        // OpenAI's filter becomes Anthropic's refusal and back
+        assert_eq!(helpers::map_openai_finish_reason_to_anthropic(&json!("content_filter")), "refusal");
+        assert_eq!(helpers::map_anthropic_stop_reason_to_openai(Some(&json!("refusal"))), "content_filter");
+        assert_eq!(helpers::content_filter_detail(&ApiType::Gemini, r#"{"candidates": [{"finishReason": "STOP"}]}"#), None);
+        assert_eq!(
+            helpers::content_filter_detail(&ApiType::Gemini, r#"{"candidates": [{"finishReason": "RECITATION"}]}"#).as_deref(),
+            Some("content_filter; provider=gemini; reason=RECITATION; scope=output")
+        );
+    }
 
     #[tokio::test]
     async fn test_openai_to_anthropic_response() {
@@ -956,6 +1521,8 @@ mod tests {
             "test".to_string(),
             ApiType::OpenAI,
             ApiType::Anthropic,
+            true,
+            DEFAULT_MAX_RESPONSE_BYTES,
         ).await;
         
         let body_bytes = axum_resp.into_body().collect().await.unwrap().to_bytes();
@@ -963,7 +1530,8 @@ mod tests {
         let json_body: Value = serde_json::from_str(&body_str).unwrap();
 
         assert_eq!(json_body["model"], "test");
-        assert_eq!(json_body["usage"]["input_tokens"], 170);
+        assert_eq!(json_body["usage"]["input_tokens"], 127);
+        assert_eq!(json_body["usage"]["cache_read_input_tokens"], 43);
         assert_eq!(json_body["stop_reason"], "tool_use");
         assert_eq!(json_body["content"][0]["thinking"], "use function");
         assert_eq!(json_body["content"][1]["text"], "\nI'll calculate 365 + 96 for you.\n");
@@ -1022,6 +1590,8 @@ mod tests {
             "test".to_string(),
             ApiType::Anthropic,
             ApiType::Anthropic,
+            true,
+            DEFAULT_MAX_RESPONSE_BYTES,
         ).await;
         
         let body_bytes = axum_resp.into_body().collect().await.unwrap().to_bytes();
@@ -1088,6 +1658,8 @@ mod tests {
             "test".to_string(),
             ApiType::Anthropic,
             ApiType::OpenAI,
+            true,
+            DEFAULT_MAX_RESPONSE_BYTES,
         ).await;
         
         let body_bytes = axum_resp.into_body().collect().await.unwrap().to_bytes();
@@ -1161,6 +1733,8 @@ mod tests {
             "test".to_string(),
             ApiType::Gemini,
             ApiType::Gemini,
+            true,
+            DEFAULT_MAX_RESPONSE_BYTES,
         )
         .await;
 
@@ -1223,6 +1797,8 @@ mod tests {
             "test".to_string(),
             ApiType::Gemini,
             ApiType::OpenAI,
+            true,
+            DEFAULT_MAX_RESPONSE_BYTES,
         )
         .await;
 
@@ -1290,6 +1866,8 @@ mod tests {
             "test".to_string(),
             ApiType::Gemini,
             ApiType::Anthropic,
+            true,
+            DEFAULT_MAX_RESPONSE_BYTES,
         )
         .await;
 
@@ -1362,6 +1940,8 @@ mod tests {
             "test".to_string(),
             ApiType::OpenAI,
             ApiType::Gemini,
+            true,
+            DEFAULT_MAX_RESPONSE_BYTES,
         )
         .await;
 
@@ -1434,6 +2014,8 @@ mod tests {
             "test".to_string(),
             ApiType::Anthropic,
             ApiType::Gemini,
+            true,
+            DEFAULT_MAX_RESPONSE_BYTES,
         )
         .await;
 
@@ -1515,6 +2097,7 @@ mod tests {
             "test".to_string(),
             ApiType::OpenAI,
             ApiType::OpenAI,
+            StreamOptions::default(),
         ).await;
         let body = resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
@@ -1552,6 +2135,7 @@ mod tests {
             "test".to_string(),
             ApiType::Anthropic,
             ApiType::Anthropic,
+            StreamOptions::default(),
         ).await;
         let body = resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
@@ -1563,6 +2147,43 @@ mod tests {
         assert_eq!(v["message"]["model"], "test");
     }
 
+    #[tokio::test]
+    async fn test_stream_anthropic_error_event_maps_to_client_format() {
+        let upstream = "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n\
+            event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n";
+        let collect = |target: ApiType| async move {
+            let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let sink = reported.clone();
+            let options = StreamOptions {
+                on_upstream_error: Some(UpstreamErrorHook(Arc::new(move |kind, _| sink.lock().unwrap().push(kind.to_string())))),
+                ..Default::default()
+            };
+            let s = stream::iter(vec![Ok::<_, reqwest::Error>(Bytes::from(upstream))]);
+            let resp = handle_streaming_response(s, "test".to_string(), ApiType::Anthropic, target, options).await;
+            let body = resp.into_body().collect().await.unwrap().to_bytes();
+            let reported = reported.lock().unwrap().clone();
+            (String::from_utf8(body.to_vec()).unwrap(), reported)
+        };
+
+        let (body, reported) = collect(ApiType::OpenAI).await;
+        assert_eq!(reported, vec!["overloaded_error"]);
+        let chunks = extract_sse_data_json_chunks(&body);
+        let error: Value = serde_json::from_str(chunks.last().unwrap()).unwrap();
+        assert_eq!(error["error"]["type"], "overloaded_error");
+        assert_eq!(error["error"]["message"], "Overloaded");
+        assert_eq!(body.matches("data: [DONE]").count(), 1);
+
+        let (body, _) = collect(ApiType::Gemini).await;
+        let chunks = extract_sse_data_json_chunks(&body);
+        let error: Value = serde_json::from_str(chunks.last().unwrap()).unwrap();
+        assert_eq!(error["error"]["code"], 503);
+        assert_eq!(error["error"]["status"], "UNAVAILABLE");
+
+        let (body, _) = collect(ApiType::Anthropic).await;
+        let data = find_event_data(&body, "error").expect("error event passed through");
+        assert!(data.contains("overloaded_error"));
+    }
+
     #[tokio::test]
     async fn test_stream_anthropic_to_openai_content_delta() {
         // Anthropic content_block_delta (text) -> OpenAI chunk with delta.content
@@ -1580,6 +2201,7 @@ mod tests {
             "test".to_string(),
             ApiType::Anthropic,
             ApiType::OpenAI,
+            StreamOptions::default(),
         ).await;
         let body = resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
@@ -1591,6 +2213,212 @@ mod tests {
         // model is overridden in openai->openai path; here we convert from anthropic and model can be default
     }
 
+    #[tokio::test]
+    async fn test_stream_chunks_share_id_and_created() {
+        let _frozen = crate::utils::clock::freeze(1_700_000_000);
+        let delta = |text: &str| json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": text}});
+        let s = stream::iter(vec![
+            Ok(Bytes::from(format!("data: {}\n", delta("Hel")))),
+            Ok(Bytes::from(format!("data: {}\n", delta("lo")))),
+        ]);
+
+        let resp = handle_streaming_response(s, "test".to_string(), ApiType::Anthropic, ApiType::OpenAI, StreamOptions::default()).await;
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+
+        let frames = extract_sse_data_json_chunks(&body_str);
+        assert_eq!(frames.len(), 2);
+        for frame in &frames {
+            let v: Value = serde_json::from_str(frame).unwrap();
+            // No message_start reached us, so the id is synthesized once for the whole stream
+            assert_eq!(v["id"], "chatcmpl-1");
+            assert_eq!(v["created"], 1_700_000_000);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_terminator_ensure_and_suppress() {
+        let anthropic_chunk = json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": { "type": "text_delta", "text": "Hi" }
+        });
+        let line = format!("data: {}\n", serde_json::to_string(&anthropic_chunk).unwrap());
+
+        // Anthropic upstream never sends [DONE]; ensure mode adds exactly one for OpenAI clients
+        let s = stream::iter(vec![Ok(Bytes::from(line.clone()))]);
+        let resp = handle_streaming_response(
+            s,
+            "test".to_string(),
+            ApiType::Anthropic,
+            ApiType::OpenAI,
+            StreamOptions::default(),
+        ).await;
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(body_str.matches("data: [DONE]").count(), 1);
+
+        // Suppress mode drops upstream [DONE] frames
+        let s = stream::iter(vec![
+            Ok(Bytes::from("data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"m\",\"choices\":[]}\n")),
+            Ok(Bytes::from("data: [DONE]\n")),
+        ]);
+        let resp = handle_streaming_response(
+            s,
+            "test".to_string(),
+            ApiType::OpenAI,
+            ApiType::OpenAI,
+            StreamOptions { terminator: TerminatorMode::Suppress, ..Default::default() },
+        ).await;
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        assert!(!body_str.contains("[DONE]"));
+
+        // A stream that ended in an error is not closed as if it were complete
+        let error = json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}});
+        let s = stream::iter(vec![
+            Ok(Bytes::from(line.clone())),
+            Ok(Bytes::from(format!("event: error\ndata: {}\n", error))),
+        ]);
+        let resp = handle_streaming_response(s, "test".to_string(), ApiType::Anthropic, ApiType::Anthropic, StreamOptions::default()).await;
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body_str.contains("overloaded_error"));
+        assert!(!body_str.contains("message_stop"));
+    }
+
+    #[tokio::test]
+    async fn test_gemini_stream_as_json_array() {
+        let chunk = |text: &str| json!({"candidates": [{"content": {"role": "model", "parts": [{"text": text}]}, "index": 0}]});
+        let s = stream::iter(vec![
+            Ok(Bytes::from(format!("data: {}\n\n", chunk("Hel")))),
+            Ok(Bytes::from(format!("data: {}\n\n", chunk("lo")))),
+        ]);
+        let resp = handle_streaming_response(s, "test".to_string(), ApiType::Gemini, ApiType::Gemini, StreamOptions::default()).await;
+        let resp = sse_to_json_array(resp);
+        assert_eq!(resp.headers()["content-type"], "application/json");
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let chunks: Vec<Value> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1]["candidates"][0]["content"]["parts"][0]["text"], "lo");
+    }
+
+    #[tokio::test]
+    async fn test_stream_truncated_tool_arguments_repaired() {
+        let chunk = |delta: Value| format!(
+            "data: {}\n",
+            json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1,
+                "model": "gpt-4",
+                "choices": [ { "index": 0, "delta": delta, "finish_reason": null } ]
+            })
+        );
+        let lines = vec![
+            chunk(json!({ "tool_calls": [ { "index": 0, "id": "call_1", "type": "function",
+                "function": { "name": "get_weather", "arguments": "{\"city\": " } } ] })),
+            chunk(json!({ "tool_calls": [ { "index": 0, "type": "function",
+                "function": { "arguments": "\"Par" } } ] })),
+        ];
+        let run = |repair: bool| {
+            let s = stream::iter(lines.clone().into_iter().map(|l| Ok(Bytes::from(l))));
+            handle_streaming_response(
+                s,
+                "test".to_string(),
+                ApiType::OpenAI,
+                ApiType::Gemini,
+                StreamOptions { repair_tool_arguments: repair, ..Default::default() },
+            )
+        };
+
+        // Without repair the incomplete call is still flushed, with the arguments as received
+        let body = run(false).await.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let frames = extract_sse_data_json_chunks(&body_str);
+        assert_eq!(frames.len(), 1);
+        let v: Value = serde_json::from_str(&frames[0]).unwrap();
+        assert_eq!(v["candidates"][0]["content"]["parts"][0]["functionCall"]["args"], "{\"city\": \"Par");
+        assert!(v.get("routerWarning").is_none());
+
+        let body = run(true).await.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let frames = extract_sse_data_json_chunks(&body_str);
+        assert_eq!(frames.len(), 1);
+        let v: Value = serde_json::from_str(&frames[0]).unwrap();
+        let call = &v["candidates"][0]["content"]["parts"][0]["functionCall"];
+        assert_eq!(call["name"], "get_weather");
+        assert_eq!(call["args"], json!({ "city": "Par" }));
+        assert_eq!(v["routerWarning"], "truncated_tool_arguments_repaired");
+    }
+
+    #[tokio::test]
+    async fn test_stream_tool_arguments_modes() {
+        let chunk = |delta: Value, finish: Value| format!(
+            "data: {}\n",
+            json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1,
+                "model": "gpt-4",
+                "choices": [ { "index": 0, "delta": delta, "finish_reason": finish } ]
+            })
+        );
+        // Only the first fragment names the call, as OpenAI streams do
+        let lines = vec![
+            chunk(json!({ "tool_calls": [ { "index": 0, "id": "call_1", "type": "function",
+                "function": { "name": "get_weather", "arguments": "" } } ] }), Value::Null),
+            chunk(json!({ "tool_calls": [ { "index": 0, "function": { "arguments": "{\"city\":" } } ] }), Value::Null),
+            chunk(json!({ "tool_calls": [ { "index": 0, "function": { "arguments": " \"Paris\"}" } } ] }), Value::Null),
+        ];
+        let run = |target: ApiType, mode: ToolArgumentsMode, lines: Vec<String>| {
+            let s = stream::iter(lines.into_iter().map(|l| Ok(Bytes::from(l))));
+            handle_streaming_response(
+                s,
+                "test".to_string(),
+                ApiType::OpenAI,
+                target,
+                StreamOptions { tool_arguments: Some(mode), ..Default::default() },
+            )
+        };
+
+        // Aggregated for an Anthropic client: one input_json_delta with the whole object
+        let body = run(ApiType::Anthropic, ToolArgumentsMode::Aggregate, lines.clone())
+            .await.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let start = find_event_data(&body_str, "content_block_start").unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&start).unwrap()["content_block"]["name"], "get_weather");
+        let deltas: Vec<Value> = body_str
+            .split("\n\n")
+            .filter_map(|section| section.lines().find_map(|l| l.strip_prefix("data: ")))
+            .filter_map(|d| serde_json::from_str::<Value>(d).ok())
+            .filter(|v| v["delta"]["type"] == "input_json_delta" && v["delta"]["partial_json"] != "")
+            .collect();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0]["delta"]["partial_json"], "{\"city\": \"Paris\"}");
+
+        // Passthrough for a Gemini client: every fragment goes out as it arrives
+        let body = run(ApiType::Gemini, ToolArgumentsMode::Passthrough, lines.clone())
+            .await.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(extract_sse_data_json_chunks(&body_str).len(), 3);
+
+        // A finish flushes the buffered call even though its arguments never completed
+        let mut truncated = lines[..2].to_vec();
+        truncated.push(chunk(json!({}), json!("tool_calls")));
+        let body = run(ApiType::OpenAI, ToolArgumentsMode::Aggregate, truncated)
+            .await.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let frames = extract_sse_data_json_chunks(&body_str);
+        assert_eq!(frames.len(), 2);
+        let v: Value = serde_json::from_str(&frames[0]).unwrap();
+        let call = &v["choices"][0]["delta"]["tool_calls"][0];
+        assert_eq!(call["function"]["name"], "get_weather");
+        assert_eq!(call["function"]["arguments"], "{\"city\":");
+        let v: Value = serde_json::from_str(&frames[1]).unwrap();
+        assert_eq!(v["choices"][0]["finish_reason"], "tool_calls");
+    }
+
     #[tokio::test]
     async fn test_stream_openai_to_anthropic_sequence() {
         // OpenAI text delta should expand to message_start + content_block_start(text) + content_block_delta
@@ -1610,6 +2438,7 @@ mod tests {
             "test".to_string(),
             ApiType::OpenAI,
             ApiType::Anthropic,
+            StreamOptions::default(),
         ).await;
         let body = resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
@@ -1666,6 +2495,7 @@ mod tests {
             "test".to_string(),
             ApiType::OpenAI,
             ApiType::Anthropic,
+            StreamOptions::default(),
         ).await;
         let body = resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
@@ -1732,6 +2562,7 @@ mod tests {
             "test".to_string(),
             ApiType::OpenAI,
             ApiType::Anthropic,
+            StreamOptions::default(),
         ).await;
         let body = resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
@@ -1748,6 +2579,75 @@ mod tests {
         assert_eq!(v_delta["delta"]["partial_json"], "{\"a\":1}");
     }
 
+    #[tokio::test]
+    async fn test_stream_openai_to_anthropic_tool_call_split_across_chunks() {
+        // Name/id arrive alone; arguments follow in fragments, one repeating the name/id
+        // as some upstreams do; a second call starts a new block
+        let tool_chunk = |tool_call: Value| json!({
+            "id": "chatcmpl-3", "object": "chat.completion.chunk", "created": 42, "model": "gpt-4",
+            "choices": [ { "index": 0, "delta": { "tool_calls": [tool_call] }, "finish_reason": null } ]
+        });
+        let chunks = vec![
+            tool_chunk(json!({"index": 0, "id": "call_1", "type": "function", "function": {"name": "add", "arguments": ""}})),
+            tool_chunk(json!({"index": 0, "function": {"arguments": "{\"a\":"}})),
+            tool_chunk(json!({"index": 0, "id": "call_1", "function": {"name": "add", "arguments": "1}"}})),
+            tool_chunk(json!({"index": 1, "id": "call_2", "type": "function", "function": {"name": "mul"}})),
+            tool_chunk(json!({"index": 1, "function": {"arguments": "{}"}})),
+            json!({
+                "id": "chatcmpl-3", "object": "chat.completion.chunk", "created": 42, "model": "gpt-4",
+                "choices": [ { "index": 0, "delta": {}, "finish_reason": "tool_calls" } ]
+            }),
+        ];
+        let s = stream::iter(
+            chunks
+                .into_iter()
+                .map(|c| Ok(Bytes::from(format!("data: {}\n", c))))
+                .collect::<Vec<_>>(),
+        );
+
+        let resp = handle_streaming_response(
+            s,
+            "test".to_string(),
+            ApiType::OpenAI,
+            ApiType::Anthropic,
+            StreamOptions::default(),
+        ).await;
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+
+        let events: Vec<Value> = body_str
+            .lines()
+            .filter_map(|l| l.strip_prefix("data: "))
+            .map(|d| serde_json::from_str(d).unwrap())
+            .collect();
+        let kinds: Vec<String> = events
+            .iter()
+            .map(|v| match v["type"].as_str().unwrap() {
+                "content_block_start" => format!("start:{}:{}", v["index"], v["content_block"]["name"].as_str().unwrap()),
+                "content_block_delta" => format!("delta:{}:{}", v["index"], v["delta"]["partial_json"].as_str().unwrap()),
+                "content_block_stop" => format!("stop:{}", v["index"]),
+                other => other.to_string(),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "message_start",
+                "start:0:add",
+                "delta:0:{\"a\":",
+                "delta:0:1}",
+                "stop:0",
+                "start:1:mul",
+                "delta:1:{}",
+                "stop:1",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(events[1]["content_block"]["id"], "call_1");
+        assert_eq!(events[5]["content_block"]["id"], "call_2");
+    }
+
     #[tokio::test]
     async fn test_stream_openai_to_openai_only_done_when_no_json() {
         // Provide only [DONE] and a malformed JSON frame
@@ -1761,6 +2661,7 @@ mod tests {
             "test".to_string(),
             ApiType::OpenAI,
             ApiType::OpenAI,
+            StreamOptions::default(),
         ).await;
         let body = resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
@@ -1826,6 +2727,7 @@ data: [DONE]
             "test".to_string(),
             ApiType::OpenAI,
             ApiType::Anthropic,
+            StreamOptions::default(),
         ).await;
         let body = resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
@@ -1901,7 +2803,8 @@ data: [DONE]
         let msg_delta = find_event_data(&body_str, "message_delta").expect("message_delta not found");
         let v_msg_delta: Value = serde_json::from_str(&msg_delta).unwrap();
         assert_eq!(v_msg_delta["delta"]["stop_reason"], "tool_use");
-        assert_eq!(v_msg_delta["usage"]["input_tokens"], 170);
+        assert_eq!(v_msg_delta["usage"]["input_tokens"], 127);
+        assert_eq!(v_msg_delta["usage"]["cache_read_input_tokens"], 43);
         assert_eq!(v_msg_delta["usage"]["output_tokens"], 72);
 
         // 6) Ends with message_stop
@@ -2000,6 +2903,7 @@ data: {"type":"message_stop"}
             "test".to_string(),
             ApiType::Anthropic,
             ApiType::OpenAI,
+            StreamOptions::default(),
         ).await;
         let body = resp.into_body().collect().await.unwrap().to_bytes();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
@@ -2074,6 +2978,7 @@ data: {"type":"message_stop"}
             &model,
             &mut previous_event,
             &mut previous_delta_type,
+            &mut None,
             &mut msg_index,
         );
 
@@ -2159,6 +3064,7 @@ data: {"type":"message_stop"}
             &model,
             &mut previous_event,
             &mut previous_delta_type,
+            &mut None,
             &mut msg_index,
         );
 
@@ -2232,6 +3138,7 @@ data: {"type":"message_stop"}
             &model,
             &mut previous_event,
             &mut previous_delta_type,
+            &mut None,
             &mut msg_index,
         );
 
@@ -2315,6 +3222,7 @@ data: {"type":"message_stop"}
             &model,
             &mut previous_event,
             &mut previous_delta_type,
+            &mut None,
             &mut msg_index,
         );
 
@@ -2388,6 +3296,7 @@ data: {"type":"message_stop"}
             &model,
             &mut previous_event,
             &mut previous_delta_type,
+            &mut None,
             &mut msg_index,
         );
 
@@ -2451,6 +3360,7 @@ data: {"type":"message_stop"}
             &model,
             &mut previous_event,
             &mut previous_delta_type,
+            &mut None,
             &mut msg_index,
         );
 
@@ -2466,4 +3376,98 @@ data: {"type":"message_stop"}
         assert_eq!(previous_delta_type, "");
         assert_eq!(msg_index, 0);
     }
+
+    #[tokio::test]
+    async fn test_collect_streaming_response_returns_partial_after_soft_deadline() {
+        let chunks = [
+            json!({"id": "chatcmpl-1", "object": "chat.completion.chunk", "created": 1, "model": "gpt-4",
+                "choices": [{"index": 0, "delta": {"role": "assistant", "content": "Hello"}, "finish_reason": null}]}),
+            json!({"id": "chatcmpl-1", "object": "chat.completion.chunk", "created": 1, "model": "gpt-4",
+                "choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "id": "call_1", "type": "function",
+                    "function": {"name": "lookup", "arguments": "{\"q\": \"ru"}}]}, "finish_reason": null}]}),
+        ];
+        let sse: Vec<Result<Bytes, reqwest::Error>> =
+            chunks.iter().map(|c| Ok(Bytes::from(format!("data: {}\n\n", c)))).collect();
+        // The upstream stalls after two chunks
+        let upstream = stream::iter(sse).chain(stream::pending());
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(50);
+
+        let response =
+            collect_streaming_response(upstream, "gpt-4".to_string(), ApiType::OpenAI, ApiType::Anthropic, deadline, DEFAULT_MAX_RESPONSE_BYTES).await;
+        assert_eq!(response.headers()["x-llm-router-partial"], "true");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["stop_reason"], "max_tokens");
+        assert_eq!(body["content"][0]["text"], "Hello");
+        assert_eq!(body["content"][1]["name"], "lookup");
+        assert_eq!(body["content"][1]["input"], json!({"q": "ru"}));
+
+        // A stream that ends in time is returned whole
+        let done = format!("data: {}\n\ndata: [DONE]\n\n", json!({"id": "chatcmpl-2", "object": "chat.completion.chunk",
+            "created": 1, "model": "gpt-4", "choices": [{"index": 0, "delta": {"content": "Hi"}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4}}));
+        let upstream = stream::iter(vec![Ok(Bytes::from(done.clone()))]);
+        let response =
+            collect_streaming_response(upstream, "gpt-4".to_string(), ApiType::OpenAI, ApiType::OpenAI, deadline, DEFAULT_MAX_RESPONSE_BYTES).await;
+        assert!(response.headers().get("x-llm-router-partial").is_none());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["id"], "chatcmpl-2");
+        assert_eq!(body["choices"][0]["message"]["content"], "Hi");
+        assert_eq!(body["choices"][0]["finish_reason"], "stop");
+        assert_eq!(body["usage"]["total_tokens"], 4);
+
+        // An error event mid-answer fails the request instead of returning the text so far
+        let failed = format!(
+            "event: content_block_delta\ndata: {}\n\nevent: error\ndata: {}\n\n",
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hel"}}),
+            json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}})
+        );
+        let upstream = stream::iter(vec![Ok(Bytes::from(failed))]);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        let response =
+            collect_streaming_response(upstream, "claude".to_string(), ApiType::Anthropic, ApiType::OpenAI, deadline, DEFAULT_MAX_RESPONSE_BYTES).await;
+        assert_eq!(response.status(), 502);
+
+        // A stream larger than the limit fails rather than growing without bound
+        let upstream = stream::iter(vec![Ok(Bytes::from(done.clone())), Ok(Bytes::from(done.clone()))]);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        let response =
+            collect_streaming_response(upstream, "gpt-4".to_string(), ApiType::OpenAI, ApiType::OpenAI, deadline, done.len() as u64).await;
+        assert_eq!(response.status(), 502);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("response_too_large"));
+    }
+
+    #[tokio::test]
+    async fn test_audio_output_passes_through_and_collects() {
+        let chunk = |delta: Value, finish: Value| {
+            json!({"id": "chatcmpl-a", "object": "chat.completion.chunk", "created": 1, "model": "gpt-4o-audio-preview",
+                "choices": [{"index": 0, "delta": delta, "finish_reason": finish}]})
+        };
+        let chunks = [
+            chunk(json!({"role": "assistant", "content": null, "audio": {"id": "audio_1", "transcript": "Hel"}}), Value::Null),
+            chunk(json!({"audio": {"data": "UklG", "transcript": "lo"}}), Value::Null),
+            chunk(json!({"audio": {"data": "RiQA", "expires_at": 1729018505}}), json!("stop")),
+        ];
+        let sse = || -> Vec<Result<Bytes, reqwest::Error>> {
+            chunks.iter().map(|c| Ok(Bytes::from(format!("data: {}\n\n", c)))).collect()
+        };
+
+        let resp = handle_streaming_response(stream::iter(sse()), "test".to_string(), ApiType::OpenAI, ApiType::OpenAI, StreamOptions::default()).await;
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let frames = extract_sse_data_json_chunks(std::str::from_utf8(&body).unwrap());
+        let deltas: Vec<Value> = frames.iter().map(|f| serde_json::from_str::<Value>(f).unwrap()["choices"][0]["delta"]["audio"].clone()).collect();
+        assert_eq!(deltas[0], json!({"id": "audio_1", "transcript": "Hel"}));
+        assert_eq!(deltas[1]["data"], "UklG");
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        let response = collect_streaming_response(stream::iter(sse()), "test".to_string(), ApiType::OpenAI, ApiType::OpenAI, deadline, DEFAULT_MAX_RESPONSE_BYTES).await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["choices"][0]["message"]["audio"],
+            json!({"id": "audio_1", "data": "UklGRiQA", "transcript": "Hello", "expires_at": 1729018505})
+        );
+    }
 }
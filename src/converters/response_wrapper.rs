@@ -12,5 +12,30 @@ pub enum ResponseWrapper {
     Gemini(GeminiResponse),
 }
 
+/// Token counts reported by the upstream, attached to converted responses as an extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
 impl ResponseWrapper {
+    pub fn token_usage(&self) -> Option<TokenUsage> {
+        match self {
+            ResponseWrapper::OpenAI(resp) => resp.usage.as_ref().map(|u| TokenUsage {
+                input_tokens: u.prompt_tokens as u64,
+                output_tokens: u.completion_tokens as u64,
+            }),
+            // Cache reads and writes are billed as input
+            ResponseWrapper::Anthropic(resp) => resp.usage.as_ref().map(|u| TokenUsage {
+                input_tokens: u.prompt_tokens() as u64,
+                output_tokens: u.output_tokens as u64,
+            }),
+            // Thinking tokens are billed as output
+            ResponseWrapper::Gemini(resp) => resp.usage_metadata.as_ref().map(|u| TokenUsage {
+                input_tokens: u.prompt_token_count.unwrap_or(0) as u64,
+                output_tokens: (u.candidates_token_count.unwrap_or(0) + u.thoughts_token_count.unwrap_or(0)) as u64,
+            }),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,183 @@
+use crate::models::{ErrorDetail, ErrorResponse};
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+
+/// Coarse error class used for metrics and retry decisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Client,
+    RateLimited,
+    UpstreamServer,
+    Conversion,
+    Timeout,
+    Internal,
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 6] = [
+        ErrorKind::Client,
+        ErrorKind::RateLimited,
+        ErrorKind::UpstreamServer,
+        ErrorKind::Conversion,
+        ErrorKind::Timeout,
+        ErrorKind::Internal,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Client => "client",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::UpstreamServer => "upstream_server",
+            ErrorKind::Conversion => "conversion",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Internal => "internal",
+        }
+    }
+
+    /// Whether another attempt (same or different upstream) may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorKind::RateLimited | ErrorKind::UpstreamServer | ErrorKind::Timeout)
+    }
+}
+
+#[derive(Debug)]
+pub enum RouterError {
+    /// Rejected by the router itself (bad request, unknown model, auth)
+    Client { status: StatusCode, code: &'static str, message: String },
+    /// Upstream answered with a non-success status; body is passed through as-is
+    Upstream { status: StatusCode, content_type: Option<HeaderValue>, body: Bytes },
+    /// Upstream could not be reached or the connection broke
+    Transport(String),
+    /// Upstream did not answer in time
+    Timeout(String),
+    /// Local concurrency limit for the model reached; request not sent
+    Overloaded(String),
+    /// Upstream response could not be read or converted to the client format
+    Conversion { code: &'static str, message: String },
+    /// The router itself failed while handling the request (e.g. a handler panicked)
+    Internal(String),
+}
+
+impl RouterError {
+    pub fn client(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        RouterError::Client { status, code, message: message.into() }
+    }
+
+    pub fn conversion(code: &'static str, message: impl Into<String>) -> Self {
+        RouterError::Conversion { code, message: message.into() }
+    }
+
+    pub fn from_reqwest(e: &reqwest::Error) -> Self {
+        if e.is_timeout() {
+            RouterError::Timeout(format!("Upstream request timed out: {}", e))
+        } else {
+            RouterError::Transport(format!("Failed to send request: {}", e))
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            RouterError::Client { .. } => ErrorKind::Client,
+            RouterError::Upstream { status, .. } => {
+                if *status == StatusCode::TOO_MANY_REQUESTS {
+                    ErrorKind::RateLimited
+                } else if status.is_server_error() {
+                    ErrorKind::UpstreamServer
+                } else {
+                    ErrorKind::Client
+                }
+            }
+            RouterError::Transport(_) => ErrorKind::UpstreamServer,
+            RouterError::Timeout(_) => ErrorKind::Timeout,
+            RouterError::Overloaded(_) => ErrorKind::RateLimited,
+            RouterError::Conversion { .. } => ErrorKind::Conversion,
+            RouterError::Internal(_) => ErrorKind::Internal,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            RouterError::Client { status, .. } => *status,
+            RouterError::Upstream { status, .. } => *status,
+            RouterError::Transport(_) => StatusCode::BAD_GATEWAY,
+            RouterError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            RouterError::Overloaded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            RouterError::Conversion { .. } => StatusCode::BAD_GATEWAY,
+            RouterError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The `error` object of the response body.
+    pub(crate) fn detail(&self) -> ErrorDetail {
+        let (message, r#type, code) = match self {
+            RouterError::Client { code, message, .. } => (message.clone(), "invalid_request_error", *code),
+            RouterError::Upstream { status, .. } => (format!("Upstream returned {}", status), "api_error", "upstream_error"),
+            RouterError::Transport(message) => (message.clone(), "api_error", "request_failed"),
+            RouterError::Timeout(message) => (message.clone(), "timeout_error", "upstream_timeout"),
+            RouterError::Overloaded(message) => (message.clone(), "overloaded_error", "model_overloaded"),
+            RouterError::Conversion { code, message } => (message.clone(), "api_error", *code),
+            RouterError::Internal(message) => (message.clone(), "api_error", "internal_error"),
+        };
+        ErrorDetail { message, r#type: r#type.to_string(), code: Some(code.to_string()) }
+    }
+}
+
+impl std::fmt::Display for RouterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.detail().message, self.kind().as_str())
+    }
+}
+
+impl IntoResponse for RouterError {
+    fn into_response(self) -> Response {
+        let kind = self.kind();
+        let status = self.status();
+        let mut resp = match self {
+            RouterError::Upstream { content_type, body, .. } => {
+                let mut resp = (status, body).into_response();
+                if let Some(ct) = content_type { resp.headers_mut().insert(CONTENT_TYPE, ct); }
+                resp
+            }
+            other => (status, Json(ErrorResponse { error: other.detail() })).into_response(),
+        };
+        // Let outer layers (metrics) see the classification without re-parsing the body
+        resp.extensions_mut().insert(kind);
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_status_classification() {
+        let upstream = |code: u16| RouterError::Upstream {
+            status: StatusCode::from_u16(code).unwrap(),
+            content_type: None,
+            body: Bytes::new(),
+        };
+        assert_eq!(upstream(429).kind(), ErrorKind::RateLimited);
+        assert_eq!(upstream(503).kind(), ErrorKind::UpstreamServer);
+        assert_eq!(upstream(400).kind(), ErrorKind::Client);
+        assert!(upstream(429).is_retryable());
+        assert!(upstream(500).is_retryable());
+        assert!(!upstream(401).is_retryable());
+        assert!(!RouterError::conversion("deserialize_error", "bad").is_retryable());
+    }
+
+    #[test]
+    fn test_into_response_carries_kind() {
+        let resp = RouterError::Timeout("slow".to_string()).into_response();
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(resp.extensions().get::<ErrorKind>(), Some(&ErrorKind::Timeout));
+    }
+}
@@ -0,0 +1,387 @@
+//! Optional gRPC front end (feature `grpc`) for internal clients. The service in
+//! `proto/llm_router.proto` wraps the HTTP bodies, so requests go through the same
+//! `route_chat` pipeline. Messages and routing are written out by hand because
+//! the build does not run protoc; keep them in sync with the proto file.
+
+use crate::auth::AppState;
+use crate::config::ApiType;
+use crate::converters::request_wrapper::RequestWrapper;
+use crate::error::ErrorKind;
+use crate::request_id::RequestId;
+use crate::router::route_chat;
+use crate::stream_fence::frame_end;
+use crate::{loop_guard, panic_guard};
+use axum::body::Bytes;
+use axum::http::StatusCode;
+use futures::{FutureExt, Stream, StreamExt, stream};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use tonic::codegen::{Body, BoxFuture, Context, Poll, Service, StdError, http};
+use tonic::server::{Grpc, NamedService};
+use tonic::{Code, Status};
+use tonic_prost::ProstCodec;
+use tracing::debug;
+
+const CHAT_COMPLETION: &str = "/llm_router.v1.LlmRouter/ChatCompletion";
+const STREAM_CHAT_COMPLETION: &str = "/llm_router.v1.LlmRouter/StreamChatCompletion";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChatRequest {
+    #[prost(string, tag = "1")]
+    pub api_type: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub body: Vec<u8>,
+    #[prost(string, tag = "3")]
+    pub model: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChatResponse {
+    #[prost(bytes = "vec", tag = "1")]
+    pub body: Vec<u8>,
+    #[prost(map = "string, string", tag = "2")]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChatChunk {
+    #[prost(string, tag = "1")]
+    pub event: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: Vec<u8>,
+}
+
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<ChatChunk, Status>> + Send + 'static>>;
+
+/// `llm_router.v1.LlmRouter`, ready for `tonic::transport::Server::add_service`.
+#[derive(Clone)]
+pub struct LlmRouterServer {
+    state: AppState,
+}
+
+impl LlmRouterServer {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+
+    // Authenticates like the HTTP middleware (bearer token in `authorization`
+    // metadata) and routes the request under the same hop limit, panic guard
+    // and error counting as the HTTP stack; returns the HTTP-style response
+    async fn route(&self, request: tonic::Request<ChatRequest>, stream: bool) -> Result<axum::response::Response, Status> {
+        let request_id = request
+            .metadata()
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().strip_prefix("Bearer "))
+            .map(|t| t.trim().to_string());
+        let hops = loop_guard::parse_hops(request.metadata().get(loop_guard::HOPS_HEADER).and_then(|v| v.to_str().ok()));
+        loop_guard::admit(&self.state, hops).await.map_err(|e| Status::new(code_for(e.status()), e.to_string()))?;
+        let (state, virtual_key) = if self.state.auth_required().await {
+            let credentials = self.state.authenticate(token.as_deref()).await.map_err(|e| {
+                self.state.metrics.record_error(e.kind());
+                Status::unauthenticated(e.to_string())
+            })?;
+            (self.state.scoped(credentials.tenant.as_ref()), credentials.virtual_key)
+        } else {
+            (self.state.clone(), None)
+        };
+
+        let message = request.into_inner();
+        let payload = Bytes::from(message.body);
+        let api_type: ApiType = serde_json::from_value(serde_json::Value::String(message.api_type.clone()))
+            .map_err(|_| Status::invalid_argument(format!("unknown api_type '{}'", message.api_type)))?;
+        let mut body: serde_json::Value =
+            serde_json::from_slice(&payload).map_err(|e| Status::invalid_argument(format!("body is not JSON: {}", e)))?;
+        if !body.is_object() {
+            return Err(Status::invalid_argument("body must be a JSON object"));
+        }
+        if !message.model.is_empty() {
+            body["model"] = message.model.into();
+        }
+        body["stream"] = stream.into();
+        let invalid = |e: serde_json::Error| Status::invalid_argument(format!("invalid request: {}", e));
+        let request_wrapper = match api_type {
+            ApiType::OpenAI => RequestWrapper::OpenAI(serde_json::from_value(body).map_err(invalid)?),
+            ApiType::Anthropic => RequestWrapper::Anthropic(serde_json::from_value(body).map_err(invalid)?),
+            ApiType::Gemini => RequestWrapper::Gemini(serde_json::from_value(body).map_err(invalid)?),
+        };
+
+        debug!("gRPC request {} ({:?}, stream: {})", request_id, api_type, stream);
+        let routed = route_chat(api_type, state, RequestId(request_id), virtual_key, None, Default::default(), request_wrapper);
+        let metrics = self.state.metrics.clone();
+        let response = match loop_guard::with_hops(hops, AssertUnwindSafe(routed).catch_unwind()).await {
+            Ok(response) => response,
+            Err(panic) => {
+                panic_guard::report(&metrics, &payload, panic.as_ref());
+                metrics.record_error(ErrorKind::Internal);
+                return Err(Status::internal("Internal error while handling the request"));
+            }
+        };
+        if let Some(kind) = response.extensions().get::<ErrorKind>() {
+            metrics.record_error(*kind);
+        }
+        if response.status().is_success() {
+            return Ok(panic_guard::guard_body(metrics, payload, response));
+        }
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+        Err(Status::new(code_for(status), String::from_utf8_lossy(&body)))
+    }
+
+    async fn chat_completion(&self, request: tonic::Request<ChatRequest>) -> Result<tonic::Response<ChatResponse>, Status> {
+        let response = self.route(request, false).await?;
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| name.as_str().starts_with("x-llm-router-"))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(|e| Status::internal(format!("failed to read response: {}", e)))?;
+        Ok(tonic::Response::new(ChatResponse { body: body.to_vec(), headers }))
+    }
+
+    async fn stream_chat_completion(&self, request: tonic::Request<ChatRequest>) -> Result<tonic::Response<ChunkStream>, Status> {
+        let response = self.route(request, true).await?;
+        let mut pending = Vec::new();
+        let chunks = response
+            .into_body()
+            .into_data_stream()
+            .map(move |bytes| match bytes {
+                Ok(bytes) => {
+                    pending.extend_from_slice(&bytes);
+                    stream::iter(drain_frames(&mut pending).into_iter().map(Ok).collect::<Vec<_>>())
+                }
+                Err(e) => stream::iter(vec![Err(Status::internal(format!("stream failed: {}", e)))]),
+            })
+            .flatten();
+        Ok(tonic::Response::new(Box::pin(chunks)))
+    }
+}
+
+// Complete frames at the front of `pending` as chunks. Bytes are held until a
+// frame is complete so a character split across network chunks is decoded whole.
+fn drain_frames(pending: &mut Vec<u8>) -> Vec<ChatChunk> {
+    let mut chunks = Vec::new();
+    while let Some((at, len)) = frame_end(pending) {
+        let frame: Vec<u8> = pending.drain(..at + len).collect();
+        chunks.extend(parse_sse_frame(&String::from_utf8_lossy(&frame)));
+    }
+    chunks
+}
+
+// One SSE event as a chunk; comments and keep-alives have no data and are skipped
+fn parse_sse_frame(frame: &str) -> Option<ChatChunk> {
+    let mut event = String::new();
+    let mut data: Option<String> = None;
+    for line in frame.lines() {
+        if let Some(name) = line.strip_prefix("event:") {
+            event = name.trim_start().to_string();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_string()),
+            }
+        }
+    }
+    data.map(|data| ChatChunk { event, data: data.into_bytes() })
+}
+
+// gRPC status code closest to the router's HTTP status
+fn code_for(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::SERVICE_UNAVAILABLE | StatusCode::BAD_GATEWAY => Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+        _ => Code::Internal,
+    }
+}
+
+// Per-method adapters in the shape tonic's `Grpc` expects
+struct ChatCompletionSvc(LlmRouterServer);
+
+impl Service<tonic::Request<ChatRequest>> for ChatCompletionSvc {
+    type Response = tonic::Response<ChatResponse>;
+    type Error = Status;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: tonic::Request<ChatRequest>) -> Self::Future {
+        let server = self.0.clone();
+        Box::pin(async move { server.chat_completion(request).await })
+    }
+}
+
+struct StreamChatCompletionSvc(LlmRouterServer);
+
+impl Service<tonic::Request<ChatRequest>> for StreamChatCompletionSvc {
+    type Response = tonic::Response<ChunkStream>;
+    type Error = Status;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: tonic::Request<ChatRequest>) -> Self::Future {
+        let server = self.0.clone();
+        Box::pin(async move { server.stream_chat_completion(request).await })
+    }
+}
+
+impl<B> Service<http::Request<B>> for LlmRouterServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let server = self.clone();
+        match request.uri().path() {
+            CHAT_COMPLETION => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::<ChatResponse, ChatRequest>::default());
+                Ok(grpc.unary(ChatCompletionSvc(server), request).await)
+            }),
+            STREAM_CHAT_COMPLETION => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::<ChatChunk, ChatRequest>::default());
+                Ok(grpc.server_streaming(StreamChatCompletionSvc(server), request).await)
+            }),
+            path => {
+                let status = Status::unimplemented(format!("unknown method {}", path));
+                Box::pin(async move { Ok(status.into_http()) })
+            }
+        }
+    }
+}
+
+impl NamedService for LlmRouterServer {
+    const NAME: &'static str = "llm_router.v1.LlmRouter";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::llm_client::LlmClient;
+    use crate::mcp::McpGateway;
+    use crate::metrics::Metrics;
+    use crate::model_manager::ModelManager;
+    use crate::response_store::ResponseStore;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::RwLock;
+
+    fn app_state(api_base: &str, token: Option<&str>) -> AppState {
+        let config: Config = serde_yaml::from_str(&format!(
+            "model_list:\n  - model_name: m1\n    llm_params: {{api_type: openai, model: gpt-test, api_base: '{}', api_key: k}}\n\
+             router_settings:\n  strategy: roundrobin\n  model_groups: []\n",
+            api_base
+        ))
+        .unwrap();
+        let client = Arc::new(reqwest::Client::new());
+        AppState {
+            model_manager: Arc::new(RwLock::new(ModelManager::new(Arc::new(config)))),
+            token: token.map(str::to_string),
+            llm_client: Arc::new(LlmClient::new(client.clone(), client.clone())),
+            metrics: Arc::new(Metrics::default()),
+            config_path: String::new(),
+            config_overrides: Arc::new(Vec::new()),
+            response_store: Arc::new(ResponseStore::new(&Default::default())),
+            mcp: Arc::new(McpGateway::new(client)),
+            sessions: Default::default(),
+            sizes: Default::default(),
+            retry_queue: Default::default(),
+            tenants: Arc::new(HashMap::new()),
+            tenant_index: Default::default(),
+            tenant: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_routes_through_converters() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "id": "chatcmpl-1", "object": "chat.completion", "created": 1, "model": "gpt-test",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}]
+                })
+                .to_string(),
+            )
+            .create();
+        let grpc = LlmRouterServer::new(app_state(&server.url(), Some("secret")));
+        let request = |token: &str| {
+            let mut request = tonic::Request::new(ChatRequest {
+                api_type: "anthropic".to_string(),
+                body: json!({"max_tokens": 16, "messages": [{"role": "user", "content": "Hello"}]}).to_string().into_bytes(),
+                model: "m1".to_string(),
+            });
+            request.metadata_mut().insert("authorization", format!("Bearer {}", token).parse().unwrap());
+            request
+        };
+
+        let status = grpc.chat_completion(request("wrong")).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+
+        let response = grpc.chat_completion(request("secret")).await.unwrap().into_inner();
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["type"], "message");
+        assert_eq!(body["content"][0]["text"], "Hi");
+    }
+
+    #[test]
+    fn test_parse_sse_frame() {
+        let chunk = parse_sse_frame("event: message_start\nid: 0\ndata: {\"type\":\"message_start\"}\n\n").unwrap();
+        assert_eq!(chunk.event, "message_start");
+        assert_eq!(chunk.data, b"{\"type\":\"message_start\"}");
+        let chunk = parse_sse_frame("data: [DONE]\n\n").unwrap();
+        assert_eq!(chunk.event, "");
+        assert_eq!(chunk.data, b"[DONE]");
+        assert!(parse_sse_frame(":\n\n").is_none());
+    }
+
+    #[test]
+    fn test_drain_frames_keeps_split_characters_whole() {
+        let frames = "data: {\"text\":\"héllo\"}\r\n\r\ndata: [DONE]\n\n".as_bytes();
+        let split = frames.iter().position(|&b| b == 0xC3).unwrap() + 1;
+        let mut pending = frames[..split].to_vec();
+        assert!(drain_frames(&mut pending).is_empty());
+        pending.extend_from_slice(&frames[split..]);
+        let chunks = drain_frames(&mut pending);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].data, "{\"text\":\"héllo\"}".as_bytes());
+        assert_eq!(chunks[1].data, b"[DONE]");
+        assert!(pending.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::config::ImageFetchSettings;
+use crate::converters::request_wrapper::RequestWrapper;
+use crate::llm_client::LlmClient;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tracing::info;
+
+/// Replace every http(s) image URL in `request` with the downloaded image as
+/// inline base64 data. Fails on the first image that cannot be fetched under
+/// `settings`, so the upstream never receives a half-converted request.
+pub async fn inline_remote_images(request: &mut RequestWrapper, client: &LlmClient, settings: &ImageFetchSettings) -> Result<(), String> {
+    let mut urls = Vec::new();
+    request.inline_remote_images(&mut |url| {
+        urls.push(url.to_string());
+        None
+    });
+    urls.sort();
+    urls.dedup();
+    if urls.len() > settings.max_images {
+        return Err(format!("request has {} image URLs, more than the {} that are fetched", urls.len(), settings.max_images));
+    }
+    let mut fetched = HashMap::new();
+    for url in urls {
+        if fetched.contains_key(&url) {
+            continue;
+        }
+        let (mime_type, bytes) = client.fetch_image(&url, settings).await.map_err(|e| format!("cannot fetch image {}: {}", url, e))?;
+        info!("Inlined remote image {} ({}, {} bytes)", url, mime_type, bytes.len());
+        fetched.insert(url, (mime_type, STANDARD.encode(bytes)));
+    }
+    request.inline_remote_images(&mut |url| fetched.get(url).cloned());
+    Ok(())
+}
+
+/// The client image downloads go through: no proxy, no redirects, and unless
+/// `allow_private` every address a host resolves to must be public. The check
+/// runs in the resolver, so the connection goes to the addresses that passed.
+pub fn client(allow_private: bool) -> reqwest::Client {
+    let builder = reqwest::Client::builder().no_proxy().redirect(reqwest::redirect::Policy::none());
+    let builder = if allow_private { builder } else { builder.dns_resolver(std::sync::Arc::new(PublicOnly)) };
+    builder.build().expect("Failed to build image fetch client")
+}
+
+// Resolves like the system resolver but fails for hosts with a non-public address
+struct PublicOnly;
+
+impl Resolve for PublicOnly {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if let Some(private) = addrs.iter().find(|a| !is_public(a.ip())) {
+                return Err(not_public(&host, private.ip()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+pub fn not_public(host: &str, ip: IpAddr) -> String {
+    format!("{} resolves to {}, which is not a public address", host, ip)
+}
+
+/// A download error with its causes, so a refusal from the resolver is not
+/// hidden behind reqwest's generic message.
+pub fn describe(error: &reqwest::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+/// Whether `host` may be fetched: deny_hosts wins, and a non-empty allow_hosts
+/// must list it. Entries match exactly or, written as `*.example.com`, any subdomain.
+pub fn host_permitted(host: &str, settings: &ImageFetchSettings) -> bool {
+    let host = host.to_ascii_lowercase();
+    let matches = |pattern: &String| {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+            None => host == pattern,
+        }
+    };
+    if settings.deny_hosts.iter().any(matches) {
+        return false;
+    }
+    settings.allow_hosts.is_empty() || settings.allow_hosts.iter().any(matches)
+}
+
+/// True for addresses reachable on the public internet; loopback, private,
+/// link-local, shared, documentation and multicast ranges are not.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_public_v4(v4),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public_v4(v4),
+            None => is_public_v6(v6),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // 100.64.0.0/10 carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        // 198.18.0.0/15 benchmarking
+        || (a == 198 && (b == 18 || b == 19))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 unique local
+        || (first & 0xfe00) == 0xfc00
+        // fe80::/10 link-local
+        || (first & 0xffc0) == 0xfe80
+        // 2001:db8::/32 documentation
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+        // 64:ff9b::/96 and 64:ff9b:1::/48 NAT64, which can reach any IPv4 address
+        || (first == 0x0064 && ip.segments()[1] == 0xff9b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_host_and_address_checks() {
+        let settings = ImageFetchSettings {
+            allow_hosts: vec!["*.example.com".to_string(), "images.test".to_string()],
+            deny_hosts: vec!["private.example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(host_permitted("cdn.example.com", &settings));
+        assert!(host_permitted("images.test", &settings));
+        assert!(!host_permitted("example.com", &settings));
+        assert!(!host_permitted("badexample.com", &settings));
+        assert!(!host_permitted("private.example.com", &settings));
+        assert!(host_permitted("anything.org", &ImageFetchSettings::default()));
+
+        for ip in ["10.0.0.1", "127.0.0.1", "169.254.169.254", "192.168.1.1", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "64:ff9b::a00:1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inline_remote_images() {
+        let mut server = mockito::Server::new_async().await;
+        let _png = server.mock("GET", "/cat.png").with_header("content-type", "image/png").with_body("png-bytes").create();
+        let _html = server.mock("GET", "/page").with_header("content-type", "text/html").with_body("<html>").create();
+        let _big = server.mock("GET", "/big.png").with_header("content-type", "image/png").with_body(vec![0u8; 64]).create();
+        let client = LlmClient::new(Arc::new(reqwest::Client::new()), Arc::new(reqwest::Client::new()));
+        let request = |path: &str| {
+            RequestWrapper::OpenAI(
+                serde_json::from_value(json!({
+                    "model": "m",
+                    "messages": [{"role": "user", "content": [{"type": "image_url", "image_url": {"url": format!("{}{}", server.url(), path)}}]}]
+                }))
+                .unwrap(),
+            )
+        };
+
+        // The mock server listens on loopback, which is refused by default
+        let mut req = request("/cat.png");
+        let err = inline_remote_images(&mut req, &client, &ImageFetchSettings::default()).await.unwrap_err();
+        assert!(err.contains("not a public address"), "{}", err);
+        // Names are checked by the resolver as the connection is made
+        let url = format!("{}/cat.png", server.url().replace("127.0.0.1", "localhost"));
+        let err = client.fetch_image(&url, &ImageFetchSettings::default()).await.unwrap_err();
+        assert!(err.contains("localhost resolves to"), "{}", err);
+
+        let settings = ImageFetchSettings { allow_private: true, max_bytes: 32, ..Default::default() };
+        let mut req = request("/cat.png");
+        inline_remote_images(&mut req, &client, &settings).await.unwrap();
+        let body = serde_json::to_value(&req).unwrap();
+        assert_eq!(body["messages"][0]["content"][0]["image_url"]["url"], format!("data:image/png;base64,{}", STANDARD.encode("png-bytes")));
+
+        let err = inline_remote_images(&mut request("/page"), &client, &settings).await.unwrap_err();
+        assert!(err.contains("content type text/html"), "{}", err);
+        let err = inline_remote_images(&mut request("/big.png"), &client, &settings).await.unwrap_err();
+        assert!(err.contains("larger than 32 bytes"), "{}", err);
+
+        let settings = ImageFetchSettings { allow_private: true, max_images: 0, ..Default::default() };
+        let err = inline_remote_images(&mut request("/cat.png"), &client, &settings).await.unwrap_err();
+        assert!(err.contains("more than the 0 that are fetched"), "{}", err);
+    }
+}
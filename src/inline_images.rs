@@ -0,0 +1,146 @@
+use crate::config::ImageLimits;
+use crate::converters::request_wrapper::RequestWrapper;
+use tracing::debug;
+
+/// Size of a base64 payload once decoded, computed without decoding it.
+pub fn decoded_len(data: &str) -> u64 {
+    let digits = data.bytes().filter(|b| !b.is_ascii_whitespace() && *b != b'=').count() as u64;
+    digits * 3 / 4
+}
+
+/// Enforce `limits` on every inline image of `request`. Oversized images are
+/// recompressed when `transcode` is set; otherwise, or when recompression cannot
+/// bring one under the limit, the error says which image is too large.
+pub fn enforce(request: &mut RequestWrapper, limits: &ImageLimits) -> Result<(), String> {
+    let mut index = 0;
+    request.for_each_inline_image(&mut |mime_type, data| {
+        index += 1;
+        let size = decoded_len(data);
+        if size <= limits.max_bytes {
+            return Ok(());
+        }
+        let too_large = format!(
+            "inline image #{} ({}) is {} bytes, over this model's limit of {} bytes",
+            index, mime_type, size, limits.max_bytes
+        );
+        if !limits.transcode {
+            return Err(too_large);
+        }
+        let (new_mime_type, new_data) = transcode(data, limits.max_bytes).map_err(|e| format!("{}; {}", too_large, e))?;
+        debug!("Transcoded inline image #{} from {} bytes to {} bytes", index, size, decoded_len(&new_data));
+        *mime_type = new_mime_type;
+        *data = new_data;
+        Ok(())
+    })
+}
+
+/// `enforce` for use on the async workers: with `transcode` set the check runs
+/// on a blocking thread, since decoding and re-encoding an image can take
+/// long enough to stall the streams served next to it.
+pub async fn enforce_off_worker(mut request: RequestWrapper, limits: &ImageLimits) -> Result<RequestWrapper, String> {
+    if !limits.transcode {
+        return enforce(&mut request, limits).map(|()| request);
+    }
+    let limits = limits.clone();
+    tokio::task::spawn_blocking(move || enforce(&mut request, &limits).map(|()| request))
+        .await
+        .map_err(|e| format!("inline image check failed: {}", e))?
+}
+
+// Re-encode as JPEG, shrinking the image until it fits
+#[cfg(feature = "image-transcode")]
+fn transcode(data: &str, max_bytes: u64) -> Result<(String, String), String> {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::imageops::FilterType;
+
+    let bytes = STANDARD.decode(data.trim()).map_err(|e| format!("invalid base64: {}", e))?;
+    let mut img = image::load_from_memory(&bytes).map_err(|e| format!("cannot decode image: {}", e))?.to_rgb8();
+    for _ in 0..8 {
+        let mut out = Vec::new();
+        JpegEncoder::new_with_quality(&mut out, 80)
+            .encode_image(&img)
+            .map_err(|e| format!("cannot encode image: {}", e))?;
+        if out.len() as u64 <= max_bytes {
+            return Ok(("image/jpeg".to_string(), STANDARD.encode(out)));
+        }
+        let (width, height) = ((img.width() * 7 / 10).max(1), (img.height() * 7 / 10).max(1));
+        img = image::imageops::resize(&img, width, height, FilterType::Triangle);
+    }
+    Err("still too large after downscaling".to_string())
+}
+
+#[cfg(not(feature = "image-transcode"))]
+fn transcode(_data: &str, _max_bytes: u64) -> Result<(String, String), String> {
+    Err("transcoding needs the image-transcode feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_enforce_rejects_oversized_images() {
+        let image = |data: &str| {
+            RequestWrapper::OpenAI(
+                serde_json::from_value(json!({
+                    "model": "m",
+                    "messages": [{"role": "user", "content": [
+                        {"type": "text", "text": "what is this?"},
+                        {"type": "image_url", "image_url": {"url": format!("data:image/png;base64,{}", data)}}
+                    ]}]
+                }))
+                .unwrap(),
+            )
+        };
+        assert_eq!(decoded_len("aGVsbG8="), 5);
+
+        let limits = ImageLimits { max_bytes: 5, transcode: false };
+        assert!(enforce(&mut image("aGVsbG8="), &limits).is_ok());
+        let err = enforce(&mut image("aGVsbG8gd29ybGQ="), &limits).unwrap_err();
+        assert!(err.contains("inline image #1 (image/png) is 11 bytes"), "{}", err);
+
+        // Not an image, so transcoding fails either way and the request is still rejected
+        let limits = ImageLimits { max_bytes: 5, transcode: true };
+        assert!(enforce(&mut image("aGVsbG8gd29ybGQ="), &limits).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_enforce_off_worker_returns_the_checked_request() {
+        let request = RequestWrapper::Gemini(
+            serde_json::from_value(json!({"contents": [{"role": "user", "parts": [{"inlineData": {"mimeType": "image/png", "data": "aGVsbG8gd29ybGQ="}}]}]}))
+                .unwrap(),
+        );
+        let small = ImageLimits { max_bytes: 64, transcode: true };
+        let checked = enforce_off_worker(request.clone(), &small).await.unwrap();
+        assert_eq!(serde_json::to_value(&checked).unwrap(), serde_json::to_value(&request).unwrap());
+        let tiny = ImageLimits { max_bytes: 5, transcode: true };
+        assert!(enforce_off_worker(request, &tiny).await.unwrap_err().contains("inline image #1"));
+    }
+
+    #[cfg(feature = "image-transcode")]
+    #[test]
+    fn test_enforce_transcodes_oversized_images() {
+        use base64::Engine;
+        let noise = image::RgbImage::from_fn(256, 256, |x, y| image::Rgb([((x * 31) ^ (y * 17)) as u8, (x * y) as u8, (x + y) as u8]));
+        let mut png = std::io::Cursor::new(Vec::new());
+        noise.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let data = base64::engine::general_purpose::STANDARD.encode(png.into_inner());
+
+        let mut request = RequestWrapper::Gemini(
+            serde_json::from_value(json!({"contents": [{"role": "user", "parts": [{"inlineData": {"mimeType": "image/png", "data": data}}]}]}))
+                .unwrap(),
+        );
+        let limits = ImageLimits { max_bytes: 8_000, transcode: true };
+        enforce(&mut request, &limits).unwrap();
+        request
+            .for_each_inline_image(&mut |mime_type, data| {
+                assert_eq!(mime_type, "image/jpeg");
+                assert!(decoded_len(data) <= 8_000);
+                Ok::<(), ()>(())
+            })
+            .unwrap();
+    }
+}
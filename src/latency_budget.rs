@@ -0,0 +1,122 @@
+use crate::config::{ApiType, LLMParams};
+use axum::http::HeaderMap;
+use serde_json::{Value, json};
+use std::time::Duration;
+use tracing::debug;
+
+/// Client header carrying the time (in milliseconds) the caller is willing to wait.
+pub const HEADER: &str = "x-llm-router-latency-budget-ms";
+
+pub fn from_headers(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(HEADER)?;
+    match value.to_str().ok().and_then(|v| v.trim().parse::<u64>().ok()) {
+        Some(ms) if ms > 0 => Some(Duration::from_millis(ms)),
+        _ => {
+            debug!("Ignoring invalid {} header: {:?}", HEADER, value);
+            None
+        }
+    }
+}
+
+/// Rewrite an upstream request body (already in the upstream's format) to fit
+/// `budget` using the model's `latency_hints`. A tight budget sets the configured
+/// OpenAI service tier and downgrades reasoning (OpenAI `reasoning_effort` to
+/// "low", Anthropic `thinking` removed); with `output_tokens_per_sec` the max
+/// output tokens are capped to what can be generated within the budget.
+pub fn apply(body: &mut Value, params: &LLMParams, budget: Duration) {
+    let Some(hints) = &params.latency_hints else { return };
+    let Some(obj) = body.as_object_mut() else { return };
+
+    if budget.as_millis() <= hints.tight_budget_ms as u128 {
+        match params.api_type {
+            ApiType::OpenAI => {
+                if let Some(tier) = &hints.service_tier {
+                    obj.insert("service_tier".to_string(), json!(tier));
+                }
+                if obj
+                    .get("reasoning_effort")
+                    .and_then(Value::as_str)
+                    .is_some_and(|effort| effort != "minimal" && effort != "low")
+                {
+                    obj.insert("reasoning_effort".to_string(), json!("low"));
+                }
+            }
+            ApiType::Anthropic => {
+                obj.remove("thinking");
+            }
+            ApiType::Gemini => {}
+        }
+    }
+
+    let Some(tps) = hints.output_tokens_per_sec else { return };
+    let cap = ((budget.as_secs_f64() * tps as f64) as u64).max(1);
+    let slot = match params.api_type {
+        ApiType::OpenAI => {
+            let key = if obj.contains_key("max_completion_tokens") { "max_completion_tokens" } else { "max_tokens" };
+            obj.entry(key).or_insert(Value::Null)
+        }
+        ApiType::Anthropic => {
+            // Extended thinking needs max_tokens above its own budget
+            if obj
+                .get("thinking")
+                .and_then(|t| t.get("budget_tokens"))
+                .and_then(Value::as_u64)
+                .is_some_and(|thinking| thinking >= cap)
+            {
+                obj.remove("thinking");
+            }
+            obj.entry("max_tokens").or_insert(Value::Null)
+        }
+        ApiType::Gemini => {
+            let config = obj.entry("generationConfig").or_insert_with(|| json!({}));
+            let Some(config) = config.as_object_mut() else { return };
+            config.entry("maxOutputTokens").or_insert(Value::Null)
+        }
+    };
+    if slot.as_u64().is_none_or(|current| current > cap) {
+        debug!("Capping max output tokens to {} for a {:?} budget", cap, budget);
+        *slot = json!(cap);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LatencyHints;
+
+    fn params(api_type: ApiType) -> LLMParams {
+        serde_json::from_value(json!({
+            "api_type": api_type,
+            "model": "m",
+            "api_base": "http://localhost",
+            "api_key": "",
+        }))
+        .map(|mut p: LLMParams| {
+            p.latency_hints = Some(LatencyHints {
+                tight_budget_ms: 5_000,
+                service_tier: Some("priority".to_string()),
+                output_tokens_per_sec: Some(50),
+            });
+            p
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tight_budget_rewrites_request() {
+        let mut body = json!({"model": "m", "reasoning_effort": "high", "max_tokens": 4096});
+        apply(&mut body, &params(ApiType::OpenAI), Duration::from_secs(2));
+        assert_eq!(body["service_tier"], "priority");
+        assert_eq!(body["reasoning_effort"], "low");
+        assert_eq!(body["max_tokens"], 100);
+
+        let mut body = json!({"max_tokens": 4096, "thinking": {"type": "enabled", "budget_tokens": 2048}});
+        apply(&mut body, &params(ApiType::Anthropic), Duration::from_secs(60));
+        assert_eq!(body["max_tokens"], 3000);
+        assert_eq!(body["thinking"]["budget_tokens"], 2048);
+
+        let mut body = json!({"contents": []});
+        apply(&mut body, &params(ApiType::Gemini), Duration::from_secs(1));
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 50);
+    }
+}
@@ -0,0 +1,48 @@
+//! Library half of llm-router: routing, conversion and server building blocks
+//! used by the binary and by benchmarks.
+
+pub mod admin;
+pub mod api_version;
+pub mod auth;
+pub mod capabilities;
+pub mod config;
+pub mod image_fetch;
+pub mod latency_budget;
+pub mod reasoning_shaping;
+pub mod inline_images;
+pub mod converters;
+pub mod error;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod mcp;
+pub mod models;
+pub mod model_manager;
+pub mod router;
+pub mod router_tools;
+pub mod llm_client;
+pub mod request_caps;
+pub mod request_id;
+pub mod request_signing;
+pub mod response_store;
+pub mod session_caps;
+pub mod size_stats;
+pub mod stream_fence;
+pub mod stream_pacing;
+pub mod stream_smoothing;
+pub mod startup_report;
+pub mod warm_pool;
+pub mod utils;
+pub mod web_search;
+pub mod logging;
+pub mod loop_guard;
+pub mod metrics;
+pub mod model_checks;
+pub mod convert;
+pub mod panic_guard;
+pub mod offload;
+pub mod output_limit;
+pub mod output_validation;
+pub mod priority;
+pub mod queue_events;
+pub mod refusal;
+pub mod retry_queue;
@@ -1,20 +1,106 @@
-use crate::config::{ApiType, ModelConfig};
+use crate::config::{ApiType, ImageFetchSettings, ModelConfig, UpstreamIdentity, WebSearchSettings};
 use crate::converters::request_wrapper::RequestWrapper;
 use anyhow::Result;
-use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, USER_AGENT};
 use std::future::Future;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tracing::{debug, info, warn};
 use crate::request_id::RequestId;
+use crate::api_version;
+use crate::image_fetch;
+use crate::latency_budget;
+use crate::reasoning_shaping;
+use crate::loop_guard;
+use crate::offload;
+use crate::metrics::{self, ConversionKind};
+use crate::request_signing;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
 pub struct LlmClient {
     http_client: Arc<reqwest::Client>,
+    // Client that never uses the proxy, for models with `use_proxy: false`
+    direct_client: Arc<reqwest::Client>,
+    // Image downloads, refusing non-public addresses or allowing them
+    image_client: reqwest::Client,
+    image_client_private: reqwest::Client,
+}
+
+/// Request details that `rewrite_body` and `rewrite_header` values may refer to.
+#[derive(Debug, Clone, Default)]
+pub struct RewriteContext {
+    // Name of the virtual key the request came with; empty for unnamed keys
+    pub key_name: String,
+    pub tenant: String,
+    // Group that picked the model; empty when the model was called directly
+    pub group: String,
+    // Model or group the client asked for
+    pub requested_model: String,
+}
+
+impl RewriteContext {
+    /// Fill the placeholders of `template` for one upstream request.
+    pub fn render(&self, template: &str, request_id: &str, model: &str, timestamp: u64) -> String {
+        if !template.contains('{') {
+            return template.to_string();
+        }
+        // One pass, so a value that itself looks like a placeholder is left alone
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let tail = &rest[start..];
+            let Some(end) = tail.find('}') else {
+                rest = tail;
+                break;
+            };
+            match &tail[1..end] {
+                "request_id" => out.push_str(request_id),
+                "key_name" => out.push_str(&self.key_name),
+                "tenant" => out.push_str(&self.tenant),
+                "group" => out.push_str(&self.group),
+                "requested_model" => out.push_str(&self.requested_model),
+                "model" => out.push_str(model),
+                "timestamp" => out.push_str(&timestamp.to_string()),
+                // Not a placeholder; keep the brace and look for one after it
+                _ => {
+                    out.push('{');
+                    rest = &tail[1..];
+                    continue;
+                }
+            }
+            rest = &tail[end + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    // Renders every string in `value`, however deeply nested
+    fn render_value(&self, value: &serde_json::Value, request_id: &str, model: &str, timestamp: u64) -> serde_json::Value {
+        match value {
+            serde_json::Value::String(s) => serde_json::Value::String(self.render(s, request_id, model, timestamp)),
+            serde_json::Value::Array(items) => items.iter().map(|v| self.render_value(v, request_id, model, timestamp)).collect(),
+            serde_json::Value::Object(map) => {
+                map.iter().map(|(k, v)| (k.clone(), self.render_value(v, request_id, model, timestamp))).collect()
+            }
+            other => other.clone(),
+        }
+    }
 }
 
 impl LlmClient {
-    pub fn new(http_client: Arc<reqwest::Client>) -> Self {
-        Self { http_client }
+    pub fn new(http_client: Arc<reqwest::Client>, direct_client: Arc<reqwest::Client>) -> Self {
+        Self {
+            http_client,
+            direct_client,
+            image_client: image_fetch::client(false),
+            image_client_private: image_fetch::client(true),
+        }
+    }
+
+    fn client_for(&self, model_config: &ModelConfig) -> &reqwest::Client {
+        if model_config.llm_params.use_proxy { &self.http_client } else { &self.direct_client }
     }
 
     fn build_target_url(model_config: &ModelConfig, request: &RequestWrapper) -> String {
@@ -50,9 +136,14 @@ impl LlmClient {
         request: &RequestWrapper,
         model_config: &ModelConfig,
         request_id: &RequestId,
+        identity: &UpstreamIdentity,
+        rewrite: &RewriteContext,
+        latency_budget: Option<Duration>,
     ) -> impl Future<Output = Result<reqwest::Response, reqwest::Error>> {
         // Prepare body per upstream api type to know if streaming is needed for Gemini
-        let mut target_body = match model_config.llm_params.api_type {
+        let upstream_api = &model_config.llm_params.api_type;
+        // Large requests are converted off the async worker
+        let mut target_body = offload::run_for_request(|| metrics::time_conversion(ConversionKind::Request, &request.api_type(), upstream_api, || match upstream_api {
             ApiType::Anthropic => {
                 let mut anthropic_req = request.get_anthropic();
                 anthropic_req.model = model_config.llm_params.model.clone();
@@ -67,43 +158,72 @@ impl LlmClient {
                 let mut gemini_req = request.get_gemini();
                 // Path uses model; body does not include model
                 gemini_req.model = model_config.llm_params.model.clone();
+                // Labels carried over from another format are Vertex-only
+                if request.api_type() != ApiType::Gemini && !is_vertex(&model_config.llm_params.api_base) {
+                    gemini_req.extra_fields.remove("labels");
+                }
                 serde_json::to_value(gemini_req).expect("Failed to serialize converted Gemini request")
             }
-        };
+        }));
 
         // Build target URL (Gemini stream/non-stream handled inside)
         let target_url = Self::build_target_url(model_config, request);
 
-        let mut target_request = self
-            .http_client
-            .post(&target_url)
-            .header("Content-Type", "application/json");
+        // Every header goes into one map, so a later entry replaces an earlier one
+        // with the same name: router defaults, then rewrite_header, then auth_headers
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
 
-        // Propagate request id upstream
-        if let Ok(val) = HeaderValue::from_str(&request_id.0) {
-            target_request = target_request.header("x-request-id", val);
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let user_agent = UpstreamIdentity::render(&identity.user_agent, &request_id.0, &model_config.model_name);
+        insert_header(&mut headers, USER_AGENT, &user_agent);
+        if let Some(user) = &identity.user {
+            let user = UpstreamIdentity::render(user, &request_id.0, &model_config.model_name);
+            set_upstream_user(&mut target_body, &model_config.llm_params.api_type, user);
         }
 
+        // Count this router, so a request that loops back to it is eventually rejected
+        if loop_guard::is_self_target(&model_config.llm_params.api_base) {
+            headers.insert(HeaderName::from_static(loop_guard::HOPS_HEADER), HeaderValue::from(loop_guard::current_hops() + 1));
+        }
+        // Propagate request id upstream
+        insert_header(&mut headers, HeaderName::from_static("x-request-id"), &request_id.0);
+
         match model_config.llm_params.api_type {
             ApiType::Anthropic => {
-                target_request = target_request.header("x-api-key", model_config.llm_params.api_key.to_string());
+                insert_header(&mut headers, HeaderName::from_static("x-api-key"), &model_config.llm_params.api_key);
             }
             ApiType::OpenAI => {
-                target_request = target_request.header(
-                    "Authorization",
-                    format!("Bearer {}", model_config.llm_params.api_key),
-                );
+                insert_header(&mut headers, AUTHORIZATION, &format!("Bearer {}", model_config.llm_params.api_key));
             }
             ApiType::Gemini => {
                 // Gemini commonly uses API key query param; no auth header required.
                 // For SSE streaming, hint Accept header
                 if request.is_stream().unwrap_or(false) {
-                    target_request = target_request.header("Accept", "text/event-stream");
+                    headers.insert(ACCEPT, HeaderValue::from_static("text/event-stream"));
                 }
             }
         }
 
-        // Apply rewrite_header functionality
+        // The only version clients may ask for; rewrite_header can still pin another
+        if model_config.llm_params.api_type == ApiType::Anthropic {
+            headers.insert("anthropic-version", HeaderValue::from_static(api_version::ANTHROPIC_DEFAULT_VERSION));
+        }
+        // OpenAI-Beta has no equivalent at other providers and is only forwarded to OpenAI
+        if model_config.llm_params.api_type == ApiType::OpenAI && !request.openai_betas().is_empty() {
+            insert_header(&mut headers, HeaderName::from_static("openai-beta"), &request.openai_betas().join(","));
+        }
+
+        // Betas were checked against anthropic_betas by the router
+        if model_config.llm_params.api_type == ApiType::Anthropic
+            && model_config.llm_params.anthropic_betas.is_some()
+            && !request.anthropic_betas().is_empty()
+        {
+            insert_header(&mut headers, HeaderName::from_static("anthropic-beta"), &request.anthropic_betas().join(","));
+        }
+
+        // Apply rewrite_header functionality; entries were validated at config load.
+        // Null entries drop headers the router set above.
         if let serde_json::Value::Object(map) = &model_config.llm_params.rewrite_header {
             for (k, v) in map {
                 if v.is_object() || v.is_array() {
@@ -117,37 +237,346 @@ impl LlmClient {
                         continue;
                     }
                 };
+                if v.is_null() {
+                    headers.remove(&name);
+                    continue;
+                }
 
                 let value_str = if let Some(s) = v.as_str() {
-                    s.to_string()
+                    rewrite.render(s, &request_id.0, &model_config.model_name, timestamp)
                 } else {
                     v.to_string().trim_matches('"').to_string()
                 };
+                headers.insert(name, rendered_header_value(&value_str));
+            }
+        }
 
-                match HeaderValue::from_str(&value_str) {
-                    Ok(val) => {
-                        target_request = target_request.header(name.clone(), val);
-                    }
-                    Err(e) => {
-                        warn!("Invalid header value for {}: {}", k, e);
-                    }
-                }
+        // Auth headers are validated at config load; applied last so they win over rewrite_header
+        for (name, value) in &model_config.llm_params.auth_headers {
+            if let (Ok(n), Ok(v)) = (HeaderName::try_from(name.as_str()), HeaderValue::from_str(value)) {
+                headers.insert(n, v);
             }
         }
 
+        // Model shaping and budget knobs go first so rewrite_body can still override them
+        if let Some(shaping) = &model_config.llm_params.reasoning_model {
+            reasoning_shaping::apply(&mut target_body, &model_config.llm_params.api_type, shaping);
+        }
+        if let Some(budget) = latency_budget {
+            latency_budget::apply(&mut target_body, &model_config.llm_params, budget);
+        }
+
         if let serde_json::Value::Object(map) = &model_config.llm_params.rewrite_body {
             if let Some(t_body) = target_body.as_object_mut() {
                 for (k, v) in map {
-                    t_body.insert(k.clone(), v.clone());
+                    t_body.insert(k.clone(), rewrite.render_value(v, &request_id.0, &model_config.model_name, timestamp));
+                }
+            }
+        }
+
+        if model_config.llm_params.scrub_unknown_fields
+            && let Some(t_body) = target_body.as_object_mut()
+        {
+            let allowed = model_config.llm_params.api_type.request_fields();
+            let rewritten = model_config.llm_params.rewrite_body.as_object();
+            let scrubbed: Vec<String> = t_body
+                .keys()
+                .filter(|k| !allowed.contains(&k.as_str()) && !rewritten.is_some_and(|m| m.contains_key(*k)))
+                .cloned()
+                .collect();
+            if !scrubbed.is_empty() {
+                debug!("Scrubbed request fields not accepted by {}: {:?}", model_config.model_name, scrubbed);
+                for k in &scrubbed {
+                    t_body.remove(k);
+                }
+            }
+        }
+
+        // Serialize once so signatures cover the exact bytes sent
+        let body = offload::run_for_request(|| serde_json::to_vec(&target_body).expect("Failed to serialize request"));
+        if let Some(signing) = &model_config.llm_params.signing {
+            for (name, value) in request_signing::signing_headers(signing, &body, timestamp) {
+                if let Ok(name) = HeaderName::try_from(name.as_str()) {
+                    insert_header(&mut headers, name, &value);
                 }
             }
         }
 
         info!("Forwarding request to: {}", target_url);
-        debug!(
-            "request body: {}",
-            serde_json::to_string(&target_body).expect("Failed to serialize request")
+        debug!("request body: {}", String::from_utf8_lossy(&body));
+        self.client_for(model_config).post(&target_url).headers(headers).body(body).send()
+    }
+
+    /// Download an image for inlining, refusing hosts and addresses `settings`
+    /// does not permit. Addresses are checked as the connection is made, and
+    /// redirects are not followed, so DNS tricks cannot reach an internal host.
+    /// Returns the content type and body.
+    pub async fn fetch_image(&self, url: &str, settings: &ImageFetchSettings) -> Result<(String, Vec<u8>), String> {
+        let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid URL: {}", e))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(format!("unsupported scheme {}", parsed.scheme()));
+        }
+        let host = parsed.host_str().ok_or("URL has no host")?.trim_start_matches('[').trim_end_matches(']').to_string();
+        if !image_fetch::host_permitted(&host, settings) {
+            return Err(format!("host {} is not allowed", host));
+        }
+        // Addresses in the URL skip the resolver, so they are checked here
+        if !settings.allow_private
+            && let Ok(ip) = host.parse::<IpAddr>()
+            && !image_fetch::is_public(ip)
+        {
+            return Err(image_fetch::not_public(&host, ip));
+        }
+
+        let client = if settings.allow_private { &self.image_client_private } else { &self.image_client };
+        let mut response = client
+            .get(parsed)
+            .timeout(Duration::from_millis(settings.timeout_ms))
+            .send()
+            .await
+            .map_err(|e| image_fetch::describe(&e))?;
+        if !response.status().is_success() {
+            return Err(format!("status {}", response.status()));
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        if !settings.content_types.iter().any(|t| t.eq_ignore_ascii_case(&content_type)) {
+            return Err(format!("content type {} is not accepted", content_type));
+        }
+        let too_large = || format!("image is larger than {} bytes", settings.max_bytes);
+        if response.content_length().is_some_and(|len| len > settings.max_bytes) {
+            return Err(too_large());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+            if (body.len() + chunk.len()) as u64 > settings.max_bytes {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok((content_type, body))
+    }
+
+    /// Send a HEAD request to the model's api_base on the client its requests
+    /// use, leaving a connection in that client's pool. Any status will do.
+    pub async fn warm(&self, model_config: &ModelConfig) -> Result<reqwest::StatusCode, reqwest::Error> {
+        let response = self
+            .client_for(model_config)
+            .head(&model_config.llm_params.api_base)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?;
+        Ok(response.status())
+    }
+
+    /// Query the search API of `settings` and return its JSON response.
+    pub async fn web_search(&self, query: &str, settings: &WebSearchSettings) -> Result<serde_json::Value, String> {
+        let mut url = reqwest::Url::parse(&settings.url).map_err(|e| format!("invalid URL: {}", e))?;
+        {
+            let mut pairs = url.query_pairs_mut();
+            for (name, value) in &settings.params {
+                pairs.append_pair(name, value);
+            }
+            pairs.append_pair(&settings.query_param, query);
+            if !settings.count_param.is_empty() {
+                pairs.append_pair(&settings.count_param, &settings.max_results.to_string());
+            }
+        }
+        let mut request = self.http_client.get(url).timeout(Duration::from_millis(settings.timeout_ms));
+        for (name, value) in &settings.headers {
+            request = request.header(name, value);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("status {}", response.status()));
+        }
+        response.json().await.map_err(|e| format!("invalid response: {}", e))
+    }
+}
+
+// Insert a header whose value is only known at runtime, replacing any earlier
+// value; values that are not valid header values are logged and left out
+fn insert_header(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    match HeaderValue::from_str(value) {
+        Ok(value) => {
+            headers.insert(name, value);
+        }
+        Err(e) => warn!("Invalid header value for {}: {}", name, e),
+    }
+}
+
+// Vertex AI serves Gemini from `*aiplatform.googleapis.com`
+fn is_vertex(api_base: &str) -> bool {
+    reqwest::Url::parse(api_base)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.ends_with("aiplatform.googleapis.com")))
+        .unwrap_or(false)
+}
+
+// Rendered values carry client-controlled text (model, key and tenant names), so control
+// characters are dropped rather than letting one bad value lose the whole header
+fn rendered_header_value(value: &str) -> HeaderValue {
+    let clean: String = value.chars().filter(|c| !c.is_ascii_control() || *c == '\t').collect();
+    if clean.len() != value.len() {
+        debug!("Dropped control characters from rendered header value");
+    }
+    HeaderValue::from_bytes(clean.as_bytes()).expect("control characters removed")
+}
+
+// Attribute the request to `user` unless the client already named one
+fn set_upstream_user(body: &mut serde_json::Value, api_type: &ApiType, user: String) {
+    let Some(body) = body.as_object_mut() else { return };
+    match api_type {
+        ApiType::OpenAI => {
+            body.entry("user").or_insert(serde_json::Value::String(user));
+        }
+        ApiType::Anthropic => {
+            let metadata = body.entry("metadata").or_insert_with(|| serde_json::json!({}));
+            if let Some(metadata) = metadata.as_object_mut()
+                && metadata.get("user_id").is_none_or(serde_json::Value::is_null)
+            {
+                metadata.insert("user_id".to_string(), serde_json::Value::String(user));
+            }
+        }
+        ApiType::Gemini => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_is_vertex() {
+        assert!(is_vertex("https://us-central1-aiplatform.googleapis.com/v1/projects/p/locations/us-central1/publishers/google"));
+        assert!(!is_vertex("https://generativelanguage.googleapis.com/v1beta"));
+        assert!(!is_vertex("https://aiplatform.googleapis.com.example.net/v1"));
+    }
+
+    #[tokio::test]
+    async fn test_upstream_identity() {
+        let mut server = mockito::Server::new_async().await;
+        let upstream = server
+            .mock("POST", "/v1/messages")
+            .match_header("user-agent", format!("llm-router/{} (req-1)", env!("CARGO_PKG_VERSION")).as_str())
+            .match_body(mockito::Matcher::PartialJson(json!({"metadata": {"user_id": "router:claude"}})))
+            .with_body("{}")
+            .create();
+        let model: ModelConfig = serde_yaml::from_str(&format!(
+            "model_name: claude\nllm_params: {{api_type: anthropic, model: m, api_base: '{}', api_key: k}}",
+            server.url()
+        ))
+        .unwrap();
+        let identity = UpstreamIdentity {
+            user_agent: "llm-router/{version} ({request_id})".to_string(),
+            user: Some("router:{model}".to_string()),
+        };
+        let request = RequestWrapper::OpenAI(
+            serde_json::from_value(json!({"model": "claude", "messages": [{"role": "user", "content": "hi"}]})).unwrap(),
         );
-        target_request.json(&target_body).send()
+        let client = LlmClient::new(Arc::new(reqwest::Client::new()), Arc::new(reqwest::Client::new()));
+        let response = client.forward_request(&request, &model, &RequestId("req-1".to_string()), &identity, &RewriteContext::default(), None).await.unwrap();
+        assert!(response.status().is_success());
+        upstream.assert();
+
+        // A user the client named is kept
+        let mut body = json!({"user": "alice"});
+        set_upstream_user(&mut body, &ApiType::OpenAI, "router".to_string());
+        assert_eq!(body["user"], "alice");
+        let mut body = json!({"metadata": {"user_id": null}});
+        set_upstream_user(&mut body, &ApiType::Anthropic, "router".to_string());
+        assert_eq!(body["metadata"]["user_id"], "router");
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_templates() {
+        let mut server = mockito::Server::new_async().await;
+        let upstream = server
+            .mock("POST", "/chat/completions")
+            .match_header("x-route-hint", "acme/team-a")
+            .match_header("x-request-id", mockito::Matcher::Missing)
+            .match_body(mockito::Matcher::PartialJson(json!({"metadata": {"origin": "req-2 via fast asked auto", "tags": ["gpt"]}})))
+            .with_body("{}")
+            .create();
+        let model: ModelConfig = serde_yaml::from_str(&format!(
+            r#"
+model_name: gpt
+llm_params:
+  api_type: openai
+  model: m
+  api_base: '{}'
+  api_key: k
+  rewrite_header: {{x-route-hint: "{{tenant}}/{{key_name}}", x-request-id: null}}
+  rewrite_body: {{metadata: {{origin: "{{request_id}} via {{group}} asked {{requested_model}}", tags: ["{{model}}"]}}}}
+"#,
+            server.url()
+        ))
+        .unwrap();
+        let rewrite = RewriteContext {
+            key_name: "team-a".to_string(),
+            tenant: "acme".to_string(),
+            group: "fast".to_string(),
+            requested_model: "auto".to_string(),
+        };
+        let request = RequestWrapper::OpenAI(
+            serde_json::from_value(json!({"model": "auto", "messages": [{"role": "user", "content": "hi"}]})).unwrap(),
+        );
+        let client = LlmClient::new(Arc::new(reqwest::Client::new()), Arc::new(reqwest::Client::new()));
+        let identity = UpstreamIdentity::default();
+        let response = client.forward_request(&request, &model, &RequestId("req-2".to_string()), &identity, &rewrite, None).await.unwrap();
+        assert!(response.status().is_success());
+        upstream.assert();
+
+        assert_eq!(rewrite.render("{timestamp}", "r", "m", 1700000000), "1700000000");
+        assert_eq!(rendered_header_value("a\r\nX-Injected: 1\tb"), "aX-Injected: 1\tb");
+        assert_eq!(rendered_header_value("modèle").as_bytes(), "modèle".as_bytes());
+        assert_eq!(rewrite.render("{unknown} {}", "r", "m", 0), "{unknown} {}");
+        assert_eq!(rewrite.render("{{model}} {tenant", "r", "m", 0), "{m} {tenant");
+        // Values are inserted as they are, even when they look like placeholders
+        assert_eq!(rewrite.render("{model}", "r", "{request_id}", 0), "{request_id}");
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_header_replaces_router_headers() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A raw listener, to see repeated headers that a mock server would merge
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let captured = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            let mut buf = [0u8; 4096];
+            while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                head.extend_from_slice(&buf[..n]);
+            }
+            socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}").await.unwrap();
+            String::from_utf8_lossy(&head).to_lowercase()
+        });
+        let model: ModelConfig = serde_yaml::from_str(&format!(
+            "model_name: claude\nllm_params: {{api_type: anthropic, model: m, api_base: 'http://{}', api_key: k, \
+             rewrite_header: {{User-Agent: custom/1.0, anthropic-version: '2023-01-01'}}}}",
+            addr
+        ))
+        .unwrap();
+        let request = RequestWrapper::OpenAI(
+            serde_json::from_value(json!({"model": "claude", "messages": [{"role": "user", "content": "hi"}]})).unwrap(),
+        );
+        let client = LlmClient::new(Arc::new(reqwest::Client::new()), Arc::new(reqwest::Client::new()));
+        let identity = UpstreamIdentity::default();
+        let response = client.forward_request(&request, &model, &RequestId("req-3".to_string()), &identity, &RewriteContext::default(), None).await.unwrap();
+        assert!(response.status().is_success());
+
+        let head = captured.await.unwrap();
+        let values = |name: &str| head.lines().filter_map(|l| l.strip_prefix(name)).map(str::trim).collect::<Vec<_>>();
+        assert_eq!(values("user-agent:"), ["custom/1.0"]);
+        assert_eq!(values("anthropic-version:"), ["2023-01-01"]);
     }
 }
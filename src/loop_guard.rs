@@ -0,0 +1,157 @@
+//! Protection against proxy loops: an `api_base` that points back at the router
+//! would otherwise forward each request to itself until connections run out.
+//! Requests to such a model carry a hop count header, and a request that comes
+//! back too many times is refused. Other upstreams never see the header.
+
+use crate::auth::AppState;
+use crate::config::Config;
+use crate::error::RouterError;
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use tracing::warn;
+
+/// Routers a request has passed through; sent upstream incremented by one.
+pub const HOPS_HEADER: &str = "x-llm-router-hops";
+
+tokio::task_local! {
+    static HOPS: u32;
+}
+
+// Addresses (ip, port) this router serves inference on, set once at startup
+static LISTENERS: OnceLock<Vec<(String, u16)>> = OnceLock::new();
+
+/// Records the addresses this router serves inference on, for `is_self_target`.
+pub fn set_listeners(listeners: Vec<(String, u16)>) {
+    let _ = LISTENERS.set(listeners);
+}
+
+/// The addresses set by `set_listeners`, empty before that.
+pub fn listeners() -> &'static [(String, u16)] {
+    LISTENERS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Whether `api_base` points back at this router. Checked per request, so
+/// models added or changed by a reload are covered too.
+pub fn is_self_target(api_base: &str) -> bool {
+    listeners().iter().any(|(ip, port)| points_at(api_base, ip, *port))
+}
+
+/// Hop count of the request being handled, 0 outside a request.
+pub fn current_hops() -> u32 {
+    HOPS.try_with(|hops| *hops).unwrap_or(0)
+}
+
+/// Reject requests that already passed through `router_settings.max_hops`
+/// routers with 508, and make the count available to upstream requests.
+pub async fn check_hops(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let hops = parse_hops(req.headers().get(HOPS_HEADER).and_then(|v| v.to_str().ok()));
+    if let Err(e) = admit(&state, hops).await {
+        return e.into_response();
+    }
+    with_hops(hops, next.run(req)).await
+}
+
+/// Hop count carried in a `x-llm-router-hops` value, 0 when absent or malformed.
+pub fn parse_hops(value: Option<&str>) -> u32 {
+    value.and_then(|v| v.trim().parse::<u32>().ok()).unwrap_or(0)
+}
+
+/// The 508 for a request that reached `router_settings.max_hops`.
+pub async fn admit(state: &AppState, hops: u32) -> Result<(), RouterError> {
+    let max_hops = state.model_manager.read().await.get_config().router_settings.max_hops;
+    if hops >= max_hops {
+        warn!("Rejecting request after {} router hops; an api_base probably points back at this router", hops);
+        return Err(RouterError::client(
+            StatusCode::LOOP_DETECTED,
+            "loop_detected",
+            format!("request already passed through {} routers", hops),
+        ));
+    }
+    Ok(())
+}
+
+/// Runs `fut` with `hops` as the current hop count.
+pub async fn with_hops<F: Future>(hops: u32, fut: F) -> F::Output {
+    HOPS.scope(hops, fut).await
+}
+
+/// Run `fut` with the hop count of the calling task, for work handed to a
+/// spawned task.
+pub fn inherit<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    with_hops(current_hops(), fut)
+}
+
+/// Models (as `tenant/model` for tenants) whose api_base is this router itself
+/// on one of `listeners`, for reporting at startup and on reload.
+pub fn self_targets(config: &Config, listeners: &[(String, u16)]) -> Vec<String> {
+    let is_self = |api_base: &str| listeners.iter().any(|(ip, port)| points_at(api_base, ip, *port));
+    let mut found: Vec<String> = config
+        .model_list
+        .iter()
+        .filter(|m| is_self(&m.llm_params.api_base))
+        .map(|m| m.model_name.clone())
+        .collect();
+    for tenant in &config.tenants {
+        found.extend(
+            tenant.config.model_list.iter().filter(|m| is_self(&m.llm_params.api_base)).map(|m| format!("{}/{}", tenant.name, m.model_name)),
+        );
+    }
+    found
+}
+
+// A loopback or unspecified address, `localhost` or the listen address `ip`,
+// on the listen `port`
+fn points_at(api_base: &str, ip: &str, port: u16) -> bool {
+    let Ok(url) = reqwest::Url::parse(api_base) else { return false };
+    if url.port_or_known_default() != Some(port) {
+        return false;
+    }
+    let Some(host) = url.host_str() else { return false };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.eq_ignore_ascii_case("localhost") || host.eq_ignore_ascii_case(ip) {
+        return true;
+    }
+    let listen_ip = ip.parse::<IpAddr>().ok();
+    host.parse::<IpAddr>().is_ok_and(|addr| addr.is_loopback() || addr.is_unspecified() || Some(addr) == listen_ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_self_targets_and_hops() {
+        let config: Config = serde_yaml::from_str(
+            "model_list:
+  - model_name: self
+    llm_params: {api_type: openai, model: m, api_base: 'http://127.0.0.1:8000/v1', api_key: k}
+  - model_name: self_v6
+    llm_params: {api_type: openai, model: m, api_base: 'http://[::1]:8000/v1', api_key: k}
+  - model_name: other_port
+    llm_params: {api_type: openai, model: m, api_base: 'http://localhost:8001/v1', api_key: k}
+  - model_name: remote
+    llm_params: {api_type: openai, model: m, api_base: 'https://api.openai.com/v1', api_key: k}
+  - model_name: listen_ip
+    llm_params: {api_type: openai, model: m, api_base: 'http://10.1.2.3:8000', api_key: k}
+router_settings:
+  strategy: roundrobin
+  model_groups: []
+",
+        )
+        .unwrap();
+        let listen = |ip: &str, port: u16| vec![(ip.to_string(), port)];
+        assert_eq!(self_targets(&config, &listen("0.0.0.0", 8000)), vec!["self", "self_v6"]);
+        assert_eq!(self_targets(&config, &listen("10.1.2.3", 8000)), vec!["self", "self_v6", "listen_ip"]);
+        assert!(self_targets(&config, &listen("0.0.0.0", 443)).is_empty());
+        assert_eq!(self_targets(&config, &[("0.0.0.0".to_string(), 443), ("0.0.0.0".to_string(), 8001)]), vec!["other_port"]);
+
+        assert_eq!(current_hops(), 0);
+        assert_eq!(HOPS.scope(2, async { current_hops() }).await, 2);
+    }
+}
@@ -1,67 +1,131 @@
-mod auth;
-mod config;
-mod converters;
-mod models;
-mod model_manager;
-mod router;
-mod llm_client;
-mod request_id;
-mod utils;
-mod logging;
-mod model_checks;
-
+use llm_router::{
+    admin, auth, capabilities, config, convert, llm_client, logging, loop_guard, mcp, metrics, model_checks, model_manager, offload, panic_guard,
+    request_id, response_store, retry_queue, router, session_caps, size_stats, startup_report, warm_pool,
+};
 use axum::{
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    routing::{get, patch, post},
     Router,
 };
 use tower_http::cors::CorsLayer;
-use config::Config;
-use router::{anthropic_chat, openai_chat, gemini_chat, list_models};
+use config::{Config, RouteSet};
+use router::{anthropic_chat, openai_chat, gemini_chat, list_models, not_found};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{info, Level};
 use std::str::FromStr;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(name = "llm-router")]
 #[command(about = "A router for LLM API requests")]
 struct Args {
-    #[arg(short, long, default_value = "0.0.0.0")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(short, long, default_value = "0.0.0.0", global = true)]
     ip: String,
 
-    #[arg(short, long, default_value = "8000")]
+    #[arg(short, long, default_value = "8000", global = true)]
     port: u16,
 
     /// Path to config file
-    #[arg(short, long, default_value = "config.yaml")]
+    #[arg(short, long, default_value = "config.yaml", global = true)]
     config: String,
 
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     token: Option<String>,
 
     /// trace, debug, info, warn, error
-    #[arg(short, long, default_value = "warn")]
+    #[arg(short, long, default_value = "warn", global = true)]
     log_level: String,
 
     /// Also write logs to this file (max 10MB)
-    #[arg(long)]
+    #[arg(long, global = true)]
     log_file: Option<String>,
 
     /// socks and http proxy, example: socks5://192.168.0.2:10080
-    #[arg(long)]
+    #[arg(long, global = true)]
     proxy: Option<String>,
 
-    /// Check availability of all models in config and exit
-    #[arg(long)]
+    /// Username for proxy authentication
+    #[arg(long, requires = "proxy", global = true)]
+    proxy_user: Option<String>,
+
+    /// Password for proxy authentication, used with --proxy-user
+    #[arg(long, env = "LLM_ROUTER_PROXY_PASSWORD", hide_env_values = true, global = true)]
+    proxy_password: Option<String>,
+
+    /// Comma-separated hosts that bypass the proxy, example: localhost,10.0.0.0/8,.internal
+    #[arg(long, requires = "proxy", global = true)]
+    no_proxy: Option<String>,
+
+    /// Same as the check subcommand, kept for existing scripts
+    #[arg(long, hide = true)]
     check: bool,
+
+    /// Override a config value, example: --set router_settings.strategy=leastconn
+    /// (repeatable; list items by index or name, e.g. model_list.gpt4.llm_params.api_key=...)
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    set: Vec<String>,
+
+    /// Seed random routing picks so they repeat across runs (same as router_settings.routing_seed)
+    #[arg(long, value_name = "SEED", global = true)]
+    routing_seed: Option<u64>,
+
+    /// Summary printed to stderr once the server is listening: text, json or off
+    #[arg(long, value_name = "FORMAT", default_value = "text", value_parser = ["text", "json", "off"], global = true)]
+    startup_report: String,
+
+    /// Also serve the gRPC interface (proto/llm_router.proto) on this port
+    #[cfg(feature = "grpc")]
+    #[arg(long, global = true)]
+    grpc_port: Option<u16>,
+}
+
+// Without a subcommand the router serves, as it did before subcommands existed
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the router (the default)
+    Serve,
+    /// Check availability of all models in config and exit
+    Check,
+    /// Load the config with its --set overrides, print its warnings and exit
+    Validate,
+    /// Send one model repeated one-token requests and report upstream latency
+    Bench {
+        /// model_name from model_list
+        #[arg(long)]
+        model: String,
+        #[arg(long, default_value = "10")]
+        requests: usize,
+        #[arg(long, default_value = "1")]
+        concurrency: usize,
+        /// text or json
+        #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+        format: String,
+    },
+    /// Convert a request or response body between API formats, as the router would
+    Convert {
+        #[arg(long, default_value = "request", value_parser = ["request", "response"])]
+        kind: String,
+        #[arg(long, value_parser = ["openai", "anthropic", "gemini"])]
+        from: String,
+        #[arg(long, value_parser = ["openai", "anthropic", "gemini"])]
+        to: String,
+        /// Go through the OpenAI format even where a direct converter exists
+        #[arg(long)]
+        via_openai: bool,
+        /// JSON file to convert; reads stdin when omitted
+        input: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Parse command line arguments
-    let args = Args::parse();
-    let ip = args.ip;
+    let mut args = Args::parse();
+    let ip = args.ip.clone();
     let port = args.port;
 
     // Parse log level
@@ -70,71 +134,325 @@ async fn main() -> anyhow::Result<()> {
         Level::INFO
     });
 
+    let command = match args.command.take() {
+        Some(command) => command,
+        None if args.check => Command::Check,
+        None => Command::Serve,
+    };
+    // Needs no config, and runs before logging so stdout holds only the converted JSON
+    if let Command::Convert { kind, from, to, via_openai, input } = &command {
+        return run_convert(kind, from, to, *via_openai, input.as_deref()).await;
+    }
+
     // Initialize logging: always log to stdout, optionally also to file (capped at 10MB)
     logging::init_logging(log_level, args.log_file.as_deref());
 
     // Load configuration
     let config_path = args.config.clone();
-    let config = Arc::new(Config::from_file(&config_path)?);
+    let mut overrides = args.set.clone();
+    if let Some(seed) = args.routing_seed {
+        overrides.push(format!("router_settings.routing_seed={}", seed));
+    }
+    let config = Arc::new(Config::from_file_with_overrides(&config_path, &overrides)?);
     info!("Configuration loaded successfully from: {}", config_path);
+    if !overrides.is_empty() {
+        // Keys only; values may be secrets
+        let keys: Vec<&str> = overrides.iter().map(|s| s.split('=').next().unwrap_or_default()).collect();
+        info!("Applied config overrides: {}", keys.join(", "));
+    }
+
+    // A model served by this very router would forward every request back to it;
+    // such requests carry the hop count so the loop is cut off after max_hops
+    let mut inference_listeners = vec![(ip.clone(), port)];
+    for extra in config.router_settings.listeners.iter().filter(|l| l.routes.contains(&RouteSet::Inference)) {
+        inference_listeners.push((extra.ip.clone().unwrap_or_else(|| ip.clone()), extra.port));
+    }
+    loop_guard::set_listeners(inference_listeners);
+    let self_targets = loop_guard::self_targets(&config, loop_guard::listeners());
+    if !self_targets.is_empty() {
+        tracing::warn!(
+            "api_base of {} points back at this router; requests to them are refused after {} hops",
+            self_targets.join(", "),
+            config.router_settings.max_hops
+        );
+    }
 
-    // Create a reqwest client
+    // Create reqwest clients: one honoring --proxy, one always connecting directly
+    // for models that opt out via `use_proxy: false`.
     let client_builder = reqwest::Client::builder();
-    let client_builder = if let Some(proxy) = &args.proxy {
-        let proxy = reqwest::Proxy::all(proxy).expect("Failed to create proxy");
-        client_builder.proxy(proxy)
-    } else {
-        client_builder
+    let client_builder = match build_proxy(&args)? {
+        Some(proxy) => client_builder.proxy(proxy),
+        None => client_builder,
     };
     let http_client = Arc::new(client_builder.build().expect("Failed to build HTTP client"));
+    let direct_client = Arc::new(
+        reqwest::Client::builder()
+            .no_proxy()
+            .build()
+            .expect("Failed to build HTTP client"),
+    );
 
     // Create LlmClient
-    let llm_client = Arc::new(llm_client::LlmClient::new(http_client));
+    let llm_client = Arc::new(llm_client::LlmClient::new(http_client.clone(), direct_client));
 
-    // If --check is provided, verify all models and exit
-    if args.check {
-        model_checks::perform_model_checks(&config, &llm_client).await?;
-        return Ok(());
+    match command {
+        Command::Check => {
+            model_checks::perform_model_checks(&config, &llm_client).await?;
+            return Ok(());
+        }
+        Command::Validate => {
+            let report = startup_report::StartupReport::new(&config, Vec::new());
+            println!(
+                "Config {} is valid: {} models, {} groups",
+                config_path,
+                config.model_list.len(),
+                config.router_settings.model_groups.len()
+            );
+            for warning in &report.warnings {
+                println!("warning: {}", warning);
+            }
+            for key in &report.unknown_keys {
+                println!("warning: unknown key {} is ignored", key);
+            }
+            return Ok(());
+        }
+        Command::Bench { model, requests, concurrency, format } => {
+            let report = model_checks::bench_model(&config, &llm_client, &model, requests, concurrency).await?;
+            match format.as_str() {
+                "json" => println!("{}", serde_json::to_string(&report)?),
+                _ => print_bench_report(&report),
+            }
+            return Ok(());
+        }
+        Command::Serve | Command::Convert { .. } => {}
     }
 
     // Create model manager with RwLock for dynamic updates
     let model_manager = Arc::new(RwLock::new(model_manager::ModelManager::new(config.clone())));
 
+    // Warm start: pick up breaker/weight state saved before the last shutdown
+    let health_state_path = config.router_settings.health_state.path.clone();
+    if let Some(path) = &health_state_path {
+        match model_manager::StateSnapshot::load(path) {
+            Ok(Some(snapshot)) => {
+                let restored = model_manager.read().await.restore(&snapshot);
+                info!("Restored health state for {} group members from {}", restored, path);
+            }
+            Ok(None) => info!("No health state at {}, starting fresh", path),
+            Err(e) => tracing::warn!("Ignoring unreadable health state {}: {}", path, e),
+        }
+        tokio::spawn(model_manager::run_state_saver(
+            model_manager.clone(),
+            path.clone(),
+            std::time::Duration::from_secs(config.router_settings.health_state.save_interval_secs.max(1)),
+        ));
+    }
+
     // Create app state with model manager and token
     let app_state = auth::AppState {
         model_manager: model_manager.clone(),
         token: args.token,
         llm_client,
+        metrics: Arc::new(metrics::Metrics::default()),
+        config_path: config_path.clone(),
+        config_overrides: Arc::new(overrides),
+        response_store: Arc::new(response_store::ResponseStore::new(&config.router_settings.response_store)),
+        mcp: Arc::new(mcp::McpGateway::new(http_client)),
+        sessions: Arc::new(session_caps::SessionLedger::default()),
+        sizes: Arc::new(size_stats::SizeStats::default()),
+        retry_queue: Arc::new(retry_queue::RetryQueue::default()),
+        tenants: Arc::new(
+            config
+                .tenants
+                .iter()
+                .map(|t| {
+                    let manager = model_manager::ModelManager::new(Arc::new(t.config.clone()));
+                    (t.name.clone(), Arc::new(RwLock::new(manager)))
+                })
+                .collect(),
+        ),
+        tenant_index: Arc::new(auth::TenantIndex::new(&config)),
+        tenant: None,
     };
 
+    // Started only when configured at startup; reloads then adjust the pings but cannot start them
+    if warm_pool::wanted(&config) || config.tenants.iter().any(|t| warm_pool::wanted(&t.config)) {
+        let warmed = std::iter::once(app_state.model_manager.clone()).chain(app_state.tenants.values().cloned()).collect();
+        tokio::spawn(warm_pool::run(warmed, app_state.llm_client.clone()));
+    }
+
+    let mut listeners = Vec::new();
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_port) = args.grpc_port {
+        let grpc_address: std::net::SocketAddr = format!("{}:{}", ip, grpc_port).parse()?;
+        let service = llm_router::grpc::LlmRouterServer::new(app_state.clone());
+        info!("gRPC server started on {}", grpc_address);
+        listeners.push(format!("grpc://{}", grpc_address));
+        tokio::spawn(async move {
+            let server = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_shutdown(grpc_address, shutdown_signal());
+            if let Err(e) = server.await {
+                tracing::error!("gRPC server failed: {}", e);
+            }
+        });
+    }
+
+    // Extra listeners share the state; each gets its own endpoint sets and auth
+    for extra in &config.router_settings.listeners {
+        let extra_ip = extra.ip.clone().unwrap_or_else(|| ip.clone());
+        let extra_address = format!("{}:{}", extra_ip, extra.port);
+        let extra_listener = tokio::net::TcpListener::bind(&extra_address).await?;
+        let extra_app = build_app(app_state.clone(), &extra.routes, extra.auth, config.router_settings.max_request_bytes);
+        info!("Listener started on http://{}", extra_address);
+        listeners.push(format!("http://{}", extra_address));
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(extra_listener, extra_app).with_graceful_shutdown(shutdown_signal()).await {
+                tracing::error!("Listener {} failed: {}", extra_address, e);
+            }
+        });
+    }
+
     // Create router
-    let app = Router::new()
-        .route("/v1/chat/completions", post(openai_chat))
-        .route("/v1/messages", post(anthropic_chat))
-        .route("/v1beta/models/{*tail}", post(gemini_chat))
-        .route("/v1/models", get(list_models))
-        .route("/health", get(|| async { "OK" }))
-        .layer(axum::middleware::from_fn_with_state(
-            app_state.clone(),
-            auth::require_authorization,
-        ))
-        .layer(CorsLayer::permissive())
-        .layer(axum::middleware::from_fn(request_id::inject_request_id))
-        .with_state(app_state);
+    let app = build_app(app_state, &[RouteSet::Inference, RouteSet::Admin, RouteSet::Metrics], true, config.router_settings.max_request_bytes);
 
     // Start server
     let bind_address = format!("{}:{}", ip, port);
     let listener = tokio::net::TcpListener::bind(&bind_address).await?;
     info!("Server started on http://{}", bind_address);
+    listeners.insert(0, format!("http://{}", bind_address));
+    let report = startup_report::StartupReport::new(&config, listeners);
+    match args.startup_report.as_str() {
+        // Logs go to stdout, so the report goes to stderr to stay parseable
+        "json" => eprintln!("{}", serde_json::to_string(&report)?),
+        "text" => eprint!("{}", report.render_text()),
+        _ => {}
+    }
+    for warning in &report.warnings {
+        tracing::warn!("Config: {}", warning);
+    }
 
     // Graceful shutdown: stop accepting new connections on Ctrl+C/SIGTERM
     // and wait for in-flight requests to complete.
     axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal())
         .await?;
+    if let Some(path) = &health_state_path {
+        model_manager::save_state(&model_manager, path).await;
+    }
     Ok(())
 }
 
+/// The router for one listener: the chosen endpoint sets plus /health, behind
+/// the shared middleware stack. `auth: false` drops the authorization layer.
+fn build_app(app_state: auth::AppState, routes: &[RouteSet], auth: bool, max_request_bytes: usize) -> Router {
+    let mut app = Router::new().route("/health", get(|| async { "OK" }));
+    if routes.contains(&RouteSet::Inference) {
+        app = app
+            .route("/v1/chat/completions", post(openai_chat))
+            .route("/v1/messages", post(anthropic_chat))
+            .route("/v1beta/models/{*tail}", post(gemini_chat))
+            .route("/v1/models/{*tail}", post(gemini_chat))
+            .route("/v1/models", get(list_models))
+            .route("/v1/capabilities", get(capabilities::capabilities))
+            .route("/v1/responses/{id}/events", get(response_store::resume_events));
+    }
+    if routes.contains(&RouteSet::Metrics) {
+        app = app.route("/metrics", get(metrics::metrics_handler));
+    }
+    if routes.contains(&RouteSet::Admin) {
+        app = app
+            .route("/admin/groups", get(admin::group_status))
+            .route("/admin/groups/{group}/weights", patch(admin::patch_group_weights))
+            .route("/admin/reload", post(admin::reload_config))
+            .route("/admin/orphans", get(admin::orphaned_state))
+            .route("/admin/health/models", get(admin::model_health))
+            .route("/admin/health/models/reset-peaks", post(admin::reset_peaks))
+            .route("/admin/heavy-hitters", get(admin::heavy_hitters));
+    }
+    let mut app = app
+        .fallback(not_found)
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            session_caps::enforce,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            size_stats::record,
+        ));
+    if auth {
+        app = app.layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::require_authorization,
+        ));
+    }
+    app.layer(axum::middleware::from_fn_with_state(
+            (app_state.metrics.clone(), max_request_bytes),
+            panic_guard::catch_panics,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            metrics::record_errors,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            loop_guard::check_hops,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            offload::track,
+        ))
+        .layer(DefaultBodyLimit::max(max_request_bytes))
+        .layer(CorsLayer::permissive())
+        .layer(axum::middleware::from_fn(request_id::inject_request_id))
+        .with_state(app_state)
+}
+
+async fn run_convert(kind: &str, from: &str, to: &str, via_openai: bool, input: Option<&str>) -> anyhow::Result<()> {
+    let api_type = |name: &str| serde_json::from_value::<config::ApiType>(serde_json::Value::from(name));
+    let kind = if kind == "response" { convert::ConvertKind::Response } else { convert::ConvertKind::Request };
+    let body = match input {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?,
+        None => std::io::read_to_string(std::io::stdin())?,
+    };
+    println!("{}", convert::convert(kind, api_type(from)?, api_type(to)?, &body, via_openai).await?);
+    Ok(())
+}
+
+fn print_bench_report(report: &model_checks::BenchReport) {
+    let ms = |v: Option<u64>| v.map_or_else(|| "-".to_string(), |v| format!("{}ms", v));
+    println!(
+        "{}: {} ok, {} failed; p50 {}, p90 {}, p99 {}, max {}",
+        report.model,
+        report.succeeded,
+        report.failed,
+        ms(report.p50_ms),
+        ms(report.p90_ms),
+        ms(report.p99_ms),
+        ms(report.max_ms)
+    );
+    if let Some(error) = &report.first_error {
+        println!("  first error: {}", error);
+    }
+}
+
+// Builds the upstream proxy from --proxy, --proxy-user/--proxy-password and --no-proxy.
+fn build_proxy(args: &Args) -> anyhow::Result<Option<reqwest::Proxy>> {
+    let Some(url) = &args.proxy else { return Ok(None) };
+    let mut proxy = reqwest::Proxy::all(url)
+        .map_err(|e| anyhow::anyhow!("Invalid proxy '{}': {}", url, e))?;
+    if let Some(user) = &args.proxy_user {
+        if url.starts_with("socks4") {
+            return Err(anyhow::anyhow!("Proxy authentication is not supported for socks4 proxies"));
+        }
+        proxy = proxy.basic_auth(user, args.proxy_password.as_deref().unwrap_or(""));
+    }
+    if let Some(list) = &args.no_proxy {
+        proxy = proxy.no_proxy(reqwest::NoProxy::from_string(list));
+    }
+    Ok(Some(proxy))
+}
+
 // Waits for Ctrl+C (all platforms) or SIGTERM (unix) and returns.
 async fn shutdown_signal() {
     // Listen for Ctrl+C
@@ -0,0 +1,283 @@
+//! Tool gateway for MCP (Model Context Protocol) servers. Tools listed by the
+//! configured servers are advertised to the model as `<server>__<tool>`; when the
+//! model calls only such tools, the router runs them and sends the results back,
+//! so clients get tool execution without speaking MCP. Only the Streamable HTTP
+//! transport is supported.
+
+use crate::config::{McpServer, McpSettings};
+use crate::router_tools::ToolDefinition;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+const PROTOCOL_VERSION: &str = "2025-03-26";
+const SESSION_HEADER: &str = "mcp-session-id";
+
+/// A tool offered by an MCP server.
+#[derive(Debug, Clone)]
+pub struct McpTool {
+    pub server: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub input_schema: Value,
+}
+
+impl McpTool {
+    /// Name the model sees; server names never contain `__`, so it splits back unambiguously.
+    pub fn exposed_name(&self) -> String {
+        format!("{}__{}", self.server, self.name)
+    }
+
+    pub fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.exposed_name(),
+            description: self.description.clone().unwrap_or_default(),
+            parameters: self.input_schema.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ServerState {
+    url: String,
+    session: Option<String>,
+    tools: Vec<McpTool>,
+    listed: Instant,
+}
+
+/// Sessions and tool lists of the configured servers, shared by all requests.
+#[derive(Debug)]
+pub struct McpGateway {
+    client: Arc<reqwest::Client>,
+    servers: Mutex<HashMap<String, ServerState>>,
+    next_id: AtomicU64,
+}
+
+impl McpGateway {
+    pub fn new(client: Arc<reqwest::Client>) -> Self {
+        Self { client, servers: Mutex::new(HashMap::new()), next_id: AtomicU64::new(1) }
+    }
+
+    /// Tools of every server in `settings`, listed again once `tools_ttl_secs` have
+    /// passed. A server that cannot be reached is skipped with a warning.
+    pub async fn tools(&self, settings: &McpSettings) -> Vec<McpTool> {
+        let ttl = Duration::from_secs(settings.tools_ttl_secs);
+        let mut tools = Vec::new();
+        for server in &settings.servers {
+            let cached = {
+                let servers = self.servers.lock().unwrap();
+                servers
+                    .get(&server.name)
+                    .filter(|s| s.url == server.url && s.listed.elapsed() < ttl)
+                    .map(|s| s.tools.clone())
+            };
+            if let Some(cached) = cached {
+                tools.extend(cached);
+                continue;
+            }
+            match self.list_tools(server).await {
+                Ok((session, listed)) => {
+                    debug!("MCP server '{}' offers {} tools", server.name, listed.len());
+                    tools.extend(listed.iter().cloned());
+                    let state = ServerState { url: server.url.clone(), session, tools: listed, listed: Instant::now() };
+                    self.servers.lock().unwrap().insert(server.name.clone(), state);
+                }
+                Err(e) => warn!("Skipping MCP server '{}': {}", server.name, e),
+            }
+        }
+        tools
+    }
+
+    /// Run `tool` on `server` and return its output as text for the model. Failures
+    /// are reported in the text too, so the model can react to them.
+    pub async fn call(&self, server: &McpServer, tool: &str, arguments: Value) -> String {
+        let params = json!({"name": tool, "arguments": arguments});
+        let session = self.servers.lock().unwrap().get(&server.name).and_then(|s| s.session.clone());
+        let mut result = self.request(server, session.as_deref(), "tools/call", params.clone()).await;
+        // Sessions expire on server restarts; start a new one and try once more
+        if result.is_err() {
+            match self.initialize(server).await {
+                Ok(session) => {
+                    if let Some(state) = self.servers.lock().unwrap().get_mut(&server.name) {
+                        state.session = session.clone();
+                    }
+                    result = self.request(server, session.as_deref(), "tools/call", params).await;
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+        match result {
+            Ok((result, _)) => result_text(&result),
+            Err(e) => {
+                warn!("MCP tool '{}' on server '{}' failed: {}", tool, server.name, e);
+                format!("Error: tool call failed: {}", e)
+            }
+        }
+    }
+
+    async fn list_tools(&self, server: &McpServer) -> Result<(Option<String>, Vec<McpTool>), String> {
+        let session = self.initialize(server).await?;
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({"cursor": cursor}),
+                None => json!({}),
+            };
+            let (result, _) = self.request(server, session.as_deref(), "tools/list", params).await?;
+            for tool in result["tools"].as_array().into_iter().flatten() {
+                let Some(name) = tool["name"].as_str() else { continue };
+                tools.push(McpTool {
+                    server: server.name.clone(),
+                    name: name.to_string(),
+                    description: tool["description"].as_str().map(str::to_string),
+                    input_schema: tool.get("inputSchema").cloned().unwrap_or_else(|| json!({"type": "object"})),
+                });
+            }
+            cursor = result["nextCursor"].as_str().map(str::to_string);
+            if cursor.is_none() {
+                return Ok((session, tools));
+            }
+        }
+    }
+
+    // Handshake: `initialize`, then the `notifications/initialized` notification
+    async fn initialize(&self, server: &McpServer) -> Result<Option<String>, String> {
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": {"name": "llm-router", "version": env!("CARGO_PKG_VERSION")},
+        });
+        let (_, session) = self.request(server, None, "initialize", params).await?;
+        let notification = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        self.post(server, session.as_deref(), &notification).await?;
+        Ok(session)
+    }
+
+    // One JSON-RPC request; returns the result and the session id the server assigned, if any
+    async fn request(&self, server: &McpServer, session: Option<&str>, method: &str, params: Value) -> Result<(Value, Option<String>), String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let message = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        let response = self.post(server, session, &message).await?;
+        let session = response
+            .headers()
+            .get(SESSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .or_else(|| session.map(str::to_string));
+        let is_sse = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+        let body = response.text().await.map_err(|e| e.to_string())?;
+        let reply = if is_sse {
+            // The reply may follow requests and notifications the server sends first
+            body.lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+                .find(|message| message["id"] == json!(id))
+                .ok_or_else(|| format!("no reply to {} in event stream", method))?
+        } else {
+            serde_json::from_str::<Value>(&body).map_err(|e| format!("invalid reply to {}: {}", method, e))?
+        };
+        if let Some(error) = reply.get("error") {
+            return Err(format!("{} failed: {}", method, error["message"].as_str().unwrap_or("unknown error")));
+        }
+        Ok((reply["result"].clone(), session))
+    }
+
+    async fn post(&self, server: &McpServer, session: Option<&str>, message: &Value) -> Result<reqwest::Response, String> {
+        let mut request = self
+            .client
+            .post(&server.url)
+            .timeout(Duration::from_millis(server.timeout_ms))
+            .header(reqwest::header::ACCEPT, "application/json, text/event-stream")
+            .header("mcp-protocol-version", PROTOCOL_VERSION)
+            .json(message);
+        for (name, value) in &server.headers {
+            request = request.header(name, value);
+        }
+        if let Some(session) = session {
+            request = request.header(SESSION_HEADER, session);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("status {}", response.status()));
+        }
+        Ok(response)
+    }
+}
+
+// Text content joined; other content (images, resources) is passed on as JSON
+fn result_text(result: &Value) -> String {
+    let text = result["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|item| match item["text"].as_str() {
+            Some(text) if item["type"] == "text" => text.to_string(),
+            _ => item.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if result["isError"].as_bool().unwrap_or(false) { format!("Error: {}", text) } else { text }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(url: String) -> McpServer {
+        McpServer { name: "files".to_string(), url, headers: Default::default(), timeout_ms: 5_000 }
+    }
+
+    #[tokio::test]
+    async fn test_lists_and_calls_tools() {
+        let mut upstream = mockito::Server::new_async().await;
+        let _init = upstream
+            .mock("POST", "/mcp")
+            .match_body(mockito::Matcher::PartialJson(json!({"method": "initialize"})))
+            .with_header("mcp-session-id", "s1")
+            .with_body(json!({"jsonrpc": "2.0", "id": 1, "result": {"protocolVersion": PROTOCOL_VERSION}}).to_string())
+            .create();
+        let _initialized = upstream
+            .mock("POST", "/mcp")
+            .match_body(mockito::Matcher::PartialJson(json!({"method": "notifications/initialized"})))
+            .with_status(202)
+            .create();
+        let _list = upstream
+            .mock("POST", "/mcp")
+            .match_header("mcp-session-id", "s1")
+            .match_body(mockito::Matcher::PartialJson(json!({"method": "tools/list"})))
+            .with_body(
+                json!({"jsonrpc": "2.0", "id": 2, "result": {"tools": [{"name": "read", "description": "Read a file", "inputSchema": {"type": "object"}}]}})
+                    .to_string(),
+            )
+            .create();
+        // Replies may come as an event stream
+        let _call = upstream
+            .mock("POST", "/mcp")
+            .match_header("mcp-session-id", "s1")
+            .match_body(mockito::Matcher::PartialJson(json!({"method": "tools/call", "params": {"name": "read"}})))
+            .with_header("content-type", "text/event-stream")
+            .with_body(format!(
+                "event: message\ndata: {}\n\n",
+                json!({"jsonrpc": "2.0", "id": 3, "result": {"content": [{"type": "text", "text": "hello"}]}})
+            ))
+            .create();
+
+        let gateway = McpGateway::new(Arc::new(reqwest::Client::new()));
+        let settings = McpSettings { servers: vec![server(format!("{}/mcp", upstream.url()))], ..Default::default() };
+        let tools = gateway.tools(&settings).await;
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].exposed_name(), "files__read");
+        assert_eq!(gateway.call(&settings.servers[0], "read", json!({"path": "a.txt"})).await, "hello");
+
+        let unreachable = McpSettings { servers: vec![server("http://127.0.0.1:1/mcp".to_string())], ..Default::default() };
+        assert!(gateway.tools(&unreachable).await.is_empty());
+    }
+}
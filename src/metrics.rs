@@ -0,0 +1,233 @@
+use crate::auth::AppState;
+use crate::config::ApiType;
+use crate::error::ErrorKind;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Process-wide counters exposed on `/metrics` in Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    errors: [AtomicU64; ErrorKind::ALL.len()],
+    panics: AtomicU64,
+    // Output validation retries, indexed by whether the retry's answer passed
+    validation_retries: [AtomicU64; 2],
+}
+
+impl Metrics {
+    pub fn record_error(&self, kind: ErrorKind) {
+        self.errors[kind as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn error_count(&self, kind: ErrorKind) -> u64 {
+        self.errors[kind as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn record_panic(&self) {
+        self.panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn panic_count(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    pub fn record_validation_retry(&self, fixed: bool) {
+        self.validation_retries[fixed as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn validation_retry_count(&self, fixed: bool) -> u64 {
+        self.validation_retries[fixed as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# TYPE llm_router_errors_total counter\n");
+        for kind in ErrorKind::ALL {
+            let _ = writeln!(
+                out,
+                "llm_router_errors_total{{kind=\"{}\",retryable=\"{}\"}} {}",
+                kind.as_str(),
+                kind.is_retryable(),
+                self.error_count(kind)
+            );
+        }
+        out.push_str("# TYPE llm_router_panics_total counter\n");
+        let _ = writeln!(out, "llm_router_panics_total {}", self.panic_count());
+        out.push_str("# TYPE llm_router_validation_retries_total counter\n");
+        for (outcome, fixed) in [("fixed", true), ("failed", false)] {
+            let _ = writeln!(
+                out,
+                "llm_router_validation_retries_total{{outcome=\"{}\"}} {}",
+                outcome,
+                self.validation_retry_count(fixed)
+            );
+        }
+        render_conversions(&mut out);
+        out
+    }
+}
+
+/// What a timed format conversion handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionKind {
+    Request,
+    Response,
+    StreamChunk,
+}
+
+impl ConversionKind {
+    const ALL: [ConversionKind; 3] = [ConversionKind::Request, ConversionKind::Response, ConversionKind::StreamChunk];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ConversionKind::Request => "request",
+            ConversionKind::Response => "response",
+            ConversionKind::StreamChunk => "stream_chunk",
+        }
+    }
+}
+
+const API_TYPES: [ApiType; 3] = [ApiType::OpenAI, ApiType::Anthropic, ApiType::Gemini];
+
+pub(crate) fn api_name(api_type: &ApiType) -> &'static str {
+    match api_type {
+        ApiType::OpenAI => "openai",
+        ApiType::Anthropic => "anthropic",
+        ApiType::Gemini => "gemini",
+    }
+}
+
+#[derive(Debug)]
+struct ConversionStat {
+    count: AtomicU64,
+    nanos: AtomicU64,
+}
+
+// Conversions run deep inside the converters, away from AppState, so their
+// timings are kept process-wide, indexed by kind, source and target format
+static CONVERSIONS: [[[ConversionStat; 3]; 3]; 3] =
+    [const { [const { [const { ConversionStat { count: AtomicU64::new(0), nanos: AtomicU64::new(0) } }; 3] }; 3] }; 3];
+
+fn conversion_stat(kind: ConversionKind, from: &ApiType, to: &ApiType) -> &'static ConversionStat {
+    let index = |api_type: &ApiType| API_TYPES.iter().position(|a| a == api_type).unwrap_or(0);
+    &CONVERSIONS[kind as usize][index(from)][index(to)]
+}
+
+pub fn record_conversion(kind: ConversionKind, from: &ApiType, to: &ApiType, elapsed: Duration) {
+    let stat = conversion_stat(kind, from, to);
+    stat.count.fetch_add(1, Ordering::Relaxed);
+    stat.nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Run `convert` (parsing included) and record how long it took for this pair.
+pub fn time_conversion<T>(kind: ConversionKind, from: &ApiType, to: &ApiType, convert: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = convert();
+    record_conversion(kind, from, to, started.elapsed());
+    result
+}
+
+/// (conversions, total time) recorded so far for one pair.
+pub fn conversion_totals(kind: ConversionKind, from: &ApiType, to: &ApiType) -> (u64, Duration) {
+    let stat = conversion_stat(kind, from, to);
+    (stat.count.load(Ordering::Relaxed), Duration::from_nanos(stat.nanos.load(Ordering::Relaxed)))
+}
+
+// Only pairs that have been used are listed
+fn render_conversions(out: &mut String) {
+    let mut counts = String::new();
+    let mut seconds = String::new();
+    for kind in ConversionKind::ALL {
+        for from in &API_TYPES {
+            for to in &API_TYPES {
+                let (count, elapsed) = conversion_totals(kind, from, to);
+                if count == 0 {
+                    continue;
+                }
+                let labels = format!("kind=\"{}\",from=\"{}\",to=\"{}\"", kind.as_str(), api_name(from), api_name(to));
+                let _ = writeln!(counts, "llm_router_conversions_total{{{}}} {}", labels, count);
+                let _ = writeln!(seconds, "llm_router_conversion_seconds_total{{{}}} {:.6}", labels, elapsed.as_secs_f64());
+            }
+        }
+    }
+    out.push_str("# TYPE llm_router_conversions_total counter\n");
+    out.push_str(&counts);
+    out.push_str("# TYPE llm_router_conversion_seconds_total counter\n");
+    out.push_str(&seconds);
+}
+
+// Counts classified errors attached to responses by `RouterError::into_response`
+pub async fn record_errors(State(app_state): State<AppState>, request: Request, next: Next) -> Response {
+    let resp = next.run(request).await;
+    if let Some(kind) = resp.extensions().get::<ErrorKind>() {
+        app_state.metrics.record_error(*kind);
+    }
+    resp
+}
+
+pub async fn metrics_handler(State(app_state): State<AppState>) -> impl IntoResponse {
+    let mut body = app_state.metrics.render();
+    app_state.sizes.render(&mut body);
+    body.push_str("# TYPE llm_router_retry_queue_waiting gauge\n");
+    let _ = writeln!(body, "llm_router_retry_queue_waiting {}", app_state.retry_queue.waiting());
+    // In-flight requests per group member; direct model calls have an empty group
+    // label and the root config an empty tenant label
+    body.push_str("# TYPE llm_router_active_requests gauge\n");
+    let mut managers = vec![(String::new(), app_state.model_manager.clone())];
+    managers.extend(app_state.tenants.iter().map(|(name, m)| (name.clone(), m.clone())));
+    managers.sort_by(|a, b| a.0.cmp(&b.0));
+    for (tenant, model_manager) in &managers {
+        for (group, model, count) in model_manager.read().await.active_counts() {
+            let _ = writeln!(
+                body,
+                "llm_router_active_requests{{tenant=\"{}\",group=\"{}\",model=\"{}\"}} {}",
+                tenant, group, model, count
+            );
+        }
+    }
+    // High-water marks per model since start or the last reset-peaks
+    let mut peak_active = String::new();
+    let mut peak_queued = String::new();
+    for (tenant, model_manager) in &managers {
+        for (model, in_flight, queued) in model_manager.read().await.peak_counts() {
+            let labels = format!("tenant=\"{}\",model=\"{}\"", tenant, model);
+            let _ = writeln!(peak_active, "llm_router_peak_active_requests{{{}}} {}", labels, in_flight);
+            if let Some(queued) = queued {
+                let _ = writeln!(peak_queued, "llm_router_peak_queued_requests{{{}}} {}", labels, queued);
+            }
+        }
+    }
+    body.push_str("# TYPE llm_router_peak_active_requests gauge\n");
+    body.push_str(&peak_active);
+    body.push_str("# TYPE llm_router_peak_queued_requests gauge\n");
+    body.push_str(&peak_queued);
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        body,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversion_timings_render_per_pair() {
+        let (before, _) = conversion_totals(ConversionKind::Response, &ApiType::Gemini, &ApiType::Anthropic);
+        let value = time_conversion(ConversionKind::Response, &ApiType::Gemini, &ApiType::Anthropic, || 42);
+        assert_eq!(value, 42);
+        record_conversion(ConversionKind::Response, &ApiType::Gemini, &ApiType::Anthropic, Duration::from_millis(2));
+        let (after, elapsed) = conversion_totals(ConversionKind::Response, &ApiType::Gemini, &ApiType::Anthropic);
+        assert!(after >= before + 2);
+        assert!(elapsed >= Duration::from_millis(2));
+
+        let out = Metrics::default().render();
+        assert!(out.contains("llm_router_conversions_total{kind=\"response\",from=\"gemini\",to=\"anthropic\"}"));
+        assert!(out.contains("llm_router_conversion_seconds_total{kind=\"response\",from=\"gemini\",to=\"anthropic\"}"));
+    }
+}
@@ -1,107 +1,255 @@
 use std::sync::Arc;
-use crate::config::Config;
+use crate::config::{ApiType, Config, LLMParams, ModelConfig, UpstreamIdentity};
+use crate::converters::request_wrapper::RequestWrapper;
+use crate::llm_client::{LlmClient, RewriteContext};
+use crate::converters::openai::{OpenAIRequest, OpenAIMessage, OpenAIContent};
+use crate::converters::anthropic::{AnthropicRequest, AnthropicMessage, AnthropicContent};
+use crate::converters::gemini::{GeminiRequest, gemini_content::GeminiContent, gemini_part::GeminiPart, gemini_generation_config::GeminiGenerationConfig};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::time::Duration;
+
+// Probes sent at once
+const CONCURRENCY: usize = 20;
 
 pub async fn perform_model_checks(
     config: &Arc<Config>,
-    llm_client: &Arc<crate::llm_client::LlmClient>,
+    llm_client: &Arc<LlmClient>,
 ) -> anyhow::Result<()> {
-    use crate::config::ApiType;
-    use crate::converters::request_wrapper::RequestWrapper;
-    use crate::converters::openai::{OpenAIRequest, OpenAIMessage, OpenAIContent};
-    use crate::converters::anthropic::{AnthropicRequest, AnthropicMessage, AnthropicContent};
-    use crate::converters::gemini::{GeminiRequest, gemini_content::GeminiContent, gemini_part::GeminiPart, gemini_generation_config::GeminiGenerationConfig};
-    use futures::stream::{self, StreamExt};
-
     println!("Checking models ({} total):", config.model_list.len());
-    let concurrency: usize = 20;
-    let client = llm_client.clone();
     let tasks = stream::iter(config.model_list.iter().cloned()).map(|mc| {
-        let client = client.clone();
+        let client = llm_client.clone();
+        let identity = config.router_settings.upstream_identity.clone();
         async move {
-            let request = match mc.llm_params.api_type {
-                ApiType::OpenAI => {
-                    let req = OpenAIRequest {
-                        model: mc.model_name.clone(),
-                        messages: vec![OpenAIMessage {
-                            role: "user".to_string(),
-                            content: OpenAIContent::Text("ping".to_string()),
-                            tool_calls: None,
-                            tool_call_id: None,
-                            reasoning_content: None,
-                        }],
-                        max_tokens: Some(1),
-                        temperature: Some(0.0),
-                        response_format: None,
-                        tools: None,
-                        stream: Some(false),
-                        extra_fields: std::collections::HashMap::new(),
-                    };
-                    RequestWrapper::OpenAI(req)
-                }
-                ApiType::Anthropic => {
-                    let req = AnthropicRequest {
-                        model: mc.model_name.clone(),
-                        max_tokens: 1,
-                        messages: Some(vec![AnthropicMessage { role: "user".to_string(), content: AnthropicContent::Text("ping".to_string()) }]),
-                        system: None,
-                        tools: None,
-                        metadata: None,
-                        stream: Some(false),
-                        temperature: Some(0.0),
-                        extra_fields: std::collections::HashMap::new(),
-                    };
-                    RequestWrapper::Anthropic(req)
-                }
-                ApiType::Gemini => {
-                    let req = GeminiRequest {
-                        model: mc.model_name.clone(),
-                        contents: vec![GeminiContent { role: Some("user".to_string()), parts: vec![GeminiPart::Text { text: "ping".to_string(), thought: None, thought_signature: None }] }],
-                        system_instruction: None,
-                        tools: None,
-                        generation_config: Some(GeminiGenerationConfig { response_mime_type: None, response_schema: None, temperature: Some(0.0), max_output_tokens: Some(1), ..Default::default() }),
-                        stream: Some(false),
-                        extra_fields: std::collections::HashMap::new(),
-                    };
-                    RequestWrapper::Gemini(req)
-                }
-            };
-
-            let req_id = crate::request_id::RequestId(uuid::Uuid::new_v4().to_string());
-            let result = client.forward_request(&request, &mc, &req_id).await;
-            match result {
-                Ok(resp) => {
-                    if resp.status().is_success() {
-                        println!(
-                            "[OK] {} -> {} ({})",
-                            mc.model_name,
-                            mc.llm_params.model,
-                            match mc.llm_params.api_type { ApiType::OpenAI => "openai", ApiType::Anthropic => "anthropic", ApiType::Gemini => "gemini" }
-                        );
-                    } else {
-                        let status = resp.status();
-                        let body = resp.text().await.unwrap_or_else(|_| "<failed to read body>".to_string());
-                        println!(
-                            "[FAIL] {} -> {} (status: {})\n  {}",
-                            mc.model_name, mc.llm_params.model, status, truncate(&body, 500)
-                        );
-                    }
-                }
-                Err(e) => {
-                    println!(
-                        "[ERROR] {} -> {}: {}",
-                        mc.model_name, mc.llm_params.model, e
-                    );
-                }
+            match probe(&client, &mc, &identity).await {
+                Ok(()) => println!(
+                    "[OK] {} -> {} ({})",
+                    mc.model_name,
+                    mc.llm_params.model,
+                    match mc.llm_params.api_type { ApiType::OpenAI => "openai", ApiType::Anthropic => "anthropic", ApiType::Gemini => "gemini" }
+                ),
+                Err(ProbeError::Status(status, body)) => println!(
+                    "[FAIL] {} -> {} (status: {})\n  {}",
+                    mc.model_name, mc.llm_params.model, status, body
+                ),
+                Err(ProbeError::Transport(e)) => println!(
+                    "[ERROR] {} -> {}: {}",
+                    mc.model_name, mc.llm_params.model, e
+                ),
             }
         }
     })
-    .buffer_unordered(concurrency)
+    .buffer_unordered(CONCURRENCY)
     .collect::<Vec<()>>();
 
     tasks.await;
     Ok(())
 }
 
+/// Latencies of `llm-router bench` pings to one model.
+#[derive(Debug, Default, Serialize)]
+pub struct BenchReport {
+    pub model: String,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub p50_ms: Option<u64>,
+    pub p90_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+    // The first failure, so a broken upstream is recognisable
+    pub first_error: Option<String>,
+}
+
+impl BenchReport {
+    fn new(model: &str, results: Vec<Result<Duration, ProbeError>>) -> Self {
+        let mut report = BenchReport { model: model.to_string(), ..Default::default() };
+        let mut latencies = Vec::new();
+        for result in results {
+            match result {
+                Ok(latency) => latencies.push(latency.as_millis() as u64),
+                Err(e) => {
+                    report.failed += 1;
+                    report.first_error.get_or_insert_with(|| e.to_string());
+                }
+            }
+        }
+        latencies.sort_unstable();
+        report.succeeded = latencies.len();
+        // Nearest-rank percentiles
+        let percentile = |p: usize| latencies.get((latencies.len() * p).div_ceil(100).saturating_sub(1)).copied();
+        report.p50_ms = percentile(50);
+        report.p90_ms = percentile(90);
+        report.p99_ms = percentile(99);
+        report.max_ms = latencies.last().copied();
+        report
+    }
+}
+
+/// Send `requests` pings to `model_name`, `concurrency` at a time, and report
+/// how long the upstream took to answer.
+pub async fn bench_model(
+    config: &Config,
+    llm_client: &LlmClient,
+    model_name: &str,
+    requests: usize,
+    concurrency: usize,
+) -> anyhow::Result<BenchReport> {
+    let mc = config
+        .model_list
+        .iter()
+        .find(|m| m.model_name == model_name)
+        .ok_or_else(|| anyhow::anyhow!("No model named '{}' in model_list", model_name))?;
+    let identity = &config.router_settings.upstream_identity;
+    let results = stream::iter(0..requests)
+        .map(|_| async move {
+            let started = std::time::Instant::now();
+            probe(llm_client, mc, identity).await.map(|()| started.elapsed())
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    Ok(BenchReport::new(model_name, results))
+}
+
+/// Why a model did not answer its probe.
+#[derive(Debug)]
+pub enum ProbeError {
+    // Upstream status and the start of its body
+    Status(reqwest::StatusCode, String),
+    Transport(String),
+}
+
+impl std::fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeError::Status(status, body) => write!(f, "status {}: {}", status, body),
+            ProbeError::Transport(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Send `mc` a one-token "ping" request in its own format.
+pub async fn probe(client: &LlmClient, mc: &ModelConfig, identity: &UpstreamIdentity) -> Result<(), ProbeError> {
+    let request = ping_request(mc);
+    let req_id = crate::request_id::RequestId(uuid::Uuid::new_v4().to_string());
+    let resp = client
+        .forward_request(&request, mc, &req_id, identity, &RewriteContext::default(), None)
+        .await
+        .map_err(|e| ProbeError::Transport(e.to_string()))?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_else(|_| "<failed to read body>".to_string());
+    Err(ProbeError::Status(status, truncate(&body, 500)))
+}
+
+/// A model of a reloaded config that did not answer its probe.
+#[derive(Debug, Serialize)]
+pub struct FailedProbe {
+    pub model: String,
+    pub api_base: String,
+    pub error: String,
+}
+
+/// Probe the upstreams of `candidates` that no model of `current` calls yet,
+/// once per upstream, each within `timeout`. Returns how many were probed and
+/// the ones that failed.
+pub async fn probe_added(
+    current: &[&ModelConfig],
+    candidates: &[&ModelConfig],
+    llm_client: &LlmClient,
+    identity: &UpstreamIdentity,
+    timeout: Duration,
+) -> (usize, Vec<FailedProbe>) {
+    let mut added: Vec<&ModelConfig> = Vec::new();
+    for mc in candidates {
+        let known = |other: &&ModelConfig| same_upstream(&other.llm_params, &mc.llm_params);
+        if !current.iter().any(known) && !added.iter().any(known) {
+            added.push(mc);
+        }
+    }
+    let probed = added.len();
+    let failed = stream::iter(added.into_iter().cloned())
+        .map(|mc| async move {
+            let result = match tokio::time::timeout(timeout, probe(llm_client, &mc, identity)).await {
+                Ok(result) => result,
+                Err(_) => Err(ProbeError::Transport(format!("no answer within {}ms", timeout.as_millis()))),
+            };
+            result.err().map(|e| FailedProbe { model: mc.model_name, api_base: mc.llm_params.api_base, error: e.to_string() })
+        })
+        .buffer_unordered(CONCURRENCY)
+        .filter_map(|failed| async move { failed })
+        .collect()
+        .await;
+    (probed, failed)
+}
+
+// Same provider endpoint, model and credentials, so a probe would tell nothing new
+fn same_upstream(a: &LLMParams, b: &LLMParams) -> bool {
+    a.api_type == b.api_type && a.api_base == b.api_base && a.model == b.model && a.api_key == b.api_key
+}
+
+fn ping_request(mc: &ModelConfig) -> RequestWrapper {
+    match mc.llm_params.api_type {
+        ApiType::OpenAI => {
+            let req = OpenAIRequest {
+                model: mc.model_name.clone(),
+                messages: vec![OpenAIMessage {
+                    role: "user".to_string(),
+                    content: OpenAIContent::Text("ping".to_string()),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    reasoning_content: None,
+                    extra_fields: std::collections::HashMap::new(),
+                }],
+                max_tokens: Some(1),
+                temperature: Some(0.0),
+                response_format: None,
+                tools: None,
+                tool_choice: None,
+                parallel_tool_calls: None,
+                stream: Some(false),
+                stop: None,
+                betas: Vec::new(),
+                modalities: None,
+                extra_fields: std::collections::HashMap::new(),
+            };
+            RequestWrapper::OpenAI(req)
+        }
+        ApiType::Anthropic => {
+            let req = AnthropicRequest {
+                model: mc.model_name.clone(),
+                max_tokens: 1,
+                messages: Some(vec![AnthropicMessage { role: "user".to_string(), content: AnthropicContent::Text("ping".to_string()), extra_fields: std::collections::HashMap::new() }]),
+                system: None,
+                tools: None,
+                tool_choice: None,
+                metadata: None,
+                stop_sequences: None,
+                stream: Some(false),
+                temperature: Some(0.0),
+                betas: Vec::new(),
+                extra_fields: std::collections::HashMap::new(),
+            };
+            RequestWrapper::Anthropic(req)
+        }
+        ApiType::Gemini => {
+            let req = GeminiRequest {
+                model: mc.model_name.clone(),
+                contents: vec![GeminiContent { role: Some("user".to_string()), parts: vec![GeminiPart::Text { text: "ping".to_string(), thought: None, thought_signature: None }] }],
+                system_instruction: None,
+                tools: None,
+                generation_config: Some(GeminiGenerationConfig { response_mime_type: None, response_schema: None, temperature: Some(0.0), max_output_tokens: Some(1), ..Default::default() }),
+                cached_content: None,
+                stream: Some(false),
+                extra_fields: std::collections::HashMap::new(),
+            };
+            RequestWrapper::Gemini(req)
+        }
+    }
+}
+
 fn truncate(s: &str, max_len: usize) -> String {
     if s.len() <= max_len {
         s.to_string()
@@ -113,3 +261,58 @@ fn truncate(s: &str, max_len: usize) -> String {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(name: &str, api_base: &str) -> ModelConfig {
+        serde_yaml::from_str(&format!(
+            "model_name: {}\nllm_params: {{api_type: openai, model: m, api_base: '{}', api_key: k}}",
+            name, api_base
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_bench_report_percentiles() {
+        let mut results: Vec<Result<Duration, ProbeError>> = (1..=10).map(|ms| Ok(Duration::from_millis(ms * 10))).collect();
+        results.push(Err(ProbeError::Transport("refused".to_string())));
+        let report = BenchReport::new("m", results);
+        assert_eq!((report.succeeded, report.failed), (10, 1));
+        assert_eq!((report.p50_ms, report.p90_ms, report.p99_ms, report.max_ms), (Some(50), Some(90), Some(100), Some(100)));
+        assert_eq!(report.first_error.as_deref(), Some("refused"));
+        assert_eq!(BenchReport::new("m", Vec::new()).p50_ms, None);
+    }
+
+    #[tokio::test]
+    async fn test_only_added_upstreams_are_probed() {
+        let mut server = mockito::Server::new_async().await;
+        let healthy = server.mock("POST", "/ok/chat/completions").with_body("{}").expect(1).create();
+        let broken = server.mock("POST", "/typo/chat/completions").with_status(404).with_body("no such route").create();
+        let known = server.mock("POST", "/known/chat/completions").expect(0).create();
+        let current = [model("a", &format!("{}/known", server.url()))];
+        let candidates = [
+            model("a", &format!("{}/known", server.url())),
+            model("b", &format!("{}/ok", server.url())),
+            model("b-alias", &format!("{}/ok", server.url())),
+            model("c", &format!("{}/typo", server.url())),
+        ];
+        let client = LlmClient::new(Arc::new(reqwest::Client::new()), Arc::new(reqwest::Client::new()));
+        let (probed, failed) = probe_added(
+            &current.iter().collect::<Vec<_>>(),
+            &candidates.iter().collect::<Vec<_>>(),
+            &client,
+            &UpstreamIdentity::default(),
+            Duration::from_secs(5),
+        )
+        .await;
+        assert_eq!(probed, 2);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].model, "c");
+        assert!(failed[0].error.contains("404") && failed[0].error.contains("no such route"));
+        healthy.assert();
+        broken.assert();
+        known.assert();
+    }
+}
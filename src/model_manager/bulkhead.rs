@@ -0,0 +1,188 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use crate::config::Priority;
+
+// How often a waiting request checks its place in the queue
+const POSITION_POLL: Duration = Duration::from_millis(250);
+
+/// Concurrency limit of one model. Requests that find it full may wait for a
+/// slot; freed slots go to the highest priority waiter, oldest first.
+#[derive(Debug)]
+pub struct Bulkhead {
+    limit: u32,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    in_use: u32,
+    // Ordered so the first entry is the next to be served
+    waiters: BTreeMap<(Reverse<Priority>, u64), oneshot::Sender<BulkheadPermit>>,
+    next_seq: u64,
+    // Most waiters seen at once since creation or the last reset
+    peak_waiters: usize,
+}
+
+/// A slot in a bulkhead, handed to the next waiter or freed when dropped.
+#[derive(Debug)]
+pub struct BulkheadPermit {
+    bulkhead: Arc<Bulkhead>,
+}
+
+impl Drop for BulkheadPermit {
+    fn drop(&mut self) {
+        self.bulkhead.release();
+    }
+}
+
+impl Bulkhead {
+    pub fn new(limit: u32) -> Self {
+        Self { limit, state: Mutex::new(State::default()) }
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Take a free slot. Low priority requests leave `low_reserve` slots free.
+    pub fn try_acquire(self: &Arc<Self>, priority: Priority, low_reserve: u32) -> Option<BulkheadPermit> {
+        let mut state = self.state.lock().unwrap();
+        let needed = if priority == Priority::Low { low_reserve.saturating_add(1) } else { 1 };
+        if self.limit - state.in_use < needed {
+            return None;
+        }
+        state.in_use += 1;
+        Some(BulkheadPermit { bulkhead: self.clone() })
+    }
+
+    /// Take a slot, waiting up to `timeout` for one when the bulkhead is full.
+    /// Low priority requests are shed instead of waiting.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority, low_reserve: u32, timeout: Duration) -> Option<BulkheadPermit> {
+        self.acquire_reporting(priority, low_reserve, timeout, &|_| {}).await
+    }
+
+    /// `acquire`, telling `report` how many requests are ahead whenever that
+    /// changes while waiting.
+    pub async fn acquire_reporting(
+        self: &Arc<Self>,
+        priority: Priority,
+        low_reserve: u32,
+        timeout: Duration,
+        report: &(dyn Fn(usize) + Sync),
+    ) -> Option<BulkheadPermit> {
+        if let Some(permit) = self.try_acquire(priority, low_reserve) {
+            return Some(permit);
+        }
+        if priority == Priority::Low || timeout.is_zero() {
+            return None;
+        }
+        let (tx, rx) = oneshot::channel();
+        let key = {
+            let mut state = self.state.lock().unwrap();
+            // A slot may have been freed since try_acquire
+            if state.in_use < self.limit {
+                state.in_use += 1;
+                return Some(BulkheadPermit { bulkhead: self.clone() });
+            }
+            let key = (Reverse(priority), state.next_seq);
+            state.next_seq += 1;
+            state.waiters.insert(key, tx);
+            state.peak_waiters = state.peak_waiters.max(state.waiters.len());
+            key
+        };
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut rx = rx;
+        let mut reported = None;
+        loop {
+            let ahead = self.state.lock().unwrap().waiters.range(..key).count();
+            if reported != Some(ahead) {
+                report(ahead);
+                reported = Some(ahead);
+            }
+            let poll = (tokio::time::Instant::now() + POSITION_POLL).min(deadline);
+            match tokio::time::timeout_at(poll, &mut rx).await {
+                Ok(Ok(permit)) => return Some(permit),
+                Err(_) if poll < deadline => continue,
+                _ => {
+                    // A permit sent after the timeout is dropped with the receiver and freed
+                    self.state.lock().unwrap().waiters.remove(&key);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Requests waiting for a slot.
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().waiters.len()
+    }
+
+    /// Most requests that waited at once since creation or the last reset.
+    pub fn peak_queued(&self) -> usize {
+        self.state.lock().unwrap().peak_waiters
+    }
+
+    /// Restart the queue peak from the requests waiting now.
+    pub fn reset_peak_queued(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.peak_waiters = state.waiters.len();
+    }
+
+    fn release(self: &Arc<Self>) {
+        let waiter = {
+            let mut state = self.state.lock().unwrap();
+            match state.waiters.pop_first() {
+                Some((_, waiter)) => waiter,
+                None => {
+                    state.in_use -= 1;
+                    return;
+                }
+            }
+        };
+        // The slot passes to the waiter; one that gave up drops it, which releases it again
+        let _ = waiter.send(BulkheadPermit { bulkhead: self.clone() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_waiters_are_served_by_priority() {
+        let bulkhead = Arc::new(Bulkhead::new(2));
+        let first = bulkhead.try_acquire(Priority::Normal, 1).unwrap();
+        // The last slot is reserved from low priority traffic
+        assert!(bulkhead.try_acquire(Priority::Low, 1).is_none());
+        let second = bulkhead.try_acquire(Priority::Normal, 1).unwrap();
+        assert!(bulkhead.acquire(Priority::Low, 1, Duration::from_secs(1)).await.is_none());
+
+        let wait = Duration::from_secs(5);
+        let normal = tokio::spawn({
+            let bulkhead = bulkhead.clone();
+            async move { bulkhead.acquire(Priority::Normal, 1, wait).await.map(|_| "normal") }
+        });
+        tokio::task::yield_now().await;
+        let high = tokio::spawn({
+            let bulkhead = bulkhead.clone();
+            async move { bulkhead.acquire(Priority::High, 1, wait).await.map(|p| ("high", p)) }
+        });
+        while bulkhead.queued() < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        drop(first);
+        let (served, permit) = high.await.unwrap().unwrap();
+        assert_eq!(served, "high");
+        assert_eq!(bulkhead.queued(), 1);
+        drop(permit);
+        assert_eq!(normal.await.unwrap(), Some("normal"));
+        drop(second);
+        assert!(bulkhead.try_acquire(Priority::Normal, 0).is_some());
+        assert_eq!(bulkhead.state.lock().unwrap().in_use, 0);
+    }
+}
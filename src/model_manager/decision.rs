@@ -0,0 +1,108 @@
+use std::fmt;
+
+use crate::config::{ModelGroupEntry, RoutingStrategy};
+
+/// How a group picked its member: what was weighed and what was left out.
+/// The router logs it with the request id, so routing fairness and which
+/// provider served a request can be analysed afterwards.
+#[derive(Debug, Clone)]
+pub struct GroupDecision {
+    pub group: String,
+    // None when the client's provider order made the pick
+    pub strategy: Option<RoutingStrategy>,
+    pub candidates: Vec<Candidate>,
+    // Members dropped before the pick, with the filter that dropped them
+    pub excluded: Vec<(String, &'static str)>,
+    pub chosen: String,
+}
+
+/// A member the pick was made from, as it stood at that moment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub name: String,
+    pub effective_weight: u32,
+    // Health factor in percent
+    pub health: u32,
+    pub circuit_open: bool,
+}
+
+impl GroupDecision {
+    pub(super) fn new(group: &str) -> Self {
+        Self { group: group.to_string(), strategy: None, candidates: Vec::new(), excluded: Vec::new(), chosen: String::new() }
+    }
+
+    // Record the members of `before` that a filter left out of `after`
+    pub(super) fn exclude_dropped(&mut self, before: &[ModelGroupEntry], after: &[ModelGroupEntry], reason: &'static str) {
+        for entry in before.iter().filter(|b| !after.iter().any(|a| a.name == b.name)) {
+            self.excluded.push((entry.name.clone(), reason));
+        }
+    }
+
+    /// How the pick was made, as the audit log names it.
+    pub fn strategy_name(&self) -> &'static str {
+        match &self.strategy {
+            Some(RoutingStrategy::RoundRobin) => "roundrobin",
+            Some(RoutingStrategy::LeastConn) => "leastconn",
+            Some(RoutingStrategy::Random) => "random",
+            Some(RoutingStrategy::Explore) => "explore",
+            None => "provider order",
+        }
+    }
+}
+
+/// Builds a group's [`GroupDecision`] only while the audit log is enabled, so
+/// routing does not pay for snapshots and health lookups nobody reads.
+pub(super) struct DecisionRecorder(Option<GroupDecision>);
+
+impl DecisionRecorder {
+    pub(super) fn start(group: &str) -> Self {
+        Self(tracing::enabled!(tracing::Level::INFO).then(|| GroupDecision::new(group)))
+    }
+
+    // The members before a filter runs; None when nothing is recorded
+    pub(super) fn snapshot(&self, entries: &[ModelGroupEntry]) -> Option<Vec<ModelGroupEntry>> {
+        self.0.as_ref().map(|_| entries.to_vec())
+    }
+
+    pub(super) fn exclude_dropped(&mut self, before: &[ModelGroupEntry], after: &[ModelGroupEntry], reason: &'static str) {
+        if let Some(decision) = &mut self.0 {
+            decision.exclude_dropped(before, after, reason);
+        }
+    }
+
+    pub(super) fn exclude_since(&mut self, before: Option<Vec<ModelGroupEntry>>, after: &[ModelGroupEntry], reason: &'static str) {
+        if let Some(before) = before {
+            self.exclude_dropped(&before, after, reason);
+        }
+    }
+
+    pub(super) fn strategy(&mut self, strategy: &RoutingStrategy) {
+        if let Some(decision) = &mut self.0 {
+            decision.strategy = Some(strategy.clone());
+        }
+    }
+
+    pub(super) fn finish(self, chosen: &str, candidates: impl FnOnce() -> Vec<Candidate>) -> Option<GroupDecision> {
+        self.0.map(|mut decision| {
+            decision.candidates = candidates();
+            decision.chosen = chosen.to_string();
+            decision
+        })
+    }
+}
+
+impl fmt::Display for GroupDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "group '{}' picked '{}' by {} from ", self.group, self.chosen, self.strategy_name())?;
+        for (i, c) in self.candidates.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(f, "{}{} (weight {}, health {}%", sep, c.name, c.effective_weight, c.health)?;
+            f.write_str(if c.circuit_open { ", circuit open)" } else { ")" })?;
+        }
+        if !self.excluded.is_empty() {
+            let excluded: Vec<String> = self.excluded.iter().map(|(name, reason)| format!("{} ({})", name, reason)).collect();
+            write!(f, "; excluded: {}", excluded.join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -1,38 +1,40 @@
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Mutex;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::config::{Config, ModelGroupEntry};
-use super::types::ModelKey;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ModelGroup, ModelGroupEntry, Recovery, RecoverySchedule};
+use super::snapshot::{MemberState, unix_ms};
+use super::types::{FastMap, ModelKey, ModelKeyRef};
 
 pub struct Health {
     // factor in percentage points (100 = 1.0x)
-    factors: HashMap<ModelKey, AtomicU32>,
-    breaker: Mutex<HashMap<ModelKey, Breaker>>, // protected as it carries Instants
+    factors: FastMap<ModelKey, AtomicU32>,
+    breaker: Mutex<FastMap<ModelKey, Breaker>>, // protected as it carries Instants
     cfg: HealthConfig,
+    // Group name -> its recovery settings; groups without an entry use the defaults
+    recovery: HashMap<String, Recovery>,
 }
 
 impl Health {
-    pub fn new_from_config(cfg: &Config) -> Self {
-        let mut factors = HashMap::new();
-        let mut breaker = HashMap::new();
-        for g in &cfg.router_settings.model_groups {
-            for m in &g.models {
-                let key = ModelKey::new(g.name.clone(), m.name.clone());
-                factors.insert(key.clone(), AtomicU32::new(100));
-                breaker.insert(key, Breaker::default());
-            }
+    pub fn new<'a>(keys: impl IntoIterator<Item = &'a ModelKey>, groups: &[ModelGroup]) -> Self {
+        let mut factors = FastMap::new();
+        let mut breaker = FastMap::new();
+        for key in keys {
+            factors.insert(key.clone(), AtomicU32::new(100));
+            breaker.insert(key.clone(), Breaker::default());
         }
-        Self { factors, breaker: Mutex::new(breaker), cfg: HealthConfig::default() }
+        let recovery = groups.iter().map(|g| (g.name.clone(), g.recovery.clone())).collect();
+        Self { factors, breaker: Mutex::new(breaker), cfg: HealthConfig::default(), recovery }
     }
 
     pub fn effective_weight(&self, group_name: &str, entry: &ModelGroupEntry) -> u32 {
-        let key = ModelKey::new(group_name.to_string(), entry.name.clone());
         let base = entry.weight;
         let factor = self
             .factors
-            .get(&key)
+            .get(&ModelKeyRef::new(group_name, &entry.name))
             .map(|a| a.load(Ordering::SeqCst))
             .unwrap_or(100);
         let mut eff = (base as u64 * factor as u64) / 100;
@@ -40,8 +42,22 @@ impl Health {
         eff as u32
     }
 
-    pub fn decay(&self, key: &ModelKey) {
-        if let Some(f) = self.factors.get(key) {
+    /// Health factor of the member in percent (100 = full weight).
+    pub fn factor(&self, group_name: &str, model_name: &str) -> u32 {
+        self.factors
+            .get(&ModelKeyRef::new(group_name, model_name))
+            .map_or(100, |f| f.load(Ordering::SeqCst))
+    }
+
+    /// Whether the member's circuit is open, without moving it to half-open as `permit` does.
+    pub fn is_open(&self, group_name: &str, model_name: &str) -> bool {
+        let map = self.breaker.lock().unwrap();
+        map.get(&ModelKeyRef::new(group_name, model_name))
+            .is_some_and(|b| b.state == CircuitState::Open && b.open_until.is_some_and(|t| Instant::now() < t))
+    }
+
+    pub fn decay(&self, key: ModelKeyRef<'_>) {
+        if let Some(f) = self.factors.get(&key) {
             loop {
                 let cur = f.load(Ordering::SeqCst);
                 let next = (cur / 2).max(1);
@@ -52,34 +68,47 @@ impl Health {
         }
     }
 
-    pub fn recover_on_success(&self, key: &ModelKey) {
-        if let Some(f) = self.factors.get(key) {
-            loop {
-                let cur = f.load(Ordering::SeqCst);
-                if cur >= 100 { break; }
-                let step = self.cfg.recovery_step;
-                let mut next = cur.saturating_add(step);
-                if next > 100 { next = 100; }
-                if f.compare_exchange_weak(cur, next, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
-                    break;
+    /// Raise the factor by the group's recovery schedule. With a
+    /// `success_threshold` of N the factor stays below full weight, and a
+    /// half-open circuit stays half-open, until the Nth success in a row,
+    /// which restores full weight whatever the schedule has reached.
+    pub fn recover_on_success(&self, key: ModelKeyRef<'_>) {
+        let recovery = self.recovery.get(key.group).cloned().unwrap_or_default();
+        let mut map = self.breaker.lock().unwrap();
+        let Some(b) = map.get_mut(&key) else { return };
+        b.consecutive_failures = 0;
+        b.consecutive_successes = b.consecutive_successes.saturating_add(1);
+        let recovered = recovery.success_threshold.is_none_or(|n| b.consecutive_successes >= n);
+        if let Some(f) = self.factors.get(&key) {
+            if recovered && recovery.success_threshold.is_some() {
+                f.store(100, Ordering::SeqCst);
+            } else {
+                let ceiling = if recovered { 100 } else { 99 };
+                loop {
+                    let cur = f.load(Ordering::SeqCst);
+                    if cur >= ceiling { break; }
+                    let next = match recovery.schedule {
+                        RecoverySchedule::Linear => cur.saturating_add(self.cfg.recovery_step),
+                        RecoverySchedule::Exponential => cur.saturating_mul(2),
+                    };
+                    if f.compare_exchange_weak(cur, next.min(ceiling), Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                        break;
+                    }
                 }
             }
         }
         // Close/half-open transitions
-        let mut map = self.breaker.lock().unwrap();
-        if let Some(b) = map.get_mut(key) {
-            b.consecutive_failures = 0;
-            if let CircuitState::HalfOpen = b.state {
-                b.state = CircuitState::Closed;
-                b.open_until = None;
-            }
+        if b.state == CircuitState::HalfOpen && recovered {
+            b.state = CircuitState::Closed;
+            b.open_until = None;
         }
     }
 
-    pub fn on_failure(&self, key: &ModelKey) {
+    pub fn on_failure(&self, key: ModelKeyRef<'_>) {
         let mut map = self.breaker.lock().unwrap();
-        let b = map.entry(key.clone()).or_insert_with(Breaker::default);
+        let b = map.entry_ref(&key).or_default();
         b.consecutive_failures = b.consecutive_failures.saturating_add(1);
+        b.consecutive_successes = 0;
         if b.consecutive_failures >= self.cfg.fail_threshold {
             b.state = CircuitState::Open;
             b.open_until = Some(Instant::now() + self.cfg.open_duration);
@@ -87,9 +116,9 @@ impl Health {
     }
 
     pub fn permit(&self, group_name: &str, entry: &ModelGroupEntry) -> bool {
-        let key = ModelKey::new(group_name.to_string(), entry.name.clone());
+        let key = ModelKeyRef::new(group_name, &entry.name);
         let mut map = self.breaker.lock().unwrap();
-        let b = map.entry(key).or_insert_with(Breaker::default);
+        let b = map.entry_ref(&key).or_default();
         match b.state {
             CircuitState::Closed => true,
             CircuitState::HalfOpen => true, // allow probing
@@ -110,21 +139,69 @@ impl Health {
             }
         }
     }
+
+    /// Drop all state kept for `key`.
+    pub fn forget(&mut self, key: ModelKeyRef<'_>) {
+        self.factors.remove(&key);
+        self.breaker.get_mut().unwrap().remove(&key);
+    }
+
+    /// Copy the member's factor and breaker into `state`.
+    pub fn export(&self, state: &mut MemberState) {
+        let key = ModelKeyRef::new(&state.group, &state.model);
+        if let Some(f) = self.factors.get(&key) {
+            state.factor = f.load(Ordering::SeqCst);
+        }
+        let map = self.breaker.lock().unwrap();
+        if let Some(b) = map.get(&key) {
+            state.circuit = b.state;
+            state.consecutive_failures = b.consecutive_failures;
+            state.consecutive_successes = b.consecutive_successes;
+            // Instants do not survive a restart; store the deadline as wall-clock time
+            state.open_until_ms = b
+                .open_until
+                .map(|t| unix_ms(SystemTime::now() + t.saturating_duration_since(Instant::now())));
+        }
+    }
+
+    /// Restore a member saved by `export`. A circuit whose open period ended
+    /// while the router was down comes back half-open, so the next request probes it.
+    pub fn import(&self, state: &MemberState) {
+        let key = ModelKeyRef::new(&state.group, &state.model);
+        if let Some(f) = self.factors.get(&key) {
+            f.store(state.factor.clamp(1, 100), Ordering::SeqCst);
+        }
+        let mut map = self.breaker.lock().unwrap();
+        let Some(b) = map.get_mut(&key) else { return };
+        b.consecutive_failures = state.consecutive_failures;
+        b.consecutive_successes = state.consecutive_successes;
+        b.state = state.circuit;
+        b.open_until = None;
+        if state.circuit == CircuitState::Open {
+            let deadline = state.open_until_ms.map(|ms| UNIX_EPOCH + Duration::from_millis(ms));
+            match deadline.and_then(|d| d.duration_since(SystemTime::now()).ok()) {
+                Some(remaining) => b.open_until = Some(Instant::now() + remaining),
+                None => b.state = CircuitState::HalfOpen,
+            }
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum CircuitState { Closed, Open, HalfOpen }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState { Closed, Open, HalfOpen }
 
 #[derive(Clone, Debug)]
 struct Breaker {
     state: CircuitState,
     consecutive_failures: u32,
+    consecutive_successes: u32,
     open_until: Option<Instant>,
 }
 
 impl Default for Breaker {
     fn default() -> Self {
-        Self { state: CircuitState::Closed, consecutive_failures: 0, open_until: None }
+        Self { state: CircuitState::Closed, consecutive_failures: 0, consecutive_successes: 0, open_until: None }
     }
 }
 
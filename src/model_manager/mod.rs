@@ -1,30 +1,60 @@
-use crate::config::{Config, ModelConfig, ModelGroupEntry, RoutingStrategy};
+use crate::config::{Config, ModelConfig, ModelGroup, ModelGroupEntry, RoutingStrategy, checked_group_weights};
+use crate::utils::clock;
 use crate::utils::jq_util::run_jaq;
+use serde::Deserialize;
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tracing::{debug, info, warn};
 
+mod bulkhead;
+mod decision;
 mod health;
 mod registry;
+mod reload;
+mod rules;
+mod snapshot;
+mod stats;
 mod strategy;
+mod ttfb;
 mod types;
 
-use types::ModelKey;
+pub use bulkhead::{Bulkhead, BulkheadPermit};
+pub use decision::{Candidate, GroupDecision};
+pub use reload::{OrphanedState, ReloadReport, collect_when_drained};
+pub use snapshot::{StateSnapshot, run_state_saver, save_state};
+pub use stats::{MemberHealth, ModelHealth, OUTCOME_WINDOW_SECS};
+
+use decision::DecisionRecorder;
+use types::{FastMap, ModelKey, ModelKeyRef};
+pub use types::DIRECT_GROUP;
 
 pub struct ModelManager {
     pub(super) config: Arc<Config>,
     // Key: (group_name, model_name), Value: current weight for smooth weighted round robin
-    pub(super) current_weights: HashMap<ModelKey, AtomicIsize>,
+    pub(super) current_weights: FastMap<ModelKey, AtomicIsize>,
     // Key: (group_name, model_name), Value: active request count for the model in the group
-    pub(super) active_requests: HashMap<ModelKey, AtomicUsize>,
+    pub(super) active_requests: FastMap<ModelKey, AtomicUsize>,
     // Per-group lock to make SWRR selection + update atomic across the group
     pub(super) group_locks: HashMap<String, Mutex<()>>,
     // Runtime health/weight factors
     pub(super) health: health::Health,
     // Hot path cache: model name -> index in config.model_list
     pub(super) model_index: HashMap<String, usize>,
+    // Hot path cache: group name -> index in config.router_settings.model_groups
+    pub(super) group_index: HashMap<String, usize>,
+    // Per-model concurrency bulkheads for models with max_concurrency
+    pub(super) bulkheads: HashMap<String, Arc<Bulkhead>>,
+    // Model name -> latency, outcomes and peaks observed for it
+    pub(super) stats: HashMap<String, Arc<stats::ModelStats>>,
+    // Pairs removed by a config reload while requests were in flight
+    pub(super) orphans: reload::Orphans,
+    // Requests started per second, for load-based strategy rules
+    pub(super) request_rate: rules::RateMeter,
+    pub(super) active_rule: rules::ActiveRule,
+    pub(super) rng: strategy::RoutingRng,
 }
 
 impl fmt::Debug for ModelManager {
@@ -48,56 +78,54 @@ pub struct Selection {
     pub group: Option<String>,
     pub model_name: String,
     pub config: ModelConfig,
+    // (parent group, nested group) edges walked to reach `group`, outermost first
+    pub via: Vec<(String, String)>,
+    // How each group on the way made its pick, outermost first; empty for direct
+    // calls and when the audit log (info level) is off
+    pub decisions: Vec<GroupDecision>,
+}
+
+/// OpenRouter-style `provider` object in the request body
+#[derive(Debug, Deserialize)]
+struct ProviderPreferences {
+    // Member names to try first, in priority order
+    #[serde(default)]
+    order: Vec<String>,
+    // When false, only members listed in `order` may serve the request
+    #[serde(default = "default_allow_fallbacks")]
+    allow_fallbacks: bool,
+}
+
+fn default_allow_fallbacks() -> bool { true }
+
+impl ProviderPreferences {
+    fn from_request(request_json: &serde_json::Value) -> Option<Self> {
+        let prefs: Self = serde_json::from_value(request_json.get("provider")?.clone())
+            .inspect_err(|e| debug!("Ignoring malformed provider preferences: {}", e))
+            .ok()?;
+        (!prefs.order.is_empty()).then_some(prefs)
+    }
 }
 
 impl ModelManager {
     pub fn resolve(&self, hint: &str, request_json: &serde_json::Value) -> Option<Selection> {
+        self.resolve_within(hint, request_json, None, &|_| true)
+    }
+
+    /// Like `resolve`, but group members observed to be slower than
+    /// `latency_budget` are skipped while a faster one is available, and only
+    /// models that pass `fits` are picked from groups. A model named directly
+    /// is returned whether it fits or not.
+    pub fn resolve_within(
+        &self,
+        hint: &str,
+        request_json: &serde_json::Value,
+        latency_budget: Option<Duration>,
+        fits: &dyn Fn(&ModelConfig) -> bool,
+    ) -> Option<Selection> {
         // If it's a group alias
-        if let Some(model_group) = self
-            .config
-            .router_settings
-            .model_groups
-            .iter()
-            .find(|g| g.name == hint)
-        {
-            // Filter valid
-            let registry = registry::Registry::new(&self.config);
-            let valid_models: Vec<crate::config::ModelGroupEntry> =
-                registry.filter_valid_entries(&model_group.models);
-            if valid_models.is_empty() {
-                return None;
-            }
-            // Further filter by selector if provided
-            let filtered_by_selector: Vec<ModelGroupEntry> = valid_models
-                .into_iter()
-                .filter(|e| selector_matches(e, request_json))
-                .collect();
-            let candidate_models: Vec<ModelGroupEntry> = if filtered_by_selector.is_empty() {
-                // If none match selectors, there is no eligible model
-                return None;
-            } else {
-                filtered_by_selector
-            };
-            let chosen = match self.config.router_settings.strategy {
-                RoutingStrategy::RoundRobin => {
-                    self.select_round_robin(&model_group.name, &candidate_models)
-                }
-                RoutingStrategy::LeastConn => {
-                    self.select_least_conn(&model_group.name, &candidate_models)
-                }
-                RoutingStrategy::Random => self.select_random(&candidate_models),
-            };
-            if chosen.is_empty() {
-                return None;
-            }
-            if let Some(cfg) = self.find_model(&chosen) {
-                return Some(Selection {
-                    group: Some(model_group.name.clone()),
-                    model_name: chosen,
-                    config: cfg.clone(),
-                });
-            }
-            return None;
+        if self.group_index.contains_key(hint) {
+            return self.resolve_group(hint, request_json, latency_budget, &|_| true, fits, &mut Vec::new());
         }
 
         // Otherwise treat as direct model name
@@ -105,13 +133,179 @@ impl ModelManager {
             group: None,
             model_name: hint.to_string(),
             config: cfg.clone(),
+            via: Vec::new(),
+            decisions: Vec::new(),
         })
     }
+
+    /// Pick another member of `group_name` for a request a member refused: only
+    /// members flagged `uncensored` and not named in `tried` are eligible.
+    pub fn resolve_uncensored(
+        &self,
+        group_name: &str,
+        request_json: &serde_json::Value,
+        latency_budget: Option<Duration>,
+        tried: &[String],
+        fits: &dyn Fn(&ModelConfig) -> bool,
+    ) -> Option<Selection> {
+        let eligible = |e: &ModelGroupEntry| e.uncensored && !tried.contains(&e.name);
+        self.resolve_group(group_name, request_json, latency_budget, &eligible, fits, &mut Vec::new())
+    }
+
+    /// Pick a member of the group other than those already tried.
+    pub fn resolve_untried(
+        &self,
+        group_name: &str,
+        request_json: &serde_json::Value,
+        latency_budget: Option<Duration>,
+        tried: &[String],
+        fits: &dyn Fn(&ModelConfig) -> bool,
+    ) -> Option<Selection> {
+        let eligible = |e: &ModelGroupEntry| !tried.contains(&e.name);
+        self.resolve_group(group_name, request_json, latency_budget, &eligible, fits, &mut Vec::new())
+    }
+
+    // Picks a member of `group_name` that passes `eligible`; members that are
+    // groups themselves are expanded recursively. Models that fail `fits` are
+    // passed over at every level. `path` holds the groups already entered and
+    // guards against cycles.
+    fn resolve_group(
+        &self,
+        group_name: &str,
+        request_json: &serde_json::Value,
+        latency_budget: Option<Duration>,
+        eligible: &dyn Fn(&ModelGroupEntry) -> bool,
+        fits: &dyn Fn(&ModelConfig) -> bool,
+        path: &mut Vec<String>,
+    ) -> Option<Selection> {
+        if path.iter().any(|g| g == group_name) {
+            warn!("Cycle detected in nested model groups: {} -> {}", path.join(" -> "), group_name);
+            return None;
+        }
+        let model_group = self.find_group(group_name)?;
+        let mut decision = DecisionRecorder::start(&model_group.name);
+
+        // Filter valid
+        let registry = registry::Registry::new(&self.config);
+        let mut valid_models: Vec<crate::config::ModelGroupEntry> =
+            registry.filter_valid_entries(&model_group.models);
+        decision.exclude_dropped(&model_group.models, &valid_models, "unknown");
+        let before = decision.snapshot(&valid_models);
+        // Nested groups are checked member by member once they are entered
+        valid_models.retain(|e| self.find_model(&e.name).is_none() || eligible(e));
+        decision.exclude_since(before, &valid_models, "retry_ineligible");
+        if valid_models.is_empty() {
+            return None;
+        }
+        // Further filter by selector if provided
+        let filtered_by_selector: Vec<ModelGroupEntry> = valid_models
+            .iter()
+            .filter(|e| selector_matches(e, request_json))
+            .cloned()
+            .collect();
+        decision.exclude_dropped(&valid_models, &filtered_by_selector, "selector");
+        if filtered_by_selector.is_empty() {
+            // If none match selectors, there is no eligible model
+            return None;
+        }
+        // Drop models that cannot serve the request; nested groups filter their own
+        let candidate_models: Vec<ModelGroupEntry> = filtered_by_selector
+            .iter()
+            .filter(|e| self.find_model(&e.name).is_none_or(fits))
+            .cloned()
+            .collect();
+        decision.exclude_dropped(&filtered_by_selector, &candidate_models, "unsupported");
+        if candidate_models.is_empty() {
+            return None;
+        }
+        // Drop members whose context window cannot hold the request
+        let before = decision.snapshot(&candidate_models);
+        let mut candidate_models = self.filter_by_context_window(candidate_models, request_json);
+        decision.exclude_since(before, &candidate_models, "context_window");
+        if let Some(budget) = latency_budget {
+            let before = decision.snapshot(&candidate_models);
+            candidate_models = self.filter_by_latency(candidate_models, budget);
+            decision.exclude_since(before, &candidate_models, "latency_budget");
+        }
+        let before = decision.snapshot(&candidate_models);
+        let preferred = match ProviderPreferences::from_request(request_json) {
+            Some(prefs) => self.select_preferred(model_group, &mut candidate_models, &prefs),
+            None => None,
+        };
+        decision.exclude_since(before, &candidate_models, "provider_order");
+        if candidate_models.is_empty() {
+            return None;
+        }
+        let chosen = match preferred {
+            Some(name) => name,
+            None => {
+                let strategy = self.apply_strategy_rules(&model_group.name, &mut candidate_models);
+                decision.strategy(&strategy);
+                match strategy {
+                    RoutingStrategy::RoundRobin => {
+                        self.select_round_robin(&model_group.name, &candidate_models)
+                    }
+                    RoutingStrategy::LeastConn => {
+                        self.select_least_conn(&model_group.name, &candidate_models)
+                    }
+                    RoutingStrategy::Random => self.select_random(&candidate_models),
+                    RoutingStrategy::Explore => {
+                        self.select_explore(&model_group.name, &candidate_models)
+                    }
+                }
+            }
+        };
+        if chosen.is_empty() {
+            return None;
+        }
+        let decision = decision.finish(&chosen, || {
+            candidate_models
+                .iter()
+                .map(|e| Candidate {
+                    name: e.name.clone(),
+                    effective_weight: self.health.effective_weight(&model_group.name, e),
+                    health: self.health.factor(&model_group.name, &e.name),
+                    circuit_open: self.health.is_open(&model_group.name, &e.name),
+                })
+                .collect()
+        });
+        if let Some(cfg) = self.find_model(&chosen) {
+            return Some(Selection {
+                group: Some(model_group.name.clone()),
+                model_name: chosen,
+                config: cfg.clone(),
+                via: Vec::new(),
+                decisions: decision.into_iter().collect(),
+            });
+        }
+        if self.group_index.contains_key(&chosen) {
+            path.push(group_name.to_string());
+            let mut selection = self.resolve_group(&chosen, request_json, latency_budget, eligible, fits, path)?;
+            selection.via.insert(0, (group_name.to_string(), chosen));
+            selection.decisions.splice(0..0, decision);
+            return Some(selection);
+        }
+        None
+    }
+
     pub fn new(config: Arc<Config>) -> Self {
-        let mut current_weights = HashMap::new();
-        let mut active_requests = HashMap::new();
+        let mut current_weights = FastMap::new();
+        let mut active_requests = FastMap::new();
         let mut group_locks = HashMap::new();
         let mut model_index = HashMap::new();
+        let mut group_index = HashMap::new();
+
+        // Every key naming a model shares one copy of the name
+        let mut names: std::collections::HashSet<Arc<str>> = std::collections::HashSet::new();
+        let mut intern = |name: &str| -> Arc<str> {
+            if let Some(interned) = names.get(name) {
+                return interned.clone();
+            }
+            let interned: Arc<str> = name.into();
+            names.insert(interned.clone());
+            interned
+        };
+        let mut keys = Vec::new();
 
         // Initialize counters for all model groups
         for model_group in &config.router_settings.model_groups {
@@ -119,20 +313,37 @@ impl ModelManager {
             group_locks
                 .entry(model_group.name.clone())
                 .or_insert_with(|| Mutex::new(()));
-            // Initialize connection counts and current weights for each model in the group
+            let group = intern(&model_group.name);
             for model in &model_group.models {
-                let key = ModelKey::new(model_group.name.clone(), model.name.clone());
-                // Initialize current weight to 0 for SWRR
-                current_weights.insert(key.clone(), AtomicIsize::new(0));
-                active_requests.insert(key.clone(), AtomicUsize::new(0));
+                keys.push(ModelKey { group: group.clone(), model: intern(&model.name) });
             }
         }
-        let health = health::Health::new_from_config(&config.clone());
+        // Direct model calls get their own counters so they are not invisible
+        let direct = intern(DIRECT_GROUP);
+        for model in &config.model_list {
+            keys.push(ModelKey { group: direct.clone(), model: intern(&model.model_name) });
+        }
+        // Initialize connection counts and current weights (0 for SWRR)
+        for key in &keys {
+            current_weights.insert(key.clone(), AtomicIsize::new(0));
+            active_requests.insert(key.clone(), AtomicUsize::new(0));
+        }
+        let health = health::Health::new(&keys, &config.router_settings.model_groups);
         // Build hot cache for model lookups
+        let mut bulkheads = HashMap::new();
+        let mut stats = HashMap::new();
         for (idx, model) in config.model_list.iter().enumerate() {
             model_index.insert(model.model_name.clone(), idx);
+            stats.insert(model.model_name.clone(), Arc::default());
+            if let Some(limit) = model.llm_params.max_concurrency {
+                bulkheads.insert(model.model_name.clone(), Arc::new(Bulkhead::new(limit)));
+            }
+        }
+        for (idx, group) in config.router_settings.model_groups.iter().enumerate() {
+            group_index.insert(group.name.clone(), idx);
         }
-        Self { config, current_weights, active_requests, group_locks, health: health, model_index }
+        let rng = strategy::RoutingRng::new(config.router_settings.routing_seed);
+        Self { config, current_weights, active_requests, group_locks, health: health, model_index, group_index, bulkheads, stats, orphans: HashMap::new(), request_rate: Default::default(), active_rule: Default::default(), rng }
     }
 
     // Helper: find a model config by exact name
@@ -143,17 +354,221 @@ impl ModelManager {
             .and_then(|&idx| self.config.model_list.get(idx))
     }
 
-    pub(super) fn model_exists(&self, model_name: &str) -> bool {
-        self.model_index.contains_key(model_name)
+    pub fn find_group(&self, name: &str) -> Option<&ModelGroup> {
+        self
+            .group_index
+            .get(name)
+            .and_then(|&idx| self.config.router_settings.model_groups.get(idx))
+    }
+
+    /// Models of `group` other than `except` whose output limit allows `max_tokens`.
+    pub fn members_allowing_max_tokens(&self, group: &str, except: &str, max_tokens: u32) -> Vec<String> {
+        let Some(group) = self.find_group(group) else { return Vec::new() };
+        group
+            .models
+            .iter()
+            .filter(|e| e.name != except)
+            .filter_map(|e| self.find_model(&e.name))
+            .filter(|m| m.llm_params.max_output_tokens.is_none_or(|limit| limit >= max_tokens))
+            .map(|m| m.model_name.clone())
+            .collect()
+    }
+
+    // A group member is either a model or a nested group
+    pub(super) fn member_exists(&self, name: &str) -> bool {
+        self.model_index.contains_key(name) || self.group_index.contains_key(name)
+    }
+
+    /// Keep only entries whose context window fits the estimated request size.
+    /// Entries without `context_window` always fit. When nothing fits, the entry
+    /// with the largest context window is returned alone.
+    fn filter_by_context_window(&self, entries: Vec<ModelGroupEntry>, request_json: &serde_json::Value) -> Vec<ModelGroupEntry> {
+        let window = |e: &ModelGroupEntry| self.find_model(&e.name).and_then(|m| m.llm_params.context_window);
+        if entries.iter().all(|e| window(e).is_none()) {
+            return entries;
+        }
+        let estimated = estimate_tokens(request_json);
+        let (fits, too_small): (Vec<ModelGroupEntry>, Vec<ModelGroupEntry>) = entries
+            .into_iter()
+            .partition(|e| window(e).is_none_or(|w| w as u64 >= estimated));
+        if !fits.is_empty() {
+            return fits;
+        }
+        debug!("No model context window fits ~{} tokens, using the largest one", estimated);
+        too_small.into_iter().max_by_key(|e| window(e)).into_iter().collect()
+    }
+
+    /// In-flight requests per (group, model); direct model calls use `DIRECT_GROUP`.
+    pub fn active_counts(&self) -> Vec<(String, String, usize)> {
+        let mut counts: Vec<_> = self
+            .active_requests
+            .iter()
+            .map(|(key, count)| (key.group.to_string(), key.model.to_string(), count.load(Ordering::SeqCst)))
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    /// Runtime state of every group member and directly called model, for the health state file.
+    pub fn snapshot(&self) -> StateSnapshot {
+        let mut members: Vec<snapshot::MemberState> = self
+            .current_weights
+            .iter()
+            .map(|(key, current)| {
+                let mut state = snapshot::MemberState {
+                    group: key.group.to_string(),
+                    model: key.model.to_string(),
+                    factor: 100,
+                    current_weight: current.load(Ordering::SeqCst),
+                    circuit: health::CircuitState::Closed,
+                    consecutive_failures: 0,
+                    consecutive_successes: 0,
+                    open_until_ms: None,
+                };
+                self.health.export(&mut state);
+                state
+            })
+            .collect();
+        members.sort_by(|a, b| (&a.group, &a.model).cmp(&(&b.group, &b.model)));
+        StateSnapshot { saved_at_ms: snapshot::unix_ms(std::time::SystemTime::now()), members }
+    }
+
+    /// Apply state saved by a previous run. Members no longer in the config are
+    /// skipped; returns how many were restored.
+    pub fn restore(&self, snapshot: &StateSnapshot) -> usize {
+        let mut restored = 0;
+        for state in &snapshot.members {
+            let key = ModelKeyRef::new(&state.group, &state.model);
+            let Some(current) = self.current_weights.get(&key) else { continue };
+            current.store(state.current_weight, Ordering::SeqCst);
+            self.health.import(state);
+            restored += 1;
+        }
+        restored
+    }
+
+    /// Keep only entries not known to be slower than `budget`. Nested groups and
+    /// models without observations always qualify. When nothing qualifies, the
+    /// fastest entry is returned alone.
+    fn filter_by_latency(&self, entries: Vec<ModelGroupEntry>, budget: Duration) -> Vec<ModelGroupEntry> {
+        let (fits, too_slow): (Vec<ModelGroupEntry>, Vec<ModelGroupEntry>) = entries
+            .into_iter()
+            .partition(|e| self.observed_latency(&e.name).is_none_or(|l| l <= budget));
+        if !fits.is_empty() {
+            return fits;
+        }
+        debug!("No group member is observed within {:?}, using the fastest one", budget);
+        too_slow.into_iter().min_by_key(|e| self.observed_latency(&e.name)).into_iter().collect()
+    }
+
+    /// Fold an upstream latency sample into the model's moving average.
+    pub fn record_latency(&self, model_name: &str, latency: Duration) {
+        let Some(stats) = self.stats.get(model_name) else { return };
+        let sample = (latency.as_millis() as u64).max(1);
+        let _ = stats.latency_ms.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |cur| {
+            Some(if cur == 0 { sample } else { (cur * 7 + sample) / 8 })
+        });
+    }
+
+    pub fn observed_latency(&self, model_name: &str) -> Option<Duration> {
+        match self.stats.get(model_name)?.latency_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    /// Record how long a stream took to send its first byte. A run of slow
+    /// streams lowers the health factor of the pair, as a failure would, but
+    /// leaves the circuit breaker alone since nothing failed.
+    pub fn record_ttfb(&self, selection: &Selection, ttfb: Duration) {
+        let settings = &self.config.router_settings.ttfb_health;
+        if !settings.enabled {
+            return;
+        }
+        let Some(tracker) = self.stats.get(&selection.model_name).map(|s| &s.ttfb) else { return };
+        if !tracker.record(ttfb, settings) {
+            return;
+        }
+        let group = selection.group.as_deref().unwrap_or(DIRECT_GROUP);
+        warn!(
+            "Model {} in group {} keeps taking {:?} to start streaming (usual {:?}), reducing its health",
+            selection.model_name, group, ttfb, tracker.baseline().unwrap_or_default()
+        );
+        for (parent, nested) in &selection.via {
+            self.health.decay(ModelKeyRef::new(parent, nested));
+        }
+        self.health.decay(ModelKeyRef::new(group, &selection.model_name));
+    }
+
+    /// Apply `provider.order` to a group: the first listed candidate whose circuit
+    /// is not open is chosen directly. With `allow_fallbacks: false` the candidates
+    /// are narrowed to the listed members. Groups that list none of the
+    /// preferred names are left to the default strategy.
+    fn select_preferred(&self, group: &ModelGroup, candidates: &mut Vec<ModelGroupEntry>, prefs: &ProviderPreferences) -> Option<String> {
+        if !group.models.iter().any(|m| prefs.order.contains(&m.name)) {
+            return None;
+        }
+        if !prefs.allow_fallbacks {
+            candidates.retain(|e| prefs.order.contains(&e.name));
+        }
+        let chosen = prefs
+            .order
+            .iter()
+            .filter_map(|name| candidates.iter().find(|e| &e.name == name))
+            .find(|e| self.health.permit(&group.name, e))
+            .map(|e| e.name.clone());
+        debug!("Provider order {:?} in group {} -> {:?}", prefs.order, group.name, chosen);
+        chosen
     }
 
     pub fn get_config(&self) -> &Arc<Config> {
         &self.config
     }
 
+    /// Replace the configured weight of members in a group at runtime.
+    /// Health factors and counters are kept; unknown members are rejected.
+    pub fn set_group_weights(&mut self, group_name: &str, weights: &HashMap<String, u32>) -> anyhow::Result<()> {
+        let idx = *self
+            .group_index
+            .get(group_name)
+            .ok_or_else(|| anyhow::anyhow!("Model group '{}' not found", group_name))?;
+        let config = Arc::make_mut(&mut self.config);
+        let group = &mut config.router_settings.model_groups[idx];
+        if let Some(unknown) = weights.keys().find(|name| !group.models.iter().any(|m| &m.name == *name)) {
+            return Err(anyhow::anyhow!("'{}' is not a member of group '{}'", unknown, group_name));
+        }
+        let updated: Vec<u32> = group.models.iter().map(|m| weights.get(&m.name).copied().unwrap_or(m.weight)).collect();
+        // The same rules as at load, so a patch cannot leave a group that would be refused
+        let updated = checked_group_weights(group_name, &updated, config.router_settings.normalize_weights)?;
+        for (entry, w) in group.models.iter_mut().zip(updated) {
+            if entry.weight != w {
+                info!("Weight for {} in group {} changed from {} to {}", entry.name, group_name, entry.weight, w);
+                entry.weight = w;
+            }
+        }
+        Ok(())
+    }
+
+    /// Configured and health-adjusted weight of each member of `group_name`, as SWRR sees them.
+    pub fn effective_weights(&self, group_name: &str) -> Option<Vec<(String, u32, u32)>> {
+        let group = self.find_group(group_name)?;
+        Some(
+            group
+                .models
+                .iter()
+                .map(|m| (m.name.clone(), m.weight, self.health.effective_weight(group_name, m)))
+                .collect(),
+        )
+    }
+
+    /// The model's bulkhead; `None` when the model is unbounded.
+    pub fn bulkhead(&self, model_name: &str) -> Option<Arc<Bulkhead>> {
+        self.bulkheads.get(model_name).cloned()
+    }
+
     /// Track the start of a chat completion request
     pub fn start_request(&self, group_name: &str, model_name: &str) {
-        let key = ModelKey::new(group_name.to_string(), model_name.to_string());
+        let key = ModelKeyRef::new(group_name, model_name);
 
         // Increment active request count
         if let Some(active_requests) = self.active_requests.get(&key) {
@@ -167,26 +582,21 @@ impl ModelManager {
 
     /// Start using a selection handle
     pub fn start(&self, selection: &Selection) {
-        if let Some(group) = &selection.group {
-            self.start_request(group, &selection.model_name);
+        self.record_request_rate();
+        if let Some(stats) = self.stats.get(&selection.model_name) {
+            stats.peaks.start();
+        }
+        for (parent, nested) in &selection.via {
+            self.start_request(parent, nested);
         }
+        let group = selection.group.as_deref().unwrap_or(DIRECT_GROUP);
+        self.start_request(group, &selection.model_name);
     }
 
     /// Track the end of a chat completion request
     pub fn end_request(&self, group_name: &str, model_name: &str, success: bool) {
-        let key = ModelKey::new(group_name.to_string(), model_name.to_string());
-
-        // Decrement active request count
-        if let Some(active_requests) = self.active_requests.get(&key) {
-            let new_count = active_requests.fetch_sub(1, Ordering::SeqCst) - 1;
-            debug!(
-                "Ended request for model {} in group {}, success: {}, active requests: {}",
-                model_name,
-                group_name,
-                success,
-                new_count.max(0)
-            );
-        }
+        let key = ModelKeyRef::new(group_name, model_name);
+        self.release_request(group_name, model_name);
 
         // Handle health updates
         if !success {
@@ -196,16 +606,33 @@ impl ModelManager {
             );
             self.reduce_model_weight(group_name, model_name);
         } else {
-            self.health.recover_on_success(&key);
+            self.health.recover_on_success(key);
+        }
+    }
+
+    /// Track the end of a request without judging the model, e.g. when the
+    /// upstream rejected it as a client error
+    pub fn release_request(&self, group_name: &str, model_name: &str) {
+        let key = ModelKeyRef::new(group_name, model_name);
+
+        // Decrement active request count
+        if let Some(active_requests) = self.active_requests.get(&key) {
+            let new_count = active_requests.fetch_sub(1, Ordering::SeqCst) - 1;
+            debug!(
+                "Ended request for model {} in group {}, active requests: {}",
+                model_name,
+                group_name,
+                new_count.max(0)
+            );
         }
     }
 
     /// Reduce the weight of a model by half when it fails
     fn reduce_model_weight(&self, group_name: &str, model_name: &str) {
-        let key = ModelKey::new(group_name.to_string(), model_name.to_string());
+        let key = ModelKeyRef::new(group_name, model_name);
         // Update runtime health factor and breaker state
-        self.health.decay(&key);
-        self.health.on_failure(&key);
+        self.health.decay(key);
+        self.health.on_failure(key);
 
         // Find the model group and model entry to get the original weight
         if let Some(model_group) = self
@@ -251,19 +678,56 @@ impl ModelManager {
 
     /// End using a selection handle
     pub fn end(&self, selection: &Selection, success: bool) {
-        if let Some(group) = &selection.group {
-            self.end_request(group, &selection.model_name, success);
-        } else {
-            // Direct model (no group). Keep current behavior: no counters/health updates.
+        if let Some(stats) = self.stats.get(&selection.model_name) {
+            stats.outcomes.record(clock::now_secs(), success);
+            stats.peaks.end();
+            if !success {
+                stats.peaks.record_error(snapshot::unix_ms(std::time::SystemTime::now()));
+            }
+        }
+        for (parent, nested) in &selection.via {
+            self.end_request(parent, nested, success);
+        }
+        let group = selection.group.as_deref().unwrap_or(DIRECT_GROUP);
+        self.end_request(group, &selection.model_name, success);
+    }
+
+    /// Count a failure the upstream reported after its stream had started, when
+    /// the request itself has already ended
+    pub fn record_stream_failure(&self, selection: &Selection) {
+        if let Some(stats) = self.stats.get(&selection.model_name) {
+            stats.peaks.record_error(snapshot::unix_ms(std::time::SystemTime::now()));
+        }
+        for (parent, nested) in &selection.via {
+            self.reduce_model_weight(parent, nested);
         }
+        let group = selection.group.as_deref().unwrap_or(DIRECT_GROUP);
+        self.reduce_model_weight(group, &selection.model_name);
+    }
+
+    /// End using a selection handle without affecting health or weights
+    pub fn release(&self, selection: &Selection) {
+        if let Some(stats) = self.stats.get(&selection.model_name) {
+            stats.peaks.end();
+        }
+        for (parent, nested) in &selection.via {
+            self.release_request(parent, nested);
+        }
+        let group = selection.group.as_deref().unwrap_or(DIRECT_GROUP);
+        self.release_request(group, &selection.model_name);
     }
 }
 
+// Rough token estimate (~4 bytes per token) of the whole request body
+pub(crate) fn estimate_tokens(request_json: &serde_json::Value) -> u64 {
+    (request_json.to_string().len() as u64).div_ceil(4)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::{
-        Config, LLMParams, ModelConfig, ModelGroup, ModelGroupEntry, RoutingStrategy,
+        Config, LLMParams, ModelConfig, ModelGroup, ModelGroupEntry, Priority, Recovery, RecoverySchedule, RoutingStrategy,
     };
 
     // Helper function to create a test config
@@ -279,6 +743,29 @@ mod tests {
                         api_key: "test-key".to_string(),
                         rewrite_body: serde_json::json!({}),
                         rewrite_header: serde_json::json!({}),
+                        rewrite_auth_headers: false,
+                        stream_delta_chars: None,
+                        use_proxy: true,
+                        context_window: None,
+                        max_concurrency: None,
+                        auth_headers: Default::default(),
+                        signing: None,
+                        anthropic_betas: None,
+                        scrub_unknown_fields: false,
+                        latency_hints: None,
+                        reasoning_model: None,
+                        pricing: None,
+                        tool_arguments: None,
+                        image_limits: None,
+                        max_messages: None,
+                        max_body_bytes: None,
+                        max_output_tokens: None,
+                        max_tokens_policy: Default::default(),
+                        max_response_bytes: None,
+                        native_web_search: false,
+                        soft_timeout_ms: None,
+                        forward_headers: Vec::new(),
+                        keepalive_ms: None,
                     },
                 },
                 ModelConfig {
@@ -290,6 +777,29 @@ mod tests {
                         api_key: "test-key".to_string(),
                         rewrite_body: serde_json::json!({}),
                         rewrite_header: serde_json::json!({}),
+                        rewrite_auth_headers: false,
+                        stream_delta_chars: None,
+                        use_proxy: true,
+                        context_window: None,
+                        max_concurrency: None,
+                        auth_headers: Default::default(),
+                        signing: None,
+                        anthropic_betas: None,
+                        scrub_unknown_fields: false,
+                        latency_hints: None,
+                        reasoning_model: None,
+                        pricing: None,
+                        tool_arguments: None,
+                        image_limits: None,
+                        max_messages: None,
+                        max_body_bytes: None,
+                        max_output_tokens: None,
+                        max_tokens_policy: Default::default(),
+                        max_response_bytes: None,
+                        native_web_search: false,
+                        soft_timeout_ms: None,
+                        forward_headers: Vec::new(),
+                        keepalive_ms: None,
                     },
                 },
                 ModelConfig {
@@ -301,6 +811,29 @@ mod tests {
                         api_key: "test-key".to_string(),
                         rewrite_body: serde_json::json!({}),
                         rewrite_header: serde_json::json!({}),
+                        rewrite_auth_headers: false,
+                        stream_delta_chars: None,
+                        use_proxy: true,
+                        context_window: None,
+                        max_concurrency: None,
+                        auth_headers: Default::default(),
+                        signing: None,
+                        anthropic_betas: None,
+                        scrub_unknown_fields: false,
+                        latency_hints: None,
+                        reasoning_model: None,
+                        pricing: None,
+                        tool_arguments: None,
+                        image_limits: None,
+                        max_messages: None,
+                        max_body_bytes: None,
+                        max_output_tokens: None,
+                        max_tokens_policy: Default::default(),
+                        max_response_bytes: None,
+                        native_web_search: false,
+                        soft_timeout_ms: None,
+                        forward_headers: Vec::new(),
+                        keepalive_ms: None,
                     },
                 },
             ],
@@ -314,18 +847,23 @@ mod tests {
                                 name: "model1".to_string(),
                                 weight: 1,
                                 selector: None,
+                                uncensored: false,
                             },
                             ModelGroupEntry {
                                 name: "model2".to_string(),
                                 weight: 2,
                                 selector: None,
+                                uncensored: false,
                             },
                             ModelGroupEntry {
                                 name: "model3".to_string(),
                                 weight: 3,
                                 selector: None,
+                                uncensored: false,
                             },
                         ],
+                        defaults: Default::default(),
+                        recovery: Default::default(),
                     },
                     ModelGroup {
                         name: "group2".to_string(),
@@ -334,16 +872,54 @@ mod tests {
                                 name: "model1".to_string(),
                                 weight: 1,
                                 selector: None,
+                                uncensored: false,
                             },
                             ModelGroupEntry {
                                 name: "model3".to_string(),
                                 weight: 1,
                                 selector: None,
+                                uncensored: false,
                             },
                         ],
+                        defaults: Default::default(),
+                        recovery: Default::default(),
                     },
                 ],
+                routing_headers: false,
+                list_all_models: false,
+                sse_terminators: Default::default(),
+                response_store: Default::default(),
+                default_model: None,
+                health_state: Default::default(),
+                repair_tool_arguments: false,
+                tool_arguments: Default::default(),
+                refusal_fallback: Default::default(),
+                image_fetch: Default::default(),
+                mcp: Default::default(),
+                web_search: Default::default(),
+                max_hops: 5,
+                upstream_identity: Default::default(),
+                normalize_weights: None,
+                session_caps: Default::default(),
+                strategy_rules: Vec::new(),
+                output_validation: Default::default(),
+                priority: Default::default(),
+                retry_queue: Default::default(),
+                ttfb_health: Default::default(),
+                stream_failover: Default::default(),
+                reload_probes: Default::default(),
+                exploration_percent: 5,
+                stream_delta_chars: None,
+                offload_conversion_bytes: None,
+                listeners: Vec::new(),
+                routing_seed: None,
+                direct_conversions: Vec::new(),
+                count_aborted_usage: true,
+                max_request_bytes: 2 * 1024 * 1024,
             },
+            virtual_keys: Vec::new(),
+            tenants: Vec::new(),
+            unknown_keys: Vec::new(),
         }
     }
 
@@ -358,16 +934,19 @@ mod tests {
                 name: "model1".to_string(),
                 weight: 1,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model2".to_string(),
                 weight: 2,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model3".to_string(),
                 weight: 3,
                 selector: None,
+                uncensored: false,
             },
         ];
 
@@ -415,16 +994,19 @@ mod tests {
                 name: "model1".to_string(),
                 weight: 1,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model2".to_string(), // This model doesn't exist in model_list
                 weight: 2,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model3".to_string(),
                 weight: 3,
                 selector: None,
+                uncensored: false,
             },
         ];
 
@@ -447,26 +1029,28 @@ mod tests {
                 name: "model1".to_string(),
                 weight: 1,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model2".to_string(),
                 weight: 2,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model3".to_string(),
                 weight: 3,
                 selector: None,
+                uncensored: false,
             },
         ];
 
         let group_name = "test_group";
 
-        // Initially, all models have 0 connections, so it should select based on weight
-        // With equal connections, higher weight models are preferred
+        // Initially, all models have 0 connections: (0 + 1) / weight is lowest for the heaviest
         let selected = model_manager.select_least_conn(group_name, &models);
         println!("Initial selection: {}", selected);
-        assert!(selected == "model1" || selected == "model2" || selected == "model3");
+        assert_eq!(selected, "model3");
 
         // Add connections to model3 to make it less preferred
         for _ in 0..5 {
@@ -476,7 +1060,7 @@ mod tests {
         // Now model3 has more connections, check the selection
         let selected = model_manager.select_least_conn(group_name, &models);
         println!("After adding connections to model3: {}", selected);
-        assert!(selected == "model1" || selected == "model2" || selected == "model3");
+        assert_eq!(selected, "model2");
 
         // Add connections to model2 to make it less preferred
         for _ in 0..5 {
@@ -486,7 +1070,7 @@ mod tests {
         // Now model2 has more connections, check the selection
         let selected = model_manager.select_least_conn(group_name, &models);
         println!("After adding connections to model2: {}", selected);
-        assert!(selected == "model1" || selected == "model2" || selected == "model3");
+        assert_eq!(selected, "model1");
 
         // Add connections to model1 to make it less preferred
         for _ in 0..5 {
@@ -496,7 +1080,7 @@ mod tests {
         // Now model1 has more connections, check the selection
         let selected = model_manager.select_least_conn(group_name, &models);
         println!("After adding connections to model1: {}", selected);
-        assert!(selected == "model1" || selected == "model2" || selected == "model3");
+        assert_eq!(selected, "model3");
 
         // Reset all connections by ending them
         for _ in 0..5 {
@@ -508,7 +1092,62 @@ mod tests {
         // Now all models should have 0 connections again, check the selection
         let selected = model_manager.select_least_conn(group_name, &models);
         println!("After resetting connections: {}", selected);
-        assert!(selected == "model1" || selected == "model2" || selected == "model3");
+        assert_eq!(selected, "model3");
+    }
+
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(16))]
+
+        // Requests arrive steadily and each member serves at its own speed; the
+        // time-averaged in-flight requests still split in weight proportion
+        #[test]
+        fn prop_least_conn_load_follows_weights(
+            weights in proptest::collection::vec(1u32..=8, 2..=3),
+            speeds in proptest::collection::vec(1u64..=4, 3),
+            seed in proptest::prelude::any::<u64>(),
+        ) {
+            use rand::{Rng, SeedableRng};
+            let mut config = create_test_config();
+            let group = &mut config.router_settings.model_groups[0];
+            group.models.truncate(weights.len());
+            for (member, weight) in group.models.iter_mut().zip(&weights) {
+                member.weight = *weight;
+            }
+            let models = group.models.clone();
+            let model_manager = ModelManager::new(Arc::new(config));
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            // (model, tick it ends)
+            let mut in_flight: Vec<(String, u64)> = Vec::new();
+            let mut load = vec![0u64; models.len()];
+            for tick in 0..1500u64 {
+                in_flight.retain(|(model, ends)| {
+                    let done = *ends <= tick;
+                    if done {
+                        model_manager.end_request("test_group", model, true);
+                    }
+                    !done
+                });
+                for _ in 0..8 {
+                    let model = model_manager.select_least_conn("test_group", &models);
+                    model_manager.start_request("test_group", &model);
+                    let index = models.iter().position(|m| m.name == model).unwrap();
+                    let duration = rng.gen_range(1..=10) * speeds[index];
+                    in_flight.push((model, tick + duration));
+                }
+                if tick >= 100 {
+                    for (i, m) in models.iter().enumerate() {
+                        load[i] += in_flight.iter().filter(|(model, _)| *model == m.name).count() as u64;
+                    }
+                }
+            }
+            let total_load: u64 = load.iter().sum();
+            let total_weight: u32 = weights.iter().sum();
+            for (i, weight) in weights.iter().enumerate() {
+                let share = load[i] as f64 / total_load as f64;
+                let expected = *weight as f64 / total_weight as f64;
+                proptest::prop_assert!((share - expected).abs() < 0.03, "member {} carries {:.3} of the load, weight share {:.3}", i, share, expected);
+            }
+        }
     }
 
     #[test]
@@ -524,16 +1163,19 @@ mod tests {
                 name: "model1".to_string(),
                 weight: 1,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model2".to_string(), // This model doesn't exist in model_list
                 weight: 2,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model3".to_string(),
                 weight: 3,
                 selector: None,
+                uncensored: false,
             },
         ];
 
@@ -556,16 +1198,19 @@ mod tests {
                 name: "model1".to_string(),
                 weight: 1,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model2".to_string(),
                 weight: 2,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model3".to_string(),
                 weight: 3,
                 selector: None,
+                uncensored: false,
             },
         ];
 
@@ -599,6 +1244,89 @@ mod tests {
         assert!((model3_ratio - 3.0 / 6.0).abs() < 0.1);
     }
 
+    #[test]
+    fn test_routing_seed_repeats_random_picks() {
+        let picks = |seed: u64| {
+            let mut config = create_test_config();
+            config.router_settings.routing_seed = Some(seed);
+            let model_manager = ModelManager::new(Arc::new(config));
+            let group = model_manager.find_group("test_group").unwrap().models.clone();
+            (0..50).map(|_| model_manager.select_random(&group)).collect::<Vec<_>>()
+        };
+        assert_eq!(picks(7), picks(7));
+        assert_ne!(picks(7), picks(8));
+    }
+
+    #[test]
+    fn test_explore_sends_share_to_deprioritized_members() {
+        let mut config = create_test_config();
+        config.router_settings.routing_seed = Some(3);
+        config.router_settings.exploration_percent = 100;
+        let model_manager = ModelManager::new(Arc::new(config));
+        let group = model_manager.find_group("test_group").unwrap().models.clone();
+
+        // Every member at full weight: nothing to explore, plain weighted picks
+        let picks: Vec<_> = (0..30).map(|_| model_manager.select_explore("test_group", &group)).collect();
+        assert!(picks.iter().any(|p| p != "model3"));
+
+        model_manager.health.decay(ModelKeyRef::new("test_group", "model3"));
+        for _ in 0..30 {
+            assert_eq!(model_manager.select_explore("test_group", &group), "model3");
+        }
+    }
+
+    #[test]
+    fn test_recovery_needs_successes_in_a_row() {
+        let mut config = create_test_config();
+        config.router_settings.model_groups[0].recovery =
+            Recovery { success_threshold: Some(3), schedule: RecoverySchedule::Exponential };
+        let model_manager = ModelManager::new(Arc::new(config));
+        let health = &model_manager.health;
+        let key = ModelKeyRef::new("test_group", "model2");
+        let state = |circuit| snapshot::MemberState {
+            group: "test_group".to_string(),
+            model: "model2".to_string(),
+            factor: 12,
+            current_weight: 0,
+            circuit,
+            consecutive_failures: 3,
+            consecutive_successes: 0,
+            open_until_ms: None,
+        };
+        let circuit = || {
+            let mut exported = state(health::CircuitState::Closed);
+            health.export(&mut exported);
+            exported.circuit
+        };
+
+        // The open period is over: probes double the factor, and the third restores it and closes the circuit
+        health.import(&state(health::CircuitState::HalfOpen));
+        health.recover_on_success(key);
+        health.recover_on_success(key);
+        assert_eq!((health.factor("test_group", "model2"), circuit()), (48, health::CircuitState::HalfOpen));
+        health.recover_on_success(key);
+        assert_eq!((health.factor("test_group", "model2"), circuit()), (100, health::CircuitState::Closed));
+
+        // A failure restarts the count; full weight waits for three successes again
+        health.decay(key);
+        health.on_failure(key);
+        for _ in 0..2 {
+            health.recover_on_success(key);
+        }
+        assert_eq!(health.factor("test_group", "model2"), 99);
+
+        // The count survives a snapshot, so a reload or restart does not reset it
+        let restarted = ModelManager::new(model_manager.config.clone());
+        restarted.restore(&model_manager.snapshot());
+        restarted.health.recover_on_success(key);
+        assert_eq!(restarted.health.factor("test_group", "model2"), 100);
+
+        // Other groups keep the default: one success, a linear step
+        health.decay(ModelKeyRef::new("group2", "model1"));
+        health.recover_on_success(ModelKeyRef::new("group2", "model1"));
+        assert_eq!(health.factor("group2", "model1"), 60);
+    }
+
     #[test]
     fn test_select_random_with_nonexistent_models() {
         let mut config = create_test_config();
@@ -612,16 +1340,19 @@ mod tests {
                 name: "model1".to_string(),
                 weight: 1,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model2".to_string(), // This model doesn't exist in model_list
                 weight: 2,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model3".to_string(),
                 weight: 3,
                 selector: None,
+                uncensored: false,
             },
         ];
 
@@ -632,6 +1363,415 @@ mod tests {
         assert!(selected == "model1" || selected == "model3");
     }
 
+    #[test]
+    fn test_resolve_skips_models_with_small_context_window() {
+        let mut config = create_test_config();
+        config.model_list[0].llm_params.context_window = Some(8);
+        config.model_list[2].llm_params.context_window = Some(200_000);
+        let model_manager = ModelManager::new(Arc::new(config));
+        let request = serde_json::json!({"messages": [{"role": "user", "content": "x".repeat(400)}]});
+
+        // model1 (8 tokens) is too small; model2 has no limit, model3 fits
+        for _ in 0..20 {
+            let sel = model_manager.resolve("test_group", &request).unwrap();
+            assert_ne!(sel.model_name, "model1");
+        }
+
+        // Only model1 and model3 in group2; a request larger than both falls back to the largest
+        let mut config = create_test_config();
+        config.model_list[0].llm_params.context_window = Some(8);
+        config.model_list[2].llm_params.context_window = Some(16);
+        let model_manager = ModelManager::new(Arc::new(config));
+        for _ in 0..10 {
+            let sel = model_manager.resolve("group2", &request).unwrap();
+            assert_eq!(sel.model_name, "model3");
+        }
+    }
+
+    // Decisions are only recorded while info logs are enabled
+    fn audit_logging() -> tracing::subscriber::DefaultGuard {
+        tracing::subscriber::set_default(tracing_subscriber::fmt().with_max_level(tracing::Level::INFO).with_test_writer().finish())
+    }
+
+    #[test]
+    fn test_resolve_records_group_decision() {
+        let request = serde_json::json!({});
+        let model_manager = ModelManager::new(Arc::new(create_test_config()));
+        let quiet = tracing::subscriber::set_default(tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).finish());
+        assert!(model_manager.resolve("test_group", &request).unwrap().decisions.is_empty());
+        drop(quiet);
+
+        let _audit = audit_logging();
+        let mut config = create_test_config();
+        config.model_list[0].llm_params.context_window = Some(8);
+        let model_manager = ModelManager::new(Arc::new(config));
+        model_manager.health.decay(ModelKeyRef::new("test_group", "model2"));
+        let request = serde_json::json!({"messages": [{"role": "user", "content": "x".repeat(400)}]});
+
+        let sel = model_manager.resolve("test_group", &request).unwrap();
+        let [decision] = sel.decisions.as_slice() else { panic!("one group decided") };
+        assert_eq!(decision.chosen, sel.model_name);
+        assert_eq!(decision.excluded, vec![("model1".to_string(), "context_window")]);
+        let health: Vec<_> = decision.candidates.iter().map(|c| (c.name.as_str(), c.effective_weight, c.health)).collect();
+        assert_eq!(health, vec![("model2", 1, 50), ("model3", 3, 100)]);
+        assert!(decision.to_string().ends_with("from model2 (weight 1, health 50%), model3 (weight 3, health 100%); excluded: model1 (context_window)"));
+
+        assert!(model_manager.resolve("model1", &request).unwrap().decisions.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_nested_group() {
+        let mut config = create_test_config();
+        config.router_settings.model_groups.push(ModelGroup {
+            name: "prod".to_string(),
+            models: vec![ModelGroupEntry {
+                name: "group2".to_string(),
+                weight: 1,
+                selector: None,
+                uncensored: false,
+            }],
+            defaults: Default::default(),
+            recovery: Default::default(),
+        });
+        let model_manager = ModelManager::new(Arc::new(config));
+        let request = serde_json::json!({});
+
+        let sel = model_manager.resolve("prod", &request).unwrap();
+        assert_eq!(sel.group.as_deref(), Some("group2"));
+        assert!(sel.model_name == "model1" || sel.model_name == "model3");
+        assert_eq!(sel.via, vec![("prod".to_string(), "group2".to_string())]);
+
+        // Counters are tracked on every edge of the path
+        model_manager.start(&sel);
+        let key = ModelKey::new("prod", "group2");
+        assert_eq!(model_manager.active_requests[&key].load(Ordering::SeqCst), 1);
+        model_manager.end(&sel, true);
+        assert_eq!(model_manager.active_requests[&key].load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_members_that_do_not_fit_are_passed_over() {
+        let mut config = create_test_config();
+        config.router_settings.model_groups.push(ModelGroup {
+            name: "prod".to_string(),
+            models: vec![ModelGroupEntry { name: "group2".to_string(), weight: 1, selector: None, uncensored: false }],
+            defaults: Default::default(),
+            recovery: Default::default(),
+        });
+        let model_manager = ModelManager::new(Arc::new(config));
+        let request = serde_json::json!({});
+        let fits = |m: &ModelConfig| m.model_name != "model1";
+        let _audit = audit_logging();
+
+        for _ in 0..10 {
+            let sel = model_manager.resolve_within("prod", &request, None, &fits).unwrap();
+            assert_eq!(sel.model_name, "model3");
+            let sel = model_manager.resolve_untried("test_group", &request, None, &[], &fits).unwrap();
+            assert_ne!(sel.model_name, "model1");
+        }
+        let decision = &model_manager.resolve_within("test_group", &request, None, &fits).unwrap().decisions[0];
+        assert!(decision.excluded.contains(&("model1".to_string(), "unsupported")));
+        assert!(model_manager.resolve_within("group2", &request, None, &|_| false).is_none());
+        // Models named directly are left to the caller
+        assert!(model_manager.resolve_within("model1", &request, None, &|_| false).is_some());
+    }
+
+    #[test]
+    fn test_resolve_nested_group_cycle() {
+        let mut config = create_test_config();
+        for (name, member) in [("a", "b"), ("b", "a")] {
+            config.router_settings.model_groups.push(ModelGroup {
+                name: name.to_string(),
+                models: vec![ModelGroupEntry {
+                    name: member.to_string(),
+                    weight: 1,
+                    selector: None,
+                    uncensored: false,
+                }],
+                defaults: Default::default(),
+                recovery: Default::default(),
+            });
+        }
+        let model_manager = ModelManager::new(Arc::new(config));
+        assert!(model_manager.resolve("a", &serde_json::json!({})).is_none());
+    }
+
+    #[test]
+    fn test_resolve_uncensored() {
+        let mut config = create_test_config();
+        for entry in &mut config.router_settings.model_groups[0].models {
+            entry.uncensored = entry.name != "model1";
+        }
+        // Members of a nested group are checked too: only model3 in group2 is flagged
+        config.router_settings.model_groups[1].models[1].uncensored = true;
+        config.router_settings.model_groups.push(ModelGroup {
+            name: "prod".to_string(),
+            models: vec![ModelGroupEntry { name: "group2".to_string(), weight: 1, selector: None, uncensored: false }],
+            defaults: Default::default(),
+            recovery: Default::default(),
+        });
+        let model_manager = ModelManager::new(Arc::new(config));
+        let request = serde_json::json!({});
+        for _ in 0..10 {
+            assert_eq!(model_manager.resolve_uncensored("prod", &request, None, &[], &|_| true).unwrap().model_name, "model3");
+        }
+        assert!(model_manager.resolve_uncensored("prod", &request, None, &["model3".to_string()], &|_| true).is_none());
+
+        for _ in 0..10 {
+            let sel = model_manager.resolve_uncensored("test_group", &request, None, &["model1".to_string()], &|_| true).unwrap();
+            assert_ne!(sel.model_name, "model1");
+        }
+        let tried = ["model1".to_string(), "model2".to_string()];
+        assert_eq!(model_manager.resolve_uncensored("test_group", &request, None, &tried, &|_| true).unwrap().model_name, "model3");
+        let tried = ["model2".to_string(), "model3".to_string()];
+        assert!(model_manager.resolve_uncensored("test_group", &request, None, &tried, &|_| true).is_none());
+    }
+
+    #[test]
+    fn test_set_group_weights() {
+        let mut model_manager = ModelManager::new(Arc::new(create_test_config()));
+        let weights = HashMap::from([("model1".to_string(), 0), ("model3".to_string(), 10)]);
+        model_manager.set_group_weights("group2", &weights).unwrap();
+        for _ in 0..10 {
+            let sel = model_manager.resolve("group2", &serde_json::json!({})).unwrap();
+            assert_eq!(sel.model_name, "model3");
+        }
+
+        let unknown = HashMap::from([("model2".to_string(), 1)]);
+        assert!(model_manager.set_group_weights("group2", &unknown).is_err());
+        assert!(model_manager.set_group_weights("missing", &weights).is_err());
+
+        // All-zero weights are refused as at load, and nothing changes
+        let zero = HashMap::from([("model3".to_string(), 0)]);
+        let err = model_manager.set_group_weights("group2", &zero).unwrap_err();
+        assert!(err.to_string().contains("no member with a nonzero weight"));
+        let weights = model_manager.effective_weights("group2").unwrap();
+        assert!(weights.iter().any(|(name, weight, _)| name == "model3" && *weight == 10));
+    }
+
+    #[test]
+    fn test_strategy_rule_overrides_weights() {
+        let mut config = create_test_config();
+        config.router_settings.strategy_rules = vec![serde_yaml::from_str(
+            "{name: busy, min_rps: 0, strategy: leastconn, weights: {group2: {model1: 0}}}",
+        )
+        .unwrap()];
+        let model_manager = ModelManager::new(Arc::new(config));
+        for _ in 0..5 {
+            let sel = model_manager.resolve("group2", &serde_json::json!({})).unwrap();
+            assert_eq!(sel.model_name, "model3");
+            model_manager.start(&sel);
+        }
+        let (_, rule) = model_manager.active_rule().unwrap();
+        assert_eq!(rule.name.as_deref(), Some("busy"));
+        assert!(matches!(rule.strategy, Some(RoutingStrategy::LeastConn)));
+    }
+
+    #[test]
+    fn test_set_group_weights_renormalizes() {
+        let mut config = create_test_config();
+        config.router_settings.normalize_weights = Some(100);
+        let mut model_manager = ModelManager::new(Arc::new(config));
+        let weights = HashMap::from([("model1".to_string(), 1), ("model3".to_string(), 3)]);
+        model_manager.set_group_weights("group2", &weights).unwrap();
+        let effective = model_manager.effective_weights("group2").unwrap();
+        assert_eq!(effective, vec![("model1".to_string(), 25, 25), ("model3".to_string(), 75, 75)]);
+    }
+
+    #[test]
+    fn test_resolve_provider_order() {
+        let model_manager = ModelManager::new(Arc::new(create_test_config()));
+        let request = serde_json::json!({"provider": {"order": ["model9", "model1", "model2"]}});
+        for _ in 0..5 {
+            assert_eq!(model_manager.resolve("test_group", &request).unwrap().model_name, "model1");
+        }
+
+        // An open circuit moves on to the next listed member
+        for _ in 0..3 {
+            let sel = model_manager.resolve("test_group", &request).unwrap();
+            model_manager.start(&sel);
+            model_manager.end(&sel, false);
+        }
+        assert_eq!(model_manager.resolve("test_group", &request).unwrap().model_name, "model2");
+
+        // Without fallbacks, members outside the list are never picked
+        let strict = serde_json::json!({"provider": {"order": ["model3"], "allow_fallbacks": false}});
+        for _ in 0..5 {
+            assert_eq!(model_manager.resolve("test_group", &strict).unwrap().model_name, "model3");
+        }
+        // Groups containing none of the listed members ignore the preferences
+        let unlisted = serde_json::json!({"provider": {"order": ["model2"], "allow_fallbacks": false}});
+        assert!(model_manager.resolve("group2", &unlisted).is_some());
+    }
+
+    #[test]
+    fn test_resolve_within_latency_budget() {
+        let model_manager = ModelManager::new(Arc::new(create_test_config()));
+        model_manager.record_latency("model1", Duration::from_millis(200));
+        model_manager.record_latency("model2", Duration::from_secs(5));
+        model_manager.record_latency("model3", Duration::from_secs(8));
+        let request = serde_json::json!({});
+        for _ in 0..5 {
+            let sel = model_manager.resolve_within("test_group", &request, Some(Duration::from_secs(1)), &|_| true).unwrap();
+            assert_eq!(sel.model_name, "model1");
+        }
+
+        // Nobody fits: the fastest member is still used
+        model_manager.record_latency("model1", Duration::from_secs(10));
+        assert_eq!(model_manager.observed_latency("model1"), Some(Duration::from_millis(1425)));
+        let sel = model_manager.resolve_within("test_group", &request, Some(Duration::from_millis(100)), &|_| true).unwrap();
+        assert_eq!(sel.model_name, "model1");
+    }
+
+    #[test]
+    fn test_snapshot_restores_open_circuit() {
+        let model_manager = ModelManager::new(Arc::new(create_test_config()));
+        for _ in 0..3 {
+            model_manager.start_request("test_group", "model1");
+            model_manager.end_request("test_group", "model1", false);
+        }
+        let saved = serde_json::to_string(&model_manager.snapshot()).unwrap();
+
+        let restarted = ModelManager::new(Arc::new(create_test_config()));
+        assert!(restarted.restore(&serde_json::from_str(&saved).unwrap()) > 0);
+        let state = restarted.snapshot();
+        let model1 = state.members.iter().find(|m| m.group == "test_group" && m.model == "model1").unwrap();
+        assert_eq!(model1.circuit, health::CircuitState::Open);
+        assert_eq!(model1.factor, 12);
+        assert!(model1.open_until_ms.is_some());
+        let entry = ModelGroupEntry { name: "model1".to_string(), weight: 1, selector: None, uncensored: false };
+        assert!(!restarted.health.permit("test_group", &entry));
+    }
+
+    #[test]
+    fn test_direct_model_requests_are_tracked() {
+        let model_manager = ModelManager::new(Arc::new(create_test_config()));
+        let sel = model_manager.resolve("model1", &serde_json::json!({})).unwrap();
+        assert!(sel.group.is_none());
+        model_manager.start(&sel);
+        let key = ModelKey::new(DIRECT_GROUP, "model1");
+        assert_eq!(model_manager.active_requests[&key].load(Ordering::SeqCst), 1);
+        assert!(model_manager.active_counts().contains(&(String::new(), "model1".to_string(), 1)));
+
+        // Direct load counts against the model in least-conn groups
+        let entries = vec![
+            ModelGroupEntry { name: "model1".to_string(), weight: 1, selector: None, uncensored: false },
+            ModelGroupEntry { name: "model2".to_string(), weight: 1, selector: None, uncensored: false },
+        ];
+        for _ in 0..5 {
+            assert_eq!(model_manager.select_least_conn("test_group", &entries), "model2");
+        }
+
+        for _ in 0..3 {
+            model_manager.end(&sel, false);
+            model_manager.start(&sel);
+        }
+        model_manager.end(&sel, true);
+        assert_eq!(model_manager.active_requests[&key].load(Ordering::SeqCst), 0);
+        let state = model_manager.snapshot();
+        let direct = state.members.iter().find(|m| m.group.is_empty() && m.model == "model1").unwrap();
+        assert_eq!(direct.factor, 22);
+    }
+
+    #[test]
+    fn test_release_keeps_health() {
+        let model_manager = ModelManager::new(Arc::new(create_test_config()));
+        for _ in 0..5 {
+            model_manager.start_request("test_group", "model1");
+            model_manager.release_request("test_group", "model1");
+        }
+        let key = ModelKey::new("test_group", "model1");
+        assert_eq!(model_manager.active_requests[&key].load(Ordering::SeqCst), 0);
+        let state = model_manager.snapshot();
+        let model1 = state.members.iter().find(|m| m.group == "test_group" && m.model == "model1").unwrap();
+        assert_eq!((model1.factor, model1.circuit), (100, health::CircuitState::Closed));
+    }
+
+    #[test]
+    fn test_slow_first_bytes_lower_health() {
+        let mut config = create_test_config();
+        config.router_settings.ttfb_health.enabled = true;
+        let model_manager = ModelManager::new(Arc::new(config));
+        let sel = model_manager.resolve("test_group", &serde_json::json!({"provider": {"order": ["model1"]}})).unwrap();
+        for _ in 0..5 {
+            model_manager.record_ttfb(&sel, Duration::from_millis(300));
+        }
+        for _ in 0..3 {
+            model_manager.record_ttfb(&sel, Duration::from_secs(10));
+        }
+        let state = model_manager.snapshot();
+        let model1 = state.members.iter().find(|m| m.group == "test_group" && m.model == "model1").unwrap();
+        assert_eq!((model1.factor, model1.circuit), (50, health::CircuitState::Closed));
+    }
+
+    #[test]
+    fn test_keys_share_names_and_borrowed_lookups_find_them() {
+        let model_manager = ModelManager::new(Arc::new(create_test_config()));
+        let (grouped, _) = model_manager.active_requests.get_key_value(&ModelKeyRef::new("test_group", "model1")).unwrap();
+        let (direct, _) = model_manager.active_requests.get_key_value(&ModelKeyRef::new(DIRECT_GROUP, "model1")).unwrap();
+        assert!(Arc::ptr_eq(&grouped.model, &direct.model));
+        assert!(model_manager.current_weights.get(&ModelKeyRef::new("test_group", "missing")).is_none());
+    }
+
+    #[test]
+    fn test_model_health_reports_outcomes_and_members() {
+        let model_manager = ModelManager::new(Arc::new(create_test_config()));
+        let sel = model_manager.resolve("test_group", &serde_json::json!({"provider": {"order": ["model1"]}})).unwrap();
+        model_manager.start(&sel);
+        model_manager.end(&sel, false);
+        model_manager.start(&sel);
+        model_manager.record_latency("model1", Duration::from_millis(120));
+        let report = model_manager.model_health();
+        let model1 = report.iter().find(|m| m.model == "model1").unwrap();
+        assert_eq!((model1.in_flight, model1.succeeded, model1.failed, model1.error_rate), (1, 0, 1, Some(1.0)));
+        assert_eq!(model1.latency_ms, Some(120));
+        let member = model1.members.iter().find(|m| m.group == "test_group").unwrap();
+        assert_eq!((member.factor, member.effective_weight), (50, (member.weight / 2).max(1)));
+        assert!(model1.members.iter().any(|m| m.group == DIRECT_GROUP));
+    }
+
+    #[test]
+    fn test_bulkhead_limits_in_flight_requests() {
+        let mut config = create_test_config();
+        config.model_list[0].llm_params.max_concurrency = Some(1);
+        let model_manager = ModelManager::new(Arc::new(config));
+
+        let bulkhead = model_manager.bulkhead("model1").unwrap();
+        let permit = bulkhead.try_acquire(Priority::Normal, 0);
+        assert!(permit.is_some());
+        assert!(bulkhead.try_acquire(Priority::High, 0).is_none());
+        // Unbounded models never block
+        assert!(model_manager.bulkhead("model2").is_none());
+        drop(permit);
+        assert!(bulkhead.try_acquire(Priority::Normal, 0).is_some());
+    }
+
+    #[test]
+    fn test_update_config_orphans_in_flight_members() {
+        let mut model_manager = ModelManager::new(Arc::new(create_test_config()));
+        model_manager.start_request("test_group", "model1");
+        model_manager.start_request("test_group", "model2");
+        model_manager.start_request("test_group", "model3");
+        model_manager.end_request("test_group", "model3", true);
+
+        // Drop model1 and model3 from test_group
+        let mut config = create_test_config();
+        config.router_settings.model_groups[0].models.retain(|m| m.name == "model2");
+        let report = model_manager.update_config(Arc::new(config));
+        assert_eq!(report.dropped, 1);
+        assert_eq!(report.orphaned.len(), 1);
+        assert_eq!((report.orphaned[0].model.as_str(), report.orphaned[0].in_flight), ("model1", 1));
+        let model2 = ModelKey::new("test_group", "model2");
+        assert_eq!(model_manager.active_requests[&model2].load(Ordering::SeqCst), 1);
+
+        // Still in flight, so nothing to collect
+        assert_eq!(model_manager.collect_orphans(), 0);
+        model_manager.end_request("test_group", "model1", true);
+        assert_eq!(model_manager.collect_orphans(), 1);
+        assert!(model_manager.orphaned_state().is_empty());
+        assert!(!model_manager.active_requests.contains_key(&ModelKey::new("test_group", "model1")));
+    }
+
     #[test]
     fn test_select_random_with_all_nonexistent_models() {
         let mut config = create_test_config();
@@ -645,11 +1785,13 @@ mod tests {
                 name: "model1".to_string(), // Doesn't exist
                 weight: 1,
                 selector: None,
+                uncensored: false,
             },
             ModelGroupEntry {
                 name: "model2".to_string(), // Doesn't exist
                 weight: 2,
                 selector: None,
+                uncensored: false,
             },
         ];
 
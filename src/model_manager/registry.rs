@@ -11,10 +11,14 @@ impl<'a> Registry<'a> {
         self.cfg.model_list.iter().any(|m| m.model_name == model_name)
     }
 
+    pub fn group_exists(&self, group_name: &str) -> bool {
+        self.cfg.router_settings.model_groups.iter().any(|g| g.name == group_name)
+    }
+
     pub fn filter_valid_entries(&self, entries: &[ModelGroupEntry]) -> Vec<ModelGroupEntry> {
         entries
             .iter()
-            .filter(|e| self.model_exists(&e.name))
+            .filter(|e| self.model_exists(&e.name) || self.group_exists(&e.name))
             .cloned()
             .collect()
     }
@@ -0,0 +1,140 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::info;
+
+use super::ModelManager;
+use super::types::ModelKey;
+use crate::config::Config;
+
+/// A (group, model) pair removed by a config reload while requests to it were in flight.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanedState {
+    pub group: String,
+    pub model: String,
+    pub in_flight: usize,
+    pub orphaned_secs: u64,
+}
+
+/// What a config reload did with the runtime state of each (group, model) pair.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReloadReport {
+    // Pairs in both configs; their counters, weights and health carry over
+    pub kept: usize,
+    pub added: usize,
+    // Removed pairs with nothing in flight, dropped right away
+    pub dropped: usize,
+    // Removed pairs still serving requests
+    pub orphaned: Vec<OrphanedState>,
+}
+
+impl ModelManager {
+    /// Switch to `config` without losing runtime state. Pairs that remain keep
+    /// their in-flight counts, SWRR weights, health and latency. Removed pairs
+    /// with requests in flight stay tracked as orphans, so those requests end
+    /// against a counter that still exists; `collect_orphans` drops them once drained.
+    pub fn update_config(&mut self, config: Arc<Config>) -> ReloadReport {
+        let mut fresh = ModelManager::new(config);
+        let restored = fresh.restore(&self.snapshot());
+        let mut report = ReloadReport { kept: restored, ..Default::default() };
+        report.added = fresh.active_requests.len() - report.kept;
+
+        for (key, count) in self.active_requests.drain() {
+            let in_flight = count.load(Ordering::SeqCst);
+            match fresh.active_requests.get(&key) {
+                Some(counter) => counter.store(in_flight, Ordering::SeqCst),
+                None if in_flight > 0 => {
+                    let since = self.orphans.remove(&key).unwrap_or_else(Instant::now);
+                    fresh.active_requests.insert(key.clone(), AtomicUsize::new(in_flight));
+                    fresh.orphans.insert(key, since);
+                }
+                // Orphans that drained since the last reload are not reported again
+                None if self.orphans.contains_key(&key) => {}
+                None => report.dropped += 1,
+            }
+        }
+        for (model, stats) in &self.stats {
+            if fresh.stats.contains_key(model) {
+                fresh.stats.insert(model.clone(), stats.clone());
+            }
+        }
+        // Bulkheads with an unchanged limit keep counting the permits already out
+        for (model, semaphore) in &self.bulkheads {
+            let unchanged = self.find_model(model).and_then(|m| m.llm_params.max_concurrency)
+                == fresh.find_model(model).and_then(|m| m.llm_params.max_concurrency);
+            if unchanged && fresh.bulkheads.contains_key(model) {
+                fresh.bulkheads.insert(model.clone(), semaphore.clone());
+            }
+        }
+
+        fresh.request_rate = std::mem::take(&mut self.request_rate);
+        // A seeded sequence continues rather than restarting on every reload
+        if self.config.router_settings.routing_seed == fresh.config.router_settings.routing_seed {
+            fresh.rng = std::mem::take(&mut self.rng);
+        }
+        *self = fresh;
+        report.orphaned = self.orphaned_state();
+        info!(
+            "Config reloaded: {} members kept, {} added, {} dropped, {} orphaned",
+            report.kept,
+            report.added,
+            report.dropped,
+            report.orphaned.len()
+        );
+        report
+    }
+
+    /// Removed pairs that still have requests in flight.
+    pub fn orphaned_state(&self) -> Vec<OrphanedState> {
+        let mut orphaned: Vec<OrphanedState> = self
+            .orphans
+            .iter()
+            .map(|(key, since)| OrphanedState {
+                group: key.group.to_string(),
+                model: key.model.to_string(),
+                in_flight: self.active_requests.get(key).map(|c| c.load(Ordering::SeqCst)).unwrap_or(0),
+                orphaned_secs: since.elapsed().as_secs(),
+            })
+            .collect();
+        orphaned.sort_by(|a, b| (&a.group, &a.model).cmp(&(&b.group, &b.model)));
+        orphaned
+    }
+
+    /// Forget orphans whose last request has ended; returns how many were dropped.
+    pub fn collect_orphans(&mut self) -> usize {
+        let drained: Vec<ModelKey> = self
+            .orphans
+            .keys()
+            .filter(|key| self.active_requests.get(*key).is_none_or(|c| c.load(Ordering::SeqCst) == 0))
+            .cloned()
+            .collect();
+        for key in &drained {
+            self.orphans.remove(key);
+            self.active_requests.remove(key);
+            self.health.forget(key.as_ref());
+        }
+        if !drained.is_empty() {
+            info!("Collected {} drained orphaned members", drained.len());
+        }
+        drained.len()
+    }
+}
+
+/// Collect `manager`'s orphans as they drain, checking every `interval`; ends
+/// when none are left.
+pub async fn collect_when_drained(manager: Arc<RwLock<ModelManager>>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let mut manager = manager.write().await;
+        manager.collect_orphans();
+        if manager.orphans.is_empty() {
+            return;
+        }
+    }
+}
+
+// Orphans keyed by pair, with the time they were orphaned
+pub(super) type Orphans = HashMap<ModelKey, Instant>;
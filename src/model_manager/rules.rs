@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::info;
+
+use super::ModelManager;
+use crate::config::{ModelGroupEntry, RoutingStrategy, StrategyRule, Weekday};
+use crate::utils::clock;
+
+// Seconds of history behind the request rate
+const RATE_WINDOW_SECS: u64 = 10;
+
+// Marks "no rule active" in ActiveRule
+const NO_RULE: usize = usize::MAX;
+
+/// Requests started per second, averaged over the last few seconds.
+#[derive(Debug, Default)]
+pub struct RateMeter {
+    // (unix second, requests started in it), oldest first
+    buckets: Mutex<VecDeque<(u64, u64)>>,
+}
+
+impl RateMeter {
+    pub fn record(&self, now_secs: u64) {
+        let mut buckets = self.buckets.lock().unwrap();
+        match buckets.back_mut() {
+            Some((sec, count)) if *sec == now_secs => *count += 1,
+            _ => buckets.push_back((now_secs, 1)),
+        }
+        while buckets.front().is_some_and(|(sec, _)| sec + RATE_WINDOW_SECS <= now_secs) {
+            buckets.pop_front();
+        }
+    }
+
+    pub fn rate(&self, now_secs: u64) -> f64 {
+        let buckets = self.buckets.lock().unwrap();
+        let recent: u64 = buckets
+            .iter()
+            .filter(|(sec, _)| sec + RATE_WINDOW_SECS > now_secs)
+            .map(|(_, count)| count)
+            .sum();
+        recent as f64 / RATE_WINDOW_SECS as f64
+    }
+}
+
+/// Index of the strategy rule applied last, to log when it changes.
+#[derive(Debug)]
+pub struct ActiveRule(AtomicUsize);
+
+impl Default for ActiveRule {
+    fn default() -> Self {
+        Self(AtomicUsize::new(NO_RULE))
+    }
+}
+
+// Whether `rule` holds at `now_secs` (UTC) with `rps` requests per second
+fn rule_matches(rule: &StrategyRule, now_secs: u64, rps: f64) -> bool {
+    if let Some([start, end]) = rule.hours {
+        let hour = (now_secs / 3600 % 24) as u8;
+        let inside = if start < end { hour >= start && hour < end } else { hour >= start || hour < end };
+        if !inside {
+            return false;
+        }
+    }
+    if !rule.days.is_empty() {
+        // 1970-01-01 was a Thursday
+        const DAYS: [Weekday; 7] =
+            [Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun, Weekday::Mon, Weekday::Tue, Weekday::Wed];
+        if !rule.days.contains(&DAYS[(now_secs / 86_400 % 7) as usize]) {
+            return false;
+        }
+    }
+    rule.min_rps.is_none_or(|min| rps >= min) && rule.max_rps.is_none_or(|max| rps < max)
+}
+
+impl ModelManager {
+    pub(super) fn record_request_rate(&self) {
+        self.request_rate.record(clock::now_secs());
+    }
+
+    /// The first strategy rule that currently matches, with its index.
+    pub fn active_rule(&self) -> Option<(usize, &StrategyRule)> {
+        let now = clock::now_secs();
+        let rps = self.request_rate.rate(now);
+        let found = self.config.router_settings.strategy_rules.iter().enumerate().find(|(_, rule)| rule_matches(rule, now, rps));
+        let idx = found.map_or(NO_RULE, |(idx, _)| idx);
+        let previous = self.active_rule.0.swap(idx, Ordering::Relaxed);
+        if previous != idx {
+            match found {
+                Some((idx, rule)) => info!(
+                    "Strategy rule {} now applies at {:.1} requests/s",
+                    rule.name.clone().unwrap_or_else(|| format!("#{}", idx)),
+                    rps
+                ),
+                None => info!("No strategy rule applies at {:.1} requests/s, using the configured strategy", rps),
+            }
+        }
+        found
+    }
+
+    /// Strategy for a group pick, with the active rule's weights applied to `candidates`.
+    pub(super) fn apply_strategy_rules(&self, group_name: &str, candidates: &mut [ModelGroupEntry]) -> RoutingStrategy {
+        let Some((_, rule)) = self.active_rule() else { return self.config.router_settings.strategy.clone() };
+        if let Some(weights) = rule.weights.get(group_name) {
+            for entry in candidates.iter_mut() {
+                if let Some(&weight) = weights.get(&entry.name) {
+                    entry.weight = weight;
+                }
+            }
+        }
+        rule.strategy.clone().unwrap_or_else(|| self.config.router_settings.strategy.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(yaml: &str) -> StrategyRule {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        // 2024-01-01 was a Monday; 10:30 UTC
+        let monday_1030 = 1_704_105_000;
+        assert!(rule_matches(&rule("{hours: [9, 17], days: [mon, tue]}"), monday_1030, 0.0));
+        assert!(!rule_matches(&rule("{hours: [9, 17], days: [sat]}"), monday_1030, 0.0));
+        assert!(rule_matches(&rule("{hours: [22, 11]}"), monday_1030, 0.0));
+        assert!(!rule_matches(&rule("{hours: [22, 6]}"), monday_1030, 0.0));
+        assert!(rule_matches(&rule("{min_rps: 5}"), monday_1030, 5.0));
+        assert!(!rule_matches(&rule("{min_rps: 5, max_rps: 10}"), monday_1030, 10.0));
+
+        let meter = RateMeter::default();
+        for sec in 0..20 {
+            for _ in 0..3 {
+                meter.record(sec);
+            }
+        }
+        assert_eq!(meter.rate(19), 3.0);
+        assert_eq!(meter.rate(100), 0.0);
+    }
+}
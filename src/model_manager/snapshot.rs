@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+use super::ModelManager;
+use super::health::CircuitState;
+
+/// Runtime routing state of one group member, as written to the state file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberState {
+    pub group: String,
+    pub model: String,
+    // Health factor in percentage points (100 = full weight)
+    pub factor: u32,
+    // Smooth weighted round robin accumulator
+    pub current_weight: isize,
+    pub circuit: CircuitState,
+    pub consecutive_failures: u32,
+    // Counts toward the group's recovery success_threshold; absent in older files
+    #[serde(default)]
+    pub consecutive_successes: u32,
+    // Wall-clock end of an open circuit, ms since the Unix epoch
+    pub open_until_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub saved_at_ms: u64,
+    pub members: Vec<MemberState>,
+}
+
+impl StateSnapshot {
+    /// Read a state file; a missing file is not an error.
+    pub fn load(path: &str) -> anyhow::Result<Option<Self>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    /// Write the state file, replacing it atomically.
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        let content = serde_json::to_vec(self)?;
+        let dir = std::path::Path::new(path)
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| std::path::Path::new("."));
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+        std::io::Write::write_all(&mut tmp, &content)?;
+        tmp.persist(path)?;
+        Ok(())
+    }
+}
+
+pub(super) fn unix_ms(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Snapshot the manager and write it to `path`; failures are logged, not returned.
+pub async fn save_state(manager: &RwLock<ModelManager>, path: &str) {
+    // Snapshot under the read lock, write without holding it
+    let snapshot = manager.read().await.snapshot();
+    let path_owned = path.to_string();
+    match tokio::task::spawn_blocking(move || snapshot.save(&path_owned)).await {
+        Ok(Ok(())) => debug!("Saved health state to {}", path),
+        Ok(Err(e)) => warn!("Failed to save health state to {}: {}", path, e),
+        Err(e) => warn!("Failed to save health state to {}: {}", path, e),
+    }
+}
+
+/// Save the state every `interval` until the task is dropped.
+pub async fn run_state_saver(manager: Arc<RwLock<ModelManager>>, path: String, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        save_state(&manager, &path).await;
+    }
+}
@@ -0,0 +1,241 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use super::ModelManager;
+use super::health::CircuitState;
+use super::ttfb::Ttfb;
+use super::types::{DIRECT_GROUP, ModelKeyRef};
+use crate::utils::clock;
+
+// Seconds of history behind the per-model success and failure counts
+pub const OUTCOME_WINDOW_SECS: u64 = 60;
+
+/// What the router has observed of one model. Shared by every group the model
+/// is in, and carried over by config reloads while the model stays.
+#[derive(Debug, Default)]
+pub struct ModelStats {
+    // Moving average of upstream latency in ms; 0 until observed
+    pub latency_ms: AtomicU64,
+    pub ttfb: Ttfb,
+    pub outcomes: Outcomes,
+    pub peaks: Peaks,
+}
+
+/// Requests a model finished, split by outcome, over the last minute.
+#[derive(Debug, Default)]
+pub struct Outcomes {
+    // (unix second, succeeded, failed), oldest first
+    buckets: Mutex<VecDeque<(u64, u64, u64)>>,
+}
+
+impl Outcomes {
+    pub fn record(&self, now_secs: u64, success: bool) {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.back().is_none_or(|(sec, _, _)| *sec != now_secs) {
+            buckets.push_back((now_secs, 0, 0));
+        }
+        let (_, succeeded, failed) = buckets.back_mut().unwrap();
+        if success { *succeeded += 1 } else { *failed += 1 }
+        while buckets.front().is_some_and(|(sec, _, _)| sec + OUTCOME_WINDOW_SECS <= now_secs) {
+            buckets.pop_front();
+        }
+    }
+
+    /// (succeeded, failed) within the window ending at `now_secs`.
+    pub fn counts(&self, now_secs: u64) -> (u64, u64) {
+        let buckets = self.buckets.lock().unwrap();
+        buckets
+            .iter()
+            .filter(|(sec, _, _)| sec + OUTCOME_WINDOW_SECS > now_secs)
+            .fold((0, 0), |(ok, failed), (_, s, f)| (ok + s, failed + f))
+    }
+}
+
+/// High-water marks of one model since start or the last reset, for
+/// capacity planning.
+#[derive(Debug, Default)]
+pub struct Peaks {
+    // Requests routed to the model now, whatever the group
+    in_flight: AtomicUsize,
+    peak_in_flight: AtomicUsize,
+    // Unix ms of the last failed request; 0 when none
+    last_error_ms: AtomicU64,
+}
+
+impl Peaks {
+    pub fn start(&self) {
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak_in_flight.fetch_max(now, Ordering::SeqCst);
+    }
+
+    pub fn end(&self) {
+        let _ = self.in_flight.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+    }
+
+    pub fn record_error(&self, at_ms: u64) {
+        self.last_error_ms.store(at_ms, Ordering::SeqCst);
+    }
+
+    pub fn peak_in_flight(&self) -> usize {
+        self.peak_in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn last_error_ms(&self) -> Option<u64> {
+        Some(self.last_error_ms.load(Ordering::SeqCst)).filter(|ms| *ms > 0)
+    }
+
+    /// Restart the peak from the requests in flight now.
+    pub fn reset(&self) {
+        self.peak_in_flight.store(self.in_flight.load(Ordering::SeqCst), Ordering::SeqCst);
+        self.last_error_ms.store(0, Ordering::SeqCst);
+    }
+}
+
+/// One model's routing signals, for external schedulers.
+#[derive(Debug, Serialize)]
+pub struct ModelHealth {
+    pub model: String,
+    pub in_flight: usize,
+    pub succeeded: u64,
+    pub failed: u64,
+    // failed / (succeeded + failed); null without requests in the window
+    pub error_rate: Option<f64>,
+    // Moving averages; null until observed
+    pub latency_ms: Option<u64>,
+    pub ttfb_ms: Option<u64>,
+    // High-water marks since start or the last reset; peak_queued is null
+    // for models without max_concurrency
+    pub peak_in_flight: usize,
+    pub peak_queued: Option<usize>,
+    pub last_error_at_ms: Option<u64>,
+    // The model as a member of each group, and as called directly (group "")
+    pub members: Vec<MemberHealth>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemberHealth {
+    pub group: String,
+    pub weight: u32,
+    pub effective_weight: u32,
+    // Health factor in percentage points (100 = full weight)
+    pub factor: u32,
+    pub circuit: CircuitState,
+    pub consecutive_failures: u32,
+}
+
+impl ModelManager {
+    /// Health, weights and recent outcomes of every model in `model_list`.
+    pub fn model_health(&self) -> Vec<ModelHealth> {
+        let now = clock::now_secs();
+        let members = self.snapshot().members;
+        self.config
+            .model_list
+            .iter()
+            .map(|model| {
+                let name = &model.model_name;
+                let stats = self.stats.get(name);
+                let (succeeded, failed) = stats.map_or((0, 0), |s| s.outcomes.counts(now));
+                let peaks = stats.map(|s| &s.peaks);
+                let members: Vec<MemberHealth> = members
+                    .iter()
+                    .filter(|m| &m.model == name)
+                    .map(|m| {
+                        let weight = match m.group.as_str() {
+                            DIRECT_GROUP => None,
+                            group => self.find_group(group).and_then(|g| g.models.iter().find(|e| &e.name == name)),
+                        };
+                        MemberHealth {
+                            group: m.group.clone(),
+                            weight: weight.map_or(0, |e| e.weight),
+                            effective_weight: weight.map_or(0, |e| self.health.effective_weight(&m.group, e)),
+                            factor: m.factor,
+                            circuit: m.circuit,
+                            consecutive_failures: m.consecutive_failures,
+                        }
+                    })
+                    .collect();
+                ModelHealth {
+                    model: name.clone(),
+                    in_flight: members
+                        .iter()
+                        .filter_map(|m| self.active_requests.get(&ModelKeyRef::new(&m.group, name)))
+                        .map(|count| count.load(Ordering::SeqCst))
+                        .sum(),
+                    succeeded,
+                    failed,
+                    error_rate: (succeeded + failed > 0).then(|| failed as f64 / (succeeded + failed) as f64),
+                    latency_ms: self.observed_latency(name).map(|l| l.as_millis() as u64),
+                    ttfb_ms: stats.and_then(|s| s.ttfb.baseline()).map(|t| t.as_millis() as u64),
+                    peak_in_flight: peaks.map_or(0, |p| p.peak_in_flight()),
+                    peak_queued: self.bulkheads.get(name).map(|b| b.peak_queued()),
+                    last_error_at_ms: peaks.and_then(|p| p.last_error_ms()),
+                    members,
+                }
+            })
+            .collect()
+    }
+
+    /// Start the high-water marks of every model over; returns how many
+    /// models were reset.
+    pub fn reset_peaks(&self) -> usize {
+        for stats in self.stats.values() {
+            stats.peaks.reset();
+        }
+        for bulkhead in self.bulkheads.values() {
+            bulkhead.reset_peak_queued();
+        }
+        self.stats.len()
+    }
+
+    /// (model, peak in flight, peak queued) for every model, for metrics.
+    pub fn peak_counts(&self) -> Vec<(String, usize, Option<usize>)> {
+        let mut counts: Vec<_> = self
+            .stats
+            .iter()
+            .map(|(model, stats)| (model.clone(), stats.peaks.peak_in_flight(), self.bulkheads.get(model).map(|b| b.peak_queued())))
+            .collect();
+        counts.sort();
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcomes_forget_old_seconds() {
+        let outcomes = Outcomes::default();
+        outcomes.record(100, true);
+        outcomes.record(100, false);
+        outcomes.record(130, true);
+        assert_eq!(outcomes.counts(130), (2, 1));
+        assert_eq!(outcomes.counts(165), (1, 0));
+        outcomes.record(200, false);
+        assert_eq!(outcomes.counts(200), (0, 1));
+    }
+
+    #[test]
+    fn test_peaks_keep_high_water_mark_until_reset() {
+        let peaks = Peaks::default();
+        peaks.start();
+        peaks.start();
+        peaks.end();
+        peaks.start();
+        peaks.end();
+        assert_eq!(peaks.peak_in_flight(), 2);
+        assert_eq!(peaks.last_error_ms(), None);
+        peaks.record_error(1_700_000_000_000);
+        assert_eq!(peaks.last_error_ms(), Some(1_700_000_000_000));
+
+        peaks.reset();
+        assert_eq!(peaks.peak_in_flight(), 1);
+        assert_eq!(peaks.last_error_ms(), None);
+        peaks.end();
+        peaks.end();
+        peaks.start();
+        assert_eq!(peaks.peak_in_flight(), 1);
+    }
+}
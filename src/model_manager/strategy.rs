@@ -1,14 +1,36 @@
-use rand::Rng;
+use rand::distributions::uniform::SampleUniform;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::ops::Range;
+use std::sync::Mutex;
 use tracing::{debug, warn};
 
 use super::ModelManager;
-use super::types::ModelKey;
+use super::types::{DIRECT_GROUP, ModelKeyRef};
+
+/// Randomness behind routing picks and tie-breaks. With `routing_seed` set the
+/// sequence is reproducible; otherwise it comes from the thread RNG.
+#[derive(Debug, Default)]
+pub struct RoutingRng(Option<Mutex<StdRng>>);
+
+impl RoutingRng {
+    pub fn new(seed: Option<u64>) -> Self {
+        Self(seed.map(|seed| Mutex::new(StdRng::seed_from_u64(seed))))
+    }
+
+    fn gen_range<T: SampleUniform + PartialOrd>(&self, range: Range<T>) -> T {
+        match &self.0 {
+            Some(rng) => rng.lock().unwrap().gen_range(range),
+            None => rand::thread_rng().gen_range(range),
+        }
+    }
+}
 
 impl ModelManager {
     pub fn select_round_robin(&self, group_name: &str, models: &[crate::config::ModelGroupEntry]) -> String {
         let base_models: Vec<&crate::config::ModelGroupEntry> = models
             .iter()
-            .filter(|model| self.model_exists(&model.name))
+            .filter(|model| self.member_exists(&model.name))
             .collect();
 
         // Apply circuit breaker permit; fallback to base list if all filtered out
@@ -42,8 +64,7 @@ impl ModelManager {
             .sum();
         if total_weight == 0 {
             // If all weights are 0, select one randomly (unweighted)
-            let mut rng = rand::thread_rng();
-            let index = rng.gen_range(0..valid_models.len());
+            let index = self.rng.gen_range(0..valid_models.len());
             return valid_models[index].name.clone();
         }
 
@@ -51,7 +72,7 @@ impl ModelManager {
         for model in &valid_models {
             if let Some(current) = self
                 .current_weights
-                .get(&ModelKey::new(group_name.to_string(), model.name.clone()))
+                .get(&ModelKeyRef::new(group_name, &model.name))
             {
                 let w = self.health.effective_weight(group_name, model) as isize;
                 current.fetch_add(w, std::sync::atomic::Ordering::SeqCst);
@@ -64,7 +85,7 @@ impl ModelManager {
         for model in &valid_models {
             if let Some(current) = self
                 .current_weights
-                .get(&ModelKey::new(group_name.to_string(), model.name.clone()))
+                .get(&ModelKeyRef::new(group_name, &model.name))
             {
                 let val = current.load(std::sync::atomic::Ordering::SeqCst);
                 if val > max_current {
@@ -80,8 +101,7 @@ impl ModelManager {
         let selected_model = if best_models.len() == 1 {
             best_models[0]
         } else if !best_models.is_empty() {
-            let mut rng = rand::thread_rng();
-            let index = rng.gen_range(0..best_models.len());
+            let index = self.rng.gen_range(0..best_models.len());
             best_models[index]
         } else {
             // Fallback should not happen; choose first valid
@@ -91,7 +111,7 @@ impl ModelManager {
         // 3) Subtract total weight from the selected model's current weight
         if let Some(curr) = self
             .current_weights
-            .get(&ModelKey::new(group_name.to_string(), selected_model.name.clone()))
+            .get(&ModelKeyRef::new(group_name, &selected_model.name))
         {
             curr.fetch_sub(total_weight, std::sync::atomic::Ordering::SeqCst);
         }
@@ -99,13 +119,12 @@ impl ModelManager {
         selected_model.name.clone()
     }
 
+    /// The member with the fewest in-flight requests per unit of weight.
+    /// Deterministic: no randomness is involved.
     pub fn select_least_conn(&self, group_name: &str, models: &[crate::config::ModelGroupEntry]) -> String {
-        let mut min_score = f64::MAX;
-        let mut best_models: Vec<&crate::config::ModelGroupEntry> = Vec::new();
-
         let base_models: Vec<&crate::config::ModelGroupEntry> = models
             .iter()
-            .filter(|model| self.model_exists(&model.name))
+            .filter(|model| self.member_exists(&model.name))
             .collect();
 
         let mut valid_models: Vec<&crate::config::ModelGroupEntry> = base_models
@@ -125,57 +144,46 @@ impl ModelManager {
             );
         }
 
-        for model_entry in &valid_models {
-            let key = ModelKey::new(group_name.to_string(), model_entry.name.clone());
-
-            let active_requests = self
-                .active_requests
-                .get(&key)
-                .map(|count| count.load(std::sync::atomic::Ordering::SeqCst) as f64)
-                .unwrap_or(0.0);
-            
-            let current_weight = self.health.effective_weight(group_name, model_entry) as f64;
-            
-            let score = if current_weight > 0.0 {
-                active_requests / current_weight
-            } else {
-                f64::MAX
+        // Load per unit of weight once this request is added: (active + 1) / weight.
+        // Counting the new request keeps an idle low-weight member from beating a
+        // busier high-weight one, so in-flight requests settle in weight proportion.
+        // Scores are compared as cross products to stay exact.
+        let load = |entry: &crate::config::ModelGroupEntry| -> (u64, u64) {
+            // Direct calls to the same model load it too
+            let active: usize = [ModelKeyRef::new(group_name, &entry.name), ModelKeyRef::new(DIRECT_GROUP, &entry.name)]
+                .iter()
+                .filter_map(|k| self.active_requests.get(k))
+                .map(|count| count.load(std::sync::atomic::Ordering::SeqCst))
+                .sum();
+            (active as u64, self.health.effective_weight(group_name, entry) as u64)
+        };
+        let loads: Vec<(u64, u64)> = valid_models.iter().map(|m| load(m)).collect();
+        let mut best = 0;
+        for (i, &(active, weight)) in loads.iter().enumerate().skip(1) {
+            let (best_active, best_weight) = loads[best];
+            let ordering = match (weight, best_weight) {
+                // Zero-weight members only serve when every member has zero weight
+                (0, 0) => active.cmp(&best_active),
+                (0, _) => std::cmp::Ordering::Greater,
+                (_, 0) => std::cmp::Ordering::Less,
+                _ => ((active + 1) * best_weight).cmp(&((best_active + 1) * weight)),
             };
-            
-            debug!("Model {} in group {}: active_requests={}, current_weight={}, score={}",
-                   model_entry.name, group_name, active_requests, current_weight, score);
-
-            if score < min_score {
-                min_score = score;
-                best_models.clear();
-                best_models.push(model_entry);
-            } else if (score - min_score).abs() < f64::EPSILON {
-                best_models.push(model_entry);
+            // Ties go to the heavier member, then to the one listed first
+            if ordering.then(best_weight.cmp(&weight)).is_lt() {
+                best = i;
             }
         }
-
-        if best_models.is_empty() {
-            warn!("No suitable model found in group {}, falling back to first valid model in group.", group_name);
-            return valid_models.first().map_or_else(
-                || self.config.model_list.get(0).map_or_else(|| String::new(), |m| m.model_name.clone()),
-                |m| m.name.clone()
-            );
-        }
-
-        if best_models.len() == 1 {
-            return best_models[0].name.clone();
-        }
-        
-        let best_models_owned: Vec<crate::config::ModelGroupEntry> = best_models.into_iter().cloned().collect();
-
-        debug!("Multiple models with best score, using weighted random selection.");
-        self.select_random_with_group(group_name, &best_models_owned)
+        debug!(
+            "Least connections in group {}: {} (active={}, weight={})",
+            group_name, valid_models[best].name, loads[best].0, loads[best].1
+        );
+        valid_models[best].name.clone()
     }
 
     pub fn select_random(&self, models: &[crate::config::ModelGroupEntry]) -> String {
         let valid_models: Vec<_> = models
             .iter()
-            .filter(|model| self.model_exists(&model.name))
+            .filter(|model| self.member_exists(&model.name))
             .collect();
 
         if valid_models.is_empty() {
@@ -194,13 +202,11 @@ impl ModelManager {
             .sum();
         if total_weight == 0 {
             // If all weights are 0, select one randomly (unweighted)
-            let mut rng = rand::thread_rng();
-            let index = rng.gen_range(0..valid_models.len());
+            let index = self.rng.gen_range(0..valid_models.len());
             return valid_models[index].name.clone();
         }
 
-        let mut rng = rand::thread_rng();
-        let mut random_weight = rng.gen_range(0..total_weight);
+        let mut random_weight = self.rng.gen_range(0..total_weight);
 
         for model in &valid_models {
             let w = model.weight;
@@ -217,10 +223,32 @@ impl ModelManager {
         )
     }
 
+    /// Weighted random by effective weight, except that `exploration_percent`
+    /// of picks go uniformly to members running below their configured weight.
+    /// Their successes restore the weight, so recovered upstreams win traffic
+    /// back without waiting for the few requests their decayed weight draws.
+    pub fn select_explore(&self, group_name: &str, models: &[crate::config::ModelGroupEntry]) -> String {
+        let percent = self.config.router_settings.exploration_percent;
+        if percent > 0 && self.rng.gen_range(0..100) < percent {
+            let deprioritized: Vec<_> = models
+                .iter()
+                .filter(|m| self.member_exists(&m.name))
+                .filter(|m| self.health.effective_weight(group_name, m) < m.weight)
+                .filter(|m| self.health.permit(group_name, m))
+                .collect();
+            if !deprioritized.is_empty() {
+                let pick = deprioritized[self.rng.gen_range(0..deprioritized.len())];
+                debug!("Exploring deprioritized member {} in group {}", pick.name, group_name);
+                return pick.name.clone();
+            }
+        }
+        self.select_random_with_group(group_name, models)
+    }
+
     pub fn select_random_with_group(&self, group_name: &str, models: &[crate::config::ModelGroupEntry]) -> String {
         let base_models: Vec<_> = models
             .iter()
-            .filter(|model| self.model_exists(&model.name))
+            .filter(|model| self.member_exists(&model.name))
             .collect();
 
         if base_models.is_empty() {
@@ -246,13 +274,11 @@ impl ModelManager {
             .sum();
         if total_weight == 0 {
             // If all weights are 0, select one randomly (unweighted)
-            let mut rng = rand::thread_rng();
-            let index = rng.gen_range(0..valid_models.len());
+            let index = self.rng.gen_range(0..valid_models.len());
             return valid_models[index].name.clone();
         }
 
-        let mut rng = rand::thread_rng();
-        let mut random_weight = rng.gen_range(0..total_weight);
+        let mut random_weight = self.rng.gen_range(0..total_weight);
 
         for model in &valid_models {
             let w = self.health.effective_weight(group_name, model);
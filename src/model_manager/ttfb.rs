@@ -0,0 +1,77 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::TtfbHealthSettings;
+
+/// Time to first byte of one model's streams. A provider that accepts
+/// connections but queues work before streaming never fails a request, so
+/// its health only shows in how long the first chunk takes.
+#[derive(Debug, Default)]
+pub struct Ttfb {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // Slow moving average in ms; 0 until the first sample
+    baseline: u64,
+    // Slow streams in a row
+    streak: u32,
+}
+
+impl Ttfb {
+    /// Fold in a sample. True when it completes a run of `settings.samples`
+    /// slow streams, after which the run starts over.
+    pub fn record(&self, ttfb: Duration, settings: &TtfbHealthSettings) -> bool {
+        let sample = (ttfb.as_millis() as u64).max(1);
+        let mut state = self.state.lock().unwrap();
+        let limit = (state.baseline as f64 * settings.ratio) as u64;
+        let slow = state.baseline > 0 && sample > limit.max(settings.min_ms);
+        state.baseline = match state.baseline {
+            0 => sample,
+            cur => (cur * 31 + sample) / 32,
+        };
+        if !slow {
+            state.streak = 0;
+            return false;
+        }
+        state.streak += 1;
+        if state.streak < settings.samples {
+            return false;
+        }
+        state.streak = 0;
+        true
+    }
+
+    pub fn baseline(&self) -> Option<Duration> {
+        match self.state.lock().unwrap().baseline {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_sustained_slowness_counts() {
+        let settings = TtfbHealthSettings { enabled: true, ratio: 3.0, min_ms: 500, samples: 3 };
+        let ttfb = Ttfb::default();
+        for _ in 0..10 {
+            assert!(!ttfb.record(Duration::from_millis(200), &settings));
+        }
+        // Slow, but under min_ms
+        assert!(!ttfb.record(Duration::from_millis(450), &settings));
+        // One slow stream between fast ones resets the run
+        assert!(!ttfb.record(Duration::from_secs(5), &settings));
+        assert!(!ttfb.record(Duration::from_secs(5), &settings));
+        assert!(!ttfb.record(Duration::from_millis(200), &settings));
+        assert!(!ttfb.record(Duration::from_secs(5), &settings));
+        assert!(!ttfb.record(Duration::from_secs(5), &settings));
+        assert!(ttfb.record(Duration::from_secs(5), &settings));
+        assert!(!ttfb.record(Duration::from_secs(5), &settings));
+        assert!(ttfb.baseline().unwrap() > Duration::from_millis(200));
+    }
+}
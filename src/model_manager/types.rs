@@ -1,13 +1,52 @@
+use std::sync::Arc;
+
+use hashbrown::Equivalent;
+
+/// Group name under which models called directly (outside any group) are tracked.
+pub const DIRECT_GROUP: &str = "";
+
+/// Maps on the request path: foldhash instead of SipHash, and lookups by
+/// `ModelKeyRef` so finding a pair allocates nothing.
+pub type FastMap<K, V> = hashbrown::HashMap<K, V>;
+
+// Names are shared, so cloning a key only bumps two reference counts
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ModelKey {
-    pub group: String,
-    pub model: String,
+    pub group: Arc<str>,
+    pub model: Arc<str>,
 }
 
 impl ModelKey {
-    pub fn new<G: Into<String>, M: Into<String>>(group: G, model: M) -> Self {
+    pub fn new<G: Into<Arc<str>>, M: Into<Arc<str>>>(group: G, model: M) -> Self {
         Self { group: group.into(), model: model.into() }
     }
+
+    pub fn as_ref(&self) -> ModelKeyRef<'_> {
+        ModelKeyRef::new(&self.group, &self.model)
+    }
 }
 
+/// A borrowed `ModelKey`. Hashes the same as the key it stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModelKeyRef<'a> {
+    pub group: &'a str,
+    pub model: &'a str,
+}
 
+impl<'a> ModelKeyRef<'a> {
+    pub fn new(group: &'a str, model: &'a str) -> Self {
+        Self { group, model }
+    }
+}
+
+impl Equivalent<ModelKey> for ModelKeyRef<'_> {
+    fn equivalent(&self, key: &ModelKey) -> bool {
+        self.group == &*key.group && self.model == &*key.model
+    }
+}
+
+impl From<&ModelKeyRef<'_>> for ModelKey {
+    fn from(key: &ModelKeyRef<'_>) -> Self {
+        ModelKey::new(key.group, key.model)
+    }
+}
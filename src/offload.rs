@@ -0,0 +1,129 @@
+//! Moves CPU-heavy conversion of large bodies off the async workers, so one
+//! multi-megabyte request does not stall the streams served next to it.
+
+use crate::auth::AppState;
+use axum::{
+    extract::{Request, State},
+    http::header::CONTENT_LENGTH,
+    middleware::Next,
+    response::Response,
+};
+use std::cell::Cell;
+use std::future::Future;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tracing::debug;
+
+/// Offload settings of the request being handled.
+#[derive(Debug, Clone, Copy)]
+pub struct Offload {
+    /// `router_settings.offload_conversion_bytes`
+    pub threshold: usize,
+    /// Size of the client request: its Content-Length until the handler has
+    /// read the body, which also sizes chunked uploads
+    pub request_bytes: usize,
+}
+
+tokio::task_local! {
+    static OFFLOAD: Cell<Offload>;
+}
+
+/// Offload settings of the request being handled, if offloading is enabled.
+pub fn current() -> Option<Offload> {
+    OFFLOAD.try_with(Cell::get).ok()
+}
+
+/// Record the size of the request body once it has been read.
+pub fn set_request_bytes(request_bytes: usize) {
+    let _ = OFFLOAD.try_with(|offload| offload.set(Offload { request_bytes, ..offload.get() }));
+}
+
+/// Run `fut` with the offload settings of the calling task, for work handed to
+/// a spawned task.
+pub fn inherit<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    let offload = current();
+    async move {
+        match offload {
+            Some(offload) => OFFLOAD.scope(Cell::new(offload), fut).await,
+            None => fut.await,
+        }
+    }
+}
+
+/// Make `router_settings.offload_conversion_bytes` and the request size
+/// available to the conversion code of this request.
+pub async fn track(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let threshold = state.model_manager.read().await.get_config().router_settings.offload_conversion_bytes;
+    let Some(threshold) = threshold else { return next.run(req).await };
+    let request_bytes = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    OFFLOAD.scope(Cell::new(Offload { threshold, request_bytes }), next.run(req)).await
+}
+
+/// Run `work` for a body of `bytes` bytes. At or above the threshold it runs
+/// under `block_in_place`: still on this thread, which hands its other tasks
+/// to another worker first. Otherwise, outside a request, or on a
+/// single-threaded runtime it runs inline with nothing handed off.
+pub fn run<T>(bytes: usize, work: impl FnOnce() -> T) -> T {
+    let offload = current().is_some_and(|offload| bytes >= offload.threshold)
+        && Handle::try_current().is_ok_and(|handle| handle.runtime_flavor() == RuntimeFlavor::MultiThread);
+    if !offload {
+        return work();
+    }
+    debug!("Converting {} bytes off the async worker", bytes);
+    tokio::task::block_in_place(work)
+}
+
+/// `run` sized by the client request.
+pub fn run_for_request<T>(work: impl FnOnce() -> T) -> T {
+    run(current().map_or(0, |offload| offload.request_bytes), work)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+
+    // With a single worker, a spawned task only runs while `run` blocks if the
+    // work left the worker
+    fn other_task_ran(bytes: usize) -> bool {
+        let flag = Arc::new(AtomicBool::new(false));
+        let spawned = flag.clone();
+        tokio::spawn(async move { spawned.store(true, Ordering::SeqCst) });
+        run(bytes, || {
+            let deadline = Instant::now() + Duration::from_millis(300);
+            while !flag.load(Ordering::SeqCst) && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            flag.load(Ordering::SeqCst)
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_large_bodies_leave_the_worker() {
+        // The test body itself runs outside the runtime's worker; occupy it
+        tokio::spawn(async {
+            // Outside a request everything runs inline
+            assert!(!other_task_ran(usize::MAX));
+
+            let offload = Offload { threshold: 1000, request_bytes: 0 };
+            OFFLOAD
+                .scope(Cell::new(offload), async move {
+                    assert!(!other_task_ran(10));
+                    assert!(other_task_ran(1000));
+                    // A chunked upload is sized once its body is read
+                    set_request_bytes(5000);
+                    let inherited = tokio::spawn(inherit(async { current().map(|o| o.request_bytes) }));
+                    assert_eq!(inherited.await.unwrap(), Some(5000));
+                })
+                .await;
+        })
+        .await
+        .unwrap();
+    }
+}
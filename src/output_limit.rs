@@ -0,0 +1,81 @@
+//! Per-model output token limits (`max_output_tokens`). A request asking for
+//! more than the model can produce only fails upstream, so it is clamped to
+//! the limit or rejected, depending on the model's `max_tokens_policy`.
+
+use crate::config::LLMParams;
+use crate::converters::request_wrapper::RequestWrapper;
+
+/// Max tokens a request asks for over a model's limit.
+#[derive(Debug, PartialEq)]
+pub struct Oversized {
+    pub requested: u32,
+    pub limit: u32,
+}
+
+impl Oversized {
+    pub fn warning(&self, model: &str) -> String {
+        format!("max_tokens {} lowered to {}, the limit of model '{}'", self.requested, self.limit, model)
+    }
+
+    /// `peers` are the members of the model's group that allow `requested`.
+    pub fn message(&self, model: &str, group: Option<&str>, peers: &[String]) -> String {
+        let mut message = format!("max_tokens {} is over the limit of {} for model '{}'", self.requested, self.limit, model);
+        if let (Some(group), false) = (group, peers.is_empty()) {
+            message.push_str(&format!("; models in group '{}' that allow it: {}", group, peers.join(", ")));
+        }
+        message
+    }
+}
+
+/// Check the max tokens of `request` against the `max_output_tokens` of `params`.
+/// Requests that leave max tokens out get the upstream's default and pass.
+pub fn check(request: &RequestWrapper, params: &LLMParams) -> Option<Oversized> {
+    let limit = params.max_output_tokens?;
+    let requested = request.max_tokens()?;
+    (requested > limit).then_some(Oversized { requested, limit })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModelConfig;
+    use serde_json::json;
+
+    fn params(limit: &str) -> LLMParams {
+        let model: ModelConfig = serde_yaml::from_str(&format!(
+            "model_name: small\nllm_params: {{api_type: openai, model: m, api_base: 'http://localhost', api_key: k, {}}}",
+            limit
+        ))
+        .unwrap();
+        model.llm_params
+    }
+
+    #[test]
+    fn test_oversized_max_tokens_in_every_field() {
+        let openai = |body| RequestWrapper::OpenAI(serde_json::from_value(body).unwrap());
+        let limited = params("max_output_tokens: 4096");
+        assert_eq!(check(&openai(json!({"messages": [], "max_tokens": 8192})), &params("")), None);
+        assert_eq!(check(&openai(json!({"messages": []})), &limited), None);
+        assert_eq!(check(&openai(json!({"messages": [], "max_tokens": 4096})), &limited), None);
+        assert_eq!(
+            check(&openai(json!({"messages": [], "max_tokens": 100, "max_completion_tokens": 8192})), &limited),
+            Some(Oversized { requested: 8192, limit: 4096 })
+        );
+        let anthropic = RequestWrapper::Anthropic(serde_json::from_value(json!({"max_tokens": 5000, "messages": []})).unwrap());
+        assert_eq!(check(&anthropic, &limited), Some(Oversized { requested: 5000, limit: 4096 }));
+
+        let mut gemini = RequestWrapper::Gemini(
+            serde_json::from_value(json!({"contents": [], "generationConfig": {"maxOutputTokens": 9000}})).unwrap(),
+        );
+        gemini.set_max_tokens(4096);
+        assert_eq!(check(&gemini, &limited), None);
+        assert_eq!(gemini.max_tokens(), Some(4096));
+
+        let over = Oversized { requested: 8192, limit: 4096 };
+        assert_eq!(
+            over.message("small", Some("chat"), &["big".to_string()]),
+            "max_tokens 8192 is over the limit of 4096 for model 'small'; models in group 'chat' that allow it: big"
+        );
+        assert_eq!(over.message("small", None, &[]), "max_tokens 8192 is over the limit of 4096 for model 'small'");
+    }
+}
@@ -0,0 +1,210 @@
+use crate::config::{ApiType, OutputValidationSettings, Pattern};
+use crate::converters::request_wrapper::RequestWrapper;
+use serde_json::{Value, json};
+
+/// What an answer to one request has to look like.
+#[derive(Debug, Clone, Default)]
+pub struct Expectation {
+    pub json: bool,
+    pub schema: Option<Value>,
+    pub pattern: Option<Pattern>,
+}
+
+/// What to check the answer to `request` against, or `None` when nothing applies.
+/// JSON mode is OpenAI `response_format` or Gemini `responseMimeType`; Anthropic
+/// has no JSON mode, so only the pattern applies to it.
+pub fn expectation(settings: &OutputValidationSettings, request: &RequestWrapper) -> Option<Expectation> {
+    let mut expect = Expectation {
+        pattern: settings.pattern.clone(),
+        ..Default::default()
+    };
+    if settings.json {
+        match request {
+            RequestWrapper::OpenAI(req) => {
+                if let Some(format) = &req.response_format {
+                    expect.json = format.r#type == "json_object" || format.r#type == "json_schema";
+                    expect.schema = format.json_schema.as_ref().map(|spec| spec.schema.clone());
+                }
+            }
+            RequestWrapper::Gemini(req) => {
+                if let Some(config) = &req.generation_config {
+                    expect.json = config.response_mime_type.as_deref() == Some("application/json");
+                    expect.schema = config.response_schema.clone().filter(|_| expect.json);
+                }
+            }
+            RequestWrapper::Anthropic(_) => {}
+        }
+    }
+    (expect.json || expect.pattern.is_some()).then_some(expect)
+}
+
+/// Check a complete response body in the client-facing format of `api_type`.
+/// Answers that only call tools have no text to check and pass.
+pub fn validate(expect: &Expectation, api_type: &ApiType, body: &Value) -> Result<(), String> {
+    let Some(text) = answer_text(api_type, body) else { return Ok(()) };
+    if expect.json {
+        let value: Value = serde_json::from_str(text.trim()).map_err(|e| format!("the answer is not valid JSON ({})", e))?;
+        if let Some(schema) = &expect.schema {
+            check_schema(schema, &value, "$")?;
+        }
+    }
+    if let Some(pattern) = &expect.pattern
+        && !pattern.is_match(&text)
+    {
+        return Err(format!("the answer does not match the required pattern {}", pattern.as_str()));
+    }
+    Ok(())
+}
+
+// Text of the first choice/candidate; None when it is a tool call without text
+fn answer_text(api_type: &ApiType, body: &Value) -> Option<String> {
+    let texts: Vec<&str> = match api_type {
+        ApiType::OpenAI => body["choices"][0]["message"]["content"].as_str().into_iter().collect(),
+        ApiType::Anthropic => body["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect(),
+        ApiType::Gemini => body["candidates"][0]["content"]["parts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|part| part["thought"] != true)
+            .filter_map(|part| part["text"].as_str())
+            .collect(),
+    };
+    let text = texts.concat();
+    let calls_tools = match api_type {
+        ApiType::OpenAI => body["choices"][0]["message"]["tool_calls"].as_array().is_some_and(|calls| !calls.is_empty()),
+        ApiType::Anthropic => body["content"].as_array().into_iter().flatten().any(|block| block["type"] == "tool_use"),
+        ApiType::Gemini => body["candidates"][0]["content"]["parts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|part| part.get("functionCall").is_some()),
+    };
+    (!(calls_tools && text.trim().is_empty())).then_some(text)
+}
+
+// The parts of JSON Schema structured output relies on: type, enum, required,
+// properties, additionalProperties: false and items. Gemini writes types in capitals.
+fn check_schema(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<String> = match expected {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).map(str::to_lowercase).collect(),
+            other => other.as_str().map(str::to_lowercase).into_iter().collect(),
+        };
+        let actual = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        let matches = types.iter().any(|t| t == actual || (t == "number" && actual == "integer"));
+        if !types.is_empty() && !matches {
+            return Err(format!("{} should be {} but is {}", path, types.join(" or "), actual));
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        return Err(format!("{} is not one of {}", path, Value::Array(allowed.clone())));
+    }
+    if let Value::Object(obj) = value {
+        for key in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+            if !obj.contains_key(key) {
+                return Err(format!("{} is missing required field '{}'", path, key));
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, item) in obj {
+            match properties.and_then(|p| p.get(key)) {
+                Some(property) => check_schema(property, item, &format!("{}.{}", path, key))?,
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    return Err(format!("{} has unexpected field '{}'", path, key));
+                }
+                None => {}
+            }
+        }
+    }
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check_schema(item_schema, item, &format!("{}[{}]", path, i))?;
+        }
+    }
+    Ok(())
+}
+
+/// Add the corrective note for a retry as a system instruction in the request's own format.
+pub fn add_nudge(request: &mut RequestWrapper, nudge: &str) -> serde_json::Result<()> {
+    let api_type = request.api_type();
+    request.edit_json(|obj| match api_type {
+        ApiType::OpenAI => {
+            if let Some(Value::Array(messages)) = obj.get_mut("messages") {
+                messages.push(json!({"role": "system", "content": nudge}));
+            }
+        }
+        ApiType::Anthropic => {
+            let system = match obj.remove("system") {
+                Some(Value::String(text)) if !text.is_empty() => json!(format!("{}\n\n{}", text, nudge)),
+                Some(Value::Array(mut blocks)) => {
+                    blocks.push(json!({"type": "text", "text": nudge}));
+                    Value::Array(blocks)
+                }
+                _ => json!(nudge),
+            };
+            obj.insert("system".to_string(), system);
+        }
+        ApiType::Gemini => {
+            let instruction = obj.entry("system_instruction").or_insert_with(|| json!({"parts": []}));
+            if let Some(parts) = instruction.get_mut("parts").and_then(Value::as_array_mut) {
+                parts.push(json!({"text": nudge}));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_json_answers() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "properties": {"name": {"type": "string"}, "tags": {"type": "ARRAY", "items": {"type": "STRING"}}},
+            "additionalProperties": false,
+        });
+        let expect = Expectation { json: true, schema: Some(schema), pattern: None };
+        let answer = |content: &str| json!({"choices": [{"message": {"role": "assistant", "content": content}}]});
+
+        assert!(validate(&expect, &ApiType::OpenAI, &answer(r#" {"name": "a", "tags": ["x"]} "#)).is_ok());
+        let err = validate(&expect, &ApiType::OpenAI, &answer("Sure! {\"name\": \"a\"}")).unwrap_err();
+        assert!(err.starts_with("the answer is not valid JSON"), "{}", err);
+        let err = validate(&expect, &ApiType::OpenAI, &answer(r#"{"name": "a", "tags": [1]}"#)).unwrap_err();
+        assert_eq!(err, "$.tags[0] should be string but is integer");
+        let err = validate(&expect, &ApiType::OpenAI, &answer(r#"{"name": "a", "tags": [], "x": 1}"#)).unwrap_err();
+        assert_eq!(err, "$ has unexpected field 'x'");
+        let tool_call = json!({"choices": [{"message": {"content": null, "tool_calls": [{"id": "1"}]}}]});
+        assert!(validate(&expect, &ApiType::OpenAI, &tool_call).is_ok());
+
+        let expect = Expectation { pattern: Some(Pattern::new(r"^\d+$", false).unwrap()), ..Default::default() };
+        let anthropic = json!({"content": [{"type": "text", "text": "forty-two"}]});
+        assert!(validate(&expect, &ApiType::Anthropic, &anthropic).is_err());
+    }
+
+    #[test]
+    fn test_add_nudge() {
+        let mut request = RequestWrapper::Anthropic(
+            serde_json::from_value(json!({"model": "m", "max_tokens": 10, "system": "Be brief.", "messages": []})).unwrap(),
+        );
+        add_nudge(&mut request, "Reply with JSON only.").unwrap();
+        assert_eq!(serde_json::to_value(&request).unwrap()["system"], "Be brief.\n\nReply with JSON only.");
+    }
+}
@@ -0,0 +1,158 @@
+use crate::error::{ErrorKind, RouterError};
+use crate::metrics::Metrics;
+use crate::router::error_body_in_client_format;
+use crate::session_caps::client_api;
+use axum::{
+    Json,
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::{FutureExt, StreamExt};
+use http_body_util::{BodyStream, StreamBody};
+use serde_json::Value;
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+// Object keys whose string values are kept when redacting: they identify the
+// request shape without carrying user content.
+const KEPT_KEYS: [&str; 3] = ["model", "type", "role"];
+
+/// Isolates panics to the request that caused them. A panic while building the
+/// response becomes a 500 in the error format of the client's API; a panic
+/// while streaming ends that response body. Either way the panic is counted and
+/// logged with a redacted copy of the request payload. The state carries
+/// `router_settings.max_request_bytes`, the cap the body is buffered under.
+pub async fn catch_panics(
+    State((metrics, body_limit)): State<(Arc<Metrics>, usize)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    let client = client_api(parts.uri.path());
+    let payload = match axum::body::to_bytes(body, body_limit).await {
+        Ok(payload) => payload,
+        Err(e) => {
+            return RouterError::client(StatusCode::PAYLOAD_TOO_LARGE, "invalid_body", format!("Failed to read request body: {}", e))
+                .into_response();
+        }
+    };
+    let request = Request::from_parts(parts, Body::from(payload.clone()));
+
+    let resp = match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+        Ok(resp) => resp,
+        Err(panic) => {
+            report(&metrics, &payload, panic.as_ref());
+            let message = "Internal error while handling the request".to_string();
+            let Some(api_type) = client else { return RouterError::Internal(message).into_response() };
+            let body = error_body_in_client_format(&api_type, StatusCode::INTERNAL_SERVER_ERROR, "internal_error", message);
+            let mut response = (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+            response.extensions_mut().insert(ErrorKind::Internal);
+            return response;
+        }
+    };
+
+    guard_body(metrics, payload, resp)
+}
+
+/// Ends a streamed body at the first panic while producing it, reporting the
+/// panic against `payload`. Bodies with a known size are already complete and
+/// returned as they are.
+pub(crate) fn guard_body(metrics: Arc<Metrics>, payload: Bytes, resp: Response) -> Response {
+    if resp.body().size_hint().exact().is_some() {
+        return resp;
+    }
+    let (parts, body) = resp.into_parts();
+    let stream = AssertUnwindSafe(BodyStream::new(body)).catch_unwind().map(move |item| match item {
+        Ok(frame) => frame,
+        Err(panic) => {
+            report(&metrics, &payload, panic.as_ref());
+            Err(axum::Error::new("Internal error while streaming the response"))
+        }
+    });
+    Response::from_parts(parts, Body::new(StreamBody::new(stream)))
+}
+
+pub(crate) fn report(metrics: &Metrics, payload: &Bytes, panic: &(dyn Any + Send)) {
+    metrics.record_panic();
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string panic payload>");
+    tracing::error!("Request handler panicked: {}; payload: {}", message, redact(payload));
+}
+
+// Keeps the JSON structure of the payload but replaces string content with its
+// length, so a malformed request can be reproduced without logging user data.
+fn redact(payload: &[u8]) -> String {
+    match serde_json::from_slice::<Value>(payload) {
+        Ok(mut value) => {
+            mask(&mut value);
+            value.to_string()
+        }
+        Err(_) => format!("<{} bytes, not JSON>", payload.len()),
+    }
+}
+
+fn mask(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = format!("<{} chars>", s.chars().count()),
+        Value::Array(items) => items.iter_mut().for_each(mask),
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                if !(KEPT_KEYS.contains(&key.as_str()) && item.is_string()) {
+                    mask(item);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_keeps_shape_only() {
+        let payload = br#"{"model":"gpt-4o","max_tokens":5,"messages":[{"role":"user","content":"secret"}]}"#;
+        assert_eq!(
+            redact(payload),
+            r#"{"max_tokens":5,"messages":[{"content":"<6 chars>","role":"user"}],"model":"gpt-4o"}"#
+        );
+        assert_eq!(redact(b"not json"), "<8 bytes, not JSON>");
+    }
+
+    #[tokio::test]
+    async fn test_panics_answer_in_the_client_format() {
+        async fn boom() -> Response {
+            panic!("boom")
+        }
+        let metrics = Arc::new(Metrics::default());
+        let app = axum::Router::new()
+            .route("/v1/messages", axum::routing::post(boom))
+            .route("/admin/reload", axum::routing::post(boom))
+            .layer(axum::middleware::from_fn_with_state((metrics.clone(), 64), catch_panics));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let client = reqwest::Client::new();
+        let post = |path: &str, body: &'static str| client.post(format!("http://{}{}", addr, path)).body(body).send();
+
+        let response = post("/v1/messages", "{}").await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!((body["type"].as_str(), body["error"]["type"].as_str()), (Some("error"), Some("api_error")));
+
+        let body: Value = post("/admin/reload", "").await.unwrap().json().await.unwrap();
+        assert_eq!(body["error"]["code"], "internal_error");
+
+        // The configured cap bounds the buffered body
+        let response = post("/v1/messages", "a request body longer than sixty-four bytes, which the cap refuses").await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::auth::AppState;
+use crate::config::{Priority, VirtualKey};
+use crate::error::RouterError;
+use axum::http::{HeaderMap, StatusCode};
+
+/// Client header asking for `low`, `normal` or `high` priority.
+pub const HEADER: &str = "x-llm-router-priority";
+
+/// The request's priority. Virtual keys may ask for up to their `max_priority`,
+/// the router token and tenant keys up to `priority.max_priority` of the config
+/// they use.
+pub async fn for_request(headers: &HeaderMap, virtual_key: Option<&VirtualKey>, config: &AppState) -> Result<Priority, RouterError> {
+    let max = match virtual_key {
+        Some(key) => key.max_priority,
+        None => config.model_manager.read().await.get_config().router_settings.priority.max_priority,
+    };
+    from_headers(headers, max)
+}
+
+fn from_headers(headers: &HeaderMap, max: Priority) -> Result<Priority, RouterError> {
+    let Some(value) = headers.get(HEADER) else { return Ok(Priority::default()) };
+    let priority = match value.to_str().unwrap_or_default().trim().to_ascii_lowercase().as_str() {
+        "low" => Priority::Low,
+        "normal" => Priority::Normal,
+        "high" => Priority::High,
+        other => {
+            return Err(RouterError::client(
+                StatusCode::BAD_REQUEST,
+                "invalid_priority",
+                format!("{} '{}' is not one of low, normal, high", HEADER, other),
+            ));
+        }
+    };
+    if priority > max {
+        return Err(RouterError::client(
+            StatusCode::FORBIDDEN,
+            "priority_not_allowed",
+            format!("This key may not use {} above {:?}", HEADER, max).to_lowercase(),
+        ));
+    }
+    Ok(priority)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_priority_is_limited_by_key() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(from_headers(&headers, Priority::Low).unwrap(), Priority::Normal);
+        headers.insert(HEADER, HeaderValue::from_static("High"));
+        assert_eq!(from_headers(&headers, Priority::High).unwrap(), Priority::High);
+        assert_eq!(from_headers(&headers, Priority::Normal).unwrap_err().status(), StatusCode::FORBIDDEN);
+        headers.insert(HEADER, HeaderValue::from_static("urgent"));
+        assert_eq!(from_headers(&headers, Priority::High).unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,289 @@
+//! A streaming request that waits in a queue (a full bulkhead or the retry
+//! queue) would show the client nothing until the model starts. With
+//! `priority.queue_events_ms` set, such a request is answered at once with an
+//! SSE stream that carries its queue position and ETA as comments, followed by
+//! the model's own stream. The routing headers of the model's response arrive
+//! too late for the status line and follow the stream as trailers.
+
+use crate::config::ApiType;
+use crate::error::RouterError;
+use crate::{loop_guard, offload};
+use crate::router::error_body_in_client_format;
+use crate::size_stats::ServedModel;
+use axum::body::Body;
+use axum::http::{Extensions, HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use futures::stream;
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody};
+use serde_json::{Value, json};
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::{JoinError, JoinHandle};
+use tokio::time::{Instant, Interval};
+use tracing::warn;
+
+/// Where a waiting request stands.
+#[derive(Debug, Clone)]
+pub struct QueueStatus {
+    // Model the request waits for
+    pub model: String,
+    // Requests ahead of it; `None` when the queue has no order
+    pub position: Option<usize>,
+    // When it expects to leave the queue, if that can be told
+    pub eta: Option<Instant>,
+}
+
+/// Set to the request's status while it waits and back to `None` once it
+/// leaves the queue.
+pub type QueueReporter = watch::Sender<Option<QueueStatus>>;
+
+/// Extensions of the response `route` produced behind an early answer, set
+/// once it arrives. Layers that settle usage when the stream ends read its
+/// `Pricing` and `TokenUsage` from here.
+#[derive(Debug, Clone, Default)]
+pub struct Routed(Arc<OnceLock<Extensions>>);
+
+impl Routed {
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.0.get()?.get::<T>().cloned()
+    }
+}
+
+/// Run `route`. A request that never queues gets its response as usual; one
+/// that does is answered with queue status comments every `interval` until
+/// `route` finishes, then with the stream it produced. An error at that point
+/// becomes an error event in the client's format, since the status line has
+/// already gone out. Dropping the answer stops `route`.
+pub async fn answer_while_queued<F, Fut>(api_type: ApiType, interval: Duration, route: F) -> Response
+where
+    F: FnOnce(QueueReporter) -> Fut,
+    Fut: Future<Output = Response> + Send + 'static,
+{
+    let (reporter, mut updates) = watch::channel(None);
+    let mut routed = tokio::spawn(loop_guard::inherit(offload::inherit(route(reporter))));
+    let model = loop {
+        tokio::select! {
+            response = &mut routed => return joined(response),
+            changed = updates.changed() => {
+                if changed.is_err() {
+                    return joined(routed.await);
+                }
+                if let Some(status) = updates.borrow_and_update().as_ref() {
+                    break status.model.clone();
+                }
+            }
+        }
+    };
+
+    let routed_parts = Routed::default();
+    let state = Waiting { api_type, routed, routed_parts: routed_parts.clone(), updates, ticker: tokio::time::interval(interval) };
+    let body = stream::unfold(State::Waiting(Box::new(state)), next_frame);
+    let mut response = Body::new(StreamBody::new(body)).into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    headers.insert("x-llm-router-queued", HeaderValue::from_static("true"));
+    response.extensions_mut().insert(ServedModel(model));
+    response.extensions_mut().insert(routed_parts);
+    response
+}
+
+struct Waiting {
+    api_type: ApiType,
+    routed: JoinHandle<Response>,
+    routed_parts: Routed,
+    updates: watch::Receiver<Option<QueueStatus>>,
+    ticker: Interval,
+}
+
+// A client that goes away while the request still waits takes its place in
+// the queue with it
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.routed.abort();
+    }
+}
+
+enum State {
+    Waiting(Box<Waiting>),
+    Forwarding(Body, HeaderMap),
+    Done,
+}
+
+type Chunk = Result<Frame<Bytes>, axum::Error>;
+
+async fn next_frame(state: State) -> Option<(Chunk, State)> {
+    match state {
+        State::Waiting(mut waiting) => {
+            tokio::select! {
+                response = &mut waiting.routed => {
+                    let (parts, body) = joined(response).into_parts();
+                    let _ = waiting.routed_parts.0.set(parts.extensions);
+                    if parts.status.is_success() {
+                        return Some((Ok(Frame::data(Bytes::new())), State::Forwarding(body, routing_headers(&parts.headers))));
+                    }
+                    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+                    Some((Ok(Frame::data(error_event(&waiting.api_type, parts.status, &body))), State::Done))
+                }
+                _ = waiting.ticker.tick() => {
+                    let comment = waiting.updates.borrow().as_ref().map(status_comment).unwrap_or_default();
+                    Some((Ok(Frame::data(comment)), State::Waiting(waiting)))
+                }
+            }
+        }
+        State::Forwarding(mut body, mut trailers) => loop {
+            match body.frame().await {
+                // The stream's own trailers, such as its cost, join the routing headers
+                Some(Ok(frame)) => match frame.into_trailers() {
+                    Ok(own) => trailers.extend(own),
+                    Err(frame) => return Some((Ok(frame), State::Forwarding(body, trailers))),
+                },
+                Some(Err(e)) => return Some((Err(e), State::Forwarding(body, trailers))),
+                None if trailers.is_empty() => return None,
+                None => return Some((Ok(Frame::trailers(trailers)), State::Done)),
+            }
+        },
+        State::Done => None,
+    }
+}
+
+// The router's own headers and the upstream's, including the cost
+fn routing_headers(headers: &HeaderMap) -> HeaderMap {
+    headers
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-llm-router-") || name.as_str().starts_with("x-upstream-"))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+fn joined(response: Result<Response, JoinError>) -> Response {
+    response.unwrap_or_else(|e| {
+        warn!("Queued request failed: {}", e);
+        RouterError::Internal("request handler failed".to_string()).into_response()
+    })
+}
+
+// `: queue {"model":...,"position":2,"eta_ms":1500}`; SSE clients skip comments
+fn status_comment(status: &QueueStatus) -> Bytes {
+    let eta_ms = status.eta.map(|eta| eta.saturating_duration_since(Instant::now()).as_millis() as u64);
+    let status = json!({"model": status.model, "position": status.position, "eta_ms": eta_ms});
+    Bytes::from(format!(": queue {}\n\n", status))
+}
+
+// The failed response as the stream's last event, with OpenAI's [DONE] after
+// it; an Anthropic error event and a Gemini error object end their streams
+fn error_event(api_type: &ApiType, status: StatusCode, body: &[u8]) -> Bytes {
+    let error = serde_json::from_slice::<Value>(body).ok();
+    let error = error.as_ref().map(|e| e.get("error").unwrap_or(e));
+    let message = match error.and_then(|e| e.get("message")).and_then(Value::as_str) {
+        Some(message) => message.to_string(),
+        None => String::from_utf8_lossy(body).into_owned(),
+    };
+    let data = error_body_in_client_format(api_type, status, "upstream_error", message);
+    Bytes::from(match api_type {
+        ApiType::OpenAI => format!("data: {}\n\ndata: [DONE]\n\n", data),
+        ApiType::Anthropic => format!("event: error\ndata: {}\n\n", data),
+        ApiType::Gemini => format!("data: {}\n\n", data),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(reporter: &QueueReporter, position: usize) {
+        let status = QueueStatus { model: "m".to_string(), position: Some(position), eta: Some(Instant::now() + Duration::from_secs(1)) };
+        reporter.send_replace(Some(status));
+    }
+
+    #[tokio::test]
+    async fn test_unqueued_requests_are_answered_as_usual() {
+        let response = answer_while_queued(ApiType::OpenAI, Duration::from_millis(10), |_| async { StatusCode::BAD_GATEWAY.into_response() }).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert!(response.headers().get("x-llm-router-queued").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_queued_requests_keep_the_hop_count() {
+        // The route runs on its own task, which must still see the incoming count
+        let route = |_| async { loop_guard::current_hops().to_string().into_response() };
+        let response = loop_guard::with_hops(3, answer_while_queued(ApiType::OpenAI, Duration::from_millis(10), route)).await;
+        assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), "3");
+    }
+
+    #[tokio::test]
+    async fn test_queued_requests_see_status_then_the_stream() {
+        let response = answer_while_queued(ApiType::OpenAI, Duration::from_millis(10), |reporter| async move {
+            queued(&reporter, 1);
+            tokio::time::sleep(Duration::from_millis(15)).await;
+            queued(&reporter, 0);
+            tokio::time::sleep(Duration::from_millis(15)).await;
+            reporter.send_replace(None);
+            let mut response = "data: {\"delta\":\"hi\"}\n\n".into_response();
+            response.headers_mut().insert("x-llm-router-model", HeaderValue::from_static("m"));
+            response.headers_mut().insert("x-upstream-x-ratelimit-remaining", HeaderValue::from_static("9"));
+            response.extensions_mut().insert(7u32);
+            response
+        })
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let routed = response.extensions().get::<Routed>().cloned().unwrap();
+        let body = response.into_body().collect().await.unwrap();
+        assert_eq!(routed.get::<u32>(), Some(7));
+        let trailers = body.trailers().cloned().unwrap();
+        assert_eq!(trailers["x-llm-router-model"], "m");
+        assert_eq!(trailers["x-upstream-x-ratelimit-remaining"], "9");
+        assert!(trailers.get(header::CONTENT_TYPE).is_none());
+        let body = String::from_utf8(body.to_bytes().to_vec()).unwrap();
+        assert!(body.starts_with(": queue {"));
+        assert!(body.contains("\"position\":1") && body.contains("\"position\":0"));
+        assert!(body.ends_with("data: {\"delta\":\"hi\"}\n\n"));
+    }
+
+    #[tokio::test]
+    async fn test_errors_after_queueing_become_error_events() {
+        let overloaded = |api_type: ApiType| {
+            answer_while_queued(api_type, Duration::from_millis(10), |reporter| async move {
+                queued(&reporter, 0);
+                tokio::time::sleep(Duration::from_millis(15)).await;
+                RouterError::Overloaded("model is busy".to_string()).into_response()
+            })
+        };
+        let body = |response: Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        let openai = body(overloaded(ApiType::OpenAI).await).await;
+        assert!(openai.contains("data: {\"error\"") && openai.contains("model is busy"));
+        assert!(openai.ends_with("data: [DONE]\n\n"));
+
+        let anthropic = body(overloaded(ApiType::Anthropic).await).await;
+        assert!(anthropic.ends_with("\n\n") && anthropic.contains("event: error\ndata: {"));
+        assert!(anthropic.contains("\"type\":\"error\"") && anthropic.contains("model is busy"));
+
+        let gemini = body(overloaded(ApiType::Gemini).await).await;
+        assert!(gemini.contains("data: {\"error\"") && gemini.contains("\"code\":503"));
+        assert!(!gemini.contains("[DONE]"));
+    }
+
+    #[tokio::test]
+    async fn test_dropping_the_answer_stops_the_request() {
+        let (done, mut finished) = watch::channel(false);
+        let response = answer_while_queued(ApiType::OpenAI, Duration::from_millis(10), |reporter| async move {
+            queued(&reporter, 0);
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            done.send_replace(true);
+            StatusCode::OK.into_response()
+        })
+        .await;
+        drop(response);
+        // The sender goes with the aborted task, without ever sending true
+        assert!(finished.changed().await.is_err());
+        assert!(!*finished.borrow());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::config::{ApiType, ReasoningModel};
+use serde_json::{Value, json};
+use tracing::debug;
+
+/// Adjust an upstream request body (already in the upstream's format) for a
+/// reasoning model: drop the sampling fields it rejects, send OpenAI
+/// `max_tokens` as `max_completion_tokens`, and fill in `reasoning_effort`.
+/// Gemini sampling fields live in `generationConfig` under camelCase names.
+pub fn apply(body: &mut Value, api_type: &ApiType, shaping: &ReasoningModel) {
+    let Some(obj) = body.as_object_mut() else { return };
+    match api_type {
+        ApiType::OpenAI | ApiType::Anthropic => {
+            for field in &shaping.strip {
+                if obj.remove(field).is_some() {
+                    debug!("Removed {} for a reasoning model", field);
+                }
+            }
+        }
+        ApiType::Gemini => {
+            if let Some(config) = obj.get_mut("generationConfig").and_then(Value::as_object_mut) {
+                for field in &shaping.strip {
+                    config.remove(&camel_case(field));
+                }
+            }
+        }
+    }
+    if *api_type != ApiType::OpenAI {
+        return;
+    }
+    if shaping.max_completion_tokens
+        && let Some(max_tokens) = obj.remove("max_tokens")
+        && !max_tokens.is_null()
+    {
+        // A client that sent both already chose max_completion_tokens
+        obj.entry("max_completion_tokens").or_insert(max_tokens);
+    }
+    if let Some(effort) = &shaping.default_effort
+        && obj.get("reasoning_effort").is_none_or(Value::is_null)
+    {
+        obj.insert("reasoning_effort".to_string(), json!(effort));
+    }
+}
+
+fn camel_case(field: &str) -> String {
+    let mut parts = field.split('_');
+    let mut out = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            out.extend(first.to_uppercase());
+            out.push_str(chars.as_str());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shaping(yaml: &str) -> ReasoningModel {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_reasoning_requests_are_reshaped() {
+        let defaults = shaping("{}");
+        let mut body = json!({"model": "o3", "temperature": 0.2, "top_p": 0.9, "max_tokens": 512, "messages": []});
+        apply(&mut body, &ApiType::OpenAI, &defaults);
+        assert_eq!(body, json!({"model": "o3", "max_completion_tokens": 512, "messages": []}));
+
+        let mut body = json!({"max_tokens": 512, "max_completion_tokens": 1024, "reasoning_effort": "high"});
+        apply(&mut body, &ApiType::OpenAI, &shaping("{strip: [], default_effort: low}"));
+        assert_eq!(body, json!({"max_completion_tokens": 1024, "reasoning_effort": "high"}));
+
+        let mut body = json!({"max_tokens": null});
+        apply(&mut body, &ApiType::OpenAI, &shaping("{max_completion_tokens: false, default_effort: medium}"));
+        assert_eq!(body, json!({"max_tokens": null, "reasoning_effort": "medium"}));
+
+        let mut body = json!({"generationConfig": {"temperature": 1.0, "topP": 0.5, "maxOutputTokens": 64}});
+        apply(&mut body, &ApiType::Gemini, &shaping("{default_effort: high}"));
+        assert_eq!(body, json!({"generationConfig": {"maxOutputTokens": 64}}));
+    }
+}
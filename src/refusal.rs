@@ -0,0 +1,77 @@
+use crate::config::{ApiType, RefusalFallbackSettings};
+use serde_json::Value;
+
+/// Why a converted response body (in the client-facing format of `api_type`)
+/// counts as a refusal under `settings`, or `None` when it does not. OpenAI
+/// `message.refusal` always counts; otherwise a configured finish reason or a
+/// pattern matching the response text does.
+pub fn detect(settings: &RefusalFallbackSettings, api_type: &ApiType, body: &Value) -> Option<String> {
+    let mut reasons = Vec::new();
+    let mut texts = Vec::new();
+    match api_type {
+        ApiType::OpenAI => {
+            for choice in body["choices"].as_array().into_iter().flatten() {
+                if let Some(refusal) = choice["message"]["refusal"].as_str() {
+                    return Some(format!("refusal message {:?}", refusal));
+                }
+                reasons.extend(choice["finish_reason"].as_str());
+                texts.extend(choice["message"]["content"].as_str());
+            }
+        }
+        ApiType::Anthropic => {
+            reasons.extend(body["stop_reason"].as_str());
+            texts.extend(body["content"].as_array().into_iter().flatten().filter_map(|block| block["text"].as_str()));
+        }
+        ApiType::Gemini => {
+            reasons.extend(body["promptFeedback"]["blockReason"].as_str());
+            for candidate in body["candidates"].as_array().into_iter().flatten() {
+                reasons.extend(candidate["finishReason"].as_str());
+                texts.extend(candidate["content"]["parts"].as_array().into_iter().flatten().filter_map(|part| part["text"].as_str()));
+            }
+        }
+    }
+
+    if let Some(reason) = reasons.iter().find(|r| settings.finish_reasons.iter().any(|f| f.eq_ignore_ascii_case(r))) {
+        return Some(format!("finish reason {}", reason));
+    }
+    for pattern in &settings.patterns {
+        if texts.iter().any(|text| pattern.is_match(text)) {
+            return Some(format!("text matched {:?}", pattern.as_str()));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_refusals() {
+        let settings = RefusalFallbackSettings { enabled: true, ..Default::default() };
+
+        let filtered = json!({"choices": [{"message": {"content": ""}, "finish_reason": "content_filter"}]});
+        assert_eq!(detect(&settings, &ApiType::OpenAI, &filtered).unwrap(), "finish reason content_filter");
+        let refused = json!({"choices": [{"message": {"content": null, "refusal": "No."}, "finish_reason": "stop"}]});
+        assert!(detect(&settings, &ApiType::OpenAI, &refused).is_some());
+        let answered = json!({"choices": [{"message": {"content": "Sure, here it is", "refusal": null}, "finish_reason": "stop"}]});
+        assert!(detect(&settings, &ApiType::OpenAI, &answered).is_none());
+
+        let apology = json!({"content": [{"type": "text", "text": "I'm sorry, but I can't help with that."}], "stop_reason": "end_turn"});
+        assert!(detect(&settings, &ApiType::Anthropic, &apology).unwrap().starts_with("text matched"));
+        let declined = json!({"content": [], "stop_reason": "refusal"});
+        assert!(detect(&settings, &ApiType::Anthropic, &declined).is_some());
+
+        let blocked = json!({"candidates": [{"content": {"parts": []}, "finishReason": "SAFETY"}]});
+        assert_eq!(detect(&settings, &ApiType::Gemini, &blocked).unwrap(), "finish reason SAFETY");
+        let quoted = json!({"candidates": [{"content": {"parts": [{"text": "He said: I can't help with that"}]}, "finishReason": "STOP"}]});
+        assert!(detect(&settings, &ApiType::Gemini, &quoted).is_none());
+
+        // Configured patterns are compiled case-insensitively at load, and a bad one fails it
+        let settings: RefusalFallbackSettings = serde_yaml::from_str("patterns: ['^as an ai']").unwrap();
+        let disclaimer = json!({"content": [{"type": "text", "text": "As an AI, I won't."}], "stop_reason": "end_turn"});
+        assert!(detect(&settings, &ApiType::Anthropic, &disclaimer).is_some());
+        assert!(serde_yaml::from_str::<RefusalFallbackSettings>("patterns: ['(unclosed']").is_err());
+    }
+}
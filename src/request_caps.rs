@@ -0,0 +1,104 @@
+//! Per-model caps on the size of a conversation, for small (often local)
+//! models that fail slowly or crash on enormous inputs. Requests are measured
+//! as the model would receive them, after conversion to its API type.
+
+use crate::config::{ApiType, LLMParams};
+use crate::converters::request_wrapper::RequestWrapper;
+
+/// A cap `request` exceeds for a model.
+#[derive(Debug, PartialEq)]
+pub enum Exceeded {
+    Messages { count: usize, max: usize },
+    BodyBytes { bytes: u64, max: u64 },
+}
+
+impl Exceeded {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Exceeded::Messages { .. } => "too_many_messages",
+            Exceeded::BodyBytes { .. } => "body_too_large",
+        }
+    }
+
+    pub fn message(&self, model: &str) -> String {
+        match self {
+            Exceeded::Messages { count, max } => {
+                format!("Request has {} messages, over the limit of {} for model '{}'", count, max, model)
+            }
+            Exceeded::BodyBytes { bytes, max } => {
+                format!("Request body is {} bytes, over the limit of {} for model '{}'", bytes, max, model)
+            }
+        }
+    }
+}
+
+/// Check `request` against the `max_messages` and `max_body_bytes` of `params`.
+pub fn check(request: &RequestWrapper, params: &LLMParams) -> Result<(), Exceeded> {
+    if params.max_messages.is_none() && params.max_body_bytes.is_none() {
+        return Ok(());
+    }
+    // Conversion is only paid for by models with a cap
+    let (count, body) = match params.api_type {
+        ApiType::OpenAI => {
+            let req = request.get_openai();
+            (req.messages.len(), serde_json::to_vec(&req))
+        }
+        ApiType::Anthropic => {
+            let req = request.get_anthropic();
+            (req.messages.as_ref().map_or(0, Vec::len), serde_json::to_vec(&req))
+        }
+        ApiType::Gemini => {
+            let req = request.get_gemini();
+            (req.contents.len(), serde_json::to_vec(&req))
+        }
+    };
+    if let Some(max) = params.max_messages
+        && count > max
+    {
+        return Err(Exceeded::Messages { count, max });
+    }
+    let bytes = body.map_or(0, |body| body.len() as u64);
+    if let Some(max) = params.max_body_bytes
+        && bytes > max
+    {
+        return Err(Exceeded::BodyBytes { bytes, max });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModelConfig;
+    use serde_json::json;
+
+    fn params(api_type: &str, caps: &str) -> LLMParams {
+        let model: ModelConfig = serde_yaml::from_str(&format!(
+            "model_name: small\nllm_params: {{api_type: {}, model: m, api_base: 'http://localhost', api_key: k, {}}}",
+            api_type, caps
+        ))
+        .unwrap();
+        model.llm_params
+    }
+
+    #[test]
+    fn test_caps_count_what_the_model_receives() {
+        let request = RequestWrapper::OpenAI(
+            serde_json::from_value(json!({"model": "small", "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": "hello"},
+                {"role": "user", "content": "x".repeat(2000)},
+            ]}))
+            .unwrap(),
+        );
+        assert!(check(&request, &params("openai", "")).is_ok());
+        assert_eq!(check(&request, &params("openai", "max_messages: 3")), Err(Exceeded::Messages { count: 4, max: 3 }));
+        // Anthropic takes the system prompt outside of messages
+        assert!(check(&request, &params("anthropic", "max_messages: 3")).is_ok());
+        let Err(Exceeded::BodyBytes { bytes, max: 1000 }) = check(&request, &params("gemini", "max_body_bytes: 1000")) else {
+            panic!("the body is over 1000 bytes");
+        };
+        assert!(bytes > 2000);
+    }
+}
@@ -0,0 +1,47 @@
+use crate::config::RequestSigning;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Compute the signing headers for an upstream request body.
+/// For `hmac_sha256` the signed string is `"{timestamp}\n{hex(sha256(body))}"`.
+pub fn signing_headers(signing: &RequestSigning, body: &[u8], timestamp: u64) -> Vec<(String, String)> {
+    match signing {
+        RequestSigning::HmacSha256 { secret, timestamp_header, digest_header, signature_header } => {
+            let digest = hex::encode(Sha256::digest(body));
+            let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+            mac.update(format!("{}\n{}", timestamp, digest).as_bytes());
+            let signature = hex::encode(mac.finalize().into_bytes());
+            vec![
+                (timestamp_header.clone(), timestamp.to_string()),
+                (digest_header.clone(), digest),
+                (signature_header.clone(), signature),
+            ]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256_signing_headers() {
+        let signing = RequestSigning::HmacSha256 {
+            secret: "key".to_string(),
+            timestamp_header: "X-Timestamp".to_string(),
+            digest_header: "X-Content-SHA256".to_string(),
+            signature_header: "X-Signature".to_string(),
+        };
+        let headers = signing_headers(&signing, b"{}", 1700000000);
+        assert_eq!(headers[0], ("X-Timestamp".to_string(), "1700000000".to_string()));
+        // sha256("{}")
+        assert_eq!(headers[1].1, "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a");
+        assert_eq!(headers[2].0, "X-Signature");
+        assert_eq!(headers[2].1.len(), 64);
+        // Same input, same signature; different body, different signature
+        assert_eq!(signing_headers(&signing, b"{}", 1700000000)[2], headers[2]);
+        assert_ne!(signing_headers(&signing, b"[]", 1700000000)[2], headers[2]);
+    }
+}
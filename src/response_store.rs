@@ -0,0 +1,225 @@
+use crate::auth::{AppState, TenantId};
+use crate::config::{ResponseStoreSettings, VirtualKey};
+use crate::converters::response_handler::SseFrame;
+use crate::error::RouterError;
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response, sse::Event, sse::Sse},
+};
+use futures::{Stream, StreamExt, stream};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::debug;
+
+/// Buffers emitted stream frames per response id so clients can resume
+/// after a disconnect. Ids come from clients, so each stream belongs to the
+/// virtual key that started it (None for the router token and tenant keys) and only that key
+/// can resume it. Entries expire `ttl` after their stream finished, or
+/// `pending_ttl` after it started if it never does.
+#[derive(Debug)]
+pub struct ResponseStore {
+    ttl: Duration,
+    pending_ttl: Duration,
+    max_streams: usize,
+    streams: Mutex<HashMap<StreamKey, Arc<StoredStream>>>,
+}
+
+// Owning virtual key and response key
+type StreamKey = (Option<String>, String);
+
+impl ResponseStore {
+    pub fn new(settings: &ResponseStoreSettings) -> Self {
+        Self {
+            ttl: Duration::from_secs(settings.ttl_secs),
+            pending_ttl: Duration::from_secs(settings.pending_ttl_secs),
+            max_streams: settings.max_streams.max(1),
+            streams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn create(&self, id: &str, owner: Option<&str>) -> Arc<StoredStream> {
+        let stored = Arc::new(StoredStream::new());
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|_, s| !s.expired(self.ttl, self.pending_ttl));
+        let key = (owner.map(str::to_string), id.to_string());
+        if !streams.contains_key(&key) && streams.len() >= self.max_streams {
+            // Finished streams go first, then the oldest
+            let oldest = streams
+                .iter()
+                .min_by_key(|(_, s)| (s.finished_at.lock().unwrap().is_none(), s.created_at))
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                streams.remove(&oldest);
+            }
+        }
+        streams.insert(key, stored.clone());
+        stored
+    }
+
+    pub fn get(&self, id: &str, owner: Option<&str>) -> Option<Arc<StoredStream>> {
+        let streams = self.streams.lock().unwrap();
+        streams
+            .get(&(owner.map(str::to_string), id.to_string()))
+            .filter(|s| !s.expired(self.ttl, self.pending_ttl))
+            .cloned()
+    }
+}
+
+#[derive(Debug)]
+pub struct StoredStream {
+    frames: Mutex<Vec<SseFrame>>,
+    // (number of frames, finished)
+    state: watch::Sender<(usize, bool)>,
+    created_at: Instant,
+    finished_at: Mutex<Option<Instant>>,
+}
+
+impl StoredStream {
+    fn new() -> Self {
+        let (state, _) = watch::channel((0, false));
+        Self { frames: Mutex::new(Vec::new()), state, created_at: Instant::now(), finished_at: Mutex::new(None) }
+    }
+
+    fn push(&self, frame: SseFrame) {
+        let len = {
+            let mut frames = self.frames.lock().unwrap();
+            frames.push(frame);
+            frames.len()
+        };
+        self.state.send_replace((len, false));
+    }
+
+    fn finish(&self) {
+        *self.finished_at.lock().unwrap() = Some(Instant::now());
+        self.state.send_modify(|s| s.1 = true);
+    }
+
+    fn expired(&self, ttl: Duration, pending_ttl: Duration) -> bool {
+        match *self.finished_at.lock().unwrap() {
+            Some(finished) => finished.elapsed() > ttl,
+            None => self.created_at.elapsed() > pending_ttl,
+        }
+    }
+
+    /// Drive `frames` to completion in the background, independent of any client.
+    pub fn record(self: &Arc<Self>, frames: impl Stream<Item = SseFrame> + Send + 'static) {
+        let stored = self.clone();
+        tokio::spawn(async move {
+            futures::pin_mut!(frames);
+            while let Some(frame) = frames.next().await {
+                stored.push(frame);
+            }
+            stored.finish();
+        });
+    }
+
+    /// Frames with sequence number greater than `starting_after` (all when None),
+    /// followed by live frames until the stream finishes.
+    pub fn subscribe(self: &Arc<Self>, starting_after: Option<usize>) -> impl Stream<Item = (usize, SseFrame)> + Send + 'static {
+        let next = starting_after.map_or(0, |seq| seq + 1);
+        let rx = self.state.subscribe();
+        stream::unfold((self.clone(), rx, next), |(stored, mut rx, next)| async move {
+            loop {
+                let (len, finished) = *rx.borrow_and_update();
+                if next < len {
+                    let frame = stored.frames.lock().unwrap()[next].clone();
+                    return Some(((next, frame), (stored, rx, next + 1)));
+                }
+                if finished || rx.changed().await.is_err() {
+                    return None;
+                }
+            }
+        })
+    }
+}
+
+pub fn frames_to_sse(frames: impl Stream<Item = (usize, SseFrame)> + Send + 'static) -> Response {
+    let events = frames.map(|(seq, (event_opt, payload))| -> Result<Event, Infallible> {
+        let mut ev = Event::default().id(seq.to_string()).data(payload);
+        if let Some(name) = event_opt {
+            ev = ev.event(name);
+        }
+        Ok(ev)
+    });
+    Sse::new(events)
+        .keep_alive(axum::response::sse::KeepAlive::new().interval(Duration::from_secs(1)))
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResumeQuery {
+    pub starting_after: Option<usize>,
+}
+
+// GET /v1/responses/{id}/events?starting_after=seq
+pub async fn resume_events(
+    State(app_state): State<AppState>,
+    tenant: Option<Extension<TenantId>>,
+    virtual_key: Option<Extension<VirtualKey>>,
+    Path(id): Path<String>,
+    Query(query): Query<ResumeQuery>,
+) -> Response {
+    let app_state = app_state.scoped(tenant.as_deref());
+    match app_state.response_store.get(&app_state.response_key(&id), virtual_key.as_ref().map(|vk| vk.key.as_str())) {
+        Some(stored) => {
+            debug!("Resuming stream {} after {:?}", id, query.starting_after);
+            frames_to_sse(stored.subscribe(query.starting_after))
+        }
+        None => RouterError::client(StatusCode::NOT_FOUND, "response_not_found", format!("No stored stream for response '{}'", id))
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resume_from_sequence() {
+        let store = ResponseStore::new(&ResponseStoreSettings::default());
+        let stored = store.create("resp-1", None);
+        let frames = (0..3).map(|i| (None, format!("frame{}", i))).collect::<Vec<_>>();
+        stored.record(stream::iter(frames));
+
+        let all: Vec<_> = store.get("resp-1", None).unwrap().subscribe(None).collect().await;
+        assert_eq!(all.len(), 3);
+        let rest: Vec<_> = stored.subscribe(Some(0)).collect().await;
+        assert_eq!(rest.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(rest[1].1.1, "frame2");
+        assert!(store.get("missing", None).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_streams_belong_to_their_key_and_are_bounded() {
+        let settings = ResponseStoreSettings { max_streams: 2, pending_ttl_secs: 0, ..Default::default() };
+        let store = ResponseStore::new(&settings);
+        let (alice, mallory) = ("alice", "mallory");
+
+        // The same client-chosen id under another key is a different stream
+        let mine = store.create("req-1", Some(alice));
+        mine.record(stream::iter(vec![(None, "secret".to_string())]));
+        assert_eq!(mine.subscribe(None).count().await, 1);
+        let theirs = store.create("req-1", Some(mallory));
+        assert!(!Arc::ptr_eq(&store.get("req-1", Some(alice)).unwrap(), &theirs));
+        assert!(store.get("req-1", None).is_none());
+
+        // Streams that never finish expire after pending_ttl_secs
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(store.get("req-1", Some(mallory)).is_none());
+
+        // Past max_streams the oldest finished stream makes room, even if newer
+        let store = ResponseStore::new(&ResponseStoreSettings { max_streams: 2, ..Default::default() });
+        let _pending = store.create("req-1", None);
+        let finished = store.create("req-2", None);
+        finished.record(stream::iter(Vec::new()));
+        assert_eq!(finished.subscribe(None).count().await, 0);
+        let _third = store.create("req-3", None);
+        assert!(store.get("req-1", None).is_some());
+        assert!(store.get("req-2", None).is_none());
+    }
+}
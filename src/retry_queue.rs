@@ -0,0 +1,93 @@
+//! Requests turned away with 429 by every candidate can wait for the earliest
+//! Retry-After to pass and be sent again. The number of requests waiting at
+//! once is bounded router-wide.
+
+use axum::http::HeaderMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+// Shortest wait, so a `retry-after: 0` cannot turn into a busy loop
+const MIN_WAIT: Duration = Duration::from_millis(250);
+
+/// How long the upstream asked to wait: `retry-after-ms`, else `retry-after`
+/// in seconds, capped at `max`. HTTP dates are not understood.
+pub fn retry_after(headers: &HeaderMap, max: Duration) -> Option<Duration> {
+    let value = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<f64>().ok().filter(|v| v.is_finite() && *v >= 0.0);
+    let secs = match value("retry-after-ms") {
+        Some(ms) => ms / 1000.0,
+        None => value("retry-after")?,
+    };
+    // Clamped before converting: from_secs_f64 panics on values past Duration::MAX
+    let wait = Duration::from_secs_f64(secs.min(max.as_secs_f64()));
+    Some(wait.max(MIN_WAIT))
+}
+
+#[derive(Debug, Default)]
+pub struct RetryQueue {
+    waiting: AtomicUsize,
+}
+
+/// A place in the queue, given back when dropped.
+#[derive(Debug)]
+pub struct QueueSlot<'a> {
+    queue: &'a RetryQueue,
+}
+
+impl RetryQueue {
+    /// A place in the queue, unless `limit` requests are already waiting.
+    pub fn enter(&self, limit: usize) -> Option<QueueSlot<'_>> {
+        self.waiting
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < limit).then_some(n + 1))
+            .ok()
+            .map(|_| QueueSlot { queue: self })
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.queue.waiting.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_retry_after_and_queue_bound() {
+        let max = Duration::from_secs(30);
+        let retry_after = |headers: &HeaderMap| retry_after(headers, max);
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after", HeaderValue::from_static("2"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+        headers.insert("retry-after-ms", HeaderValue::from_static("1500"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(1500)));
+        headers.insert("retry-after-ms", HeaderValue::from_static("0"));
+        assert_eq!(retry_after(&headers), Some(MIN_WAIT));
+        headers.remove("retry-after-ms");
+        headers.insert("retry-after", HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after", HeaderValue::from_static("1e20"));
+        assert_eq!(retry_after(&headers), Some(max));
+        headers.insert("retry-after", HeaderValue::from_static("NaN"));
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after", HeaderValue::from_static("-1"));
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after-ms", HeaderValue::from_static("inf"));
+        assert_eq!(retry_after(&headers), None);
+
+        let queue = RetryQueue::default();
+        let first = queue.enter(2).unwrap();
+        let _second = queue.enter(2).unwrap();
+        assert!(queue.enter(2).is_none());
+        drop(first);
+        assert_eq!(queue.waiting(), 1);
+        assert!(queue.enter(2).is_some());
+    }
+}
@@ -1,53 +1,143 @@
-use crate::auth::AppState;
-use crate::model_manager::Selection;
-use crate::config::ApiType;
-use crate::models::{ErrorResponse, ErrorDetail, ModelsResponse, ModelInfo};
+use crate::auth::{AppState, TenantId};
+use crate::model_manager::{ModelManager, Selection};
+use crate::config::{ApiType, ConversionPair, MaxTokensPolicy, ModelConfig, OutputValidationSettings, Pricing, Priority, RefusalFallbackSettings, RetryQueueSettings, StreamFailoverSettings, VirtualKey};
+use crate::error::RouterError;
+use crate::capabilities::{ENDPOINTS, endpoint_list};
+use crate::models::{ModelsResponse, ModelInfo};
 use crate::converters::{
     openai::{OpenAIRequest},
     anthropic::{AnthropicRequest},
-    gemini::GeminiRequest,
+    gemini::{GeminiRequest, gemini_request::is_cached_content_name},
     request_wrapper::RequestWrapper,
-    response_handler::{handle_non_streaming_response, handle_streaming_response},
+    response_handler::{DEFAULT_MAX_RESPONSE_BYTES, collect_streaming_response, handle_non_streaming_response, read_error_body, handle_streaming_response, sse_to_json_array, StreamOptions, UpstreamErrorHook},
+    response_wrapper::TokenUsage,
 };
 use axum::{
+    body::Bytes,
     extract::{State, Extension},
-    http::{StatusCode},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, header::CONTENT_TYPE},
     response::{IntoResponse},
     Json,
 };
-use axum::extract::Path;
+use futures::StreamExt;
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody};
 use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 use crate::request_id::RequestId;
+use crate::api_version;
+use crate::image_fetch;
+use crate::inline_images;
+use crate::latency_budget;
+use crate::llm_client::RewriteContext;
+use crate::offload;
+use crate::output_validation;
+use crate::priority;
+use crate::refusal;
+use crate::output_limit;
+use crate::request_caps;
+use crate::queue_events::{self, QueueReporter, QueueStatus};
+use crate::retry_queue;
+use crate::stream_fence::{self, FirstChunk};
+use crate::size_stats::ServedModel;
+use crate::session_caps::{PromptEstimate, StreamUsage};
+use crate::model_manager::estimate_tokens;
+use crate::stream_pacing;
+use crate::router_tools::RouterTools;
 
 #[axum_macros::debug_handler]
 pub async fn openai_chat(
     State(config): State<AppState>,
     Extension(request_id): Extension<RequestId>,
-    Json(openai_request): Json<OpenAIRequest>,
+    virtual_key: Option<Extension<VirtualKey>>,
+    tenant: Option<Extension<TenantId>>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> impl IntoResponse {
-    route_chat(ApiType::OpenAI, config, request_id, RequestWrapper::OpenAI(openai_request)).await
+    let mut openai_request: OpenAIRequest = match parse_body(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    openai_request.betas = match api_version::openai_betas(&headers) {
+        Ok(betas) => betas,
+        Err(e) => return e.into_response(),
+    };
+    let latency_budget = latency_budget::from_headers(&headers);
+    let config = config.scoped(tenant.as_deref());
+    let priority = match priority::for_request(&headers, virtual_key.as_deref(), &config).await {
+        Ok(priority) => priority,
+        Err(e) => return e.into_response(),
+    };
+    let mut response = route_chat(ApiType::OpenAI, config, request_id, virtual_key.map(|Extension(vk)| vk), latency_budget, priority, RequestWrapper::OpenAI(openai_request)).await;
+    api_version::apply(&mut response, api_version::OPENAI_VERSION);
+    response
 }
 
 #[axum_macros::debug_handler]
 pub async fn anthropic_chat(
     State(config): State<AppState>,
     Extension(request_id): Extension<RequestId>,
-    Json(anthropic_request): Json<AnthropicRequest>,
+    virtual_key: Option<Extension<VirtualKey>>,
+    tenant: Option<Extension<TenantId>>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> impl IntoResponse {
-    route_chat(ApiType::Anthropic, config, request_id, RequestWrapper::Anthropic(anthropic_request)).await
+    let mut anthropic_request: AnthropicRequest = match parse_body(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    // The header may repeat and each value may list several comma-separated betas
+    anthropic_request.betas = headers
+        .get_all("anthropic-beta")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|b| !b.is_empty())
+        .map(str::to_string)
+        .collect();
+    let version = match api_version::anthropic_version(&headers) {
+        Ok(version) => version,
+        Err(e) => return e.into_response(),
+    };
+    let latency_budget = latency_budget::from_headers(&headers);
+    let config = config.scoped(tenant.as_deref());
+    let priority = match priority::for_request(&headers, virtual_key.as_deref(), &config).await {
+        Ok(priority) => priority,
+        Err(e) => return e.into_response(),
+    };
+    let mut response = route_chat(ApiType::Anthropic, config, request_id, virtual_key.map(|Extension(vk)| vk), latency_budget, priority, RequestWrapper::Anthropic(anthropic_request)).await;
+    api_version::apply(&mut response, version);
+    response.headers_mut().insert("anthropic-version", HeaderValue::from_static(version));
+    response
 }
 
 // Gemini API entrypoint compatible with:
-// - POST /models/{model}:generateContent
-// - POST /models/{model}:streamGenerateContent?alt=sse
+// - POST /{v1,v1beta}/models/{model}:generateContent
+// - POST /{v1,v1beta}/models/{model}:streamGenerateContent?alt=sse
+// - POST /{v1,v1beta}/models/{model}:streamGenerateContent (a JSON array of chunks)
 #[axum_macros::debug_handler]
 pub async fn gemini_chat(
     State(config): State<AppState>,
     Extension(request_id): Extension<RequestId>,
-    Path(path_tail): Path<String>,
-    Json(mut body): Json<serde_json::Value>,
+    virtual_key: Option<Extension<VirtualKey>>,
+    tenant: Option<Extension<TenantId>>,
+    headers: HeaderMap,
+    uri: Uri,
+    body: Bytes,
 ) -> impl IntoResponse {
+    let mut body: serde_json::Value = match parse_body(&body) {
+        Ok(body) => body,
+        Err(e) => return e.into_response(),
+    };
+    let version = match api_version::gemini_version(uri.path()) {
+        Ok(version) => version,
+        Err(e) => return e.into_response(),
+    };
+    let path_tail = uri.path().split_once("/models/").map(|(_, tail)| tail).unwrap_or_default();
     // Parse model from tail like "models/{model}:generateContent" or "models/{model}:streamGenerateContent"
     // Our route is defined as /models/*tail, so tail includes "{model}:..."
     let (model, is_stream) = match path_tail.rsplit_once(":") {
@@ -57,10 +147,7 @@ pub async fn gemini_chat(
             (model, is_stream)
         }
         None => {
-            let error = json!({
-                "error": {"message": "invalid Gemini path", "type": "invalid_request"}
-            });
-            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+            return RouterError::client(StatusCode::BAD_REQUEST, "invalid_request", "invalid Gemini path").into_response();
         }
     };
 
@@ -68,113 +155,1137 @@ pub async fn gemini_chat(
     body["model"] = json!(model);
     body["stream"] = json!(is_stream);
 
-    let gemini_request: GeminiRequest = match serde_json::from_value(body) {
+    let gemini_request: GeminiRequest = match offload::run_for_request(|| serde_json::from_value(body)) {
         Ok(r) => r,
         Err(e) => {
-            let error = json!({"error": {"message": format!("invalid request: {}", e), "type": "invalid_request"}});
-            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+            return RouterError::client(StatusCode::BAD_REQUEST, "invalid_request", format!("invalid request: {}", e)).into_response();
         }
     };
 
-    route_chat(ApiType::Gemini, config, request_id, RequestWrapper::Gemini(gemini_request)).await.into_response()
+    let latency_budget = latency_budget::from_headers(&headers);
+    let config = config.scoped(tenant.as_deref());
+    let priority = match priority::for_request(&headers, virtual_key.as_deref(), &config).await {
+        Ok(priority) => priority,
+        Err(e) => return e.into_response(),
+    };
+    let mut response = route_chat(ApiType::Gemini, config, request_id, virtual_key.map(|Extension(vk)| vk), latency_budget, priority, RequestWrapper::Gemini(gemini_request)).await;
+    let json_array = is_stream && !uri.query().unwrap_or_default().split('&').any(|p| p == "alt=sse");
+    let event_stream = response.headers().get(CONTENT_TYPE).is_some_and(|v| v.as_bytes().starts_with(b"text/event-stream"));
+    if json_array && event_stream {
+        response = sse_to_json_array(response);
+    }
+    api_version::apply(&mut response, version);
+    response
 }
 
+// Parse a client request body; a large one is parsed off the async worker
+// and sizes the conversion that follows, chunked uploads included
+fn parse_body<T: serde::de::DeserializeOwned>(body: &Bytes) -> Result<T, RouterError> {
+    offload::set_request_bytes(body.len());
+    offload::run(body.len(), || serde_json::from_slice(body))
+        .map_err(|e| RouterError::client(StatusCode::BAD_REQUEST, "invalid_request", format!("invalid request: {}", e)))
+}
 
 pub async fn route_chat(
     api_type: ApiType,
     config: AppState,
     request_id: RequestId,
+    virtual_key: Option<VirtualKey>,
+    latency_budget: Option<Duration>,
+    priority: Priority,
     request_wrapper: RequestWrapper,
 ) -> axum::response::Response {
-    
+    // Only streaming clients can be told where they stand while they wait
+    let queue_events_ms = match request_wrapper.is_stream().unwrap_or(false) {
+        true => config.model_manager.read().await.get_config().router_settings.priority.queue_events_ms,
+        false => 0,
+    };
+    if queue_events_ms == 0 {
+        return route(api_type, config, request_id, virtual_key, latency_budget, priority, request_wrapper, None).await;
+    }
+    queue_events::answer_while_queued(api_type.clone(), Duration::from_millis(queue_events_ms), move |reporter| {
+        route(api_type, config, request_id, virtual_key, latency_budget, priority, request_wrapper, Some(reporter))
+    })
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn route(
+    api_type: ApiType,
+    config: AppState,
+    request_id: RequestId,
+    virtual_key: Option<VirtualKey>,
+    latency_budget: Option<Duration>,
+    priority: Priority,
+    mut request_wrapper: RequestWrapper,
+    queue_reporter: Option<QueueReporter>,
+) -> axum::response::Response {
+    if let Some(vk) = &virtual_key {
+        let model = request_wrapper.get_model();
+        if let Some(default_model) = &vk.default_model
+            && (model.is_empty() || model == "auto")
+        {
+            request_wrapper.set_model(default_model.clone());
+        }
+        if let Some(defaults) = vk.defaults.as_object()
+            && let Err(e) = request_wrapper.merge_defaults(defaults)
+        {
+            return RouterError::client(StatusCode::BAD_REQUEST, "invalid_request", format!("invalid request after applying key defaults: {}", e))
+                .into_response();
+        }
+    }
+
+    // Requests without a model go to the configured default; the client learns
+    // which model answered from the response body and routing headers
+    let mut defaulted = false;
+    if request_wrapper.get_model().is_empty() {
+        let model_manager = config.model_manager.read().await;
+        if let Some(default_model) = &model_manager.get_config().router_settings.default_model {
+            request_wrapper.set_model(default_model.clone());
+            defaulted = true;
+        }
+    }
+
     // Parse the request into the appropriate structure based on API type
     let model = request_wrapper.get_model();
-    
-    let stream = request_wrapper.is_stream().unwrap_or(false);
+
+    // Same answer as for unknown models, so keys cannot probe for models they may not use
+    if let Some(vk) = &virtual_key
+        && !vk.is_listed(model)
+    {
+        info!("Key {} may not use model '{}'", vk.label(), model);
+        return RouterError::client(StatusCode::NOT_FOUND, "model_not_found", format!("Model '{}' not found", model))
+            .into_response();
+    }
+    // Denied models are ones the key knows about, so the answer points elsewhere
+    if let Some(vk) = &virtual_key
+        && vk.denies(model)
+    {
+        info!("Key {} is denied model '{}'", vk.label(), model);
+        let alternatives = {
+            let model_manager = config.model_manager.read().await;
+            vk.alternatives(model, &model_manager.get_config().router_settings.model_groups)
+        };
+        let mut message = format!("Model '{}' is not available to this key", model);
+        if !alternatives.is_empty() {
+            message.push_str(&format!("; use one of: {}", alternatives.join(", ")));
+        }
+        return RouterError::client(StatusCode::FORBIDDEN, "model_denied", message).into_response();
+    }
     
     debug!("raw request: {}", serde_json::to_string(&request_wrapper).expect("Failed to serialize request"));
 
+    if let RequestWrapper::Gemini(req) = &request_wrapper
+        && let Some(cached) = req.cached_content.as_deref().filter(|name| !is_cached_content_name(name))
+    {
+        return error_in_client_format(
+            &api_type,
+            StatusCode::BAD_REQUEST,
+            "invalid_cached_content",
+            format!("cachedContent '{}' is not of the form cachedContents/{{id}} or projects/{{project}}/locations/{{location}}/cachedContents/{{id}}", cached),
+        );
+    }
+
     // Narrow read-lock scope to selection only
-    let selection: Selection = {
+    let (selection, routing_headers, stream_options, prompt_estimate): (Selection, bool, StreamOptions, PromptEstimate) = {
         let model_manager = config.model_manager.read().await;
+        let settings = &model_manager.get_config().router_settings;
+        let routing_headers = settings.routing_headers;
+        let stream_options = StreamOptions {
+            terminator: settings.sse_terminators.for_target(&api_type),
+            resumable: settings.response_store.enabled,
+            repair_tool_arguments: settings.repair_tool_arguments,
+            ..Default::default()
+        };
         let request_json = serde_json::to_value(&request_wrapper).unwrap_or_else(|_| json!({}));
-        match model_manager.resolve(model, &request_json) {
+        let prompt_estimate = PromptEstimate(estimate_tokens(&request_json));
+        let fits = |model: &ModelConfig| unsupported(&request_wrapper, model).is_none();
+        let resolved = model_manager
+            .resolve_within(model, &request_json, latency_budget, &fits)
+            // When no member can serve the request, pick one anyway to say why
+            .or_else(|| model_manager.resolve_within(model, &request_json, latency_budget, &|_| true));
+        match resolved {
             Some(sel) => {
                 debug!("Resolved model selection for: {} -> {:?}", model, sel);
-                sel
+                let mut stream_options = stream_options;
+                stream_options.tool_arguments = Some(
+                    sel.config.llm_params.tool_arguments.unwrap_or_else(|| settings.tool_arguments.for_target(&api_type)),
+                );
+                stream_options.max_delta_chars = sel.config.llm_params.stream_delta_chars.or(settings.stream_delta_chars);
+                (sel, routing_headers, stream_options, prompt_estimate)
             }
             None => {
                 info!("Model '{}' not found in configuration", model);
-                let error_response = ErrorResponse {
-                    error: ErrorDetail {
-                        message: format!("Model '{}' not found", model),
-                        r#type: "invalid_request_error".to_string(),
-                        code: Some("model_not_found".to_string()),
-                    },
-                };
-                return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
+                return RouterError::client(StatusCode::NOT_FOUND, "model_not_found", format!("Model '{}' not found", model))
+                    .into_response();
             }
         }
     };
 
-    // Track the start of the request
+    if let Some(unsupported) = unsupported(&request_wrapper, &selection.config) {
+        info!("Rejecting request: {}", unsupported.message);
+        return error_in_client_format(&api_type, unsupported.status, unsupported.code, unsupported.message);
+    }
+
+    // Group defaults fill what the client and its key left out; outer groups first
     {
         let model_manager = config.model_manager.read().await;
-        model_manager.start(&selection);
+        let groups = selection.via.iter().map(|(parent, _)| parent).chain(&selection.group);
+        for group in groups.filter_map(|name| model_manager.find_group(name)) {
+            request_wrapper.merge_generation_defaults(&group.defaults);
+        }
     }
 
+    // `provider` preferences are consumed by the router, not the upstream
+    request_wrapper.remove_extra_field("provider");
+
+    let refusal_fallback = {
+        let model_manager = config.model_manager.read().await;
+        let settings = &model_manager.get_config().router_settings.refusal_fallback;
+        settings.enabled.then(|| settings.clone())
+    };
+    let output_validation = {
+        let model_manager = config.model_manager.read().await;
+        let settings = &model_manager.get_config().router_settings.output_validation;
+        settings
+            .enabled
+            .then(|| output_validation::expectation(settings, &request_wrapper).map(|expect| (settings.clone(), expect)))
+            .flatten()
+    };
+
+    // Router-run tools (MCP, emulated web search) need the model's complete answer between turns
+    let mut router_tools = if request_wrapper.is_stream().unwrap_or(false) {
+        RouterTools::default()
+    } else {
+        RouterTools::for_request(&config, &mut request_wrapper, &selection.config, &request_id).await
+    };
+
+    let retry_queue = {
+        let model_manager = config.model_manager.read().await;
+        let settings = &model_manager.get_config().router_settings.retry_queue;
+        settings.enabled.then(|| settings.clone())
+    };
+
+    let stream_failover = {
+        let model_manager = config.model_manager.read().await;
+        let settings = &model_manager.get_config().router_settings.stream_failover;
+        (settings.enabled && request_wrapper.is_stream().unwrap_or(false)).then(|| settings.clone())
+    };
+
+    // Only complete answers are raced; a stream reaches the client as soon as it starts
+    let hedge_after = {
+        let model_manager = config.model_manager.read().await;
+        let ms = model_manager.get_config().router_settings.priority.hedge_after_ms;
+        (ms > 0 && priority == Priority::High && selection.group.is_some() && !request_wrapper.is_stream().unwrap_or(false))
+            .then(|| Duration::from_millis(ms))
+    };
+
+    let rewrite = RewriteContext {
+        key_name: virtual_key.as_ref().and_then(|vk| vk.name.clone()).unwrap_or_default(),
+        tenant: config.tenant.clone().unwrap_or_default(),
+        requested_model: request_wrapper.get_model().to_string(),
+        ..Default::default()
+    };
+    let store_owner = virtual_key.as_ref().map(|vk| vk.key.clone());
+    let mut meta = RoutingMeta { latency_budget, priority, fence_streams: stream_failover.is_some(), queue_reporter, rewrite, store_owner, ..Default::default() };
+    let mut selection = selection;
+    let started = Instant::now();
+    let mut response = match hedge_after {
+        Some(delay) => hedged_dispatch(api_type.clone(), &config, &request_id, &request_wrapper, &mut selection, stream_options.clone(), &mut meta, delay).await,
+        None => dispatch(api_type.clone(), &config, &request_id, &request_wrapper, &selection, stream_options.clone(), &mut meta).await,
+    };
+    if let Some(settings) = &retry_queue {
+        response = wait_out_rate_limits(api_type.clone(), &config, &request_id, &request_wrapper, &mut selection, stream_options.clone(), &mut meta, response, settings, started).await;
+    }
+    if let Some(settings) = &stream_failover {
+        response = fail_over_streams(api_type.clone(), &config, &request_id, &request_wrapper, &mut selection, stream_options.clone(), &mut meta, response, settings).await;
+    }
+    if !router_tools.definitions().is_empty() {
+        response = run_router_tools(api_type.clone(), &config, &request_id, &mut request_wrapper, &selection, stream_options.clone(), &mut meta, response, &mut router_tools).await;
+    }
+    // A streamed refusal has already reached the client, so only complete bodies are retried
+    if let Some(settings) = &refusal_fallback
+        && !request_wrapper.is_stream().unwrap_or(false)
+    {
+        response = retry_refusals(api_type.clone(), &config, &request_id, &request_wrapper, &mut selection, stream_options.clone(), &mut meta, response, settings).await;
+    }
+    if let Some((settings, expect)) = &output_validation
+        && !request_wrapper.is_stream().unwrap_or(false)
+    {
+        response = validate_output(api_type.clone(), &config, &request_id, &request_wrapper, &mut selection, stream_options, &mut meta, response, settings, expect).await;
+    }
+    // Betas only reach OpenAI upstreams, so only then were they honored
+    if selection.config.llm_params.api_type == ApiType::OpenAI
+        && !request_wrapper.openai_betas().is_empty()
+        && let Ok(v) = HeaderValue::from_str(&request_wrapper.openai_betas().join(","))
+    {
+        response.headers_mut().insert("openai-beta", v);
+    }
+    if routing_headers || defaulted {
+        apply_routing_headers(&mut response, &selection, &meta);
+    }
+    apply_upstream_headers(&mut response, &meta);
+    if let Some(Ok(v)) = meta.max_tokens_warning.as_deref().map(HeaderValue::from_str) {
+        response.headers_mut().insert("x-llm-router-warning", v);
+    }
+    if let Some(rate) = virtual_key.as_ref().and_then(|vk| vk.stream_tokens_per_sec)
+        && request_wrapper.is_stream().unwrap_or(false)
+    {
+        response = stream_pacing::pace(response, rate);
+    }
+    // After pacing, which passes on data only
+    if let Some(pricing) = &selection.config.llm_params.pricing {
+        response = apply_cost(response, &api_type, pricing);
+    }
+    response.extensions_mut().insert(ServedModel(selection.model_name.clone()));
+    response.extensions_mut().insert(prompt_estimate);
+    response
+}
+
+// Why a model cannot serve a request. Group members that cannot are passed
+// over when one is picked; a model named directly is refused with this
+struct Unsupported {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+fn unsupported(request: &RequestWrapper, model: &ModelConfig) -> Option<Unsupported> {
+    // Only OpenAI upstreams can speak; other formats would silently answer in text
+    if let RequestWrapper::OpenAI(req) = request
+        && req.modalities.iter().flatten().any(|m| m == "audio")
+        && model.llm_params.api_type != ApiType::OpenAI
+    {
+        let message = format!("Audio output needs an OpenAI model, but '{}' is not one", model.model_name);
+        return Some(Unsupported { status: StatusCode::BAD_REQUEST, code: "unsupported_modality", message });
+    }
+    // Explicit caches live at the provider, so only a Gemini upstream can use one
+    if let RequestWrapper::Gemini(req) = request
+        && req.cached_content.is_some()
+        && model.llm_params.api_type != ApiType::Gemini
+    {
+        let message = format!("cachedContent needs a Gemini model, but '{}' is not one", model.model_name);
+        return Some(Unsupported { status: StatusCode::BAD_REQUEST, code: "unsupported_cached_content", message });
+    }
+    // Betas the model does not list would be rejected or misread upstream
+    if let Some(allowed) = &model.llm_params.anthropic_betas
+        && let Some(beta) = request.anthropic_betas().iter().find(|b| !allowed.contains(b))
+    {
+        let message = format!("anthropic-beta '{}' is not supported by model '{}'", beta, model.model_name);
+        return Some(Unsupported { status: StatusCode::BAD_REQUEST, code: "unsupported_beta", message });
+    }
+    // Models that clamp max tokens serve any request
+    if model.llm_params.max_tokens_policy == MaxTokensPolicy::Reject
+        && let Some(over) = output_limit::check(request, &model.llm_params)
+    {
+        let message = over.message(&model.model_name, None, &[]);
+        return Some(Unsupported { status: StatusCode::BAD_REQUEST, code: "max_tokens_too_large", message });
+    }
+    if let Err(exceeded) = request_caps::check(request, &model.llm_params) {
+        return Some(Unsupported { status: StatusCode::PAYLOAD_TOO_LARGE, code: exceeded.code(), message: exceeded.message(&model.model_name) });
+    }
+    None
+}
+
+// Runs the router's tools the model called and sends the results back for
+// another turn, at most `max_rounds` times. A response that calls any tool the
+// router does not own goes back to the client unchanged.
+#[allow(clippy::too_many_arguments)]
+async fn run_router_tools(
+    api_type: ApiType,
+    config: &AppState,
+    request_id: &RequestId,
+    request_wrapper: &mut RequestWrapper,
+    selection: &Selection,
+    stream_options: StreamOptions,
+    meta: &mut RoutingMeta,
+    mut response: axum::response::Response,
+    tools: &mut RouterTools,
+) -> axum::response::Response {
+    for round in 1..=tools.max_rounds {
+        if !response.status().is_success() {
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Failed to read response body for tool calls: {}", e);
+                return RouterError::Internal(format!("Failed to read response body: {}", e)).into_response();
+            }
+        };
+        let body = serde_json::from_slice::<serde_json::Value>(&bytes).unwrap_or_default();
+        response = axum::response::Response::from_parts(parts, axum::body::Body::from(bytes));
+
+        let calls = crate::router_tools::tool_calls(&api_type, &body);
+        let mut searches_left = tools.searches_left;
+        let runs: Option<Vec<_>> = calls.iter().map(|call| tools.run(config, call, &mut searches_left)).collect();
+        let Some(runs) = runs.filter(|r| !r.is_empty()) else { return response };
+
+        info!("Running {} router tool call(s) for request {} (round {})", runs.len(), request_id.0, round);
+        let outputs = futures::future::join_all(runs).await;
+        tools.searches_left = searches_left;
+        let results: Vec<_> = calls.iter().cloned().zip(outputs).collect();
+        if let Err(e) = crate::router_tools::append_results(request_wrapper, &body, &results) {
+            warn!("Cannot continue request {} after tool calls: {}", request_id.0, e);
+            return response;
+        }
+        response = dispatch(api_type.clone(), config, request_id, request_wrapper, selection, stream_options.clone(), meta).await;
+    }
+    response
+}
+
+// Sends the request to a second group member when the first has not answered
+// within `delay`, and keeps the first successful answer. The slower attempt is
+// dropped, which cancels its upstream request.
+#[allow(clippy::too_many_arguments)]
+async fn hedged_dispatch(
+    api_type: ApiType,
+    config: &AppState,
+    request_id: &RequestId,
+    request_wrapper: &RequestWrapper,
+    selection: &mut Selection,
+    stream_options: StreamOptions,
+    meta: &mut RoutingMeta,
+    delay: Duration,
+) -> axum::response::Response {
+    let mut hedge_meta = RoutingMeta {
+        latency_budget: meta.latency_budget,
+        priority: meta.priority,
+        rewrite: meta.rewrite.clone(),
+        store_owner: meta.store_owner.clone(),
+        ..Default::default()
+    };
+    let hedge = {
+        let model_manager = config.model_manager.read().await;
+        let request_json = serde_json::to_value(request_wrapper).unwrap_or_else(|_| json!({}));
+        let group = selection.group.as_deref().unwrap_or_default();
+        model_manager.resolve_untried(group, &request_json, meta.latency_budget, std::slice::from_ref(&selection.model_name), &|m| unsupported(request_wrapper, m).is_none())
+    };
+    let Some(hedge) = hedge else {
+        return dispatch(api_type, config, request_id, request_wrapper, selection, stream_options, meta).await;
+    };
+
+    // The slower attempt is dropped with this block
+    let (response, hedge_won) = {
+        let first = dispatch(api_type.clone(), config, request_id, request_wrapper, selection, stream_options.clone(), meta);
+        tokio::pin!(first);
+        tokio::select! {
+            response = &mut first => return response,
+            _ = tokio::time::sleep(delay) => {}
+        }
+        info!("Hedging [{}]: '{}' has not answered within {:?}, also trying '{}'", request_id.0, selection.model_name, delay, hedge.model_name);
+        let second = dispatch(api_type, config, request_id, request_wrapper, &hedge, stream_options, &mut hedge_meta);
+        tokio::pin!(second);
+        // An error only wins when the other attempt fails too
+        tokio::select! {
+            response = &mut first => match response.status().is_success() {
+                true => (response, false),
+                false => ((&mut second).await, true),
+            },
+            response = &mut second => match response.status().is_success() {
+                true => (response, true),
+                false => ((&mut first).await, false),
+            },
+        }
+    };
+    meta.attempts += hedge_meta.attempts;
+    if hedge_won {
+        info!("Hedging [{}]: answer from '{}' used", request_id.0, hedge.model_name);
+        *selection = hedge;
+        meta.upstream_latency = hedge_meta.upstream_latency;
+        meta.conversion = hedge_meta.conversion;
+        meta.upstream_headers = hedge_meta.upstream_headers;
+        meta.retry_after = hedge_meta.retry_after;
+        meta.max_tokens_warning = hedge_meta.max_tokens_warning;
+    }
+    response
+}
+
+// Sends a rate-limited request to the group members not tried yet; once every
+// candidate has answered 429 with a Retry-After, waits in the retry queue for the
+// earliest limit to lift and tries that member again, until the deadline
+#[allow(clippy::too_many_arguments)]
+async fn wait_out_rate_limits(
+    api_type: ApiType,
+    config: &AppState,
+    request_id: &RequestId,
+    request_wrapper: &RequestWrapper,
+    selection: &mut Selection,
+    stream_options: StreamOptions,
+    meta: &mut RoutingMeta,
+    mut response: axum::response::Response,
+    settings: &RetryQueueSettings,
+    started: Instant,
+) -> axum::response::Response {
+    let max_wait = Duration::from_millis(settings.max_wait_ms);
+    let Some(deadline) = started.checked_add(meta.latency_budget.map_or(max_wait, |budget| budget.min(max_wait))) else {
+        return response;
+    };
+    // Members that answered 429, with the time their limit lifts
+    let mut limited: Vec<(Selection, Instant)> = Vec::new();
+    loop {
+        if response.status() != StatusCode::TOO_MANY_REQUESTS {
+            return response;
+        }
+        let Some(until) = meta.retry_after.and_then(|wait| Instant::now().checked_add(wait)) else { return response };
+        limited.push((selection.clone(), until));
+
+        if let Some(group) = selection.group.clone() {
+            let tried: Vec<String> = limited.iter().map(|(s, _)| s.model_name.clone()).collect();
+            let next = {
+                let model_manager = config.model_manager.read().await;
+                let request_json = serde_json::to_value(request_wrapper).unwrap_or_else(|_| json!({}));
+                model_manager.resolve_untried(&group, &request_json, meta.latency_budget, &tried, &|m| unsupported(request_wrapper, m).is_none())
+            };
+            if let Some(next) = next {
+                info!("Rate limited [{}]: '{}' answered 429, trying '{}'", request_id.0, selection.model_name, next.model_name);
+                *selection = next;
+                response = dispatch(api_type.clone(), config, request_id, request_wrapper, selection, stream_options.clone(), meta).await;
+                continue;
+            }
+        }
+
+        let Some((index, until)) = limited.iter().enumerate().min_by_key(|(_, (_, until))| *until).map(|(i, (_, until))| (i, *until)) else {
+            return response;
+        };
+        if until > deadline {
+            info!("Rate limited [{}]: earliest limit lifts after the deadline, giving up", request_id.0);
+            return response;
+        }
+        {
+            let Some(_slot) = config.retry_queue.enter(settings.max_waiting) else {
+                warn!("Rate limited [{}]: retry queue full ({} waiting), giving up", request_id.0, settings.max_waiting);
+                return response;
+            };
+            let (candidate, _) = &limited[index];
+            info!("Rate limited [{}]: waiting {:?} for '{}'", request_id.0, until.saturating_duration_since(Instant::now()), candidate.model_name);
+            meta.report_queue(Some(QueueStatus { model: candidate.model_name.clone(), position: None, eta: Some(until.into()) }));
+            tokio::time::sleep(until.saturating_duration_since(Instant::now())).await;
+            meta.report_queue(None);
+        }
+        *selection = limited.remove(index).0;
+        response = dispatch(api_type.clone(), config, request_id, request_wrapper, selection, stream_options.clone(), meta).await;
+    }
+}
+
+// Send a streamed request that failed upstream to the group members not tried
+// yet. dispatch only answers with an error while nothing has reached the
+// client, so a retry can never repeat events the client already has.
+#[allow(clippy::too_many_arguments)]
+async fn fail_over_streams(
+    api_type: ApiType,
+    config: &AppState,
+    request_id: &RequestId,
+    request_wrapper: &RequestWrapper,
+    selection: &mut Selection,
+    stream_options: StreamOptions,
+    meta: &mut RoutingMeta,
+    mut response: axum::response::Response,
+    settings: &StreamFailoverSettings,
+) -> axum::response::Response {
+    let mut tried = vec![selection.model_name.clone()];
+    while response.status().is_server_error() && tried.len() <= settings.max_retries as usize {
+        let Some(group) = selection.group.clone() else { break };
+        let next = {
+            let model_manager = config.model_manager.read().await;
+            let request_json = serde_json::to_value(request_wrapper).unwrap_or_else(|_| json!({}));
+            model_manager.resolve_untried(&group, &request_json, meta.latency_budget, &tried, &|m| unsupported(request_wrapper, m).is_none())
+        };
+        let Some(next) = next else {
+            info!("Stream failover [{}]: no untried member left in group '{}'", request_id.0, group);
+            break;
+        };
+        info!(
+            "Stream failover [{}]: '{}' failed with {}, trying '{}'",
+            request_id.0, selection.model_name, response.status(), next.model_name
+        );
+        tried.push(next.model_name.clone());
+        *selection = next;
+        response = dispatch(api_type.clone(), config, request_id, request_wrapper, selection, stream_options.clone(), meta).await;
+    }
+    response
+}
+
+// Check a complete answer and retry once with a corrective note when it fails;
+// a second failure goes back to the client, marked with x-llm-router-output-invalid
+#[allow(clippy::too_many_arguments)]
+async fn validate_output(
+    api_type: ApiType,
+    config: &AppState,
+    request_id: &RequestId,
+    request_wrapper: &RequestWrapper,
+    selection: &mut Selection,
+    stream_options: StreamOptions,
+    meta: &mut RoutingMeta,
+    response: axum::response::Response,
+    settings: &OutputValidationSettings,
+    expect: &output_validation::Expectation,
+) -> axum::response::Response {
+    let (response, error) = check_output(&api_type, expect, response).await;
+    let Some(error) = error else { return response };
+    info!("Output validation [{}]: answer from '{}' rejected: {}", request_id.0, selection.model_name, error);
+
+    let mut retry_request = request_wrapper.clone();
+    if let Err(e) = output_validation::add_nudge(&mut retry_request, &settings.nudge.replace("{error}", &error)) {
+        warn!("Output validation [{}]: could not add the corrective note: {}", request_id.0, e);
+        return mark_invalid(response, &error);
+    }
+    if settings.alternate_model
+        && let Some(group) = selection.group.clone()
+    {
+        let next = {
+            let model_manager = config.model_manager.read().await;
+            let request_json = serde_json::to_value(request_wrapper).unwrap_or_else(|_| json!({}));
+            model_manager.resolve_untried(&group, &request_json, meta.latency_budget, std::slice::from_ref(&selection.model_name), &|m| unsupported(request_wrapper, m).is_none())
+        };
+        match next {
+            Some(next) => *selection = next,
+            None => debug!("Output validation [{}]: no other member in group '{}', retrying on the same model", request_id.0, group),
+        }
+    }
+    info!("Output validation [{}]: retrying on '{}'", request_id.0, selection.model_name);
+    let retried = dispatch(api_type.clone(), config, request_id, &retry_request, selection, stream_options, meta).await;
+    if !retried.status().is_success() {
+        config.metrics.record_validation_retry(false);
+        return retried;
+    }
+    let (retried, error) = check_output(&api_type, expect, retried).await;
+    config.metrics.record_validation_retry(error.is_none());
+    match error {
+        None => retried,
+        Some(error) => {
+            warn!("Output validation [{}]: retry on '{}' also rejected: {}", request_id.0, selection.model_name, error);
+            mark_invalid(retried, &error)
+        }
+    }
+}
+
+// Buffer a successful response and say what is wrong with its answer, if anything
+async fn check_output(
+    api_type: &ApiType,
+    expect: &output_validation::Expectation,
+    response: axum::response::Response,
+) -> (axum::response::Response, Option<String>) {
+    if !response.status().is_success() {
+        return (response, None);
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read response body for output validation: {}", e);
+            return (RouterError::Internal(format!("Failed to read response body: {}", e)).into_response(), None);
+        }
+    };
+    let error = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(body) => output_validation::validate(expect, api_type, &body).err(),
+        Err(e) => Some(format!("the response body is not JSON ({})", e)),
+    };
+    (axum::response::Response::from_parts(parts, axum::body::Body::from(bytes)), error)
+}
+
+fn mark_invalid(mut response: axum::response::Response, error: &str) -> axum::response::Response {
+    // Header values must be visible ASCII
+    let reason: String = error.chars().map(|c| if c.is_ascii_graphic() || c == ' ' { c } else { '?' }).collect();
+    if let Ok(v) = HeaderValue::from_str(&reason) {
+        response.headers_mut().insert("x-llm-router-output-invalid", v);
+    }
+    response
+}
+
+// Re-sends a refused request to `uncensored` members of the group that served it,
+// at most `max_retries` times. Every refusal and fallback decision is logged with
+// the request id so the chain can be audited.
+#[allow(clippy::too_many_arguments)]
+async fn retry_refusals(
+    api_type: ApiType,
+    config: &AppState,
+    request_id: &RequestId,
+    request_wrapper: &RequestWrapper,
+    selection: &mut Selection,
+    stream_options: StreamOptions,
+    meta: &mut RoutingMeta,
+    mut response: axum::response::Response,
+    settings: &RefusalFallbackSettings,
+) -> axum::response::Response {
+    let mut tried = vec![selection.model_name.clone()];
+    loop {
+        if !response.status().is_success() {
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Failed to read response body for refusal check: {}", e);
+                return RouterError::Internal(format!("Failed to read response body: {}", e)).into_response();
+            }
+        };
+        let reason = serde_json::from_slice::<serde_json::Value>(&bytes)
+            .ok()
+            .and_then(|body| refusal::detect(settings, &api_type, &body));
+        response = axum::response::Response::from_parts(parts, axum::body::Body::from(bytes));
+        let Some(reason) = reason else { return response };
+
+        info!(
+            "Refusal audit [{}]: model '{}' in group {:?} refused ({}), attempt {}",
+            request_id.0, selection.model_name, selection.group, reason, tried.len()
+        );
+        if tried.len() > settings.max_retries as usize {
+            warn!("Refusal audit [{}]: retry cap of {} reached, returning the refusal", request_id.0, settings.max_retries);
+            return response;
+        }
+        let Some(group) = selection.group.clone() else {
+            info!("Refusal audit [{}]: '{}' was requested directly, no fallback", request_id.0, selection.model_name);
+            return response;
+        };
+        let next = {
+            let model_manager = config.model_manager.read().await;
+            let request_json = serde_json::to_value(request_wrapper).unwrap_or_else(|_| json!({}));
+            model_manager.resolve_uncensored(&group, &request_json, meta.latency_budget, &tried, &|m| unsupported(request_wrapper, m).is_none())
+        };
+        let Some(next) = next else {
+            warn!("Refusal audit [{}]: no untried uncensored member left in group '{}', returning the refusal", request_id.0, group);
+            return response;
+        };
+        info!(
+            "Refusal audit [{}]: retrying on uncensored model '{}' (previously tried: {})",
+            request_id.0, next.model_name, tried.join(", ")
+        );
+        tried.push(next.model_name.clone());
+        *selection = next;
+        response = dispatch(api_type.clone(), config, request_id, request_wrapper, selection, stream_options.clone(), meta).await;
+    }
+}
+
+// Complete bodies get their cost as a header. A stream's usage is only known
+// at its end, so its cost follows as a trailer once the upstream reported it.
+fn apply_cost(mut response: axum::response::Response, api_type: &ApiType, pricing: &Pricing) -> axum::response::Response {
+    // Session caps price streamed usage once the stream is done
+    response.extensions_mut().insert(pricing.clone());
+    if let Some(usage) = response.extensions().get::<TokenUsage>() {
+        let cost = pricing.cost(usage.input_tokens, usage.output_tokens);
+        if let Ok(v) = HeaderValue::from_str(&format!("{:.6}", cost)) {
+            response.headers_mut().insert(COST_HEADER, v);
+        }
+        return response;
+    }
+    let is_stream = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_stream || !response.status().is_success() {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let state = (body, StreamUsage::new(api_type.clone()), Some(pricing.clone()));
+    let frames = futures::stream::unfold(state, |(mut body, mut usage, pricing)| async move {
+        let pricing = pricing?;
+        match body.frame().await {
+            Some(Ok(frame)) => {
+                if let Some(bytes) = frame.data_ref() {
+                    usage.feed(bytes);
+                }
+                Some((Ok(frame), (body, usage, Some(pricing))))
+            }
+            Some(Err(e)) => Some((Err(e), (body, usage, Some(pricing)))),
+            None => {
+                // No trailer for usage the stream never reported in full
+                let (input_tokens, output_tokens, estimated) = usage.reconciled(0);
+                if estimated {
+                    return None;
+                }
+                let cost = HeaderValue::from_str(&format!("{:.6}", pricing.cost(input_tokens, output_tokens))).ok()?;
+                let trailers = HeaderMap::from_iter([(HeaderName::from_static(COST_HEADER), cost)]);
+                Some((Ok(Frame::trailers(trailers)), (body, usage, None)))
+            }
+        }
+    });
+    axum::response::Response::from_parts(parts, axum::body::Body::new(StreamBody::new(frames)))
+}
+
+const COST_HEADER: &str = "x-llm-router-cost";
+
+// Per-request routing facts surfaced via the opt-in x-llm-router-* response headers
+#[derive(Debug, Default)]
+struct RoutingMeta {
+    // Client latency budget, passed on to the upstream request
+    latency_budget: Option<Duration>,
+    // Client priority, for bulkhead queueing and shedding
+    priority: Priority,
+    attempts: u32,
+    upstream_latency: Option<Duration>,
+    // How the request and the answer were converted, e.g. "direct/openai"
+    conversion: Option<String>,
+    // Headers of the last upstream response picked by the model's forward_headers
+    upstream_headers: HeaderMap,
+    // Wait the last upstream asked for when it answered 429
+    retry_after: Option<Duration>,
+    // Hold streamed answers back until the upstream's first chunk, so a
+    // failure before it can still go to another member
+    fence_streams: bool,
+    // Told where the request stands while it waits in a queue
+    queue_reporter: Option<QueueReporter>,
+    // Filled into rewrite templates; the group is set per attempt
+    rewrite: RewriteContext,
+    // Set when the last attempt lowered max tokens to the model's limit
+    max_tokens_warning: Option<String>,
+    // Virtual key the request came with; only it can resume the stored stream
+    store_owner: Option<String>,
+}
+
+impl RoutingMeta {
+    fn report_queue(&self, status: Option<QueueStatus>) {
+        if let Some(reporter) = &self.queue_reporter {
+            reporter.send_replace(status);
+        }
+    }
+}
+
+fn apply_routing_headers(response: &mut axum::response::Response, selection: &Selection, meta: &RoutingMeta) {
+    let headers = response.headers_mut();
+    if let Ok(v) = HeaderValue::from_str(&selection.model_name) {
+        headers.insert("x-llm-router-model", v);
+    }
+    if let Some(Ok(v)) = selection.group.as_deref().map(HeaderValue::from_str) {
+        headers.insert("x-llm-router-group", v);
+    }
+    headers.insert("x-llm-router-attempts", HeaderValue::from(meta.attempts));
+    if let Some(latency) = meta.upstream_latency {
+        headers.insert("x-llm-router-upstream-latency-ms", HeaderValue::from(latency.as_millis() as u64));
+    }
+    if let Some(Ok(v)) = meta.conversion.as_deref().map(HeaderValue::from_str) {
+        headers.insert("x-llm-router-conversion", v);
+    }
+}
+
+// Upstream headers are always passed on, renamed so they cannot be mistaken
+// for the router's own
+fn apply_upstream_headers(response: &mut axum::response::Response, meta: &RoutingMeta) {
+    let headers = response.headers_mut();
+    for (name, value) in &meta.upstream_headers {
+        if let Ok(name) = HeaderName::try_from(format!("x-upstream-{}", name)) {
+            headers.insert(name, value.clone());
+        }
+    }
+}
+
+// Headers matching one of the patterns; a trailing * matches any suffix
+fn pick_headers(headers: &HeaderMap, patterns: &[String]) -> HeaderMap {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            patterns.iter().any(|pattern| {
+                let pattern = pattern.to_ascii_lowercase();
+                match pattern.strip_suffix('*') {
+                    Some(prefix) => name.as_str().starts_with(prefix),
+                    None => name.as_str() == pattern,
+                }
+            })
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+// A request counted as started on its model. One dropped before it ends, such
+// as the slower half of a hedged request, is released without touching health.
+struct InFlight {
+    model_manager: Arc<RwLock<ModelManager>>,
+    selection: Option<Selection>,
+}
+
+impl InFlight {
+    async fn start(config: &AppState, selection: &Selection) -> Self {
+        config.model_manager.read().await.start(selection);
+        InFlight { model_manager: config.model_manager.clone(), selection: Some(selection.clone()) }
+    }
+
+    async fn end(mut self, success: bool) {
+        if let Some(selection) = self.selection.take() {
+            self.model_manager.read().await.end(&selection, success);
+        }
+    }
+
+    async fn release(mut self) {
+        if let Some(selection) = self.selection.take() {
+            self.model_manager.read().await.release(&selection);
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Some(selection) = self.selection.take() {
+            let model_manager = self.model_manager.clone();
+            tokio::spawn(async move { model_manager.read().await.release(&selection) });
+        }
+    }
+}
+
+// Forwards the request to the selected upstream and converts the response back
+async fn dispatch(
+    api_type: ApiType,
+    config: &AppState,
+    request_id: &RequestId,
+    request_wrapper: &RequestWrapper,
+    selection: &Selection,
+    stream_options: StreamOptions,
+    meta: &mut RoutingMeta,
+) -> axum::response::Response {
+    for decision in &selection.decisions {
+        info!(
+            request_id = %request_id.0,
+            attempt = meta.attempts + 1,
+            group = %decision.group,
+            chosen = %decision.chosen,
+            strategy = decision.strategy_name(),
+            "Routing audit: {}",
+            decision
+        );
+    }
+    // Gemini takes images only as inline data, so http image URLs are downloaded
+    // when image_fetch allows it; inline images must then fit the model's limit.
+    // Both happen before anything is reserved.
+    let mut prepared = None;
+    if selection.config.llm_params.api_type == ApiType::Gemini {
+        let settings = config.model_manager.read().await.get_config().router_settings.image_fetch.clone();
+        if settings.enabled {
+            let mut request = request_wrapper.clone();
+            if let Err(message) = image_fetch::inline_remote_images(&mut request, &config.llm_client, &settings).await {
+                info!("Rejecting request for model {}: {}", selection.model_name, message);
+                return RouterError::client(StatusCode::BAD_REQUEST, "image_fetch_failed", message).into_response();
+            }
+            prepared = Some(request);
+        }
+    }
+    if let Some(limits) = &selection.config.llm_params.image_limits {
+        let request = prepared.take().unwrap_or_else(|| request_wrapper.clone());
+        match inline_images::enforce_off_worker(request, limits).await {
+            Ok(request) => prepared = Some(request),
+            Err(message) => {
+                info!("Rejecting request for model {}: {}", selection.model_name, message);
+                return RouterError::client(StatusCode::PAYLOAD_TOO_LARGE, "image_too_large", message).into_response();
+            }
+        }
+    }
+    let stream = request_wrapper.is_stream().unwrap_or(false);
+    // A soft timeout reads non-streaming answers from a stream, so whatever
+    // arrived before the deadline can still be returned
+    let soft_deadline = selection
+        .config
+        .llm_params
+        .soft_timeout_ms
+        .filter(|_| !stream)
+        .map(|ms| tokio::time::Instant::now() + Duration::from_millis(ms));
+    if soft_deadline.is_some() {
+        prepared.get_or_insert_with(|| request_wrapper.clone()).set_stream(true);
+    }
+    // Pairs with their own converter use it unless left out of direct_conversions;
+    // streamed answers always go through the OpenAI format
+    let upstream_api = selection.config.llm_params.api_type.clone();
+    let pair = ConversionPair { from: api_type.clone(), to: upstream_api.clone() };
+    let direct = pair.has_direct_converter()
+        && config.model_manager.read().await.get_config().router_settings.direct_conversions.contains(&pair);
+    if pair.has_direct_converter() && !direct {
+        prepared = Some(prepared.as_ref().unwrap_or(request_wrapper).via_openai());
+    }
+    // OpenAI upstreams report streamed usage only when asked; soft timeouts
+    // need it for the answer, session caps and pricing to count what was used
+    let caps_enabled = config.model_manager.read().await.get_config().router_settings.session_caps.enabled();
+    let priced = selection.config.llm_params.pricing.is_some();
+    if upstream_api == ApiType::OpenAI && (soft_deadline.is_some() || (stream && (caps_enabled || priced))) {
+        let request = prepared.get_or_insert_with(|| request_wrapper.clone());
+        if !matches!(request, RequestWrapper::OpenAI(_)) {
+            *request = request.via_openai();
+        }
+        if let RequestWrapper::OpenAI(req) = request {
+            let options = req.extra_fields.entry("stream_options".to_string()).or_insert_with(|| json!({}));
+            if let Some(options) = options.as_object_mut() {
+                options.insert("include_usage".to_string(), json!(true));
+            }
+        }
+    }
+    let direct_response = direct && !stream && soft_deadline.is_none();
+    let path = |direct: bool| if api_type == upstream_api { "none" } else if direct { "direct" } else { "openai" };
+    meta.conversion = Some(format!("{}/{}", path(direct), path(direct_response)));
+    meta.max_tokens_warning = None;
+    if let Some(over) = output_limit::check(prepared.as_ref().unwrap_or(request_wrapper), &selection.config.llm_params) {
+        match selection.config.llm_params.max_tokens_policy {
+            MaxTokensPolicy::Clamp => {
+                let warning = over.warning(&selection.model_name);
+                info!("Clamping request: {}", warning);
+                prepared.get_or_insert_with(|| request_wrapper.clone()).set_max_tokens(over.limit);
+                meta.max_tokens_warning = Some(warning);
+            }
+            MaxTokensPolicy::Reject => {
+                let peers = match &selection.group {
+                    Some(group) => config.model_manager.read().await.members_allowing_max_tokens(group, &selection.model_name, over.requested),
+                    None => Vec::new(),
+                };
+                let message = over.message(&selection.model_name, selection.group.as_deref(), &peers);
+                info!("Rejecting request: {}", message);
+                return error_in_client_format(&api_type, StatusCode::BAD_REQUEST, "max_tokens_too_large", message);
+            }
+        }
+    }
+    let request_wrapper = prepared.as_ref().unwrap_or(request_wrapper);
+    if let Err(exceeded) = request_caps::check(request_wrapper, &selection.config.llm_params) {
+        let message = exceeded.message(&selection.model_name);
+        info!("Rejecting request: {}", message);
+        return error_in_client_format(&api_type, StatusCode::PAYLOAD_TOO_LARGE, exceeded.code(), message);
+    }
+    let model = request_wrapper.get_model();
+
+    // Bulkhead: bound in-flight requests per model so one stuck upstream
+    // cannot starve the others. The permit lives until the response body ends.
+    let (bulkhead, settings, latency) = {
+        let model_manager = config.model_manager.read().await;
+        (
+            model_manager.bulkhead(&selection.model_name),
+            model_manager.get_config().router_settings.priority.clone(),
+            model_manager.observed_latency(&selection.model_name),
+        )
+    };
+    let permit = match bulkhead {
+        Some(bulkhead) => {
+            let timeout = Duration::from_millis(settings.queue_timeout_ms);
+            // Each slot frees up about once per average request
+            let report = |ahead: usize| {
+                let eta = latency.map(|l| tokio::time::Instant::now() + l * (ahead as u32 + 1) / bulkhead.limit().max(1));
+                meta.report_queue(Some(QueueStatus { model: selection.model_name.clone(), position: Some(ahead), eta }));
+            };
+            let permit = bulkhead.acquire_reporting(meta.priority, settings.low_reserve, timeout, &report).await;
+            meta.report_queue(None);
+            match permit {
+                Some(permit) => Some(permit),
+                None => {
+                    warn!("Bulkhead full for model {} ({} in flight), shedding {:?} priority request", selection.model_name, bulkhead.limit(), meta.priority);
+                    return RouterError::Overloaded(format!(
+                        "Model '{}' is at its concurrency limit ({})",
+                        selection.model_name,
+                        bulkhead.limit()
+                    ))
+                    .into_response();
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Track the start of the request
+    let in_flight = InFlight::start(config, selection).await;
+
+    let started = Instant::now();
+    meta.attempts += 1;
+    meta.upstream_headers.clear();
+    meta.retry_after = None;
+    let identity = config.model_manager.read().await.get_config().router_settings.upstream_identity.clone();
+    meta.rewrite.group = selection.group.clone().unwrap_or_default();
     let response = config
         .llm_client
-        .forward_request(&request_wrapper, &selection.config, &request_id);
-    let response = match response.await {
+        .forward_request(request_wrapper, &selection.config, request_id, &identity, &meta.rewrite, meta.latency_budget);
+    let response = match soft_deadline {
+        Some(deadline) => match tokio::time::timeout_at(deadline, response).await {
+            Ok(response) => response.map_err(|e| RouterError::from_reqwest(&e)),
+            Err(_) => Err(RouterError::Timeout(format!("Model '{}' sent no response before its soft timeout", selection.model_name))),
+        },
+        None => response.await.map_err(|e| RouterError::from_reqwest(&e)),
+    };
+    let response = match response {
         Ok(resp) => resp,
-        Err(e) => {
-            warn!("Failed to send streaming request: {}", e);
+        Err(err) => {
+            warn!("Failed to send request: {} (retryable: {})", err, err.is_retryable());
             // Track the failed request
-            {
-                let model_manager = config.model_manager.read().await;
-                model_manager.end(&selection, false);
-            }
-            let error_response = ErrorResponse {
-                error: ErrorDetail {
-                    message: format!("Failed to send request: {}", e),
-                    r#type: "api_error".to_string(),
-                    code: Some("request_failed".to_string()),
-                },
-            };
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)).into_response();
+            in_flight.end(false).await;
+            return err.into_response();
         }
     };
+    meta.upstream_latency = Some(started.elapsed());
+    meta.upstream_headers = pick_headers(response.headers(), &selection.config.llm_params.forward_headers);
+    let max_response_bytes = selection.config.llm_params.max_response_bytes.unwrap_or(DEFAULT_MAX_RESPONSE_BYTES);
     if !response.status().is_success() {
-        use axum::http::header::CONTENT_TYPE;
         let status = response.status();
         let content_type = response.headers().get(CONTENT_TYPE).cloned();
-        let body_bytes = response.bytes().await.unwrap_or_default();
-        warn!("Upstream request failed with status {}", status);
-        // Track the failed request
-        {
-            let model_manager = config.model_manager.read().await;
-            model_manager.end(&selection, false);
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let max_wait = config.model_manager.read().await.get_config().router_settings.retry_queue.max_wait_ms;
+            meta.retry_after = retry_queue::retry_after(response.headers(), Duration::from_millis(max_wait));
+        }
+        let body = read_error_body(response, max_response_bytes).await;
+        let err = RouterError::Upstream { status, content_type, body };
+        warn!("Upstream request failed with status {} (retryable: {})", status, err.is_retryable());
+        // Only upstream-side failures count against the model; a 4xx caused by
+        // the request itself says nothing about the model's health
+        if err.is_retryable() {
+            in_flight.end(false).await;
+        } else {
+            in_flight.release().await;
         }
 
-        let mut resp = (status, body_bytes).into_response();
-        if let Some(ct) = content_type { resp.headers_mut().insert(CONTENT_TYPE, ct); }
-        return resp;
+        return err.into_response();
+    }
+    // Only successful answers feed the latency average used for budgets
+    {
+        let model_manager = config.model_manager.read().await;
+        model_manager.record_latency(&selection.model_name, started.elapsed());
     }
     // Handle streaming and non-streaming responses
     if stream {
         info!("Processing streaming request");
+        // The first chunk's arrival feeds the model's time-to-first-byte health
+        let mut first_byte = Some((config.model_manager.clone(), selection.clone()));
+        let body_stream = response.bytes_stream().map(move |chunk| {
+            let _held = &permit;
+            if let Some((model_manager, selection)) = first_byte.take() {
+                let ttfb = started.elapsed();
+                tokio::spawn(async move { model_manager.read().await.record_ttfb(&selection, ttfb) });
+            }
+            chunk
+        });
+        let body_stream = if meta.fence_streams {
+            match stream_fence::first_chunk(body_stream).await {
+                FirstChunk::Started(body_stream) => body_stream.left_stream(),
+                FirstChunk::Failed(reason) => {
+                    warn!("Stream from model {} failed: {}", selection.model_name, reason);
+                    in_flight.end(false).await;
+                    return RouterError::Transport(reason).into_response();
+                }
+            }
+        } else {
+            body_stream.right_stream()
+        };
+        let mut stream_options = stream_options;
+        let (model_manager, failed) = (config.model_manager.clone(), selection.clone());
+        stream_options.on_upstream_error = Some(UpstreamErrorHook(std::sync::Arc::new(move |_, _| {
+            let (model_manager, failed) = (model_manager.clone(), failed.clone());
+            tokio::spawn(async move { model_manager.read().await.record_stream_failure(&failed) });
+        })));
+        if stream_options.resumable {
+            stream_options.store = Some(config.response_store.create(&config.response_key(&request_id.0), meta.store_owner.as_deref()));
+        }
         let result = handle_streaming_response(
-            response.bytes_stream(),
+            body_stream,
             model.to_string(),
             selection.config.llm_params.api_type.clone(),
             api_type.clone(),
+            stream_options,
         ).await;
         // Track the successful completion of streaming request
-        {
-            let model_manager = config.model_manager.read().await;
-            model_manager.end(&selection, true);
-        }
+        in_flight.end(true).await;
+        result
+    } else if let Some(deadline) = soft_deadline {
+        info!("Collecting streamed answer for non-streaming request");
+        let result = collect_streaming_response(
+            response.bytes_stream(),
+            model.to_string(),
+            selection.config.llm_params.api_type.clone(),
+            api_type.clone(),
+            deadline,
+            max_response_bytes,
+        ).await;
+        drop(permit);
+        in_flight.end(true).await;
         result
     } else {
         info!("Processing non-streaming request");
@@ -183,12 +1294,12 @@ pub async fn route_chat(
             model.to_string(),
             selection.config.llm_params.api_type.clone(),
             api_type.clone(),
+            direct_response,
+            max_response_bytes,
         ).await;
+        drop(permit);
         // Track the successful completion of non-streaming request
-        {
-            let model_manager = config.model_manager.read().await;
-            model_manager.end(&selection, true);
-        }
+        in_flight.end(true).await;
         result
     }
 }
@@ -197,19 +1308,25 @@ pub async fn route_chat(
 #[axum_macros::debug_handler]
 pub async fn list_models(
     State(config): State<AppState>,
+    tenant: Option<Extension<TenantId>>,
+    virtual_key: Option<Extension<VirtualKey>>,
 ) -> impl IntoResponse {
     debug!("Received models list request");
+    let config = config.scoped(tenant.as_deref());
     
-    let model_groups = {
+    let (model_groups, list_all) = {
         let model_manager = config.model_manager.read().await;
         let cfg = model_manager.get_config();
-        cfg.router_settings.model_groups.clone()
+        (cfg.router_settings.model_groups.clone(), cfg.router_settings.list_all_models)
     };
     
     let mut models = Vec::new();
     
-    // Add all model group aliases
+    // Add the model group aliases the caller may use
     for model_group in &model_groups {
+        if !list_all && virtual_key.as_ref().is_some_and(|vk| !vk.allows(&model_group.name)) {
+            continue;
+        }
         models.push(ModelInfo {
             id: model_group.name.clone(),
             object: "model".to_string()
@@ -224,3 +1341,354 @@ pub async fn list_models(
     debug!("Returning {} models", response.data.len());
     Json(response).into_response()
 }
+
+// A router-made client error shaped like the errors of the client's own API
+fn error_in_client_format(api_type: &ApiType, status: StatusCode, code: &'static str, message: String) -> axum::response::Response {
+    let body = error_body_in_client_format(api_type, status, code, message);
+    let mut response = (status, Json(body)).into_response();
+    response.extensions_mut().insert(crate::error::ErrorKind::Client);
+    response
+}
+
+/// The error object a client of `api_type` expects, for bodies and stream events.
+pub(crate) fn error_body_in_client_format(api_type: &ApiType, status: StatusCode, code: &str, message: String) -> serde_json::Value {
+    let server = status.is_server_error();
+    match api_type {
+        ApiType::Anthropic => {
+            let r#type = match status {
+                StatusCode::PAYLOAD_TOO_LARGE => "request_too_large",
+                StatusCode::NOT_FOUND => "not_found_error",
+                _ if server => "api_error",
+                _ => "invalid_request_error",
+            };
+            json!({"type": "error", "error": {"type": r#type, "message": message}})
+        }
+        ApiType::Gemini => {
+            let state = match status {
+                StatusCode::NOT_FOUND => "NOT_FOUND",
+                _ if server => "INTERNAL",
+                _ => "INVALID_ARGUMENT",
+            };
+            json!({"error": {"code": status.as_u16(), "message": message, "status": state}})
+        }
+        ApiType::OpenAI => {
+            let r#type = if server { "server_error" } else { "invalid_request_error" };
+            json!({"error": {"message": message, "type": r#type, "code": code}})
+        }
+    }
+}
+
+/// Fallback for unknown routes: a JSON 404 listing the endpoints the router serves,
+/// shaped like the client SDK expects. OpenAI format unless the request carries
+/// Anthropic or Gemini headers.
+pub async fn not_found(method: Method, uri: Uri, headers: HeaderMap) -> axum::response::Response {
+    let path = uri.path();
+    info!("No route for {} {}", method, path);
+    let endpoints = endpoint_list();
+    let mut message = format!("Unknown endpoint {} {}.", method, path);
+    // SDKs pointed at the wrong base path usually still end in a known endpoint
+    if let Some((_, known)) = ENDPOINTS.iter().find(|(_, p)| {
+        let suffix = p.trim_start_matches("/v1beta").trim_start_matches("/v1");
+        !p.contains('{') && path != *p && path.ends_with(suffix)
+    }) {
+        message.push_str(&format!(" Did you mean {}? Check the client's base URL.", known));
+    }
+    message.push_str(&format!(" Supported endpoints: {}", endpoints.join(", ")));
+
+    let api_type = if headers.contains_key("anthropic-version") {
+        ApiType::Anthropic
+    } else if headers.contains_key("x-goog-api-key") {
+        ApiType::Gemini
+    } else {
+        ApiType::OpenAI
+    };
+    error_in_client_format(&api_type, StatusCode::NOT_FOUND, "unknown_endpoint", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::llm_client::LlmClient;
+    use crate::mcp::McpGateway;
+    use crate::model_manager::ModelManager;
+    use crate::response_store::ResponseStore;
+    use http_body_util::BodyExt;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn app_state(config: Config) -> AppState {
+        let client = Arc::new(reqwest::Client::new());
+        AppState {
+            model_manager: Arc::new(RwLock::new(ModelManager::new(Arc::new(config)))),
+            token: None,
+            llm_client: Arc::new(LlmClient::new(client.clone(), client.clone())),
+            metrics: Arc::new(crate::metrics::Metrics::default()),
+            config_path: String::new(),
+            config_overrides: Arc::new(Vec::new()),
+            response_store: Arc::new(ResponseStore::new(&Default::default())),
+            mcp: Arc::new(McpGateway::new(client)),
+            sessions: Default::default(),
+            sizes: Default::default(),
+            retry_queue: Default::default(),
+            tenants: Arc::new(HashMap::new()),
+            tenant_index: Default::default(),
+            tenant: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_not_found_answers_in_the_client_format() {
+        let body = |response: axum::response::Response| async move {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+        let uri: Uri = "/api/v1/chat/completions".parse().unwrap();
+        let response = not_found(Method::POST, uri.clone(), HeaderMap::new()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.extensions().get::<crate::error::ErrorKind>(), Some(&crate::error::ErrorKind::Client));
+        let openai = body(response).await;
+        assert_eq!(openai["error"]["code"], "unknown_endpoint");
+        assert!(openai["error"]["message"].as_str().unwrap().contains("Did you mean /v1/chat/completions?"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-version", "2023-06-01".parse().unwrap());
+        assert_eq!(body(not_found(Method::POST, uri.clone(), headers).await).await["error"]["type"], "not_found_error");
+        let mut headers = HeaderMap::new();
+        headers.insert("x-goog-api-key", "k".parse().unwrap());
+        assert_eq!(body(not_found(Method::POST, uri, headers).await).await["error"]["status"], "NOT_FOUND");
+    }
+
+    #[tokio::test]
+    async fn test_stream_failover_passes_over_a_leading_error_event() {
+        let mut failing = mockito::Server::new_async().await;
+        let failing_mock = failing
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_body(": keep-alive\n\ndata: {\"error\":{\"message\":\"overloaded\"}}\n\n")
+            .expect(1)
+            .create_async()
+            .await;
+        let mut healthy = mockito::Server::new_async().await;
+        let chunk = json!({
+            "id": "chatcmpl-1", "object": "chat.completion.chunk", "created": 1, "model": "gpt-test",
+            "choices": [{"index": 0, "delta": {"role": "assistant", "content": "from the backup"}, "finish_reason": "stop"}]
+        });
+        let _healthy_mock = healthy
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_body(format!("data: {}\n\ndata: [DONE]\n\n", chunk))
+            .create_async()
+            .await;
+        let config: Config = serde_yaml::from_str(&format!(
+            "model_list:\n\
+             \x20 - model_name: m1\n    llm_params: {{api_type: openai, model: gpt-test, api_base: '{}', api_key: k}}\n\
+             \x20 - model_name: m2\n    llm_params: {{api_type: openai, model: gpt-test, api_base: '{}', api_key: k}}\n\
+             router_settings:\n  strategy: roundrobin\n  stream_failover: {{enabled: true}}\n\
+             \x20 model_groups: [{{name: g, models: [{{name: m1, weight: 2}}, {{name: m2, weight: 1}}]}}]\n",
+            failing.url(),
+            healthy.url()
+        ))
+        .unwrap();
+        let request: OpenAIRequest =
+            serde_json::from_value(json!({"model": "g", "stream": true, "messages": [{"role": "user", "content": "hi"}]})).unwrap();
+
+        let response = route_chat(
+            ApiType::OpenAI,
+            app_state(config),
+            RequestId("req-1".to_string()),
+            None,
+            None,
+            Priority::default(),
+            RequestWrapper::OpenAI(request),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("from the backup"), "{}", body);
+        assert!(!body.contains("overloaded"), "{}", body);
+        failing_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_requests_are_hedged() {
+        // Accepts connections and never answers
+        let hanging = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hanging_url = format!("http://{}", hanging.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = hanging.accept().await {
+                held.push(socket);
+            }
+        });
+        let mut healthy = mockito::Server::new_async().await;
+        let _healthy_mock = healthy
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "id": "chatcmpl-1", "object": "chat.completion", "created": 1, "model": "gpt-test",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": "from the hedge"}, "finish_reason": "stop"}]
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let config: Config = serde_yaml::from_str(&format!(
+            "model_list:\n\
+             \x20 - model_name: m1\n    llm_params: {{api_type: openai, model: gpt-test, api_base: '{}', api_key: k}}\n\
+             \x20 - model_name: m2\n    llm_params: {{api_type: openai, model: gpt-test, api_base: '{}', api_key: k}}\n\
+             router_settings:\n  strategy: roundrobin\n  priority: {{hedge_after_ms: 50}}\n\
+             \x20 model_groups: [{{name: g, models: [{{name: m1, weight: 2}}, {{name: m2, weight: 1}}]}}]\n",
+            hanging_url,
+            healthy.url()
+        ))
+        .unwrap();
+        let route = |state: AppState, priority| {
+            let request: OpenAIRequest =
+                serde_json::from_value(json!({"model": "g", "messages": [{"role": "user", "content": "hi"}]})).unwrap();
+            route_chat(ApiType::OpenAI, state, RequestId("req-1".to_string()), None, None, priority, RequestWrapper::OpenAI(request))
+        };
+
+        // Normal priority waits for the member it was sent to
+        let waited = tokio::time::timeout(Duration::from_millis(300), route(app_state(config.clone()), Priority::Normal)).await;
+        assert!(waited.is_err());
+
+        let state = app_state(config);
+        let response = route(state.clone(), Priority::High).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.extensions().get::<ServedModel>().map(|m| m.0.as_str()), Some("m2"));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("from the hedge"));
+        // The abandoned attempt no longer counts as in flight
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let health = state.model_manager.read().await.model_health();
+        assert!(health.iter().all(|m| m.in_flight == 0), "{:?}", health);
+    }
+
+    #[tokio::test]
+    async fn test_group_members_without_the_beta_are_passed_over() {
+        let mut upstream = mockito::Server::new_async().await;
+        let upstream_mock = upstream
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "id": "chatcmpl-1", "object": "chat.completion", "created": 1, "model": "gpt-test",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": "hi"}, "finish_reason": "stop"}]
+                })
+                .to_string(),
+            )
+            .expect(4)
+            .create_async()
+            .await;
+        // m1 would fail the test if picked: nothing listens on its api_base
+        let config: Config = serde_yaml::from_str(&format!(
+            "model_list:\n\
+             \x20 - model_name: m1\n    llm_params: {{api_type: anthropic, model: claude-test, api_base: 'http://127.0.0.1:1', api_key: k, anthropic_betas: [beta-x]}}\n\
+             \x20 - model_name: m2\n    llm_params: {{api_type: openai, model: gpt-test, api_base: '{}', api_key: k, anthropic_betas: [beta-y]}}\n\
+             router_settings:\n  strategy: roundrobin\n\
+             \x20 model_groups: [{{name: g, models: [{{name: m1, weight: 1}}, {{name: m2, weight: 1}}]}}]\n",
+            upstream.url()
+        ))
+        .unwrap();
+        let state = app_state(config);
+        let route = |beta: &str| {
+            let mut request: AnthropicRequest = serde_json::from_value(
+                json!({"model": "g", "max_tokens": 16, "messages": [{"role": "user", "content": "hi"}]}),
+            )
+            .unwrap();
+            request.betas = vec![beta.to_string()];
+            route_chat(ApiType::Anthropic, state.clone(), RequestId("req-1".to_string()), None, None, Priority::Normal, RequestWrapper::Anthropic(request))
+        };
+
+        for _ in 0..4 {
+            let response = route("beta-y").await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.extensions().get::<ServedModel>().map(|m| m.0.as_str()), Some("m2"));
+        }
+        upstream_mock.assert_async().await;
+
+        // No member accepts the beta
+        let response = route("beta-z").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["error"]["message"].as_str().unwrap().starts_with("anthropic-beta 'beta-z' is not supported"));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_cost_follows_as_a_trailer() {
+        let mut upstream = mockito::Server::new_async().await;
+        let chunk = json!({
+            "id": "chatcmpl-1", "object": "chat.completion.chunk", "created": 1, "model": "gpt-test",
+            "choices": [{"index": 0, "delta": {"role": "assistant", "content": "hi"}, "finish_reason": "stop"}]
+        });
+        let usage = json!({
+            "id": "chatcmpl-1", "object": "chat.completion.chunk", "created": 1, "model": "gpt-test",
+            "choices": [], "usage": {"prompt_tokens": 1000, "completion_tokens": 500, "total_tokens": 1500}
+        });
+        let upstream_mock = upstream
+            .mock("POST", "/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(json!({"stream_options": {"include_usage": true}})))
+            .with_header("content-type", "text/event-stream")
+            .with_body(format!("data: {}\n\ndata: {}\n\ndata: [DONE]\n\n", chunk, usage))
+            .create_async()
+            .await;
+        let config: Config = serde_yaml::from_str(&format!(
+            "model_list:\n\
+             \x20 - model_name: m1\n    llm_params: {{api_type: openai, model: gpt-test, api_base: '{}', api_key: k, pricing: {{input_per_mtok: 2.0, output_per_mtok: 10.0}}}}\n\
+             router_settings:\n  strategy: roundrobin\n  model_groups: []\n",
+            upstream.url()
+        ))
+        .unwrap();
+        let request: OpenAIRequest =
+            serde_json::from_value(json!({"model": "m1", "stream": true, "messages": [{"role": "user", "content": "hi"}]})).unwrap();
+
+        let response =
+            route_chat(ApiType::OpenAI, app_state(config), RequestId("req-1".to_string()), None, None, Priority::default(), RequestWrapper::OpenAI(request))
+                .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(COST_HEADER).is_none());
+        let body = response.into_body().collect().await.unwrap();
+        assert_eq!(body.trailers().unwrap()[COST_HEADER], "0.007000");
+        upstream_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_model_list_honours_the_virtual_key() {
+        let config: Config = serde_yaml::from_str(
+            "model_list:\n\
+             \x20 - model_name: m1\n    llm_params: {api_type: openai, model: gpt-test, api_base: 'http://localhost:1', api_key: k}\n\
+             router_settings:\n  strategy: roundrobin\n\
+             \x20 model_groups: [{name: g1, models: [{name: m1, weight: 1}]}, {name: g2, models: [{name: m1, weight: 1}]}]\n\
+             virtual_keys: [{key: restricted, allowed_models: [g1]}]\n",
+        )
+        .unwrap();
+        let state = app_state(config);
+        let app = axum::Router::new()
+            .route("/v1/models", axum::routing::get(list_models))
+            .layer(axum::middleware::from_fn_with_state(state.clone(), crate::auth::require_authorization))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let client = reqwest::Client::new();
+        let list = |token: Option<&'static str>| {
+            let request = client.get(format!("http://{}/v1/models", addr));
+            let request = match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            };
+            async move {
+                let body: serde_json::Value = request.send().await.unwrap().json().await.unwrap();
+                body["data"].as_array().unwrap().iter().map(|m| m["id"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(list(Some("restricted")).await, vec!["g1"]);
+        assert_eq!(list(None).await, vec!["g1", "g2"]);
+    }
+}
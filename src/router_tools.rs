@@ -0,0 +1,269 @@
+//! Tools the router runs itself (MCP tools, emulated web search) rather than
+//! the client: advertising them in the client's request format, finding the
+//! model's calls in converted responses and appending the results for the next turn.
+
+use crate::auth::AppState;
+use crate::config::{ApiType, McpServer, ModelConfig, WebSearchSettings};
+use crate::converters::request_wrapper::RequestWrapper;
+use crate::mcp::McpTool;
+use crate::request_id::RequestId;
+use crate::web_search;
+use serde_json::{Value, json};
+use std::future::Future;
+use tracing::{debug, warn};
+
+/// A function tool as offered to the model.
+#[derive(Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    // JSON schema of the arguments
+    pub parameters: Value,
+}
+
+/// A tool call found in a converted response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    // Empty for Gemini, which matches results by name
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+/// Tools the router runs for one request and how many more times they may run.
+#[derive(Default)]
+pub struct RouterTools {
+    mcp: Vec<McpTool>,
+    mcp_servers: Vec<McpServer>,
+    web_search: Option<WebSearchSettings>,
+    // Name the emulated search is offered under, clear of the client's own tools
+    web_search_name: String,
+    pub searches_left: u32,
+    pub max_rounds: u32,
+}
+
+impl RouterTools {
+    /// Gather the MCP tools and emulated web search that apply to `request`
+    /// sent to `model`, and advertise them in it.
+    pub async fn for_request(config: &AppState, request: &mut RequestWrapper, model: &ModelConfig, request_id: &RequestId) -> Self {
+        let (mcp_settings, web_search_settings) = {
+            let model_manager = config.model_manager.read().await;
+            let settings = &model_manager.get_config().router_settings;
+            (settings.mcp.clone(), settings.web_search.clone())
+        };
+        let mut tools = RouterTools::default();
+        if !mcp_settings.servers.is_empty() {
+            tools.mcp = config.mcp.tools(&mcp_settings).await;
+            tools.max_rounds = mcp_settings.max_rounds;
+            tools.mcp_servers = mcp_settings.servers;
+        }
+        if web_search_settings.enabled
+            && !model.llm_params.native_web_search
+            && let Some(hosted) = web_search::take_hosted_tool(request)
+        {
+            debug!("Emulating hosted web search for request {}", request_id.0);
+            tools.searches_left = hosted.max_uses.map_or(web_search_settings.max_uses, |n| n.min(web_search_settings.max_uses));
+            tools.max_rounds = tools.max_rounds.max(tools.searches_left + 1);
+            tools.web_search_name = web_search::tool_name(&client_tool_names(request));
+            tools.web_search = Some(web_search_settings);
+        }
+        let definitions = tools.definitions();
+        if !definitions.is_empty()
+            && let Err(e) = advertise(request, &definitions)
+        {
+            warn!("Not offering router tools to request {}: {}", request_id.0, e);
+            return RouterTools::default();
+        }
+        tools
+    }
+
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        let mut definitions: Vec<_> = self.mcp.iter().map(McpTool::definition).collect();
+        if self.web_search.is_some() {
+            definitions.push(web_search::definition(&self.web_search_name));
+        }
+        definitions
+    }
+
+    /// Output of one call, or None when the router does not own the tool.
+    pub fn run<'a>(&'a self, config: &'a AppState, call: &'a ToolCall, searches_left: &mut u32) -> Option<impl Future<Output = String> + use<'a>> {
+        enum Target<'a> {
+            Mcp(&'a McpServer, &'a McpTool),
+            WebSearch(&'a WebSearchSettings),
+            SearchLimit,
+        }
+        let target = if let Some(settings) = self.web_search.as_ref().filter(|_| call.name == self.web_search_name) {
+            if *searches_left == 0 {
+                Target::SearchLimit
+            } else {
+                *searches_left -= 1;
+                Target::WebSearch(settings)
+            }
+        } else {
+            let tool = self.mcp.iter().find(|t| t.exposed_name() == call.name)?;
+            Target::Mcp(self.mcp_servers.iter().find(|s| s.name == tool.server)?, tool)
+        };
+        Some(async move {
+            match target {
+                Target::Mcp(server, tool) => config.mcp.call(server, &tool.name, call.arguments.clone()).await,
+                Target::WebSearch(settings) => web_search::run(&config.llm_client, settings, &call.arguments).await,
+                Target::SearchLimit => "Error: search limit reached, answer with the results so far".to_string(),
+            }
+        })
+    }
+}
+
+/// Names of the function tools the client declared.
+pub fn client_tool_names(request: &RequestWrapper) -> Vec<String> {
+    match request {
+        RequestWrapper::OpenAI(req) => req.tools.iter().flatten().map(|t| t.function.name.clone()).collect(),
+        RequestWrapper::Anthropic(req) => req.tools.iter().flatten().map(|t| t.name.clone()).collect(),
+        RequestWrapper::Gemini(req) => {
+            req.tools.iter().flatten().flat_map(|t| &t.function_declarations).map(|f| f.name.clone()).collect()
+        }
+    }
+}
+
+/// Add `tools` to the request's tool list, in the client's format.
+pub fn advertise(request: &mut RequestWrapper, tools: &[ToolDefinition]) -> serde_json::Result<()> {
+    let definitions: Vec<Value> = match request {
+        RequestWrapper::OpenAI(_) => tools
+            .iter()
+            .map(|t| json!({"type": "function", "function": {"name": t.name, "description": t.description, "parameters": t.parameters}}))
+            .collect(),
+        RequestWrapper::Anthropic(_) => tools
+            .iter()
+            .map(|t| json!({"name": t.name, "description": t.description, "input_schema": t.parameters}))
+            .collect(),
+        RequestWrapper::Gemini(_) => vec![json!({"functionDeclarations": tools
+            .iter()
+            .map(|t| json!({"name": t.name, "description": t.description, "parameters": t.parameters}))
+            .collect::<Vec<_>>()})],
+    };
+    request.edit_json(|obj| {
+        let list = obj.entry("tools").or_insert_with(|| json!([]));
+        if !list.is_array() {
+            *list = json!([]);
+        }
+        list.as_array_mut().unwrap().extend(definitions);
+    })
+}
+
+/// Tool calls of the first choice/candidate of a converted response body.
+pub fn tool_calls(api_type: &ApiType, body: &Value) -> Vec<ToolCall> {
+    match api_type {
+        ApiType::OpenAI => body["choices"][0]["message"]["tool_calls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|call| ToolCall {
+                id: call["id"].as_str().unwrap_or_default().to_string(),
+                name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+                arguments: call["function"]["arguments"].as_str().and_then(|a| serde_json::from_str(a).ok()).unwrap_or_else(|| json!({})),
+            })
+            .collect(),
+        ApiType::Anthropic => body["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|block| block["type"] == "tool_use")
+            .map(|block| ToolCall {
+                id: block["id"].as_str().unwrap_or_default().to_string(),
+                name: block["name"].as_str().unwrap_or_default().to_string(),
+                arguments: block["input"].clone(),
+            })
+            .collect(),
+        ApiType::Gemini => body["candidates"][0]["content"]["parts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|part| part.get("functionCall"))
+            .map(|call| ToolCall {
+                id: String::new(),
+                name: call["name"].as_str().unwrap_or_default().to_string(),
+                arguments: call.get("args").cloned().unwrap_or_else(|| json!({})),
+            })
+            .collect(),
+    }
+}
+
+/// Continue the conversation: append the model turn of `body` and one result per call.
+pub fn append_results(request: &mut RequestWrapper, body: &Value, results: &[(ToolCall, String)]) -> serde_json::Result<()> {
+    let turns: (&str, Vec<Value>) = match request {
+        RequestWrapper::OpenAI(_) => {
+            let message = &body["choices"][0]["message"];
+            let mut turns = vec![json!({
+                "role": "assistant",
+                "content": message["content"].as_str().unwrap_or_default(),
+                "tool_calls": message["tool_calls"],
+            })];
+            turns.extend(results.iter().map(|(call, output)| json!({"role": "tool", "tool_call_id": call.id, "content": output})));
+            ("messages", turns)
+        }
+        RequestWrapper::Anthropic(_) => {
+            let outputs: Vec<Value> = results
+                .iter()
+                .map(|(call, output)| json!({"type": "tool_result", "tool_use_id": call.id, "content": output}))
+                .collect();
+            ("messages", vec![json!({"role": "assistant", "content": body["content"]}), json!({"role": "user", "content": outputs})])
+        }
+        RequestWrapper::Gemini(_) => {
+            let mut content = body["candidates"][0]["content"].clone();
+            content["role"] = json!("model");
+            let outputs: Vec<Value> = results
+                .iter()
+                .map(|(call, output)| json!({"functionResponse": {"name": call.name, "response": {"content": output}}}))
+                .collect();
+            ("contents", vec![content, json!({"role": "user", "parts": outputs})])
+        }
+    };
+    let (key, turns) = turns;
+    request.edit_json(|obj| {
+        if let Some(list) = obj.get_mut(key).and_then(Value::as_array_mut) {
+            list.extend(turns);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_in_client_formats() {
+        let tool = ToolDefinition { name: "files__read".to_string(), description: String::new(), parameters: json!({"type": "object"}) };
+        let results = |call: ToolCall| vec![(call, "hello".to_string())];
+
+        let mut openai = RequestWrapper::OpenAI(serde_json::from_value(json!({"model": "m", "messages": [{"role": "user", "content": "read a.txt"}]})).unwrap());
+        advertise(&mut openai, std::slice::from_ref(&tool)).unwrap();
+        let response = json!({"choices": [{"message": {"role": "assistant", "content": null, "tool_calls": [
+            {"id": "call_1", "type": "function", "function": {"name": "files__read", "arguments": "{\"path\":\"a.txt\"}"}}
+        ]}, "finish_reason": "tool_calls"}]});
+        let calls = tool_calls(&ApiType::OpenAI, &response);
+        assert_eq!(calls, vec![ToolCall { id: "call_1".to_string(), name: "files__read".to_string(), arguments: json!({"path": "a.txt"}) }]);
+        append_results(&mut openai, &response, &results(calls[0].clone())).unwrap();
+        let body = serde_json::to_value(&openai).unwrap();
+        assert_eq!(body["tools"][0]["function"]["name"], "files__read");
+        assert_eq!(body["messages"][2], json!({"role": "tool", "tool_call_id": "call_1", "content": "hello"}));
+
+        let mut anthropic = RequestWrapper::Anthropic(
+            serde_json::from_value(json!({"model": "m", "max_tokens": 16, "messages": [{"role": "user", "content": "read a.txt"}]})).unwrap(),
+        );
+        advertise(&mut anthropic, std::slice::from_ref(&tool)).unwrap();
+        let response = json!({"content": [{"type": "tool_use", "id": "toolu_1", "name": "files__read", "input": {"path": "a.txt"}}], "stop_reason": "tool_use"});
+        let calls = tool_calls(&ApiType::Anthropic, &response);
+        append_results(&mut anthropic, &response, &results(calls[0].clone())).unwrap();
+        let body = serde_json::to_value(&anthropic).unwrap();
+        assert_eq!(body["tools"][0]["name"], "files__read");
+        assert_eq!(body["messages"][2]["content"][0]["tool_use_id"], "toolu_1");
+
+        let mut gemini = RequestWrapper::Gemini(serde_json::from_value(json!({"contents": [{"role": "user", "parts": [{"text": "read a.txt"}]}]})).unwrap());
+        advertise(&mut gemini, std::slice::from_ref(&tool)).unwrap();
+        let response = json!({"candidates": [{"content": {"role": "model", "parts": [{"functionCall": {"name": "files__read", "args": {"path": "a.txt"}}}]}}]});
+        let calls = tool_calls(&ApiType::Gemini, &response);
+        append_results(&mut gemini, &response, &results(calls[0].clone())).unwrap();
+        let body = serde_json::to_value(&gemini).unwrap();
+        assert_eq!(body["tools"][0]["functionDeclarations"][0]["name"], "files__read");
+        assert_eq!(body["contents"][2]["parts"][0]["functionResponse"]["response"]["content"], "hello");
+    }
+}
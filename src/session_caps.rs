@@ -0,0 +1,495 @@
+//! Cumulative token and cost caps per client session. Agent loops that keep
+//! calling the router under one session id are refused with 403
+//! `session_cap_exceeded` once the session has used up its cap, whichever key
+//! they authenticate with. A tenant's `budget` is kept in the same ledger and
+//! refuses all of its requests with 403 `tenant_budget_exceeded`.
+
+use crate::auth::{AppState, TenantId};
+use crate::config::{ApiType, Pricing};
+use crate::converters::response_wrapper::TokenUsage;
+use crate::error::RouterError;
+use crate::queue_events::Routed;
+use crate::stream_pacing;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Tokens and cost used so far by each session, keyed `tenant/session`, and
+/// by each tenant with a budget, keyed by the tenant name alone.
+#[derive(Debug, Default)]
+pub struct SessionLedger {
+    sessions: Mutex<Entries>,
+}
+
+// Entries kept at most; past it the one seen least recently makes room
+const MAX_ENTRIES: usize = 100_000;
+// Expired entries are swept out at most this often
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct Entries {
+    spends: HashMap<String, SessionSpend>,
+    swept: Option<Instant>,
+}
+
+impl Entries {
+    fn sweep(&mut self, now: Instant) {
+        self.spends.retain(|_, spend| spend.live(spend.expiry));
+        self.swept = Some(now);
+    }
+}
+
+/// When a ledger entry starts over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    // After this long without a request, as sessions do
+    Idle(Duration),
+    // This long after its first request, as tenant budgets do
+    Period(Duration),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SessionSpend {
+    tokens: u64,
+    cost: f64,
+    started: Instant,
+    last_seen: Instant,
+    // As last recorded, for sweeping
+    expiry: Expiry,
+}
+
+impl SessionSpend {
+    fn live(&self, expiry: Expiry) -> bool {
+        match expiry {
+            Expiry::Idle(idle) => self.last_seen.elapsed() < idle,
+            Expiry::Period(period) => self.started.elapsed() < period,
+        }
+    }
+}
+
+impl SessionLedger {
+    /// Tokens and cost the entry has used since it last started over.
+    pub fn spent(&self, key: &str, expiry: Expiry) -> (u64, f64) {
+        let entries = self.sessions.lock().unwrap();
+        match entries.spends.get(key) {
+            Some(spend) if spend.live(expiry) => (spend.tokens, spend.cost),
+            _ => (0, 0.0),
+        }
+    }
+
+    pub fn record(&self, key: &str, tokens: u64, cost: f64, expiry: Expiry) {
+        let mut entries = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        if entries.swept.is_none_or(|swept| now.duration_since(swept) >= SWEEP_INTERVAL) {
+            entries.sweep(now);
+        }
+        if !entries.spends.contains_key(key) && entries.spends.len() >= MAX_ENTRIES {
+            entries.sweep(now);
+            if entries.spends.len() >= MAX_ENTRIES
+                && let Some(oldest) = entries.spends.iter().min_by_key(|(_, spend)| spend.last_seen).map(|(key, _)| key.clone())
+            {
+                entries.spends.remove(&oldest);
+            }
+        }
+        let fresh = SessionSpend { tokens: 0, cost: 0.0, started: now, last_seen: now, expiry };
+        let spend = entries.spends.entry(key.to_string()).or_insert(fresh);
+        if !spend.live(expiry) {
+            *spend = fresh;
+        }
+        spend.tokens += tokens;
+        spend.cost += cost;
+        spend.last_seen = now;
+        spend.expiry = expiry;
+        debug!("Session {} has used {} tokens, cost {:.6}", key, spend.tokens, spend.cost);
+    }
+}
+
+// One ledger entry a request is charged to, with its caps
+#[derive(Debug, Clone)]
+struct Account {
+    key: String,
+    expiry: Expiry,
+    max_tokens: Option<u64>,
+    max_cost: Option<f64>,
+}
+
+impl Account {
+    // What the entry has used, when it is at or over a cap
+    fn over(&self, ledger: &SessionLedger) -> Option<String> {
+        let (tokens, cost) = ledger.spent(&self.key, self.expiry);
+        match (self.max_tokens, self.max_cost) {
+            (Some(max), _) if tokens >= max => Some(format!("{} of {} tokens", tokens, max)),
+            (_, Some(max)) if cost >= max => Some(format!("cost {:.6} of {}", cost, max)),
+            _ => None,
+        }
+    }
+}
+
+fn charge(ledger: &SessionLedger, accounts: &[Account], tokens: u64, cost: f64) {
+    for account in accounts {
+        ledger.record(&account.key, tokens, cost, account.expiry);
+    }
+}
+
+/// Estimated prompt tokens of a routed request, set on the response by the
+/// router for streams that never report usage.
+#[derive(Debug, Clone, Copy)]
+pub struct PromptEstimate(pub u64);
+
+// Client format of a chat endpoint
+pub(crate) fn client_api(path: &str) -> Option<ApiType> {
+    if path.starts_with("/v1/chat/completions") {
+        Some(ApiType::OpenAI)
+    } else if path.starts_with("/v1/messages") {
+        Some(ApiType::Anthropic)
+    } else if path.contains("/models/") {
+        Some(ApiType::Gemini)
+    } else {
+        None
+    }
+}
+
+/// Refuse requests of sessions over their cap and of tenants over their
+/// budget, and add each response's usage to both. Streamed usage is counted
+/// when the stream ends.
+pub async fn enforce(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let Some(api_type) = client_api(req.uri().path()) else { return next.run(req).await };
+    let tenant = req.extensions().get::<TenantId>().cloned();
+    let mut accounts = Vec::new();
+    if let Some(TenantId(name)) = &tenant {
+        let budget = state.model_manager.read().await.get_config().tenants.iter().find(|t| &t.name == name).map(|t| t.budget.clone());
+        if let Some(budget) = budget.filter(|b| !b.is_unlimited()) {
+            let account = Account {
+                key: name.clone(),
+                expiry: Expiry::Period(Duration::from_secs(budget.period_secs)),
+                max_tokens: budget.max_tokens,
+                max_cost: budget.max_cost,
+            };
+            if let Some(over) = account.over(&state.sessions) {
+                warn!("Refusing request for tenant {}: used {}", name, over);
+                return RouterError::client(
+                    StatusCode::FORBIDDEN,
+                    "tenant_budget_exceeded",
+                    format!("Tenant '{}' has used {}", name, over),
+                )
+                .into_response();
+            }
+            accounts.push(account);
+        }
+    }
+
+    let state = state.scoped(tenant.as_ref());
+    let caps = state.model_manager.read().await.get_config().router_settings.session_caps.clone();
+    let session = req.headers().get(&caps.header).and_then(|v| v.to_str().ok()).map(str::trim).filter(|_| caps.enabled());
+    if let Some(session) = session {
+        let account = Account {
+            key: state.response_key(session),
+            expiry: Expiry::Idle(Duration::from_secs(caps.idle_secs)),
+            max_tokens: caps.max_tokens,
+            max_cost: caps.max_cost,
+        };
+        if let Some(over) = account.over(&state.sessions) {
+            warn!("Refusing request for session {}: used {}", account.key, over);
+            return RouterError::client(
+                StatusCode::FORBIDDEN,
+                "session_cap_exceeded",
+                format!("Session '{}' has used {}", session, over),
+            )
+            .into_response();
+        }
+        accounts.push(account);
+    }
+    if accounts.is_empty() {
+        return next.run(req).await;
+    }
+
+    let response = next.run(req).await;
+    let pricing = response.extensions().get::<Pricing>().cloned();
+    if let Some(usage) = response.extensions().get::<TokenUsage>() {
+        let cost = pricing.map_or(0.0, |p| p.cost(usage.input_tokens, usage.output_tokens));
+        charge(&state.sessions, &accounts, usage.input_tokens + usage.output_tokens, cost);
+        return response;
+    }
+    let is_stream = response
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_stream {
+        return response;
+    }
+
+    let mut spend = StreamSpend {
+        ledger: state.sessions.clone(),
+        accounts,
+        pricing,
+        usage: StreamUsage::new(api_type),
+        prompt_estimate: response.extensions().get::<PromptEstimate>().copied(),
+        routed: response.extensions().get::<Routed>().cloned(),
+    };
+    let (parts, body) = response.into_parts();
+    let body = body.map_frame(move |frame| {
+        if let Some(bytes) = frame.data_ref() {
+            spend.usage.feed(bytes);
+        }
+        frame
+    });
+    Response::from_parts(parts, Body::new(body))
+}
+
+// Usage seen in a stream's frames, added to the session and tenant when the
+// stream is dropped, whether it completed or the client went away
+struct StreamSpend {
+    ledger: Arc<SessionLedger>,
+    accounts: Vec<Account>,
+    pricing: Option<Pricing>,
+    usage: StreamUsage,
+    // For estimating usage the stream never reported
+    prompt_estimate: Option<PromptEstimate>,
+    // Set when the request was answered before it was routed
+    routed: Option<Routed>,
+}
+
+impl Drop for StreamSpend {
+    fn drop(&mut self) {
+        if let Some(routed) = &self.routed {
+            self.pricing = self.pricing.take().or_else(|| routed.get::<Pricing>());
+            self.prompt_estimate = self.prompt_estimate.or_else(|| routed.get::<PromptEstimate>());
+        }
+        let (input_tokens, output_tokens, estimated) = match self.routed.as_ref().and_then(Routed::get::<TokenUsage>) {
+            Some(usage) => (usage.input_tokens, usage.output_tokens, false),
+            None => self.usage.reconciled(self.prompt_estimate.map_or(0, |e| e.0)),
+        };
+        let tokens = input_tokens + output_tokens;
+        if estimated {
+            debug!("Stream for {} reported no usage; estimated {} tokens", self.accounts[0].key, tokens);
+        }
+        if tokens > 0 {
+            let cost = self.pricing.as_ref().map_or(0.0, |p| p.cost(input_tokens, output_tokens));
+            charge(&self.ledger, &self.accounts, tokens, cost);
+        }
+    }
+}
+
+/// Token counts read from the frames of a stream in the client's format.
+pub(crate) struct StreamUsage {
+    api_type: ApiType,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    // Some frame carried usage
+    reported: bool,
+    // The usage came from the frame that ends the answer, so it is the total
+    // rather than a running count a cut-off stream never updated
+    reported_final: bool,
+    // Generated text seen, for estimating usage the upstream never reported
+    text_bytes: u64,
+    // Some frame was an error event; all three formats put it under "error"
+    pub errored: bool,
+    pending: Vec<u8>,
+}
+
+impl StreamUsage {
+    pub fn new(api_type: ApiType) -> Self {
+        Self {
+            api_type,
+            input_tokens: 0,
+            output_tokens: 0,
+            reported: false,
+            reported_final: false,
+            text_bytes: 0,
+            errored: false,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        while let Some(pos) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            let Some(data) = std::str::from_utf8(&line).ok().and_then(|l| l.trim_end().strip_prefix("data:")) else {
+                continue;
+            };
+            let data = data.trim_start();
+            self.text_bytes += stream_pacing::raw_text_len(data) as u64;
+            // Most frames carry only text; parse just those that can report usage or an error
+            if !data.contains("\"usage") && !data.contains("\"error\"") {
+                continue;
+            }
+            let Ok(value) = serde_json::from_str::<Value>(data) else { continue };
+            // Cumulative counts in all three formats, so the largest seen is the total
+            if let Some((input, output)) = stream_usage(&self.api_type, &value) {
+                self.reported = true;
+                self.reported_final |= is_final_usage(&self.api_type, &value);
+                self.input_tokens = self.input_tokens.max(input);
+                self.output_tokens = self.output_tokens.max(output);
+            }
+            self.errored |= value.get("error").is_some();
+        }
+    }
+
+    /// Input and output tokens, and whether they are estimated. A stream that
+    /// reported no usage at all is estimated from `prompt_estimate` and the
+    /// generated text at about 4 bytes per token. One cut off before its final
+    /// usage keeps the reported input but counts at least the text it sent,
+    /// since Anthropic's message_start reports a placeholder output of 1.
+    pub fn reconciled(&self, prompt_estimate: u64) -> (u64, u64, bool) {
+        let estimated_output = self.text_bytes.div_ceil(4);
+        if !self.reported {
+            return (prompt_estimate, estimated_output, true);
+        }
+        if self.reported_final || self.output_tokens >= estimated_output {
+            return (self.input_tokens, self.output_tokens, false);
+        }
+        (self.input_tokens, estimated_output, true)
+    }
+}
+
+// Whether a frame carrying usage ends the answer: OpenAI's usage chunk comes
+// last, Anthropic's message_delta closes the message, Gemini's last chunk
+// has a finish reason
+fn is_final_usage(api_type: &ApiType, value: &Value) -> bool {
+    match api_type {
+        ApiType::OpenAI => true,
+        ApiType::Anthropic => value.get("type").and_then(Value::as_str) == Some("message_delta"),
+        ApiType::Gemini => value
+            .get("candidates")
+            .and_then(Value::as_array)
+            .is_some_and(|c| c.iter().any(|c| c.get("finishReason").is_some())),
+    }
+}
+
+// Input and output tokens reported by one stream frame in the client's format
+fn stream_usage(api_type: &ApiType, value: &Value) -> Option<(u64, u64)> {
+    let count = |usage: &Value, field: &str| usage.get(field).and_then(Value::as_u64).unwrap_or(0);
+    Some(match api_type {
+        ApiType::OpenAI => {
+            let usage = value.get("usage")?;
+            (count(usage, "prompt_tokens"), count(usage, "completion_tokens"))
+        }
+        // message_start carries the input count, message_delta the running
+        // output count; cache reads and writes are input too
+        ApiType::Anthropic => {
+            let usage = value.get("usage").or_else(|| value.get("message").and_then(|m| m.get("usage")))?;
+            let input = ["input_tokens", "cache_read_input_tokens", "cache_creation_input_tokens"].iter().map(|f| count(usage, f)).sum();
+            (input, count(usage, "output_tokens"))
+        }
+        ApiType::Gemini => {
+            let usage = value.get("usageMetadata")?;
+            (count(usage, "promptTokenCount"), count(usage, "candidatesTokenCount"))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_usage_is_recorded_on_drop() {
+        let ledger = Arc::new(SessionLedger::default());
+        let idle = Expiry::Idle(Duration::from_secs(60));
+        let mut spend = StreamSpend {
+            ledger: ledger.clone(),
+            accounts: vec![Account { key: "s1".to_string(), expiry: idle, max_tokens: Some(50), max_cost: None }],
+            pricing: Some(Pricing { input_per_mtok: 1_000_000.0, output_per_mtok: 2_000_000.0 }),
+            usage: StreamUsage::new(ApiType::Anthropic),
+            prompt_estimate: Some(PromptEstimate(100)),
+            routed: None,
+        };
+        spend.usage.feed(b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n");
+        spend.usage.feed(b"data: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":");
+        spend.usage.feed(b"30}}\n\n");
+        drop(spend);
+        assert_eq!(ledger.spent("s1", idle), (42, 72.0));
+
+        ledger.record("s1", 8, 0.0, idle);
+        assert_eq!(ledger.spent("s1", idle).0, 50);
+        assert_eq!(ledger.spent("s1", Expiry::Idle(Duration::ZERO)), (0, 0.0));
+        let frame = serde_json::json!({"usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 4}});
+        assert_eq!(stream_usage(&ApiType::Gemini, &frame), Some((3, 4)));
+        let frame = serde_json::json!({"type": "message_start", "message": {"usage": {"input_tokens": 5, "cache_read_input_tokens": 100, "cache_creation_input_tokens": 20, "output_tokens": 1}}});
+        assert_eq!(stream_usage(&ApiType::Anthropic, &frame), Some((125, 1)));
+    }
+
+    #[test]
+    fn test_streams_without_usage_are_estimated() {
+        let ledger = Arc::new(SessionLedger::default());
+        let idle = Expiry::Idle(Duration::from_secs(60));
+        let mut spend = StreamSpend {
+            ledger: ledger.clone(),
+            accounts: vec![Account { key: "s2".to_string(), expiry: idle, max_tokens: None, max_cost: None }],
+            pricing: None,
+            usage: StreamUsage::new(ApiType::OpenAI),
+            prompt_estimate: Some(PromptEstimate(100)),
+            routed: None,
+        };
+        spend.usage.feed(b"data: {\"choices\":[{\"delta\":{\"content\":\"0123456789abcdef\"}}]}\n\n");
+        spend.usage.feed(b"data: {\"choices\":[{\"delta\":{\"content\":\"0123\"},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n\n");
+        assert_eq!(spend.usage.reconciled(100), (100, 5, true));
+        drop(spend);
+        assert_eq!(ledger.spent("s2", idle).0, 105);
+    }
+
+    #[test]
+    fn test_cut_off_streams_count_at_least_their_text() {
+        let mut usage = StreamUsage::new(ApiType::Anthropic);
+        usage.feed(b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":50,\"output_tokens\":1}}}\n\n");
+        let delta = format!("{{\"type\":\"content_block_delta\",\"index\":0,\"delta\":{{\"type\":\"text_delta\",\"text\":\"{}\"}}}}", "x".repeat(40));
+        usage.feed(format!("event: content_block_delta\ndata: {}\n\n", delta).as_bytes());
+        // The client went away before message_delta: message_start's placeholder is not the output
+        assert_eq!(usage.reconciled(100), (50, 10, true));
+
+        usage.feed(b"event: message_delta\ndata: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":8}}\n\n");
+        assert_eq!(usage.reconciled(100), (50, 8, false));
+    }
+
+    #[test]
+    fn test_budget_periods_start_over_after_first_request() {
+        let now = Instant::now();
+        let minute_ago = now.checked_sub(Duration::from_secs(60)).unwrap();
+        // Started a minute ago and active just now
+        let spend = SessionSpend { tokens: 100, cost: 0.0, started: minute_ago, last_seen: now, expiry: Expiry::Idle(Duration::from_secs(30)) };
+        assert!(spend.live(Expiry::Idle(Duration::from_secs(30))));
+        assert!(!spend.live(Expiry::Period(Duration::from_secs(30))));
+        assert!(spend.live(Expiry::Period(Duration::from_secs(120))));
+
+        let ledger = SessionLedger::default();
+        let account =
+            Account { key: "team-a".to_string(), expiry: Expiry::Period(Duration::from_secs(60)), max_tokens: Some(100), max_cost: None };
+        charge(&ledger, std::slice::from_ref(&account), 60, 0.0);
+        assert_eq!(account.over(&ledger), None);
+        charge(&ledger, std::slice::from_ref(&account), 40, 0.0);
+        assert_eq!(account.over(&ledger).as_deref(), Some("100 of 100 tokens"));
+    }
+
+    #[test]
+    fn test_ledger_is_bounded() {
+        let ledger = SessionLedger::default();
+        let idle = Expiry::Idle(Duration::from_secs(60));
+        for i in 0..MAX_ENTRIES {
+            ledger.record(&format!("/s{}", i), 1, 0.0, idle);
+        }
+        ledger.record("/latest", 1, 0.0, idle);
+        assert_eq!(ledger.sessions.lock().unwrap().spends.len(), MAX_ENTRIES);
+        assert_eq!(ledger.spent("/s0", idle).0, 0);
+        assert_eq!(ledger.spent("/s1", idle).0, 1);
+        assert_eq!(ledger.spent("/latest", idle).0, 1);
+
+        // Expired entries go in the next sweep, whichever key is recorded
+        let ledger = SessionLedger::default();
+        ledger.record("/gone", 1, 0.0, Expiry::Idle(Duration::ZERO));
+        ledger.sessions.lock().unwrap().swept = None;
+        ledger.record("/kept", 1, 0.0, idle);
+        assert!(!ledger.sessions.lock().unwrap().spends.contains_key("/gone"));
+    }
+}
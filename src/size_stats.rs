@@ -0,0 +1,450 @@
+//! Request and response sizes per model and per client key, for finding the
+//! clients that send pathological prompts through the router. Per-model totals
+//! are exported on `/metrics`; `/admin/heavy-hitters` ranks models and keys and
+//! lists the largest single requests. How each response ended is counted too,
+//! so clients that cancel streams after paying for generation stand out.
+
+use crate::auth::{AppState, TenantId};
+use crate::config::VirtualKey;
+use crate::converters::response_wrapper::TokenUsage;
+use crate::queue_events::Routed;
+use crate::session_caps::{self, PromptEstimate, StreamUsage};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use http_body_util::{BodyExt, StreamBody};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Model that served a routed request, set on the response by the router.
+#[derive(Debug, Clone)]
+pub struct ServedModel(pub String);
+
+// Largest single requests kept for the report
+const LARGEST_KEPT: usize = 100;
+
+/// One finished request.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    pub key: String,
+    pub model: String,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Tokens are estimated because the stream reported no usage
+    pub estimated: bool,
+    pub outcome: Outcome,
+}
+
+/// How a routed response ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The whole body reached the client
+    Completed,
+    /// The client went away before the body was done
+    ClientAbort,
+    /// The upstream failed after the response had started
+    UpstreamAbort,
+    /// The router answered with an error status
+    Error,
+}
+
+impl Outcome {
+    const ALL: [Outcome; 4] = [Outcome::Completed, Outcome::ClientAbort, Outcome::UpstreamAbort, Outcome::Error];
+
+    fn label(self) -> &'static str {
+        match self {
+            Outcome::Completed => "completed",
+            Outcome::ClientAbort => "client_abort",
+            Outcome::UpstreamAbort => "upstream_abort",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+/// Running totals for a model or a key.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SizeTotals {
+    pub requests: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub max_request_bytes: u64,
+    // Requests whose tokens are estimated
+    pub estimated_requests: u64,
+    pub completed: u64,
+    pub client_aborts: u64,
+    pub upstream_aborts: u64,
+    // Output tokens generated for responses the client went away from
+    pub aborted_output_tokens: u64,
+}
+
+impl SizeTotals {
+    // `count_aborted` adds the tokens of a client abort to the usage totals
+    fn add(&mut self, sample: &Sample, count_aborted: bool) {
+        self.requests += 1;
+        self.request_bytes += sample.request_bytes;
+        self.response_bytes += sample.response_bytes;
+        if sample.outcome != Outcome::ClientAbort || count_aborted {
+            self.input_tokens += sample.input_tokens;
+            self.output_tokens += sample.output_tokens;
+        }
+        self.max_request_bytes = self.max_request_bytes.max(sample.request_bytes);
+        self.estimated_requests += sample.estimated as u64;
+        match sample.outcome {
+            Outcome::Completed => self.completed += 1,
+            Outcome::ClientAbort => {
+                self.client_aborts += 1;
+                self.aborted_output_tokens += sample.output_tokens;
+            }
+            Outcome::UpstreamAbort => self.upstream_aborts += 1,
+            Outcome::Error => {}
+        }
+    }
+
+    fn outcome_count(&self, outcome: Outcome) -> u64 {
+        match outcome {
+            Outcome::Completed => self.completed,
+            Outcome::ClientAbort => self.client_aborts,
+            Outcome::UpstreamAbort => self.upstream_aborts,
+            // Errors are the requests that did not end any other way
+            Outcome::Error => self.requests - self.completed - self.client_aborts - self.upstream_aborts,
+        }
+    }
+}
+
+/// What `/admin/heavy-hitters` ranks models and keys by.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeMetric {
+    #[default]
+    RequestBytes,
+    ResponseBytes,
+    InputTokens,
+    OutputTokens,
+    MaxRequestBytes,
+}
+
+impl SizeMetric {
+    fn of(self, totals: &SizeTotals) -> u64 {
+        match self {
+            SizeMetric::RequestBytes => totals.request_bytes,
+            SizeMetric::ResponseBytes => totals.response_bytes,
+            SizeMetric::InputTokens => totals.input_tokens,
+            SizeMetric::OutputTokens => totals.output_tokens,
+            SizeMetric::MaxRequestBytes => totals.max_request_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SizeStats {
+    ledger: Mutex<Ledger>,
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    models: HashMap<String, SizeTotals>,
+    keys: HashMap<String, SizeTotals>,
+    // Ordered by request size, smallest first
+    largest: Vec<Sample>,
+}
+
+impl SizeStats {
+    /// Add a finished request. Unless `count_aborted_usage`, tokens of a
+    /// client abort are left out of the key's usage, but not the model's.
+    pub fn record(&self, sample: Sample, count_aborted_usage: bool) {
+        let mut state = self.ledger.lock().unwrap();
+        state.models.entry(sample.model.clone()).or_default().add(&sample, true);
+        state.keys.entry(sample.key.clone()).or_default().add(&sample, count_aborted_usage);
+        let pos = state.largest.partition_point(|s| s.request_bytes < sample.request_bytes);
+        if state.largest.len() < LARGEST_KEPT {
+            state.largest.insert(pos, sample);
+        } else if pos > 0 {
+            state.largest.insert(pos, sample);
+            state.largest.remove(0);
+        }
+    }
+
+    /// The `top` models and keys by `by`, and the `top` largest requests.
+    pub fn report(&self, top: usize, by: SizeMetric) -> serde_json::Value {
+        let state = self.ledger.lock().unwrap();
+        let ranked = |totals: &HashMap<String, SizeTotals>, label: &str| {
+            let mut entries: Vec<_> = totals.iter().collect();
+            entries.sort_by(|a, b| by.of(b.1).cmp(&by.of(a.1)).then_with(|| a.0.cmp(b.0)));
+            entries
+                .into_iter()
+                .take(top)
+                .map(|(name, totals)| {
+                    let mut entry = json!(totals);
+                    entry[label] = json!(name);
+                    entry
+                })
+                .collect::<Vec<_>>()
+        };
+        let largest: Vec<_> = state.largest.iter().rev().take(top).collect();
+        json!({
+            "models": ranked(&state.models, "model"),
+            "keys": ranked(&state.keys, "key"),
+            "largest_requests": largest,
+        })
+    }
+
+    /// Per-model totals in Prometheus text format.
+    pub fn render(&self, out: &mut String) {
+        let state = self.ledger.lock().unwrap();
+        let mut models: Vec<_> = state.models.iter().collect();
+        models.sort_by(|a, b| a.0.cmp(b.0));
+        out.push_str("# TYPE llm_router_request_bytes_total counter\n");
+        for (model, totals) in &models {
+            let _ = writeln!(out, "llm_router_request_bytes_total{{model=\"{}\"}} {}", model, totals.request_bytes);
+        }
+        out.push_str("# TYPE llm_router_response_bytes_total counter\n");
+        for (model, totals) in &models {
+            let _ = writeln!(out, "llm_router_response_bytes_total{{model=\"{}\"}} {}", model, totals.response_bytes);
+        }
+        out.push_str("# TYPE llm_router_tokens_total counter\n");
+        for (model, totals) in &models {
+            let _ = writeln!(out, "llm_router_tokens_total{{model=\"{}\",direction=\"input\"}} {}", model, totals.input_tokens);
+            let _ = writeln!(out, "llm_router_tokens_total{{model=\"{}\",direction=\"output\"}} {}", model, totals.output_tokens);
+        }
+        out.push_str("# TYPE llm_router_estimated_usage_total counter\n");
+        for (model, totals) in &models {
+            let _ = writeln!(out, "llm_router_estimated_usage_total{{model=\"{}\"}} {}", model, totals.estimated_requests);
+        }
+        out.push_str("# TYPE llm_router_responses_total counter\n");
+        for (model, totals) in &models {
+            for outcome in Outcome::ALL {
+                let count = totals.outcome_count(outcome);
+                let _ = writeln!(out, "llm_router_responses_total{{model=\"{}\",outcome=\"{}\"}} {}", model, outcome.label(), count);
+            }
+        }
+        out.push_str("# TYPE llm_router_aborted_output_tokens_total counter\n");
+        for (model, totals) in &models {
+            let _ = writeln!(out, "llm_router_aborted_output_tokens_total{{model=\"{}\"}} {}", model, totals.aborted_output_tokens);
+        }
+    }
+}
+
+// Who sent a request, without giving away their key
+fn client_label(virtual_key: Option<&VirtualKey>, tenant: Option<&TenantId>) -> String {
+    match (virtual_key, tenant) {
+        (Some(vk), _) => vk.label(),
+        (None, Some(TenantId(name))) => format!("tenant:{}", name),
+        (None, None) => "-".to_string(),
+    }
+}
+
+/// Count the bytes of chat requests and their responses, and the tokens
+/// reported for them. Responses are recorded once their body is done.
+pub async fn record(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let Some(api_type) = session_caps::client_api(req.uri().path()) else { return next.run(req).await };
+    let tenant = req.extensions().get::<TenantId>().cloned();
+    let key = client_label(req.extensions().get::<VirtualKey>(), tenant.as_ref());
+    let count_aborted_usage = state
+        .scoped(tenant.as_ref())
+        .model_manager
+        .read()
+        .await
+        .get_config()
+        .router_settings
+        .count_aborted_usage;
+
+    let request_bytes = Arc::new(AtomicU64::new(0));
+    let (parts, body) = req.into_parts();
+    let counter = request_bytes.clone();
+    let body = body.into_data_stream().map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            counter.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        }
+        chunk
+    });
+    let response = next.run(Request::from_parts(parts, Body::from_stream(body))).await;
+
+    // Requests that were never routed have no model to count against
+    let Some(ServedModel(model)) = response.extensions().get::<ServedModel>().cloned() else { return response };
+    let usage = response.extensions().get::<TokenUsage>().copied();
+    let succeeded = response.status().is_success();
+    let is_stream = succeeded
+        && response
+            .headers()
+            .get(axum::http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+    let tally = Tally {
+        stats: state.sizes.clone(),
+        sample: Sample {
+            key,
+            model: match tenant {
+                Some(TenantId(name)) => format!("{}/{}", name, model),
+                None => model,
+            },
+            request_bytes: request_bytes.load(Ordering::Relaxed),
+            response_bytes: 0,
+            input_tokens: usage.map_or(0, |u| u.input_tokens),
+            output_tokens: usage.map_or(0, |u| u.output_tokens),
+            estimated: false,
+            outcome: if succeeded { Outcome::ClientAbort } else { Outcome::Error },
+        },
+        stream_usage: (usage.is_none() && is_stream).then(|| StreamUsage::new(api_type)),
+        prompt_estimate: response.extensions().get::<PromptEstimate>().copied(),
+        count_aborted_usage,
+        routed: response.extensions().get::<Routed>().cloned(),
+    };
+    let (parts, body) = response.into_parts();
+    // The tally goes when the body does: after its last chunk, or earlier when
+    // the client disconnects and the server drops it
+    let body = futures::stream::unfold((body, tally), |(mut body, mut tally)| async move {
+        let frame = body.frame().await;
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(bytes) = frame.data_ref() {
+                    tally.feed(bytes);
+                }
+            }
+            Some(Err(_)) => tally.end(Outcome::UpstreamAbort),
+            None => tally.end(Outcome::Completed),
+        }
+        frame.map(|frame| (frame, (body, tally)))
+    });
+    Response::from_parts(parts, Body::new(StreamBody::new(body)))
+}
+
+// A response on its way out, recorded when its body is dropped, whether it
+// completed or the client went away
+struct Tally {
+    stats: Arc<SizeStats>,
+    sample: Sample,
+    // Streamed responses report usage in their frames
+    stream_usage: Option<StreamUsage>,
+    // For estimating usage the stream never reported
+    prompt_estimate: Option<PromptEstimate>,
+    count_aborted_usage: bool,
+    // Set when the request was answered before it was routed
+    routed: Option<Routed>,
+}
+
+impl Tally {
+    fn feed(&mut self, bytes: &[u8]) {
+        self.sample.response_bytes += bytes.len() as u64;
+        if let Some(usage) = &mut self.stream_usage {
+            usage.feed(bytes);
+        }
+    }
+
+    // Until the body ends, a response counts as abandoned by the client
+    fn end(&mut self, outcome: Outcome) {
+        if self.sample.outcome == Outcome::ClientAbort {
+            self.sample.outcome = outcome;
+        }
+    }
+}
+
+impl Drop for Tally {
+    fn drop(&mut self) {
+        if let Some(usage) = self.routed.as_ref().and_then(Routed::get::<TokenUsage>) {
+            self.stream_usage = None;
+            (self.sample.input_tokens, self.sample.output_tokens) = (usage.input_tokens, usage.output_tokens);
+        }
+        if let Some(usage) = &self.stream_usage {
+            let prompt_estimate = self.prompt_estimate.or_else(|| self.routed.as_ref().and_then(Routed::get::<PromptEstimate>));
+            (self.sample.input_tokens, self.sample.output_tokens, self.sample.estimated) =
+                usage.reconciled(prompt_estimate.map_or(0, |e| e.0));
+            // An error event means the upstream gave up, however the stream closed after it
+            if usage.errored {
+                self.sample.outcome = Outcome::UpstreamAbort;
+            }
+        }
+        self.stats.record(self.sample.clone(), self.count_aborted_usage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(key: &str, model: &str, request_bytes: u64) -> Sample {
+        Sample {
+            key: key.to_string(),
+            model: model.to_string(),
+            request_bytes,
+            response_bytes: 10,
+            input_tokens: request_bytes / 4,
+            output_tokens: 2,
+            estimated: false,
+            outcome: Outcome::Completed,
+        }
+    }
+
+    #[test]
+    fn test_report_ranks_heavy_hitters() {
+        let stats = SizeStats::default();
+        for bytes in 1..=LARGEST_KEPT as u64 + 5 {
+            stats.record(sample("small", "m1", bytes), true);
+        }
+        stats.record(sample("huge", "m2", 500_000), true);
+        stats.record(sample("huge", "m1", 400_000), true);
+
+        let report = stats.report(2, SizeMetric::MaxRequestBytes);
+        assert_eq!(report["keys"][0]["key"], "huge");
+        assert_eq!(report["keys"][0]["requests"], 2);
+        assert_eq!(report["keys"][0]["request_bytes"], 900_000);
+        assert_eq!(report["models"][0]["model"], "m2");
+        let largest = report["largest_requests"].as_array().unwrap();
+        assert_eq!(largest.len(), 2);
+        assert_eq!(largest[0]["request_bytes"], 500_000);
+        assert_eq!(largest[1]["model"], "m1");
+
+        let report = stats.report(1, SizeMetric::ResponseBytes);
+        assert_eq!(report["models"][0]["model"], "m1");
+        assert_eq!(stats.ledger.lock().unwrap().largest.len(), LARGEST_KEPT);
+
+        let mut out = String::new();
+        stats.render(&mut out);
+        assert!(out.contains("llm_router_request_bytes_total{model=\"m2\"} 500000"));
+        assert!(out.contains("llm_router_tokens_total{model=\"m2\",direction=\"input\"} 125000"));
+    }
+
+    #[test]
+    fn test_outcomes_and_aborted_usage() {
+        let stats = Arc::new(SizeStats::default());
+        let aborted = Sample { outcome: Outcome::ClientAbort, ..sample("k", "m1", 40) };
+        stats.record(aborted, false);
+        stats.record(Sample { outcome: Outcome::Error, output_tokens: 0, ..sample("k", "m1", 40) }, false);
+        let key = stats.ledger.lock().unwrap().keys["k"];
+        assert_eq!((key.output_tokens, key.aborted_output_tokens, key.client_aborts), (0, 2, 1));
+        assert_eq!(stats.ledger.lock().unwrap().models["m1"].output_tokens, 2);
+
+        // An upstream error event outweighs the clean end of the stream
+        let mut tally = Tally {
+            stats: stats.clone(),
+            sample: Sample { outcome: Outcome::ClientAbort, ..sample("k", "m1", 40) },
+            stream_usage: Some(StreamUsage::new(crate::config::ApiType::OpenAI)),
+            prompt_estimate: None,
+            count_aborted_usage: false,
+            routed: None,
+        };
+        tally.feed(b"data: {\"error\":{\"message\":\"overloaded\"}}\n\ndata: [DONE]\n\n");
+        tally.end(Outcome::Completed);
+        drop(tally);
+
+        let mut out = String::new();
+        stats.render(&mut out);
+        for outcome in ["client_abort", "upstream_abort", "error"] {
+            assert!(out.contains(&format!("llm_router_responses_total{{model=\"m1\",outcome=\"{}\"}} 1", outcome)));
+        }
+        assert!(out.contains("llm_router_responses_total{model=\"m1\",outcome=\"completed\"} 0"));
+        assert!(out.contains("llm_router_aborted_output_tokens_total{model=\"m1\"} 2"));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::config::{Config, RouterSettings};
+use crate::metrics::api_name;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// What the router is about to serve, printed once at startup so deployment
+/// tooling can check it (`--startup-report json`).
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupReport {
+    pub version: &'static str,
+    // Model count per api_type, root config only
+    pub models: BTreeMap<&'static str, usize>,
+    pub groups: Vec<GroupSummary>,
+    pub virtual_keys: usize,
+    // Tenant keys and tenant virtual keys
+    pub tenant_keys: usize,
+    pub tenants: Vec<String>,
+    pub listeners: Vec<String>,
+    pub features: Vec<&'static str>,
+    pub warnings: Vec<String>,
+    // Config keys this version ignored, as paths
+    pub unknown_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupSummary {
+    pub name: String,
+    pub members: Vec<(String, u32)>,
+}
+
+impl StartupReport {
+    pub fn new(config: &Config, listeners: Vec<String>) -> Self {
+        let mut models = BTreeMap::new();
+        for mc in &config.model_list {
+            *models.entry(api_name(&mc.llm_params.api_type)).or_insert(0) += 1;
+        }
+        let groups = config
+            .router_settings
+            .model_groups
+            .iter()
+            .map(|g| GroupSummary {
+                name: g.name.clone(),
+                members: g.models.iter().map(|m| (m.name.clone(), m.weight)).collect(),
+            })
+            .collect();
+
+        let features = features(&config.router_settings);
+
+        let mut warnings = Self::warnings(config, "");
+        for tenant in &config.tenants {
+            warnings.extend(Self::warnings(&tenant.config, &format!("tenant '{}': ", tenant.name)));
+        }
+
+        StartupReport {
+            version: env!("CARGO_PKG_VERSION"),
+            models,
+            groups,
+            virtual_keys: config.virtual_keys.len(),
+            tenant_keys: config.tenants.iter().map(|t| t.keys.len() + t.config.virtual_keys.len()).sum(),
+            tenants: config.tenants.iter().map(|t| t.name.clone()).collect(),
+            listeners,
+            features,
+            warnings,
+            unknown_keys: config.unknown_keys.clone(),
+        }
+    }
+
+    // Things that load fine but are probably mistakes
+    fn warnings(config: &Config, prefix: &str) -> Vec<String> {
+        let models: HashSet<&str> = config.model_list.iter().map(|m| m.model_name.as_str()).collect();
+        let groups: HashSet<&str> = config.router_settings.model_groups.iter().map(|g| g.name.as_str()).collect();
+        let referenced: HashSet<&str> = config
+            .router_settings
+            .model_groups
+            .iter()
+            .flat_map(|g| g.models.iter().map(|m| m.name.as_str()))
+            .collect();
+
+        let mut warnings = Vec::new();
+        for group in &config.router_settings.model_groups {
+            for member in &group.models {
+                if !models.contains(member.name.as_str()) && !groups.contains(member.name.as_str()) {
+                    warnings.push(format!("{}group '{}' lists unknown model '{}'", prefix, group.name, member.name));
+                }
+            }
+        }
+        for model in &config.model_list {
+            if !referenced.contains(model.model_name.as_str()) {
+                warnings.push(format!("{}model '{}' is not in any group", prefix, model.model_name));
+            }
+        }
+        warnings
+    }
+
+    /// Human-readable form, one fact per line.
+    pub fn render_text(&self) -> String {
+        let mut out = format!("llm-router {} startup report\n", self.version);
+        let models: Vec<String> = self.models.iter().map(|(api, n)| format!("{} {}", n, api)).collect();
+        out.push_str(&format!("  models: {}\n", if models.is_empty() { "none".to_string() } else { models.join(", ") }));
+        for group in &self.groups {
+            let members: Vec<String> = group.members.iter().map(|(name, weight)| format!("{}={}", name, weight)).collect();
+            out.push_str(&format!("  group {}: {}\n", group.name, members.join(", ")));
+        }
+        out.push_str(&format!("  keys: {} virtual, {} tenant\n", self.virtual_keys, self.tenant_keys));
+        if !self.tenants.is_empty() {
+            out.push_str(&format!("  tenants: {}\n", self.tenants.join(", ")));
+        }
+        out.push_str(&format!("  listeners: {}\n", self.listeners.join(", ")));
+        out.push_str(&format!(
+            "  features: {}\n",
+            if self.features.is_empty() { "none".to_string() } else { self.features.join(", ") }
+        ));
+        for warning in &self.warnings {
+            out.push_str(&format!("  warning: {}\n", warning));
+        }
+        for key in &self.unknown_keys {
+            out.push_str(&format!("  warning: unknown key {} is ignored\n", key));
+        }
+        out
+    }
+}
+
+/// Optional router behaviour switched on in `settings`.
+pub fn features(settings: &RouterSettings) -> Vec<&'static str> {
+    [
+        ("routing_headers", settings.routing_headers),
+        ("default_model", settings.default_model.is_some()),
+        ("response_store", settings.response_store.enabled),
+        ("health_state", settings.health_state.path.is_some()),
+        ("repair_tool_arguments", settings.repair_tool_arguments),
+        ("refusal_fallback", settings.refusal_fallback.enabled),
+        ("image_fetch", settings.image_fetch.enabled),
+        ("mcp", !settings.mcp.servers.is_empty()),
+        ("web_search", settings.web_search.enabled),
+        ("session_caps", settings.session_caps.enabled()),
+        ("strategy_rules", !settings.strategy_rules.is_empty()),
+        ("output_validation", settings.output_validation.enabled),
+        ("retry_queue", settings.retry_queue.enabled),
+        ("ttfb_health", settings.ttfb_health.enabled),
+        ("stream_failover", settings.stream_failover.enabled),
+        ("reload_probes", settings.reload_probes.enabled),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_counts_and_warnings() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+model_list:
+  - model_name: a
+    llm_params: {api_type: openai, model: x, api_base: "http://localhost", api_key: k}
+  - model_name: b
+    llm_params: {api_type: anthropic, model: y, api_base: "http://localhost", api_key: k}
+  - model_name: c
+    llm_params: {api_type: openai, model: z, api_base: "http://localhost", api_key: k}
+router_settings:
+  strategy: roundrobin
+  routing_headers: true
+  model_groups:
+    - {name: g, models: [{name: a, weight: 0}, {name: missing, weight: 0}]}
+    - {name: h, models: [{name: g}, {name: b}]}
+tenants:
+  - name: t
+    keys: [k1, k2]
+    virtual_keys: [{key: k3}]
+    model_list: []
+    router_settings: {strategy: roundrobin, model_groups: []}
+"#,
+        )
+        .unwrap();
+        let report = StartupReport::new(&config, vec!["http://0.0.0.0:8000".to_string()]);
+        assert_eq!(report.models.get("openai"), Some(&2));
+        assert_eq!(report.models.get("anthropic"), Some(&1));
+        assert_eq!(report.groups[0].members, vec![("a".to_string(), 0), ("missing".to_string(), 0)]);
+        assert_eq!(report.features, vec!["routing_headers"]);
+        assert_eq!(
+            report.warnings,
+            vec![
+                "group 'g' lists unknown model 'missing'",
+                "model 'c' is not in any group",
+            ]
+        );
+        assert!(report.render_text().contains("  group h: g=100, b=100\n"));
+        assert!(report.render_text().contains("  keys: 0 virtual, 3 tenant\n"));
+    }
+}
@@ -0,0 +1,166 @@
+//! A streamed answer may only go to another upstream while the client has
+//! seen nothing of it. Waiting for the upstream's first content before the
+//! response headers are sent puts every failure that can still be retried in
+//! front of the client; once content has arrived the stream is committed, and
+//! a later failure ends it with an error event rather than a second
+//! `message_start`.
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use serde_json::Value;
+use std::fmt::Display;
+
+// Past this much buffered without a complete frame the body is not SSE, and
+// whatever arrived counts as the start of the answer
+const MAX_UNFRAMED: usize = 64 * 1024;
+
+/// How an upstream stream began.
+pub enum FirstChunk<S> {
+    /// Content arrived; the stream yields everything read so far again, then the rest.
+    Started(S),
+    /// The stream failed, sent an error event or ended before any content.
+    Failed(String),
+}
+
+/// Wait for the first frame of `upstream` that carries content. Comments,
+/// pings and empty chunks are held back with it, and a leading error event
+/// counts as a failure, so none of them commit the stream.
+pub async fn first_chunk<E: Display>(
+    upstream: impl Stream<Item = Result<Bytes, E>>,
+) -> FirstChunk<impl Stream<Item = Result<Bytes, E>>> {
+    let mut upstream = Box::pin(upstream);
+    let mut held: Vec<Bytes> = Vec::new();
+    let mut pending: Vec<u8> = Vec::new();
+    loop {
+        let bytes = match upstream.next().await {
+            None => return FirstChunk::Failed("upstream closed the stream before sending anything".to_string()),
+            Some(Err(e)) => return FirstChunk::Failed(format!("upstream streaming error before the first byte: {}", e)),
+            Some(Ok(bytes)) => bytes,
+        };
+        pending.extend_from_slice(&bytes);
+        held.push(bytes);
+        let mut started = pending.len() > MAX_UNFRAMED;
+        while let Some((end, len)) = frame_end(&pending) {
+            let frame: Vec<u8> = pending.drain(..end + len).collect();
+            match classify(&String::from_utf8_lossy(&frame[..end])) {
+                Frame::Idle => {}
+                Frame::Error(message) => {
+                    return FirstChunk::Failed(format!("upstream sent an error before any content: {}", message));
+                }
+                Frame::Content => {
+                    started = true;
+                    break;
+                }
+            }
+        }
+        if started {
+            return FirstChunk::Started(stream::iter(held.into_iter().map(Ok)).chain(upstream));
+        }
+    }
+}
+
+enum Frame {
+    // Comments, pings and blank frames
+    Idle,
+    Error(String),
+    Content,
+}
+
+// Position and length of the first blank line ending an SSE frame
+pub(crate) fn frame_end(buf: &[u8]) -> Option<(usize, usize)> {
+    let lf = buf.windows(2).position(|w| w == b"\n\n").map(|i| (i, 2));
+    let crlf = buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| (i, 4));
+    match (lf, crlf) {
+        (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn classify(frame: &str) -> Frame {
+    let mut event = "";
+    let mut data = String::new();
+    for line in frame.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            event = value.trim();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push_str(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    match event {
+        "ping" => return Frame::Idle,
+        "error" => return Frame::Error(data),
+        _ => {}
+    }
+    // A frame without data dispatches nothing
+    if data.trim().is_empty() {
+        return Frame::Idle;
+    }
+    // OpenAI and Gemini report failures as a data frame with an `error` object
+    if data.contains("\"error\"")
+        && let Ok(value) = serde_json::from_str::<Value>(&data)
+        && let Some(error) = value.get("error")
+    {
+        return Frame::Error(error.to_string());
+    }
+    Frame::Content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstream(items: Vec<Result<&'static str, &'static str>>) -> impl Stream<Item = Result<Bytes, &'static str>> {
+        stream::iter(items.into_iter().map(|item| item.map(|s| Bytes::from_static(s.as_bytes()))))
+    }
+
+    #[tokio::test]
+    async fn test_failures_before_the_first_byte_can_be_retried() {
+        let FirstChunk::Failed(reason) = first_chunk(upstream(vec![Ok(""), Err("reset")])).await else {
+            panic!("an upstream that failed before sending anything must be retryable");
+        };
+        assert!(reason.contains("reset"));
+        assert!(matches!(first_chunk(upstream(vec![])).await, FirstChunk::Failed(_)));
+    }
+
+    #[tokio::test]
+    async fn test_started_streams_replay_the_first_chunk_once() {
+        let start = "event: message_start\ndata: {}\n\n";
+        let FirstChunk::Started(stream) = first_chunk(upstream(vec![Ok(start), Err("reset"), Ok("late")])).await else {
+            panic!("the stream sent a chunk");
+        };
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(items, vec![Ok(Bytes::from_static(start.as_bytes())), Err("reset"), Ok(Bytes::from_static(b"late"))]);
+    }
+
+    #[tokio::test]
+    async fn test_comments_and_pings_do_not_commit() {
+        let items = vec![Ok(": keep-alive\n\n"), Ok("event: ping\r\ndata: {\"type\": \"ping\"}\r\n\r\n"), Err("reset")];
+        let FirstChunk::Failed(reason) = first_chunk(upstream(items)).await else {
+            panic!("comments and pings are not content");
+        };
+        assert!(reason.contains("reset"));
+
+        // A content frame split across chunks commits once complete, replaying all of it
+        let items = vec![Ok(": keep-alive\n\ndata: {\"choices\""), Ok(": []}\n\n"), Ok("data: [DONE]\n\n")];
+        let FirstChunk::Started(stream) = first_chunk(upstream(items)).await else {
+            panic!("the stream sent content");
+        };
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(items.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_leading_error_events_can_be_retried() {
+        let anthropic = "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\"}}\n\n";
+        let FirstChunk::Failed(reason) = first_chunk(upstream(vec![Ok(anthropic)])).await else {
+            panic!("an Anthropic error event is not content");
+        };
+        assert!(reason.contains("overloaded_error"));
+
+        let openai = "data: {\"error\":{\"message\":\"server busy\"}}\n\n";
+        let FirstChunk::Failed(reason) = first_chunk(upstream(vec![Ok(": hi\n\n"), Ok(openai)])).await else {
+            panic!("an OpenAI error frame is not content");
+        };
+        assert!(reason.contains("server busy"));
+    }
+}
@@ -0,0 +1,186 @@
+//! Output token rate shaping for streamed answers. Provider chunks arrive in
+//! bursts; paced streams release SSE events at a steady tokens-per-second rate,
+//! which suits demos and downstream text-to-speech.
+
+use axum::{body::Body, http::header::CONTENT_TYPE, response::Response};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+// Fields carrying generated text or tool arguments in the three client formats
+const TEXT_FIELDS: &[&str] = &["content", "text", "thinking", "reasoning_content", "partial_json", "arguments"];
+
+/// Release the events of a streamed response at about `tokens_per_sec`.
+/// Other responses are returned unchanged.
+pub fn pace(response: Response, tokens_per_sec: f64) -> Response {
+    let is_stream = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_stream {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    Response::from_parts(parts, Body::from_stream(paced(body.into_data_stream(), tokens_per_sec)))
+}
+
+struct Pacer<S> {
+    inner: S,
+    // Bytes of an event not yet complete
+    pending: Vec<u8>,
+    events: VecDeque<Bytes>,
+    next_at: Instant,
+    tokens_per_sec: f64,
+    done: bool,
+}
+
+fn paced<S, E>(inner: S, tokens_per_sec: f64) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    let pacer = Pacer { inner, pending: Vec::new(), events: VecDeque::new(), next_at: Instant::now(), tokens_per_sec, done: false };
+    futures::stream::unfold(pacer, |mut pacer| async move {
+        loop {
+            if let Some(event) = pacer.events.pop_front() {
+                let tokens = event_tokens(&event);
+                if tokens > 0 {
+                    tokio::time::sleep_until(pacer.next_at).await;
+                    let spacing = Duration::from_secs_f64(tokens as f64 / pacer.tokens_per_sec);
+                    pacer.next_at = pacer.next_at.max(Instant::now()) + spacing;
+                }
+                return Some((Ok(event), pacer));
+            }
+            if pacer.done {
+                return None;
+            }
+            match pacer.inner.next().await {
+                Some(Ok(bytes)) => {
+                    pacer.pending.extend_from_slice(&bytes);
+                    while let Some(end) = event_end(&pacer.pending) {
+                        let event: Vec<u8> = pacer.pending.drain(..end).collect();
+                        pacer.events.push_back(Bytes::from(event));
+                    }
+                }
+                Some(Err(e)) => return Some((Err(e), pacer)),
+                None => {
+                    pacer.done = true;
+                    if !pacer.pending.is_empty() {
+                        pacer.events.push_back(Bytes::from(std::mem::take(&mut pacer.pending)));
+                    }
+                }
+            }
+        }
+    })
+}
+
+// End of the first complete event in `buf`, past its blank line; SSE lines
+// may end in `\n` or `\r\n`
+fn event_end(buf: &[u8]) -> Option<usize> {
+    let lf = buf.windows(2).position(|w| w == b"\n\n").map(|i| i + 2);
+    let crlf = buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4);
+    match (lf, crlf) {
+        (Some(lf), Some(crlf)) => Some(lf.min(crlf)),
+        (end, None) | (None, end) => end,
+    }
+}
+
+// Rough output token count (~4 bytes per token) of the text in one SSE event
+fn event_tokens(event: &[u8]) -> u64 {
+    let text = String::from_utf8_lossy(event);
+    let bytes: usize = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+        .map(|value| text_len(&value, false))
+        .sum();
+    (bytes as u64).div_ceil(4)
+}
+
+/// Bytes of generated text or tool arguments in one frame's JSON.
+pub(crate) fn text_len(value: &Value, in_text_field: bool) -> usize {
+    match value {
+        Value::String(s) if in_text_field => s.len(),
+        Value::Object(obj) => obj.iter().map(|(k, v)| text_len(v, TEXT_FIELDS.contains(&k.as_str()))).sum(),
+        Value::Array(items) => items.iter().map(|v| text_len(v, in_text_field)).sum(),
+        _ => 0,
+    }
+}
+
+/// `text_len` without parsing: sums the raw (still escaped) string values of
+/// text fields in one frame's JSON. Close enough for estimates made on every
+/// frame of every stream.
+pub(crate) fn raw_text_len(data: &str) -> usize {
+    let bytes = data.as_bytes();
+    let mut total = 0;
+    let mut pos = 0;
+    while let Some(offset) = data[pos..].find("\":") {
+        let colon = pos + offset + 1;
+        let key_start = data[..pos + offset].rfind('"').map_or(pos + offset, |i| i + 1);
+        pos = colon + 1;
+        if !TEXT_FIELDS.contains(&&data[key_start..colon - 1]) {
+            continue;
+        }
+        let value = data[pos..].trim_start();
+        let Some(value) = value.strip_prefix('"') else { continue };
+        let start = bytes.len() - value.len();
+        let mut end = start;
+        while end < bytes.len() && bytes[end] != b'"' {
+            end += if bytes[end] == b'\\' { 2 } else { 1 };
+        }
+        total += end.min(bytes.len()) - start;
+        pos = (end + 1).min(bytes.len());
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_events_are_spaced_by_tokens() {
+        // 8 bytes of text = 2 tokens each, in one upstream burst split mid-event
+        let event = r#"data: {"choices":[{"delta":{"content":"abcdefgh"}}]}"#;
+        let burst = format!("{0}\n\n{0}\n\n{0}", event);
+        let (head, tail) = burst.split_at(30);
+        let chunks = vec![Ok::<_, std::io::Error>(Bytes::from(head.to_string())), Ok(Bytes::from(format!("{}\n\ndata: [DONE]\n\n", tail)))];
+
+        let start = Instant::now();
+        let mut stream = Box::pin(paced(futures::stream::iter(chunks), 100.0));
+        let mut arrivals = Vec::new();
+        while let Some(event) = stream.next().await {
+            arrivals.push((event.unwrap(), start.elapsed().as_millis()));
+        }
+        // Two tokens at 100/s is 20ms apart; [DONE] carries none and follows at once
+        let times: Vec<u128> = arrivals.iter().map(|(_, ms)| *ms).collect();
+        assert_eq!(times, [0, 20, 40, 40]);
+        assert_eq!(arrivals[0].0, Bytes::from(format!("{}\n\n", event)));
+        assert_eq!(arrivals[3].0, Bytes::from("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_crlf_events_are_split() {
+        let event = r#"data: {"choices":[{"delta":{"content":"abcdefgh"}}]}"#;
+        let chunks = vec![Ok::<_, std::io::Error>(Bytes::from(format!("{0}\r\n\r\n{0}\r\n\r\n", event)))];
+        let events: Vec<Bytes> = paced(futures::stream::iter(chunks), 100.0).map(Result::unwrap).collect().await;
+        assert_eq!(events, vec![Bytes::from(format!("{}\r\n\r\n", event)); 2]);
+    }
+
+    #[test]
+    fn test_raw_text_len_matches_parsed_text() {
+        for data in [
+            r#"{"choices":[{"delta":{"content":"abc\"d"}}]}"#,
+            r#"{"type":"content_block_delta","delta":{"type":"input_json_delta","partial_json":"{\"a\": 1}"}}"#,
+            r#"{"candidates":[{"content":{"parts":[{"text": "hello"}],"role":"model"}}]}"#,
+            r#"{"usage":{"prompt_tokens":3}}"#,
+        ] {
+            let parsed = text_len(&serde_json::from_str(data).unwrap(), false);
+            // Escapes count as written, so raw lengths may only run over
+            assert!((parsed..=parsed + 4).contains(&raw_text_len(data)), "{}", data);
+        }
+    }
+}
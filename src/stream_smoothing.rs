@@ -0,0 +1,155 @@
+use crate::config::ApiType;
+use crate::converters::response_handler::SseFrame;
+use crate::stream_pacing;
+use serde_json::Value;
+
+/// Split one outgoing frame whose text delta is longer than `max_chars` into
+/// several frames of the same kind. Only plain text deltas are split: OpenAI
+/// `delta.content`, Anthropic `text_delta` and a Gemini candidate with a single
+/// text part. Role and other delta fields stay on the first piece; finish
+/// reasons and usage move to the last.
+pub fn split_frame(frame: SseFrame, api_type: &ApiType, max_chars: usize) -> Vec<SseFrame> {
+    let (event, data) = &frame;
+    // Escaped text is never shorter than the text, so most frames need no parse
+    if stream_pacing::raw_text_len(data) <= max_chars {
+        return vec![frame];
+    }
+    let Ok(value) = serde_json::from_str::<Value>(data) else {
+        return vec![frame];
+    };
+    let pieces = match text_delta(&value, api_type) {
+        Some(text) if text.chars().count() > max_chars => split_text(text, max_chars),
+        _ => return vec![frame],
+    };
+    let last = pieces.len() - 1;
+    pieces
+        .into_iter()
+        .enumerate()
+        .map(|(i, piece)| {
+            let mut value = value.clone();
+            set_piece(&mut value, api_type, piece, i == 0, i == last);
+            (event.clone(), value.to_string())
+        })
+        .collect()
+}
+
+fn text_delta<'a>(value: &'a Value, api_type: &ApiType) -> Option<&'a str> {
+    match api_type {
+        ApiType::OpenAI => {
+            let choices = value.get("choices")?.as_array()?;
+            if choices.len() != 1 {
+                return None;
+            }
+            choices[0].get("delta")?.get("content")?.as_str()
+        }
+        ApiType::Anthropic => {
+            let delta = value.get("delta")?;
+            if value.get("type")?.as_str()? != "content_block_delta" || delta.get("type")?.as_str()? != "text_delta" {
+                return None;
+            }
+            delta.get("text")?.as_str()
+        }
+        ApiType::Gemini => {
+            let candidates = value.get("candidates")?.as_array()?;
+            let parts = candidates.first()?.get("content")?.get("parts")?.as_array()?;
+            if candidates.len() != 1 || parts.len() != 1 {
+                return None;
+            }
+            parts[0].get("text")?.as_str()
+        }
+    }
+}
+
+fn set_piece(value: &mut Value, api_type: &ApiType, piece: String, first: bool, last: bool) {
+    match api_type {
+        ApiType::OpenAI => {
+            let choice = &mut value["choices"][0];
+            if !first {
+                choice["delta"] = serde_json::json!({});
+            }
+            choice["delta"]["content"] = Value::String(piece);
+            if !last {
+                choice["finish_reason"] = Value::Null;
+                if let Some(obj) = value.as_object_mut() {
+                    obj.remove("usage");
+                }
+            }
+        }
+        ApiType::Anthropic => value["delta"]["text"] = Value::String(piece),
+        ApiType::Gemini => {
+            let candidate = &mut value["candidates"][0];
+            candidate["content"]["parts"][0]["text"] = Value::String(piece);
+            if !last {
+                if let Some(obj) = candidate.as_object_mut() {
+                    obj.remove("finishReason");
+                }
+                if let Some(obj) = value.as_object_mut() {
+                    obj.remove("usageMetadata");
+                }
+            }
+        }
+    }
+}
+
+/// Cut `text` into pieces of at most `max_chars` characters, breaking after
+/// whitespace when the piece would otherwise end mid-word.
+fn split_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.chars().count() > max_chars {
+        let window_end = rest.char_indices().nth(max_chars).map(|(i, _)| i).unwrap_or(rest.len());
+        let cut = rest[..window_end]
+            .char_indices()
+            .filter(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+            .next_back()
+            .unwrap_or(window_end);
+        pieces.push(rest[..cut].to_string());
+        rest = &rest[cut..];
+    }
+    if !rest.is_empty() {
+        pieces.push(rest.to_string());
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_large_deltas_are_split() {
+        assert_eq!(split_text("hello big world", 6), vec!["hello ", "big ", "world"]);
+        assert_eq!(split_text("日本語テキスト", 3), vec!["日本語", "テキス", "ト"]);
+
+        let chunk = json!({
+            "id": "c1", "object": "chat.completion.chunk", "model": "m",
+            "choices": [{"index": 0, "delta": {"role": "assistant", "content": "one two three"}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 3, "total_tokens": 4}
+        });
+        let frames = split_frame((None, chunk.to_string()), &ApiType::OpenAI, 5);
+        let values: Vec<Value> = frames.iter().map(|(_, d)| serde_json::from_str(d).unwrap()).collect();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0]["choices"][0]["delta"], json!({"role": "assistant", "content": "one "}));
+        assert_eq!(values[0]["choices"][0]["finish_reason"], Value::Null);
+        assert!(values[0].get("usage").is_none());
+        assert_eq!(values[2]["choices"][0]["delta"], json!({"content": "three"}));
+        assert_eq!(values[2]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(values[2]["usage"]["total_tokens"], 4);
+
+        let event = json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "abcdef"}});
+        let frames = split_frame((Some("content_block_delta".to_string()), event.to_string()), &ApiType::Anthropic, 4);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].0.as_deref(), Some("content_block_delta"));
+
+        let chunk = json!({"candidates": [{"content": {"role": "model", "parts": [{"text": "abcdef"}]}, "finishReason": "STOP"}]});
+        let frames = split_frame((None, chunk.to_string()), &ApiType::Gemini, 4);
+        let first: Value = serde_json::from_str(&frames[0].1).unwrap();
+        assert!(first["candidates"][0].get("finishReason").is_none());
+
+        // Short deltas and other frames pass through untouched
+        let frame = (None, "[DONE]".to_string());
+        assert_eq!(split_frame(frame.clone(), &ApiType::OpenAI, 4), vec![frame]);
+    }
+}
@@ -0,0 +1,92 @@
+//! Wall-clock time and generated ids for converted responses. Tests freeze both
+//! on their thread with [`freeze`] so converted bodies are reproducible.
+
+use std::cell::RefCell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of `created` timestamps and synthesized response ids.
+pub trait Clock {
+    fn now_secs(&self) -> u64;
+    fn new_id(&self, prefix: &str) -> String;
+}
+
+/// The real clock, with random ids.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_secs(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    }
+
+    fn new_id(&self, prefix: &str) -> String {
+        format!("{}-{}", prefix, uuid::Uuid::new_v4().simple())
+    }
+}
+
+/// A clock stopped at `secs` that numbers its ids 1, 2, 3...
+pub struct FrozenClock {
+    pub secs: u64,
+    issued: std::cell::Cell<u64>,
+}
+
+impl FrozenClock {
+    pub fn new(secs: u64) -> Self {
+        Self { secs, issued: std::cell::Cell::new(0) }
+    }
+}
+
+impl Clock for FrozenClock {
+    fn now_secs(&self) -> u64 {
+        self.secs
+    }
+
+    fn new_id(&self, prefix: &str) -> String {
+        self.issued.set(self.issued.get() + 1);
+        format!("{}-{}", prefix, self.issued.get())
+    }
+}
+
+thread_local! {
+    static FROZEN: RefCell<Option<FrozenClock>> = const { RefCell::new(None) };
+}
+
+/// Current time in seconds since the epoch, from the frozen clock if this thread has one.
+pub fn now_secs() -> u64 {
+    FROZEN.with(|f| f.borrow().as_ref().map(|c| c.now_secs())).unwrap_or_else(|| SystemClock.now_secs())
+}
+
+/// A fresh `<prefix>-<suffix>` id, sequential under a frozen clock.
+pub fn new_id(prefix: &str) -> String {
+    FROZEN.with(|f| f.borrow().as_ref().map(|c| c.new_id(prefix))).unwrap_or_else(|| SystemClock.new_id(prefix))
+}
+
+/// Stop the clock at `secs` for the current thread until the guard is dropped.
+pub fn freeze(secs: u64) -> FreezeGuard {
+    FROZEN.with(|f| *f.borrow_mut() = Some(FrozenClock::new(secs)));
+    FreezeGuard(())
+}
+
+pub struct FreezeGuard(());
+
+impl Drop for FreezeGuard {
+    fn drop(&mut self) {
+        FROZEN.with(|f| *f.borrow_mut() = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_freeze_time_and_ids() {
+        {
+            let _frozen = freeze(1_700_000_000);
+            assert_eq!(now_secs(), 1_700_000_000);
+            assert_eq!(new_id("gen"), "gen-1");
+            assert_eq!(new_id("gen"), "gen-2");
+        }
+        assert!(now_secs() > 1_700_000_000);
+        assert_ne!(new_id("gen"), new_id("gen"));
+    }
+}
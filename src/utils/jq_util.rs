@@ -30,6 +30,73 @@ pub fn run_jaq(filter: &str, input: &serde_json::Value) -> Option<String> {
     }
 }
 
+/// Like `run_jaq`, but returns the first output as JSON (strings stay strings).
+pub fn run_jaq_json(filter: &str, input: &serde_json::Value) -> Option<Value> {
+    let arena = Arena::default();
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let modules = loader.load(&arena, File { path: (), code: filter }).ok()?;
+    let filter = jaq_core::Compiler::default()
+        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+        .compile(modules)
+        .ok()?;
+
+    let inputs = RcIter::new(core::iter::empty());
+    let ctx = Ctx::new([], &inputs);
+    let mut outputs = filter.run((ctx, json_to_jaq_val(input)));
+    outputs.next()?.ok().map(|val| jaq_val_to_json(&val))
+}
+
+/// A jq filter compiled once, for filters that run on every request.
+#[derive(Clone)]
+pub struct JqFilter {
+    source: String,
+    filter: std::sync::Arc<jaq_core::Filter<jaq_core::Native<Val>>>,
+}
+
+impl JqFilter {
+    pub fn compile(source: &str) -> Option<Self> {
+        let arena = Arena::default();
+        let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+        let modules = loader.load(&arena, File { path: (), code: source }).ok()?;
+        let filter = jaq_core::Compiler::default()
+            .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+            .compile(modules)
+            .ok()?;
+        Some(Self { source: source.to_string(), filter: std::sync::Arc::new(filter) })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// The first output for `input` as JSON, like `run_jaq_json`.
+    pub fn run_json(&self, input: &Value) -> Option<Value> {
+        let inputs = RcIter::new(core::iter::empty());
+        let ctx = Ctx::new([], &inputs);
+        let mut outputs = self.filter.run((ctx, json_to_jaq_val(input)));
+        outputs.next()?.ok().map(|val| jaq_val_to_json(&val))
+    }
+}
+
+impl std::fmt::Debug for JqFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("JqFilter").field(&self.source).finish()
+    }
+}
+
+impl serde::Serialize for JqFilter {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for JqFilter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        JqFilter::compile(&source).ok_or_else(|| serde::de::Error::custom(format!("invalid jq filter {:?}", source)))
+    }
+}
+
 pub fn check_jaq_filter(filter: &str) -> bool {
     let arena = Arena::default();
     let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
@@ -66,6 +133,19 @@ fn json_to_jaq_val(value: &serde_json::Value) -> Val {
 }
 
 
+fn jaq_val_to_json(val: &Val) -> Value {
+    match val {
+        Val::Null => Value::Null,
+        Val::Bool(b) => Value::Bool(*b),
+        Val::Int(i) => Value::from(*i as i64),
+        Val::Float(f) => serde_json::Number::from_f64(*f).map(Value::Number).unwrap_or(Value::Null),
+        Val::Num(n) => serde_json::from_str(n).unwrap_or(Value::Null),
+        Val::Str(s) => Value::String(s.to_string()),
+        Val::Arr(arr) => Value::Array(arr.iter().map(jaq_val_to_json).collect()),
+        Val::Obj(obj) => Value::Object(obj.iter().map(|(k, v)| (k.to_string(), jaq_val_to_json(v))).collect()),
+    }
+}
+
 fn jaq_val_to_json_string(val: &Val) -> String {
     match val {
         Val::Null => "null".to_string(),
@@ -133,9 +213,23 @@ mod tests {
         assert_eq!(run_jaq(".model == \"gpt-5\"", &input), Some("true".to_string()));
         assert_eq!(run_jaq(".tools | length > 0", &input), Some("true".to_string()));
         assert_eq!(run_jaq(".tools[].name == \"get_weather\"", &input), Some("true".to_string()));
+        assert_eq!(
+            run_jaq_json(".tools | map({name, strict})", &input),
+            Some(serde_json::json!([{"name": "get_weather", "strict": true}]))
+        );
     }
 
     
+    #[test]
+    fn test_compiled_filter() {
+        let filter = JqFilter::compile(".results | map(.title)").unwrap();
+        let input = serde_json::json!({"results": [{"title": "a"}, {"title": "b"}]});
+        assert_eq!(filter.run_json(&input), Some(serde_json::json!(["a", "b"])));
+        assert_eq!(filter.run_json(&input), Some(serde_json::json!(["a", "b"])));
+        assert!(JqFilter::compile(".results |").is_none());
+        assert!(serde_json::from_value::<JqFilter>(serde_json::json!("map(")).is_err());
+    }
+
     #[tokio::test]
     async fn test_check_jaq_filter() {
         assert!(check_jaq_filter("has(\"model\")"));
@@ -0,0 +1,101 @@
+/// Best-effort completion of a JSON document cut off mid-stream: open strings,
+/// arrays and objects are closed, and a dangling key or separator at the cut is
+/// dropped or completed. Returns `None` only if no prefix can be repaired.
+pub fn repair_json(partial: &str) -> Option<String> {
+    if partial.trim().is_empty() {
+        return Some("{}".to_string());
+    }
+    // Drop trailing characters (e.g. a half-written literal) until a closed form parses
+    let mut end = partial.len();
+    loop {
+        if let Some(repaired) = close(&partial[..end])
+            && serde_json::from_str::<serde_json::Value>(&repaired).is_ok()
+        {
+            return Some(repaired);
+        }
+        if end == 0 {
+            return None;
+        }
+        end = partial[..end].char_indices().next_back().map(|(i, _)| i).unwrap_or(0);
+    }
+}
+
+// Close whatever is still open at the end of `prefix`
+fn close(prefix: &str) -> Option<String> {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in prefix.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => stack.push('}'),
+            '[' => stack.push(']'),
+            // Pops the matching opener; a mismatch means the prefix is not JSON at all
+            '}' | ']' if stack.pop() != Some(c) => return None,
+            _ => {}
+        }
+    }
+    if stack.is_empty() && !in_string {
+        return Some(prefix.to_string());
+    }
+
+    let mut out = prefix.to_string();
+    if in_string {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+    }
+    let trimmed = out.trim_end().trim_end_matches(',').trim_end();
+    let mut out = trimmed.to_string();
+    if out.ends_with(':') {
+        out.push_str("null");
+    } else if stack.last() == Some(&'}') && ends_with_bare_key(&out) {
+        out.push_str(":null");
+    }
+    out.extend(stack.iter().rev());
+    Some(out)
+}
+
+// True when the text ends with a string that follows `{` or `,` (a key without its value)
+fn ends_with_bare_key(text: &str) -> bool {
+    let Some(body) = text.strip_suffix('"') else { return false };
+    // Find the quote opening the trailing string: one not escaped by a backslash
+    let opening = body.char_indices().rev().find(|&(i, c)| {
+        c == '"' && body[..i].chars().rev().take_while(|&b| b == '\\').count() % 2 == 0
+    });
+    match opening {
+        Some((i, _)) => {
+            let before = body[..i].trim_end();
+            before.ends_with('{') || before.ends_with(',')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repair_truncated_arguments() {
+        assert_eq!(repair_json(r#"{"city": "Par"#).unwrap(), r#"{"city": "Par"}"#);
+        assert_eq!(repair_json(r#"{"a": [1, 2"#).unwrap(), r#"{"a": [1, 2]}"#);
+        assert_eq!(repair_json(r#"{"a": 1, "b""#).unwrap(), r#"{"a": 1, "b":null}"#);
+        assert_eq!(repair_json(r#"{"a": 1, "b":"#).unwrap(), r#"{"a": 1, "b":null}"#);
+        assert_eq!(repair_json(r#"{"a": tru"#).unwrap(), r#"{"a":null}"#);
+        assert_eq!(repair_json(r#"{"a": "x\"#).unwrap(), r#"{"a": "x"}"#);
+        assert_eq!(repair_json(r#"{"a": {"b": 1},"#).unwrap(), r#"{"a": {"b": 1}}"#);
+        assert_eq!(repair_json("").unwrap(), "{}");
+        assert_eq!(repair_json(r#"{"done": true}"#).unwrap(), r#"{"done": true}"#);
+    }
+}
@@ -1 +1,3 @@
-pub mod jq_util;
\ No newline at end of file
+pub mod clock;
+pub mod jq_util;
+pub mod json_repair;
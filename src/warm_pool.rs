@@ -0,0 +1,96 @@
+//! Keeps a warm connection to every api_base whose model sets `keepalive_ms`,
+//! so the first request after an idle period does not pay for TCP and TLS
+//! setup. Models that share an api_base (and proxy setting) share the pings.
+
+use crate::config::{Config, ModelConfig};
+use crate::llm_client::LlmClient;
+use crate::model_manager::ModelManager;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::debug;
+
+// How often the config is checked for pings that are due
+const TICK: Duration = Duration::from_secs(1);
+
+/// When each (api_base, use_proxy) was last pinged.
+#[derive(Debug, Default)]
+pub struct WarmPool {
+    pinged: HashMap<(String, bool), Instant>,
+}
+
+impl WarmPool {
+    /// Models whose upstream is due for a ping at `now`, one per upstream.
+    pub fn due(&mut self, config: &Config, now: Instant) -> Vec<ModelConfig> {
+        let mut due = Vec::new();
+        for model in &config.model_list {
+            let Some(interval) = model.llm_params.keepalive_ms.map(Duration::from_millis) else { continue };
+            let key = (model.llm_params.api_base.clone(), model.llm_params.use_proxy);
+            if self.pinged.get(&key).is_some_and(|last| now.duration_since(*last) < interval) {
+                continue;
+            }
+            self.pinged.insert(key, now);
+            due.push(model.clone());
+        }
+        due
+    }
+}
+
+/// Whether any model in `config` asks for keepalive pings.
+pub fn wanted(config: &Config) -> bool {
+    config.model_list.iter().any(|model| model.llm_params.keepalive_ms.is_some())
+}
+
+/// Ping due upstreams of every manager until the router stops; follows reloads.
+pub async fn run(managers: Vec<Arc<RwLock<ModelManager>>>, llm_client: Arc<LlmClient>) {
+    let mut pool = WarmPool::default();
+    let mut ticker = tokio::time::interval(TICK);
+    loop {
+        ticker.tick().await;
+        let mut due = Vec::new();
+        for manager in &managers {
+            let manager = manager.read().await;
+            due.extend(pool.due(manager.get_config(), Instant::now()));
+        }
+        for model in due {
+            let llm_client = llm_client.clone();
+            tokio::spawn(async move {
+                match llm_client.warm(&model).await {
+                    Ok(status) => debug!("Keepalive to {} for {}: {}", model.llm_params.api_base, model.model_name, status),
+                    Err(e) => debug!("Keepalive to {} for {} failed: {}", model.llm_params.api_base, model.model_name, e),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_upstreams_are_pinged_once_per_interval() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+model_list:
+  - model_name: a
+    llm_params: {api_type: openai, model: a, api_base: "https://one.example/v1", api_key: k, keepalive_ms: 30000}
+  - model_name: b
+    llm_params: {api_type: openai, model: b, api_base: "https://one.example/v1", api_key: k, keepalive_ms: 60000}
+  - model_name: c
+    llm_params: {api_type: openai, model: c, api_base: "https://two.example/v1", api_key: k}
+router_settings:
+  strategy: roundrobin
+  model_groups: []
+"#,
+        )
+        .unwrap();
+        let mut pool = WarmPool::default();
+        let start = Instant::now();
+        let names = |due: Vec<ModelConfig>| due.into_iter().map(|m| m.model_name).collect::<Vec<_>>();
+        assert_eq!(names(pool.due(&config, start)), ["a"]);
+        assert!(pool.due(&config, start + Duration::from_secs(10)).is_empty());
+        assert_eq!(names(pool.due(&config, start + Duration::from_secs(30))), ["a"]);
+    }
+}
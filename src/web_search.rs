@@ -0,0 +1,173 @@
+//! Emulated hosted web search. A client's hosted search request (OpenAI
+//! `web_search_options`, an Anthropic `web_search_*` tool) is replaced by a
+//! `web_search` function tool that the router runs against the configured
+//! search API, so upstreams without built-in search still answer. A client tool
+//! that already has that name keeps it, and the search gets a numbered name.
+
+use crate::config::WebSearchSettings;
+use crate::converters::request_wrapper::RequestWrapper;
+use crate::llm_client::LlmClient;
+use crate::router_tools::ToolDefinition;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+pub const TOOL_NAME: &str = "web_search";
+
+/// A hosted web search the client asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostedSearch {
+    // The client's limit on searches, if it set one
+    pub max_uses: Option<u32>,
+}
+
+/// Remove the client's hosted web search from `request`, if it asked for one.
+pub fn take_hosted_tool(request: &mut RequestWrapper) -> Option<HostedSearch> {
+    match request {
+        RequestWrapper::OpenAI(req) => req.extra_fields.remove("web_search_options").map(|_| HostedSearch { max_uses: None }),
+        RequestWrapper::Anthropic(req) => {
+            let tools = req.tools.as_mut()?;
+            let is_search = |tool: &crate::converters::anthropic::AnthropicTool| {
+                tool.extra_fields.get("type").and_then(Value::as_str).is_some_and(|t| t.starts_with("web_search_"))
+            };
+            let search = tools.iter().find(|tool| is_search(tool))?;
+            let max_uses = search.extra_fields.get("max_uses").and_then(Value::as_u64).map(|n| n as u32);
+            tools.retain(|tool| !is_search(tool));
+            if tools.is_empty() {
+                req.tools = None;
+            }
+            Some(HostedSearch { max_uses })
+        }
+        RequestWrapper::Gemini(_) => None,
+    }
+}
+
+/// The name to offer the search under: `web_search`, or a numbered variant
+/// when the client already has a tool of that name.
+pub fn tool_name(taken: &[String]) -> String {
+    std::iter::once(TOOL_NAME.to_string())
+        .chain((2..).map(|n| format!("{}_{}", TOOL_NAME, n)))
+        .find(|name| !taken.contains(name))
+        .expect("unbounded candidates")
+}
+
+/// The function tool offered in place of the hosted one.
+pub fn definition(name: &str) -> ToolDefinition {
+    ToolDefinition {
+        name: name.to_string(),
+        description: "Search the web. Returns the top results with title, URL and snippet.".to_string(),
+        parameters: json!({
+            "type": "object",
+            "properties": {"query": {"type": "string", "description": "Search terms"}},
+            "required": ["query"],
+        }),
+    }
+}
+
+/// Run one `web_search` call and format the results for the model. Failures
+/// are reported in the text, so the model can answer without them.
+pub async fn run(client: &LlmClient, settings: &WebSearchSettings, arguments: &Value) -> String {
+    let Some(query) = arguments["query"].as_str().filter(|q| !q.trim().is_empty()) else {
+        return "Error: web_search needs a query".to_string();
+    };
+    let response = match client.web_search(query, settings).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Web search for {:?} failed: {}", query, e);
+            return format!("Error: search failed: {}", e);
+        }
+    };
+    let results: Vec<Value> = settings
+        .results
+        .run_json(&response)
+        .and_then(|results| serde_json::from_value(results).ok())
+        .unwrap_or_default();
+    info!("Web search for {:?} returned {} results", query, results.len());
+    format_results(&results, settings.max_results as usize)
+}
+
+fn format_results(results: &[Value], max_results: usize) -> String {
+    if results.is_empty() {
+        return "No results found.".to_string();
+    }
+    results
+        .iter()
+        .take(max_results)
+        .enumerate()
+        .map(|(i, result)| {
+            format!(
+                "{}. {}\nURL: {}\n{}",
+                i + 1,
+                result["title"].as_str().unwrap_or_default(),
+                result["url"].as_str().unwrap_or_default(),
+                result["snippet"].as_str().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_take_hosted_tool() {
+        let mut openai = RequestWrapper::OpenAI(
+            serde_json::from_value(json!({"model": "m", "messages": [], "web_search_options": {"search_context_size": "low"}})).unwrap(),
+        );
+        assert_eq!(take_hosted_tool(&mut openai), Some(HostedSearch { max_uses: None }));
+        assert!(serde_json::to_value(&openai).unwrap().get("web_search_options").is_none());
+
+        let mut anthropic = RequestWrapper::Anthropic(
+            serde_json::from_value(json!({"model": "m", "max_tokens": 16, "messages": [], "tools": [
+                {"type": "web_search_20250305", "name": "web_search", "max_uses": 2},
+                {"name": "lookup", "description": "Look up", "input_schema": {"type": "object"}}
+            ]}))
+            .unwrap(),
+        );
+        assert_eq!(take_hosted_tool(&mut anthropic), Some(HostedSearch { max_uses: Some(2) }));
+        let body = serde_json::to_value(&anthropic).unwrap();
+        assert_eq!(body["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(body["tools"][0]["name"], "lookup");
+        assert_eq!(take_hosted_tool(&mut anthropic), None);
+    }
+
+    #[test]
+    fn test_tool_name_avoids_client_tools() {
+        assert_eq!(tool_name(&["lookup".to_string()]), "web_search");
+        assert_eq!(tool_name(&["web_search".to_string(), "web_search_2".to_string()]), "web_search_3");
+    }
+
+    #[tokio::test]
+    async fn test_run_formats_results() {
+        let mut server = mockito::Server::new_async().await;
+        let _search = server
+            .mock("GET", "/search")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("q".into(), "rust lang".into()),
+                mockito::Matcher::UrlEncoded("format".into(), "json".into()),
+            ]))
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({"results": [
+                    {"title": "Rust", "url": "https://www.rust-lang.org", "content": "A language empowering everyone"},
+                    {"title": "Docs", "url": "https://doc.rust-lang.org", "content": "Documentation"}
+                ]})
+                .to_string(),
+            )
+            .create();
+        let client = LlmClient::new(Arc::new(reqwest::Client::new()), Arc::new(reqwest::Client::new()));
+        let settings = WebSearchSettings {
+            enabled: true,
+            url: format!("{}/search", server.url()),
+            params: [("format".to_string(), "json".to_string())].into(),
+            max_results: 1,
+            ..Default::default()
+        };
+
+        let text = run(&client, &settings, &json!({"query": "rust lang"})).await;
+        assert_eq!(text, "1. Rust\nURL: https://www.rust-lang.org\nA language empowering everyone");
+        assert!(run(&client, &settings, &json!({})).await.starts_with("Error"));
+    }
+}
@@ -0,0 +1,363 @@
+// Property tests for the converters: generated requests and upstream streams in
+// every format are converted to every other format. Conversions must not panic,
+// and message text must come out the other side. The generators build wire
+// JSON, so they also cover what serde accepts; fuzz/ feeds raw bytes instead.
+//
+// Scope: the router converts between three formats (OpenAI chat, Anthropic and
+// Gemini) and has no OpenAI Responses request type, so this covers 3 formats
+// and 9 pairs (each format to each, itself included), not 4 formats and 16.
+
+// Only the pair list and names are used here
+#[allow(dead_code)]
+#[path = "../benches/common/transcripts.rs"]
+mod transcripts;
+
+use bytes::Bytes;
+use futures::stream;
+use http_body_util::BodyExt;
+use llm_router::config::ApiType;
+use llm_router::converters::request_wrapper::RequestWrapper;
+use llm_router::converters::response_handler::{StreamOptions, handle_streaming_response};
+use proptest::prelude::*;
+use serde_json::{Value, json};
+
+// Printable text with some non-ASCII, so multi-byte characters straddle chunk cuts
+fn text() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9 ,.!?\"\\\\é中🙂]{1,24}"
+}
+
+fn name() -> impl Strategy<Value = String> {
+    "[a-z][a-z0-9_]{0,11}"
+}
+
+fn args() -> impl Strategy<Value = Value> {
+    prop::collection::btree_map(name(), text(), 0..3).prop_map(|m| json!(m))
+}
+
+const PNG: &str = "iVBORw0KGgo=";
+
+// ---- requests ----
+
+fn openai_message() -> impl Strategy<Value = (Vec<Value>, Vec<String>)> {
+    prop_oneof![
+        (prop_oneof![Just("user"), Just("assistant")], text())
+            .prop_map(|(role, t)| (vec![json!({"role": role, "content": t})], vec![t])),
+        (text(), text()).prop_map(|(a, b)| (
+            vec![json!({"role": "user", "content": [{"type": "text", "text": a}, {"type": "text", "text": b}]})],
+            vec![a, b]
+        )),
+        (text(), any::<bool>()).prop_map(|(t, inline)| {
+            let url = if inline { format!("data:image/png;base64,{}", PNG) } else { "https://example.com/a.png".to_string() };
+            let kept = if inline { vec![t.clone(), PNG.to_string()] } else { vec![t.clone()] };
+            (vec![json!({"role": "user", "content": [{"type": "text", "text": t}, {"type": "image_url", "image_url": {"url": url}}]})], kept)
+        }),
+        (name(), args(), text()).prop_map(|(fn_name, args, result)| (
+            vec![
+                json!({"role": "assistant", "content": "", "tool_calls": [{"id": "call_1", "type": "function", "function": {"name": fn_name, "arguments": args.to_string()}}]}),
+                json!({"role": "tool", "tool_call_id": "call_1", "content": result}),
+            ],
+            vec![result]
+        )),
+    ]
+}
+
+fn openai_request() -> impl Strategy<Value = (Value, Vec<String>)> {
+    (
+        prop::option::of(text()),
+        prop::collection::vec(openai_message(), 1..5),
+        prop::option::of(1u32..4096),
+        prop::option::of(prop::collection::vec(text(), 1..3)),
+        prop::collection::vec(name(), 0..3),
+    )
+        .prop_map(|(system, messages, max_tokens, stop, tools)| {
+            let mut texts = Vec::new();
+            let mut all = Vec::new();
+            if let Some(system) = system {
+                all.push(json!({"role": "system", "content": system}));
+                texts.push(system);
+            }
+            for (msgs, t) in messages {
+                all.extend(msgs);
+                texts.extend(t);
+            }
+            let mut req = json!({"model": "m", "messages": all});
+            if let Some(max_tokens) = max_tokens {
+                req["max_tokens"] = json!(max_tokens);
+            }
+            if let Some(stop) = stop {
+                req["stop"] = json!(stop);
+            }
+            if !tools.is_empty() {
+                req["tools"] = json!(tools
+                    .iter()
+                    .map(|n| json!({"type": "function", "function": {"name": n, "description": "d", "parameters": {"type": "object"}}}))
+                    .collect::<Vec<_>>());
+            }
+            (req, texts)
+        })
+}
+
+fn anthropic_message() -> impl Strategy<Value = (Vec<Value>, Vec<String>)> {
+    prop_oneof![
+        (prop_oneof![Just("user"), Just("assistant")], text())
+            .prop_map(|(role, t)| (vec![json!({"role": role, "content": t})], vec![t])),
+        (text(), any::<bool>()).prop_map(|(t, inline)| {
+            let source = if inline {
+                json!({"type": "base64", "media_type": "image/png", "data": PNG})
+            } else {
+                json!({"type": "url", "url": "https://example.com/a.png"})
+            };
+            let kept = if inline { vec![t.clone(), PNG.to_string()] } else { vec![t.clone()] };
+            (vec![json!({"role": "user", "content": [{"type": "text", "text": t}, {"type": "image", "source": source}]})], kept)
+        }),
+        (text(), name(), args(), text()).prop_map(|(say, tool, input, result)| (
+            vec![
+                json!({"role": "assistant", "content": [{"type": "text", "text": say}, {"type": "tool_use", "id": "toolu_1", "name": tool, "input": input}]}),
+                json!({"role": "user", "content": [{"type": "tool_result", "tool_use_id": "toolu_1", "content": result}]}),
+            ],
+            vec![say, result]
+        )),
+    ]
+}
+
+fn anthropic_request() -> impl Strategy<Value = (Value, Vec<String>)> {
+    (prop::option::of(text()), prop::collection::vec(anthropic_message(), 1..5), 1u32..4096).prop_map(
+        |(system, messages, max_tokens)| {
+            let mut texts = Vec::new();
+            let mut all = Vec::new();
+            for (msgs, t) in messages {
+                all.extend(msgs);
+                texts.extend(t);
+            }
+            let mut req = json!({"model": "m", "max_tokens": max_tokens, "messages": all});
+            if let Some(system) = system {
+                req["system"] = json!(system);
+                texts.push(system);
+            }
+            (req, texts)
+        },
+    )
+}
+
+fn gemini_content() -> impl Strategy<Value = (Vec<Value>, Vec<String>)> {
+    prop_oneof![
+        (prop_oneof![Just("user"), Just("model")], text())
+            .prop_map(|(role, t)| (vec![json!({"role": role, "parts": [{"text": t}]})], vec![t])),
+        text().prop_map(|t| (
+            vec![json!({"role": "user", "parts": [{"text": t}, {"inlineData": {"mimeType": "image/png", "data": PNG}}]})],
+            vec![t, PNG.to_string()]
+        )),
+        (name(), args()).prop_map(|(tool, input)| (
+            vec![
+                json!({"role": "model", "parts": [{"functionCall": {"name": tool, "args": input}}]}),
+                json!({"role": "user", "parts": [{"functionResponse": {"name": tool, "response": {"ok": true}}}]}),
+            ],
+            vec![]
+        )),
+    ]
+}
+
+fn gemini_request() -> impl Strategy<Value = (Value, Vec<String>)> {
+    (prop::option::of(text()), prop::collection::vec(gemini_content(), 1..5), prop::option::of(1u32..4096)).prop_map(
+        |(system, contents, max_tokens)| {
+            let mut texts = Vec::new();
+            let mut all = Vec::new();
+            for (c, t) in contents {
+                all.extend(c);
+                texts.extend(t);
+            }
+            let mut req = json!({"model": "m", "contents": all});
+            if let Some(system) = system {
+                req["systemInstruction"] = json!({"parts": [{"text": system}]});
+                texts.push(system);
+            }
+            if let Some(max_tokens) = max_tokens {
+                req["generationConfig"] = json!({"maxOutputTokens": max_tokens});
+            }
+            (req, texts)
+        },
+    )
+}
+
+fn request() -> impl Strategy<Value = (RequestWrapper, Vec<String>)> {
+    prop_oneof![
+        openai_request().prop_map(|(r, t)| (RequestWrapper::OpenAI(serde_json::from_value(r).unwrap()), t)),
+        anthropic_request().prop_map(|(r, t)| (RequestWrapper::Anthropic(serde_json::from_value(r).unwrap()), t)),
+        gemini_request().prop_map(|(r, t)| (RequestWrapper::Gemini(serde_json::from_value(r).unwrap()), t)),
+    ]
+}
+
+fn converted(request: &RequestWrapper, target: &ApiType) -> RequestWrapper {
+    match target {
+        ApiType::OpenAI => RequestWrapper::OpenAI(request.get_openai()),
+        ApiType::Anthropic => RequestWrapper::Anthropic(request.get_anthropic()),
+        ApiType::Gemini => RequestWrapper::Gemini(request.get_gemini()),
+    }
+}
+
+// Every string in the request, joined; texts may be merged into one field
+fn strings(value: &Value, out: &mut String) {
+    match value {
+        Value::String(s) => {
+            out.push_str(s);
+            out.push('\n');
+        }
+        Value::Array(items) => items.iter().for_each(|v| strings(v, out)),
+        Value::Object(map) => map.values().for_each(|v| strings(v, out)),
+        _ => {}
+    }
+}
+
+// ---- streams ----
+
+fn stream_events(source: &ApiType, deltas: &[String], tool: Option<&(String, Value)>) -> String {
+    let mut body = String::new();
+    match source {
+        ApiType::OpenAI => {
+            for d in deltas {
+                let chunk = json!({"id": "c", "object": "chat.completion.chunk", "created": 1, "model": "m",
+                    "choices": [{"index": 0, "delta": {"content": d}, "finish_reason": null}]});
+                body.push_str(&format!("data: {}\n\n", chunk));
+            }
+            if let Some((name, args)) = tool {
+                let chunk = json!({"id": "c", "object": "chat.completion.chunk", "created": 1, "model": "m",
+                    "choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "id": "call_1", "type": "function",
+                        "function": {"name": name, "arguments": args.to_string()}}]}, "finish_reason": null}]});
+                body.push_str(&format!("data: {}\n\n", chunk));
+            }
+            let done = json!({"id": "c", "object": "chat.completion.chunk", "created": 1, "model": "m",
+                "choices": [{"index": 0, "delta": {}, "finish_reason": if tool.is_some() { "tool_calls" } else { "stop" }}]});
+            body.push_str(&format!("data: {}\n\ndata: [DONE]\n\n", done));
+        }
+        ApiType::Anthropic => {
+            let event = |name: &str, data: Value| format!("event: {}\ndata: {}\n\n", name, data);
+            body.push_str(&event("message_start", json!({"type": "message_start", "message": {"id": "msg_1", "type": "message",
+                "role": "assistant", "model": "m", "content": [], "stop_reason": null, "stop_sequence": null,
+                "usage": {"input_tokens": 1, "output_tokens": 0}}})));
+            body.push_str(&event("content_block_start", json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})));
+            for d in deltas {
+                body.push_str(&event("content_block_delta", json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": d}})));
+            }
+            body.push_str(&event("content_block_stop", json!({"type": "content_block_stop", "index": 0})));
+            if let Some((name, args)) = tool {
+                body.push_str(&event("content_block_start", json!({"type": "content_block_start", "index": 1,
+                    "content_block": {"type": "tool_use", "id": "toolu_1", "name": name, "input": {}}})));
+                body.push_str(&event("content_block_delta", json!({"type": "content_block_delta", "index": 1,
+                    "delta": {"type": "input_json_delta", "partial_json": args.to_string()}})));
+                body.push_str(&event("content_block_stop", json!({"type": "content_block_stop", "index": 1})));
+            }
+            body.push_str(&event("message_delta", json!({"type": "message_delta",
+                "delta": {"stop_reason": if tool.is_some() { "tool_use" } else { "end_turn" }, "stop_sequence": null},
+                "usage": {"output_tokens": 3}})));
+            body.push_str(&event("message_stop", json!({"type": "message_stop"})));
+        }
+        ApiType::Gemini => {
+            for d in deltas {
+                let chunk = json!({"candidates": [{"content": {"role": "model", "parts": [{"text": d}]}, "index": 0}]});
+                body.push_str(&format!("data: {}\r\n\r\n", chunk));
+            }
+            let mut parts = Vec::new();
+            if let Some((name, args)) = tool {
+                parts.push(json!({"functionCall": {"name": name, "args": args}}));
+            }
+            let last = json!({"candidates": [{"content": {"role": "model", "parts": parts}, "finishReason": "STOP", "index": 0}],
+                "usageMetadata": {"promptTokenCount": 1, "candidatesTokenCount": 3, "totalTokenCount": 4}});
+            body.push_str(&format!("data: {}\r\n\r\n", last));
+        }
+    }
+    body
+}
+
+// Text the client would show, read back from the converted stream
+fn streamed_text(target: &ApiType, body: &str) -> String {
+    let mut text = String::new();
+    for line in body.lines() {
+        let Some(data) = line.strip_prefix("data: ").or_else(|| line.strip_prefix("data:")) else { continue };
+        let Ok(event) = serde_json::from_str::<Value>(data.trim()) else { continue };
+        match target {
+            ApiType::OpenAI => {
+                if let Some(t) = event.pointer("/choices/0/delta/content").and_then(Value::as_str) {
+                    text.push_str(t);
+                }
+            }
+            ApiType::Anthropic => {
+                if event["type"] == "content_block_delta"
+                    && let Some(t) = event.pointer("/delta/text").and_then(Value::as_str)
+                {
+                    text.push_str(t);
+                }
+            }
+            ApiType::Gemini => {
+                for part in event.pointer("/candidates/0/content/parts").and_then(Value::as_array).into_iter().flatten() {
+                    if let Some(t) = part["text"].as_str() {
+                        text.push_str(t);
+                    }
+                }
+            }
+        }
+    }
+    text
+}
+
+// Cut the body at the given fractions, wherever they fall
+fn cut(body: &str, cuts: &[f64]) -> Vec<Bytes> {
+    let bytes = body.as_bytes();
+    let mut points: Vec<usize> = cuts.iter().map(|f| (f * bytes.len() as f64) as usize).collect();
+    points.push(bytes.len());
+    points.sort_unstable();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for end in points {
+        if end > start {
+            chunks.push(Bytes::copy_from_slice(&bytes[start..end]));
+            start = end;
+        }
+    }
+    chunks
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_requests_convert_without_loss((request, texts) in request()) {
+        for target in [ApiType::OpenAI, ApiType::Anthropic, ApiType::Gemini] {
+            let out = converted(&request, &target);
+            let json = serde_json::to_value(&out).unwrap();
+            let mut all = String::new();
+            strings(&json, &mut all);
+            for t in &texts {
+                // OpenAI carries inline images as data URLs
+                let expected = match (&target, t.as_str()) {
+                    (ApiType::OpenAI, PNG) => format!("data:image/png;base64,{}", PNG),
+                    _ => t.clone(),
+                };
+                prop_assert!(all.contains(&expected), "{:?} -> {}: lost {:?} in {}", request.api_type(), transcripts::name(&target), expected, json);
+            }
+            // And back, which must at least not panic
+            let _ = serde_json::to_value(converted(&out, &request.api_type())).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_streams_convert_without_loss(
+        deltas in prop::collection::vec(text(), 1..8),
+        tool in prop::option::of((name(), args())),
+        cuts in prop::collection::vec(0.0f64..1.0, 0..6),
+        pair in 0..transcripts::PAIRS.len(),
+    ) {
+        let (source, target) = transcripts::PAIRS[pair].clone();
+        let chunks = cut(&stream_events(&source, &deltas, tool.as_ref()), &cuts);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let body = runtime.block_on(async {
+            let input = stream::iter(chunks.into_iter().map(Ok::<_, reqwest::Error>));
+            let resp = handle_streaming_response(input, "m".to_string(), source.clone(), target.clone(), StreamOptions::default()).await;
+            resp.into_body().collect().await.unwrap().to_bytes()
+        });
+        let body = String::from_utf8_lossy(&body);
+        prop_assert_eq!(streamed_text(&target, &body), deltas.concat(), "{} -> {}: {}", transcripts::name(&source), transcripts::name(&target), body);
+        if let Some((name, _)) = &tool {
+            prop_assert!(body.contains(&format!("\"{}\"", name)), "{} -> {}: tool call lost: {}", transcripts::name(&source), transcripts::name(&target), body);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+// Regression guard for the streaming converter hot path: replays a large
+// transcript through every source -> target pair and checks throughput and
+// allocations per upstream event against generous budgets. Criterion numbers
+// live in benches/stream_conversion.rs; this test only catches big regressions.
+
+#[path = "../benches/common/transcripts.rs"]
+mod transcripts;
+
+use futures::stream;
+use http_body_util::BodyExt;
+use llm_router::converters::response_handler::{StreamOptions, handle_streaming_response};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+// Counts allocations made by the current thread; the converter runs on a
+// current-thread runtime so other tests cannot skew the numbers.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|c| c.set(c.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|c| c.set(c.get() + 1));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const TEXT_DELTAS: usize = 2_000;
+const CHUNK_SIZE: usize = 4096;
+// Throughput floor is ~10x below unoptimized builds on a laptop; allocation
+// counts are deterministic, so that budget is ~3x the current figure
+const MIN_EVENTS_PER_SEC: f64 = 2_000.0;
+const MAX_ALLOCATIONS_PER_EVENT: u64 = 60;
+
+#[test]
+fn test_stream_conversion_budgets() {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let mut failures = Vec::new();
+    for (source, target) in transcripts::PAIRS {
+        let pair = format!("{} -> {}", transcripts::name(&source), transcripts::name(&target));
+        let chunks = transcripts::transcript(&source, TEXT_DELTAS, CHUNK_SIZE);
+        let events = transcripts::event_count(&source, TEXT_DELTAS) as u64;
+
+        let allocations_before = ALLOCATIONS.with(Cell::get);
+        let started = Instant::now();
+        let body = runtime.block_on(async {
+            let input = stream::iter(chunks.into_iter().map(Ok::<_, reqwest::Error>));
+            let resp =
+                handle_streaming_response(input, "test".to_string(), source.clone(), target.clone(), StreamOptions::default())
+                    .await;
+            resp.into_body().collect().await.unwrap().to_bytes()
+        });
+        let elapsed = started.elapsed().max(Duration::from_micros(1));
+        let allocations = ALLOCATIONS.with(Cell::get) - allocations_before;
+
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("token96"), "{}: text was lost in conversion", pair);
+
+        let events_per_sec = events as f64 / elapsed.as_secs_f64();
+        let allocations_per_event = allocations / events;
+        println!("{}: {:.0} events/s, {} allocations/event", pair, events_per_sec, allocations_per_event);
+        if events_per_sec < MIN_EVENTS_PER_SEC {
+            failures.push(format!("{}: {:.0} events/s is below {}", pair, events_per_sec, MIN_EVENTS_PER_SEC));
+        }
+        if allocations_per_event > MAX_ALLOCATIONS_PER_EVENT {
+            failures.push(format!("{}: {} allocations/event exceeds {}", pair, allocations_per_event, MAX_ALLOCATIONS_PER_EVENT));
+        }
+    }
+    assert!(failures.is_empty(), "stream conversion budgets exceeded:\n{}", failures.join("\n"));
+}